@@ -0,0 +1,9 @@
+CREATE TABLE jobs
+(
+    id       SERIAL PRIMARY KEY,
+    kind     TEXT    NOT NULL,
+    status   TEXT    NOT NULL DEFAULT 'pending',
+    progress INTEGER NOT NULL DEFAULT 0,
+    result   JSONB,
+    error    TEXT
+);
@@ -0,0 +1,25 @@
+ALTER TABLE todos
+    ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT now();
+
+CREATE TABLE last_modified
+(
+    resource    TEXT PRIMARY KEY,
+    modified_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+INSERT INTO last_modified (resource)
+VALUES ('todos');
+
+CREATE FUNCTION touch_todos() RETURNS trigger AS
+$$
+BEGIN
+    UPDATE last_modified SET modified_at = now() WHERE resource = 'todos';
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER todos_last_modified
+    AFTER INSERT OR UPDATE OR DELETE
+    ON todos
+    FOR EACH STATEMENT
+EXECUTE FUNCTION touch_todos();
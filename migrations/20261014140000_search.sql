@@ -0,0 +1,5 @@
+-- 'simple' keeps words as written: the todos are not all English, so no stemming.
+ALTER TABLE todos
+    ADD COLUMN search tsvector GENERATED ALWAYS AS (to_tsvector('simple', text)) STORED;
+
+CREATE INDEX todos_search_idx ON todos USING GIN (search);
@@ -0,0 +1,3 @@
+-- Public ids for ID_FORMAT=uuid; rows from before the switch get one at startup.
+ALTER TABLE todos ADD COLUMN uuid TEXT UNIQUE;
+ALTER TABLE labels ADD COLUMN uuid TEXT UNIQUE;
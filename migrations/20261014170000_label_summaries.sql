@@ -0,0 +1,69 @@
+-- Each labelled todo's labels as the JSON array `all_with_labels` returns,
+-- kept up to date by triggers so listings read one row per todo instead of
+-- aggregating the join. Todos without labels have no row.
+CREATE TABLE todo_label_summaries
+(
+    todo_id INTEGER PRIMARY KEY REFERENCES todos (id) ON DELETE CASCADE,
+    labels  JSONB NOT NULL
+);
+
+CREATE FUNCTION refresh_todo_label_summary(target INTEGER) RETURNS void AS
+$$
+BEGIN
+    DELETE FROM todo_label_summaries WHERE todo_id = target;
+    -- joining todos skips todos being deleted in the same statement
+    INSERT INTO todo_label_summaries (todo_id, labels)
+    SELECT todos.id,
+           jsonb_agg(jsonb_build_object('id', labels.id, 'uuid', labels.uuid, 'name', labels.name)
+                     ORDER BY labels.id)
+    FROM todos
+             JOIN todo_labels tl ON tl.todo_id = todos.id
+             JOIN labels ON labels.id = tl.label_id
+    WHERE todos.id = target
+    GROUP BY todos.id;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE FUNCTION todo_labels_changed() RETURNS trigger AS
+$$
+BEGIN
+    IF TG_OP IN ('DELETE', 'UPDATE') THEN
+        PERFORM refresh_todo_label_summary(OLD.todo_id);
+    END IF;
+    IF TG_OP IN ('INSERT', 'UPDATE') THEN
+        PERFORM refresh_todo_label_summary(NEW.todo_id);
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER todo_labels_summary
+    AFTER INSERT OR UPDATE OR DELETE
+    ON todo_labels
+    FOR EACH ROW
+EXECUTE FUNCTION todo_labels_changed();
+
+-- renames and uuid backfills show up in every todo carrying the label
+CREATE FUNCTION labels_changed() RETURNS trigger AS
+$$
+BEGIN
+    PERFORM refresh_todo_label_summary(tl.todo_id)
+    FROM (SELECT DISTINCT todo_id FROM todo_labels WHERE label_id = NEW.id) tl;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER labels_summary
+    AFTER UPDATE OF name, uuid
+    ON labels
+    FOR EACH ROW
+EXECUTE FUNCTION labels_changed();
+
+INSERT INTO todo_label_summaries (todo_id, labels)
+SELECT todos.id,
+       jsonb_agg(jsonb_build_object('id', labels.id, 'uuid', labels.uuid, 'name', labels.name)
+                 ORDER BY labels.id)
+FROM todos
+         JOIN todo_labels tl ON tl.todo_id = todos.id
+         JOIN labels ON labels.id = tl.label_id
+GROUP BY todos.id;
@@ -0,0 +1,14 @@
+-- Completed todos moved out of `todos` once they've been left alone long
+-- enough; ids come from the todos sequence, so they never clash.
+CREATE TABLE archived_todos
+(
+    id          INTEGER PRIMARY KEY,
+    uuid        TEXT UNIQUE,
+    text        TEXT        NOT NULL,
+    completed   BOOLEAN     NOT NULL,
+    version     INTEGER     NOT NULL,
+    updated_at  TIMESTAMPTZ NOT NULL,
+    archived_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX todos_completed_updated_at_idx ON todos (updated_at) WHERE completed;
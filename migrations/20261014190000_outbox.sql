@@ -0,0 +1,13 @@
+-- Todo events waiting for delivery to the webhook, written in the same
+-- transaction as the change they describe. Rows are deleted once delivered.
+CREATE TABLE outbox
+(
+    id              BIGSERIAL PRIMARY KEY,
+    event           JSONB       NOT NULL,
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT now(),
+    attempts        INTEGER     NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    last_error      TEXT
+);
+
+CREATE INDEX outbox_next_attempt_at_idx ON outbox (next_attempt_at);
@@ -0,0 +1,31 @@
+-- 64-bit todo and label ids, so busy servers don't run out of them.
+-- Rewrites the tables, holding an exclusive lock on each meanwhile.
+ALTER TABLE todos ALTER COLUMN id TYPE BIGINT;
+ALTER SEQUENCE todos_id_seq AS BIGINT;
+ALTER TABLE labels ALTER COLUMN id TYPE BIGINT;
+ALTER SEQUENCE labels_id_seq AS BIGINT;
+ALTER TABLE todo_labels
+    ALTER COLUMN todo_id TYPE BIGINT,
+    ALTER COLUMN label_id TYPE BIGINT;
+ALTER TABLE todo_label_summaries ALTER COLUMN todo_id TYPE BIGINT;
+ALTER TABLE archived_todos ALTER COLUMN id TYPE BIGINT;
+
+-- the triggers call it with the new column type
+DROP FUNCTION refresh_todo_label_summary(INTEGER);
+
+CREATE FUNCTION refresh_todo_label_summary(target BIGINT) RETURNS void AS
+$$
+BEGIN
+    DELETE FROM todo_label_summaries WHERE todo_id = target;
+    -- joining todos skips todos being deleted in the same statement
+    INSERT INTO todo_label_summaries (todo_id, labels)
+    SELECT todos.id,
+           jsonb_agg(jsonb_build_object('id', labels.id, 'uuid', labels.uuid, 'name', labels.name)
+                     ORDER BY labels.id)
+    FROM todos
+             JOIN todo_labels tl ON tl.todo_id = todos.id
+             JOIN labels ON labels.id = tl.label_id
+    WHERE todos.id = target
+    GROUP BY todos.id;
+END;
+$$ LANGUAGE plpgsql;
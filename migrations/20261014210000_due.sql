@@ -0,0 +1,3 @@
+-- The day a todo should be done by, as YYYY-MM-DD.
+ALTER TABLE todos ADD COLUMN due TEXT;
+ALTER TABLE archived_todos ADD COLUMN due TEXT;
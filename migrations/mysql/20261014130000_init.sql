@@ -0,0 +1,56 @@
+CREATE TABLE IF NOT EXISTS todos
+(
+    id         INT PRIMARY KEY AUTO_INCREMENT,
+    text       TEXT      NOT NULL,
+    completed  BOOLEAN   NOT NULL DEFAULT false,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS labels
+(
+    id   INT PRIMARY KEY AUTO_INCREMENT,
+    name VARCHAR(255) NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS todo_labels
+(
+    id       INT PRIMARY KEY AUTO_INCREMENT,
+    todo_id  INT NOT NULL,
+    label_id INT NOT NULL,
+    FOREIGN KEY (todo_id) REFERENCES todos (id) ON DELETE CASCADE,
+    FOREIGN KEY (label_id) REFERENCES labels (id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS jobs
+(
+    id       INT PRIMARY KEY AUTO_INCREMENT,
+    kind     VARCHAR(64) NOT NULL,
+    status   VARCHAR(64) NOT NULL DEFAULT 'pending',
+    progress INT         NOT NULL DEFAULT 0,
+    result   JSON,
+    error    TEXT
+);
+
+CREATE TABLE IF NOT EXISTS last_modified
+(
+    resource    VARCHAR(64) PRIMARY KEY,
+    modified_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+INSERT IGNORE INTO last_modified (resource)
+VALUES ('todos');
+
+CREATE TRIGGER IF NOT EXISTS todos_last_modified_insert
+    AFTER INSERT
+    ON todos
+    FOR EACH ROW UPDATE last_modified SET modified_at = CURRENT_TIMESTAMP WHERE resource = 'todos';
+
+CREATE TRIGGER IF NOT EXISTS todos_last_modified_update
+    AFTER UPDATE
+    ON todos
+    FOR EACH ROW UPDATE last_modified SET modified_at = CURRENT_TIMESTAMP WHERE resource = 'todos';
+
+CREATE TRIGGER IF NOT EXISTS todos_last_modified_delete
+    AFTER DELETE
+    ON todos
+    FOR EACH ROW UPDATE last_modified SET modified_at = CURRENT_TIMESTAMP WHERE resource = 'todos';
@@ -0,0 +1,14 @@
+-- Completed todos moved out of `todos` once they've been left alone long
+-- enough; AUTO_INCREMENT never hands their ids out again.
+CREATE TABLE IF NOT EXISTS archived_todos
+(
+    id          INT PRIMARY KEY,
+    uuid        CHAR(36) UNIQUE,
+    text        TEXT      NOT NULL,
+    completed   BOOLEAN   NOT NULL,
+    version     INT       NOT NULL,
+    updated_at  TIMESTAMP NOT NULL,
+    archived_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX todos_completed_updated_at_idx ON todos (completed, updated_at);
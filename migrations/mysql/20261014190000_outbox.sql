@@ -0,0 +1,13 @@
+-- Todo events waiting for delivery to the webhook, written in the same
+-- transaction as the change they describe. Rows are deleted once delivered.
+CREATE TABLE IF NOT EXISTS outbox
+(
+    id              BIGINT PRIMARY KEY AUTO_INCREMENT,
+    event           JSON      NOT NULL,
+    created_at      TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    attempts        INT       NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    last_error      TEXT
+);
+
+CREATE INDEX outbox_next_attempt_at_idx ON outbox (next_attempt_at);
@@ -0,0 +1,10 @@
+-- 64-bit todo and label ids, so busy servers don't run out of them. The
+-- foreign keys are not checked while both of their sides change type.
+SET FOREIGN_KEY_CHECKS = 0;
+ALTER TABLE todos MODIFY id BIGINT AUTO_INCREMENT;
+ALTER TABLE labels MODIFY id BIGINT AUTO_INCREMENT;
+ALTER TABLE todo_labels
+    MODIFY todo_id BIGINT NOT NULL,
+    MODIFY label_id BIGINT NOT NULL;
+ALTER TABLE archived_todos MODIFY id BIGINT;
+SET FOREIGN_KEY_CHECKS = 1;
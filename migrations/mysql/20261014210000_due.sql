@@ -0,0 +1,3 @@
+-- The day a todo should be done by, as YYYY-MM-DD.
+ALTER TABLE todos ADD COLUMN due CHAR(10);
+ALTER TABLE archived_todos ADD COLUMN due CHAR(10);
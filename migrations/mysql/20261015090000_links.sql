@@ -0,0 +1,17 @@
+-- Which todo stands for which event or issue of a synced service, and the
+-- token each service's next sync carries on from.
+CREATE TABLE IF NOT EXISTS links
+(
+    service  VARCHAR(255) NOT NULL,
+    external VARCHAR(255) NOT NULL,
+    todo_id  BIGINT       NOT NULL,
+    PRIMARY KEY (service, external)
+);
+
+CREATE INDEX links_todo_id_idx ON links (todo_id);
+
+CREATE TABLE IF NOT EXISTS sync_tokens
+(
+    service VARCHAR(255) PRIMARY KEY,
+    token   TEXT         NOT NULL
+);
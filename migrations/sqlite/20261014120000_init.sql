@@ -0,0 +1,61 @@
+CREATE TABLE IF NOT EXISTS todos
+(
+    id         INTEGER PRIMARY KEY AUTOINCREMENT,
+    text       TEXT    NOT NULL,
+    completed  BOOLEAN NOT NULL DEFAULT false,
+    updated_at INTEGER NOT NULL DEFAULT (CAST(strftime('%s', 'now') AS INTEGER))
+);
+
+CREATE TABLE IF NOT EXISTS labels
+(
+    id   INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS todo_labels
+(
+    id       INTEGER PRIMARY KEY AUTOINCREMENT,
+    todo_id  INTEGER NOT NULL REFERENCES todos (id) ON DELETE CASCADE,
+    label_id INTEGER NOT NULL REFERENCES labels (id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS jobs
+(
+    id       INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind     TEXT    NOT NULL,
+    status   TEXT    NOT NULL DEFAULT 'pending',
+    progress INTEGER NOT NULL DEFAULT 0,
+    result   TEXT,
+    error    TEXT
+);
+
+CREATE TABLE IF NOT EXISTS last_modified
+(
+    resource    TEXT PRIMARY KEY,
+    modified_at INTEGER NOT NULL DEFAULT (CAST(strftime('%s', 'now') AS INTEGER))
+);
+
+INSERT OR IGNORE INTO last_modified (resource)
+VALUES ('todos');
+
+-- SQLite has no statement-level triggers, so each kind of change gets one.
+CREATE TRIGGER IF NOT EXISTS todos_last_modified_insert
+    AFTER INSERT
+    ON todos
+BEGIN
+    UPDATE last_modified SET modified_at = CAST(strftime('%s', 'now') AS INTEGER) WHERE resource = 'todos';
+END;
+
+CREATE TRIGGER IF NOT EXISTS todos_last_modified_update
+    AFTER UPDATE
+    ON todos
+BEGIN
+    UPDATE last_modified SET modified_at = CAST(strftime('%s', 'now') AS INTEGER) WHERE resource = 'todos';
+END;
+
+CREATE TRIGGER IF NOT EXISTS todos_last_modified_delete
+    AFTER DELETE
+    ON todos
+BEGIN
+    UPDATE last_modified SET modified_at = CAST(strftime('%s', 'now') AS INTEGER) WHERE resource = 'todos';
+END;
@@ -0,0 +1,6 @@
+-- Public ids for ID_FORMAT=uuid; rows from before the switch get one at startup.
+-- SQLite can't add a UNIQUE column, so the constraint is a separate index.
+ALTER TABLE todos ADD COLUMN uuid TEXT;
+CREATE UNIQUE INDEX todos_uuid_idx ON todos (uuid);
+ALTER TABLE labels ADD COLUMN uuid TEXT;
+CREATE UNIQUE INDEX labels_uuid_idx ON labels (uuid);
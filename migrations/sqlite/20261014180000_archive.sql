@@ -0,0 +1,14 @@
+-- Completed todos moved out of `todos` once they've been left alone long
+-- enough; AUTOINCREMENT never hands their ids out again.
+CREATE TABLE archived_todos
+(
+    id          INTEGER PRIMARY KEY,
+    uuid        TEXT UNIQUE,
+    text        TEXT    NOT NULL,
+    completed   BOOLEAN NOT NULL,
+    version     INTEGER NOT NULL,
+    updated_at  INTEGER NOT NULL,
+    archived_at INTEGER NOT NULL DEFAULT (CAST(strftime('%s', 'now') AS INTEGER))
+);
+
+CREATE INDEX todos_completed_updated_at_idx ON todos (updated_at) WHERE completed;
@@ -0,0 +1,13 @@
+-- Todo events waiting for delivery to the webhook, written in the same
+-- transaction as the change they describe. Rows are deleted once delivered.
+CREATE TABLE outbox
+(
+    id              INTEGER PRIMARY KEY AUTOINCREMENT,
+    event           TEXT    NOT NULL,
+    created_at      INTEGER NOT NULL DEFAULT (CAST(strftime('%s', 'now') AS INTEGER)),
+    attempts        INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at INTEGER NOT NULL DEFAULT (CAST(strftime('%s', 'now') AS INTEGER)),
+    last_error      TEXT
+);
+
+CREATE INDEX outbox_next_attempt_at_idx ON outbox (next_attempt_at);
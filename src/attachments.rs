@@ -0,0 +1,215 @@
+//! Files attached to todos, kept on disk or in an S3 bucket as
+//! `<todo id>/<name>`. Only the store knows them: there is no table of
+//! attachments, so listing a todo's attachments lists its prefix.
+
+mod disk;
+mod s3;
+
+use std::{fmt::Debug, sync::Arc};
+
+use axum::async_trait;
+use hyper::{body::HttpBody, Body, Uri};
+use serde::Serialize;
+use thiserror::Error;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{
+    config::{AttachmentBackend, AttachmentConfig},
+    events::{EventBus, TodoEvent},
+};
+
+pub use disk::AttachmentStoreForDisk;
+pub use s3::AttachmentStoreForS3;
+
+/// Where the attachment endpoints are mounted.
+pub const PATH: &str = "/attachments";
+
+/// The longest attachment name.
+const MAX_NAME_CHARS: usize = 100;
+
+/// An attachment as listed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Stored {
+    pub name: String,
+    pub size: u64,
+}
+
+/// How an attachment is downloaded.
+#[derive(Debug)]
+pub enum Download {
+    /// Its bytes, sent by this server.
+    Body { size: u64, body: Body },
+    /// A link the client fetches it from, such as a presigned S3 URL.
+    Redirect(Uri),
+}
+
+#[derive(Debug, Error)]
+pub enum UploadError {
+    #[error("the upload is larger than {0} bytes")]
+    TooLarge(u64),
+    #[error("fail read the upload: {0}")]
+    Body(#[from] hyper::Error),
+}
+
+/// The body of an upload, cut off at the configured size.
+#[derive(Debug)]
+pub struct Upload {
+    body: Body,
+    max_bytes: u64,
+    read: u64,
+}
+
+impl Upload {
+    pub fn new(body: Body, max_bytes: u64) -> Self {
+        Self {
+            body,
+            max_bytes,
+            read: 0,
+        }
+    }
+
+    /// The next chunk, `None` at the end.
+    pub async fn chunk(&mut self) -> Result<Option<hyper::body::Bytes>, UploadError> {
+        let Some(chunk) = self.body.data().await.transpose()? else {
+            return Ok(None);
+        };
+        self.read += chunk.len() as u64;
+        if self.read > self.max_bytes {
+            return Err(UploadError::TooLarge(self.max_bytes));
+        }
+        Ok(Some(chunk))
+    }
+}
+
+/// Where attachments are kept. Keys are `<todo id>/<name>`, with names
+/// [`valid_name`] allows.
+#[async_trait]
+pub trait AttachmentStore: Debug + Send + Sync + 'static {
+    /// Stores `upload` under `key`, replacing what was there, and returns
+    /// its size. Fails with an [`UploadError`] when the upload does.
+    async fn put(&self, key: &str, upload: Upload) -> anyhow::Result<u64>;
+    /// The attachments under `prefix`, by name.
+    async fn list(&self, prefix: &str) -> anyhow::Result<Vec<Stored>>;
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Download>>;
+    /// Whether there was something to delete.
+    async fn delete(&self, key: &str) -> anyhow::Result<bool>;
+}
+
+/// Whether `name` can name an attachment: a single path segment that
+/// isn't hidden, without control characters.
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.chars().count() <= MAX_NAME_CHARS
+        && !name.starts_with('.')
+        && !name
+            .chars()
+            .any(|c| c == '/' || c == '\\' || c.is_control())
+}
+
+/// The attachments of every todo, in the store `[attachments]` picks.
+#[derive(Debug, Clone)]
+pub struct Attachments {
+    store: Arc<dyn AttachmentStore>,
+    max_bytes: u64,
+}
+
+impl Attachments {
+    pub fn new(store: Arc<dyn AttachmentStore>, max_bytes: u64) -> Self {
+        Self { store, max_bytes }
+    }
+
+    /// The store of `backend`; `None` when it is unset.
+    pub fn from_config(config: &AttachmentConfig) -> Option<Self> {
+        let store: Arc<dyn AttachmentStore> = match config.backend? {
+            AttachmentBackend::Disk => Arc::new(AttachmentStoreForDisk::new(&config.dir)),
+            AttachmentBackend::S3 => Arc::new(
+                AttachmentStoreForS3::from_config(config)
+                    .unwrap_or_else(|e| panic!("invalid [attachments]: {:#}", e)),
+            ),
+        };
+        Some(Self::new(store, config.max_bytes))
+    }
+
+    fn key(todo: i64, name: &str) -> String {
+        format!("{}/{}", todo, name)
+    }
+
+    pub async fn put(&self, todo: i64, name: &str, body: Body) -> anyhow::Result<Stored> {
+        let upload = Upload::new(body, self.max_bytes);
+        let size = self.store.put(&Self::key(todo, name), upload).await?;
+        Ok(Stored {
+            name: name.to_string(),
+            size,
+        })
+    }
+
+    pub async fn list(&self, todo: i64) -> anyhow::Result<Vec<Stored>> {
+        self.store.list(&format!("{}/", todo)).await
+    }
+
+    pub async fn get(&self, todo: i64, name: &str) -> anyhow::Result<Option<Download>> {
+        self.store.get(&Self::key(todo, name)).await
+    }
+
+    pub async fn delete(&self, todo: i64, name: &str) -> anyhow::Result<bool> {
+        self.store.delete(&Self::key(todo, name)).await
+    }
+
+    /// Deletes the attachments of every todo deleted on `bus`, on a
+    /// background task. Purges don't say which todos they removed, so
+    /// theirs stay.
+    pub fn delete_with_todos(&self, bus: &EventBus) {
+        let attachments = self.clone();
+        let mut events = bus.subscribe();
+        tokio::spawn(async move {
+            loop {
+                let id = match events.recv().await {
+                    Ok(TodoEvent::Deleted { id }) => id,
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(missed)) => {
+                        tracing::warn!("missed {} todo events, attachments may be left", missed);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => return,
+                };
+                if let Err(e) = attachments.delete_all(id).await {
+                    tracing::warn!("failed to delete the attachments of todo {}: {:#}", id, e);
+                }
+            }
+        });
+    }
+
+    async fn delete_all(&self, todo: i64) -> anyhow::Result<()> {
+        for stored in self.list(todo).await? {
+            self.delete(todo, &stored.name).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn allows_plain_names_only() {
+        assert!(valid_name("receipt 2024.pdf"));
+        assert!(valid_name("日本語.txt"));
+        for name in ["", ".", "..", ".hidden", "a/b", "a\\b", "a\nb"] {
+            assert!(!valid_name(name), "{:?}", name);
+        }
+        assert!(!valid_name(&"a".repeat(MAX_NAME_CHARS + 1)));
+    }
+
+    #[tokio::test]
+    async fn cuts_uploads_off_at_the_limit() {
+        let mut upload = Upload::new(Body::from("12345"), 4);
+        assert!(matches!(
+            upload.chunk().await,
+            Err(UploadError::TooLarge(4))
+        ));
+        let mut upload = Upload::new(Body::from("1234"), 4);
+        assert_eq!(upload.chunk().await.unwrap().unwrap(), "1234");
+        assert!(upload.chunk().await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,166 @@
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use axum::async_trait;
+use futures_util::stream;
+use hyper::Body;
+use ring::rand::{SecureRandom, SystemRandom};
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncWriteExt},
+};
+
+use super::{AttachmentStore, Download, Stored, Upload};
+
+/// Bytes read from a file per chunk of a download.
+const CHUNK: usize = 64 * 1024;
+
+/// Attachments as files under a directory, one directory per todo.
+#[derive(Debug, Clone)]
+pub struct AttachmentStoreForDisk {
+    dir: PathBuf,
+}
+
+impl AttachmentStoreForDisk {
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(key)
+    }
+}
+
+#[async_trait]
+impl AttachmentStore for AttachmentStoreForDisk {
+    async fn put(&self, key: &str, mut upload: Upload) -> anyhow::Result<u64> {
+        let path = self.path(key);
+        let dir = path
+            .parent()
+            .context("an attachment key names no directory")?;
+        fs::create_dir_all(dir)
+            .await
+            .with_context(|| format!("fail create {}", dir.display()))?;
+        // hidden while it is written, as names can't start with a dot
+        let mut suffix = [0u8; 8];
+        SystemRandom::new()
+            .fill(&mut suffix)
+            .map_err(|_| anyhow::anyhow!("no randomness for an upload name"))?;
+        let partial = dir.join(format!(".upload-{}", u64::from_be_bytes(suffix)));
+
+        let written = async {
+            let mut file = fs::File::create(&partial).await?;
+            let mut size = 0;
+            while let Some(chunk) = upload.chunk().await? {
+                file.write_all(&chunk).await?;
+                size += chunk.len() as u64;
+            }
+            file.sync_all().await?;
+            fs::rename(&partial, &path).await?;
+            anyhow::Ok(size)
+        }
+        .await;
+        if written.is_err() {
+            let _ = fs::remove_file(&partial).await;
+        }
+        written
+    }
+
+    async fn list(&self, prefix: &str) -> anyhow::Result<Vec<Stored>> {
+        let dir = self.path(prefix);
+        let mut entries = match fs::read_dir(&dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e).with_context(|| format!("fail read {}", dir.display())),
+        };
+        let mut stored = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let Ok(name) = entry.file_name().into_string() else {
+                continue;
+            };
+            let metadata = entry.metadata().await?;
+            if name.starts_with('.') || !metadata.is_file() {
+                continue;
+            }
+            stored.push(Stored {
+                name,
+                size: metadata.len(),
+            });
+        }
+        stored.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(stored)
+    }
+
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Download>> {
+        let file = match fs::File::open(self.path(key)).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let size = file.metadata().await?.len();
+        let chunks = stream::try_unfold(file, |mut file| async move {
+            let mut chunk = vec![0; CHUNK];
+            let read = file.read(&mut chunk).await?;
+            chunk.truncate(read);
+            Ok::<_, std::io::Error>((read > 0).then_some((chunk, file)))
+        });
+        Ok(Some(Download::Body {
+            size,
+            body: Body::wrap_stream(chunks),
+        }))
+    }
+
+    async fn delete(&self, key: &str) -> anyhow::Result<bool> {
+        match fs::remove_file(self.path(key)).await {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::attachments::UploadError;
+
+    #[tokio::test]
+    async fn keeps_attachments_as_files() {
+        let dir = std::env::temp_dir().join(format!("my-todo-attachments-{}", std::process::id()));
+        let store = AttachmentStoreForDisk::new(&dir);
+        let upload = Upload::new(Body::from("milk, eggs"), 100);
+        assert_eq!(store.put("1/list.txt", upload).await.unwrap(), 10);
+        let upload = Upload::new(Body::from("too long for the limit"), 8);
+        let e = store.put("1/big.txt", upload).await.unwrap_err();
+        assert!(matches!(
+            e.downcast_ref::<UploadError>(),
+            Some(UploadError::TooLarge(8))
+        ));
+
+        let listed = store.list("1/").await.unwrap();
+        assert_eq!(
+            listed,
+            vec![Stored {
+                name: "list.txt".to_string(),
+                size: 10
+            }],
+            "the failed upload leaves nothing"
+        );
+        assert!(store.list("2/").await.unwrap().is_empty());
+        let Some(Download::Body { size, body }) = store.get("1/list.txt").await.unwrap() else {
+            panic!("no body");
+        };
+        assert_eq!(size, 10);
+        assert_eq!(hyper::body::to_bytes(body).await.unwrap(), "milk, eggs");
+
+        assert!(store.delete("1/list.txt").await.unwrap());
+        assert!(!store.delete("1/list.txt").await.unwrap());
+        assert!(store.get("1/list.txt").await.unwrap().is_none());
+        fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
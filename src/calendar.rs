@@ -0,0 +1,810 @@
+use std::{
+    collections::HashSet,
+    env,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+use hyper::{header, Body, Method, Request, StatusCode, Uri};
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{
+    client::{self, HttpsConnector},
+    events::{EventBus, TodoEvent},
+    handlers::{feed::rfc3339, percent_encode},
+    repositories::{
+        date::Date,
+        id::IdFormat,
+        link::{linked_todo, LinkRepository},
+        todo::{CreateTodo, TodoFilter, TodoRepository, UpdateTodo},
+    },
+};
+
+/// Longest text a todo may have; longer event titles are cut.
+const MAX_TEXT: usize = 100;
+
+/// A call to Google that takes longer than this counts as failed.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often the calendar is asked for the events changed since.
+const POLL_EVERY: Duration = Duration::from_secs(60);
+
+/// An access token is refreshed once it has less than this left.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+
+/// An event as the Calendar API lists it.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Event {
+    pub id: String,
+    /// `confirmed`, `tentative`, or `cancelled` once it was removed.
+    #[serde(default)]
+    pub status: String,
+    #[serde(default)]
+    pub summary: String,
+    #[serde(default)]
+    pub start: Option<EventTime>,
+    #[serde(default)]
+    pub end: Option<EventTime>,
+}
+
+/// When an event starts or ends: a `date` for all-day events, a
+/// `dateTime` for the others.
+#[derive(Debug, Clone, Deserialize)]
+pub struct EventTime {
+    pub date: Option<String>,
+    #[serde(rename = "dateTime")]
+    pub date_time: Option<String>,
+}
+
+impl EventTime {
+    /// The day, `YYYY-MM-DD`, without the offset of a time.
+    fn day(&self) -> Option<&str> {
+        self.date
+            .as_deref()
+            .or(self.date_time.as_deref())
+            .and_then(|at| at.get(..10))
+    }
+}
+
+impl Event {
+    /// Whether it ended before `today` (`YYYY-MM-DD`). Days are compared
+    /// without their offsets, which is exact enough to skip past events.
+    fn ended(&self, today: &str) -> bool {
+        self.end
+            .as_ref()
+            .and_then(EventTime::day)
+            .is_some_and(|day| day < today)
+    }
+
+    /// The day it starts, which its todo is due on.
+    fn due(&self) -> Option<Date> {
+        self.start.as_ref()?.day()?.parse().ok()
+    }
+}
+
+/// A page of `events.list`; the last one has the token of the next sync.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Events {
+    #[serde(default)]
+    items: Vec<Event>,
+    next_page_token: Option<String>,
+    next_sync_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Token {
+    access_token: String,
+    expires_in: u64,
+}
+
+/// Keeps the events of one Google Calendar in step with todos. Which todo
+/// stands for which event is kept in a [`LinkRepository`], along with the
+/// token the next poll carries on from. Upcoming events become todos due
+/// on the day they start, removing an event completes its todo and
+/// completing a todo removes its event. The other way round, an open todo
+/// with a due date gets an all-day event on that day, moved along with it;
+/// undated todos stay off the calendar.
+#[derive(Clone)]
+pub struct CalendarSync {
+    calendar: String,
+    client_id: String,
+    client_secret: String,
+    refresh_token: String,
+    api: String,
+    oauth: String,
+    poll: Duration,
+    /// How the todos it adds are addressed.
+    format: IdFormat,
+    /// The access token and when it expires, once refreshed.
+    access: Arc<Mutex<Option<(String, Instant)>>>,
+    client: hyper::Client<HttpsConnector>,
+}
+
+impl std::fmt::Debug for CalendarSync {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CalendarSync")
+            .field("calendar", &self.calendar)
+            .field("client_id", &self.client_id)
+            .field("api", &self.api)
+            .field("poll", &self.poll)
+            .finish_non_exhaustive()
+    }
+}
+
+impl CalendarSync {
+    pub fn new(calendar: &str, client_id: &str, client_secret: &str, refresh_token: &str) -> Self {
+        Self {
+            calendar: calendar.to_string(),
+            client_id: client_id.to_string(),
+            client_secret: client_secret.to_string(),
+            refresh_token: refresh_token.to_string(),
+            api: "https://www.googleapis.com/calendar/v3".to_string(),
+            oauth: "https://oauth2.googleapis.com/token".to_string(),
+            poll: POLL_EVERY,
+            format: IdFormat::default(),
+            access: Arc::default(),
+            client: client::https(),
+        }
+    }
+
+    /// Calls another Calendar API than Google's, e.g. through a proxy.
+    pub fn with_api(mut self, api: &str) -> Self {
+        self.api = api.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Refreshes the access token at `oauth` instead of Google's.
+    pub fn with_oauth(mut self, oauth: &str) -> Self {
+        self.oauth = oauth.to_string();
+        self
+    }
+
+    pub fn with_poll(mut self, poll: Duration) -> Self {
+        self.poll = poll;
+        self
+    }
+
+    pub fn with_format(mut self, format: IdFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Syncs the calendar `GOOGLE_CALENDAR_ID`, as the OAuth client
+    /// `GOOGLE_CLIENT_ID` with `GOOGLE_CLIENT_SECRET` and the stored
+    /// `GOOGLE_REFRESH_TOKEN`, every `GOOGLE_CALENDAR_POLL_SECS` seconds
+    /// (60 by default). `GOOGLE_CALENDAR_API_URL` and
+    /// `GOOGLE_OAUTH_TOKEN_URL` replace Google's endpoints. `None` unless
+    /// the first four are set.
+    pub fn from_env() -> Option<Self> {
+        let var = |name| {
+            env::var(name)
+                .ok()
+                .filter(|value: &String| !value.is_empty())
+        };
+        let mut sync = Self::new(
+            &var("GOOGLE_CALENDAR_ID")?,
+            &var("GOOGLE_CLIENT_ID")?,
+            &var("GOOGLE_CLIENT_SECRET")?,
+            &var("GOOGLE_REFRESH_TOKEN")?,
+        );
+        if let Some(secs) = var("GOOGLE_CALENDAR_POLL_SECS") {
+            let secs: u64 = secs
+                .parse()
+                .ok()
+                .filter(|secs| *secs > 0)
+                .unwrap_or_else(|| panic!("invalid [GOOGLE_CALENDAR_POLL_SECS]: {}", secs));
+            sync = sync.with_poll(Duration::from_secs(secs));
+        }
+        if let Some(api) = var("GOOGLE_CALENDAR_API_URL") {
+            sync = sync.with_api(&api);
+        }
+        if let Some(oauth) = var("GOOGLE_OAUTH_TOKEN_URL") {
+            sync = sync.with_oauth(&oauth);
+        }
+        Some(sync)
+    }
+
+    /// What the links to this calendar's events are kept under.
+    fn service(&self) -> String {
+        format!("gcal:{}", self.calendar)
+    }
+
+    /// Brings the todo of `event` up to date: one is added for an event
+    /// that hasn't ended before `today`, an existing one follows the title
+    /// and the day, and it is completed once the event is removed. Returns
+    /// the todo it completed, whose event needs no removing.
+    pub async fn apply<T: TodoRepository>(
+        &self,
+        todos: &T,
+        links: &dyn LinkRepository,
+        event: &Event,
+        today: &str,
+    ) -> anyhow::Result<Option<i64>> {
+        let service = self.service();
+        let text: String = event.summary.trim().chars().take(MAX_TEXT).collect();
+        let removed = event.status == "cancelled";
+        let linked = match links.todo(&service, &event.id).await? {
+            Some(id) => linked_todo(todos, id).await?,
+            None => None,
+        };
+        match linked {
+            Some(todo) => {
+                let text = (todo.text() != text && !text.is_empty() && !removed).then_some(text);
+                let completed = (removed && !todo.completed()).then_some(true);
+                let due = event
+                    .due()
+                    .filter(|due| !removed && todo.due() != Some(due));
+                if text.is_some() || completed.is_some() || due.is_some() {
+                    let mut update = UpdateTodo::new(text, completed);
+                    if let Some(due) = due {
+                        update = update.with_due(Some(due));
+                    }
+                    todos.update(todo.id(), update).await?;
+                }
+                return Ok(completed.map(|_| todo.id()));
+            }
+            None if !removed && !event.ended(today) && !text.is_empty() => {
+                let todo = todos
+                    .create(
+                        CreateTodo::new(text)
+                            .with_due(event.due())
+                            .with_format(self.format),
+                    )
+                    .await?;
+                links.link(&service, &event.id, todo.id()).await?;
+                tracing::info!("added todo {} for event {}", todo.id(), event.id);
+            }
+            None => {}
+        }
+        Ok(None)
+    }
+
+    /// A current access token, refreshed with the stored refresh token.
+    async fn access_token(&self) -> anyhow::Result<String> {
+        if let Some((token, expires)) = &*self.access.lock().unwrap_or_else(|e| e.into_inner()) {
+            if Instant::now() + EXPIRY_MARGIN < *expires {
+                return Ok(token.clone());
+            }
+        }
+        let form = serde_urlencoded::to_string([
+            ("client_id", self.client_id.as_str()),
+            ("client_secret", self.client_secret.as_str()),
+            ("refresh_token", self.refresh_token.as_str()),
+            ("grant_type", "refresh_token"),
+        ])?;
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri(self.oauth.parse::<Uri>()?)
+            .header(
+                header::CONTENT_TYPE,
+                mime::APPLICATION_WWW_FORM_URLENCODED.as_ref(),
+            )
+            .body(Body::from(form))?;
+        let res = client::request(&self.client, req, REQUEST_TIMEOUT).await?;
+        let status = res.status();
+        let body = hyper::body::to_bytes(res.into_body()).await?;
+        if !status.is_success() {
+            anyhow::bail!("google refused the refresh token: {}", status);
+        }
+        let token: Token = serde_json::from_slice(&body)?;
+        let expires = Instant::now() + Duration::from_secs(token.expires_in);
+        *self.access.lock().unwrap_or_else(|e| e.into_inner()) =
+            Some((token.access_token.clone(), expires));
+        Ok(token.access_token)
+    }
+
+    async fn call(
+        &self,
+        method: Method,
+        path: &str,
+        body: Body,
+    ) -> anyhow::Result<(StatusCode, Vec<u8>)> {
+        let req = Request::builder()
+            .method(method)
+            .uri(format!("{}{}", self.api, path).parse::<Uri>()?)
+            .header(
+                header::AUTHORIZATION,
+                format!("Bearer {}", self.access_token().await?),
+            )
+            .header(header::ACCEPT, mime::APPLICATION_JSON.as_ref())
+            .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .body(body)?;
+        let res = client::request(&self.client, req, REQUEST_TIMEOUT).await?;
+        let status = res.status();
+        let body = hyper::body::to_bytes(res.into_body()).await?;
+        Ok((status, body.to_vec()))
+    }
+
+    fn events_path(&self) -> String {
+        format!(
+            "/calendars/{}/events",
+            percent_encode(&self.calendar, false)
+        )
+    }
+
+    /// The events changed since `sync_token`, every event without one, and
+    /// the token of the next sync. `None` when the token has expired and a
+    /// full sync is needed.
+    async fn changes(
+        &self,
+        sync_token: Option<&str>,
+    ) -> anyhow::Result<Option<(Vec<Event>, String)>> {
+        let mut events = vec![];
+        let mut page_token = None;
+        loop {
+            let mut query = vec![("singleEvents", "true".to_string())];
+            if let Some(token) = sync_token {
+                query.push(("syncToken", token.to_string()));
+            }
+            if let Some(page) = page_token.take() {
+                query.push(("pageToken", page));
+            }
+            let path = format!(
+                "{}?{}",
+                self.events_path(),
+                serde_urlencoded::to_string(&query)?
+            );
+            let (status, body) = self.call(Method::GET, &path, Body::empty()).await?;
+            if status == StatusCode::GONE {
+                return Ok(None);
+            }
+            if !status.is_success() {
+                anyhow::bail!("google calendar answered {}", status);
+            }
+            let page: Events = serde_json::from_slice(&body)?;
+            events.extend(page.items);
+            match (page.next_page_token, page.next_sync_token) {
+                (Some(next), _) => page_token = Some(next),
+                (None, Some(sync_token)) => return Ok(Some((events, sync_token))),
+                (None, None) => anyhow::bail!("google calendar gave no sync token"),
+            }
+        }
+    }
+
+    fn event_path(&self, id: &str) -> String {
+        format!("{}/{}", self.events_path(), percent_encode(id, false))
+    }
+
+    async fn remove(&self, id: &str) -> anyhow::Result<()> {
+        match self
+            .call(Method::DELETE, &self.event_path(id), Body::empty())
+            .await?
+            .0
+        {
+            // Already removed.
+            StatusCode::NOT_FOUND | StatusCode::GONE => Ok(()),
+            status if status.is_success() => Ok(()),
+            status => anyhow::bail!("google calendar answered {}", status),
+        }
+    }
+
+    /// Gives the open todo `id` an all-day event on its due day, or moves
+    /// the event it has there when it starts on another day. Undated and
+    /// completed todos are left alone.
+    async fn follow_due<T: TodoRepository>(
+        &self,
+        todos: &T,
+        links: &dyn LinkRepository,
+        id: i64,
+    ) -> anyhow::Result<()> {
+        let todo = todos.find(id).await?;
+        let Some(due) = todo.due().filter(|_| !todo.completed()) else {
+            return Ok(());
+        };
+        let days = json!({
+            "start": { "date": due },
+            "end": { "date": Date::from_days(due.days() + 1) },
+        });
+        let service = self.service();
+        match links.externals(&service, id).await?.first() {
+            Some(event) => {
+                let path = self.event_path(event);
+                let (status, body) = self.call(Method::GET, &path, Body::empty()).await?;
+                if !status.is_success() {
+                    anyhow::bail!("google calendar answered {}", status);
+                }
+                let current: Event = serde_json::from_slice(&body)?;
+                if current.due().as_ref() == Some(due) {
+                    return Ok(());
+                }
+                let (status, _) = self
+                    .call(Method::PATCH, &path, Body::from(days.to_string()))
+                    .await?;
+                if !status.is_success() {
+                    anyhow::bail!("google calendar answered {}", status);
+                }
+                tracing::info!("moved event {} to {} with todo {}", event, due, id);
+            }
+            None => {
+                let mut event = days;
+                event["summary"] = json!(todo.text());
+                let (status, body) = self
+                    .call(
+                        Method::POST,
+                        &self.events_path(),
+                        Body::from(event.to_string()),
+                    )
+                    .await?;
+                if !status.is_success() {
+                    anyhow::bail!("google calendar answered {}", status);
+                }
+                let event: Event = serde_json::from_slice(&body)?;
+                links.link(&service, &event.id, id).await?;
+                tracing::info!("added event {} for todo {}", event.id, id);
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the events changed since the stored sync token, moving it
+    /// on; a full sync replaces a missing or expired one. The todos
+    /// completed for removed events join `completed`.
+    async fn sync<T: TodoRepository>(
+        &self,
+        todos: &T,
+        links: &dyn LinkRepository,
+        completed: &mut HashSet<i64>,
+    ) -> anyhow::Result<()> {
+        let service = self.service();
+        let sync_token = links.sync_token(&service).await?;
+        let (events, next) = match self.changes(sync_token.as_deref()).await? {
+            Some(changes) => changes,
+            None => {
+                tracing::info!("the calendar sync token expired, syncing every event");
+                self.changes(None)
+                    .await?
+                    .ok_or_else(|| anyhow::anyhow!("google calendar refused a full sync"))?
+            }
+        };
+        let today = rfc3339(SystemTime::now());
+        for event in &events {
+            match self.apply(todos, links, event, &today[..10]).await {
+                Ok(Some(id)) => {
+                    completed.insert(id);
+                }
+                Ok(None) => {}
+                Err(e) => tracing::warn!("failed to sync event {}: {:#}", event.id, e),
+            }
+        }
+        links.set_sync_token(&service, &next).await?;
+        Ok(())
+    }
+
+    /// Polls the calendar for changed events, removes the event of every
+    /// todo completed here and adds or moves the events of dated ones, on a
+    /// background task. The sync token is stored with the links, so a
+    /// restart carries on where the last poll left off.
+    pub fn spawn<T: TodoRepository>(
+        self,
+        todos: T,
+        links: Arc<dyn LinkRepository>,
+        bus: &EventBus,
+    ) {
+        tracing::info!(
+            "syncing the google calendar {} every {:?}",
+            self.calendar,
+            self.poll
+        );
+        let mut events = bus.subscribe();
+        tokio::spawn(async move {
+            let filter = TodoFilter {
+                completed: Some(true),
+                ..TodoFilter::default()
+            };
+            let mut completed: HashSet<i64> = match todos.all(&filter).await {
+                Ok(todos) => todos.iter().map(|todo| todo.id()).collect(),
+                Err(e) => {
+                    tracing::warn!("failed to read completed todos: {:#}", e);
+                    HashSet::new()
+                }
+            };
+            let mut ticks = tokio::time::interval(self.poll);
+            loop {
+                tokio::select! {
+                    _ = ticks.tick() => {
+                        if let Err(e) = self.sync(&todos, &*links, &mut completed).await {
+                            tracing::warn!("failed to sync the calendar: {:#}", e);
+                        }
+                    }
+                    event = events.recv() => {
+                        let id = match event {
+                            Ok(TodoEvent::Created { id }) => {
+                                if let Err(e) = self.follow_due(&todos, &*links, id).await {
+                                    tracing::warn!("failed to add the event of todo {}: {:#}", id, e);
+                                }
+                                continue;
+                            }
+                            Ok(TodoEvent::Updated { id }) => id,
+                            Ok(TodoEvent::Deleted { id }) => {
+                                completed.remove(&id);
+                                if let Err(e) = links.unlink(id).await {
+                                    tracing::warn!("failed to unlink todo {}: {:#}", id, e);
+                                }
+                                continue;
+                            }
+                            Ok(_) => continue,
+                            Err(RecvError::Lagged(missed)) => {
+                                tracing::warn!("missed {} todo events to sync", missed);
+                                continue;
+                            }
+                            Err(RecvError::Closed) => return,
+                        };
+                        if let Err(e) = self.completed(&todos, &*links, &mut completed, id).await {
+                            tracing::warn!("failed to remove the event of todo {}: {:#}", id, e);
+                        }
+                        if let Err(e) = self.follow_due(&todos, &*links, id).await {
+                            tracing::warn!("failed to move the event of todo {}: {:#}", id, e);
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    /// Removes the event of todo `id` when the update completed it.
+    async fn completed<T: TodoRepository>(
+        &self,
+        todos: &T,
+        links: &dyn LinkRepository,
+        completed: &mut HashSet<i64>,
+        id: i64,
+    ) -> anyhow::Result<()> {
+        let todo = todos.find(id).await?;
+        if !todo.completed() {
+            completed.remove(&id);
+            return Ok(());
+        }
+        if !completed.insert(id) {
+            return Ok(());
+        }
+        for event in links.externals(&self.service(), id).await? {
+            self.remove(&event).await?;
+            tracing::info!("removed event {} with todo {}", event, id);
+        }
+        Ok(())
+    }
+}
+
+/// Syncs a calendar on a background task when `GOOGLE_CALENDAR_ID` and the
+/// rest are set; see [`CalendarSync::from_env`]. Added todos are addressed
+/// as `format` does.
+pub fn spawn_from_env<T: TodoRepository>(
+    todos: T,
+    links: Arc<dyn LinkRepository>,
+    format: IdFormat,
+    bus: &EventBus,
+) {
+    if let Some(sync) = CalendarSync::from_env() {
+        sync.with_format(format).spawn(todos, links, bus);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use axum::{
+        extract::{Extension, Path, Query},
+        routing::{get, post},
+        Json, Router,
+    };
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::{
+        events::Publishing,
+        repositories::{
+            link::LinkRepositoryForSqlite, todo::TodoRepositoryForSqlite, Migrations, PoolSettings,
+        },
+    };
+
+    /// The changes made through the API, as `METHOD id` with the day an
+    /// event was put on.
+    type Calls = Arc<Mutex<Vec<String>>>;
+
+    fn event(id: &str, summary: &str, status: &str, end: &str) -> Event {
+        serde_json::from_value(json!({
+            "id": id,
+            "summary": summary,
+            "status": status,
+            "end": { "date": end },
+        }))
+        .unwrap()
+    }
+
+    /// A Calendar API listing two upcoming events and a past one, then
+    /// the first as removed, recording the events added, moved and removed
+    /// through it. Every event it is asked for starts on 2999-01-01.
+    async fn google() -> (String, Calls) {
+        let calls = Calls::default();
+        let app = Router::new()
+            .route(
+                "/token",
+                post(|form: String| async move {
+                    assert!(form.contains("grant_type=refresh_token"), "{}", form);
+                    Json(json!({ "access_token": "ya29", "expires_in": 3599 }))
+                }),
+            )
+            .route(
+                "/calendars/garden%40example.com/events",
+                get(|Query(query): Query<HashMap<String, String>>| async move {
+                    let page: Value = match query.get("syncToken").map(String::as_str) {
+                        None => json!({
+                            "items": [
+                                { "id": "dentist", "status": "confirmed", "summary": "Dentist", "end": { "date": "2999-01-01" } },
+                                { "id": "seeds", "status": "confirmed", "summary": "Buy seeds", "start": { "dateTime": "2999-01-01T09:00:00+09:00" }, "end": { "dateTime": "2999-01-01T10:00:00+09:00" } },
+                                { "id": "old", "status": "confirmed", "summary": "Long gone", "end": { "date": "2000-01-01" } },
+                            ],
+                            "nextSyncToken": "s1",
+                        }),
+                        Some(_) => json!({
+                            "items": [{ "id": "dentist", "status": "cancelled" }],
+                            "nextSyncToken": "s2",
+                        }),
+                    };
+                    Json(page)
+                }),
+            )
+            .route(
+                "/calendars/garden%40example.com/events",
+                post(
+                    |Json(event): Json<Value>, Extension(calls): Extension<Calls>| async move {
+                        assert_eq!(event["end"]["date"], "2999-01-02");
+                        let day = event["start"]["date"].as_str().unwrap();
+                        calls.lock().unwrap().push(format!("POST {}", day));
+                        Json(json!({ "id": "new", "summary": event["summary"] }))
+                    },
+                ),
+            )
+            .route(
+                "/calendars/garden%40example.com/events/:id",
+                get(|Path(id): Path<String>| async move {
+                    Json(json!({ "id": id, "start": { "date": "2999-01-01" } }))
+                })
+                .patch(
+                    |Path(id): Path<String>,
+                     Json(event): Json<Value>,
+                     Extension(calls): Extension<Calls>| async move {
+                        let day = event["start"]["date"].as_str().unwrap();
+                        calls.lock().unwrap().push(format!("PATCH {} {}", id, day));
+                        Json(json!({ "id": id }))
+                    },
+                )
+                .delete(
+                    |Path(id): Path<String>, Extension(calls): Extension<Calls>| async move {
+                        calls.lock().unwrap().push(format!("DELETE {}", id));
+                        StatusCode::NO_CONTENT
+                    },
+                ),
+            )
+            .layer(Extension(calls.clone()));
+        let addr = crate::testing::serve(app);
+        (format!("http://{}", addr), calls)
+    }
+
+    fn sync(api: &str) -> CalendarSync {
+        CalendarSync::new("garden@example.com", "id", "secret", "refresh")
+            .with_api(api)
+            .with_oauth(&format!("{}/token", api))
+            .with_poll(Duration::from_millis(50))
+    }
+
+    async fn sqlite() -> (TodoRepositoryForSqlite, Arc<LinkRepositoryForSqlite>) {
+        let pool = crate::repositories::connect_sqlite(
+            "sqlite::memory:",
+            &PoolSettings::default(),
+            Migrations::Apply,
+        )
+        .await
+        .expect("failed open sqlite");
+        (
+            TodoRepositoryForSqlite::new(pool.clone()),
+            Arc::new(LinkRepositoryForSqlite::new(pool)),
+        )
+    }
+
+    #[tokio::test]
+    async fn follows_the_upcoming_events() {
+        let (todos, links) = sqlite().await;
+        let sync = CalendarSync::new("garden@example.com", "id", "secret", "refresh")
+            .with_format(IdFormat::Uuid);
+        let today = "2024-06-01";
+
+        let past = event("old", "Long gone", "confirmed", "2024-05-31");
+        sync.apply(&todos, &*links, &past, today).await.unwrap();
+        assert!(todos.all(&TodoFilter::default()).await.unwrap().is_empty());
+
+        let upcoming = event("water", "Water", "confirmed", "2024-06-01");
+        sync.apply(&todos, &*links, &upcoming, today).await.unwrap();
+        let mut renamed = event("water", "Water the roses", "confirmed", "2024-06-02");
+        renamed.start = renamed.end.clone();
+        sync.apply(&todos, &*links, &renamed, today).await.unwrap();
+        let all = todos.all_with_labels(&TodoFilter::default()).await.unwrap();
+        assert_eq!(all.len(), 1);
+        assert_eq!(all[0].todo.text(), "Water the roses");
+        assert_eq!(all[0].todo.due(), "2024-06-02".parse().ok().as_ref());
+        assert!(!all[0].todo.completed());
+        assert!(all[0].todo.uuid().is_some());
+        assert!(all[0].labels.is_empty());
+        let service = "gcal:garden@example.com";
+        assert_eq!(
+            links.todo(service, "water").await.unwrap(),
+            Some(all[0].todo.id())
+        );
+
+        let removed = event("water", "", "cancelled", "2024-06-01");
+        sync.apply(&todos, &*links, &removed, today).await.unwrap();
+        let all = todos.all(&TodoFilter::default()).await.unwrap();
+        assert_eq!(all[0].text(), "Water the roses");
+        assert!(all[0].completed());
+    }
+
+    #[tokio::test]
+    async fn imports_events_and_removes_the_ones_completed() {
+        let (api, calls) = google().await;
+        let (todos, links) = sqlite().await;
+        let bus = EventBus::new(16);
+        let todos = Publishing::new(todos, bus.clone());
+        sync(&api).spawn(todos.clone(), links.clone(), &bus);
+
+        let mut imported = vec![];
+        for _ in 0..50 {
+            imported = todos.all(&TodoFilter::default()).await.unwrap();
+            if imported.iter().any(|todo| todo.completed()) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let mut texts: Vec<_> = imported
+            .iter()
+            .map(|todo| (todo.text(), todo.completed()))
+            .collect();
+        texts.sort();
+        assert_eq!(texts, [("Buy seeds", false), ("Dentist", true)]);
+        let token = links.sync_token("gcal:garden@example.com").await.unwrap();
+        assert_eq!(token.as_deref(), Some("s2"));
+
+        let seeds = imported.iter().find(|todo| !todo.completed()).unwrap();
+        assert_eq!(seeds.due(), "2999-01-01".parse().ok().as_ref());
+        todos
+            .update(seeds.id(), UpdateTodo::new(None, Some(true)))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(*calls.lock().unwrap(), vec!["DELETE seeds".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn adds_and_moves_the_events_of_dated_todos() {
+        let (api, calls) = google().await;
+        let (todos, links) = sqlite().await;
+        let bus = EventBus::new(16);
+        let todos = Publishing::new(todos, bus.clone());
+        sync(&api)
+            .with_poll(Duration::from_secs(60))
+            .spawn(todos.clone(), links.clone(), &bus);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        todos
+            .create(CreateTodo::new("Undated".to_string()))
+            .await
+            .unwrap();
+        let day = |day: &str| day.parse::<Date>().ok();
+        let rent = CreateTodo::new("Rent".to_string()).with_due(day("2999-01-01"));
+        let rent = todos.create(rent).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let linked = links.externals("gcal:garden@example.com", rent.id());
+        assert_eq!(linked.await.unwrap(), vec!["new"]);
+
+        let moved = UpdateTodo::new(None, None).with_due(day("2999-02-01"));
+        todos.update(rent.id(), moved).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec!["POST 2999-01-01", "PATCH new 2999-02-01"]
+        );
+    }
+}
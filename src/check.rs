@@ -0,0 +1,96 @@
+use anyhow::Context;
+
+use crate::{
+    config::AppConfig,
+    layers::{
+        access_log::AccessLog, body_limit::BodyLimit, client_ip::TrustedProxies,
+        compression::compression_from_config, cors::AllowedOrigins, degraded::StaleResponses,
+        rate_limit::RateLimiter, timeout::Timeouts,
+    },
+    repositories::{self, Migrations, PoolSettings, StorageBackend},
+    server::BindAddr,
+    spa::Spa,
+};
+
+/// `--check`: validates the settings the server is started with, connects
+/// to the database and verifies every migration was applied unchanged,
+/// then returns instead of listening. Nothing is migrated; MongoDB indexes
+/// and a missing DynamoDB table are created as on startup. Invalid
+/// settings panic like they would at startup.
+pub async fn run(
+    config: &AppConfig,
+    backend: StorageBackend,
+    database_url: &str,
+    pool_settings: &PoolSettings,
+) -> anyhow::Result<()> {
+    Timeouts::from_config(&config.server);
+    BodyLimit::from_config(&config.server);
+    AccessLog::from_config(&config.server);
+    Spa::from_config(&config.server);
+    BindAddr::admin_from_config(&config.server);
+    TrustedProxies::from_config(&config.server);
+    RateLimiter::from_config(&config.features);
+    StaleResponses::from_config(&config.features);
+    compression_from_config(&config.features);
+    AllowedOrigins::from_config(&config.cors);
+    tracing::info!("configuration is valid");
+
+    match backend {
+        StorageBackend::Memory => {
+            let snapshot = database_url.strip_prefix("memory:").unwrap_or_default();
+            if !snapshot.is_empty() {
+                repositories::todo::TodoRepositoryForMemory::with_snapshot(snapshot)
+                    .await
+                    .with_context(|| format!("fail load snapshot [{}]", snapshot))?;
+            }
+        }
+        StorageBackend::Postgres => {
+            let pool =
+                repositories::connect_postgres(database_url, pool_settings, Migrations::Verify)
+                    .await
+                    .context("fail connect database")?;
+            pool.close().await;
+            if let Some(read_url) = &config.database.read_url {
+                let pool = repositories::connect_postgres_replica(read_url, pool_settings)
+                    .await
+                    .context("fail connect read replica")?;
+                pool.close().await;
+            }
+        }
+        StorageBackend::Sqlite => {
+            let pool =
+                repositories::connect_sqlite(database_url, pool_settings, Migrations::Verify)
+                    .await
+                    .context("fail open sqlite")?;
+            pool.close().await;
+        }
+        StorageBackend::MySql => {
+            #[cfg(feature = "mysql")]
+            repositories::connect_mysql(database_url, pool_settings, Migrations::Verify)
+                .await
+                .context("fail connect mysql")?
+                .close()
+                .await;
+            #[cfg(not(feature = "mysql"))]
+            anyhow::bail!("MySQL support needs a build with `--features mysql`");
+        }
+        StorageBackend::MongoDb => {
+            #[cfg(feature = "mongodb")]
+            repositories::connect_mongo(database_url)
+                .await
+                .context("fail connect mongodb")?;
+            #[cfg(not(feature = "mongodb"))]
+            anyhow::bail!("MongoDB support needs a build with `--features mongodb`");
+        }
+        StorageBackend::DynamoDb => {
+            #[cfg(feature = "dynamodb")]
+            repositories::connect_dynamo(database_url)
+                .await
+                .context("fail connect dynamodb")?;
+            #[cfg(not(feature = "dynamodb"))]
+            anyhow::bail!("DynamoDB support needs a build with `--features dynamodb`");
+        }
+    }
+    tracing::info!("database is reachable and up to date");
+    Ok(())
+}
@@ -0,0 +1,158 @@
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use hyper::{
+    client::{
+        connect::{Connected, Connection},
+        HttpConnector,
+    },
+    service::Service,
+    Body, Client, Request, Response, Uri,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::TcpStream,
+};
+use tokio_rustls::{
+    client::TlsStream,
+    rustls::ClientConfig,
+    webpki::{DNSName, DNSNameRef},
+    TlsConnector,
+};
+
+/// A client for calls to other services, over `http` or `https`; servers
+/// are verified against the Mozilla root certificates.
+pub fn https() -> Client<HttpsConnector, Body> {
+    Client::builder().build(HttpsConnector::new())
+}
+
+/// Sends `req` on `client`, failing when there is no answer in `timeout`.
+pub async fn request(
+    client: &Client<HttpsConnector, Body>,
+    req: Request<Body>,
+    timeout: Duration,
+) -> anyhow::Result<Response<Body>> {
+    Ok(tokio::time::timeout(timeout, client.request(req))
+        .await
+        .map_err(|_| anyhow::anyhow!("no answer within {:?}", timeout))??)
+}
+
+/// A TLS client verifying servers against the Mozilla root certificates,
+/// for the protocols other than HTTP.
+pub fn tls() -> TlsConnector {
+    let mut config = ClientConfig::new();
+    config
+        .root_store
+        .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+    TlsConnector::from(Arc::new(config))
+}
+
+#[derive(Clone)]
+pub struct HttpsConnector {
+    http: HttpConnector,
+    tls: TlsConnector,
+}
+
+impl HttpsConnector {
+    fn new() -> Self {
+        let mut http = HttpConnector::new();
+        http.enforce_http(false);
+        Self { http, tls: tls() }
+    }
+}
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+impl Service<Uri> for HttpsConnector {
+    type Response = Stream;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Stream, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+        self.http.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let domain = match uri.scheme_str() {
+            Some("https") => Some(
+                uri.host()
+                    .and_then(|host| DNSNameRef::try_from_ascii_str(host).ok())
+                    .map(DNSName::from),
+            ),
+            _ => None,
+        };
+        let connecting = self.http.call(uri);
+        let tls = self.tls.clone();
+        Box::pin(async move {
+            let tcp = connecting.await?;
+            match domain {
+                None => Ok(Stream::Plain(tcp)),
+                Some(Some(domain)) => {
+                    let tls = tls.connect(domain.as_ref(), tcp).await?;
+                    Ok(Stream::Tls(Box::new(tls)))
+                }
+                Some(None) => Err("https needs a DNS name to verify".into()),
+            }
+        })
+    }
+}
+
+pub enum Stream {
+    Plain(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+impl Connection for Stream {
+    fn connected(&self) -> Connected {
+        match self {
+            Stream::Plain(tcp) => tcp.connected(),
+            Stream::Tls(tls) => tls.get_ref().0.connected(),
+        }
+    }
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Plain(tcp) => Pin::new(tcp).poll_read(cx, buf),
+            Stream::Tls(tls) => Pin::new(tls).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Stream::Plain(tcp) => Pin::new(tcp).poll_write(cx, buf),
+            Stream::Tls(tls) => Pin::new(tls).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Plain(tcp) => Pin::new(tcp).poll_flush(cx),
+            Stream::Tls(tls) => Pin::new(tls).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Plain(tcp) => Pin::new(tcp).poll_shutdown(cx),
+            Stream::Tls(tls) => Pin::new(tls).poll_shutdown(cx),
+        }
+    }
+}
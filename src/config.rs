@@ -0,0 +1,480 @@
+use std::{collections::BTreeMap, env, path::PathBuf};
+
+use figment::{
+    providers::{Format, Serialized, Toml},
+    value::Value,
+    Figment,
+};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::repositories::id::IdFormat;
+
+/// The file read when `CONFIG_FILE` is unset, if it exists.
+const DEFAULT_FILE: &str = "my-todo.toml";
+
+/// Environment variables overriding a setting of the file, by its key.
+const ENV: &[(&str, &str)] = &[
+    ("DATABASE_URL", "database.url"),
+    ("DATABASE_READ_URL", "database.read_url"),
+    ("STORAGE_BACKEND", "database.backend"),
+    ("AUTO_MIGRATE", "database.auto_migrate"),
+    ("DB_MAX_CONNECTIONS", "database.max_connections"),
+    ("DB_MIN_CONNECTIONS", "database.min_connections"),
+    ("DB_ACQUIRE_TIMEOUT_SECS", "database.acquire_timeout_secs"),
+    ("DB_IDLE_TIMEOUT_SECS", "database.idle_timeout_secs"),
+    ("DB_STATEMENT_TIMEOUT_MS", "database.statement_timeout_ms"),
+    ("DB_CONNECT_ATTEMPTS", "database.connect_attempts"),
+    ("DB_CONNECT_BACKOFF_MS", "database.connect_backoff_ms"),
+    ("DB_RETRY_ATTEMPTS", "database.retry_attempts"),
+    ("DB_RETRY_BASE_DELAY_MS", "database.retry_base_delay_ms"),
+    ("SLOW_QUERY_MS", "database.slow_query_ms"),
+    ("RUST_LOG", "server.log_filter"),
+    ("HOST", "server.host"),
+    ("PORT", "server.port"),
+    ("TLS_CERT_PATH", "server.tls_cert_path"),
+    ("TLS_KEY_PATH", "server.tls_key_path"),
+    ("SHUTDOWN_GRACE_SECS", "server.shutdown_grace_secs"),
+    ("MAX_BODY_BYTES", "server.max_body_bytes"),
+    ("REQUEST_TIMEOUT_SECS", "server.request_timeout_secs"),
+    ("TRUSTED_PROXIES", "server.trusted_proxies"),
+    ("ACCESS_LOG", "server.access_log"),
+    ("UNIX_SOCKET", "server.unix_socket"),
+    ("HTTP2", "server.http2"),
+    ("SPA_DIR", "server.spa_dir"),
+    ("MAX_CONCURRENT_REQUESTS", "server.max_concurrent_requests"),
+    ("ADMIN_HOST", "server.admin_host"),
+    ("ADMIN_PORT", "server.admin_port"),
+    ("SHED_ON_BUSY_POOL", "server.shed_on_busy_pool"),
+    ("CORS_ALLOWED_ORIGINS", "cors.allowed_origins"),
+    ("DEV_MODE", "features.dev_mode"),
+    ("STRICT_JSON", "features.strict_json"),
+    ("ID_FORMAT", "features.id_format"),
+    ("COMPRESSION", "features.compression"),
+    ("DEGRADED_READS", "features.degraded_reads"),
+    (
+        "DEGRADED_READS_CAPACITY",
+        "features.degraded_reads_capacity",
+    ),
+    ("RATE_LIMIT_REQUESTS", "features.rate_limit_requests"),
+    ("RATE_LIMIT_WINDOW_SECS", "features.rate_limit_window_secs"),
+    ("RATE_LIMIT_BURST", "features.rate_limit_burst"),
+    ("RATE_LIMIT_READS", "features.rate_limit_reads"),
+    ("ATTACHMENTS_BACKEND", "attachments.backend"),
+    ("ATTACHMENTS_DIR", "attachments.dir"),
+    ("ATTACHMENTS_MAX_BYTES", "attachments.max_bytes"),
+    ("S3_BUCKET", "attachments.s3_bucket"),
+    ("S3_ENDPOINT", "attachments.s3_endpoint"),
+    ("S3_REGION", "attachments.s3_region"),
+    ("S3_PRESIGN_SECS", "attachments.s3_presign_secs"),
+    ("FLAG_CALDAV", "flags.caldav"),
+    ("FLAG_FEEDS", "flags.feeds"),
+    ("FLAG_EVENTS", "flags.events"),
+];
+
+/// Settings from a TOML file, each overridden by its environment variable
+/// as listed in [`ENV`]: `[server] port` by `PORT`, `[database]
+/// max_connections` by `DB_MAX_CONNECTIONS`. Secrets and integrations
+/// (tokens, encryption keys, Redis, the outbox, OTLP) are read from the
+/// environment alone, which [`crate::secrets`] fills from files and Vault.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppConfig {
+    pub database: DatabaseConfig,
+    pub server: ServerConfig,
+    pub cors: CorsConfig,
+    pub features: FeatureConfig,
+    pub attachments: AttachmentConfig,
+    pub flags: FlagConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub url: Option<String>,
+    /// A Postgres replica taking the list queries.
+    pub read_url: Option<String>,
+    /// Overrides the backend the scheme of `url` names.
+    pub backend: Option<String>,
+    /// Whether to apply pending migrations, or only verify them.
+    pub auto_migrate: bool,
+    pub max_connections: u32,
+    pub min_connections: u32,
+    pub acquire_timeout_secs: u64,
+    /// `0` keeps idle connections open.
+    pub idle_timeout_secs: u64,
+    /// `0` lets queries run as long as they take.
+    pub statement_timeout_ms: u64,
+    pub connect_attempts: u32,
+    pub connect_backoff_ms: u64,
+    /// `1` disables retrying.
+    pub retry_attempts: u32,
+    pub retry_base_delay_ms: u32,
+    /// Repository calls taking longer are logged; `0` logs none.
+    pub slow_query_ms: u64,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            read_url: None,
+            backend: None,
+            auto_migrate: true,
+            max_connections: 10,
+            min_connections: 0,
+            acquire_timeout_secs: 30,
+            idle_timeout_secs: 10 * 60,
+            statement_timeout_ms: 0,
+            connect_attempts: 5,
+            connect_backoff_ms: 500,
+            retry_attempts: 3,
+            retry_base_delay_ms: 50,
+            slow_query_ms: 500,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Which logs to write, e.g. `info,my_todo=debug`.
+    pub log_filter: String,
+    /// Every interface, or `127.0.0.1` in dev mode, when unset.
+    pub host: Option<String>,
+    pub port: u16,
+    /// Serves HTTPS when set, with `tls_key_path`.
+    pub tls_cert_path: Option<PathBuf>,
+    pub tls_key_path: Option<PathBuf>,
+    pub shutdown_grace_secs: u64,
+    /// Larger request bodies are answered `413`; `0` accepts any size.
+    pub max_body_bytes: u64,
+    /// Requests not answered by then get a `504`; `0` lets them run.
+    pub request_timeout_secs: u64,
+    /// Timeouts for the routes under a path prefix, e.g. `"/admin" = 300`
+    /// for backups, overriding `request_timeout_secs`.
+    pub route_timeouts: BTreeMap<String, u64>,
+    /// Reverse proxies whose `X-Forwarded-For` names the client, addresses
+    /// or blocks such as `10.0.0.0/8`, a list or a comma separated string.
+    #[serde(deserialize_with = "comma_separated")]
+    pub trusted_proxies: Vec<String>,
+    /// The level request lines are logged at, or `off`.
+    pub access_log: String,
+    /// Serves on this unix socket rather than `host` and `port`.
+    pub unix_socket: Option<PathBuf>,
+    /// Whether to speak HTTP/2 besides HTTP/1.1: negotiated over TLS, with
+    /// prior knowledge (h2c) over plain HTTP.
+    pub http2: bool,
+    /// The built frontend, served under `/app`.
+    pub spa_dir: Option<PathBuf>,
+    /// Requests served at once, more are answered `503`; `0` serves any
+    /// number.
+    pub max_concurrent_requests: usize,
+    /// Whether to answer `503` while every pooled connection is in use,
+    /// rather than queueing for one.
+    pub shed_on_busy_pool: bool,
+    /// Serves the admin, stats and health endpoints on their own port when
+    /// set, on `admin_host`, and no longer the admin and stats ones on
+    /// `port`.
+    pub admin_port: Option<u16>,
+    pub admin_host: String,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            log_filter: "info".to_string(),
+            host: None,
+            port: 3000,
+            tls_cert_path: None,
+            tls_key_path: None,
+            shutdown_grace_secs: 30,
+            max_body_bytes: 2 * 1024 * 1024,
+            request_timeout_secs: 30,
+            route_timeouts: BTreeMap::from([
+                ("/admin".to_string(), 5 * 60),
+                ("/attachments".to_string(), 10 * 60),
+            ]),
+            trusted_proxies: Vec::new(),
+            access_log: "info".to_string(),
+            unix_socket: None,
+            http2: true,
+            spa_dir: None,
+            max_concurrent_requests: 0,
+            shed_on_busy_pool: false,
+            admin_port: None,
+            admin_host: "127.0.0.1".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsConfig {
+    /// Origins browsers may call the API from, a list or a comma separated
+    /// string; `*` lets any.
+    #[serde(deserialize_with = "comma_separated")]
+    pub allowed_origins: Vec<String>,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            allowed_origins: vec!["http://localhost:3001".to_string()],
+        }
+    }
+}
+
+/// A TOML list, or a string such as an environment variable holds, split at
+/// its commas.
+fn comma_separated<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum List {
+        Joined(String),
+        Items(Vec<String>),
+    }
+    let items = match List::deserialize(deserializer)? {
+        List::Joined(joined) => joined.split(',').map(str::to_string).collect(),
+        List::Items(items) => items,
+    };
+    Ok(items
+        .iter()
+        .map(|item| item.trim())
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeatureConfig {
+    pub dev_mode: bool,
+    pub strict_json: bool,
+    pub id_format: IdFormat,
+    /// A comma separated list of `gzip`, `br` and `deflate`, or `off`.
+    pub compression: String,
+    pub degraded_reads: bool,
+    pub degraded_reads_capacity: usize,
+    /// Throttling is off when unset.
+    pub rate_limit_requests: Option<u32>,
+    pub rate_limit_window_secs: u64,
+    /// Requests a client may send at once; `rate_limit_requests` when unset.
+    pub rate_limit_burst: Option<u32>,
+    /// Whether reads count too, not only requests changing data.
+    pub rate_limit_reads: bool,
+}
+
+impl Default for FeatureConfig {
+    fn default() -> Self {
+        Self {
+            dev_mode: false,
+            strict_json: false,
+            id_format: IdFormat::default(),
+            compression: "gzip,br,deflate".to_string(),
+            degraded_reads: false,
+            degraded_reads_capacity: 1000,
+            rate_limit_requests: None,
+            rate_limit_window_secs: 60,
+            rate_limit_burst: None,
+            rate_limit_reads: false,
+        }
+    }
+}
+
+/// Where files attached to todos are kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AttachmentBackend {
+    Disk,
+    /// S3 or a compatible store such as MinIO, with the credentials of
+    /// `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY`.
+    S3,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AttachmentConfig {
+    /// Attachments are off when unset.
+    pub backend: Option<AttachmentBackend>,
+    /// Where `disk` keeps them.
+    pub dir: PathBuf,
+    /// Larger files are answered `413`. Uploads are streamed, so
+    /// `max_body_bytes` doesn't apply to them.
+    pub max_bytes: u64,
+    pub s3_bucket: Option<String>,
+    /// A MinIO or other S3 compatible server; AWS's endpoint of
+    /// `s3_region` when unset.
+    pub s3_endpoint: Option<String>,
+    pub s3_region: String,
+    /// How long a download link stays good.
+    pub s3_presign_secs: u64,
+}
+
+impl Default for AttachmentConfig {
+    fn default() -> Self {
+        Self {
+            backend: None,
+            dir: PathBuf::from("attachments"),
+            max_bytes: 100 * 1024 * 1024,
+            s3_bucket: None,
+            s3_endpoint: None,
+            s3_region: "us-east-1".to_string(),
+            s3_presign_secs: 5 * 60,
+        }
+    }
+}
+
+/// Experimental endpoints operators can turn off, then back on at runtime
+/// through `/admin/flags`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FlagConfig {
+    /// `/caldav/todos`.
+    pub caldav: bool,
+    /// `/feeds/todos.atom`.
+    pub feeds: bool,
+    /// `/todos/events`.
+    pub events: bool,
+}
+
+impl Default for FlagConfig {
+    fn default() -> Self {
+        Self {
+            caldav: true,
+            feeds: true,
+            events: true,
+        }
+    }
+}
+
+impl AppConfig {
+    /// Reads the file `CONFIG_FILE` names, or `my-todo.toml` when there is
+    /// one, under the environment.
+    pub fn load() -> Self {
+        Self::try_load().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// [`AppConfig::load`], reporting what is wrong rather than panicking.
+    pub fn try_load() -> anyhow::Result<Self> {
+        let file = match env::var("CONFIG_FILE") {
+            Ok(path) => {
+                if !std::path::Path::new(&path).is_file() {
+                    anyhow::bail!("invalid [CONFIG_FILE]: {}, no such file", path);
+                }
+                path
+            }
+            Err(_) => DEFAULT_FILE.to_string(),
+        };
+        Self::from_figment(Figment::new().merge(Toml::file(file)), env::vars())
+    }
+
+    /// `file` with the variables of [`ENV`] among `vars` merged over it,
+    /// their values typed as TOML would: `true`, `8080`. A bad value is
+    /// reported by its variable when one set it.
+    fn from_figment(
+        file: Figment,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> anyhow::Result<Self> {
+        let overrides: Vec<(&str, &str, String)> = vars
+            .into_iter()
+            .filter_map(|(var, value)| {
+                let (name, key) = ENV.iter().find(|(name, _)| *name == var)?;
+                Some((*name, *key, value))
+            })
+            .collect();
+        let figment = overrides.iter().fold(file, |figment, (_, key, value)| {
+            let value: Value = value.parse().expect("infallible");
+            figment.merge(Serialized::default(key, value))
+        });
+        figment.extract().map_err(|e: figment::Error| {
+            let key = e.path.join(".");
+            match overrides
+                .iter()
+                .find(|(_, overridden, _)| *overridden == key)
+            {
+                Some((name, _, value)) => {
+                    anyhow::anyhow!("invalid [{}]: {}, {}", name, value, e.kind)
+                }
+                None => anyhow::anyhow!("invalid configuration: {}", e),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(var, value)| (var.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn layers_the_environment_over_the_file() {
+        let file = r#"
+            [database]
+            url = "postgres://from-the-file/todos"
+            max_connections = 20
+
+            [server]
+            port = 8080
+            route_timeouts = { "/todos/purge" = 120 }
+
+            [features]
+            id_format = "uuid"
+        "#;
+        let config = AppConfig::from_figment(
+            Figment::new().merge(Toml::string(file)),
+            vars(&[("PORT", "9090"), ("DEV_MODE", "true"), ("UNRELATED", "1")]),
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.database.url.as_deref(),
+            Some("postgres://from-the-file/todos")
+        );
+        assert_eq!(config.database.max_connections, 20);
+        assert_eq!(config.database.min_connections, 0);
+        assert_eq!(config.server.port, 9090);
+        assert_eq!(
+            config.server.route_timeouts,
+            BTreeMap::from([("/todos/purge".to_string(), 120)])
+        );
+        assert!(config.features.dev_mode);
+        assert_eq!(config.features.id_format, IdFormat::Uuid);
+        assert_eq!(config.cors, CorsConfig::default());
+        assert_eq!(config.attachments.backend, None);
+
+        let config = AppConfig::from_figment(
+            Figment::new().merge(Toml::string("[attachments]\nbackend = \"disk\"")),
+            vars(&[("S3_BUCKET", "todos"), ("ATTACHMENTS_BACKEND", "s3")]),
+        )
+        .unwrap();
+        assert_eq!(config.attachments.backend, Some(AttachmentBackend::S3));
+        assert_eq!(config.attachments.s3_bucket.as_deref(), Some("todos"));
+
+        let config = AppConfig::from_figment(
+            Figment::new().merge(Toml::string("[cors]\nallowed_origins = [\"https://a\"]")),
+            vars(&[]),
+        )
+        .unwrap();
+        assert_eq!(config.cors.allowed_origins, ["https://a"]);
+        let config = AppConfig::from_figment(
+            Figment::new(),
+            vars(&[("CORS_ALLOWED_ORIGINS", "https://a, https://b")]),
+        )
+        .unwrap();
+        assert_eq!(config.cors.allowed_origins, ["https://a", "https://b"]);
+    }
+
+    #[test]
+    fn rejects_unknown_settings() {
+        let file = Figment::new().merge(Toml::string("[server]\nprot = 8080"));
+        assert!(AppConfig::from_figment(file, vec![]).is_err());
+        let file = Figment::new();
+        let e = AppConfig::from_figment(file, vars(&[("PORT", "http")])).unwrap_err();
+        assert!(e.to_string().starts_with("invalid [PORT]: http, "), "{}", e);
+    }
+}
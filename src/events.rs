@@ -0,0 +1,295 @@
+use std::{
+    convert::Infallible,
+    time::{Duration, SystemTime},
+};
+
+use axum::{
+    async_trait,
+    extract::Extension,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures_util::stream::{self, BoxStream, Stream};
+use serde::{Deserialize, Serialize};
+use sqlx::{postgres::PgListener, PgPool};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::{
+    handlers::{error::ApiError, i18n::tr},
+    repositories::{
+        id::Key,
+        label::LabelRepository,
+        todo::{
+            CreateTodo, Import, ImportedRows, SearchHit, Todo, TodoFilter, TodoRepository,
+            TodoWithLabels, UpdateTodo,
+        },
+    },
+};
+
+/// Postgres channel the DB repository notifies on, one JSON [`TodoEvent`]
+/// per payload.
+pub const CHANNEL: &str = "todo_events";
+
+/// Events a slow subscriber may fall behind by before it misses some.
+pub const EVENT_BUFFER: usize = 256;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TodoEvent {
+    Created { id: i64 },
+    Updated { id: i64 },
+    Deleted { id: i64 },
+    Purged { count: u64 },
+    Archived { count: u64 },
+}
+
+/// In-process fan-out of todo changes to every live subscriber.
+#[derive(Debug, Clone)]
+pub struct EventBus(broadcast::Sender<TodoEvent>);
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        Self(broadcast::channel(capacity).0)
+    }
+
+    pub fn publish(&self, event: TodoEvent) {
+        // Nobody listening is fine.
+        let _ = self.0.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<TodoEvent> {
+        self.0.subscribe()
+    }
+}
+
+/// Starts forwarding the notifications of every server instance sharing the
+/// database into `bus`. Returns once listening, so no change made after it
+/// is missed; the listener reconnects on its own after a dropped connection.
+pub async fn listen_postgres(pool: &PgPool, bus: EventBus) -> anyhow::Result<()> {
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener.listen(CHANNEL).await?;
+    tokio::spawn(async move {
+        if let Err(e) = forward_notifications(listener, &bus).await {
+            tracing::error!("stopped listening for todo events: {:#}", e);
+        }
+    });
+    Ok(())
+}
+
+async fn forward_notifications(mut listener: PgListener, bus: &EventBus) -> anyhow::Result<()> {
+    loop {
+        let notification = listener.recv().await?;
+        match serde_json::from_str(notification.payload()) {
+            Ok(event) => bus.publish(event),
+            Err(e) => tracing::warn!(
+                "ignoring malformed todo event [{}]: {}",
+                notification.payload(),
+                e
+            ),
+        }
+    }
+}
+
+/// Publishes the changes made through the wrapped repository; for backends
+/// without a notification channel of their own.
+#[derive(Debug, Clone)]
+pub struct Publishing<R> {
+    inner: R,
+    bus: EventBus,
+}
+
+impl<R> Publishing<R> {
+    pub fn new(inner: R, bus: EventBus) -> Self {
+        Self { inner, bus }
+    }
+}
+
+#[async_trait]
+impl<R: TodoRepository> TodoRepository for Publishing<R> {
+    async fn create(&self, payload: CreateTodo) -> anyhow::Result<Todo> {
+        let todo = self.inner.create(payload).await?;
+        self.bus.publish(TodoEvent::Created { id: todo.id() });
+        Ok(todo)
+    }
+    async fn create_many(&self, payloads: Vec<CreateTodo>) -> anyhow::Result<Vec<Todo>> {
+        let todos = self.inner.create_many(payloads).await?;
+        for todo in &todos {
+            self.bus.publish(TodoEvent::Created { id: todo.id() });
+        }
+        Ok(todos)
+    }
+    async fn import<L: LabelRepository>(
+        &self,
+        labels: &L,
+        import: Import,
+    ) -> anyhow::Result<ImportedRows> {
+        let imported = self.inner.import(labels, import).await?;
+        for todo in &imported.todos {
+            self.bus.publish(TodoEvent::Created { id: todo.id() });
+        }
+        Ok(imported)
+    }
+    async fn find(&self, id: i64) -> anyhow::Result<Todo> {
+        self.inner.find(id).await
+    }
+    async fn all(&self, filter: &TodoFilter) -> anyhow::Result<Vec<Todo>> {
+        self.inner.all(filter).await
+    }
+    async fn count(&self, filter: &TodoFilter) -> anyhow::Result<i64> {
+        self.inner.count(filter).await
+    }
+    fn stream_all(&self) -> BoxStream<'static, anyhow::Result<Todo>> {
+        self.inner.stream_all()
+    }
+    async fn find_with_labels(&self, id: i64) -> anyhow::Result<TodoWithLabels> {
+        self.inner.find_with_labels(id).await
+    }
+    async fn all_with_labels(&self, filter: &TodoFilter) -> anyhow::Result<Vec<TodoWithLabels>> {
+        self.inner.all_with_labels(filter).await
+    }
+    async fn update(&self, id: i64, payload: UpdateTodo) -> anyhow::Result<Todo> {
+        let todo = self.inner.update(id, payload).await?;
+        self.bus.publish(TodoEvent::Updated { id });
+        Ok(todo)
+    }
+    async fn delete(&self, id: i64) -> anyhow::Result<()> {
+        self.inner.delete(id).await?;
+        self.bus.publish(TodoEvent::Deleted { id });
+        Ok(())
+    }
+    async fn purge_completed(&self) -> anyhow::Result<u64> {
+        let count = self.inner.purge_completed().await?;
+        if count > 0 {
+            self.bus.publish(TodoEvent::Purged { count });
+        }
+        Ok(count)
+    }
+    async fn archive_completed(&self, older_than: Duration) -> anyhow::Result<u64> {
+        let count = self.inner.archive_completed(older_than).await?;
+        if count > 0 {
+            self.bus.publish(TodoEvent::Archived { count });
+        }
+        Ok(count)
+    }
+    async fn archived(&self) -> anyhow::Result<Vec<Todo>> {
+        self.inner.archived().await
+    }
+    async fn last_modified(&self, id: i64) -> anyhow::Result<SystemTime> {
+        self.inner.last_modified(id).await
+    }
+    async fn collection_last_modified(&self) -> anyhow::Result<SystemTime> {
+        self.inner.collection_last_modified().await
+    }
+    async fn recently_modified(&self, limit: i64) -> anyhow::Result<Vec<(Todo, SystemTime)>> {
+        self.inner.recently_modified(limit).await
+    }
+    async fn resolve(&self, key: &Key) -> anyhow::Result<i64> {
+        self.inner.resolve(key).await
+    }
+    async fn search(&self, filter: &TodoFilter, limit: i64) -> anyhow::Result<Vec<SearchHit>> {
+        self.inner.search(filter, limit).await
+    }
+}
+
+/// `GET /todos/events`: the todo changes as server-sent events, named after
+/// their type with the JSON event as data.
+pub async fn todo_events(
+    bus: Option<Extension<EventBus>>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    let Extension(bus) =
+        bus.ok_or_else(|| ApiError::NotFound(tr("Live updates are not enabled")))?;
+    let events = stream::unfold(bus.subscribe(), |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => {
+                    let name = match &event {
+                        TodoEvent::Created { .. } => "created",
+                        TodoEvent::Updated { .. } => "updated",
+                        TodoEvent::Deleted { .. } => "deleted",
+                        TodoEvent::Purged { .. } => "purged",
+                        TodoEvent::Archived { .. } => "archived",
+                    };
+                    let data = serde_json::to_string(&event).unwrap_or_default();
+                    return Some((Ok(Event::default().event(name).data(data)), receiver));
+                }
+                // Clients resync from the collection; skipping is all we can do.
+                Err(RecvError::Lagged(missed)) => {
+                    tracing::warn!("event subscriber lagged, {} events dropped", missed)
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+
+    use super::*;
+    use crate::repositories::todo::{TodoRepositoryForDb, TodoRepositoryForMemory};
+
+    #[tokio::test]
+    async fn publishes_changes_made_through_the_repository() {
+        let bus = EventBus::new(8);
+        let mut events = bus.subscribe();
+        let repository = Publishing::new(TodoRepositoryForMemory::new(), bus);
+
+        let todo = repository
+            .create(CreateTodo::new("published".to_string()))
+            .await
+            .unwrap();
+        repository.delete(todo.id()).await.unwrap();
+
+        assert_eq!(
+            events.recv().await.unwrap(),
+            TodoEvent::Created { id: todo.id() }
+        );
+        assert_eq!(
+            events.recv().await.unwrap(),
+            TodoEvent::Deleted { id: todo.id() }
+        );
+    }
+
+    #[tokio::test]
+    async fn forwards_postgres_notifications() {
+        dotenv::dotenv().ok();
+        let database_url = &env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let pool = PgPool::connect(database_url)
+            .await
+            .unwrap_or_else(|_| panic!("fail connect database, url is [{}]", database_url));
+        let bus = EventBus::new(EVENT_BUFFER);
+        let mut events = bus.subscribe();
+        listen_postgres(&pool, bus).await.unwrap();
+
+        // Another instance sharing the database makes the change.
+        let repository = TodoRepositoryForDb::new(pool.clone());
+        let todo = repository
+            .create(CreateTodo::new(
+                "[forwards_postgres_notifications]".to_string(),
+            ))
+            .await
+            .unwrap();
+        repository.delete(todo.id()).await.unwrap();
+
+        let mut seen = vec![];
+        while seen.len() < 2 {
+            match events.recv().await.unwrap() {
+                event @ (TodoEvent::Created { id } | TodoEvent::Deleted { id })
+                    if id == todo.id() =>
+                {
+                    seen.push(event)
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(
+            seen,
+            vec![
+                TodoEvent::Created { id: todo.id() },
+                TodoEvent::Deleted { id: todo.id() }
+            ]
+        );
+    }
+}
@@ -0,0 +1,143 @@
+use std::sync::{Arc, RwLock};
+
+use axum::{
+    body::Bytes,
+    extract::Extension,
+    http::Request,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::{Map, Value};
+
+use crate::{
+    config::FlagConfig,
+    handlers::{admin::Admin, error::ApiError, i18n::trf},
+};
+
+/// The feature flags in effect, starting from the configured ones. Changes
+/// made through `/admin/flags` last until the next restart or reload.
+#[derive(Debug, Clone, Default)]
+pub struct Flags(Arc<RwLock<FlagConfig>>);
+
+impl Flags {
+    pub fn from_config(config: &FlagConfig) -> Self {
+        Self(Arc::new(RwLock::new(*config)))
+    }
+
+    pub fn current(&self) -> FlagConfig {
+        *self.0.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Puts the configured flags back in effect.
+    pub fn set(&self, config: &FlagConfig) {
+        *self.0.write().unwrap_or_else(|e| e.into_inner()) = *config;
+    }
+
+    /// Sets the flags `patch` names, leaving the others as they are.
+    fn update(&self, patch: Map<String, Value>) -> Result<FlagConfig, serde_json::Error> {
+        let mut flags = self.0.write().unwrap_or_else(|e| e.into_inner());
+        let mut merged = match serde_json::to_value(*flags)? {
+            Value::Object(merged) => merged,
+            _ => unreachable!("flags serialize to an object"),
+        };
+        merged.extend(patch);
+        *flags = serde_json::from_value(Value::Object(merged))?;
+        Ok(*flags)
+    }
+}
+
+/// The flag guarding the endpoints under `path`, by name.
+fn flag_for(path: &str, flags: &FlagConfig) -> Option<(&'static str, bool)> {
+    if path.starts_with("/caldav/") {
+        Some(("caldav", flags.caldav))
+    } else if path.starts_with("/feeds/") || path == "/export/calendar.ics" {
+        Some(("feeds", flags.feeds))
+    } else if path == "/todos/events" {
+        Some(("events", flags.events))
+    } else {
+        None
+    }
+}
+
+/// Answers `404` for endpoints whose flag is off, as if they weren't there.
+pub async fn gate_features<B>(req: Request<B>, next: Next<B>) -> Response {
+    let Some(flags) = req.extensions().get::<Flags>() else {
+        return next.run(req).await;
+    };
+    match flag_for(req.uri().path(), &flags.current()) {
+        Some((name, false)) => {
+            ApiError::NotFound(trf("Feature [{}] is disabled", &[&name])).into_response()
+        }
+        _ => next.run(req).await,
+    }
+}
+
+/// `GET /admin/flags`.
+pub async fn flags(_: Admin, Extension(flags): Extension<Flags>) -> Json<FlagConfig> {
+    Json(flags.current())
+}
+
+/// `PATCH /admin/flags` with the flags to change, e.g. `{"caldav": false}`.
+pub async fn update_flags(
+    _: Admin,
+    Extension(flags): Extension<Flags>,
+    body: Bytes,
+) -> Result<Json<FlagConfig>, ApiError> {
+    let patch: Map<String, Value> = serde_json::from_slice(&body)
+        .map_err(|e| ApiError::BadRequest(trf("Json parse error: [{}]", &[&e])))?;
+    let updated = flags
+        .update(patch)
+        .map_err(|e| ApiError::BadRequest(trf("Invalid flags: [{}]", &[&e])))?;
+    tracing::info!("feature flags changed: {:?}", updated);
+    Ok(Json(updated))
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    fn patch(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(patch) => patch,
+            _ => panic!("not an object"),
+        }
+    }
+
+    #[test]
+    fn updates_only_the_named_flags() {
+        let flags = Flags::from_config(&FlagConfig::default());
+        let updated = flags.update(patch(json!({ "caldav": false }))).unwrap();
+        assert!(!updated.caldav);
+        assert!(updated.feeds && updated.events);
+        assert_eq!(flags.current(), updated);
+
+        assert!(flags.update(patch(json!({ "graphql": true }))).is_err());
+        assert!(flags.update(patch(json!({ "feeds": "no" }))).is_err());
+        assert_eq!(flags.current(), updated);
+    }
+
+    #[test]
+    fn guards_the_endpoints_of_each_flag() {
+        let flags = FlagConfig {
+            feeds: false,
+            ..FlagConfig::default()
+        };
+        assert_eq!(
+            flag_for("/feeds/todos.atom", &flags),
+            Some(("feeds", false))
+        );
+        assert_eq!(
+            flag_for("/caldav/todos/1.ics", &flags),
+            Some(("caldav", true))
+        );
+        assert_eq!(
+            flag_for("/export/calendar.ics", &flags),
+            Some(("feeds", false))
+        );
+        assert_eq!(flag_for("/todos/events", &flags), Some(("events", true)));
+        assert_eq!(flag_for("/todos", &flags), None);
+    }
+}
@@ -0,0 +1,472 @@
+use std::{collections::HashSet, env, sync::Arc, time::Duration};
+
+use hyper::{header, Body, Method, Request, Uri};
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{
+    client::{self, HttpsConnector},
+    events::{EventBus, TodoEvent},
+    repositories::{
+        id::IdFormat,
+        link::{linked_todo, LinkRepository},
+        todo::{CreateTodo, TodoFilter, TodoRepository, UpdateTodo},
+    },
+};
+
+/// Longest text a todo may have; longer issue titles are cut.
+const MAX_TEXT: usize = 100;
+
+/// Issues read per page, GitHub's largest.
+const PER_PAGE: usize = 100;
+
+/// A call to GitHub that takes longer than this counts as failed.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// An issue as the REST API and the `issues` webhook describe it.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Issue {
+    pub number: u64,
+    pub title: String,
+    /// `open` or `closed`.
+    pub state: String,
+    #[serde(default)]
+    pub assignees: Vec<Account>,
+    /// Set on pull requests, which the issues API lists too.
+    #[serde(default)]
+    pub pull_request: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Account {
+    pub login: String,
+}
+
+/// Keeps the issues of one repository assigned to one user in step with
+/// todos. Which todo stands for which issue is kept in a
+/// [`LinkRepository`].
+#[derive(Clone)]
+pub struct GitHubSync {
+    repo: String,
+    assignee: String,
+    token: String,
+    api: String,
+    /// What webhook deliveries are signed with; they are refused without.
+    webhook_secret: Option<String>,
+    /// How the todos it adds are addressed.
+    format: IdFormat,
+    client: hyper::Client<HttpsConnector>,
+}
+
+impl std::fmt::Debug for GitHubSync {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GitHubSync")
+            .field("repo", &self.repo)
+            .field("assignee", &self.assignee)
+            .field("api", &self.api)
+            .finish_non_exhaustive()
+    }
+}
+
+impl GitHubSync {
+    pub fn new(repo: &str, assignee: &str, token: &str) -> Self {
+        Self {
+            repo: repo.to_string(),
+            assignee: assignee.to_string(),
+            token: token.to_string(),
+            api: "https://api.github.com".to_string(),
+            webhook_secret: None,
+            format: IdFormat::default(),
+            client: client::https(),
+        }
+    }
+
+    /// Calls another API than github.com's, e.g. a GitHub Enterprise one.
+    pub fn with_api(mut self, api: &str) -> Self {
+        self.api = api.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn with_webhook_secret(mut self, secret: Option<String>) -> Self {
+        self.webhook_secret = secret.filter(|secret| !secret.is_empty());
+        self
+    }
+
+    pub fn with_format(mut self, format: IdFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Syncs the issues of `GITHUB_REPO` (`owner/repo`) assigned to
+    /// `GITHUB_USER`, calling the API as `GITHUB_TOKEN`, at `GITHUB_API_URL`
+    /// when set. Webhook deliveries are checked with
+    /// `GITHUB_WEBHOOK_SECRET`. `None` unless the first three are set.
+    pub fn from_env() -> Option<Self> {
+        let var = |name| {
+            env::var(name)
+                .ok()
+                .filter(|value: &String| !value.is_empty())
+        };
+        let repo = var("GITHUB_REPO")?;
+        if repo.split('/').count() != 2 {
+            panic!("invalid [GITHUB_REPO]: {}, expected owner/repo", repo);
+        }
+        let sync = Self::new(&repo, &var("GITHUB_USER")?, &var("GITHUB_TOKEN")?)
+            .with_webhook_secret(var("GITHUB_WEBHOOK_SECRET"));
+        Some(match var("GITHUB_API_URL") {
+            Some(api) => sync.with_api(&api),
+            None => sync,
+        })
+    }
+
+    pub fn repo(&self) -> &str {
+        &self.repo
+    }
+
+    pub fn webhook_secret(&self) -> Option<&str> {
+        self.webhook_secret.as_deref()
+    }
+
+    /// What the links to this repository's issues are kept under.
+    fn service(&self) -> String {
+        format!("github:{}", self.repo)
+    }
+
+    fn is_assigned(&self, issue: &Issue) -> bool {
+        issue
+            .assignees
+            .iter()
+            .any(|account| account.login.eq_ignore_ascii_case(&self.assignee))
+    }
+
+    /// Brings the todo of `issue` up to date: one is added for an open
+    /// issue assigned to the user, and an existing one follows the title
+    /// and the state. A todo whose issue was unassigned is left alone.
+    pub async fn apply<T: TodoRepository>(
+        &self,
+        todos: &T,
+        links: &dyn LinkRepository,
+        issue: &Issue,
+    ) -> anyhow::Result<()> {
+        if issue.pull_request.is_some() {
+            return Ok(());
+        }
+        let service = self.service();
+        let number = issue.number.to_string();
+        let text: String = issue.title.trim().chars().take(MAX_TEXT).collect();
+        let closed = issue.state == "closed";
+        let linked = match links.todo(&service, &number).await? {
+            Some(id) => linked_todo(todos, id).await?,
+            None => None,
+        };
+        match linked {
+            Some(todo) => {
+                let text = (todo.text() != text && !text.is_empty()).then_some(text);
+                let completed = (todo.completed() != closed).then_some(closed);
+                if text.is_some() || completed.is_some() {
+                    todos
+                        .update(todo.id(), UpdateTodo::new(text, completed))
+                        .await?;
+                }
+            }
+            None if !closed && self.is_assigned(issue) && !text.is_empty() => {
+                let todo = todos
+                    .create(CreateTodo::new(text).with_format(self.format))
+                    .await?;
+                links.link(&service, &number, todo.id()).await?;
+                tracing::info!(
+                    "added todo {} for issue {}#{}",
+                    todo.id(),
+                    self.repo,
+                    number
+                );
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    async fn call(&self, method: Method, path: &str, body: Body) -> anyhow::Result<Vec<u8>> {
+        let req = Request::builder()
+            .method(method)
+            .uri(format!("{}{}", self.api, path).parse::<Uri>()?)
+            .header(header::AUTHORIZATION, format!("Bearer {}", self.token))
+            .header(header::ACCEPT, "application/vnd.github+json")
+            .header(header::USER_AGENT, "my-todo")
+            .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .body(body)?;
+        let res = client::request(&self.client, req, REQUEST_TIMEOUT).await?;
+        let status = res.status();
+        let body = hyper::body::to_bytes(res.into_body()).await?;
+        if !status.is_success() {
+            anyhow::bail!("github answered {}", status);
+        }
+        Ok(body.to_vec())
+    }
+
+    /// Every open issue assigned to the user.
+    async fn assigned_issues(&self) -> anyhow::Result<Vec<Issue>> {
+        let mut issues = vec![];
+        for page in 1.. {
+            let path = format!(
+                "/repos/{}/issues?assignee={}&state=open&per_page={}&page={}",
+                self.repo, self.assignee, PER_PAGE, page
+            );
+            let batch: Vec<Issue> =
+                serde_json::from_slice(&self.call(Method::GET, &path, Body::empty()).await?)?;
+            let last = batch.len() < PER_PAGE;
+            issues.extend(batch);
+            if last {
+                break;
+            }
+        }
+        Ok(issues)
+    }
+
+    async fn close(&self, number: u64) -> anyhow::Result<()> {
+        let path = format!("/repos/{}/issues/{}", self.repo, number);
+        let body = Body::from(json!({ "state": "closed" }).to_string());
+        self.call(Method::PATCH, &path, body).await?;
+        Ok(())
+    }
+
+    /// Adds todos for the issues already assigned, then closes the issue of
+    /// every todo completed from here on, on a background task. Issues
+    /// changing later come in through the webhook.
+    pub fn spawn<T: TodoRepository>(
+        self,
+        todos: T,
+        links: Arc<dyn LinkRepository>,
+        bus: &EventBus,
+    ) {
+        tracing::info!(
+            "syncing the issues of {} assigned to {}",
+            self.repo,
+            self.assignee
+        );
+        let mut events = bus.subscribe();
+        tokio::spawn(async move {
+            match self.assigned_issues().await {
+                Ok(issues) => {
+                    for issue in &issues {
+                        if let Err(e) = self.apply(&todos, &*links, issue).await {
+                            tracing::warn!("failed to sync issue {}: {:#}", issue.number, e);
+                        }
+                    }
+                }
+                Err(e) => tracing::error!("failed to read the assigned issues: {:#}", e),
+            }
+            let filter = TodoFilter {
+                completed: Some(true),
+                ..TodoFilter::default()
+            };
+            let mut completed: HashSet<i64> = match todos.all(&filter).await {
+                Ok(todos) => todos.iter().map(|todo| todo.id()).collect(),
+                Err(e) => {
+                    tracing::warn!("failed to read completed todos: {:#}", e);
+                    HashSet::new()
+                }
+            };
+            loop {
+                let id = match events.recv().await {
+                    Ok(TodoEvent::Updated { id }) => id,
+                    Ok(TodoEvent::Deleted { id }) => {
+                        completed.remove(&id);
+                        if let Err(e) = links.unlink(id).await {
+                            tracing::warn!("failed to unlink todo {}: {:#}", id, e);
+                        }
+                        continue;
+                    }
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(missed)) => {
+                        tracing::warn!("missed {} todo events to sync", missed);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => return,
+                };
+                if let Err(e) = self.completed(&todos, &*links, &mut completed, id).await {
+                    tracing::warn!("failed to close the issue of todo {}: {:#}", id, e);
+                }
+            }
+        });
+    }
+
+    /// Closes the issue of todo `id` when the update completed it.
+    async fn completed<T: TodoRepository>(
+        &self,
+        todos: &T,
+        links: &dyn LinkRepository,
+        completed: &mut HashSet<i64>,
+        id: i64,
+    ) -> anyhow::Result<()> {
+        let todo = todos.find(id).await?;
+        if !todo.completed() {
+            completed.remove(&id);
+            return Ok(());
+        }
+        if !completed.insert(id) {
+            return Ok(());
+        }
+        for number in links.externals(&self.service(), id).await? {
+            self.close(number.parse()?).await?;
+            tracing::info!("closed issue {}#{} with todo {}", self.repo, number, id);
+        }
+        Ok(())
+    }
+}
+
+/// Syncs issues on a background task when `GITHUB_REPO` and the rest are
+/// set; see [`GitHubSync::from_env`]. Added todos are addressed as `format`
+/// does.
+pub fn spawn_from_env<T: TodoRepository>(
+    todos: T,
+    links: Arc<dyn LinkRepository>,
+    format: IdFormat,
+    bus: &EventBus,
+) {
+    if let Some(sync) = GitHubSync::from_env() {
+        sync.with_format(format).spawn(todos, links, bus);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Mutex;
+
+    use axum::{
+        extract::{Extension, Path},
+        routing::{get, patch},
+        Json, Router,
+    };
+    use serde_json::Value;
+
+    use super::*;
+    use crate::{
+        events::Publishing,
+        repositories::{
+            link::LinkRepositoryForSqlite, todo::TodoRepositoryForSqlite, Migrations, PoolSettings,
+        },
+    };
+
+    type Closed = Arc<Mutex<Vec<u64>>>;
+
+    fn issue(number: u64, title: &str, state: &str, assignee: &str) -> Issue {
+        serde_json::from_value(json!({
+            "number": number,
+            "title": title,
+            "state": state,
+            "assignees": [{ "login": assignee }],
+        }))
+        .unwrap()
+    }
+
+    /// A GitHub API with one assigned issue, recording the ones closed.
+    async fn github() -> (String, Closed) {
+        let closed = Closed::default();
+        let app = Router::new()
+            .route(
+                "/repos/octo/garden/issues",
+                get(|| async { Json(json!([{ "number": 3, "title": "Prune roses", "state": "open", "assignees": [{ "login": "mona" }] }])) }),
+            )
+            .route(
+                "/repos/octo/garden/issues/:number",
+                patch(
+                    |Path(number): Path<u64>,
+                     Json(body): Json<Value>,
+                     Extension(closed): Extension<Closed>| async move {
+                        assert_eq!(body, json!({ "state": "closed" }));
+                        closed.lock().unwrap().push(number);
+                        Json(json!({}))
+                    },
+                ),
+            )
+            .layer(Extension(closed.clone()));
+        let addr = crate::testing::serve(app);
+        (format!("http://{}", addr), closed)
+    }
+
+    async fn sqlite() -> (TodoRepositoryForSqlite, Arc<LinkRepositoryForSqlite>) {
+        let pool = crate::repositories::connect_sqlite(
+            "sqlite::memory:",
+            &PoolSettings::default(),
+            Migrations::Apply,
+        )
+        .await
+        .expect("failed open sqlite");
+        (
+            TodoRepositoryForSqlite::new(pool.clone()),
+            Arc::new(LinkRepositoryForSqlite::new(pool)),
+        )
+    }
+
+    #[tokio::test]
+    async fn follows_the_issues_assigned_to_the_user() {
+        let (todos, links) = sqlite().await;
+        let sync = GitHubSync::new("octo/garden", "Mona", "t0k3n").with_format(IdFormat::Uuid);
+
+        sync.apply(
+            &todos,
+            &*links,
+            &issue(1, "someone else's", "open", "hubot"),
+        )
+        .await
+        .unwrap();
+        assert!(todos.all(&TodoFilter::default()).await.unwrap().is_empty());
+
+        sync.apply(&todos, &*links, &issue(2, "Water", "open", "mona"))
+            .await
+            .unwrap();
+        sync.apply(
+            &todos,
+            &*links,
+            &issue(2, "Water the roses", "closed", "hubot"),
+        )
+        .await
+        .unwrap();
+        let all = todos.all_with_labels(&TodoFilter::default()).await.unwrap();
+        assert_eq!(all.len(), 1);
+        assert_eq!(all[0].todo.text(), "Water the roses");
+        assert!(all[0].todo.completed());
+        assert!(all[0].todo.uuid().is_some());
+        assert!(all[0].labels.is_empty());
+        assert_eq!(
+            links.todo("github:octo/garden", "2").await.unwrap(),
+            Some(all[0].todo.id())
+        );
+        assert_eq!(
+            links.todo("github:octo/gardening", "2").await.unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn imports_assigned_issues_and_closes_completed_ones() {
+        let (api, closed) = github().await;
+        let (todos, links) = sqlite().await;
+        let bus = EventBus::new(16);
+        let todos = Publishing::new(todos, bus.clone());
+        GitHubSync::new("octo/garden", "mona", "t0k3n")
+            .with_api(&api)
+            .spawn(todos.clone(), links, &bus);
+
+        let mut imported = vec![];
+        for _ in 0..50 {
+            imported = todos.all(&TodoFilter::default()).await.unwrap();
+            if !imported.is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(imported.len(), 1);
+        assert_eq!(imported[0].text(), "Prune roses");
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        todos
+            .update(imported[0].id(), UpdateTodo::new(None, Some(true)))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(*closed.lock().unwrap(), vec![3]);
+    }
+}
@@ -1,39 +1,176 @@
 use axum::{
     async_trait,
     extract::{FromRequest, RequestParts},
-    http::StatusCode,
     BoxError, Json,
 };
-use serde::de::DeserializeOwned;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
 use validator::Validate;
 
+use self::{
+    error::{ApiError, FieldErrors},
+    i18n::{tr, trf},
+};
+
+/// Request extension switching [`ValidatedJson`] to strict mode, where
+/// fields the DTO doesn't know (say `compleated`) are rejected instead of
+/// ignored. Added when `strict_json` is on; lenient by default.
+#[derive(Debug, Clone, Copy)]
+pub struct StrictJson;
+
+/// Top-level members of `input` that don't survive a round trip through the
+/// DTO, i.e. the ones it has no field for.
+fn unknown_fields<T: Serialize>(input: &Value, value: &T) -> Vec<String> {
+    let known = serde_json::to_value(value).unwrap_or_default();
+    match (input, known) {
+        (Value::Object(input), Value::Object(known)) => input
+            .keys()
+            .filter(|key| !known.contains_key(*key))
+            .cloned()
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// `bytes` as lowercase hex, as digests and signatures are spelled.
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// The bytes `hex` spells, as webhook signatures are sent.
+fn unhex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// `text` with all but the unreserved characters of RFC 3986
+/// percent-encoded, `/` too unless `keep_slashes`: as SigV4 and RFC 5987
+/// `filename*` values have it.
+pub(crate) fn percent_encode(text: &str, keep_slashes: bool) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if keep_slashes => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// `text` escaped for XML and HTML text and attribute values.
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// XML text or an attribute value with its predefined entities resolved.
+pub(crate) fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
 #[derive(Debug)]
-pub struct ValidatedJson<T>(T);
+pub struct ValidatedJson<T>(pub T);
 
 #[async_trait]
 impl<T, B> FromRequest<B> for ValidatedJson<T>
 where
-    T: DeserializeOwned + Validate,
+    T: DeserializeOwned + Serialize + Validate,
     B: http_body::Body + Send,
     B::Data: Send,
     B::Error: Into<BoxError>,
 {
-    type Rejection = (StatusCode, String);
+    type Rejection = ApiError;
 
     async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
-        let Json(value) = Json::<T>::from_request(req).await.map_err(|rejection| {
-            let message = format!("Json parse error: [{}]", rejection);
-            (StatusCode::BAD_REQUEST, message)
+        let strict = req
+            .extensions()
+            .is_some_and(|extensions| extensions.get::<StrictJson>().is_some());
+        let Json(input) = Json::<Value>::from_request(req)
+            .await
+            .map_err(|rejection| {
+                let message = trf("Json parse error: [{}]", &[&rejection]);
+                ApiError::BadRequest(message)
+            })?;
+        let value: T = serde_json::from_value(input.clone()).map_err(|rejection| {
+            let message = trf("Json parse error: [{}]", &[&rejection]);
+            ApiError::BadRequest(message)
         })?;
 
-        value.validate().map_err(|rejection| {
-            let message = format!("Validation error: [{}]", rejection).replace('\n', ",");
-            (StatusCode::BAD_REQUEST, message)
-        })?;
+        if strict {
+            let unknown = unknown_fields(&input, &value);
+            if !unknown.is_empty() {
+                let detail = trf("Unknown fields: [{}]", &[&unknown.join(",")]);
+                let errors: FieldErrors = unknown
+                    .into_iter()
+                    .map(|field| (field, vec![tr("unknown field")]))
+                    .collect();
+                return Err(ApiError::Validation { detail, errors });
+            }
+        }
+
+        value.validate()?;
 
         Ok(ValidatedJson(value))
     }
 }
 
+pub mod admin;
+pub mod attachment;
+pub mod caldav;
+pub mod conditional;
+pub mod csv;
+pub mod error;
+pub mod feed;
+pub mod fields;
+pub mod github;
+pub mod i18n;
+pub mod include;
+pub mod job;
+pub mod json;
+pub mod jsonapi;
+pub mod key;
 pub mod label;
+pub mod links;
+pub mod mail;
+pub mod markdown;
+pub mod pdf;
+pub mod push;
+pub mod search;
+pub mod slack;
+pub mod stats;
+pub mod telegram;
 pub mod todo;
+pub mod todoist;
+pub mod trello;
+pub mod version;
+pub mod xlsx;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::repositories::todo::UpdateTodo;
+
+    #[test]
+    fn finds_fields_the_dto_does_not_know() {
+        let input = serde_json::json!({ "text": "a", "compleated": true });
+        let value = UpdateTodo::new(Some("a".to_string()), None);
+        assert_eq!(unknown_fields(&input, &value), vec!["compleated"]);
+
+        let input = serde_json::json!({ "text": "a", "completed": true });
+        assert!(unknown_fields(&input, &value).is_empty());
+    }
+}
@@ -0,0 +1,237 @@
+use std::{env, sync::Arc, time::SystemTime};
+
+use axum::{
+    async_trait,
+    body::{self, Body},
+    extract::{Extension, FromRequest, Query, RequestParts},
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures_util::StreamExt;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    repositories::{
+        backup::{Backup, BackupPart, BackupRepository, RestoreMode, BACKUP_FORMAT},
+        id::IdFormat,
+        label::LabelRepository,
+        todo::TodoRepository,
+    },
+    seed,
+};
+
+use super::{error::ApiError, feed::rfc3339, i18n::tr, ValidatedJson};
+
+/// Request extension unlocking the `/admin` development endpoints, added
+/// when `dev_mode` is on.
+#[derive(Debug, Clone, Copy)]
+pub struct DevMode;
+
+/// Bearer token unlocking the operational `/admin` endpoints, from
+/// `ADMIN_TOKEN`. Without it those endpoints answer 404.
+#[derive(Clone)]
+pub struct AdminToken(pub String);
+
+impl AdminToken {
+    pub fn from_env() -> Option<Self> {
+        env::var("ADMIN_TOKEN")
+            .ok()
+            .filter(|token| !token.is_empty())
+            .map(AdminToken)
+    }
+}
+
+impl std::fmt::Debug for AdminToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("AdminToken(..)")
+    }
+}
+
+/// Extractor for requests carrying the [`AdminToken`] as a bearer token.
+/// It leaves the headers in place, so a body extractor can still follow.
+#[derive(Debug)]
+pub struct Admin;
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for Admin {
+    type Rejection = ApiError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let AdminToken(expected) = req
+            .extensions()
+            .and_then(|extensions| extensions.get::<AdminToken>())
+            .ok_or_else(|| ApiError::NotFound(tr("Admin endpoints need an ADMIN_TOKEN")))?;
+        let given = req
+            .headers()
+            .and_then(|headers| headers.get(header::AUTHORIZATION))
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .unwrap_or_default();
+        ring::constant_time::verify_slices_are_equal(given.as_bytes(), expected.as_bytes())
+            .map(|_| Admin)
+            .map_err(|_| ApiError::Unauthorized(tr("Invalid or missing admin token")))
+    }
+}
+
+/// `GET /admin/backup`: everything stored, as one JSON document written
+/// while it is read. SQL backends read it in a single transaction.
+pub async fn backup<B: BackupRepository>(
+    _: Admin,
+    Extension(repository): Extension<Arc<B>>,
+) -> Result<Response, ApiError> {
+    let head = json!({
+        "format": BACKUP_FORMAT,
+        "backend": repository.backend(),
+        "taken_at": rfc3339(SystemTime::now()),
+    })
+    .to_string();
+    let chunks = futures_util::stream::unfold(
+        (
+            repository.backup(),
+            Some(BackupWriter::new(head.trim_end_matches('}'))),
+        ),
+        |(mut parts, writer)| async move {
+            let mut writer = writer?;
+            match parts.next().await {
+                Some(Ok(part)) => {
+                    let chunk = writer.part(&part).map_err(anyhow::Error::from);
+                    Some((chunk, (parts, Some(writer))))
+                }
+                // the client sees the body cut short
+                Some(Err(e)) => {
+                    tracing::error!("backup failed: {:#}", e);
+                    Some((Err(e), (parts, None)))
+                }
+                None => Some((Ok(writer.finish()), (parts, None))),
+            }
+        },
+    );
+    let mut res = Response::new(body::boxed(Body::wrap_stream(chunks)));
+    res.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    res.headers_mut().insert(
+        header::CONTENT_DISPOSITION,
+        HeaderValue::from_static(r#"attachment; filename="my-todo-backup.json""#),
+    );
+
+    Ok(res)
+}
+
+/// The arrays of a backup document, in the order parts arrive.
+const BACKUP_SECTIONS: [&str; 4] = ["labels", "todos", "todo_labels", "archived_todos"];
+
+/// Writes a backup document piece by piece: the metadata, then one array
+/// per section, opened as its first part arrives.
+struct BackupWriter {
+    /// Written before the first section.
+    pending: Vec<u8>,
+    section: Option<usize>,
+    empty: bool,
+}
+
+impl BackupWriter {
+    /// `head` is the metadata object without its closing brace.
+    fn new(head: &str) -> Self {
+        BackupWriter {
+            pending: head.as_bytes().to_vec(),
+            section: None,
+            empty: true,
+        }
+    }
+
+    /// Opens the arrays up to `section`, closing the one before.
+    fn open(&mut self, section: usize) -> Vec<u8> {
+        let mut out = std::mem::take(&mut self.pending);
+        while self.section.is_none_or(|current| current < section) {
+            let next = self.section.map_or(0, |current| current + 1);
+            if self.section.is_some() {
+                out.push(b']');
+            }
+            out.extend_from_slice(format!(r#","{}":["#, BACKUP_SECTIONS[next]).as_bytes());
+            self.section = Some(next);
+            self.empty = true;
+        }
+        out
+    }
+
+    fn part(&mut self, part: &BackupPart) -> serde_json::Result<Vec<u8>> {
+        let (section, value) = match part {
+            BackupPart::Label(label) => (0, serde_json::to_vec(label)?),
+            BackupPart::Todo(todo) => (1, serde_json::to_vec(todo)?),
+            BackupPart::TodoLabel(todo_label) => (2, serde_json::to_vec(todo_label)?),
+            BackupPart::Archived(todo) => (3, serde_json::to_vec(todo)?),
+        };
+        let mut out = self.open(section);
+        if !self.empty {
+            out.push(b',');
+        }
+        out.extend(value);
+        self.empty = false;
+        Ok(out)
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let mut out = self.open(BACKUP_SECTIONS.len() - 1);
+        out.extend_from_slice(b"]}");
+        out
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RestoreQuery {
+    #[serde(default)]
+    mode: RestoreMode,
+}
+
+/// `POST /admin/restore?mode=wipe|merge`: puts a backup from
+/// `GET /admin/backup` back, merging it into what is stored by default.
+/// A merge skips the todos whose uuid is stored already, so it is refused
+/// with `422` for backups with todos that have none, as with the default
+/// `ID_FORMAT=serial`; those need `mode=wipe`. Answers how many rows of
+/// each kind were added.
+pub async fn restore<B: BackupRepository>(
+    _: Admin,
+    Query(query): Query<RestoreQuery>,
+    Extension(repository): Extension<Arc<B>>,
+    ValidatedJson(backup): ValidatedJson<Backup>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::info!(
+        "restoring a {} backup taken at {} ({:?})",
+        backup.backend,
+        backup.taken_at,
+        query.mode
+    );
+    if query.mode == RestoreMode::Merge {
+        backup.check_mergeable()?;
+    }
+    let restored = repository.restore(backup, query.mode).await?;
+
+    Ok(Json(restored))
+}
+
+/// `POST /admin/seed`: fills an empty database with demo data. Answers 404
+/// outside dev mode so production servers don't advertise it.
+pub async fn seed_demo<T: TodoRepository, L: LabelRepository>(
+    dev_mode: Option<Extension<DevMode>>,
+    Extension(todo_repository): Extension<Arc<T>>,
+    Extension(label_repository): Extension<Arc<L>>,
+    format: IdFormat,
+) -> Result<impl IntoResponse, ApiError> {
+    if dev_mode.is_none() {
+        return Err(ApiError::NotFound(tr(
+            "Seeding is only available in dev mode",
+        )));
+    }
+    let seeded = seed::seed(&*todo_repository, &*label_repository, format).await?;
+    let status = if seeded.todos > 0 {
+        StatusCode::CREATED
+    } else {
+        StatusCode::OK
+    };
+
+    Ok((status, Json(seeded)))
+}
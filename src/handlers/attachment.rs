@@ -0,0 +1,157 @@
+//! `/attachments/:id`: files attached to a todo, kept by the
+//! [`Attachments`] store.
+
+use std::sync::Arc;
+
+use axum::{
+    body::boxed,
+    extract::{Extension, Path, RawBody},
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+    Json,
+};
+
+use crate::{
+    attachments::{valid_name, Attachments, Download, UploadError},
+    repositories::{
+        id::{IdFormat, Key},
+        todo::TodoRepository,
+    },
+};
+
+use super::{
+    error::ApiError,
+    i18n::{tr, trf},
+    percent_encode,
+};
+
+fn enabled(attachments: Option<Extension<Attachments>>) -> Result<Attachments, ApiError> {
+    attachments
+        .map(|Extension(attachments)| attachments)
+        .ok_or_else(|| ApiError::NotFound(tr("Attachments need an [attachments] backend")))
+}
+
+/// The id of the todo `key` names, which must exist: a serial id
+/// resolves whether or not its todo does.
+async fn existing<T: TodoRepository>(repository: &T, key: &Key) -> Result<i64, ApiError> {
+    let id = repository.resolve(key).await?;
+    repository.find(id).await?;
+    Ok(id)
+}
+
+/// The todo of the `:id` segment, and the attachment name after it.
+async fn todo_and_name<T: TodoRepository>(
+    repository: &T,
+    format: IdFormat,
+    (id, name): (String, String),
+) -> Result<(i64, String), ApiError> {
+    let key = Key::parse(&id, format)
+        .ok_or_else(|| ApiError::NotFound(trf("NotFound, id is {}", &[&id])))?;
+    let todo = existing(repository, &key).await?;
+    if !valid_name(&name) {
+        return Err(ApiError::NotFound(trf(
+            "There is no attachment {}",
+            &[&name],
+        )));
+    }
+    Ok((todo, name))
+}
+
+fn internal(e: anyhow::Error) -> ApiError {
+    tracing::error!("attachment store failed: {:#}", e);
+    ApiError::Internal
+}
+
+/// `GET /attachments/:id`: the names and sizes of the todo's attachments.
+pub async fn list_attachments<T: TodoRepository>(
+    key: Key,
+    Extension(repository): Extension<Arc<T>>,
+    attachments: Option<Extension<Attachments>>,
+) -> Result<impl IntoResponse, ApiError> {
+    let attachments = enabled(attachments)?;
+    let todo = existing(&*repository, &key).await?;
+    let stored = attachments.list(todo).await.map_err(internal)?;
+
+    Ok(Json(stored))
+}
+
+/// `PUT /attachments/:id/:name` with the file as the body, streamed to
+/// the store; a file of that name is replaced.
+pub async fn put_attachment<T: TodoRepository>(
+    Path(path): Path<(String, String)>,
+    Extension(repository): Extension<Arc<T>>,
+    attachments: Option<Extension<Attachments>>,
+    format: IdFormat,
+    RawBody(body): RawBody,
+) -> Result<impl IntoResponse, ApiError> {
+    let attachments = enabled(attachments)?;
+    let (todo, name) = todo_and_name(&*repository, format, path).await?;
+    let stored = attachments.put(todo, &name, body).await.map_err(|e| {
+        match e.downcast_ref::<UploadError>() {
+            Some(UploadError::TooLarge(max)) => {
+                ApiError::TooLarge(trf("The file is larger than {} bytes", &[max]))
+            }
+            Some(UploadError::Body(read)) => ApiError::BadRequest(read.to_string()),
+            None => internal(e),
+        }
+    })?;
+
+    Ok((StatusCode::CREATED, Json(stored)))
+}
+
+/// `GET /attachments/:id/:name`: the file, or a redirect to a link the
+/// store made for it.
+pub async fn get_attachment<T: TodoRepository>(
+    Path(path): Path<(String, String)>,
+    Extension(repository): Extension<Arc<T>>,
+    attachments: Option<Extension<Attachments>>,
+    format: IdFormat,
+) -> Result<Response, ApiError> {
+    let attachments = enabled(attachments)?;
+    let (todo, name) = todo_and_name(&*repository, format, path).await?;
+    let download = attachments
+        .get(todo, &name)
+        .await
+        .map_err(internal)?
+        .ok_or_else(|| ApiError::NotFound(trf("There is no attachment {}", &[&name])))?;
+
+    Ok(match download {
+        Download::Body { size, body } => {
+            let mut res = Response::new(boxed(body));
+            let headers = res.headers_mut();
+            headers.insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static(mime::APPLICATION_OCTET_STREAM.as_ref()),
+            );
+            headers.insert(header::CONTENT_LENGTH, HeaderValue::from(size));
+            // served as a download, so an uploaded page can't run here
+            if let Ok(disposition) = HeaderValue::from_str(&format!(
+                "attachment; filename*=UTF-8''{}",
+                percent_encode(&name, false)
+            )) {
+                headers.insert(header::CONTENT_DISPOSITION, disposition);
+            }
+            res
+        }
+        Download::Redirect(link) => Redirect::temporary(link).into_response(),
+    })
+}
+
+/// `DELETE /attachments/:id/:name`.
+pub async fn delete_attachment<T: TodoRepository>(
+    Path(path): Path<(String, String)>,
+    Extension(repository): Extension<Arc<T>>,
+    attachments: Option<Extension<Attachments>>,
+    format: IdFormat,
+) -> Result<StatusCode, ApiError> {
+    let attachments = enabled(attachments)?;
+    let (todo, name) = todo_and_name(&*repository, format, path).await?;
+    if attachments.delete(todo, &name).await.map_err(internal)? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ApiError::NotFound(trf(
+            "There is no attachment {}",
+            &[&name],
+        )))
+    }
+}
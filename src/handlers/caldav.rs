@@ -0,0 +1,472 @@
+//! Minimal CalDAV (RFC 4791) calendar collection exposing todos as VTODO
+//! resources at `/caldav/todos/{id}.ics`.
+//!
+//! Only what task clients need to sync is supported: `PROPFIND` on the
+//! collection and its members, `calendar-query`/`calendar-multiget`
+//! `REPORT`s (filters are ignored, every todo is returned) and
+//! `GET`/`PUT`/`DELETE` of single resources. `SUMMARY` and `STATUS` are the
+//! only VTODO properties mapped onto a todo.
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use axum::{
+    body::Body,
+    extract::Extension,
+    http::{header, HeaderValue, Method, Request, StatusCode},
+    response::{IntoResponse, Response},
+};
+use validator::Validate;
+
+use crate::repositories::{
+    date::Date,
+    todo::{CreateTodo, Todo, TodoRepository, UpdateTodo},
+};
+
+use super::{error::ApiError, escape, feed::rfc3339, i18n::trf, links::LinkBuilder, unescape};
+
+pub const CALENDAR: &str = "text/calendar; charset=utf-8";
+const MULTISTATUS_XML: &str = "application/xml; charset=utf-8";
+const DAV: &str = "1, calendar-access";
+const ALLOW: &str = "OPTIONS, PROPFIND, REPORT, GET, PUT, DELETE";
+
+/// `/caldav/todos/`: the calendar collection itself.
+pub async fn collection<T: TodoRepository>(
+    Extension(repository): Extension<Arc<T>>,
+    links: LinkBuilder,
+    req: Request<Body>,
+) -> Result<Response, ApiError> {
+    let (parts, body) = req.into_parts();
+    match parts.method.as_str() {
+        "OPTIONS" => Ok(options()),
+        "PROPFIND" => {
+            let depth_one = parts
+                .headers
+                .get("depth")
+                .is_some_and(|depth| depth.as_bytes() != b"0");
+            let mut responses = vec![collection_response(&links, &*repository).await?];
+            if depth_one {
+                for (todo, at) in repository.recently_modified(i64::MAX).await? {
+                    responses.push(member_response(&links, &todo, at, false));
+                }
+            }
+            Ok(multistatus(responses))
+        }
+        "REPORT" => {
+            let body = read_body(body).await?;
+            let hrefs = hrefs(&body);
+            let responses = repository
+                .recently_modified(i64::MAX)
+                .await?
+                .into_iter()
+                .map(|(todo, at)| (href(&links, todo.id()), todo, at))
+                .filter(|(href, ..)| hrefs.is_empty() || hrefs.contains(href))
+                .map(|(_, todo, at)| member_response(&links, &todo, at, true))
+                .collect();
+            Ok(multistatus(responses))
+        }
+        _ => Ok(method_not_allowed()),
+    }
+}
+
+/// `/caldav/todos/:file`: one VTODO, named `{id}.ics`.
+pub async fn resource<T: TodoRepository>(
+    Extension(repository): Extension<Arc<T>>,
+    links: LinkBuilder,
+    req: Request<Body>,
+) -> Result<Response, ApiError> {
+    let (parts, body) = req.into_parts();
+    let file = parts.uri.path().rsplit('/').next().unwrap_or_default();
+    let id = file
+        .strip_suffix(".ics")
+        .and_then(|id| id.parse::<i64>().ok());
+
+    match (parts.method, id) {
+        (Method::OPTIONS, _) => Ok(options()),
+        (Method::GET, Some(id)) => {
+            let todo = repository.find(id).await?;
+            let at = repository.last_modified(id).await?;
+            let ics = vcalendar(&todo, at);
+            let mut res = ics_response(&ics);
+            res.headers_mut().insert(header::ETAG, etag(&ics));
+            Ok(res)
+        }
+        (Method::PUT, id) => {
+            let ics = read_body(body).await?;
+            let vtodo = parse_vtodo(&ics)?;
+            let (status, todo) = match id {
+                Some(id) if repository.find(id).await.is_ok() => {
+                    let payload = UpdateTodo::new(Some(vtodo.summary), Some(vtodo.completed))
+                        .with_due(vtodo.due);
+                    payload.validate()?;
+                    (
+                        StatusCode::NO_CONTENT,
+                        repository.update(id, payload).await?,
+                    )
+                }
+                _ => {
+                    let payload = CreateTodo::new(vtodo.summary).with_due(vtodo.due);
+                    payload.validate()?;
+                    let todo = repository.create(payload).await?;
+                    let todo = if vtodo.completed {
+                        let payload = UpdateTodo::new(None, Some(true));
+                        repository.update(todo.id(), payload).await?
+                    } else {
+                        todo
+                    };
+                    (StatusCode::CREATED, todo)
+                }
+            };
+            let at = repository.last_modified(todo.id()).await?;
+            let mut res = status.into_response();
+            let headers = res.headers_mut();
+            headers.insert(header::ETAG, etag(&vcalendar(&todo, at)));
+            if let Ok(location) = HeaderValue::from_str(&href(&links, todo.id())) {
+                headers.insert(header::LOCATION, location);
+            }
+            Ok(res)
+        }
+        (Method::DELETE, Some(id)) => {
+            repository.delete(id).await?;
+            Ok(StatusCode::NO_CONTENT.into_response())
+        }
+        (Method::GET | Method::DELETE, None) => {
+            Err(ApiError::NotFound(trf("NotFound, id is {}", &[&file])))
+        }
+        _ => Ok(method_not_allowed()),
+    }
+}
+
+fn href(links: &LinkBuilder, id: i64) -> String {
+    format!("{}{}.ics", collection_href(links), id)
+}
+
+fn collection_href(links: &LinkBuilder) -> String {
+    let todos = links.todos().href;
+    let prefix = todos.trim_end_matches("/todos");
+    format!("{}/caldav/todos/", prefix)
+}
+
+async fn read_body(body: Body) -> Result<String, ApiError> {
+    let bytes = hyper::body::to_bytes(body)
+        .await
+        .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+    String::from_utf8(bytes.to_vec()).map_err(|e| ApiError::BadRequest(e.to_string()))
+}
+
+fn options() -> Response {
+    let mut res = StatusCode::OK.into_response();
+    let headers = res.headers_mut();
+    headers.insert("dav", HeaderValue::from_static(DAV));
+    headers.insert(header::ALLOW, HeaderValue::from_static(ALLOW));
+    res
+}
+
+fn method_not_allowed() -> Response {
+    let mut res = StatusCode::METHOD_NOT_ALLOWED.into_response();
+    res.headers_mut()
+        .insert(header::ALLOW, HeaderValue::from_static(ALLOW));
+    res
+}
+
+fn ics_response(ics: &str) -> Response {
+    let mut res = ics.to_string().into_response();
+    res.headers_mut()
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static(CALENDAR));
+    res
+}
+
+fn etag(ics: &str) -> HeaderValue {
+    let mut hasher = DefaultHasher::new();
+    ics.hash(&mut hasher);
+    HeaderValue::from_str(&format!("\"{:016x}\"", hasher.finish()))
+        .expect("hex digits are a valid header value")
+}
+
+async fn collection_response<T: TodoRepository>(
+    links: &LinkBuilder,
+    repository: &T,
+) -> Result<String, ApiError> {
+    let ctag = repository
+        .collection_last_modified()
+        .await?
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    Ok(format!(
+        concat!(
+            "<d:response><d:href>{}</d:href><d:propstat><d:prop>",
+            "<d:resourcetype><d:collection/><c:calendar/></d:resourcetype>",
+            "<d:displayname>Todos</d:displayname>",
+            r#"<c:supported-calendar-component-set><c:comp name="VTODO"/></c:supported-calendar-component-set>"#,
+            "<cs:getctag>{}</cs:getctag>",
+            "</d:prop><d:status>HTTP/1.1 200 OK</d:status></d:propstat></d:response>",
+        ),
+        escape(&collection_href(links)),
+        ctag
+    ))
+}
+
+fn member_response(links: &LinkBuilder, todo: &Todo, at: SystemTime, data: bool) -> String {
+    let ics = vcalendar(todo, at);
+    let etag = etag(&ics);
+    let calendar_data = if data {
+        format!("<c:calendar-data>{}</c:calendar-data>", escape(&ics))
+    } else {
+        String::new()
+    };
+    format!(
+        concat!(
+            "<d:response><d:href>{}</d:href><d:propstat><d:prop>",
+            "<d:resourcetype/>",
+            "<d:getcontenttype>{}</d:getcontenttype>",
+            "<d:getetag>{}</d:getetag>{}",
+            "</d:prop><d:status>HTTP/1.1 200 OK</d:status></d:propstat></d:response>",
+        ),
+        escape(&href(links, todo.id())),
+        CALENDAR,
+        escape(etag.to_str().unwrap_or_default()),
+        calendar_data
+    )
+}
+
+fn multistatus(responses: Vec<String>) -> Response {
+    let body = format!(
+        concat!(
+            r#"<?xml version="1.0" encoding="utf-8"?>"#,
+            r#"<d:multistatus xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav" xmlns:cs="http://calendarserver.org/ns/">"#,
+            "{}</d:multistatus>",
+        ),
+        responses.concat()
+    );
+    let mut res = (StatusCode::MULTI_STATUS, body).into_response();
+    res.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(MULTISTATUS_XML),
+    );
+    res
+}
+
+/// Contents of every `<href>` element in a REPORT body, whatever the prefix.
+fn hrefs(xml: &str) -> Vec<String> {
+    let mut hrefs = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find("href>") {
+        let is_open_tag = rest[..start]
+            .rfind('<')
+            .is_some_and(|lt| !rest[lt..start].starts_with("</"));
+        rest = &rest[start + "href>".len()..];
+        if let (true, Some(end)) = (is_open_tag, rest.find('<')) {
+            hrefs.push(unescape(rest[..end].trim()));
+        }
+    }
+    hrefs
+}
+
+/// One todo as an iCalendar object with a single VTODO.
+pub fn vcalendar(todo: &Todo, at: SystemTime) -> String {
+    format!(
+        concat!(
+            "BEGIN:VCALENDAR\r\n",
+            "VERSION:2.0\r\n",
+            "PRODID:-//my-todo//EN\r\n",
+            "{}",
+            "END:VCALENDAR\r\n",
+        ),
+        vtodo(todo, at)
+    )
+}
+
+/// The VTODO component of a todo last modified `at`.
+pub fn vtodo(todo: &Todo, at: SystemTime) -> String {
+    let stamp: String = rfc3339(at)
+        .chars()
+        .filter(|c| !matches!(c, '-' | ':'))
+        .collect();
+    let status = if todo.completed() {
+        "COMPLETED"
+    } else {
+        "NEEDS-ACTION"
+    };
+    let due = todo
+        .due()
+        .map(|due| format!("DUE;VALUE=DATE:{}\r\n", due.basic()))
+        .unwrap_or_default();
+    format!(
+        concat!(
+            "BEGIN:VTODO\r\n",
+            "UID:my-todo-{}\r\n",
+            "DTSTAMP:{}\r\n",
+            "LAST-MODIFIED:{}\r\n",
+            "SUMMARY:{}\r\n",
+            "{}",
+            "STATUS:{}\r\n",
+            "END:VTODO\r\n",
+        ),
+        todo.id(),
+        stamp,
+        stamp,
+        escape_text(todo.text()),
+        due,
+        status
+    )
+}
+
+/// An all-day VEVENT on the day `todo` is `due`, for calendar apps that
+/// show events but not VTODOs.
+pub fn vevent(todo: &Todo, due: &Date, at: SystemTime) -> String {
+    let stamp: String = rfc3339(at)
+        .chars()
+        .filter(|c| !matches!(c, '-' | ':'))
+        .collect();
+    format!(
+        concat!(
+            "BEGIN:VEVENT\r\n",
+            "UID:my-todo-{}-due\r\n",
+            "DTSTAMP:{}\r\n",
+            "DTSTART;VALUE=DATE:{}\r\n",
+            "DTEND;VALUE=DATE:{}\r\n",
+            "SUMMARY:{}\r\n",
+            "TRANSP:TRANSPARENT\r\n",
+            "END:VEVENT\r\n",
+        ),
+        todo.id(),
+        stamp,
+        due.basic(),
+        Date::from_days(due.days() + 1).basic(),
+        escape_text(todo.text()),
+    )
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct VTodo {
+    summary: String,
+    completed: bool,
+    due: Option<Date>,
+}
+
+fn parse_vtodo(ics: &str) -> Result<VTodo, ApiError> {
+    let unfolded = ics
+        .replace("\r\n ", "")
+        .replace("\r\n\t", "")
+        .replace("\n ", "");
+    let mut in_vtodo = false;
+    let mut summary = None;
+    let mut completed = false;
+    let mut due = None;
+    for line in unfolded.lines().map(|line| line.trim_end_matches('\r')) {
+        match line {
+            "BEGIN:VTODO" => in_vtodo = true,
+            "END:VTODO" => break,
+            _ if in_vtodo => {
+                let Some((name, value)) = line.split_once(':') else {
+                    continue;
+                };
+                // Drop parameters such as `SUMMARY;LANGUAGE=en`.
+                match name.split(';').next().unwrap_or_default() {
+                    "SUMMARY" => summary = Some(unescape_text(value)),
+                    "STATUS" => completed = value == "COMPLETED",
+                    "DUE" => due = Some(parse_due(value)?),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let summary = summary.ok_or_else(|| {
+        ApiError::BadRequest(trf("Invalid iCalendar: [{}]", &[&"VTODO with SUMMARY"]))
+    })?;
+    Ok(VTodo {
+        summary,
+        completed,
+        due,
+    })
+}
+
+/// The day of a `DUE` date or date-time, whatever its time zone.
+fn parse_due(value: &str) -> Result<Date, ApiError> {
+    let invalid = || ApiError::BadRequest(trf("Invalid iCalendar: [{}]", &[&"DUE"]));
+    let day = value
+        .get(..8)
+        .filter(|day| day.bytes().all(|b| b.is_ascii_digit()))
+        .ok_or_else(invalid)?;
+    format!("{}-{}-{}", &day[..4], &day[4..6], &day[6..])
+        .parse()
+        .map_err(|_| invalid())
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+fn unescape_text(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match (c, c == '\\') {
+            (_, true) => match chars.next() {
+                Some('n' | 'N') => unescaped.push('\n'),
+                Some(c) => unescaped.push(c),
+                None => {}
+            },
+            (c, false) => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trips_vtodo() {
+        let todo = Todo::new(1, "milk, eggs; bread".to_string());
+        let ics = vcalendar(&todo, SystemTime::UNIX_EPOCH);
+        assert!(ics.contains("SUMMARY:milk\\, eggs\\; bread\r\n"));
+        assert!(ics.contains("DTSTAMP:19700101T000000Z\r\n"));
+
+        let vtodo = parse_vtodo(&ics).unwrap();
+        assert_eq!(
+            vtodo,
+            VTodo {
+                summary: "milk, eggs; bread".to_string(),
+                completed: false,
+                due: None,
+            }
+        );
+
+        let folded = "BEGIN:VCALENDAR\r\nBEGIN:VTODO\r\nSUMMARY;LANGUAGE=en:long\r\n  line\r\nSTATUS:COMPLETED\r\nEND:VTODO\r\nEND:VCALENDAR\r\n";
+        let vtodo = parse_vtodo(folded).unwrap();
+        assert_eq!(vtodo.summary, "long line");
+        assert!(vtodo.completed);
+        assert!(parse_vtodo("BEGIN:VCALENDAR\r\nEND:VCALENDAR\r\n").is_err());
+
+        let due =
+            "BEGIN:VTODO\r\nSUMMARY:rent\r\nDUE;TZID=Europe/Paris:20240601T090000\r\nEND:VTODO\r\n";
+        let vtodo = parse_vtodo(due).unwrap();
+        assert_eq!(vtodo.due.as_ref().map(Date::as_str), Some("2024-06-01"));
+        let todo: Todo = serde_json::from_value(serde_json::json!({
+            "id": 2, "text": "rent", "completed": false, "due": "2024-06-01"
+        }))
+        .unwrap();
+        assert!(vcalendar(&todo, SystemTime::UNIX_EPOCH).contains("DUE;VALUE=DATE:20240601\r\n"));
+        assert!(parse_vtodo(&due.replace("20240601", "20240631")).is_err());
+    }
+
+    #[test]
+    fn extracts_multiget_hrefs() {
+        let body = r#"<c:calendar-multiget xmlns:d="DAV:"><d:prop><d:getetag/></d:prop><d:href>/caldav/todos/1.ics</d:href><href>/caldav/todos/2.ics</href></c:calendar-multiget>"#;
+        assert_eq!(
+            hrefs(body),
+            vec!["/caldav/todos/1.ics", "/caldav/todos/2.ics"]
+        );
+    }
+}
@@ -0,0 +1,50 @@
+use std::{convert::Infallible, time::SystemTime};
+
+use axum::{
+    async_trait,
+    extract::{FromRequest, RequestParts},
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+
+/// Parsed `If-Modified-Since` header; absent or malformed values are ignored
+/// as RFC 9110 requires.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IfModifiedSince(Option<SystemTime>);
+
+impl IfModifiedSince {
+    /// Whether the client's copy is still current for a resource last
+    /// modified at `last_modified` (both have one second resolution).
+    pub fn is_fresh(&self, last_modified: SystemTime) -> bool {
+        self.0.is_some_and(|since| {
+            httpdate::HttpDate::from(last_modified) <= httpdate::HttpDate::from(since)
+        })
+    }
+}
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for IfModifiedSince {
+    type Rejection = Infallible;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let since = req
+            .headers()
+            .and_then(|headers| headers.get(header::IF_MODIFIED_SINCE))
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| httpdate::parse_http_date(value).ok());
+
+        Ok(IfModifiedSince(since))
+    }
+}
+
+pub fn set_last_modified(res: &mut Response, last_modified: SystemTime) {
+    if let Ok(value) = HeaderValue::from_str(&httpdate::fmt_http_date(last_modified)) {
+        res.headers_mut().insert(header::LAST_MODIFIED, value);
+    }
+}
+
+pub fn not_modified(last_modified: SystemTime) -> Response {
+    let mut res = StatusCode::NOT_MODIFIED.into_response();
+    set_last_modified(&mut res, last_modified);
+    res
+}
@@ -0,0 +1,447 @@
+//! Todos as CSV: `POST /import/csv` creates one per row of a spreadsheet
+//! export, mapping its columns by header, and `GET /export/csv` writes
+//! them out in a form it reads back.
+
+use std::sync::Arc;
+
+use axum::{
+    body::{self, Body, Bytes},
+    extract::{Extension, Query},
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use csv::{ReaderBuilder, StringRecord, Trim, WriterBuilder};
+use futures_util::{future, stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::repositories::{
+    id::IdFormat,
+    label::{Label, LabelRepository},
+    todo::{CreateTodo, TodoRepository, TodoWithLabels},
+};
+
+use super::{
+    error::{field_errors, ApiError, FieldErrors},
+    i18n::{tr, trf},
+    search::Search,
+    todo::stream_with_labels,
+};
+
+pub const CSV: &str = "text/csv; charset=utf-8";
+
+/// Rows inserted per `create_many`, so a large upload isn't one long
+/// transaction.
+const BATCH: usize = 100;
+
+/// The columns of an export.
+const HEADER: [&str; 6] = ["id", "uuid", "text", "completed", "due", "labels"];
+
+/// The field errors about a row as a whole are reported under.
+const ROW: &str = "row";
+
+/// Which column holds what, by header, e.g. `?text=Title&labels=Tags`.
+/// Headers are matched ignoring case.
+#[derive(Debug, Default, Deserialize)]
+pub struct ColumnMapping {
+    /// `text` by default; the only column a file must have.
+    text: Option<String>,
+    /// `true`/`false`, `yes`/`no`, `1`/`0` or `x`; `completed` by default.
+    completed: Option<String>,
+    /// A day as `YYYY-MM-DD`, or empty; `due` by default.
+    due: Option<String>,
+    /// Label names or ids separated by `;`; `labels` by default.
+    labels: Option<String>,
+    /// A single character, `,` by default.
+    delimiter: Option<String>,
+}
+
+/// Where the mapped columns are in a record.
+#[derive(Debug, PartialEq, Eq)]
+struct Columns {
+    text: usize,
+    completed: Option<usize>,
+    due: Option<usize>,
+    labels: Option<usize>,
+}
+
+impl ColumnMapping {
+    fn delimiter(&self) -> Result<u8, ApiError> {
+        match self.delimiter.as_deref().map(str::as_bytes) {
+            None => Ok(b','),
+            Some(&[delimiter]) if delimiter.is_ascii() => Ok(delimiter),
+            Some(_) => Err(ApiError::BadRequest(tr(
+                "The delimiter must be a single character",
+            ))),
+        }
+    }
+
+    /// Columns the query names must be there; of the defaults only `text`.
+    fn columns(&self, headers: &StringRecord) -> Result<Columns, ApiError> {
+        let find = |name: &str| {
+            headers
+                .iter()
+                .position(|header| header.eq_ignore_ascii_case(name))
+        };
+        let unknown = |name: &str| ApiError::BadRequest(trf("Unknown column: [{}]", &[&name]));
+        let text = self.text.as_deref().unwrap_or("text");
+        let optional = |mapped: &Option<String>, default: &str| match mapped {
+            Some(name) => find(name).map(Some).ok_or_else(|| unknown(name)),
+            None => Ok(find(default)),
+        };
+        Ok(Columns {
+            text: find(text).ok_or_else(|| unknown(text))?,
+            completed: optional(&self.completed, "completed")?,
+            due: optional(&self.due, "due")?,
+            labels: optional(&self.labels, "labels")?,
+        })
+    }
+}
+
+/// A row of the upload, by the line it starts on: the todo to create, or
+/// what is wrong with it.
+#[derive(Debug)]
+struct Row {
+    line: u64,
+    parsed: Result<CreateTodo, FieldErrors>,
+}
+
+/// Reads every row of `body`, looking the labels it names up in `labels`.
+/// Fails as a whole only when the header can't be read or lacks a column.
+fn parse(body: &[u8], mapping: &ColumnMapping, labels: &[Label]) -> Result<Vec<Row>, ApiError> {
+    let mut reader = ReaderBuilder::new()
+        .delimiter(mapping.delimiter()?)
+        .flexible(true)
+        .trim(Trim::All)
+        .from_reader(body);
+    let headers = reader
+        .headers()
+        .map_err(|e| ApiError::BadRequest(trf("Invalid CSV: [{}]", &[&e])))?;
+    let columns = mapping.columns(headers)?;
+
+    let mut rows = vec![];
+    for record in reader.records() {
+        let row = match record {
+            Ok(record) => Row {
+                line: record.position().map_or(0, |position| position.line()),
+                parsed: parse_row(&record, &columns, labels),
+            },
+            Err(e) => Row {
+                line: e.position().map_or(0, |position| position.line()),
+                parsed: Err(row_error(trf("Invalid CSV: [{}]", &[&e]))),
+            },
+        };
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+fn parse_row(
+    record: &StringRecord,
+    columns: &Columns,
+    labels: &[Label],
+) -> Result<CreateTodo, FieldErrors> {
+    let field = |column: Option<usize>| column.and_then(|i| record.get(i)).unwrap_or_default();
+    let mut errors = FieldErrors::new();
+
+    let completed = parse_completed(field(columns.completed)).unwrap_or_else(|| {
+        errors.insert("completed".to_string(), vec![tr("must be true or false")]);
+        false
+    });
+    let due = match field(columns.due) {
+        "" => None,
+        due => due.parse().map(Some).unwrap_or_else(|_| {
+            errors.insert("due".to_string(), vec![tr("must be a day as YYYY-MM-DD")]);
+            None
+        }),
+    };
+    let label_ids = label_ids(field(columns.labels), labels).unwrap_or_else(|unknown| {
+        errors.insert("labels".to_string(), unknown);
+        vec![]
+    });
+    let payload = CreateTodo::new(field(Some(columns.text)).to_string())
+        .with_labels(label_ids)
+        .with_completed(completed)
+        .with_due(due);
+    if let Err(invalid) = payload.validate() {
+        errors.extend(field_errors(&invalid));
+    }
+
+    if errors.is_empty() {
+        Ok(payload)
+    } else {
+        Err(errors)
+    }
+}
+
+fn parse_completed(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "" | "false" | "no" | "0" => Some(false),
+        "true" | "yes" | "1" | "x" => Some(true),
+        _ => None,
+    }
+}
+
+/// The ids of the labels `value` names, or a message per one that is
+/// neither the name nor the id of a label.
+fn label_ids(value: &str, labels: &[Label]) -> Result<Vec<i64>, Vec<String>> {
+    let mut ids = vec![];
+    let mut unknown = vec![];
+    for entry in value
+        .split(';')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+    {
+        let label = labels
+            .iter()
+            .find(|label| label.name == entry || label.id.to_string() == entry);
+        match label {
+            Some(label) if !ids.contains(&label.id) => ids.push(label.id),
+            Some(_) => {}
+            None => unknown.push(trf("unknown label: [{}]", &[&entry])),
+        }
+    }
+    if unknown.is_empty() {
+        Ok(ids)
+    } else {
+        Err(unknown)
+    }
+}
+
+fn row_error(message: String) -> FieldErrors {
+    FieldErrors::from([(ROW.to_string(), vec![message])])
+}
+
+/// What became of a row: the todo created from it, or what was wrong with
+/// it.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RowReport {
+    pub line: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub errors: Option<FieldErrors>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ImportReport {
+    /// Rows a todo was created from.
+    pub imported: usize,
+    /// Rows that were skipped.
+    pub failed: usize,
+    /// Every row, in file order.
+    pub rows: Vec<RowReport>,
+}
+
+/// `POST /import/csv` with the file as the body and its [`ColumnMapping`]
+/// in the query. Valid rows are created [`BATCH`] at a time while the others
+/// are skipped; the report says which was which. A batch the repository
+/// rejects is skipped as a whole.
+pub async fn import_csv<T: TodoRepository, L: LabelRepository>(
+    Query(mapping): Query<ColumnMapping>,
+    Extension(todo_repository): Extension<Arc<T>>,
+    Extension(label_repository): Extension<Arc<L>>,
+    format: IdFormat,
+    body: Bytes,
+) -> Result<impl IntoResponse, ApiError> {
+    let labels = label_repository.all().await?;
+    let mut report = ImportReport::default();
+    let mut valid = vec![];
+    for row in parse(&body, &mapping, &labels)? {
+        match row.parsed {
+            Ok(payload) => valid.push((row.line, payload.with_format(format))),
+            Err(errors) => report.rows.push(RowReport {
+                line: row.line,
+                id: None,
+                errors: Some(errors),
+            }),
+        }
+    }
+
+    while !valid.is_empty() {
+        let batch: Vec<_> = valid.drain(..valid.len().min(BATCH)).collect();
+        let payloads = batch.iter().map(|(_, payload)| payload.clone()).collect();
+        let created = match todo_repository.create_many(payloads).await {
+            Ok(created) => created,
+            Err(e) => {
+                let errors = row_error(tr(&ApiError::from(e).to_string()));
+                report
+                    .rows
+                    .extend(batch.into_iter().map(|(line, ..)| RowReport {
+                        line,
+                        id: None,
+                        errors: Some(errors.clone()),
+                    }));
+                continue;
+            }
+        };
+        for ((line, _), todo) in batch.into_iter().zip(created) {
+            report.rows.push(RowReport {
+                line,
+                id: Some(todo.id()),
+                errors: None,
+            });
+        }
+    }
+
+    report.rows.sort_by_key(|row| row.line);
+    report.imported = report.rows.iter().filter(|row| row.id.is_some()).count();
+    report.failed = report.rows.len() - report.imported;
+    tracing::info!(
+        "imported {} todos from csv, {} rows failed",
+        report.imported,
+        report.failed
+    );
+    let status = if report.imported > 0 {
+        StatusCode::CREATED
+    } else {
+        StatusCode::OK
+    };
+
+    Ok((status, Json(report)))
+}
+
+/// `todos` as CSV rows under [`HEADER`], their label names joined by `;`.
+fn rows(todos: &[TodoWithLabels]) -> anyhow::Result<Vec<u8>> {
+    let mut writer = WriterBuilder::new().has_headers(false).from_writer(vec![]);
+    for TodoWithLabels { todo, labels } in todos {
+        let labels: Vec<&str> = labels.iter().map(|label| label.name.as_str()).collect();
+        writer.write_record([
+            todo.id().to_string(),
+            todo.uuid().map(ToString::to_string).unwrap_or_default(),
+            todo.text().to_string(),
+            todo.completed().to_string(),
+            todo.due().map(ToString::to_string).unwrap_or_default(),
+            labels.join(";"),
+        ])?;
+    }
+    Ok(writer.into_inner().map_err(|e| e.into_error())?)
+}
+
+/// `GET /export/csv`: the todos `GET /todos` lists for the same `?q=` and
+/// `?ids=`, oldest first, written as they are read so the export never sits
+/// in memory as a whole. `POST /import/csv` reads it back.
+pub async fn export_csv<T: TodoRepository>(
+    Extension(repository): Extension<Arc<T>>,
+    Search(filter): Search,
+) -> Response {
+    let header = format!("{}\n", HEADER.join(",")).into_bytes();
+    let rows = stream_with_labels(repository, filter).map(|todos| rows(&todos?));
+    let body = stream::once(future::ready(anyhow::Ok(header))).chain(rows);
+    let mut res = Response::new(body::boxed(Body::wrap_stream(body)));
+    let headers = res.headers_mut();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(CSV));
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        HeaderValue::from_static(r#"attachment; filename="todos.csv""#),
+    );
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::repositories::todo::Todo;
+
+    fn label(id: i64, name: &str) -> Label {
+        Label {
+            id,
+            uuid: None,
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn maps_columns_by_header() {
+        let mapping = ColumnMapping {
+            text: Some("Title".to_string()),
+            delimiter: Some(";".to_string()),
+            ..ColumnMapping::default()
+        };
+        let body = "Done;title;Labels\nyes;Buy milk;\n";
+        let rows = parse(body.as_bytes(), &mapping, &[]).unwrap();
+        let payload = rows[0].parsed.as_ref().unwrap();
+        // `Done` isn't mapped
+        assert_eq!(payload, &CreateTodo::new("Buy milk".to_string()));
+
+        let mapping = ColumnMapping {
+            due: Some("Deadline".to_string()),
+            ..ColumnMapping::default()
+        };
+        let rows = parse(b"text,deadline\nRent,2024-06-01\n", &mapping, &[]).unwrap();
+        assert_eq!(
+            rows[0].parsed.as_ref().unwrap(),
+            &CreateTodo::new("Rent".to_string()).with_due("2024-06-01".parse().ok())
+        );
+
+        let mapping = ColumnMapping {
+            completed: Some("Finished".to_string()),
+            ..ColumnMapping::default()
+        };
+        assert!(parse(b"text\nBuy milk\n", &mapping, &[]).is_err());
+        assert!(parse(b"title\nBuy milk\n", &ColumnMapping::default(), &[]).is_err());
+    }
+
+    #[test]
+    fn reports_what_is_wrong_with_each_row() {
+        let labels = [label(1, "work"), label(2, "home")];
+        let body = "text,completed,due,labels\n\
+                    Ship it,x,,work; 2\n\
+                    ,maybe,June,play\n\
+                    \"Call\nmom\",no,,home;home\n";
+        let rows = parse(body.as_bytes(), &ColumnMapping::default(), &labels).unwrap();
+        assert_eq!(
+            rows.iter().map(|row| row.line).collect::<Vec<_>>(),
+            vec![2, 3, 4]
+        );
+
+        let payload = rows[0].parsed.as_ref().unwrap();
+        assert_eq!(
+            payload,
+            &CreateTodo::new("Ship it".to_string())
+                .with_labels(vec![1, 2])
+                .with_completed(true)
+        );
+
+        let errors = rows[1].parsed.as_ref().unwrap_err();
+        assert_eq!(
+            errors.keys().collect::<Vec<_>>(),
+            vec!["completed", "due", "labels", "text"]
+        );
+        assert_eq!(errors["labels"], vec!["unknown label: [play]"]);
+
+        let payload = rows[2].parsed.as_ref().unwrap();
+        assert_eq!(
+            payload,
+            &CreateTodo::new("Call\nmom".to_string()).with_labels(vec![2])
+        );
+    }
+
+    #[test]
+    fn exports_what_it_imports() {
+        let labels = vec![label(1, "work"), label(2, "next week")];
+        let todo: Todo = serde_json::from_value(serde_json::json!({
+            "id": 7, "text": "Ship it, finally", "completed": false, "due": "2024-06-01"
+        }))
+        .unwrap();
+        let todos = [TodoWithLabels {
+            todo,
+            labels: labels.clone(),
+        }];
+        let mut body = format!("{}\n", HEADER.join(",")).into_bytes();
+        body.extend(super::rows(&todos).unwrap());
+        assert_eq!(
+            String::from_utf8(body.clone()).unwrap(),
+            "id,uuid,text,completed,due,labels\n7,,\"Ship it, finally\",false,2024-06-01,work;next week\n"
+        );
+
+        let rows = parse(&body, &ColumnMapping::default(), &labels).unwrap();
+        assert_eq!(
+            rows[0].parsed.as_ref().unwrap(),
+            &CreateTodo::new("Ship it, finally".to_string())
+                .with_labels(vec![1, 2])
+                .with_due("2024-06-01".parse().ok())
+        );
+    }
+}
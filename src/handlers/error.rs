@@ -0,0 +1,359 @@
+use std::collections::BTreeMap;
+
+use axum::{
+    body::{self, BoxBody},
+    http::{header, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use validator::ValidationErrors;
+
+use super::i18n::{tr, trf};
+use crate::{layers::request_id::RequestId, repositories::RepositoryError};
+
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// Messages per offending input field, e.g. `{"text": ["can not be empty"]}`.
+pub type FieldErrors = BTreeMap<String, Vec<String>>;
+
+#[derive(Debug, Error)]
+pub enum ApiError {
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("{detail}")]
+    Validation { detail: String, errors: FieldErrors },
+    #[error("{0}")]
+    Unauthorized(String),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    Conflict(String),
+    #[error("{0}")]
+    TooLarge(String),
+    #[error("{0}")]
+    Unavailable(String),
+    #[error("{0}")]
+    Timeout(String),
+    #[error("Unexpected error occurred")]
+    Internal,
+}
+
+impl ApiError {
+    fn status(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Validation { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            ApiError::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            ApiError::BadRequest(_) => "bad-request",
+            ApiError::Validation { .. } => "validation",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::NotFound(_) => "not-found",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::TooLarge(_) => "payload-too-large",
+            ApiError::Unavailable(_) => "unavailable",
+            ApiError::Timeout(_) => "timeout",
+            ApiError::Internal => "internal",
+        }
+    }
+}
+
+/// SQLSTATE Postgres reports when `statement_timeout` cancels a query.
+const QUERY_CANCELED: &str = "57014";
+
+/// Unique violations as Postgres and SQLite (extended code) report them;
+/// the unique columns clients write are the uuids of todos and labels.
+const UNIQUE_VIOLATIONS: [&str; 2] = ["23505", "2067"];
+
+/// MySQL's number for a duplicate key. Its SQLSTATE, 23000, also covers
+/// foreign key and not null violations.
+#[cfg(feature = "mysql")]
+const MYSQL_DUP_ENTRY: u16 = 1062;
+
+fn is_unique_violation(db: &dyn sqlx::error::DatabaseError) -> bool {
+    #[cfg(feature = "mysql")]
+    if let Some(mysql) = db.try_downcast_ref::<sqlx::mysql::MySqlDatabaseError>() {
+        return mysql.number() == MYSQL_DUP_ENTRY;
+    }
+    db.code()
+        .is_some_and(|code| UNIQUE_VIOLATIONS.contains(&code.as_ref()))
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        match e.chain().find_map(|e| e.downcast_ref::<sqlx::Error>()) {
+            Some(sqlx::Error::PoolTimedOut) => {
+                tracing::warn!("{:#}", e);
+                return ApiError::Unavailable(tr("The database is busy, try again later"));
+            }
+            Some(sqlx::Error::Database(db)) if db.code().as_deref() == Some(QUERY_CANCELED) => {
+                tracing::warn!("{:#}", e);
+                return ApiError::Timeout(tr("The database took too long to answer"));
+            }
+            Some(sqlx::Error::Database(db)) if is_unique_violation(db.as_ref()) => {
+                tracing::debug!("{:#}", e);
+                return ApiError::Conflict(tr("The id is already taken"));
+            }
+            _ => {}
+        }
+        match e.downcast_ref::<RepositoryError>() {
+            Some(RepositoryError::NotFound(id)) => {
+                tracing::debug!("{:#}", e);
+                ApiError::NotFound(trf("NotFound, id is {}", &[id]))
+            }
+            Some(RepositoryError::UnknownUuid(uuid)) => {
+                tracing::debug!("{:#}", e);
+                ApiError::NotFound(trf("NotFound, id is {}", &[uuid]))
+            }
+            Some(RepositoryError::Duplicate(id)) => {
+                tracing::debug!("{:#}", e);
+                ApiError::Conflict(trf("Duplicate data, id is {}", &[id]))
+            }
+            Some(RepositoryError::StaleVersion(id)) => {
+                tracing::debug!("{:#}", e);
+                ApiError::Conflict(trf("Stale version, id is {}", &[id]))
+            }
+            Some(RepositoryError::Unexpected(_)) | None => {
+                tracing::error!("{:#}", e);
+                ApiError::Internal
+            }
+        }
+    }
+}
+
+/// The translated messages of `errors`, by field.
+pub fn field_errors(errors: &ValidationErrors) -> FieldErrors {
+    errors
+        .field_errors()
+        .into_iter()
+        .map(|(field, errors)| {
+            let messages = errors
+                .iter()
+                .map(|error| tr(error.message.as_deref().unwrap_or(&error.code)))
+                .collect();
+            (field.to_string(), messages)
+        })
+        .collect()
+}
+
+impl From<ValidationErrors> for ApiError {
+    fn from(rejection: ValidationErrors) -> Self {
+        let errors = field_errors(&rejection);
+        let fields: Vec<String> = errors
+            .iter()
+            .flat_map(|(field, messages)| {
+                messages
+                    .iter()
+                    .map(move |message| format!("{}: {}", field, message))
+            })
+            .collect();
+        let detail = trf("Validation error: [{}]", &[&fields.join(",")]);
+        ApiError::Validation { detail, errors }
+    }
+}
+
+/// RFC 7807 problem details body.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    /// For users to quote when reporting the problem.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub errors: Option<FieldErrors>,
+}
+
+impl Problem {
+    pub fn new(status: StatusCode, kind: &str, detail: String) -> Self {
+        Self {
+            problem_type: format!("/problems/{}", kind),
+            title: tr(status.canonical_reason().unwrap_or_default()),
+            status: status.as_u16(),
+            detail,
+            instance: None,
+            request_id: None,
+            errors: None,
+        }
+    }
+}
+
+impl IntoResponse for Problem {
+    fn into_response(self) -> Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let body = serde_json::to_string(&self).unwrap_or_default();
+        let mut res = (status, body).into_response();
+        res.headers_mut()
+            .insert(header::CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON));
+        res
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let mut problem = Problem::new(self.status(), self.kind(), tr(&self.to_string()));
+        let unavailable = matches!(self, ApiError::Unavailable(_));
+        if let ApiError::Validation { errors, .. } = self {
+            problem.errors = Some(errors);
+        }
+        let mut res = problem.into_response();
+        if unavailable {
+            res.headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from_static("1"));
+        }
+        res
+    }
+}
+
+/// Fills in the `instance` member of problem responses with the request path,
+/// and `request_id` with its [`RequestId`], so handlers don't have to thread
+/// either through every error.
+pub async fn problem_instance<B>(req: Request<B>, next: Next<B>) -> Response {
+    let path = req.uri().path().to_string();
+    let request_id = req.extensions().get::<RequestId>().map(|id| id.0.clone());
+    let res = next.run(req).await;
+
+    let is_problem = res
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|value| value == PROBLEM_JSON);
+    if !is_problem {
+        return res;
+    }
+
+    let (mut parts, b) = res.into_parts();
+    parts.headers.remove(header::CONTENT_LENGTH);
+    let bytes = match hyper::body::to_bytes(b).await {
+        Ok(bytes) => bytes,
+        Err(_) => return Response::from_parts(parts, body::boxed(body::Empty::new())),
+    };
+    let body: BoxBody = match serde_json::from_slice::<Problem>(&bytes) {
+        Ok(mut problem) => {
+            problem.instance.get_or_insert(path);
+            problem.request_id = problem.request_id.or(request_id);
+            body::boxed(body::Full::from(
+                serde_json::to_vec(&problem).unwrap_or_default(),
+            ))
+        }
+        Err(_) => body::boxed(body::Full::from(bytes)),
+    };
+    Response::from_parts(parts, body)
+}
+
+/// Turns the router's bare `405 Method Not Allowed` into a problem response,
+/// keeping the `Allow` header it computed from the route's methods.
+pub async fn method_not_allowed<B>(req: Request<B>, next: Next<B>) -> Response {
+    let method = req.method().clone();
+    let res = next.run(req).await;
+    if res.status() != StatusCode::METHOD_NOT_ALLOWED
+        || res
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .is_some_and(|value| value == PROBLEM_JSON)
+    {
+        return res;
+    }
+
+    let allow = res.headers().get(header::ALLOW).cloned();
+    let detail = trf(
+        "Method [{}] is not allowed, use one of [{}]",
+        &[
+            &method,
+            &allow
+                .as_ref()
+                .and_then(|allow| allow.to_str().ok())
+                .unwrap_or_default(),
+        ],
+    );
+    let mut problem =
+        Problem::new(StatusCode::METHOD_NOT_ALLOWED, "method-not-allowed", detail).into_response();
+    if let Some(allow) = allow {
+        problem.headers_mut().insert(header::ALLOW, allow);
+    }
+    problem
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, time::Duration};
+
+    use super::*;
+    use crate::repositories::{self, Migrations, PoolSettings};
+
+    #[test]
+    fn pool_timeouts_are_unavailable() {
+        let e = ApiError::from(anyhow::Error::from(sqlx::Error::PoolTimedOut));
+        let res = e.into_response();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(res.headers()[header::RETRY_AFTER], "1");
+    }
+
+    #[tokio::test]
+    async fn only_unique_violations_are_conflicts() {
+        let pool = repositories::connect_sqlite(
+            "sqlite::memory:",
+            &PoolSettings::default(),
+            Migrations::Apply,
+        )
+        .await
+        .expect("failed open sqlite");
+        let insert = |uuid: &'static str| {
+            sqlx::query("insert into labels (name, uuid) values (?1, ?2)")
+                .bind(uuid)
+                .bind(uuid)
+        };
+        insert("a").execute(&pool).await.unwrap();
+        insert("b").execute(&pool).await.unwrap();
+        let e = sqlx::query("update labels set uuid='a' where name='b'")
+            .execute(&pool)
+            .await
+            .unwrap_err();
+        let res = ApiError::from(anyhow::Error::from(e)).into_response();
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+
+        let e = sqlx::query("insert into labels (name) values (null)")
+            .execute(&pool)
+            .await
+            .unwrap_err();
+        let res = ApiError::from(anyhow::Error::from(e)).into_response();
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn statement_timeouts_are_gateway_timeouts() {
+        dotenv::dotenv().ok();
+        let database_url = &env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let settings = PoolSettings {
+            max_connections: 1,
+            statement_timeout: Some(Duration::from_millis(20)),
+            ..Default::default()
+        };
+        let pool = repositories::connect_postgres(database_url, &settings, Migrations::Apply)
+            .await
+            .unwrap_or_else(|_| panic!("fail connect database, url is [{}]", database_url));
+
+        let e = sqlx::query("select pg_sleep(1)")
+            .execute(&pool)
+            .await
+            .unwrap_err();
+        let res = ApiError::from(anyhow::Error::from(e)).into_response();
+        assert_eq!(res.status(), StatusCode::GATEWAY_TIMEOUT);
+    }
+}
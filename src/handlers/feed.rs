@@ -0,0 +1,220 @@
+use std::{env, sync::Arc, time::SystemTime};
+
+use axum::{
+    extract::{Extension, Query},
+    http::{header, HeaderValue},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+
+use crate::repositories::{
+    date::civil_from_days,
+    todo::{Todo, TodoRepository},
+};
+
+use super::{
+    caldav::{vevent, vtodo, CALENDAR},
+    error::ApiError,
+    escape,
+    i18n::tr,
+    links::LinkBuilder,
+};
+
+pub const ATOM: &str = "application/atom+xml";
+
+/// Number of entries in a feed; readers only need what changed recently.
+const FEED_ENTRIES: i64 = 50;
+
+/// Shared secret feed readers pass as `?token=`, from `FEED_TOKEN`. Without
+/// it the Atom feed is as public as the rest of the API and the calendar
+/// feed is off.
+#[derive(Debug, Clone)]
+pub struct FeedToken(pub String);
+
+impl FeedToken {
+    pub fn from_env() -> Option<Self> {
+        env::var("FEED_TOKEN")
+            .ok()
+            .filter(|token| !token.is_empty())
+            .map(FeedToken)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FeedQuery {
+    token: Option<String>,
+}
+
+impl FeedQuery {
+    /// Whether the query carries the [`FeedToken`], when there is one.
+    fn authorize(&self, feed_token: Option<Extension<FeedToken>>) -> Result<(), ApiError> {
+        let Some(Extension(FeedToken(expected))) = feed_token else {
+            return Ok(());
+        };
+        let given = self.token.as_deref().unwrap_or_default();
+        ring::constant_time::verify_slices_are_equal(given.as_bytes(), expected.as_bytes())
+            .map_err(|_| ApiError::Unauthorized(tr("Invalid or missing feed token")))
+    }
+}
+
+pub async fn todos_feed<T: TodoRepository>(
+    Extension(repository): Extension<Arc<T>>,
+    feed_token: Option<Extension<FeedToken>>,
+    Query(query): Query<FeedQuery>,
+    links: LinkBuilder,
+) -> Result<Response, ApiError> {
+    query.authorize(feed_token)?;
+
+    let todos = repository.recently_modified(FEED_ENTRIES).await?;
+    let mut res = atom(&links, &todos).into_response();
+    res.headers_mut()
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static(ATOM));
+
+    Ok(res)
+}
+
+/// `GET /export/calendar.ics?token=`: every todo with a due date as a
+/// VTODO and an all-day VEVENT of one calendar, for calendar apps to
+/// subscribe to; Google Calendar only shows the events. Unlike the Atom
+/// feed it is never public, so it is not served without a `FEED_TOKEN`.
+pub async fn calendar_feed<T: TodoRepository>(
+    Extension(repository): Extension<Arc<T>>,
+    feed_token: Option<Extension<FeedToken>>,
+    Query(query): Query<FeedQuery>,
+) -> Result<Response, ApiError> {
+    if feed_token.is_none() {
+        return Err(ApiError::NotFound(tr(
+            "The calendar feed needs a FEED_TOKEN",
+        )));
+    }
+    query.authorize(feed_token)?;
+
+    let todos = repository.recently_modified(i64::MAX).await?;
+    let mut res = calendar(&todos).into_response();
+    res.headers_mut()
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static(CALENDAR));
+
+    Ok(res)
+}
+
+fn calendar(todos: &[(Todo, SystemTime)]) -> String {
+    let mut calendar = concat!(
+        "BEGIN:VCALENDAR\r\n",
+        "VERSION:2.0\r\n",
+        "PRODID:-//my-todo//EN\r\n",
+        "X-WR-CALNAME:Todos\r\n",
+        "REFRESH-INTERVAL;VALUE=DURATION:PT1H\r\n",
+        "X-PUBLISHED-TTL:PT1H\r\n",
+    )
+    .to_string();
+    for (todo, at) in todos {
+        if let Some(due) = todo.due() {
+            calendar.push_str(&vtodo(todo, *at));
+            calendar.push_str(&vevent(todo, due, *at));
+        }
+    }
+    calendar.push_str("END:VCALENDAR\r\n");
+    calendar
+}
+
+fn atom(links: &LinkBuilder, todos: &[(Todo, SystemTime)]) -> String {
+    let updated = todos.first().map_or(SystemTime::UNIX_EPOCH, |(_, at)| *at);
+    let mut feed = format!(
+        concat!(
+            r#"<?xml version="1.0" encoding="utf-8"?>"#,
+            r#"<feed xmlns="http://www.w3.org/2005/Atom">"#,
+            "<id>tag:my-todo,2023:todos</id>",
+            "<title>Todos</title>",
+            "<updated>{}</updated>",
+            r#"<link rel="alternate" href="{}"/>"#,
+        ),
+        rfc3339(updated),
+        escape(&links.todos().href),
+    );
+    for (todo, at) in todos {
+        let status = if todo.completed() { "done" } else { "open" };
+        feed.push_str(&format!(
+            concat!(
+                "<entry>",
+                "<id>tag:my-todo,2023:todos/{}</id>",
+                "<title>{}</title>",
+                "<updated>{}</updated>",
+                r#"<link rel="alternate" href="{}"/>"#,
+                "<category term=\"{}\"/>",
+                "<author><name>my-todo</name></author>",
+                "</entry>",
+            ),
+            todo.id(),
+            escape(todo.text()),
+            rfc3339(*at),
+            escape(&links.todo(todo).href),
+            status,
+        ));
+    }
+    feed.push_str("</feed>");
+    feed
+}
+
+pub fn rfc3339(at: SystemTime) -> String {
+    let secs = at
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+    let (days, rem) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn renders_atom_entries() {
+        let at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_717_200_000);
+        let todos = vec![(Todo::new(1, "milk & <eggs>".to_string()), at)];
+        let feed = atom(&LinkBuilder::new(""), &todos);
+
+        assert!(feed.contains("<updated>2024-06-01T00:00:00Z</updated>"));
+        assert!(feed.contains("<title>milk &amp; &lt;eggs&gt;</title>"));
+        assert!(feed.contains(r#"<link rel="alternate" href="/todos/1"/>"#));
+        assert!(feed.contains(r#"<category term="open"/>"#));
+    }
+
+    #[test]
+    fn renders_one_calendar_of_dated_todos() {
+        let dated = |id, text: &str, due: &str| {
+            serde_json::from_value::<Todo>(serde_json::json!({
+                "id": id, "text": text, "completed": false, "due": due
+            }))
+            .unwrap()
+        };
+        let todos = vec![
+            (dated(1, "milk", "2024-06-01"), SystemTime::UNIX_EPOCH),
+            (Todo::new(2, "whenever".to_string()), SystemTime::UNIX_EPOCH),
+            (dated(3, "eggs", "2024-12-31"), SystemTime::UNIX_EPOCH),
+        ];
+        let calendar = calendar(&todos);
+
+        assert!(calendar.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(calendar.ends_with("END:VEVENT\r\nEND:VCALENDAR\r\n"));
+        assert_eq!(calendar.matches("BEGIN:VTODO\r\n").count(), 2);
+        assert_eq!(calendar.matches("BEGIN:VEVENT\r\n").count(), 2);
+        assert!(calendar.contains("UID:my-todo-3\r\nDTSTAMP:19700101T000000Z\r\n"));
+        assert!(calendar.contains("DUE;VALUE=DATE:20240601\r\n"));
+        assert!(calendar.contains(concat!(
+            "DTSTART;VALUE=DATE:20241231\r\n",
+            "DTEND;VALUE=DATE:20250101\r\n",
+        )));
+        assert!(!calendar.contains("whenever"));
+    }
+}
@@ -0,0 +1,81 @@
+use std::{collections::BTreeSet, convert::Infallible};
+
+use axum::{
+    async_trait,
+    extract::{FromRequest, Query, RequestParts},
+};
+use serde::{Deserialize, Serialize, Serializer};
+
+#[derive(Debug, Deserialize)]
+struct FieldsQuery {
+    fields: Option<String>,
+}
+
+/// `?fields=id,text` selection. Extracting it never fails; with no (or an
+/// empty) `fields` parameter every member is kept.
+#[derive(Debug, Clone, Default)]
+pub struct Fields(Option<BTreeSet<String>>);
+
+impl Fields {
+    pub fn parse(fields: &str) -> Self {
+        let fields: BTreeSet<String> = fields
+            .split(',')
+            .map(str::trim)
+            .filter(|field| !field.is_empty())
+            .map(str::to_string)
+            .collect();
+
+        Fields((!fields.is_empty()).then_some(fields))
+    }
+
+    pub fn apply<T>(self, value: T) -> Sparse<T> {
+        Sparse {
+            value,
+            fields: self,
+        }
+    }
+}
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for Fields {
+    type Rejection = Infallible;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let fields = Query::<FieldsQuery>::from_request(req)
+            .await
+            .ok()
+            .and_then(|Query(query)| query.fields)
+            .map(|fields| Fields::parse(&fields))
+            .unwrap_or_default();
+
+        Ok(fields)
+    }
+}
+
+/// Serializes `T` keeping only the selected top-level members. Sequences are
+/// trimmed element by element so collections work the same as single items.
+#[derive(Debug)]
+pub struct Sparse<T> {
+    value: T,
+    fields: Fields,
+}
+
+impl<T: Serialize> Serialize for Sparse<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let Fields(Some(fields)) = &self.fields else {
+            return self.value.serialize(serializer);
+        };
+
+        let mut value = serde_json::to_value(&self.value).map_err(serde::ser::Error::custom)?;
+        let retain = |value: &mut serde_json::Value| {
+            if let Some(object) = value.as_object_mut() {
+                object.retain(|key, _| fields.contains(key));
+            }
+        };
+        match &mut value {
+            serde_json::Value::Array(items) => items.iter_mut().for_each(retain),
+            value => retain(value),
+        }
+        value.serialize(serializer)
+    }
+}
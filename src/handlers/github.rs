@@ -0,0 +1,135 @@
+//! `POST /integrations/github`: the webhook of the repository
+//! [`GitHubSync`] follows, subscribed to `issues` events, so issues opened,
+//! retitled, assigned, closed or reopened there show on their todos.
+
+use std::sync::Arc;
+
+use axum::{
+    async_trait,
+    body::Bytes,
+    extract::{Extension, FromRequest, RequestParts},
+    http::StatusCode,
+    BoxError,
+};
+use ring::hmac;
+use serde::Deserialize;
+
+use crate::{
+    github::{GitHubSync, Issue},
+    repositories::{link::LinkRepository, todo::TodoRepository},
+};
+
+use super::{
+    error::ApiError,
+    i18n::{tr, trf},
+    unhex,
+};
+
+/// A webhook delivery signed with the sync's webhook secret.
+#[derive(Debug)]
+pub struct GitHubDelivery {
+    /// `X-GitHub-Event`, e.g. `issues` or `ping`.
+    event: String,
+    body: Bytes,
+}
+
+/// Whether `signature`, as in `X-Hub-Signature-256`, signs `body`.
+fn verify(secret: &str, signature: &str, body: &[u8]) -> bool {
+    let Some(signature) = signature.strip_prefix("sha256=").and_then(unhex) else {
+        return false;
+    };
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    hmac::verify(&key, body, &signature).is_ok()
+}
+
+#[async_trait]
+impl<B> FromRequest<B> for GitHubDelivery
+where
+    B: http_body::Body + Send,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let secret = req
+            .extensions()
+            .and_then(|extensions| extensions.get::<GitHubSync>())
+            .and_then(|sync| sync.webhook_secret())
+            .map(str::to_string)
+            .ok_or_else(|| {
+                ApiError::NotFound(tr("GitHub webhooks need a GITHUB_WEBHOOK_SECRET"))
+            })?;
+        let (event, signature) = {
+            let header = |name| {
+                req.headers()
+                    .and_then(|headers| headers.get(name))
+                    .and_then(|value| value.to_str().ok())
+                    .unwrap_or_default()
+                    .to_string()
+            };
+            (header("x-github-event"), header("x-hub-signature-256"))
+        };
+        let body = Bytes::from_request(req)
+            .await
+            .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+        if !verify(&secret, &signature, &body) {
+            return Err(ApiError::Unauthorized(tr("Invalid webhook signature")));
+        }
+        Ok(Self { event, body })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct IssuesEvent {
+    issue: Issue,
+    repository: Repository,
+}
+
+#[derive(Debug, Deserialize)]
+struct Repository {
+    full_name: String,
+}
+
+/// `POST /integrations/github`. Events other than `issues`, and issues of
+/// other repositories, are acknowledged with `204` and ignored. Without a
+/// SQL database to keep the links in, there is no webhook.
+pub async fn github_webhook<T: TodoRepository>(
+    delivery: GitHubDelivery,
+    Extension(sync): Extension<GitHubSync>,
+    Extension(todo_repository): Extension<Arc<T>>,
+    links: Option<Extension<Arc<dyn LinkRepository>>>,
+) -> Result<StatusCode, ApiError> {
+    let Some(Extension(links)) = links else {
+        return Err(ApiError::NotFound(tr(
+            "Syncing GitHub issues needs a SQL database",
+        )));
+    };
+    if delivery.event != "issues" {
+        return Ok(StatusCode::NO_CONTENT);
+    }
+    let event: IssuesEvent = serde_json::from_slice(&delivery.body)
+        .map_err(|e| ApiError::BadRequest(trf("Json parse error: [{}]", &[&e])))?;
+    if !event.repository.full_name.eq_ignore_ascii_case(sync.repo()) {
+        return Ok(StatusCode::NO_CONTENT);
+    }
+    sync.apply(&*todo_repository, &*links, &event.issue).await?;
+
+    Ok(StatusCode::OK)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn verifies_the_signature() {
+        // the example of GitHub's docs on validating deliveries
+        const SECRET: &str = "It's a Secret to Everybody";
+        let signature = "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17";
+        assert!(verify(SECRET, signature, b"Hello, World!"));
+        assert!(!verify(SECRET, signature, b"Hello, World"));
+        assert!(!verify("another secret", signature, b"Hello, World!"));
+        assert!(!verify(SECRET, "sha1=757107ea", b"Hello, World!"));
+    }
+}
@@ -0,0 +1,231 @@
+use std::fmt::Display;
+
+use axum::{
+    http::{header, HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+
+tokio::task_local! {
+    static LANG: Lang;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lang {
+    En,
+    Ja,
+}
+
+impl Lang {
+    /// Language negotiated for the request being handled, English outside of
+    /// the `localize` middleware.
+    pub fn current() -> Lang {
+        LANG.try_with(|lang| *lang).unwrap_or(Lang::En)
+    }
+
+    fn tag(&self) -> &'static str {
+        match self {
+            Lang::En => "en",
+            Lang::Ja => "ja",
+        }
+    }
+
+    /// Picks the supported language with the highest `q` from an
+    /// `Accept-Language` value, e.g. `ja-JP,ja;q=0.9,en;q=0.8`.
+    pub fn from_accept_language(value: &str) -> Lang {
+        value
+            .split(',')
+            .filter_map(|range| {
+                let mut parts = range.trim().split(';');
+                let tag = parts.next()?.trim().to_ascii_lowercase();
+                let q = parts
+                    .find_map(|param| param.trim().strip_prefix("q="))
+                    .map_or(Some(1.0), |q| q.parse::<f32>().ok())?;
+                let lang = match tag.split('-').next()? {
+                    "ja" => Lang::Ja,
+                    "en" => Lang::En,
+                    _ => return None,
+                };
+                (q > 0.0).then_some((lang, q))
+            })
+            .fold(None, |best: Option<(Lang, f32)>, (lang, q)| match best {
+                Some((_, best_q)) if best_q >= q => best,
+                _ => Some((lang, q)),
+            })
+            .map_or(Lang::En, |(lang, _)| lang)
+    }
+}
+
+/// Japanese catalogue, keyed by the English message.
+fn ja(msgid: &str) -> Option<&'static str> {
+    let msgstr = match msgid {
+        // problem titles
+        "Bad Request" => "不正なリクエスト",
+        "Unauthorized" => "認証が必要です",
+        "Not Found" => "見つかりません",
+        "Method Not Allowed" => "許可されていないメソッドです",
+        "Conflict" => "競合しています",
+        "Payload Too Large" => "リクエストが大きすぎます",
+        "Unprocessable Entity" => "処理できない内容です",
+        "Too Many Requests" => "リクエストが多すぎます",
+        "Internal Server Error" => "サーバー内部エラー",
+        "Service Unavailable" => "サービスを利用できません",
+        "Gateway Timeout" => "タイムアウトしました",
+        // details
+        "Json parse error: [{}]" => "JSONの解析に失敗しました: [{}]",
+        "Validation error: [{}]" => "入力値が不正です: [{}]",
+        "Unknown fields: [{}]" => "不明なフィールドです: [{}]",
+        "unknown field" => "不明なフィールドです",
+        "can not be empty" => "空にできません",
+        "can not be over 100" => "100文字を超えることはできません",
+        "NotFound, id is {}" => "見つかりません。idは{}です",
+        "Duplicate data, id is {}" => "重複したデータです。idは{}です",
+        "Stale version, id is {}" => "古いバージョンです。idは{}です",
+        "The id is already taken" => "そのidは既に使われています",
+        "Unexpected error occurred" => "予期しないエラーが発生しました",
+        "The database is busy, try again later" => {
+            "データベースが混み合っています。しばらくしてから再試行してください"
+        }
+        "The database took too long to answer" => "データベースの応答に時間がかかりすぎました",
+        "The request took longer than {} seconds" => "リクエストが{}秒以内に終わりませんでした",
+        "Unknown include relation: [{}]" => "不明な関連です: [{}]",
+        "Unknown search term: [{}]" => "不明な検索条件です: [{}]",
+        "Invalid id: [{}]" => "idが不正です: [{}]",
+        "Invalid date: [{}]" => "日付が不正です: [{}]",
+        "Search needs at least one word" => "検索には1語以上が必要です",
+        "Too many ids, at most {} are allowed" => "idが多すぎます。最大{}件までです",
+        "Method [{}] is not allowed, use one of [{}]" => {
+            "メソッド[{}]は使えません。[{}]のいずれかを使ってください"
+        }
+        "Invalid iCalendar: [{}]" => "iCalendarの形式が不正です: [{}]",
+        "Invalid or missing feed token" => "フィードのトークンが不正か指定されていません",
+        "The calendar feed needs a FEED_TOKEN" => "カレンダーのフィードにはFEED_TOKENが必要です",
+        "Seeding is only available in dev mode" => "シードは開発モードでのみ使えます",
+        "Admin endpoints need an ADMIN_TOKEN" => "管理用エンドポイントにはADMIN_TOKENが必要です",
+        "Invalid or missing admin token" => "管理用トークンが不正か指定されていません",
+        "unsupported backup format" => "対応していないバックアップ形式です",
+        "duplicate ids" => "idが重複しています",
+        "duplicate names" => "名前が重複しています",
+        "duplicate uuids" => "uuidが重複しています",
+        "unknown todo or label" => "不明なtodoかラベルを参照しています",
+        "todos without a uuid can only be wiped back" => "uuidのないtodoはwipeでのみ復元できます",
+        "unsupported export format" => "対応していないエクスポート形式です",
+        "unknown label" => "不明なラベルを参照しています",
+        "a text is empty or over 100" => "空か100文字を超えるテキストがあります",
+        "Invalid Todoist token: [{}]" => "Todoistのトークンが不正です: [{}]",
+        "Todoist refused the token: [{}]" => "Todoistがトークンを受け付けませんでした: [{}]",
+        "Todoist could not be reached, try again later" => {
+            "Todoistに接続できませんでした。しばらくしてから再試行してください"
+        }
+        "Live updates are not enabled" => "ライブ更新は有効になっていません",
+        "Caching is not enabled" => "キャッシュは有効になっていません",
+        "Feature [{}] is disabled" => "機能[{}]は無効になっています",
+        "Invalid flags: [{}]" => "フラグが不正です: [{}]",
+        "Down for maintenance, try again later" => {
+            "メンテナンス中です。しばらくしてから再試行してください"
+        }
+        "Invalid CSV: [{}]" => "CSVの形式が不正です: [{}]",
+        "Unknown column: [{}]" => "不明な列です: [{}]",
+        "The delimiter must be a single character" => "区切り文字は1文字にしてください",
+        "must be true or false" => "trueかfalseにしてください",
+        "must be a day as YYYY-MM-DD" => "YYYY-MM-DD形式の日付にしてください",
+        "unknown label: [{}]" => "不明なラベルです: [{}]",
+        "Todos" => "Todo一覧",
+        "Without a label" => "ラベルなし",
+        "due {}" => "期限 {}",
+        "Email ingestion needs an INBOUND_MAIL_ADDRESS and a MAILGUN_SIGNING_KEY" => {
+            "メールの取り込みにはINBOUND_MAIL_ADDRESSとMAILGUN_SIGNING_KEYが必要です"
+        }
+        "Invalid form: [{}]" => "フォームが不正です: [{}]",
+        "Invalid webhook signature" => "Webhookの署名が不正です",
+        "Slack commands need a SLACK_SIGNING_SECRET" => {
+            "SlackコマンドにはSLACK_SIGNING_SECRETが必要です"
+        }
+        "The Telegram bot needs a TELEGRAM_WEBHOOK_SECRET" => {
+            "TelegramボットにはTELEGRAM_WEBHOOK_SECRETが必要です"
+        }
+        "GitHub webhooks need a GITHUB_WEBHOOK_SECRET" => {
+            "GitHubのWebhookにはGITHUB_WEBHOOK_SECRETが必要です"
+        }
+        "Syncing GitHub issues needs a SQL database" => {
+            "GitHubのissueの同期にはSQLデータベースが必要です"
+        }
+        "Web Push needs a VAPID_PRIVATE_KEY" => "Web PushにはVAPID_PRIVATE_KEYが必要です",
+        "A push subscription needs an https endpoint, a p256dh and an auth key" => {
+            "プッシュ購読にはhttpsのendpointとp256dhとauthの鍵が必要です"
+        }
+        "There are {} push subscriptions already" => "プッシュ購読はすでに{}件あります",
+        "There is no such push subscription" => "そのプッシュ購読はありません",
+        "The push endpoint {} is not at a known push service" => {
+            "プッシュのエンドポイント{}は既知のプッシュサービスではありません"
+        }
+        "Attachments need an [attachments] backend" => {
+            "添付ファイルには[attachments]のbackendが必要です"
+        }
+        "There is no attachment {}" => "添付ファイル{}はありません",
+        "The file is larger than {} bytes" => "ファイルが{}バイトを超えています",
+        _ => return None,
+    };
+    Some(msgstr)
+}
+
+/// Translates `msgid` into the current language, falling back to itself.
+pub fn tr(msgid: &str) -> String {
+    match Lang::current() {
+        Lang::Ja => ja(msgid).unwrap_or(msgid).to_string(),
+        Lang::En => msgid.to_string(),
+    }
+}
+
+/// Translates a `{}` template and fills the placeholders in order.
+pub fn trf(msgid: &str, args: &[&dyn Display]) -> String {
+    let mut args = args.iter();
+    let mut message = String::new();
+    for (i, piece) in tr(msgid).split("{}").enumerate() {
+        if i > 0 {
+            if let Some(arg) = args.next() {
+                message.push_str(&arg.to_string());
+            }
+        }
+        message.push_str(piece);
+    }
+    message
+}
+
+pub async fn localize<B>(req: Request<B>, next: Next<B>) -> Response {
+    let lang = req
+        .headers()
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok())
+        .map_or(Lang::En, Lang::from_accept_language);
+
+    let mut res = LANG.scope(lang, next.run(req)).await;
+    res.headers_mut().insert(
+        header::CONTENT_LANGUAGE,
+        HeaderValue::from_static(lang.tag()),
+    );
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn negotiates_by_quality() {
+        assert_eq!(Lang::from_accept_language("ja-JP,ja;q=0.9"), Lang::Ja);
+        assert_eq!(Lang::from_accept_language("en;q=0.5, ja;q=0.8"), Lang::Ja);
+        assert_eq!(Lang::from_accept_language("fr, en;q=0.1"), Lang::En);
+        assert_eq!(Lang::from_accept_language("ja;q=0"), Lang::En);
+        assert_eq!(Lang::from_accept_language("*"), Lang::En);
+    }
+
+    #[tokio::test]
+    async fn translates_templates_in_scope() {
+        assert_eq!(trf("NotFound, id is {}", &[&1]), "NotFound, id is 1");
+        let message = LANG
+            .scope(Lang::Ja, async { trf("NotFound, id is {}", &[&1]) })
+            .await;
+        assert_eq!(message, "見つかりません。idは1です");
+    }
+}
@@ -0,0 +1,49 @@
+use axum::{
+    async_trait,
+    extract::{FromRequest, Query, RequestParts},
+};
+use serde::Deserialize;
+
+use super::{error::ApiError, i18n::trf};
+
+#[derive(Debug, Deserialize)]
+struct IncludeQuery {
+    include: Option<String>,
+}
+
+/// `?include=labels` relation expansion. Unknown relations are rejected so a
+/// typo doesn't silently return the lean representation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Include {
+    pub labels: bool,
+}
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for Include {
+    type Rejection = ApiError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let mut include = Include::default();
+        let Ok(Query(IncludeQuery {
+            include: Some(relations),
+        })) = Query::<IncludeQuery>::from_request(req).await
+        else {
+            return Ok(include);
+        };
+
+        for relation in relations.split(',').map(str::trim) {
+            match relation {
+                "labels" => include.labels = true,
+                "" => {}
+                _ => {
+                    return Err(ApiError::BadRequest(trf(
+                        "Unknown include relation: [{}]",
+                        &[&relation],
+                    )))
+                }
+            }
+        }
+
+        Ok(include)
+    }
+}
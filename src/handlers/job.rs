@@ -0,0 +1,39 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Extension, Path},
+    http::{header, StatusCode},
+    response::{Headers, IntoResponse},
+    Json,
+};
+
+use crate::{
+    jobs,
+    repositories::{
+        job::{JobKind, JobRepository},
+        todo::TodoRepository,
+    },
+};
+
+use super::{error::ApiError, links::LinkBuilder};
+
+pub async fn find_job<J: JobRepository>(
+    Path(id): Path<i32>,
+    Extension(repository): Extension<Arc<J>>,
+) -> Result<impl IntoResponse, ApiError> {
+    let job = repository.find(id).await?;
+
+    Ok((StatusCode::OK, Json(job)))
+}
+
+pub async fn purge_todos<T: TodoRepository, J: JobRepository>(
+    Extension(todo_repository): Extension<Arc<T>>,
+    Extension(job_repository): Extension<Arc<J>>,
+    links: LinkBuilder,
+) -> Result<impl IntoResponse, ApiError> {
+    let job = job_repository.create(JobKind::PurgeCompleted).await?;
+    let location = Headers(vec![(header::LOCATION, links.job(job.id).href)]);
+    jobs::spawn(job_repository, todo_repository, job.clone());
+
+    Ok((StatusCode::ACCEPTED, location, Json(job)))
+}
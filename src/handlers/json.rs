@@ -0,0 +1,321 @@
+//! Todos as a portable JSON document: `GET /export/json` writes every todo
+//! and label in a form `POST /import/json` reads back on another instance.
+//!
+//! Unlike `GET /admin/backup` it holds what clients see, decrypted and
+//! without storage details, so it moves between backends and keys.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use axum::{
+    body::{self, Body},
+    extract::Extension,
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures_util::{future, stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use validator::{Validate, ValidationErrors};
+
+use crate::repositories::{
+    backup::{invalid, unique},
+    date::Date,
+    id::{IdFormat, Uuid},
+    label::{Label, LabelRepository},
+    todo::{CreateTodo, Import, ImportLabel, TodoFilter, TodoRepository, TodoWithLabels},
+};
+
+use super::{error::ApiError, feed::rfc3339, todo::stream_with_labels, ValidatedJson};
+
+/// Version of the export layout; bumped whenever a field changes meaning.
+pub const EXPORT_FORMAT: u32 = 1;
+
+/// A todo as clients see it, its labels by id.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportTodo {
+    pub id: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uuid: Option<Uuid>,
+    pub text: String,
+    #[serde(default)]
+    pub completed: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub due: Option<Date>,
+    #[serde(default)]
+    pub labels: Vec<i64>,
+}
+
+impl From<&TodoWithLabels> for ExportTodo {
+    fn from(TodoWithLabels { todo, labels }: &TodoWithLabels) -> Self {
+        Self {
+            id: todo.id(),
+            uuid: todo.uuid().cloned(),
+            text: todo.text().to_string(),
+            completed: todo.completed(),
+            due: todo.due().cloned(),
+            labels: labels.iter().map(|label| label.id).collect(),
+        }
+    }
+}
+
+/// An export document as `GET /export/json` writes it.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Export {
+    pub format: u32,
+    #[serde(default)]
+    pub exported_at: String,
+    #[serde(default)]
+    pub labels: Vec<Label>,
+    #[serde(default)]
+    pub todos: Vec<ExportTodo>,
+}
+
+/// Checks the export is one this version wrote and hangs together.
+impl Validate for Export {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        if self.format != EXPORT_FORMAT {
+            errors.add("format", invalid("unsupported export format"));
+        }
+        if !unique(self.labels.iter().map(|label| label.id)) {
+            errors.add("labels", invalid("duplicate ids"));
+        }
+        if !unique(self.labels.iter().map(|label| &label.name)) {
+            errors.add("labels", invalid("duplicate names"));
+        }
+        if !unique(self.todos.iter().map(|todo| todo.id)) {
+            errors.add("todos", invalid("duplicate ids"));
+        }
+        if !unique(self.todos.iter().filter_map(|todo| todo.uuid.as_ref())) {
+            errors.add("todos", invalid("duplicate uuids"));
+        }
+        let label_ids: HashSet<_> = self.labels.iter().map(|label| label.id).collect();
+        if !self
+            .todos
+            .iter()
+            .flat_map(|todo| &todo.labels)
+            .all(|id| label_ids.contains(id))
+        {
+            errors.add("todos", invalid("unknown label"));
+        }
+        if self
+            .todos
+            .iter()
+            .any(|todo| CreateTodo::new(todo.text.clone()).validate().is_err())
+        {
+            errors.add("todos", invalid("a text is empty or over 100"));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// `GET /export/json`: every label, then every todo in id order, written
+/// as the todos are read. Archived todos are left out.
+pub async fn export_json<T: TodoRepository, L: LabelRepository>(
+    Extension(todo_repository): Extension<Arc<T>>,
+    Extension(label_repository): Extension<Arc<L>>,
+) -> Result<Response, ApiError> {
+    let labels = label_repository.all().await?;
+    let head = Export {
+        format: EXPORT_FORMAT,
+        exported_at: rfc3339(SystemTime::now()),
+        labels,
+        todos: vec![],
+    };
+    // the todos go where the empty array ends
+    let mut head = serde_json::to_vec(&head).map_err(anyhow::Error::from)?;
+    head.truncate(head.len() - b"]}".len());
+
+    let todos =
+        stream_with_labels(todo_repository, TodoFilter::default()).scan(true, |first, todos| {
+            let chunk = todos.and_then(|todos| {
+                let mut chunk = vec![];
+                for todo in &todos {
+                    if !std::mem::take(first) {
+                        chunk.push(b',');
+                    }
+                    serde_json::to_writer(&mut chunk, &ExportTodo::from(todo))?;
+                }
+                Ok(chunk)
+            });
+            future::ready(Some(chunk))
+        });
+    let chunks = stream::once(future::ready(anyhow::Ok(head)))
+        .chain(todos)
+        .chain(stream::once(future::ready(Ok(b"]}".to_vec()))));
+    let mut res = Response::new(body::boxed(Body::wrap_stream(chunks)));
+    res.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    res.headers_mut().insert(
+        header::CONTENT_DISPOSITION,
+        HeaderValue::from_static(r#"attachment; filename="my-todo-export.json""#),
+    );
+
+    Ok(res)
+}
+
+/// What an import added, and the ids the todos got.
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Imported {
+    /// Labels created; those named like a stored one are reused.
+    pub labels: usize,
+    pub todos: usize,
+    /// Todos left out as their uuid is already stored, e.g. by an earlier
+    /// import of the same export.
+    pub skipped: usize,
+    /// The new id of every imported todo, by its id in the export.
+    pub ids: BTreeMap<i64, i64>,
+}
+
+/// `POST /import/json` with a document from `GET /export/json`.
+pub async fn import_json<T: TodoRepository, L: LabelRepository>(
+    Extension(todo_repository): Extension<Arc<T>>,
+    Extension(label_repository): Extension<Arc<L>>,
+    format: IdFormat,
+    ValidatedJson(export): ValidatedJson<Export>,
+) -> Result<impl IntoResponse, ApiError> {
+    let imported = import_export(&*todo_repository, &*label_repository, export, format).await?;
+
+    Ok((StatusCode::CREATED, Json(imported)))
+}
+
+/// Stores a validated `export` with one [`TodoRepository::import`], so on
+/// SQL backends its labels and todos are all stored or none are.
+pub async fn import_export<T: TodoRepository, L: LabelRepository>(
+    todo_repository: &T,
+    label_repository: &L,
+    export: Export,
+    format: IdFormat,
+) -> anyhow::Result<Imported> {
+    let taken: HashSet<_> = label_repository
+        .all()
+        .await?
+        .into_iter()
+        .filter_map(|label| label.uuid)
+        .collect();
+    let index: HashMap<_, _> = export
+        .labels
+        .iter()
+        .enumerate()
+        .map(|(i, label)| (label.id, i))
+        .collect();
+    let labels = export
+        .labels
+        .into_iter()
+        .map(|label| ImportLabel {
+            name: label.name,
+            uuid: label
+                .uuid
+                .filter(|uuid| !taken.contains(uuid))
+                .or_else(|| (format == IdFormat::Uuid).then(Uuid::now_v7)),
+        })
+        .collect();
+
+    let taken: HashSet<_> = todo_repository
+        .all(&TodoFilter::default())
+        .await?
+        .iter()
+        .filter_map(|todo| todo.uuid().cloned())
+        .collect();
+    let (todos, skipped): (Vec<_>, Vec<_>) = export
+        .todos
+        .into_iter()
+        .partition(|todo| !todo.uuid.as_ref().is_some_and(|uuid| taken.contains(uuid)));
+    let payloads = todos
+        .iter()
+        .map(|todo| {
+            let payload = CreateTodo::new(todo.text.clone())
+                .with_uuid(todo.uuid.clone())
+                .with_completed(todo.completed)
+                .with_due(todo.due.clone())
+                .with_format(format);
+            (payload, todo.labels.iter().map(|id| index[id]).collect())
+        })
+        .collect();
+    let import = Import {
+        labels,
+        todos: payloads,
+    };
+    let stored = todo_repository.import(label_repository, import).await?;
+
+    let imported = Imported {
+        labels: stored.created_labels,
+        todos: stored.todos.len(),
+        skipped: skipped.len(),
+        ids: todos
+            .iter()
+            .zip(&stored.todos)
+            .map(|(todo, created)| (todo.id, created.id()))
+            .collect(),
+    };
+    tracing::info!(
+        "imported {} todos and {} labels, skipped {} todos",
+        imported.todos,
+        imported.labels,
+        imported.skipped
+    );
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn todo(id: i64, text: &str, labels: Vec<i64>) -> ExportTodo {
+        ExportTodo {
+            id,
+            uuid: None,
+            text: text.to_string(),
+            completed: false,
+            due: None,
+            labels,
+        }
+    }
+
+    #[test]
+    fn checks_the_export_hangs_together() {
+        let export = Export {
+            format: EXPORT_FORMAT,
+            labels: vec![Label {
+                id: 1,
+                uuid: None,
+                name: "work".to_string(),
+            }],
+            todos: vec![todo(1, "a", vec![1]), todo(2, "b", vec![])],
+            ..Export::default()
+        };
+        assert!(export.validate().is_ok());
+
+        let invalid = Export {
+            format: EXPORT_FORMAT + 1,
+            todos: vec![todo(1, "", vec![2]), todo(1, "b", vec![])],
+            ..export
+        };
+        let errors = invalid.validate().unwrap_err();
+        let errors = errors.field_errors();
+        assert!(errors.contains_key("format"));
+        let messages: Vec<_> = errors["todos"]
+            .iter()
+            .filter_map(|error| error.message.as_deref())
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "duplicate ids",
+                "unknown label",
+                "a text is empty or over 100"
+            ]
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use std::{collections::BTreeMap, convert::Infallible};
+
+use axum::{
+    async_trait,
+    extract::{FromRequest, RequestParts},
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use super::links::{LinkBuilder, Links};
+use crate::repositories::{label::Label, todo::Todo};
+
+pub const JSON_API: &str = "application/vnd.api+json";
+
+/// Response representation negotiated from the `Accept` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Representation {
+    Json,
+    JsonApi,
+}
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for Representation {
+    type Rejection = Infallible;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let accepts_json_api = req
+            .headers()
+            .and_then(|headers| headers.get(header::ACCEPT))
+            .and_then(|accept| accept.to_str().ok())
+            .is_some_and(|accept| {
+                accept
+                    .split(',')
+                    .any(|media| media.trim().starts_with(JSON_API))
+            });
+
+        Ok(if accepts_json_api {
+            Representation::JsonApi
+        } else {
+            Representation::Json
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Identifier {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub id: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Relationship {
+    pub data: Vec<Identifier>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Resource {
+    #[serde(flatten)]
+    pub identifier: Identifier,
+    pub attributes: Map<String, Value>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub relationships: BTreeMap<&'static str, Relationship>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub links: Links,
+}
+
+impl Resource {
+    /// Splits a serialized model into its `id` and the remaining attributes.
+    fn new<T: Serialize>(kind: &'static str, model: &T) -> Self {
+        let mut attributes = match serde_json::to_value(model) {
+            Ok(Value::Object(attributes)) => attributes,
+            _ => Map::new(),
+        };
+        let id = attributes
+            .remove("id")
+            .map(|id| match id {
+                Value::String(id) => id,
+                id => id.to_string(),
+            })
+            .unwrap_or_default();
+
+        Self {
+            identifier: Identifier { kind, id },
+            attributes,
+            relationships: BTreeMap::new(),
+            links: Links::new(),
+        }
+    }
+
+    pub fn label(label: &Label) -> Self {
+        Resource::new("labels", label)
+    }
+
+    /// A todo resource; `labels` is only given when the relation was
+    /// requested, in which case it becomes a relationship.
+    pub fn todo(links: &LinkBuilder, todo: &Todo, labels: Option<&[Label]>) -> Self {
+        let mut resource = Resource::new("todos", todo);
+        resource.links.insert("self", links.todo(todo));
+        if let Some(labels) = labels {
+            let data = labels
+                .iter()
+                .map(|label| Resource::label(label).identifier)
+                .collect();
+            resource
+                .relationships
+                .insert("labels", Relationship { data });
+        }
+        resource
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum PrimaryData {
+    One(Resource),
+    Many(Vec<Resource>),
+}
+
+#[derive(Debug, Serialize)]
+pub struct Document {
+    pub data: PrimaryData,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub included: Vec<Resource>,
+}
+
+impl Document {
+    pub fn new(data: PrimaryData, mut included: Vec<Resource>) -> Self {
+        // compound documents must not repeat a resource
+        included.sort_by(|a, b| a.identifier.cmp(&b.identifier));
+        included.dedup_by(|a, b| a.identifier == b.identifier);
+        Self { data, included }
+    }
+}
+
+impl IntoResponse for Document {
+    fn into_response(self) -> Response {
+        let mut res = (StatusCode::OK, Json(self)).into_response();
+        res.headers_mut()
+            .insert(header::CONTENT_TYPE, HeaderValue::from_static(JSON_API));
+        res
+    }
+}
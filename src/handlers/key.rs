@@ -0,0 +1,45 @@
+use std::convert::Infallible;
+
+use axum::{
+    async_trait,
+    extract::{FromRequest, Path, RequestParts},
+};
+
+use crate::repositories::id::{IdFormat, Key};
+
+use super::{error::ApiError, i18n::trf};
+
+/// The [`IdFormat`] the server runs with; serial unless `main` layered one in.
+pub fn id_format<B>(req: &RequestParts<B>) -> IdFormat {
+    req.extensions()
+        .and_then(|extensions| extensions.get::<IdFormat>())
+        .copied()
+        .unwrap_or_default()
+}
+
+/// The `:id` path segment as a [`Key`]. Ids of the other format answer 404,
+/// as no todo or label can have them.
+#[async_trait]
+impl<B: Send> FromRequest<B> for Key {
+    type Rejection = ApiError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let format = id_format(req);
+        let Path(raw) = Path::<String>::from_request(req)
+            .await
+            .map_err(|_| ApiError::NotFound(trf("NotFound, id is {}", &[&""])))?;
+
+        Key::parse(&raw, format)
+            .ok_or_else(|| ApiError::NotFound(trf("NotFound, id is {}", &[&raw])))
+    }
+}
+
+/// Lets handlers take the format directly, like [`Key`] does.
+#[async_trait]
+impl<B: Send> FromRequest<B> for IdFormat {
+    type Rejection = Infallible;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        Ok(id_format(req))
+    }
+}
@@ -1,46 +1,44 @@
 use std::sync::Arc;
 
-use axum::{
-    extract::{Extension, Path},
-    http::StatusCode,
-    response::IntoResponse,
-    Json,
-};
+use axum::{extract::Extension, http::StatusCode, response::IntoResponse, Json};
 use serde::{Deserialize, Serialize};
 use validator::Validate;
 
-use crate::repositories::label::{LabelRepository, UpdateLabel};
+use crate::repositories::{
+    id::{IdFormat, Key, Uuid},
+    label::LabelRepository,
+};
 
-use super::ValidatedJson;
+use super::{error::ApiError, ValidatedJson};
 
 pub async fn create_label<T: LabelRepository>(
     ValidatedJson(payload): ValidatedJson<CreateLabel>,
     Extension(repository): Extension<Arc<T>>,
-) -> Result<impl IntoResponse, StatusCode> {
-    let label = repository
-        .create(payload.name)
-        .await
-        .or(Err(StatusCode::INTERNAL_SERVER_ERROR))?;
+    format: IdFormat,
+) -> Result<impl IntoResponse, ApiError> {
+    let uuid = (format == IdFormat::Uuid).then(Uuid::now_v7);
+    let label = repository.create(payload.name, uuid).await?;
 
     Ok((StatusCode::CREATED, Json(label)))
 }
 
 pub async fn all_label<T: LabelRepository>(
     Extension(repository): Extension<Arc<T>>,
-) -> Result<impl IntoResponse, StatusCode> {
-    let all = repository.all().await.unwrap();
+) -> Result<impl IntoResponse, ApiError> {
+    let all = repository.all().await?;
     Ok((StatusCode::OK, Json(all)))
 }
 
 pub async fn delete_label<T: LabelRepository>(
-    Path(id): Path<i32>,
+    key: Key,
     Extension(repository): Extension<Arc<T>>,
-) -> StatusCode {
+) -> Result<StatusCode, ApiError> {
+    let id = repository.resolve(&key).await?;
     repository
         .delete(id)
         .await
         .map(|_| StatusCode::NO_CONTENT)
-        .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+        .map_err(ApiError::from)
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Validate)]
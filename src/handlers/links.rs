@@ -0,0 +1,103 @@
+use std::{collections::BTreeMap, convert::Infallible};
+
+use axum::{
+    async_trait,
+    extract::{FromRequest, OriginalUri, RequestParts},
+};
+use serde::Serialize;
+
+use super::key::id_format;
+use crate::repositories::{id::IdFormat, todo::Todo};
+
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct Link {
+    pub href: String,
+}
+
+pub type Links = BTreeMap<&'static str, Link>;
+
+#[derive(Debug, Serialize)]
+pub struct Linked<T> {
+    #[serde(flatten)]
+    pub inner: T,
+    #[serde(rename = "_links")]
+    pub links: Links,
+}
+
+/// Builds resource links relative to wherever the router is mounted.
+///
+/// The prefix is recovered by comparing the original request uri with the one
+/// seen by the (possibly nested) router, so nothing needs to be configured.
+#[derive(Debug, Clone)]
+pub struct LinkBuilder {
+    prefix: String,
+    format: IdFormat,
+}
+
+impl LinkBuilder {
+    pub fn new(prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: prefix.into().trim_end_matches('/').to_string(),
+            format: IdFormat::default(),
+        }
+    }
+
+    pub fn with_format(mut self, format: IdFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    fn href(&self, path: &str) -> Link {
+        Link {
+            href: format!("{}{}", self.prefix, path),
+        }
+    }
+
+    pub fn todos(&self) -> Link {
+        self.href("/todos")
+    }
+
+    pub fn labels(&self) -> Link {
+        self.href("/labels")
+    }
+
+    pub fn job(&self, id: i32) -> Link {
+        self.href(&format!("/jobs/{}", id))
+    }
+
+    pub fn todo(&self, todo: &Todo) -> Link {
+        self.href(&format!("/todos/{}", todo.key(self.format)))
+    }
+
+    /// Wraps any todo representation (lean or expanded) with its links.
+    pub fn linked_todo<T: AsRef<Todo>>(&self, inner: T) -> Linked<T> {
+        let links = Links::from([
+            ("self", self.todo(inner.as_ref())),
+            ("collection", self.todos()),
+            ("labels", self.labels()),
+        ]);
+        Linked { inner, links }
+    }
+
+    /// Value for an RFC 8288 `Link` header describing a todo collection,
+    /// which stays a plain JSON array in the body.
+    pub fn todos_header(&self) -> String {
+        format!(
+            r#"<{}>; rel="self", <{}>; rel="labels""#,
+            self.todos().href,
+            self.labels().href
+        )
+    }
+}
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for LinkBuilder {
+    type Rejection = Infallible;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let path = req.uri().path().to_string();
+        let OriginalUri(original) = OriginalUri::from_request(req).await?;
+        let prefix = original.path().strip_suffix(&path).unwrap_or_default();
+        Ok(LinkBuilder::new(prefix).with_format(id_format(req)))
+    }
+}
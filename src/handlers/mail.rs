@@ -0,0 +1,246 @@
+//! `POST /integrations/mail`: turns mail sent to one address into todos,
+//! posted by a Mailgun route (`forward("https://.../integrations/mail")`)
+//! or anything else speaking its inbound webhook.
+//!
+//! The subject becomes the todo's text, or the first line of the body when
+//! there is none. Only form encoded posts are read, so mail with
+//! attachments, which Mailgun sends as multipart, is refused.
+
+use std::{
+    env,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use axum::{
+    body::Bytes,
+    extract::Extension,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use ring::hmac;
+use serde::Deserialize;
+use validator::Validate;
+
+use crate::repositories::{
+    id::IdFormat,
+    todo::{CreateTodo, TodoRepository},
+};
+
+use super::{
+    error::ApiError,
+    i18n::{tr, trf},
+    unhex,
+};
+
+/// Longest text a todo may have; longer subjects are cut.
+const MAX_TEXT: usize = 100;
+
+/// How far a webhook's timestamp may be off, so a captured one can't be
+/// replayed later.
+const MAX_SKEW: Duration = Duration::from_secs(15 * 60);
+
+/// Where todos are mailed to, from `INBOUND_MAIL_ADDRESS`, and the key
+/// webhooks are signed with, from `MAILGUN_SIGNING_KEY`. Without both the
+/// endpoint answers `404`.
+#[derive(Debug, Clone)]
+pub struct MailInbox {
+    address: String,
+    key: hmac::Key,
+}
+
+impl MailInbox {
+    pub fn new(address: &str, signing_key: &str) -> Self {
+        Self {
+            address: address.trim().to_string(),
+            key: hmac::Key::new(hmac::HMAC_SHA256, signing_key.as_bytes()),
+        }
+    }
+
+    pub fn from_env() -> Option<Self> {
+        let address = env::var("INBOUND_MAIL_ADDRESS").ok()?;
+        let signing_key = env::var("MAILGUN_SIGNING_KEY").ok()?;
+        (!address.trim().is_empty() && !signing_key.is_empty())
+            .then(|| Self::new(&address, &signing_key))
+    }
+
+    /// Whether `mail` was signed with the key, recently.
+    fn verify(&self, mail: &InboundMail, now: SystemTime) -> bool {
+        let Ok(timestamp) = mail.timestamp.parse::<u64>() else {
+            return false;
+        };
+        let sent = SystemTime::UNIX_EPOCH + Duration::from_secs(timestamp);
+        let skew = now
+            .duration_since(sent)
+            .or_else(|_| sent.duration_since(now))
+            .unwrap_or_default();
+        let Some(signature) = unhex(&mail.signature) else {
+            return false;
+        };
+        let message = format!("{}{}", mail.timestamp, mail.token);
+        skew <= MAX_SKEW && hmac::verify(&self.key, message.as_bytes(), &signature).is_ok()
+    }
+
+    /// Whether one of the `recipient` addresses, separated by `,`, is the
+    /// inbox, ignoring case and a display name.
+    fn receives(&self, recipient: &str) -> bool {
+        recipient.split(',').any(|address| {
+            let address = address.trim();
+            let address = match (address.rfind('<'), address.strip_suffix('>')) {
+                (Some(start), Some(_)) => &address[start + 1..address.len() - 1],
+                _ => address,
+            };
+            address.eq_ignore_ascii_case(&self.address)
+        })
+    }
+}
+
+/// The fields of Mailgun's inbound webhook an import reads.
+#[derive(Debug, Default, Deserialize)]
+struct InboundMail {
+    #[serde(default)]
+    recipient: String,
+    #[serde(default)]
+    sender: String,
+    #[serde(default)]
+    subject: String,
+    /// The body without quoted replies and signature.
+    #[serde(default, rename = "stripped-text")]
+    stripped_text: String,
+    #[serde(default, rename = "body-plain")]
+    body_plain: String,
+    timestamp: String,
+    token: String,
+    signature: String,
+}
+
+impl InboundMail {
+    /// The todo the mail asks for, if it says anything.
+    fn text(&self) -> Option<String> {
+        let body = if self.stripped_text.trim().is_empty() {
+            &self.body_plain
+        } else {
+            &self.stripped_text
+        };
+        let line = Some(self.subject.as_str())
+            .into_iter()
+            .chain(body.lines())
+            .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+            .find(|line| !line.is_empty())?;
+        Some(line.chars().take(MAX_TEXT).collect())
+    }
+}
+
+/// `POST /integrations/mail` with a Mailgun inbound webhook. Mail to
+/// another address is answered `406`, which Mailgun takes as a rejection
+/// not to retry.
+pub async fn receive_mail<T: TodoRepository>(
+    Extension(repository): Extension<Arc<T>>,
+    inbox: Option<Extension<MailInbox>>,
+    format: IdFormat,
+    body: Bytes,
+) -> Result<Response, ApiError> {
+    let Extension(inbox) = inbox.ok_or_else(|| {
+        ApiError::NotFound(tr(
+            "Email ingestion needs an INBOUND_MAIL_ADDRESS and a MAILGUN_SIGNING_KEY",
+        ))
+    })?;
+    let mail: InboundMail = serde_urlencoded::from_bytes(&body)
+        .map_err(|e| ApiError::BadRequest(trf("Invalid form: [{}]", &[&e])))?;
+    if !inbox.verify(&mail, SystemTime::now()) {
+        return Err(ApiError::Unauthorized(tr("Invalid webhook signature")));
+    }
+    if !inbox.receives(&mail.recipient) {
+        tracing::info!("refused mail to {} from {}", mail.recipient, mail.sender);
+        return Ok(StatusCode::NOT_ACCEPTABLE.into_response());
+    }
+    let Some(text) = mail.text() else {
+        tracing::info!("refused empty mail from {}", mail.sender);
+        return Ok(StatusCode::NOT_ACCEPTABLE.into_response());
+    };
+
+    let payload = CreateTodo::new(text);
+    payload.validate()?;
+    let todo = repository.create(payload.with_format(format)).await?;
+    tracing::info!("created todo {} from mail by {}", todo.id(), mail.sender);
+
+    Ok((StatusCode::CREATED, Json(todo)).into_response())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::handlers::hex;
+
+    const KEY: &str = "key-7f3a";
+
+    /// The form Mailgun would post for a mail, signed with [`KEY`] at `at`.
+    fn signed_form(fields: &[(&str, &str)], at: SystemTime) -> String {
+        let timestamp = at
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            .to_string();
+        let token = "c0ffee";
+        let key = hmac::Key::new(hmac::HMAC_SHA256, KEY.as_bytes());
+        let signature = hmac::sign(&key, format!("{}{}", timestamp, token).as_bytes());
+        let signature = hex(signature.as_ref());
+        let mut fields = fields.to_vec();
+        fields.extend([
+            ("timestamp", timestamp.as_str()),
+            ("token", token),
+            ("signature", signature.as_str()),
+        ]);
+        serde_urlencoded::to_string(fields).unwrap()
+    }
+
+    fn mail(form: &str) -> InboundMail {
+        serde_urlencoded::from_str(form).unwrap()
+    }
+
+    #[test]
+    fn verifies_the_signature() {
+        let inbox = MailInbox::new("todo@example.com", KEY);
+        let now = SystemTime::now();
+        let form = signed_form(&[], now);
+        assert!(inbox.verify(&mail(&form), now));
+        assert!(!inbox.verify(&mail(&form), now + 2 * MAX_SKEW));
+
+        let other = MailInbox::new("todo@example.com", "another key");
+        assert!(!other.verify(&mail(&form), now));
+        let tampered = form.replace("token=c0ffee", "token=c0ffef");
+        assert!(!inbox.verify(&mail(&tampered), now));
+    }
+
+    #[test]
+    fn receives_mail_to_the_inbox() {
+        let inbox = MailInbox::new("todo@example.com", KEY);
+        assert!(inbox.receives("TODO@example.com"));
+        assert!(inbox.receives("me@example.com, My todos <todo@example.com>"));
+        assert!(!inbox.receives("someone@example.com"));
+    }
+
+    #[test]
+    fn reads_the_subject_or_the_body() {
+        let with_subject = InboundMail {
+            subject: "  Buy\tmilk ".to_string(),
+            stripped_text: "On the way home".to_string(),
+            ..InboundMail::default()
+        };
+        assert_eq!(with_subject.text().as_deref(), Some("Buy milk"));
+
+        let without = InboundMail {
+            body_plain: "\n\nCall mum\nabout Sunday\n".to_string(),
+            ..InboundMail::default()
+        };
+        assert_eq!(without.text().as_deref(), Some("Call mum"));
+        assert_eq!(InboundMail::default().text(), None);
+
+        let long = InboundMail {
+            subject: "x".repeat(150),
+            ..InboundMail::default()
+        };
+        assert_eq!(long.text().unwrap().chars().count(), MAX_TEXT);
+    }
+}
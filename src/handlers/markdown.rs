@@ -0,0 +1,112 @@
+//! `GET /export/markdown`: the todos as a Markdown checklist, one section
+//! per label, to paste into notes or a README.
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use axum::{
+    extract::Extension,
+    http::{header, HeaderValue},
+    response::{IntoResponse, Response},
+};
+use futures_util::TryStreamExt;
+
+use crate::repositories::todo::{TodoRepository, TodoWithLabels};
+
+use super::{error::ApiError, i18n::tr, search::Search, todo::stream_with_labels};
+
+pub const MARKDOWN: &str = "text/markdown; charset=utf-8";
+
+/// `text` with everything Markdown would read as markup escaped, on one
+/// line so it stays a single item.
+fn escape(text: &str) -> String {
+    let line = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let mut escaped = String::with_capacity(line.len());
+    for c in line.chars() {
+        if "\\`*_[]<>#|~".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn item(out: &mut String, todo: &TodoWithLabels) {
+    let mark = if todo.todo.completed() { 'x' } else { ' ' };
+    out.push_str(&format!("- [{}] {}\n", mark, escape(todo.todo.text())));
+}
+
+/// The checklist: a section per label by name, a todo under each of its
+/// labels, then one for the todos without. Without any labels it is a
+/// single list.
+fn render(todos: &[TodoWithLabels]) -> String {
+    let mut sections: BTreeMap<&str, Vec<&TodoWithLabels>> = BTreeMap::new();
+    let mut unlabelled = vec![];
+    for todo in todos {
+        if todo.labels.is_empty() {
+            unlabelled.push(todo);
+        }
+        for label in &todo.labels {
+            sections.entry(&label.name).or_default().push(todo);
+        }
+    }
+
+    let mut out = format!("# {}\n", tr("Todos"));
+    for (name, todos) in &sections {
+        out.push_str(&format!("\n## {}\n\n", escape(name)));
+        todos.iter().for_each(|todo| item(&mut out, todo));
+    }
+    if !unlabelled.is_empty() {
+        if !sections.is_empty() {
+            out.push_str(&format!("\n## {}\n", tr("Without a label")));
+        }
+        out.push('\n');
+        unlabelled.iter().for_each(|todo| item(&mut out, todo));
+    }
+    out
+}
+
+/// `GET /export/markdown`: the todos `GET /todos` lists for the same `?q=`
+/// and `?ids=`, oldest first in every section.
+pub async fn export_markdown<T: TodoRepository>(
+    Extension(repository): Extension<Arc<T>>,
+    Search(filter): Search,
+) -> Result<Response, ApiError> {
+    let todos: Vec<_> = stream_with_labels(repository, filter).try_concat().await?;
+    let mut res = render(&todos).into_response();
+    let headers = res.headers_mut();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(MARKDOWN));
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        HeaderValue::from_static(r#"attachment; filename="todos.md""#),
+    );
+    Ok(res)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::todo;
+
+    #[test]
+    fn groups_the_checklist_by_label() {
+        let todos = [
+            todo(1, "Prune *all* roses", true, &["garden"]),
+            todo(2, "Call\nmum", false, &[]),
+            todo(3, "Buy [seeds]", false, &["garden", "errands"]),
+        ];
+        assert_eq!(
+            render(&todos),
+            "# Todos\n\
+             \n## errands\n\n- [ ] Buy \\[seeds\\]\n\
+             \n## garden\n\n- [x] Prune \\*all\\* roses\n- [ ] Buy \\[seeds\\]\n\
+             \n## Without a label\n\n- [ ] Call mum\n"
+        );
+    }
+
+    #[test]
+    fn lists_unlabelled_todos_without_sections() {
+        let todos = [todo(1, "a", false, &[]), todo(2, "b", true, &[])];
+        assert_eq!(render(&todos), "# Todos\n\n- [ ] a\n- [x] b\n");
+        assert_eq!(render(&[]), "# Todos\n");
+    }
+}
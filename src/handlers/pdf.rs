@@ -0,0 +1,345 @@
+//! `GET /labels/:id/export.pdf`: the todos of a label as a checklist to
+//! print, a box before each, a tick in the box once completed and the due
+//! day, if any, at the right.
+//!
+//! Todos have no projects here; labels stand in for them, so a project's
+//! checklist is that of the label named after it.
+//!
+//! Pages use the standard Helvetica fonts every viewer has, so nothing is
+//! embedded; text outside Latin-1 prints as `?`.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::Extension,
+    http::{header, HeaderValue},
+    response::{IntoResponse, Response},
+};
+use futures_util::TryStreamExt;
+
+use crate::repositories::{
+    id::Key,
+    label::LabelRepository,
+    todo::{TodoFilter, TodoRepository, TodoWithLabels},
+};
+
+use super::{error::ApiError, i18n::trf, todo::stream_with_labels};
+
+pub const PDF: &str = "application/pdf";
+
+/// A4, in points.
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+const MARGIN: f32 = 56.0;
+
+const TITLE_SIZE: f32 = 18.0;
+const TEXT_SIZE: f32 = 11.0;
+/// From one line of an item to the next.
+const LINE: f32 = 15.0;
+/// Between two items.
+const GAP: f32 = 7.0;
+/// The side of a checkbox.
+const BOX: f32 = 9.0;
+/// Where the text of an item starts, right of the box's left edge.
+const INDENT: f32 = 18.0;
+
+/// Helvetica's advance widths of `' '..='~'`, in thousandths of the size.
+const WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667,
+    611, 778, 722, 278, 500, 667, 556, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 278, 278, 278, 469, 556, 333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500,
+    222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+
+/// `text` in the fonts' WinAnsi encoding, which Latin-1 is a part of.
+fn encode(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| match c as u32 {
+            code @ (0x20..=0x7e | 0xa0..=0xff) => code as u8,
+            _ if c.is_whitespace() => b' ',
+            _ => b'?',
+        })
+        .collect()
+}
+
+/// How wide `text` sets at `size`, taking the letters Helvetica's table
+/// lacks as wide as a digit.
+fn width(text: &[u8], size: f32) -> f32 {
+    let units: u32 = text
+        .iter()
+        .map(|&byte| match byte {
+            b' '..=b'~' => WIDTHS[(byte - b' ') as usize] as u32,
+            _ => 556,
+        })
+        .sum();
+    units as f32 * size / 1000.0
+}
+
+/// `text` broken into lines at most `max` wide, between words where it
+/// can be and inside a word too long for a line of its own.
+fn wrap(text: &[u8], max: f32, size: f32) -> Vec<Vec<u8>> {
+    let mut lines: Vec<Vec<u8>> = vec![];
+    let mut line: Vec<u8> = vec![];
+    for word in text
+        .split(|&byte| byte == b' ')
+        .filter(|word| !word.is_empty())
+    {
+        let mut candidate = line.clone();
+        if !candidate.is_empty() {
+            candidate.push(b' ');
+        }
+        candidate.extend(word);
+        if width(&candidate, size) <= max {
+            line = candidate;
+            continue;
+        }
+        if !line.is_empty() {
+            lines.push(std::mem::take(&mut line));
+        }
+        for &byte in word {
+            line.push(byte);
+            if width(&line, size) > max && line.len() > 1 {
+                line.pop();
+                lines.push(std::mem::replace(&mut line, vec![byte]));
+            }
+        }
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// The operators showing `text`, its baseline starting at `x`, `y`.
+fn show(ops: &mut String, font: &str, size: f32, x: f32, y: f32, text: &[u8]) {
+    let hex: String = text.iter().map(|byte| format!("{:02X}", byte)).collect();
+    ops.push_str(&format!(
+        "BT /{} {} Tf {:.2} {:.2} Td <{}> Tj ET\n",
+        font, size, x, y, hex
+    ));
+}
+
+/// The content stream of every page: `title` on the first, then an item
+/// per todo, starting a page whenever the next one doesn't fit.
+fn pages(title: &str, todos: &[TodoWithLabels]) -> Vec<String> {
+    let mut pages = vec![];
+    let mut ops = String::from("0.6 w\n");
+    let mut y = PAGE_HEIGHT - MARGIN - TITLE_SIZE;
+    let text_width = PAGE_WIDTH - 2.0 * MARGIN - INDENT;
+    for line in wrap(&encode(title), PAGE_WIDTH - 2.0 * MARGIN, TITLE_SIZE) {
+        show(&mut ops, "F2", TITLE_SIZE, MARGIN, y, &line);
+        y -= TITLE_SIZE * 1.25;
+    }
+    y -= GAP * 2.0;
+
+    for TodoWithLabels { todo, .. } in todos {
+        // the due day sits right of the first line, which makes room for it
+        let due = todo
+            .due()
+            .map(|due| encode(&trf("due {}", &[&due.as_str()])));
+        let room = due
+            .as_ref()
+            .map_or(0.0, |due| width(due, TEXT_SIZE) + INDENT);
+        let lines = wrap(&encode(todo.text()), text_width - room, TEXT_SIZE);
+        let height = LINE * (lines.len() - 1) as f32;
+        if y - height < MARGIN && y < PAGE_HEIGHT - MARGIN - TEXT_SIZE {
+            pages.push(std::mem::replace(&mut ops, String::from("0.6 w\n")));
+            y = PAGE_HEIGHT - MARGIN - TEXT_SIZE;
+        }
+        // the box sits on the baseline of the first line
+        ops.push_str(&format!(
+            "{:.2} {:.2} {} {} re S\n",
+            MARGIN,
+            y - 1.0,
+            BOX,
+            BOX
+        ));
+        if todo.completed() {
+            ops.push_str(&format!(
+                "{:.2} {:.2} m {:.2} {:.2} l {:.2} {:.2} l S\n",
+                MARGIN + 2.0,
+                y + 3.5,
+                MARGIN + 4.0,
+                y + 1.0,
+                MARGIN + 7.5,
+                y + 6.5
+            ));
+        }
+        if let Some(due) = &due {
+            let x = PAGE_WIDTH - MARGIN - width(due, TEXT_SIZE);
+            show(&mut ops, "F1", TEXT_SIZE, x, y, due);
+        }
+        for line in &lines {
+            show(&mut ops, "F1", TEXT_SIZE, MARGIN + INDENT, y, line);
+            y -= LINE;
+        }
+        y -= GAP;
+    }
+    pages.push(ops);
+
+    let count = pages.len();
+    for (i, ops) in pages.iter_mut().enumerate() {
+        let number = encode(&format!("{} / {}", i + 1, count));
+        let x = PAGE_WIDTH - MARGIN - width(&number, 9.0);
+        show(ops, "F1", 9.0, x, MARGIN / 2.0, &number);
+    }
+    pages
+}
+
+/// The document: a catalog, the page tree, both fonts, then each page
+/// followed by its contents, and the cross-reference table readers seek by.
+fn render(title: &str, todos: &[TodoWithLabels]) -> Vec<u8> {
+    let pages = pages(title, todos);
+    // objects 5, 7, 9... are pages, each followed by its contents
+    let kids: Vec<String> = (0..pages.len())
+        .map(|i| format!("{} 0 R", 5 + 2 * i))
+        .collect();
+    let mut objects = vec![
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            kids.join(" "),
+            pages.len()
+        ),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>"
+            .to_string(),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica-Bold /Encoding /WinAnsiEncoding >>"
+            .to_string(),
+    ];
+    for (i, ops) in pages.iter().enumerate() {
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] \
+             /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+            PAGE_WIDTH,
+            PAGE_HEIGHT,
+            6 + 2 * i
+        ));
+        objects.push(format!(
+            "<< /Length {} >>\nstream\n{}\nendstream",
+            ops.len(),
+            ops
+        ));
+    }
+
+    // past ASCII on the second line, so transfers treat the file as binary
+    let mut out = "%PDF-1.4\n%âãÏÓ\n".as_bytes().to_vec();
+    let mut offsets = vec![];
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(out.len());
+        out.extend(format!("{} 0 obj\n{}\nendobj\n", i + 1, object).as_bytes());
+    }
+    let xref = out.len();
+    out.extend(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+    for offset in offsets {
+        out.extend(format!("{:010} 00000 n \n", offset).as_bytes());
+    }
+    out.extend(
+        format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            objects.len() + 1,
+            xref
+        )
+        .as_bytes(),
+    );
+    out
+}
+
+/// `GET /labels/:id/export.pdf`: the label's todos, oldest first, under
+/// its name.
+pub async fn export_pdf<T: TodoRepository, L: LabelRepository>(
+    key: Key,
+    Extension(todo_repository): Extension<Arc<T>>,
+    Extension(label_repository): Extension<Arc<L>>,
+) -> Result<Response, ApiError> {
+    let id = label_repository.resolve(&key).await?;
+    let label = label_repository
+        .all()
+        .await?
+        .into_iter()
+        .find(|label| label.id == id)
+        .ok_or_else(|| ApiError::NotFound(trf("NotFound, id is {}", &[&id])))?;
+    let filter = TodoFilter {
+        labels: vec![label.name.clone()],
+        ..TodoFilter::default()
+    };
+    let todos: Vec<_> = stream_with_labels(todo_repository, filter)
+        .try_concat()
+        .await?;
+
+    let mut res = render(&label.name, &todos).into_response();
+    let headers = res.headers_mut();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(PDF));
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        HeaderValue::from_static(r#"inline; filename="todos.pdf""#),
+    );
+    Ok(res)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::todo;
+
+    #[test]
+    fn wraps_between_words() {
+        let wrapped = wrap(b"aaa bbb  ccc", width(b"aaa bbb", 10.0), 10.0);
+        assert_eq!(wrapped, [b"aaa bbb".to_vec(), b"ccc".to_vec()]);
+        let wrapped = wrap(b"abcdef", width(b"abc", 10.0), 10.0);
+        assert_eq!(wrapped, [b"abc".to_vec(), b"def".to_vec()]);
+        assert_eq!(wrap(b"", 100.0, 10.0), [Vec::<u8>::new()]);
+    }
+
+    #[test]
+    fn encodes_latin_1_only() {
+        assert_eq!(encode("Café\tPlan 日本"), b"Caf\xe9 Plan ??");
+    }
+
+    #[test]
+    fn ticks_the_completed_todos() {
+        let mut todos = [
+            todo(1, "Prune roses", false, &[]),
+            todo(2, "Mow", true, &[]),
+        ];
+        todos[0].todo = serde_json::from_value(serde_json::json!({
+            "id": 1, "text": "Prune roses", "completed": false, "due": "2024-06-01"
+        }))
+        .unwrap();
+        let pages = pages("garden", &todos);
+        assert_eq!(pages.len(), 1);
+        let page = &pages[0];
+        assert_eq!(page.matches(" re S").count(), 2, "a box per todo");
+        assert_eq!(
+            page.matches(" l S").count(),
+            1,
+            "a tick for the completed one"
+        );
+        let hex = |text: &[u8]| -> String { text.iter().map(|b| format!("{:02X}", b)).collect() };
+        assert!(page.contains(&hex(b"Prune roses")));
+        assert_eq!(page.matches(&hex(b"due 2024-06-01")).count(), 1);
+    }
+
+    #[test]
+    fn writes_a_document_readers_can_seek() {
+        let todos: Vec<_> = (1..=60)
+            .map(|id| todo(id, "Water plants", false, &[]))
+            .collect();
+        let pdf = render("garden", &todos);
+        let text = String::from_utf8(pdf).unwrap();
+        assert!(text.contains("/Count 2 >>"), "60 items take two pages");
+        assert!(text.ends_with("%%EOF\n"));
+
+        let startxref = text.rfind("startxref\n").unwrap() + "startxref\n".len();
+        let xref: usize = text[startxref..].lines().next().unwrap().parse().unwrap();
+        let table: Vec<&str> = text[xref..].lines().skip(3).take(8).collect();
+        for (i, entry) in table.iter().enumerate() {
+            let offset: usize = entry[..10].parse().unwrap();
+            assert!(
+                text[offset..].starts_with(&format!("{} 0 obj", i + 1)),
+                "{}",
+                entry
+            );
+        }
+    }
+}
@@ -0,0 +1,66 @@
+//! `/push`: the browsers' side of [`WebPush`], the key they subscribe with
+//! and their subscriptions.
+
+use axum::{extract::Extension, http::StatusCode, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use validator::Validate;
+
+use crate::push::{SubscribeError, SubscriptionJson, WebPush, MAX_SUBSCRIPTIONS};
+
+use super::{
+    error::ApiError,
+    i18n::{tr, trf},
+    ValidatedJson,
+};
+
+fn enabled(push: Option<Extension<WebPush>>) -> Result<WebPush, ApiError> {
+    push.map(|Extension(push)| push)
+        .ok_or_else(|| ApiError::NotFound(tr("Web Push needs a VAPID_PRIVATE_KEY")))
+}
+
+/// `GET /push/key`: the `applicationServerKey` to subscribe with.
+pub async fn push_key(push: Option<Extension<WebPush>>) -> Result<impl IntoResponse, ApiError> {
+    let push = enabled(push)?;
+    Ok(Json(json!({ "public_key": push.public_key() })))
+}
+
+/// `POST /push/subscriptions` with a `PushSubscription`'s JSON.
+pub async fn subscribe_push(
+    ValidatedJson(subscription): ValidatedJson<SubscriptionJson>,
+    push: Option<Extension<WebPush>>,
+) -> Result<StatusCode, ApiError> {
+    let push = enabled(push)?;
+    match push.subscribe(&subscription) {
+        Ok(()) => Ok(StatusCode::CREATED),
+        Err(SubscribeError::Invalid) => Err(ApiError::BadRequest(tr(
+            "A push subscription needs an https endpoint, a p256dh and an auth key",
+        ))),
+        Err(SubscribeError::UnknownService) => Err(ApiError::BadRequest(trf(
+            "The push endpoint {} is not at a known push service",
+            &[&subscription.endpoint],
+        ))),
+        Err(SubscribeError::Full) => Err(ApiError::Conflict(trf(
+            "There are {} push subscriptions already",
+            &[&MAX_SUBSCRIPTIONS],
+        ))),
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct Unsubscribe {
+    endpoint: String,
+}
+
+/// `DELETE /push/subscriptions` with the `endpoint` to stop pushing to.
+pub async fn unsubscribe_push(
+    ValidatedJson(payload): ValidatedJson<Unsubscribe>,
+    push: Option<Extension<WebPush>>,
+) -> Result<StatusCode, ApiError> {
+    let push = enabled(push)?;
+    if push.unsubscribe(&payload.endpoint) {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ApiError::NotFound(tr("There is no such push subscription")))
+    }
+}
@@ -0,0 +1,211 @@
+use axum::{
+    async_trait,
+    extract::{FromRequest, Query, RequestParts},
+};
+use serde::Deserialize;
+
+use crate::repositories::{date::Date, todo::TodoFilter};
+
+use super::{error::ApiError, i18n::trf};
+
+#[derive(Debug, Deserialize)]
+struct SearchQuery {
+    q: Option<String>,
+    ids: Option<String>,
+}
+
+/// Most ids a single `?ids=` lookup may ask for.
+const MAX_IDS: usize = 100;
+
+/// `?q=` search expression, e.g. `is:open label:work due<2024-06-01
+/// groceries`, and/or an `?ids=1,5,9` lookup that returns exactly those
+/// todos in that order.
+///
+/// Terms are separated by whitespace and all of them must match:
+/// `is:open` / `is:done` filter on completion, `label:NAME` requires a
+/// label, `due<DAY`, `due>DAY` and `due=DAY` compare the due date and bare
+/// words are matched against the text. Double quotes keep a phrase or a
+/// label name with spaces together (`label:"next week"`).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Search(pub TodoFilter);
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for Search {
+    type Rejection = ApiError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let Ok(Query(query)) = Query::<SearchQuery>::from_request(req).await else {
+            return Ok(Search::default());
+        };
+
+        let mut filter = match query.q {
+            Some(q) => parse(&q)?,
+            None => TodoFilter::default(),
+        };
+        if let Some(ids) = query.ids {
+            filter.ids = Some(parse_ids(&ids)?);
+        }
+
+        Ok(Search(filter))
+    }
+}
+
+fn parse_ids(ids: &str) -> Result<Vec<i64>, ApiError> {
+    let mut parsed: Vec<i64> = Vec::new();
+    for id in ids.split(',').map(str::trim).filter(|id| !id.is_empty()) {
+        let id = id
+            .parse()
+            .map_err(|_| ApiError::BadRequest(trf("Invalid id: [{}]", &[&id])))?;
+        if !parsed.contains(&id) {
+            parsed.push(id);
+        }
+    }
+    if parsed.len() > MAX_IDS {
+        return Err(ApiError::BadRequest(trf(
+            "Too many ids, at most {} are allowed",
+            &[&MAX_IDS],
+        )));
+    }
+    Ok(parsed)
+}
+
+fn parse(q: &str) -> Result<TodoFilter, ApiError> {
+    let mut filter = TodoFilter::default();
+    for term in tokenize(q) {
+        match term.split_once(':') {
+            Some(("is", "open")) => filter.completed = Some(false),
+            Some(("is", "done" | "completed")) => filter.completed = Some(true),
+            Some(("label", name)) if !name.is_empty() => filter.labels.push(name.to_string()),
+            Some(_) => {
+                return Err(ApiError::BadRequest(trf(
+                    "Unknown search term: [{}]",
+                    &[&term],
+                )))
+            }
+            None if term.contains(['<', '>', '=']) => parse_due(&mut filter, &term)?,
+            None => filter.text.push(term),
+        }
+    }
+
+    Ok(filter)
+}
+
+/// Applies a `due<DAY`, `due>DAY` or `due=DAY` term to `filter`.
+fn parse_due(filter: &mut TodoFilter, term: &str) -> Result<(), ApiError> {
+    let Some((op, day)) = term
+        .strip_prefix("due")
+        .and_then(|rest| rest.split_at_checked(1))
+    else {
+        return Err(ApiError::BadRequest(trf(
+            "Unknown search term: [{}]",
+            &[&term],
+        )));
+    };
+    let day: Date = day
+        .parse()
+        .map_err(|_| ApiError::BadRequest(trf("Invalid date: [{}]", &[&day])))?;
+    match op {
+        "<" => filter.due_before = Some(day),
+        ">" => filter.due_after = Some(day),
+        "=" => {
+            filter.due_before = Some(Date::from_days(day.days() + 1));
+            filter.due_after = Some(Date::from_days(day.days() - 1));
+        }
+        _ => {
+            return Err(ApiError::BadRequest(trf(
+                "Unknown search term: [{}]",
+                &[&term],
+            )))
+        }
+    }
+    Ok(())
+}
+
+/// Splits on whitespace outside double quotes and drops the quotes.
+fn tokenize(q: &str) -> Vec<String> {
+    let mut terms = Vec::new();
+    let mut term = String::new();
+    let mut quoted = false;
+    for c in q.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !term.is_empty() {
+                    terms.push(std::mem::take(&mut term));
+                }
+            }
+            c => term.push(c),
+        }
+    }
+    if !term.is_empty() {
+        terms.push(term);
+    }
+    terms
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_search_expressions() {
+        let filter = parse(r#"is:open label:work label:"next week" buy  "oat milk""#).unwrap();
+        assert_eq!(
+            filter,
+            TodoFilter {
+                completed: Some(false),
+                labels: vec!["work".to_string(), "next week".to_string()],
+                text: vec!["buy".to_string(), "oat milk".to_string()],
+                ids: None,
+                due_before: None,
+                due_after: None,
+            }
+        );
+        assert_eq!(parse("is:done").unwrap().completed, Some(true));
+        assert_eq!(parse("  ").unwrap(), TodoFilter::default());
+    }
+
+    #[test]
+    fn parses_id_lists() {
+        assert_eq!(parse_ids("9, 1,5,1").unwrap(), vec![9, 1, 5]);
+        assert!(matches!(parse_ids("1,x"), Err(ApiError::BadRequest(_))));
+        let too_many: Vec<String> = (0..=MAX_IDS).map(|id| id.to_string()).collect();
+        assert!(parse_ids(&too_many.join(",")).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_terms() {
+        assert!(matches!(parse("is:later"), Err(ApiError::BadRequest(_))));
+        assert!(matches!(parse("label:"), Err(ApiError::BadRequest(_))));
+        assert!(matches!(parse("due<June"), Err(ApiError::BadRequest(_))));
+        assert!(matches!(
+            parse("due<=2024-06-01"),
+            Err(ApiError::BadRequest(_))
+        ));
+        assert!(matches!(
+            parse("size>2024-06-01"),
+            Err(ApiError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn parses_due_dates() {
+        let day = |day: &str| day.parse::<Date>().ok();
+        assert_eq!(
+            parse("due<2024-06-01 due>2024-05-01").unwrap(),
+            TodoFilter {
+                due_before: day("2024-06-01"),
+                due_after: day("2024-05-01"),
+                ..TodoFilter::default()
+            }
+        );
+        assert_eq!(
+            parse("due=2024-03-01").unwrap(),
+            TodoFilter {
+                due_before: day("2024-03-02"),
+                due_after: day("2024-02-29"),
+                ..TodoFilter::default()
+            }
+        );
+    }
+}
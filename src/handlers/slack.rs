@@ -0,0 +1,258 @@
+//! `POST /integrations/slack/command`: the `/todo` slash command of a Slack
+//! app, `/todo add Buy milk` and `/todo list`, answered with Block Kit
+//! messages.
+
+use std::{
+    env,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use axum::{
+    async_trait,
+    body::Bytes,
+    extract::{Extension, FromRequest, RequestParts},
+    BoxError, Json,
+};
+use ring::hmac;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use validator::Validate;
+
+use crate::repositories::{
+    id::IdFormat,
+    todo::{CreateTodo, Todo, TodoFilter, TodoRepository},
+};
+
+use super::{
+    error::ApiError,
+    i18n::{tr, trf},
+    unhex,
+};
+
+/// Open todos `/todo list` shows; Slack allows 50 blocks a message.
+const LIST_LIMIT: usize = 20;
+
+/// How far a request's timestamp may be off, as Slack recommends.
+const MAX_SKEW: Duration = Duration::from_secs(5 * 60);
+
+/// The secret Slack signs requests with, from `SLACK_SIGNING_SECRET`.
+/// Without it the endpoint answers `404`.
+#[derive(Debug, Clone)]
+pub struct SlackSecret(hmac::Key);
+
+impl SlackSecret {
+    pub fn new(secret: &str) -> Self {
+        Self(hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes()))
+    }
+
+    pub fn from_env() -> Option<Self> {
+        env::var("SLACK_SIGNING_SECRET")
+            .ok()
+            .filter(|secret| !secret.is_empty())
+            .map(|secret| Self::new(&secret))
+    }
+
+    /// Whether `signature`, as in `X-Slack-Signature`, signs `body` sent at
+    /// `timestamp`, recently.
+    fn verify(&self, timestamp: &str, signature: &str, body: &[u8], now: SystemTime) -> bool {
+        let Ok(sent) = timestamp.parse::<u64>() else {
+            return false;
+        };
+        let sent = SystemTime::UNIX_EPOCH + Duration::from_secs(sent);
+        let skew = now
+            .duration_since(sent)
+            .or_else(|_| sent.duration_since(now))
+            .unwrap_or_default();
+        let Some(signature) = signature.strip_prefix("v0=").and_then(unhex) else {
+            return false;
+        };
+        let mut message = format!("v0:{}:", timestamp).into_bytes();
+        message.extend_from_slice(body);
+        skew <= MAX_SKEW && hmac::verify(&self.0, &message, &signature).is_ok()
+    }
+}
+
+/// The fields of a slash command request the command reads.
+#[derive(Debug, Default, Deserialize)]
+pub struct SlackCommand {
+    #[serde(default)]
+    text: String,
+    #[serde(default)]
+    user_id: String,
+}
+
+/// Extractor for slash commands signed with the [`SlackSecret`].
+#[async_trait]
+impl<B> FromRequest<B> for SlackCommand
+where
+    B: http_body::Body + Send,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let secret = req
+            .extensions()
+            .and_then(|extensions| extensions.get::<SlackSecret>())
+            .cloned()
+            .ok_or_else(|| ApiError::NotFound(tr("Slack commands need a SLACK_SIGNING_SECRET")))?;
+        let (timestamp, signature) = {
+            let header = |name| {
+                req.headers()
+                    .and_then(|headers| headers.get(name))
+                    .and_then(|value| value.to_str().ok())
+                    .unwrap_or_default()
+                    .to_string()
+            };
+            (
+                header("x-slack-request-timestamp"),
+                header("x-slack-signature"),
+            )
+        };
+        let body = Bytes::from_request(req)
+            .await
+            .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+        if !secret.verify(&timestamp, &signature, &body, SystemTime::now()) {
+            return Err(ApiError::Unauthorized(tr("Invalid webhook signature")));
+        }
+        serde_urlencoded::from_bytes(&body)
+            .map_err(|e| ApiError::BadRequest(trf("Invalid form: [{}]", &[&e])))
+    }
+}
+
+/// `text` as Slack shows it, not read as a mention or link.
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn section(text: &str) -> Value {
+    json!({ "type": "section", "text": { "type": "mrkdwn", "text": text } })
+}
+
+/// A reply only the user who ran the command sees.
+fn ephemeral(text: &str, blocks: Vec<Value>) -> Value {
+    json!({ "response_type": "ephemeral", "text": text, "blocks": blocks })
+}
+
+fn usage() -> Value {
+    let text = "`/todo add <text>` adds a todo, `/todo list` shows the open ones.";
+    ephemeral(text, vec![section(text)])
+}
+
+fn added(todo: &Todo, user_id: &str) -> Value {
+    let text = format!("<@{}> added *{}*", user_id, escape(todo.text()));
+    json!({
+        "response_type": "in_channel",
+        "text": text,
+        "blocks": [
+            section(&text),
+            {
+                "type": "context",
+                "elements": [{ "type": "mrkdwn", "text": format!("Todo #{}", todo.id()) }],
+            },
+        ],
+    })
+}
+
+fn list(todos: &[Todo], open: usize) -> Value {
+    if todos.is_empty() {
+        return ephemeral("Nothing to do.", vec![section("Nothing to do. :tada:")]);
+    }
+    let header = format!("{} open todos", open);
+    let lines: Vec<_> = todos
+        .iter()
+        .map(|todo| format!("• {} `#{}`", escape(todo.text()), todo.id()))
+        .collect();
+    let mut blocks = vec![
+        json!({ "type": "header", "text": { "type": "plain_text", "text": header } }),
+        section(&lines.join("\n")),
+    ];
+    if open > todos.len() {
+        blocks.push(json!({
+            "type": "context",
+            "elements": [{
+                "type": "mrkdwn",
+                "text": format!("and {} more", open - todos.len()),
+            }],
+        }));
+    }
+    ephemeral(&header, blocks)
+}
+
+/// `POST /integrations/slack/command`. Slack shows whatever answers `200`,
+/// so a todo it can't add is explained in the reply instead.
+pub async fn slack_command<T: TodoRepository>(
+    command: SlackCommand,
+    Extension(repository): Extension<Arc<T>>,
+    format: IdFormat,
+) -> Result<Json<Value>, ApiError> {
+    let text = command.text.trim();
+    let (subcommand, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    let reply = match subcommand.to_lowercase().as_str() {
+        "add" => {
+            let payload = CreateTodo::new(rest.trim().to_string());
+            if payload.validate().is_err() {
+                let text = "A todo needs a text of at most 100 characters.";
+                return Ok(Json(ephemeral(text, vec![section(text)])));
+            }
+            let todo = repository.create(payload.with_format(format)).await?;
+            added(&todo, &command.user_id)
+        }
+        "list" => {
+            let filter = TodoFilter {
+                completed: Some(false),
+                ..TodoFilter::default()
+            };
+            let mut todos = repository.all(&filter).await?;
+            let open = todos.len();
+            todos.truncate(LIST_LIMIT);
+            list(&todos, open)
+        }
+        _ => usage(),
+    };
+
+    Ok(Json(reply))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::handlers::hex;
+
+    fn sign(secret: &str, timestamp: &str, body: &str) -> String {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+        let tag = hmac::sign(&key, format!("v0:{}:{}", timestamp, body).as_bytes());
+        format!("v0={}", hex(tag.as_ref()))
+    }
+
+    #[test]
+    fn verifies_the_signature() {
+        let secret = SlackSecret::new("8f742231b10e8888abcd99yyyzzz85a5");
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_531_420_618);
+        let body = "command=%2Ftodo&text=add+Buy+milk";
+        let signature = sign("8f742231b10e8888abcd99yyyzzz85a5", "1531420618", body);
+        assert!(secret.verify("1531420618", &signature, body.as_bytes(), now));
+        assert!(!secret.verify("1531420618", &signature, b"text=list", now));
+        assert!(!secret.verify("1531420618", "v0=zz", body.as_bytes(), now));
+        let later = now + Duration::from_secs(6 * 60);
+        assert!(!secret.verify("1531420618", &signature, body.as_bytes(), later));
+    }
+
+    #[test]
+    fn lists_the_first_open_todos() {
+        let todos = vec![Todo::new(1, "milk & <eggs>".to_string())];
+        let reply = list(&todos, 21);
+        assert_eq!(reply["response_type"], "ephemeral");
+        assert_eq!(reply["blocks"][0]["text"]["text"], "21 open todos");
+        assert_eq!(
+            reply["blocks"][1]["text"]["text"],
+            "• milk &amp; &lt;eggs&gt; `#1`"
+        );
+        assert_eq!(reply["blocks"][2]["elements"][0]["text"], "and 20 more");
+        assert_eq!(list(&[], 0)["text"], "Nothing to do.");
+    }
+}
@@ -0,0 +1,28 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use axum::{extract::Extension, Json};
+
+use crate::repositories::{
+    cache::{CacheMetrics, CacheStats},
+    instrument::{MethodStats, QueryMetrics},
+};
+
+use super::{error::ApiError, i18n::tr};
+
+/// `GET /cache/stats`: hit and miss counts of the read cache since startup.
+/// Answers 404 when no `REDIS_URL` is configured.
+pub async fn cache_stats(
+    metrics: Option<Extension<Arc<CacheMetrics>>>,
+) -> Result<Json<CacheStats>, ApiError> {
+    let Extension(metrics) =
+        metrics.ok_or_else(|| ApiError::NotFound(tr("Caching is not enabled")))?;
+    Ok(Json(metrics.stats()))
+}
+
+/// `GET /repository/stats`: calls, errors and time spent per repository
+/// method since startup.
+pub async fn repository_stats(
+    Extension(metrics): Extension<Arc<QueryMetrics>>,
+) -> Json<BTreeMap<&'static str, MethodStats>> {
+    Json(metrics.stats())
+}
@@ -0,0 +1,378 @@
+//! `POST /integrations/telegram`: a Telegram bot to add, list and complete
+//! todos from a chat, through the bot's webhook (`setWebhook` with this
+//! URL and `secret_token`).
+//!
+//! Replies go back in the webhook's answer, so the server needs no bot
+//! token and makes no calls to Telegram. Only linked chats are served: a
+//! chat links itself by sending `/link <code>` with a code from
+//! `POST /admin/telegram/codes`.
+
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    async_trait,
+    body::Bytes,
+    extract::{Extension, FromRequest, RequestParts},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    BoxError, Json,
+};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use validator::Validate;
+
+use crate::repositories::{
+    id::{IdFormat, Key},
+    todo::{CreateTodo, Todo, TodoFilter, TodoRepository, UpdateTodo},
+};
+
+use super::{
+    admin::Admin,
+    error::ApiError,
+    i18n::{tr, trf},
+};
+
+/// How long a link code can be used.
+const CODE_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Wrong codes sent before every pending one is dropped, so they can't be
+/// guessed.
+const MAX_WRONG_CODES: u32 = 5;
+
+/// Open todos `/list` shows.
+const LIST_LIMIT: usize = 20;
+
+#[derive(Debug, Default)]
+struct Links {
+    chats: HashSet<i64>,
+    /// Codes not used yet, with when they were made.
+    codes: HashMap<String, Instant>,
+    wrong_codes: u32,
+}
+
+/// The bot's state: the secret Telegram sends with every update, from
+/// `TELEGRAM_WEBHOOK_SECRET`, and the linked chats. Chats linked with a
+/// code are kept until the next restart; `TELEGRAM_CHATS` lists the ones
+/// linked for good. Without the secret the endpoints answer `404`.
+#[derive(Debug, Clone)]
+pub struct TelegramBot {
+    secret: String,
+    links: Arc<Mutex<Links>>,
+}
+
+impl TelegramBot {
+    pub fn new(secret: &str, chats: impl IntoIterator<Item = i64>) -> Self {
+        let links = Links {
+            chats: chats.into_iter().collect(),
+            ..Links::default()
+        };
+        Self {
+            secret: secret.to_string(),
+            links: Arc::new(Mutex::new(links)),
+        }
+    }
+
+    pub fn from_env() -> Option<Self> {
+        let secret = env::var("TELEGRAM_WEBHOOK_SECRET")
+            .ok()
+            .filter(|secret| !secret.is_empty())?;
+        let chats = env::var("TELEGRAM_CHATS").unwrap_or_default();
+        let chats: Vec<i64> = chats
+            .split(',')
+            .map(str::trim)
+            .filter(|chat| !chat.is_empty())
+            .map(|chat| {
+                chat.parse()
+                    .unwrap_or_else(|_| panic!("invalid [TELEGRAM_CHATS]: {}", chat))
+            })
+            .collect();
+        Some(Self::new(&secret, chats))
+    }
+
+    fn links(&self) -> std::sync::MutexGuard<'_, Links> {
+        self.links.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// A new single-use code linking the chat it is sent from.
+    fn issue_code(&self) -> anyhow::Result<String> {
+        let mut bytes = [0u8; 4];
+        SystemRandom::new()
+            .fill(&mut bytes)
+            .map_err(|_| anyhow::anyhow!("no randomness for a link code"))?;
+        let code = format!("{:08}", u32::from_be_bytes(bytes) % 100_000_000);
+        let mut links = self.links();
+        links.codes.retain(|_, made| made.elapsed() < CODE_TTL);
+        links.codes.insert(code.clone(), Instant::now());
+        Ok(code)
+    }
+
+    /// Links `chat` if `code` is a pending one.
+    fn link(&self, chat: i64, code: &str) -> bool {
+        let mut links = self.links();
+        match links.codes.remove(code) {
+            Some(made) if made.elapsed() < CODE_TTL => {
+                links.chats.insert(chat);
+                links.wrong_codes = 0;
+                tracing::info!(
+                    "linked telegram chat {}; add it to TELEGRAM_CHATS to keep it",
+                    chat
+                );
+                true
+            }
+            _ => {
+                links.wrong_codes += 1;
+                if links.wrong_codes >= MAX_WRONG_CODES {
+                    tracing::warn!("dropped the telegram link codes after wrong guesses");
+                    links.codes.clear();
+                    links.wrong_codes = 0;
+                }
+                false
+            }
+        }
+    }
+
+    fn is_linked(&self, chat: i64) -> bool {
+        self.links().chats.contains(&chat)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Update {
+    message: Option<Message>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Message {
+    chat: Chat,
+    #[serde(default)]
+    text: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Chat {
+    id: i64,
+}
+
+/// Extractor for updates sent with the [`TelegramBot`]'s secret.
+#[async_trait]
+impl<B> FromRequest<B> for Update
+where
+    B: http_body::Body + Send,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let bot = bot(req)?;
+        let given = req
+            .headers()
+            .and_then(|headers| headers.get("x-telegram-bot-api-secret-token"))
+            .map(|value| value.as_bytes().to_vec())
+            .unwrap_or_default();
+        ring::constant_time::verify_slices_are_equal(&given, bot.secret.as_bytes())
+            .map_err(|_| ApiError::Unauthorized(tr("Invalid webhook signature")))?;
+        let body = Bytes::from_request(req)
+            .await
+            .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+        serde_json::from_slice(&body)
+            .map_err(|e| ApiError::BadRequest(trf("Json parse error: [{}]", &[&e])))
+    }
+}
+
+fn bot<B>(req: &RequestParts<B>) -> Result<TelegramBot, ApiError> {
+    req.extensions()
+        .and_then(|extensions| extensions.get::<TelegramBot>())
+        .cloned()
+        .ok_or_else(|| ApiError::NotFound(tr("The Telegram bot needs a TELEGRAM_WEBHOOK_SECRET")))
+}
+
+/// What the bot says back, sent as the `sendMessage` the webhook answers
+/// with.
+#[derive(Debug, Serialize)]
+struct Reply {
+    method: &'static str,
+    chat_id: i64,
+    text: String,
+}
+
+const HELP: &str = "/add <text> adds a todo\n/list shows the open ones\n/done <id> completes one";
+
+async fn answer<T: TodoRepository>(
+    repository: &T,
+    format: IdFormat,
+    bot: &TelegramBot,
+    chat: i64,
+    text: &str,
+) -> anyhow::Result<String> {
+    let (command, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    // in groups commands are sent as /add@my_todo_bot
+    let command = command.split('@').next().unwrap_or_default();
+    let rest = rest.trim();
+    if command == "/link" {
+        return Ok(if bot.link(chat, rest) {
+            format!("Linked. {}", HELP)
+        } else {
+            "That code is wrong or expired.".to_string()
+        });
+    }
+    if !bot.is_linked(chat) {
+        return Ok("Send /link with a code from POST /admin/telegram/codes first.".to_string());
+    }
+
+    Ok(match command {
+        "/add" => {
+            let payload = CreateTodo::new(rest.to_string());
+            if payload.validate().is_err() {
+                return Ok("A todo needs a text of at most 100 characters.".to_string());
+            }
+            let todo = repository.create(payload.with_format(format)).await?;
+            format!("Added #{}: {}", todo.key(format), todo.text())
+        }
+        "/list" => {
+            let filter = TodoFilter {
+                completed: Some(false),
+                ..TodoFilter::default()
+            };
+            let todos = repository.all(&filter).await?;
+            list(&todos, format)
+        }
+        "/done" => {
+            let key = Key::parse(rest.trim_start_matches('#'), format);
+            let id = match key {
+                Some(key) => repository.resolve(&key).await.ok(),
+                None => None,
+            };
+            let Some(id) = id else {
+                return Ok(format!("There is no todo {}.", rest));
+            };
+            let todo = repository
+                .update(id, UpdateTodo::new(None, Some(true)))
+                .await?;
+            format!("Done: {}", todo.text())
+        }
+        _ => HELP.to_string(),
+    })
+}
+
+fn list(todos: &[Todo], format: IdFormat) -> String {
+    if todos.is_empty() {
+        return "Nothing to do.".to_string();
+    }
+    let mut text = format!("{} open todos:", todos.len());
+    for todo in todos.iter().take(LIST_LIMIT) {
+        text.push_str(&format!("\n#{} {}", todo.key(format), todo.text()));
+    }
+    if todos.len() > LIST_LIMIT {
+        text.push_str(&format!("\nand {} more", todos.len() - LIST_LIMIT));
+    }
+    text
+}
+
+/// `POST /integrations/telegram` with an update. Updates other than text
+/// messages are acknowledged and ignored.
+pub async fn telegram_update<T: TodoRepository>(
+    update: Update,
+    Extension(repository): Extension<Arc<T>>,
+    Extension(bot): Extension<TelegramBot>,
+    format: IdFormat,
+) -> Result<Response, ApiError> {
+    let Some(message) = update.message.filter(|message| !message.text.is_empty()) else {
+        return Ok(StatusCode::OK.into_response());
+    };
+    let chat_id = message.chat.id;
+    let text = answer(&*repository, format, &bot, chat_id, message.text.trim()).await?;
+
+    Ok(Json(Reply {
+        method: "sendMessage",
+        chat_id,
+        text,
+    })
+    .into_response())
+}
+
+/// `POST /admin/telegram/codes`: a code a chat links itself with by
+/// sending `/link <code>` to the bot within ten minutes.
+pub async fn issue_telegram_code(
+    _: Admin,
+    bot: Option<Extension<TelegramBot>>,
+) -> Result<impl IntoResponse, ApiError> {
+    let Extension(bot) = bot.ok_or_else(|| {
+        ApiError::NotFound(tr("The Telegram bot needs a TELEGRAM_WEBHOOK_SECRET"))
+    })?;
+    let code = bot.issue_code()?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({ "code": code, "expires_in": CODE_TTL.as_secs() })),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::repositories::todo::TodoRepositoryForMemory;
+
+    #[test]
+    fn links_a_chat_once_per_code() {
+        let bot = TelegramBot::new("s3cret", [7]);
+        assert!(bot.is_linked(7));
+        assert!(!bot.is_linked(42));
+
+        let code = bot.issue_code().unwrap();
+        assert_eq!(code.len(), 8);
+        assert!(bot.link(42, &code));
+        assert!(bot.is_linked(42));
+        assert!(!bot.link(43, &code), "a code links one chat");
+    }
+
+    #[test]
+    fn drops_the_codes_after_wrong_guesses() {
+        let bot = TelegramBot::new("s3cret", []);
+        let code = bot.issue_code().unwrap();
+        for guess in 0..MAX_WRONG_CODES {
+            assert!(!bot.link(42, &format!("x{}", guess)));
+        }
+        assert!(!bot.link(42, &code));
+    }
+
+    #[test]
+    fn lists_the_first_open_todos() {
+        let todos: Vec<_> = (1..=22)
+            .map(|id| Todo::new(id, format!("todo {}", id)))
+            .collect();
+        let text = list(&todos, IdFormat::Serial);
+        assert!(text.starts_with("22 open todos:\n#1 todo 1\n"));
+        assert!(text.ends_with("\n#20 todo 20\nand 2 more"));
+        assert_eq!(list(&[], IdFormat::Serial), "Nothing to do.");
+    }
+
+    #[tokio::test]
+    async fn shows_the_ids_done_takes() {
+        let repository = TodoRepositoryForMemory::new();
+        let bot = TelegramBot::new("s3cret", [7]);
+        let format = IdFormat::Uuid;
+
+        let added = answer(&repository, format, &bot, 7, "/add buy milk")
+            .await
+            .unwrap();
+        let todo = &repository.all(&TodoFilter::default()).await.unwrap()[0];
+        let key = todo.key(format);
+        assert!(key.contains('-'));
+        assert_eq!(added, format!("Added #{}: buy milk", key));
+        let listed = answer(&repository, format, &bot, 7, "/list").await.unwrap();
+        assert_eq!(listed, format!("1 open todos:\n#{} buy milk", key));
+
+        let done = format!("/done #{}", key);
+        let done = answer(&repository, format, &bot, 7, &done).await.unwrap();
+        assert_eq!(done, "Done: buy milk");
+        let listed = answer(&repository, format, &bot, 7, "/list").await.unwrap();
+        assert_eq!(listed, "Nothing to do.");
+    }
+}
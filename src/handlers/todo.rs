@@ -1,69 +1,323 @@
-use std::sync::Arc;
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    sync::Arc,
+    time::{Instant, SystemTime},
+};
 
 use axum::{
-    extract::{Extension, Path},
-    http::StatusCode,
-    response::IntoResponse,
+    body::{self, Body},
+    extract::{Extension, RawQuery},
+    http::{header, HeaderValue, StatusCode},
+    response::{Headers, IntoResponse, Response},
     Json,
 };
 
-use crate::repositories::todo::{CreateTodo, TodoRepository, UpdateTodo};
+use futures_util::{Stream, StreamExt};
+
+use crate::repositories::{
+    id::{IdFormat, Key},
+    todo::{CreateTodo, Todo, TodoFilter, TodoRepository, TodoWithLabels, UpdateTodo},
+};
+
+use super::{
+    conditional::{not_modified, set_last_modified, IfModifiedSince},
+    error::ApiError,
+    fields::Fields,
+    i18n::tr,
+    include::Include,
+    jsonapi::{Document, PrimaryData, Representation, Resource},
+    links::LinkBuilder,
+    search::Search,
+    ValidatedJson,
+};
 
-use super::ValidatedJson;
+pub const NDJSON: &str = "application/x-ndjson";
 
 pub async fn create_todo<T: TodoRepository>(
     ValidatedJson(payload): ValidatedJson<CreateTodo>,
     Extension(repository): Extension<Arc<T>>,
-) -> Result<impl IntoResponse, StatusCode> {
-    let todo = repository
-        .create(payload)
-        .await
-        .or(Err(StatusCode::NOT_FOUND))?;
+    links: LinkBuilder,
+    format: IdFormat,
+) -> Result<impl IntoResponse, ApiError> {
+    let todo = repository.create(payload.with_format(format)).await?;
 
-    Ok((StatusCode::CREATED, Json(todo)))
+    Ok((StatusCode::CREATED, Json(links.linked_todo(todo))))
 }
 
 pub async fn find_todo<T: TodoRepository>(
-    Path(id): Path<i32>,
+    key: Key,
     Extension(repository): Extension<Arc<T>>,
-) -> Result<impl IntoResponse, StatusCode> {
-    let todo = repository.find(id).await.or(Err(StatusCode::NOT_FOUND))?;
+    links: LinkBuilder,
+    fields: Fields,
+    include: Include,
+    representation: Representation,
+    if_modified_since: IfModifiedSince,
+) -> Result<Response, ApiError> {
+    let id = repository.resolve(&key).await?;
+    let last_modified = repository.last_modified(id).await?;
+    if if_modified_since.is_fresh(last_modified) {
+        return Ok(not_modified(last_modified));
+    }
+
+    let mut res = match (representation, include.labels) {
+        (Representation::Json, true) => {
+            let todo = repository.find_with_labels(id).await?;
+            Json(fields.apply(links.linked_todo(todo))).into_response()
+        }
+        (Representation::Json, false) => {
+            let todo = repository.find(id).await?;
+            Json(fields.apply(links.linked_todo(todo))).into_response()
+        }
+        (Representation::JsonApi, true) => {
+            let todo = repository.find_with_labels(id).await?;
+            let included = todo.labels.iter().map(Resource::label).collect();
+            let data = Resource::todo(&links, &todo.todo, Some(&todo.labels));
+            Document::new(PrimaryData::One(data), included).into_response()
+        }
+        (Representation::JsonApi, false) => {
+            let todo = repository.find(id).await?;
+            let data = Resource::todo(&links, &todo, None);
+            Document::new(PrimaryData::One(data), vec![]).into_response()
+        }
+    };
+    set_last_modified(&mut res, last_modified);
+
+    Ok(res)
+}
 
-    Ok((StatusCode::OK, Json(todo)))
+/// [`TodoRepository::all_with_labels`], logging how long the one query took
+/// for how many todos.
+async fn all_with_labels<T: TodoRepository>(
+    repository: &T,
+    filter: &TodoFilter,
+) -> anyhow::Result<Vec<TodoWithLabels>> {
+    let started = Instant::now();
+    let todos = repository.all_with_labels(filter).await?;
+    tracing::debug!(
+        "loaded {} todos with labels in {:?}",
+        todos.len(),
+        started.elapsed()
+    );
+    Ok(todos)
 }
 
+/// Todos whose labels [`stream_with_labels`] looks up together.
+const STREAM_BATCH: usize = 100;
+
+/// The todos `filter` matches with their labels, in id order: every todo
+/// streams past and those of each [`STREAM_BATCH`] are looked up together.
+pub fn stream_with_labels<T: TodoRepository>(
+    repository: Arc<T>,
+    filter: TodoFilter,
+) -> impl Stream<Item = anyhow::Result<Vec<TodoWithLabels>>> {
+    repository
+        .stream_all()
+        .chunks(STREAM_BATCH)
+        .then(move |chunk| {
+            let repository = repository.clone();
+            let mut filter = filter.clone();
+            async move {
+                let mut ids = Vec::with_capacity(chunk.len());
+                for todo in chunk {
+                    ids.push(todo?.id());
+                }
+                if let Some(wanted) = &filter.ids {
+                    ids.retain(|id| wanted.contains(id));
+                }
+                if ids.is_empty() {
+                    return Ok(vec![]);
+                }
+                filter.ids = Some(ids);
+                repository.all_with_labels(&filter).await
+            }
+        })
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn all_todo<T: TodoRepository>(
     Extension(repository): Extension<Arc<T>>,
-) -> Result<impl IntoResponse, StatusCode> {
-    let todos = repository.all().await.or(Err(StatusCode::NOT_FOUND))?;
+    Search(filter): Search,
+    RawQuery(query): RawQuery,
+    links: LinkBuilder,
+    fields: Fields,
+    include: Include,
+    representation: Representation,
+    if_modified_since: IfModifiedSince,
+) -> Result<Response, ApiError> {
+    let last_modified = repository.collection_last_modified().await?;
+    if if_modified_since.is_fresh(last_modified) {
+        return Ok(not_modified(last_modified));
+    }
+
+    let header = Headers(vec![(header::LINK, links.todos_header())]);
+    let query = query.as_deref();
+    let (etag, body) = match (representation, include.labels) {
+        (Representation::Json, true) => {
+            let todos = all_with_labels(&*repository, &filter).await?;
+            let etag = collection_etag(query, representation, todos.iter().map(|todo| &todo.todo));
+            let todos: Vec<_> = todos
+                .into_iter()
+                .map(|todo| links.linked_todo(todo))
+                .collect();
+            (etag, Json(fields.apply(todos)).into_response())
+        }
+        (Representation::Json, false) => {
+            let todos = repository.all(&filter).await?;
+            let etag = collection_etag(query, representation, todos.iter());
+            let todos: Vec<_> = todos
+                .into_iter()
+                .map(|todo| links.linked_todo(todo))
+                .collect();
+            (etag, Json(fields.apply(todos)).into_response())
+        }
+        (Representation::JsonApi, true) => {
+            let todos = all_with_labels(&*repository, &filter).await?;
+            let etag = collection_etag(query, representation, todos.iter().map(|todo| &todo.todo));
+            let included = todos
+                .iter()
+                .flat_map(|todo| todo.labels.iter().map(Resource::label))
+                .collect();
+            let data = todos
+                .iter()
+                .map(|todo| Resource::todo(&links, &todo.todo, Some(&todo.labels)))
+                .collect();
+            let body = Document::new(PrimaryData::Many(data), included).into_response();
+            (etag, body)
+        }
+        (Representation::JsonApi, false) => {
+            let todos = repository.all(&filter).await?;
+            let etag = collection_etag(query, representation, todos.iter());
+            let data = todos
+                .iter()
+                .map(|todo| Resource::todo(&links, todo, None))
+                .collect();
+            let body = Document::new(PrimaryData::Many(data), vec![]).into_response();
+            (etag, body)
+        }
+    };
+
+    let count = repository.count(&filter).await?;
+    let mut res = (StatusCode::OK, header, body).into_response();
+    set_collection_headers(&mut res, count, last_modified, etag);
+
+    Ok(res)
+}
+
+/// `HEAD /todos`: the collection's validators and size without the body, so
+/// clients can cheaply check for changes.
+pub async fn head_todos<T: TodoRepository>(
+    Extension(repository): Extension<Arc<T>>,
+    Search(filter): Search,
+    RawQuery(query): RawQuery,
+    representation: Representation,
+    if_modified_since: IfModifiedSince,
+) -> Result<Response, ApiError> {
+    let last_modified = repository.collection_last_modified().await?;
+    if if_modified_since.is_fresh(last_modified) {
+        return Ok(not_modified(last_modified));
+    }
 
-    Ok((StatusCode::OK, Json(todos)))
+    let todos = repository.all(&filter).await?;
+    let etag = collection_etag(query.as_deref(), representation, todos.iter());
+    let count = repository.count(&filter).await?;
+    let mut res = StatusCode::OK.into_response();
+    set_collection_headers(&mut res, count, last_modified, etag);
+
+    Ok(res)
+}
+
+/// A weak validator for the todos a listing with `query` shows: a hash of
+/// the query, the representation and every listed todo's id and version,
+/// so any edit, addition or removal among them changes it.
+fn collection_etag<'a>(
+    query: Option<&str>,
+    representation: Representation,
+    todos: impl Iterator<Item = &'a Todo>,
+) -> HeaderValue {
+    let mut hasher = DefaultHasher::new();
+    query.hash(&mut hasher);
+    (representation == Representation::JsonApi).hash(&mut hasher);
+    for todo in todos {
+        (todo.id(), todo.version()).hash(&mut hasher);
+    }
+    HeaderValue::from_str(&format!("W/\"{:016x}\"", hasher.finish()))
+        .expect("hex digits are a valid header value")
+}
+
+fn set_collection_headers(
+    res: &mut Response,
+    count: i64,
+    last_modified: SystemTime,
+    etag: HeaderValue,
+) {
+    set_last_modified(res, last_modified);
+    let headers = res.headers_mut();
+    headers.insert("x-total-count", HeaderValue::from(count));
+    headers.insert(header::ETAG, etag);
+}
+
+/// `GET /todos/export.ndjson`: one todo per line, written as rows arrive
+/// so the export never sits in memory as a whole.
+pub async fn export_todos<T: TodoRepository>(Extension(repository): Extension<Arc<T>>) -> Response {
+    let lines = repository.stream_all().map(|todo| {
+        let mut line = serde_json::to_vec(&todo?)?;
+        line.push(b'\n');
+        anyhow::Ok(line)
+    });
+    let mut res = Response::new(body::boxed(Body::wrap_stream(lines)));
+    res.headers_mut()
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static(NDJSON));
+    res
+}
+
+/// Most hits a single `GET /todos/search` returns.
+const SEARCH_LIMIT: i64 = 50;
+
+/// `GET /todos/search?q=`: like listing with `?q=`, but ranked by how well
+/// the text terms match and with the matches highlighted.
+pub async fn search_todos<T: TodoRepository>(
+    Search(filter): Search,
+    Extension(repository): Extension<Arc<T>>,
+) -> Result<impl IntoResponse, ApiError> {
+    if filter.text.is_empty() {
+        return Err(ApiError::BadRequest(tr("Search needs at least one word")));
+    }
+    let hits = repository.search(&filter, SEARCH_LIMIT).await?;
+
+    Ok(Json(hits))
+}
+
+/// `GET /todos/archived`: the todos the archiver moved out of the listing,
+/// most recently archived first.
+pub async fn archived_todos<T: TodoRepository>(
+    Extension(repository): Extension<Arc<T>>,
+) -> Result<impl IntoResponse, ApiError> {
+    let todos = repository.archived().await?;
+
+    Ok(Json(todos))
 }
 
 pub async fn update_todo<T: TodoRepository>(
-    Path(id): Path<i32>,
+    key: Key,
     ValidatedJson(payload): ValidatedJson<UpdateTodo>,
     Extension(repository): Extension<Arc<T>>,
-) -> Result<impl IntoResponse, StatusCode> {
-    // let todo = repository
-    //     .update(id, payload)
-    //     .map_err(|_| StatusCode::NOT_FOUND)?;
-
-    let todo = repository
-        .update(id, payload)
-        .await
-        .or(Err(StatusCode::NOT_FOUND))?;
+    links: LinkBuilder,
+) -> Result<impl IntoResponse, ApiError> {
+    let id = repository.resolve(&key).await?;
+    let todo = repository.update(id, payload).await?;
 
-    Ok((StatusCode::OK, Json(todo)))
+    Ok((StatusCode::OK, Json(links.linked_todo(todo))))
 }
 
 pub async fn delete_todo<T: TodoRepository>(
-    Path(id): Path<i32>,
+    key: Key,
     Extension(repositories): Extension<Arc<T>>,
-) -> StatusCode {
+) -> Result<StatusCode, ApiError> {
+    let id = repositories.resolve(&key).await?;
     repositories
         .delete(id)
         .await
         .map(|_| StatusCode::NO_CONTENT)
-        .unwrap_or(StatusCode::NOT_FOUND)
+        .map_err(ApiError::from)
 }
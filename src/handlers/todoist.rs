@@ -0,0 +1,294 @@
+//! `POST /import/todoist`: moves a Todoist account over, from its Sync API
+//! data or read with an API token.
+//!
+//! Tasks become todos and labels labels. There are no projects here, so a
+//! task's project becomes a label too, but for the inbox. A due date keeps
+//! its day, without the time or recurrence; descriptions are left behind.
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use axum::{
+    body::Bytes,
+    extract::Extension,
+    http::{header, Request, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use hyper::Body;
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+use validator::Validate;
+
+use crate::{
+    client,
+    repositories::{
+        date::Date,
+        id::IdFormat,
+        label::{Label, LabelRepository},
+        todo::TodoRepository,
+    },
+};
+
+use super::{
+    error::ApiError,
+    i18n::{tr, trf},
+    json::{import_export, Export, ExportTodo, EXPORT_FORMAT},
+};
+
+/// Where an API token's data is read, everything in one full sync.
+const SYNC_URL: &str = "https://api.todoist.com/api/v1/sync";
+
+/// `sync_token=*&resource_types=["items","labels","projects"]`.
+const FULL_SYNC: &str =
+    "sync_token=%2A&resource_types=%5B%22items%22%2C%22labels%22%2C%22projects%22%5D";
+
+/// Longest text a todo may have; longer task names are cut.
+const MAX_TEXT: usize = 100;
+
+/// What `POST /import/todoist` takes: `{"token": "..."}`, or the data a
+/// full sync answers with.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum TodoistImport {
+    Token { token: String },
+    Data(TodoistData),
+}
+
+/// The parts of a Todoist full sync an import reads.
+#[derive(Debug, Default, Deserialize)]
+struct TodoistData {
+    #[serde(default)]
+    items: Vec<Item>,
+    #[serde(default)]
+    labels: Vec<Named>,
+    #[serde(default)]
+    projects: Vec<Project>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Item {
+    content: String,
+    #[serde(default)]
+    checked: bool,
+    /// By name.
+    #[serde(default)]
+    labels: Vec<String>,
+    #[serde(default, deserialize_with = "id")]
+    project_id: Option<String>,
+    #[serde(default)]
+    due: Option<Due>,
+    #[serde(default)]
+    is_deleted: bool,
+}
+
+/// When a task is due: `2024-06-01`, or with a time as
+/// `2024-06-01T12:00:00`, with a trailing `Z` when it is in UTC.
+#[derive(Debug, Deserialize)]
+struct Due {
+    date: String,
+}
+
+impl Due {
+    /// The day, or `None` for a date that can't be read.
+    fn day(&self) -> Option<Date> {
+        self.date.get(..10)?.parse().ok()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Named {
+    name: String,
+    #[serde(default)]
+    is_deleted: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct Project {
+    #[serde(deserialize_with = "id")]
+    id: Option<String>,
+    name: String,
+    #[serde(default)]
+    inbox_project: bool,
+    #[serde(default)]
+    is_deleted: bool,
+}
+
+/// Todoist ids, numbers in older exports and strings since.
+fn id<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    Ok(match Value::deserialize(deserializer)? {
+        Value::String(id) => Some(id),
+        Value::Number(id) => Some(id.to_string()),
+        _ => None,
+    })
+}
+
+impl TodoistData {
+    /// The data as an export of this app, labels numbered as they first
+    /// appear.
+    fn into_export(self) -> Export {
+        let mut labels: BTreeMap<String, i64> = BTreeMap::new();
+        let mut label_id = |name: &str| {
+            let next = labels.len() as i64 + 1;
+            *labels.entry(name.to_string()).or_insert(next)
+        };
+        for label in self.labels.iter().filter(|label| !label.is_deleted) {
+            label_id(&label.name);
+        }
+        let projects: BTreeMap<_, _> = self
+            .projects
+            .iter()
+            .filter(|project| !project.is_deleted && !project.inbox_project)
+            .filter_map(|project| Some((project.id.clone()?, project.name.as_str())))
+            .collect();
+
+        let todos = self
+            .items
+            .into_iter()
+            .filter(|item| !item.is_deleted)
+            .enumerate()
+            .map(|(i, item)| {
+                let project = item.project_id.as_ref().and_then(|id| projects.get(id));
+                let mut label_ids = vec![];
+                for name in item
+                    .labels
+                    .iter()
+                    .map(String::as_str)
+                    .chain(project.copied())
+                {
+                    let id = label_id(name);
+                    if !label_ids.contains(&id) {
+                        label_ids.push(id);
+                    }
+                }
+                ExportTodo {
+                    id: i as i64 + 1,
+                    uuid: None,
+                    text: item.content.chars().take(MAX_TEXT).collect(),
+                    completed: item.checked,
+                    due: item.due.as_ref().and_then(Due::day),
+                    labels: label_ids,
+                }
+            })
+            .collect();
+
+        let mut labels: Vec<Label> = labels
+            .into_iter()
+            .map(|(name, id)| Label {
+                id,
+                uuid: None,
+                name,
+            })
+            .collect();
+        labels.sort_by_key(|label| label.id);
+        Export {
+            format: EXPORT_FORMAT,
+            labels,
+            todos,
+            ..Export::default()
+        }
+    }
+}
+
+/// Reads everything the account behind `token` has.
+async fn fetch(token: &str) -> Result<TodoistData, ApiError> {
+    let req = Request::post(SYNC_URL)
+        .header(header::AUTHORIZATION, format!("Bearer {}", token))
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from(FULL_SYNC))
+        .map_err(|e| ApiError::BadRequest(trf("Invalid Todoist token: [{}]", &[&e])))?;
+    let unreachable = |e: &dyn std::fmt::Display| {
+        tracing::warn!("failed to read todoist: {}", e);
+        ApiError::Unavailable(tr("Todoist could not be reached, try again later"))
+    };
+    let res = client::https()
+        .request(req)
+        .await
+        .map_err(|e| unreachable(&e))?;
+    let status = res.status();
+    let body = hyper::body::to_bytes(res.into_body())
+        .await
+        .map_err(|e| unreachable(&e))?;
+    if status.is_client_error() {
+        return Err(ApiError::BadRequest(trf(
+            "Todoist refused the token: [{}]",
+            &[&status],
+        )));
+    }
+    if !status.is_success() {
+        return Err(unreachable(&status));
+    }
+    serde_json::from_slice(&body).map_err(|e| unreachable(&e))
+}
+
+/// `POST /import/todoist` with `{"token": "..."}` or the JSON of a full
+/// sync, imported like `POST /import/json` does an export.
+pub async fn import_todoist<T: TodoRepository, L: LabelRepository>(
+    Extension(todo_repository): Extension<Arc<T>>,
+    Extension(label_repository): Extension<Arc<L>>,
+    format: IdFormat,
+    body: Bytes,
+) -> Result<impl IntoResponse, ApiError> {
+    let import: TodoistImport = serde_json::from_slice(&body)
+        .map_err(|e| ApiError::BadRequest(trf("Json parse error: [{}]", &[&e])))?;
+    let data = match import {
+        TodoistImport::Token { token } => fetch(&token).await?,
+        TodoistImport::Data(data) => data,
+    };
+    let export = data.into_export();
+    export.validate()?;
+    let imported = import_export(&*todo_repository, &*label_repository, export, format).await?;
+
+    Ok((StatusCode::CREATED, Json(imported)))
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn maps_tasks_labels_and_projects() {
+        let data = json!({
+            "sync_token": "abc",
+            "labels": [{ "id": "1", "name": "work" }, { "id": "2", "name": "gone", "is_deleted": true }],
+            "projects": [
+                { "id": "10", "name": "Inbox", "inbox_project": true },
+                { "id": 11, "name": "Garden" },
+            ],
+            "items": [
+                { "id": "100", "content": "Ship it", "checked": true, "labels": ["work"], "project_id": "10" },
+                { "id": "101", "content": "x".repeat(150), "labels": ["errand"], "project_id": "11",
+                  "due": { "date": "2024-06-01T12:00:00Z", "is_recurring": false } },
+                { "id": "102", "content": "deleted", "is_deleted": true },
+            ],
+        });
+        let import: TodoistImport = serde_json::from_value(data).unwrap();
+        let TodoistImport::Data(data) = import else {
+            panic!("read as a token");
+        };
+        let export = data.into_export();
+
+        let labels: Vec<_> = export
+            .labels
+            .iter()
+            .map(|label| (label.id, label.name.as_str()))
+            .collect();
+        assert_eq!(labels, vec![(1, "work"), (2, "errand"), (3, "Garden")]);
+        assert_eq!(export.todos.len(), 2);
+        assert_eq!(export.todos[0].text, "Ship it");
+        assert!(export.todos[0].completed);
+        assert_eq!(export.todos[0].labels, vec![1]);
+        assert_eq!(export.todos[1].text.chars().count(), MAX_TEXT);
+        assert_eq!(export.todos[1].labels, vec![2, 3]);
+        assert_eq!(export.todos[0].due, None);
+        assert_eq!(export.todos[1].due, "2024-06-01".parse().ok());
+        assert!(export.validate().is_ok());
+    }
+
+    #[test]
+    fn reads_a_token() {
+        let import: TodoistImport = serde_json::from_value(json!({ "token": "t0k3n" })).unwrap();
+        assert!(matches!(import, TodoistImport::Token { token } if token == "t0k3n"));
+    }
+}
@@ -0,0 +1,270 @@
+//! `POST /import/trello`: moves a Trello board over from its JSON export
+//! (Menu → Print, export and share → Export as JSON).
+//!
+//! Cards become todos, and their labels labels. A card's list becomes a
+//! label named after it, unless it is a done list, which completes the card
+//! instead. A due date keeps its day in UTC, without the time. Archived
+//! cards and lists are left out, and card descriptions are dropped.
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use axum::{
+    body::Bytes,
+    extract::{Extension, Query},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde::Deserialize;
+use validator::Validate;
+
+use crate::repositories::{
+    date::Date,
+    id::IdFormat,
+    label::{Label, LabelRepository},
+    todo::TodoRepository,
+};
+
+use super::{
+    error::ApiError,
+    i18n::trf,
+    json::{import_export, Export, ExportTodo, EXPORT_FORMAT},
+};
+
+/// Longest text a todo may have; longer card names are cut.
+const MAX_TEXT: usize = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct TrelloQuery {
+    /// The lists whose cards are done, by name and separated by `,`;
+    /// `Done` by default. Names are matched ignoring case.
+    done: Option<String>,
+}
+
+impl TrelloQuery {
+    fn is_done(&self, list: &str) -> bool {
+        self.done
+            .as_deref()
+            .unwrap_or("Done")
+            .split(',')
+            .any(|done| done.trim().eq_ignore_ascii_case(list))
+    }
+}
+
+/// The parts of a board export an import reads.
+#[derive(Debug, Default, Deserialize)]
+struct Board {
+    #[serde(default)]
+    lists: Vec<List>,
+    #[serde(default)]
+    cards: Vec<Card>,
+    #[serde(default)]
+    labels: Vec<TrelloLabel>,
+}
+
+#[derive(Debug, Deserialize)]
+struct List {
+    id: String,
+    name: String,
+    #[serde(default)]
+    closed: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Card {
+    name: String,
+    id_list: String,
+    #[serde(default)]
+    id_labels: Vec<String>,
+    #[serde(default)]
+    closed: bool,
+    /// When the card is due, as `2024-06-01T12:00:00.000Z`.
+    #[serde(default)]
+    due: Option<String>,
+    #[serde(default)]
+    due_complete: bool,
+}
+
+impl Card {
+    /// The day the card is due, or `None` for a date that can't be read.
+    fn due_day(&self) -> Option<Date> {
+        self.due.as_deref()?.get(..10)?.parse().ok()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TrelloLabel {
+    id: String,
+    #[serde(default)]
+    name: String,
+    /// What names the label when it has no name.
+    #[serde(default)]
+    color: Option<String>,
+}
+
+impl TrelloLabel {
+    fn name(&self) -> Option<&str> {
+        Some(self.name.as_str())
+            .filter(|name| !name.is_empty())
+            .or(self.color.as_deref())
+    }
+}
+
+impl Board {
+    /// The board as an export of this app, labels numbered as they first
+    /// appear. Cards of archived lists count as archived.
+    fn into_export(self, query: &TrelloQuery) -> Export {
+        let mut labels: BTreeMap<String, i64> = BTreeMap::new();
+        let mut label_id = |name: &str| {
+            let next = labels.len() as i64 + 1;
+            *labels.entry(name.to_string()).or_insert(next)
+        };
+        let trello_labels: BTreeMap<_, _> = self
+            .labels
+            .iter()
+            .filter_map(|label| Some((label.id.as_str(), label.name()?)))
+            .collect();
+        let lists: BTreeMap<_, _> = self
+            .lists
+            .iter()
+            .filter(|list| !list.closed)
+            .map(|list| (list.id.as_str(), list.name.as_str()))
+            .collect();
+
+        let todos = self
+            .cards
+            .iter()
+            .filter(|card| !card.closed)
+            .filter_map(|card| Some((card, *lists.get(card.id_list.as_str())?)))
+            .enumerate()
+            .map(|(i, (card, list))| {
+                let done = query.is_done(list);
+                let mut label_ids = vec![];
+                let names = card
+                    .id_labels
+                    .iter()
+                    .filter_map(|id| trello_labels.get(id.as_str()).copied())
+                    .chain((!done).then_some(list));
+                for name in names {
+                    let id = label_id(name);
+                    if !label_ids.contains(&id) {
+                        label_ids.push(id);
+                    }
+                }
+                ExportTodo {
+                    id: i as i64 + 1,
+                    uuid: None,
+                    text: card.name.chars().take(MAX_TEXT).collect(),
+                    completed: done || card.due_complete,
+                    due: card.due_day(),
+                    labels: label_ids,
+                }
+            })
+            .collect();
+
+        let mut labels: Vec<Label> = labels
+            .into_iter()
+            .map(|(name, id)| Label {
+                id,
+                uuid: None,
+                name,
+            })
+            .collect();
+        labels.sort_by_key(|label| label.id);
+        Export {
+            format: EXPORT_FORMAT,
+            labels,
+            todos,
+            ..Export::default()
+        }
+    }
+}
+
+/// `POST /import/trello?done=Done,Shipped` with a board's JSON export,
+/// imported like `POST /import/json` does an export.
+pub async fn import_trello<T: TodoRepository, L: LabelRepository>(
+    Extension(todo_repository): Extension<Arc<T>>,
+    Extension(label_repository): Extension<Arc<L>>,
+    Query(query): Query<TrelloQuery>,
+    format: IdFormat,
+    body: Bytes,
+) -> Result<impl IntoResponse, ApiError> {
+    let board: Board = serde_json::from_slice(&body)
+        .map_err(|e| ApiError::BadRequest(trf("Json parse error: [{}]", &[&e])))?;
+    let export = board.into_export(&query);
+    export.validate()?;
+    let imported = import_export(&*todo_repository, &*label_repository, export, format).await?;
+
+    Ok((StatusCode::CREATED, Json(imported)))
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn maps_lists_and_cards() {
+        let board = json!({
+            "name": "Garden",
+            "lists": [
+                { "id": "l1", "name": "To Do" },
+                { "id": "l2", "name": "Done" },
+                { "id": "l3", "name": "Old", "closed": true },
+            ],
+            "labels": [
+                { "id": "g", "name": "", "color": "green" },
+                { "id": "u", "name": "urgent", "color": "red" },
+            ],
+            "cards": [
+                { "id": "c1", "name": "Prune roses", "desc": "Before frost", "idList": "l1", "idLabels": ["u", "g"] },
+                { "id": "c2", "name": "Buy seeds", "idList": "l2", "idLabels": ["u"] },
+                { "id": "c3", "name": "Archived", "idList": "l1", "closed": true },
+                { "id": "c4", "name": "In an archived list", "idList": "l3" },
+                { "id": "c5", "name": "Water", "idList": "l1", "due": "2024-06-01T12:00:00.000Z", "dueComplete": true },
+            ],
+        });
+        let board: Board = serde_json::from_value(board).unwrap();
+        let export = board.into_export(&TrelloQuery { done: None });
+
+        let labels: Vec<_> = export
+            .labels
+            .iter()
+            .map(|label| (label.id, label.name.as_str()))
+            .collect();
+        assert_eq!(labels, vec![(1, "urgent"), (2, "green"), (3, "To Do")]);
+        let todos: Vec<_> = export
+            .todos
+            .iter()
+            .map(|todo| {
+                (
+                    todo.text.as_str(),
+                    todo.completed,
+                    todo.due.as_ref().map(Date::as_str),
+                    todo.labels.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            todos,
+            vec![
+                ("Prune roses", false, None, vec![1, 2, 3]),
+                ("Buy seeds", true, None, vec![1]),
+                ("Water", true, Some("2024-06-01"), vec![3]),
+            ]
+        );
+        assert!(export.validate().is_ok());
+    }
+
+    #[test]
+    fn names_the_done_lists() {
+        let query = TrelloQuery {
+            done: Some("Shipped, done ".to_string()),
+        };
+        assert!(query.is_done("shipped"));
+        assert!(query.is_done("Done"));
+        assert!(!query.is_done("Doing"));
+    }
+}
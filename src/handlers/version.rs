@@ -0,0 +1,39 @@
+use axum::Json;
+use serde::Serialize;
+
+/// What is running, fixed when the binary was built.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Version {
+    pub version: &'static str,
+    /// `unknown` when built outside a checkout without `GIT_SHA`.
+    pub git_sha: &'static str,
+    pub built_at: &'static str,
+    /// The cargo features compiled in.
+    pub features: Vec<&'static str>,
+}
+
+const FEATURES: [(&str, bool); 3] = [
+    ("mysql", cfg!(feature = "mysql")),
+    ("mongodb", cfg!(feature = "mongodb")),
+    ("dynamodb", cfg!(feature = "dynamodb")),
+];
+
+impl Version {
+    pub fn current() -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            git_sha: env!("GIT_SHA"),
+            built_at: env!("BUILD_TIMESTAMP"),
+            features: FEATURES
+                .iter()
+                .filter(|(_, enabled)| *enabled)
+                .map(|(name, _)| *name)
+                .collect(),
+        }
+    }
+}
+
+/// `GET /version`.
+pub async fn version() -> Json<Version> {
+    Json(Version::current())
+}
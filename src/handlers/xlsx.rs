@@ -0,0 +1,447 @@
+//! `GET /export/xlsx`: the todos as an Excel workbook, one sheet per label
+//! as the Markdown export has a section per label, with the completion a
+//! boolean cell and the due day and last change date ones.
+//!
+//! Todos have no projects here; labels stand in for them, so the sheet a
+//! project would get is the sheet of the label named after it.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::Write,
+    sync::Arc,
+    time::SystemTime,
+};
+
+use axum::{
+    extract::Extension,
+    http::{header, HeaderValue},
+    response::{IntoResponse, Response},
+};
+use flate2::{write::DeflateEncoder, Compression, Crc};
+use futures_util::TryStreamExt;
+
+use crate::repositories::todo::{TodoRepository, TodoWithLabels};
+
+use super::{error::ApiError, i18n::tr, search::Search, todo::stream_with_labels};
+
+pub const XLSX: &str = "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet";
+
+/// The columns of every sheet.
+const HEADER: [&str; 7] = [
+    "id",
+    "uuid",
+    "text",
+    "completed",
+    "due",
+    "labels",
+    "modified",
+];
+
+/// The longest sheet name Excel opens.
+const MAX_SHEET_CHARS: usize = 31;
+
+/// Days from Excel's day 0 to 1970-01-01.
+const UNIX_EPOCH_SERIAL: f64 = 25_569.0;
+
+const CONTENT_TYPES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"><Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/><Default Extension="xml" ContentType="application/xml"/><Override PartName="/xl/workbook.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml"/><Override PartName="/xl/styles.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.styles+xml"/>{}</Types>"#;
+
+const ROOT_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="xl/workbook.xml"/></Relationships>"#;
+
+/// Style 1 shows a date and time, style 2 bolds the header and style 3
+/// shows a date.
+const STYLES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<styleSheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><numFmts count="2"><numFmt numFmtId="164" formatCode="yyyy-mm-dd hh:mm:ss"/><numFmt numFmtId="165" formatCode="yyyy-mm-dd"/></numFmts><fonts count="2"><font><sz val="11"/><name val="Calibri"/></font><font><b/><sz val="11"/><name val="Calibri"/></font></fonts><fills count="2"><fill><patternFill patternType="none"/></fill><fill><patternFill patternType="gray125"/></fill></fills><borders count="1"><border><left/><right/><top/><bottom/><diagonal/></border></borders><cellStyleXfs count="1"><xf numFmtId="0" fontId="0" fillId="0" borderId="0"/></cellStyleXfs><cellXfs count="4"><xf numFmtId="0" fontId="0" fillId="0" borderId="0" xfId="0"/><xf numFmtId="164" fontId="0" fillId="0" borderId="0" xfId="0" applyNumberFormat="1"/><xf numFmtId="0" fontId="1" fillId="0" borderId="0" xfId="0" applyFont="1"/><xf numFmtId="165" fontId="0" fillId="0" borderId="0" xfId="0" applyNumberFormat="1"/></cellXfs></styleSheet>"#;
+
+/// `text` escaped for XML, without the control characters it can't hold.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// `name` as Excel allows sheets to be named: without `[]:*?/\`, at most
+/// [`MAX_SHEET_CHARS`], and unlike the names in `taken` ignoring case.
+fn sheet_name(name: &str, taken: &[String]) -> String {
+    let clean: String = name
+        .chars()
+        .map(|c| if "[]:*?/\\".contains(c) { '_' } else { c })
+        .collect();
+    let clean = clean.trim_matches('\'');
+    let clean = if clean.is_empty() { "_" } else { clean };
+    let is_taken = |name: &str| {
+        taken
+            .iter()
+            .any(|t| t.to_lowercase() == name.to_lowercase())
+    };
+    let mut n = 1;
+    loop {
+        let suffix = if n == 1 {
+            String::new()
+        } else {
+            format!(" ({})", n)
+        };
+        let keep = MAX_SHEET_CHARS - suffix.chars().count();
+        let candidate = format!("{}{}", clean.chars().take(keep).collect::<String>(), suffix);
+        if !is_taken(&candidate) {
+            return candidate;
+        }
+        n += 1;
+    }
+}
+
+/// Excel's serial number of `at`, in days, as UTC.
+fn serial(at: SystemTime) -> f64 {
+    let secs = at
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0.0, |d| d.as_secs_f64());
+    UNIX_EPOCH_SERIAL + secs / 86_400.0
+}
+
+/// The column letter of the 0-based `column`, 26 at most.
+fn column(column: usize) -> char {
+    (b'A' + column as u8) as char
+}
+
+fn text_cell(out: &mut String, at: &str, text: &str) {
+    out.push_str(&format!(
+        r#"<c r="{}" t="inlineStr"><is><t xml:space="preserve">{}</t></is></c>"#,
+        at,
+        escape(text)
+    ));
+}
+
+/// A worksheet of `todos` under the header, `modified` giving the last
+/// change of each by id.
+fn sheet(todos: &[&TodoWithLabels], modified: &HashMap<i64, SystemTime>) -> String {
+    let mut out = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><sheetViews><sheetView workbookViewId="0"><pane ySplit="1" topLeftCell="A2" state="frozen"/></sheetView></sheetViews><cols><col min="3" max="3" width="60" customWidth="1"/><col min="5" max="5" width="12" customWidth="1"/><col min="7" max="7" width="20" customWidth="1"/></cols><sheetData><row r="1">"#,
+    );
+    for (i, name) in HEADER.iter().enumerate() {
+        out.push_str(&format!(
+            r#"<c r="{}1" t="inlineStr" s="2"><is><t>{}</t></is></c>"#,
+            column(i),
+            name
+        ));
+    }
+    out.push_str("</row>");
+    for (i, TodoWithLabels { todo, labels }) in todos.iter().enumerate() {
+        let row = i + 2;
+        out.push_str(&format!(r#"<row r="{}">"#, row));
+        out.push_str(&format!(r#"<c r="A{}"><v>{}</v></c>"#, row, todo.id()));
+        if let Some(uuid) = todo.uuid() {
+            text_cell(&mut out, &format!("B{}", row), &uuid.to_string());
+        }
+        text_cell(&mut out, &format!("C{}", row), todo.text());
+        out.push_str(&format!(
+            r#"<c r="D{}" t="b"><v>{}</v></c>"#,
+            row,
+            u8::from(todo.completed())
+        ));
+        if let Some(due) = todo.due() {
+            out.push_str(&format!(
+                r#"<c r="E{}" s="3"><v>{}</v></c>"#,
+                row,
+                UNIX_EPOCH_SERIAL + due.days() as f64
+            ));
+        }
+        let labels: Vec<&str> = labels.iter().map(|label| label.name.as_str()).collect();
+        if !labels.is_empty() {
+            text_cell(&mut out, &format!("F{}", row), &labels.join(";"));
+        }
+        if let Some(at) = modified.get(&todo.id()) {
+            out.push_str(&format!(
+                r#"<c r="G{}" s="1"><v>{}</v></c>"#,
+                row,
+                serial(*at)
+            ));
+        }
+        out.push_str("</row>");
+    }
+    out.push_str("</sheetData></worksheet>");
+    out
+}
+
+/// The sheets of the workbook by name: one per label, by label name, and
+/// one for the todos without. Without any labels it is a single sheet.
+fn sheets(todos: &[TodoWithLabels]) -> Vec<(String, Vec<&TodoWithLabels>)> {
+    let mut by_label: BTreeMap<&str, Vec<&TodoWithLabels>> = BTreeMap::new();
+    let mut unlabelled = vec![];
+    for todo in todos {
+        if todo.labels.is_empty() {
+            unlabelled.push(todo);
+        }
+        for label in &todo.labels {
+            by_label.entry(&label.name).or_default().push(todo);
+        }
+    }
+
+    let mut sheets: Vec<(String, Vec<&TodoWithLabels>)> = vec![];
+    let mut taken = vec![];
+    for (name, todos) in by_label {
+        let name = sheet_name(name, &taken);
+        taken.push(name.clone());
+        sheets.push((name, todos));
+    }
+    if !unlabelled.is_empty() || sheets.is_empty() {
+        let name = if sheets.is_empty() {
+            tr("Todos")
+        } else {
+            tr("Without a label")
+        };
+        sheets.push((sheet_name(&name, &taken), unlabelled));
+    }
+    sheets
+}
+
+/// The workbook: its parts zipped as the Office Open XML package wants.
+fn workbook(
+    todos: &[TodoWithLabels],
+    modified: &HashMap<i64, SystemTime>,
+) -> anyhow::Result<Vec<u8>> {
+    let sheets = sheets(todos);
+    let mut overrides = String::new();
+    let mut entries = String::new();
+    let mut rels = String::new();
+    for (i, (name, _)) in sheets.iter().enumerate() {
+        let n = i + 1;
+        overrides.push_str(&format!(
+            r#"<Override PartName="/xl/worksheets/sheet{}.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml"/>"#,
+            n
+        ));
+        entries.push_str(&format!(
+            r#"<sheet name="{}" sheetId="{}" r:id="rId{}"/>"#,
+            escape(name),
+            n,
+            n
+        ));
+        rels.push_str(&format!(
+            r#"<Relationship Id="rId{}" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet{}.xml"/>"#,
+            n, n
+        ));
+    }
+    let styles = sheets.len() + 1;
+    rels.push_str(&format!(
+        r#"<Relationship Id="rId{}" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles" Target="styles.xml"/>"#,
+        styles
+    ));
+
+    let mut zip = Zip::default();
+    zip.add(
+        "[Content_Types].xml",
+        CONTENT_TYPES.replace("{}", &overrides).as_bytes(),
+    )?;
+    zip.add("_rels/.rels", ROOT_RELS.as_bytes())?;
+    zip.add(
+        "xl/workbook.xml",
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><sheets>{}</sheets></workbook>"#,
+            entries
+        )
+        .as_bytes(),
+    )?;
+    zip.add(
+        "xl/_rels/workbook.xml.rels",
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">{}</Relationships>"#,
+            rels
+        )
+        .as_bytes(),
+    )?;
+    zip.add("xl/styles.xml", STYLES.as_bytes())?;
+    for (i, (_, todos)) in sheets.iter().enumerate() {
+        zip.add(
+            &format!("xl/worksheets/sheet{}.xml", i + 1),
+            sheet(todos, modified).as_bytes(),
+        )?;
+    }
+    Ok(zip.finish())
+}
+
+/// A ZIP archive of deflated files, written in memory: enough of the
+/// format for a workbook, which is small and fixed in its parts.
+#[derive(Debug, Default)]
+struct Zip {
+    out: Vec<u8>,
+    directory: Vec<u8>,
+    entries: u16,
+}
+
+impl Zip {
+    fn add(&mut self, name: &str, contents: &[u8]) -> anyhow::Result<()> {
+        let mut crc = Crc::new();
+        crc.update(contents);
+        let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+        encoder.write_all(contents)?;
+        let deflated = encoder.finish()?;
+        let offset = self.out.len() as u32;
+
+        // version 2.0, no flags, deflated, 1980-01-01 00:00
+        let common = |out: &mut Vec<u8>| {
+            out.extend(20u16.to_le_bytes());
+            out.extend(0u16.to_le_bytes());
+            out.extend(8u16.to_le_bytes());
+            out.extend(0u16.to_le_bytes());
+            out.extend(0x21u16.to_le_bytes());
+            out.extend(crc.sum().to_le_bytes());
+            out.extend((deflated.len() as u32).to_le_bytes());
+            out.extend((contents.len() as u32).to_le_bytes());
+            out.extend((name.len() as u16).to_le_bytes());
+            out.extend(0u16.to_le_bytes());
+        };
+        self.out.extend(0x0403_4b50u32.to_le_bytes());
+        common(&mut self.out);
+        self.out.extend(name.as_bytes());
+        self.out.extend(&deflated);
+
+        self.directory.extend(0x0201_4b50u32.to_le_bytes());
+        self.directory.extend(20u16.to_le_bytes());
+        common(&mut self.directory);
+        // no comment, on disk 0, no attributes
+        self.directory.extend([0; 10]);
+        self.directory.extend(offset.to_le_bytes());
+        self.directory.extend(name.as_bytes());
+        self.entries += 1;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let offset = self.out.len() as u32;
+        let size = self.directory.len() as u32;
+        self.out.append(&mut self.directory);
+        self.out.extend(0x0605_4b50u32.to_le_bytes());
+        self.out.extend([0; 4]);
+        self.out.extend(self.entries.to_le_bytes());
+        self.out.extend(self.entries.to_le_bytes());
+        self.out.extend(size.to_le_bytes());
+        self.out.extend(offset.to_le_bytes());
+        self.out.extend(0u16.to_le_bytes());
+        self.out
+    }
+}
+
+/// `GET /export/xlsx`: the todos `GET /todos` lists for the same `?q=`
+/// and `?ids=`, oldest first on every sheet.
+pub async fn export_xlsx<T: TodoRepository>(
+    Extension(repository): Extension<Arc<T>>,
+    Search(filter): Search,
+) -> Result<Response, ApiError> {
+    let modified: HashMap<i64, SystemTime> = repository
+        .recently_modified(i64::MAX)
+        .await?
+        .into_iter()
+        .map(|(todo, at)| (todo.id(), at))
+        .collect();
+    let todos: Vec<_> = stream_with_labels(repository, filter).try_concat().await?;
+    let mut res = workbook(&todos, &modified)?.into_response();
+    let headers = res.headers_mut();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(XLSX));
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        HeaderValue::from_static(r#"attachment; filename="todos.xlsx""#),
+    );
+    Ok(res)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{io::Read, time::Duration};
+
+    use super::*;
+    use crate::testing::todo;
+    use flate2::read::DeflateDecoder;
+
+    /// The files of a ZIP archive by name, read from its central directory.
+    fn unzip(zip: &[u8]) -> BTreeMap<String, String> {
+        let u16_at = |at: usize| u16::from_le_bytes([zip[at], zip[at + 1]]) as usize;
+        let u32_at = |at: usize| u32::from_le_bytes(zip[at..at + 4].try_into().unwrap()) as usize;
+        let end = zip.len() - 22;
+        assert_eq!(u32_at(end), 0x0605_4b50);
+        let mut at = u32_at(end + 16);
+        let mut files = BTreeMap::new();
+        for _ in 0..u16_at(end + 10) {
+            assert_eq!(u32_at(at), 0x0201_4b50);
+            let (size, name_len, offset) = (u32_at(at + 20), u16_at(at + 28), u32_at(at + 42));
+            let name = String::from_utf8(zip[at + 46..at + 46 + name_len].to_vec()).unwrap();
+            let data = offset + 30 + u16_at(offset + 26);
+            let mut contents = String::new();
+            DeflateDecoder::new(&zip[data..data + size])
+                .read_to_string(&mut contents)
+                .unwrap();
+            assert_eq!(contents.len(), u32_at(at + 24));
+            files.insert(name, contents);
+            at += 46 + name_len;
+        }
+        files
+    }
+
+    #[test]
+    fn writes_a_sheet_per_label() {
+        let mut todos = [
+            todo(1, "Ship <it> & tell", true, &["work"]),
+            todo(2, "Call mom", false, &[]),
+            todo(3, "Plan", false, &["work", "home"]),
+        ];
+        todos[0].todo = serde_json::from_value(serde_json::json!({
+            "id": 1, "text": "Ship <it> & tell", "completed": true, "due": "2024-06-01"
+        }))
+        .unwrap();
+        let modified = HashMap::from([(1, SystemTime::UNIX_EPOCH + Duration::from_secs(43_200))]);
+        let files = unzip(&workbook(&todos, &modified).unwrap());
+        assert_eq!(
+            files.keys().collect::<Vec<_>>(),
+            [
+                "[Content_Types].xml",
+                "_rels/.rels",
+                "xl/_rels/workbook.xml.rels",
+                "xl/styles.xml",
+                "xl/workbook.xml",
+                "xl/worksheets/sheet1.xml",
+                "xl/worksheets/sheet2.xml",
+                "xl/worksheets/sheet3.xml",
+            ]
+        );
+        let workbook = &files["xl/workbook.xml"];
+        assert!(workbook.contains(r#"<sheet name="home" sheetId="1" r:id="rId1"/><sheet name="work" sheetId="2" r:id="rId2"/><sheet name="Without a label" sheetId="3" r:id="rId3"/>"#), "{}", workbook);
+
+        let work = &files["xl/worksheets/sheet2.xml"];
+        assert!(work.contains(r#"<row r="2"><c r="A2"><v>1</v></c><c r="C2" t="inlineStr"><is><t xml:space="preserve">Ship &lt;it&gt; &amp; tell</t></is></c><c r="D2" t="b"><v>1</v></c><c r="E2" s="3"><v>45444</v></c><c r="F2" t="inlineStr"><is><t xml:space="preserve">work</t></is></c><c r="G2" s="1"><v>25569.5</v></c></row>"#), "{}", work);
+        assert!(work.contains(r#"<row r="3"><c r="A3"><v>3</v></c>"#));
+        assert!(!work.contains(r#"<row r="4">"#));
+    }
+
+    #[test]
+    fn names_sheets_as_excel_allows() {
+        let taken = vec!["Work".to_string()];
+        assert_eq!(sheet_name("work", &taken), "work (2)");
+        assert_eq!(sheet_name("a/b:c", &[]), "a_b_c");
+        assert_eq!(sheet_name("'", &[]), "_");
+        let long = "x".repeat(40);
+        assert_eq!(sheet_name(&long, &[]).chars().count(), MAX_SHEET_CHARS);
+        let first = sheet_name(&long, &[]);
+        assert_eq!(
+            sheet_name(&long, &[first]),
+            format!("{} (2)", "x".repeat(27))
+        );
+    }
+
+    #[test]
+    fn writes_one_sheet_without_labels() {
+        let names: Vec<String> = sheets(&[]).into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["Todos"]);
+    }
+}
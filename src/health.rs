@@ -0,0 +1,156 @@
+use std::{
+    env,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{extract::Extension, http::StatusCode, response::IntoResponse, Json};
+use serde::Serialize;
+use serde_json::json;
+use sqlx::{migrate::Migrate, migrate::Migrator, Connection, Database, Pool};
+
+use crate::repositories::check_migrations;
+
+/// How often the database is probed by default.
+const PROBE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// What the latest database probe found, shared with `/readyz` and the
+/// degraded read layer. Up until a probe says otherwise; backends without a
+/// pool are never probed.
+#[derive(Debug, Clone, Default)]
+pub struct Health(Arc<Mutex<Probed>>);
+
+/// Why each check failed, `None` while it passes.
+#[derive(Debug, Clone, Default)]
+struct Probed {
+    database: Option<String>,
+    migrations: Option<String>,
+}
+
+impl Health {
+    pub fn database_up(&self) -> bool {
+        self.database_error().is_none()
+    }
+
+    /// Why the latest probe failed, `None` when it succeeded.
+    pub fn database_error(&self) -> Option<String> {
+        self.probed().database
+    }
+
+    fn probed(&self) -> Probed {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Records a probe's outcome, logging when the database goes away or
+    /// comes back.
+    pub fn record(&self, result: Result<(), String>) {
+        let mut probed = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        match (&probed.database, &result) {
+            (None, Err(e)) => tracing::warn!("database unreachable: {}", e),
+            (Some(_), Ok(())) => tracing::info!("database reachable again"),
+            _ => {}
+        }
+        probed.database = result.err();
+    }
+
+    /// Records whether the schema had every migration of this build.
+    fn record_migrations(&self, result: Result<(), String>) {
+        let mut probed = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        match (&probed.migrations, &result) {
+            (None, Err(e)) => tracing::warn!("schema out of date: {}", e),
+            (Some(_), Ok(())) => tracing::info!("schema up to date again"),
+            _ => {}
+        }
+        probed.migrations = result.err();
+    }
+
+    /// Pings a pooled connection every `HEALTH_CHECK_INTERVAL_SECS` (5 by
+    /// default) until the process exits, and checks the schema still has
+    /// every migration in `migrator` while the database answers. A ping
+    /// slower than the interval counts as a failure.
+    pub fn watch<DB>(&self, pool: Pool<DB>, migrator: &'static Migrator)
+    where
+        DB: Database,
+        DB::Connection: Migrate,
+    {
+        let interval = match env::var("HEALTH_CHECK_INTERVAL_SECS") {
+            Ok(secs) => match secs.parse() {
+                Ok(secs) if secs > 0 => Duration::from_secs(secs),
+                _ => panic!("invalid [HEALTH_CHECK_INTERVAL_SECS]: {}", secs),
+            },
+            Err(_) => PROBE_INTERVAL,
+        };
+        let health = self.clone();
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            loop {
+                ticks.tick().await;
+                let result = match tokio::time::timeout(interval, ping(&pool)).await {
+                    Ok(result) => result.map_err(|e| e.to_string()),
+                    Err(_) => Err(format!("no answer within {:?}", interval)),
+                };
+                let up = result.is_ok();
+                health.record(result);
+                if up {
+                    let result = check_migrations(migrator, &pool).await;
+                    health.record_migrations(result.map(drop).map_err(|e| e.to_string()));
+                }
+            }
+        });
+    }
+}
+
+async fn ping<DB: Database>(pool: &Pool<DB>) -> Result<(), sqlx::Error> {
+    pool.acquire().await?.ping().await
+}
+
+#[derive(Debug, Serialize)]
+struct Check {
+    up: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Readiness {
+    ready: bool,
+    checks: Checks,
+}
+
+#[derive(Debug, Serialize)]
+struct Checks {
+    database: Check,
+    migrations: Check,
+}
+
+impl Check {
+    fn new(error: Option<String>) -> Self {
+        Check {
+            up: error.is_none(),
+            error,
+        }
+    }
+}
+
+/// `GET /healthz`: 200 as long as the process serves requests at all, for
+/// liveness probes; dependencies are `/readyz`'s business.
+pub async fn healthz() -> impl IntoResponse {
+    Json(json!({ "alive": true }))
+}
+
+/// `GET /readyz`: 503 while the latest database probe failed or the schema
+/// misses a migration of this build, so load balancers stop routing here.
+pub async fn readyz(Extension(health): Extension<Health>) -> impl IntoResponse {
+    let probed = health.probed();
+    let checks = Checks {
+        database: Check::new(probed.database),
+        migrations: Check::new(probed.migrations),
+    };
+    let ready = checks.database.up && checks.migrations.up;
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(Readiness { ready, checks }))
+}
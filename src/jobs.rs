@@ -0,0 +1,105 @@
+use std::{env, sync::Arc, time::Duration};
+
+use serde_json::json;
+
+use crate::repositories::{
+    encrypt::Encrypting,
+    job::{Job, JobKind, JobRepository, JobStatus, UpdateJob},
+    todo::TodoRepository,
+};
+
+/// Runs `job` on a background task, recording its progress and outcome in the
+/// job repository so `GET /jobs/:id` can report on it.
+pub fn spawn<J: JobRepository, T: TodoRepository>(jobs: Arc<J>, todos: Arc<T>, job: Job) {
+    tokio::spawn(async move {
+        if let Err(e) = run(&*jobs, &*todos, &job).await {
+            tracing::error!("failed to record job {} outcome: {:#}", job.id, e);
+        }
+    });
+}
+
+async fn run<J: JobRepository, T: TodoRepository>(
+    jobs: &J,
+    todos: &T,
+    job: &Job,
+) -> anyhow::Result<()> {
+    jobs.update(
+        job.id,
+        UpdateJob {
+            status: Some(JobStatus::Running),
+            ..Default::default()
+        },
+    )
+    .await?;
+
+    let outcome = match job.kind {
+        JobKind::PurgeCompleted => todos
+            .purge_completed()
+            .await
+            .map(|purged| json!({ "purged": purged })),
+    };
+
+    let update = match outcome {
+        Ok(result) => UpdateJob {
+            status: Some(JobStatus::Succeeded),
+            progress: Some(100),
+            result: Some(result),
+            error: None,
+        },
+        Err(e) => {
+            tracing::error!("job {} failed: {:#}", job.id, e);
+            UpdateJob {
+                status: Some(JobStatus::Failed),
+                error: Some(e.to_string()),
+                ..Default::default()
+            }
+        }
+    };
+    jobs.update(job.id, update).await?;
+
+    Ok(())
+}
+
+/// How often the archiver looks for todos to move.
+const ARCHIVE_EVERY: Duration = Duration::from_secs(60 * 60);
+
+/// Archives the todos completed more than `ARCHIVE_AFTER_DAYS` days ago,
+/// hourly on a background task. Nothing is archived unless it is set;
+/// only the SQL repositories keep an archive, so `main` refuses the
+/// setting on the other backends.
+pub fn archive_from_env<T: TodoRepository>(todos: T) {
+    let days: u64 = match env::var("ARCHIVE_AFTER_DAYS") {
+        Ok(days) => days
+            .parse()
+            .unwrap_or_else(|_| panic!("invalid [ARCHIVE_AFTER_DAYS]: {}", days)),
+        Err(_) => return,
+    };
+    tracing::info!("archiving todos completed over {} days ago", days);
+    let older_than = Duration::from_secs(days * 24 * 60 * 60);
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(ARCHIVE_EVERY);
+        loop {
+            ticks.tick().await;
+            match todos.archive_completed(older_than).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("archived {} completed todos", count),
+                Err(e) => tracing::error!("failed to archive todos: {:#}", e),
+            }
+        }
+    });
+}
+
+/// Brings every stored text under the current encryption key on a
+/// background task, once at startup; see [`Encrypting::reencrypt`].
+pub fn reencrypt<T: TodoRepository>(todos: Encrypting<T>) {
+    if !todos.enabled() {
+        return;
+    }
+    tokio::spawn(async move {
+        match todos.reencrypt().await {
+            Ok(0) => {}
+            Ok(count) => tracing::info!("encrypted {} todos with the current key", count),
+            Err(e) => tracing::error!("failed to re-encrypt todos: {:#}", e),
+        }
+    });
+}
@@ -0,0 +1,13 @@
+pub mod access_log;
+pub mod body_limit;
+pub mod catch_panic;
+pub mod client_ip;
+pub mod compression;
+pub mod cors;
+pub mod degraded;
+pub mod internal;
+pub mod load_shed;
+pub mod rate_limit;
+pub mod request_id;
+pub mod timeout;
+pub mod trace;
@@ -0,0 +1,163 @@
+use std::time::Duration;
+
+use axum::http::{Request, Response};
+use http_body::Body;
+use tower_http::{
+    classify::{ServerErrorsAsFailures, SharedClassifier},
+    trace::{MakeSpan, OnResponse, TraceLayer},
+};
+use tracing::{Level, Span};
+
+use crate::config::ServerConfig;
+
+/// The target access log lines are written under, which `RUST_LOG` leaves
+/// alone so they show whatever the application logs.
+pub const TARGET: &str = "access";
+
+/// Emits `$macro!(target: TARGET, level, ...)` at a level only known at
+/// runtime, as tracing wants it to be a constant.
+macro_rules! at_level {
+    ($macro:ident, $level:expr, $($args:tt)*) => {
+        match $level {
+            Level::ERROR => tracing::$macro!(target: TARGET, Level::ERROR, $($args)*),
+            Level::WARN => tracing::$macro!(target: TARGET, Level::WARN, $($args)*),
+            Level::INFO => tracing::$macro!(target: TARGET, Level::INFO, $($args)*),
+            Level::DEBUG => tracing::$macro!(target: TARGET, Level::DEBUG, $($args)*),
+            Level::TRACE => tracing::$macro!(target: TARGET, Level::TRACE, $($args)*),
+        }
+    };
+}
+
+/// One line per request with its method, path, status, latency and, unless
+/// it is streamed, response size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccessLog {
+    level: Level,
+}
+
+pub type AccessLogLayer =
+    TraceLayer<SharedClassifier<ServerErrorsAsFailures>, AccessLog, (), AccessLog, (), (), ()>;
+
+impl AccessLog {
+    /// `access_log`, a level such as `info` (the default) or `off`.
+    pub fn from_config(config: &ServerConfig) -> Option<Self> {
+        if config.access_log.eq_ignore_ascii_case("off") {
+            return None;
+        }
+        let level = config
+            .access_log
+            .parse()
+            .unwrap_or_else(|e| panic!("invalid [ACCESS_LOG]: {}, {}", config.access_log, e));
+        Some(Self { level })
+    }
+
+    pub fn layer(self) -> AccessLogLayer {
+        TraceLayer::new_for_http()
+            .make_span_with(self)
+            .on_request(())
+            .on_response(self)
+            .on_body_chunk(())
+            .on_eos(())
+            .on_failure(())
+    }
+}
+
+impl<B> MakeSpan<B> for AccessLog {
+    fn make_span(&mut self, req: &Request<B>) -> Span {
+        at_level!(
+            span,
+            self.level,
+            "access",
+            method = %req.method(),
+            path = %req.uri().path()
+        )
+    }
+}
+
+impl<B: Body> OnResponse<B> for AccessLog {
+    fn on_response(self, res: &Response<B>, latency: Duration, _: &Span) {
+        let status = res.status().as_u16();
+        let latency_ms = latency.as_millis() as u64;
+        let bytes = res.body().size_hint().exact();
+        at_level!(
+            event,
+            self.level,
+            status,
+            latency_ms,
+            bytes,
+            "{} in {}ms",
+            status,
+            latency_ms
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+    };
+
+    use axum::{body::Body, http::StatusCode, routing::post, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn logs_a_line_per_request() {
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(Level::TRACE)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let config = ServerConfig {
+            access_log: "warn".to_string(),
+            ..ServerConfig::default()
+        };
+        let app = Router::new()
+            .route(
+                "/todos",
+                post(|| async { (StatusCode::CREATED, "created") }),
+            )
+            .layer(AccessLog::from_config(&config).unwrap().layer());
+        let req = Request::post("/todos").body(Body::empty()).unwrap();
+        app.oneshot(req).await.unwrap();
+
+        let logs = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        assert!(
+            logs.contains("WARN access{method=POST path=/todos}"),
+            "{}",
+            logs
+        );
+        assert!(logs.contains("status=201 latency_ms="), "{}", logs);
+        assert!(logs.contains("bytes=7"), "{}", logs);
+    }
+
+    #[test]
+    fn can_be_turned_off() {
+        let config = ServerConfig {
+            access_log: "off".to_string(),
+            ..ServerConfig::default()
+        };
+        assert_eq!(AccessLog::from_config(&config), None);
+    }
+}
@@ -0,0 +1,132 @@
+use axum::{
+    body::{Body, Bytes},
+    http::{header, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use hyper::body::HttpBody;
+
+use crate::{attachments, config::ServerConfig, handlers::error::Problem};
+
+/// Largest request body accepted, as sent on the wire, so a giant create or
+/// import payload is refused before it is buffered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BodyLimit(pub u64);
+
+impl BodyLimit {
+    /// `max_body_bytes`; `0` accepts bodies of any size.
+    pub fn from_config(config: &ServerConfig) -> Option<Self> {
+        (config.max_body_bytes > 0).then_some(Self(config.max_body_bytes))
+    }
+}
+
+pub(super) fn is_under(path: &str, prefix: &str) -> bool {
+    path.strip_prefix(prefix)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+fn too_large(limit: BodyLimit, path: &str) -> Response {
+    let mut problem = Problem::new(
+        StatusCode::PAYLOAD_TOO_LARGE,
+        "payload-too-large",
+        format!("Request body exceeds {} bytes", limit.0),
+    );
+    problem.instance = Some(path.to_string());
+    problem.into_response()
+}
+
+/// Answers `413 Payload Too Large` to a body over `limit`: at once when its
+/// `Content-Length` says so, or once a chunked one has sent that much.
+/// Attachments are streamed to their store, which checks
+/// `[attachments] max_bytes` instead.
+pub async fn limit_body(req: Request<Body>, next: Next<Body>, limit: BodyLimit) -> Response {
+    if is_under(req.uri().path(), attachments::PATH) {
+        return next.run(req).await;
+    }
+    let declared = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    match declared {
+        Some(length) if length > limit.0 => return too_large(limit, req.uri().path()),
+        // hyper reads no further than the declared length
+        Some(_) => return next.run(req).await,
+        None => {}
+    }
+
+    let path = req.uri().path().to_string();
+    let (parts, mut body) = req.into_parts();
+    let mut buffered = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk: Bytes = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                let mut problem =
+                    Problem::new(StatusCode::BAD_REQUEST, "bad-request", e.to_string());
+                problem.instance = Some(path);
+                return problem.into_response();
+            }
+        };
+        if (buffered.len() + chunk.len()) as u64 > limit.0 {
+            return too_large(limit, &path);
+        }
+        buffered.extend_from_slice(&chunk);
+    }
+    next.run(Request::from_parts(parts, Body::from(buffered)))
+        .await
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{middleware, routing::post, Router};
+    use futures_util::stream;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn refuses_bodies_over_the_limit() {
+        let app = Router::new()
+            .route(
+                "/todos",
+                post(|body: Bytes| async move { body.len().to_string() }),
+            )
+            .layer(middleware::from_fn(|req, next| {
+                limit_body(req, next, BodyLimit(8))
+            }));
+
+        let req = Request::post("/todos")
+            .header(header::CONTENT_LENGTH, 8)
+            .body(Body::from("12345678"))
+            .unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let req = Request::post("/todos")
+            .header(header::CONTENT_LENGTH, 9)
+            .body(Body::from("123456789"))
+            .unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let problem: Problem = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(problem.problem_type, "/problems/payload-too-large");
+        assert_eq!(problem.instance.as_deref(), Some("/todos"));
+
+        let chunks: Vec<Result<&str, std::io::Error>> = vec![Ok("12345"), Ok("6789")];
+        let req = Request::post("/todos")
+            .body(Body::wrap_stream(stream::iter(chunks)))
+            .unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let chunks: Vec<Result<&str, std::io::Error>> = vec![Ok("1234"), Ok("5678")];
+        let req = Request::post("/todos")
+            .body(Body::wrap_stream(stream::iter(chunks)))
+            .unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&bytes[..], b"8");
+    }
+}
@@ -0,0 +1,73 @@
+use std::{any::Any, panic::AssertUnwindSafe};
+
+use axum::{
+    http::Request,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures_util::FutureExt;
+
+use crate::handlers::error::ApiError;
+
+fn message(panic: &(dyn Any + Send)) -> &str {
+    panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("non-string panic payload")
+}
+
+/// Answers a panicking handler's request with a logged `500` problem, where
+/// hyper would drop the connection and the client see an outage.
+pub async fn catch_panic<B>(req: Request<B>, next: Next<B>) -> Response {
+    let target = format!("{} {}", req.method(), req.uri().path());
+    match AssertUnwindSafe(next.run(req)).catch_unwind().await {
+        Ok(res) => res,
+        Err(panic) => {
+            tracing::error!("{} panicked: {}", target, message(&*panic));
+            ApiError::Internal.into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{
+        body::Body,
+        http::StatusCode,
+        middleware,
+        routing::{get, post},
+        Router,
+    };
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::handlers::error::Problem;
+
+    #[tokio::test]
+    async fn turns_panics_into_500s() {
+        let app = Router::new()
+            .route("/", get(|| async { "ok" }))
+            .route(
+                "/todos",
+                post(|| async {
+                    if true {
+                        panic!("bad request");
+                    }
+                    "unreachable"
+                }),
+            )
+            .layer(middleware::from_fn(catch_panic));
+
+        let req = Request::post("/todos").body(Body::empty()).unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let problem: Problem = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(problem.problem_type, "/problems/internal");
+
+        let req = Request::get("/").body(Body::empty()).unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,172 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+};
+
+use axum::{extract::ConnectInfo, http::Request};
+
+use crate::config::ServerConfig;
+
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+/// An address, or a block of them: `10.0.0.0/8`, `::1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl FromStr for Cidr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr.parse::<IpAddr>()?, Some(prefix.parse::<u8>()?)),
+            None => (s.parse::<IpAddr>()?, None),
+        };
+        let bits = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(bits);
+        if prefix > bits {
+            anyhow::bail!("prefix longer than {} bits", bits);
+        }
+        Ok(Self { addr, prefix })
+    }
+}
+
+impl Cidr {
+    fn contains(&self, ip: IpAddr) -> bool {
+        let mask = |bits: u32| {
+            let host = bits - u32::from(self.prefix);
+            u128::MAX.checked_shl(host).unwrap_or(0)
+        };
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = mask(32) as u32;
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = mask(128);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The reverse proxies in front of the API, whose `X-Forwarded-For` names
+/// the client. Anyone else's is ignored, as a client could forge it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrustedProxies(Vec<Cidr>);
+
+impl TrustedProxies {
+    /// `trusted_proxies`, addresses such as `127.0.0.1` or blocks such as
+    /// `10.0.0.0/8`; none by default.
+    pub fn from_config(config: &ServerConfig) -> Self {
+        let cidrs = config
+            .trusted_proxies
+            .iter()
+            .map(|proxy| {
+                proxy
+                    .parse()
+                    .unwrap_or_else(|e| panic!("invalid [TRUSTED_PROXIES]: {}, {}", proxy, e))
+            })
+            .collect();
+        Self(cidrs)
+    }
+
+    fn trusts(&self, ip: IpAddr) -> bool {
+        self.0.iter().any(|cidr| cidr.contains(ip))
+    }
+
+    /// The address a request comes from: its peer, or when that is a
+    /// trusted proxy, the nearest hop of `X-Forwarded-For` that isn't one.
+    /// A request without a peer came over the unix socket, from a proxy on
+    /// the same host.
+    pub fn client_ip<B>(&self, req: &Request<B>) -> Option<IpAddr> {
+        let peer = req
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip().to_canonical());
+        if let Some(peer) = peer.filter(|peer| !self.trusts(*peer)) {
+            return Some(peer);
+        }
+        let hops: Vec<&str> = req
+            .headers()
+            .get_all(X_FORWARDED_FOR)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect();
+        let mut client = peer;
+        for hop in hops.iter().rev() {
+            match hop.parse::<IpAddr>() {
+                Ok(ip) => client = Some(ip.to_canonical()),
+                Err(_) => break,
+            }
+            if client.is_some_and(|client| !self.trusts(client)) {
+                break;
+            }
+        }
+        client
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use axum::body::Body;
+
+    use super::*;
+
+    fn request(peer: &str, forwarded_for: Option<&str>) -> Request<Body> {
+        let mut builder = Request::builder().uri("/todos");
+        if let Some(forwarded_for) = forwarded_for {
+            builder = builder.header(X_FORWARDED_FOR, forwarded_for);
+        }
+        let mut req = builder.body(Body::empty()).unwrap();
+        let peer: SocketAddr = peer.parse().unwrap();
+        req.extensions_mut().insert(ConnectInfo(peer));
+        req
+    }
+
+    fn ip(ip: &str) -> Option<IpAddr> {
+        Some(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn follows_forwarded_for_through_trusted_proxies_only() {
+        let proxies = TrustedProxies::from_config(&ServerConfig {
+            trusted_proxies: vec!["10.0.0.0/8".to_string(), "::1".to_string()],
+            ..ServerConfig::default()
+        });
+
+        let req = request("203.0.113.9:4000", Some("198.51.100.1"));
+        assert_eq!(proxies.client_ip(&req), ip("203.0.113.9"));
+
+        let req = request("10.1.2.3:4000", Some("6.6.6.6, 198.51.100.1, 10.0.0.7"));
+        assert_eq!(proxies.client_ip(&req), ip("198.51.100.1"));
+
+        let req = request("[::1]:4000", None);
+        assert_eq!(proxies.client_ip(&req), ip("::1"));
+
+        let req = request("10.1.2.3:4000", Some("not an ip, 10.0.0.7"));
+        assert_eq!(proxies.client_ip(&req), ip("10.0.0.7"));
+
+        let req = request("[::ffff:10.0.0.1]:4000", Some("198.51.100.1"));
+        assert_eq!(proxies.client_ip(&req), ip("198.51.100.1"));
+
+        let mut req = request("10.1.2.3:4000", Some("198.51.100.1"));
+        req.extensions_mut().remove::<ConnectInfo<SocketAddr>>();
+        assert_eq!(proxies.client_ip(&req), ip("198.51.100.1"));
+    }
+
+    #[test]
+    fn parses_addresses_and_blocks() {
+        assert!("10.0.0.0/8".parse::<Cidr>().is_ok());
+        assert!("fd00::/8".parse::<Cidr>().is_ok());
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("localhost".parse::<Cidr>().is_err());
+        let any: Cidr = "0.0.0.0/0".parse().unwrap();
+        assert!(any.contains("8.8.8.8".parse().unwrap()));
+    }
+}
@@ -0,0 +1,263 @@
+use std::pin::Pin;
+
+use async_compression::tokio::bufread::{BrotliDecoder, GzipDecoder, ZlibDecoder};
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures_util::TryStreamExt;
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncReadExt};
+use tokio_util::io::{ReaderStream, StreamReader};
+use tower_http::compression::CompressionLayer;
+
+use super::body_limit::{is_under, BodyLimit};
+use crate::{
+    attachments,
+    config::{FeatureConfig, ServerConfig},
+    handlers::error::Problem,
+};
+
+/// Upper bound for a decompressed request body when `max_body_bytes`
+/// accepts bodies of any size, so a small compressed payload still can't
+/// expand into an arbitrary amount of memory.
+const MAX_DECOMPRESSED_BYTES: u64 = 10 * 1024 * 1024;
+
+/// The size a request body may inflate to: `max_body_bytes`, the most an
+/// uncompressed one may send, or [`MAX_DECOMPRESSED_BYTES`] without it.
+pub fn inflate_limit(config: &ServerConfig) -> BodyLimit {
+    BodyLimit::from_config(config).unwrap_or(BodyLimit(MAX_DECOMPRESSED_BYTES))
+}
+
+/// Response compression from `compression`, a comma separated list of
+/// `gzip`, `br` and `deflate` (default: all of them). `off` disables it.
+pub fn compression_from_config(config: &FeatureConfig) -> Option<CompressionLayer> {
+    let algorithms: Vec<&str> = config.compression.split(',').map(str::trim).collect();
+    if algorithms.iter().all(|a| matches!(*a, "" | "off" | "none")) {
+        return None;
+    }
+    for algorithm in &algorithms {
+        if !matches!(*algorithm, "gzip" | "br" | "deflate") {
+            panic!("invalid [COMPRESSION] algorithm: {}", algorithm);
+        }
+    }
+
+    Some(
+        CompressionLayer::new()
+            .gzip(algorithms.contains(&"gzip"))
+            .br(algorithms.contains(&"br"))
+            .deflate(algorithms.contains(&"deflate")),
+    )
+}
+
+/// A `Content-Encoding` request bodies may be sent with.
+#[derive(Debug, Clone, Copy)]
+enum Encoding {
+    Gzip,
+    Deflate,
+    Brotli,
+}
+
+impl Encoding {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "gzip" | "x-gzip" => Some(Self::Gzip),
+            "deflate" => Some(Self::Deflate),
+            "br" => Some(Self::Brotli),
+            _ => None,
+        }
+    }
+
+    /// `reader` inflated.
+    fn decoder<'a>(
+        self,
+        reader: impl AsyncBufRead + Unpin + Send + 'a,
+    ) -> Pin<Box<dyn AsyncRead + Send + 'a>> {
+        match self {
+            Self::Gzip => Box::pin(GzipDecoder::new(reader)),
+            Self::Deflate => Box::pin(ZlibDecoder::new(reader)),
+            Self::Brotli => Box::pin(BrotliDecoder::new(reader)),
+        }
+    }
+}
+
+async fn decode(
+    reader: impl AsyncRead + Unpin,
+    limit: BodyLimit,
+) -> std::io::Result<Option<Vec<u8>>> {
+    let mut decoded = Vec::new();
+    reader.take(limit.0 + 1).read_to_end(&mut decoded).await?;
+    Ok((decoded.len() as u64 <= limit.0).then_some(decoded))
+}
+
+/// `body` inflated as it is read, rather than buffered first.
+fn inflating(body: Body, encoding: Encoding) -> Body {
+    let compressed = StreamReader::new(body.map_err(std::io::Error::other));
+    Body::wrap_stream(ReaderStream::new(encoding.decoder(compressed)))
+}
+
+fn problem(status: StatusCode, kind: &str, detail: String, path: &str) -> Response {
+    let mut problem = Problem::new(status, kind, detail);
+    problem.instance = Some(path.to_string());
+    problem.into_response()
+}
+
+/// Transparently inflates request bodies sent with `Content-Encoding`
+/// gzip, deflate or br, answering `413 Payload Too Large` to one that
+/// inflates past `limit`. Attachments are inflated as they are streamed to
+/// their store, which cuts them off at `[attachments] max_bytes` instead.
+pub async fn decompress_request(
+    req: Request<Body>,
+    next: Next<Body>,
+    limit: BodyLimit,
+) -> Response {
+    let Some(name) = req
+        .headers()
+        .get(header::CONTENT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().to_ascii_lowercase())
+        .filter(|value| value != "identity")
+    else {
+        return next.run(req).await;
+    };
+
+    let path = req.uri().path().to_string();
+    let Some(encoding) = Encoding::parse(&name) else {
+        return problem(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "unsupported-encoding",
+            format!("Unsupported Content-Encoding: [{}]", name),
+            &path,
+        );
+    };
+    let (mut parts, body) = req.into_parts();
+    parts.headers.remove(header::CONTENT_ENCODING);
+    if is_under(&path, attachments::PATH) {
+        parts.headers.remove(header::CONTENT_LENGTH);
+        let body = inflating(body, encoding);
+        return next.run(Request::from_parts(parts, body)).await;
+    }
+
+    let compressed = match hyper::body::to_bytes(body).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            return problem(StatusCode::BAD_REQUEST, "bad-request", e.to_string(), &path);
+        }
+    };
+    let decoded = match decode(encoding.decoder(&compressed[..]), limit).await {
+        Ok(Some(decoded)) => decoded,
+        Ok(None) => {
+            return problem(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "payload-too-large",
+                format!("Decompressed body exceeds {} bytes", limit.0),
+                &path,
+            );
+        }
+        Err(e) => {
+            return problem(StatusCode::BAD_REQUEST, "bad-request", e.to_string(), &path);
+        }
+    };
+
+    parts
+        .headers
+        .insert(header::CONTENT_LENGTH, decoded.len().into());
+    next.run(Request::from_parts(parts, Body::from(decoded)))
+        .await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use async_compression::tokio::write::GzipEncoder;
+    use axum::{middleware, routing::post, Router};
+    use tokio::io::AsyncWriteExt;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn inflates_gzip_request_bodies() {
+        let mut encoder = GzipEncoder::new(Vec::new());
+        encoder.write_all(b"hello compressed").await.unwrap();
+        encoder.shutdown().await.unwrap();
+
+        let app = Router::new()
+            .route("/", post(|body: String| async move { body }))
+            .layer(middleware::from_fn(|req, next| {
+                decompress_request(req, next, BodyLimit(1024))
+            }));
+        let req = Request::builder()
+            .method("POST")
+            .uri("/")
+            .header(header::CONTENT_ENCODING, "gzip")
+            .body(Body::from(encoder.into_inner()))
+            .unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"hello compressed");
+
+        let req = Request::builder()
+            .method("POST")
+            .uri("/")
+            .header(header::CONTENT_ENCODING, "zstd")
+            .body(Body::from("x"))
+            .unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[tokio::test]
+    async fn refuses_bodies_inflating_past_the_limit() {
+        let mut encoder = GzipEncoder::new(Vec::new());
+        encoder.write_all(&[b'0'; 64 * 1024]).await.unwrap();
+        encoder.shutdown().await.unwrap();
+        let bomb = encoder.into_inner();
+        assert!(bomb.len() < 1024);
+
+        let app = Router::new()
+            .route("/todos", post(|body: String| async move { body }))
+            .layer(middleware::from_fn(|req, next| {
+                decompress_request(req, next, BodyLimit(1024))
+            }));
+        let req = Request::builder()
+            .method("POST")
+            .uri("/todos")
+            .header(header::CONTENT_ENCODING, "gzip")
+            .body(Body::from(bomb))
+            .unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let problem: Problem = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(problem.detail, "Decompressed body exceeds 1024 bytes");
+    }
+
+    #[tokio::test]
+    async fn streams_attachments_inflated_past_the_limit() {
+        let mut encoder = GzipEncoder::new(Vec::new());
+        encoder.write_all(&[b'0'; 64 * 1024]).await.unwrap();
+        encoder.shutdown().await.unwrap();
+
+        let app = Router::new()
+            .route(
+                "/attachments/1/zeros.txt",
+                post(|req: Request<Body>| async move {
+                    assert!(req.headers().get(header::CONTENT_LENGTH).is_none());
+                    let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+                    body.len().to_string()
+                }),
+            )
+            .layer(middleware::from_fn(|req, next| {
+                decompress_request(req, next, BodyLimit(1024))
+            }));
+        let req = Request::builder()
+            .method("POST")
+            .uri("/attachments/1/zeros.txt")
+            .header(header::CONTENT_ENCODING, "gzip")
+            .body(Body::from(encoder.into_inner()))
+            .unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"65536");
+    }
+}
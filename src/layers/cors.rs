@@ -0,0 +1,187 @@
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use axum::http::{header, HeaderName, HeaderValue, Method};
+use tower_http::cors::{CorsLayer, Origin};
+
+use super::request_id::X_REQUEST_ID;
+use crate::config::CorsConfig;
+
+/// How long browsers may cache a preflight answer.
+const MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// The origins browsers may call the API from, which a reload can change.
+#[derive(Debug, Clone, Default)]
+pub struct AllowedOrigins(Arc<RwLock<Origins>>);
+
+#[derive(Debug, Default)]
+struct Origins {
+    any: bool,
+    listed: Vec<HeaderValue>,
+}
+
+impl AllowedOrigins {
+    /// `allowed_origins`, any when one is `*`.
+    pub fn from_config(config: &CorsConfig) -> Self {
+        let origins = Self::default();
+        origins
+            .set(config)
+            .unwrap_or_else(|e| panic!("invalid [CORS_ALLOWED_ORIGINS]: {}", e));
+        origins
+    }
+
+    /// Replaces the origins with `allowed_origins`, unless one is invalid.
+    pub fn set(&self, config: &CorsConfig) -> anyhow::Result<()> {
+        let listed = config
+            .allowed_origins
+            .iter()
+            .filter(|origin| *origin != "*")
+            .map(|origin| {
+                origin
+                    .parse()
+                    .map_err(|e| anyhow::anyhow!("{}, {}", origin, e))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        let any = config.allowed_origins.iter().any(|origin| origin == "*");
+        *self.0.write().unwrap_or_else(|e| e.into_inner()) = Origins { any, listed };
+        Ok(())
+    }
+
+    fn allows(&self, origin: &HeaderValue) -> bool {
+        let origins = self.0.read().unwrap_or_else(|e| e.into_inner());
+        origins.any || origins.listed.contains(origin)
+    }
+}
+
+/// Lets browsers on the `origins` call the API, any origin in dev mode.
+/// Preflights list the methods explicitly, so PATCH and DELETE pass
+/// browsers that ignore a wildcard, and the headers clients send and read:
+/// tokens, conditional requests, request ids.
+pub fn cors_layer(origins: AllowedOrigins, dev_mode: bool) -> CorsLayer {
+    CorsLayer::new()
+        .allow_methods(vec![
+            Method::GET,
+            Method::HEAD,
+            Method::POST,
+            Method::PUT,
+            Method::PATCH,
+            Method::DELETE,
+        ])
+        .allow_headers(vec![
+            header::CONTENT_TYPE,
+            header::AUTHORIZATION,
+            header::ACCEPT_LANGUAGE,
+            header::IF_MODIFIED_SINCE,
+            HeaderName::from_static(X_REQUEST_ID),
+        ])
+        .expose_headers(vec![
+            header::ETAG,
+            header::LAST_MODIFIED,
+            header::LOCATION,
+            header::LINK,
+            header::RETRY_AFTER,
+            HeaderName::from_static(X_REQUEST_ID),
+        ])
+        .max_age(MAX_AGE)
+        .allow_origin(Origin::predicate(move |origin, _| {
+            dev_mode || origins.allows(origin)
+        }))
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{body::Body, http::Request, routing::patch, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn app(config: &CorsConfig, dev_mode: bool) -> Router {
+        Router::new()
+            .route("/todos/1", patch(|| async { "patched" }))
+            .layer(cors_layer(AllowedOrigins::from_config(config), dev_mode))
+    }
+
+    fn preflight(origin: &str) -> Request<Body> {
+        Request::builder()
+            .method(Method::OPTIONS)
+            .uri("/todos/1")
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "PATCH")
+            .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "content-type")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn answers_preflights_from_allowed_origins() {
+        let config = CorsConfig {
+            allowed_origins: vec![
+                "https://todos.example".to_string(),
+                "https://admin.example".to_string(),
+            ],
+        };
+        let res = app(&config, false)
+            .oneshot(preflight("https://admin.example"))
+            .await
+            .unwrap();
+        assert!(res.status().is_success());
+        let headers = res.headers();
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://admin.example"
+        );
+        let methods = headers[header::ACCESS_CONTROL_ALLOW_METHODS]
+            .to_str()
+            .unwrap();
+        assert!(methods.contains("PATCH") && methods.contains("DELETE"));
+
+        let res = app(&config, false)
+            .oneshot(preflight("https://elsewhere.example"))
+            .await
+            .unwrap();
+        assert!(!res
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+    }
+
+    #[tokio::test]
+    async fn lets_any_origin_in_dev_mode() {
+        let res = app(&CorsConfig::default(), true)
+            .oneshot(preflight("http://localhost:5173"))
+            .await
+            .unwrap();
+        assert_eq!(
+            res.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "http://localhost:5173"
+        );
+    }
+
+    #[tokio::test]
+    async fn follows_changes_to_the_origins() {
+        let origins = AllowedOrigins::from_config(&CorsConfig::default());
+        let app = Router::new()
+            .route("/todos/1", patch(|| async { "patched" }))
+            .layer(cors_layer(origins.clone(), false));
+        let allowed = |res: axum::response::Response| {
+            res.headers()
+                .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+        };
+
+        let req = preflight("https://todos.example");
+        assert!(!allowed(app.clone().oneshot(req).await.unwrap()));
+        let config = CorsConfig {
+            allowed_origins: vec!["https://todos.example".to_string()],
+        };
+        origins.set(&config).unwrap();
+        let req = preflight("https://todos.example");
+        assert!(allowed(app.oneshot(req).await.unwrap()));
+
+        let config = CorsConfig {
+            allowed_origins: vec!["bad\norigin".to_string()],
+        };
+        assert!(origins.set(&config).is_err());
+        assert!(origins.allows(&HeaderValue::from_static("https://todos.example")));
+    }
+}
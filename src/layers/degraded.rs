@@ -0,0 +1,222 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use axum::{
+    body::{self, Bytes},
+    http::{header, HeaderMap, HeaderValue, Method, Request, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use http_body::Body as _;
+
+use crate::{config::FeatureConfig, health::Health};
+
+/// Largest response body kept for replay.
+const MAX_BODY: u64 = 1024 * 1024;
+
+/// RFC 7234 warnings: served without asking the database, or after asking
+/// it failed.
+const STALE: &str = "110 - \"Response is Stale\"";
+const REVALIDATION_FAILED: &str = "111 - \"Revalidation Failed\"";
+
+#[derive(Debug, Clone)]
+struct Stored {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+    at: Instant,
+}
+
+/// The last successful answer to each GET, replayed while the database is
+/// unreachable.
+#[derive(Debug, Clone)]
+pub struct StaleResponses {
+    capacity: usize,
+    entries: Arc<Mutex<HashMap<String, Stored>>>,
+}
+
+impl StaleResponses {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Arc::default(),
+        }
+    }
+
+    /// Enabled by `degraded_reads`, keeping up to
+    /// `degraded_reads_capacity` responses; off by default.
+    pub fn from_config(config: &FeatureConfig) -> Option<Self> {
+        config
+            .degraded_reads
+            .then(|| Self::new(config.degraded_reads_capacity))
+    }
+
+    fn get(&self, key: &str) -> Option<Stored> {
+        self.entries.lock().unwrap().get(key).cloned()
+    }
+
+    fn put(&self, key: String, stored: Stored) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.capacity && !entries.contains_key(&key) {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, stored)| stored.at)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(key, stored);
+    }
+}
+
+/// Answers vary by representation and language, so those are part of the key.
+fn cache_key<B>(req: &Request<B>) -> String {
+    let header = |name| {
+        req.headers()
+            .get(name)
+            .and_then(|value: &HeaderValue| value.to_str().ok())
+            .unwrap_or_default()
+    };
+    format!(
+        "{} {} {}",
+        req.uri(),
+        header(header::ACCEPT),
+        header(header::ACCEPT_LANGUAGE)
+    )
+}
+
+fn replay(stored: Stored, warning: &'static str) -> Response {
+    let mut res = Response::new(body::boxed(body::Full::from(stored.body)));
+    *res.status_mut() = stored.status;
+    *res.headers_mut() = stored.headers;
+    res.headers_mut()
+        .insert(header::WARNING, HeaderValue::from_static(warning));
+    res
+}
+
+/// Keeps successful GET answers in `stale` and replays them, marked with a
+/// `Warning` header, while `health` reports the database down or when the
+/// handler fails with a server error. Streamed answers (exports, events)
+/// are never kept.
+pub async fn degraded_reads<B>(
+    req: Request<B>,
+    next: Next<B>,
+    stale: StaleResponses,
+    health: Health,
+) -> Response {
+    if req.method() != Method::GET {
+        return next.run(req).await;
+    }
+    let key = cache_key(&req);
+    if !health.database_up() {
+        if let Some(stored) = stale.get(&key) {
+            return replay(stored, STALE);
+        }
+    }
+
+    let res = next.run(req).await;
+    if res.status().is_server_error() {
+        return match stale.get(&key) {
+            Some(stored) => replay(stored, REVALIDATION_FAILED),
+            None => res,
+        };
+    }
+    let size = res.body().size_hint().exact();
+    if res.status() != StatusCode::OK || size.is_none_or(|size| size > MAX_BODY) {
+        return res;
+    }
+
+    let (mut parts, b) = res.into_parts();
+    let bytes = match hyper::body::to_bytes(b).await {
+        Ok(bytes) => bytes,
+        Err(_) => return Response::from_parts(parts, body::boxed(body::Empty::new())),
+    };
+    parts.headers.remove(header::CONTENT_LENGTH);
+    stale.put(
+        key,
+        Stored {
+            status: parts.status,
+            headers: parts.headers.clone(),
+            body: bytes.clone(),
+            at: Instant::now(),
+        },
+    );
+    Response::from_parts(parts, body::boxed(body::Full::from(bytes)))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use axum::{body::Body, middleware, routing::get, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn app(stale: StaleResponses, health: Health, failing: Arc<AtomicBool>) -> Router {
+        Router::new()
+            .route(
+                "/todos",
+                get(move || {
+                    let failing = failing.clone();
+                    async move {
+                        if failing.load(Ordering::Relaxed) {
+                            Err(StatusCode::INTERNAL_SERVER_ERROR)
+                        } else {
+                            Ok("[]")
+                        }
+                    }
+                }),
+            )
+            .layer(middleware::from_fn(move |req, next| {
+                degraded_reads(req, next, stale.clone(), health.clone())
+            }))
+    }
+
+    async fn get_todos(app: &Router) -> Response {
+        let req = Request::get("/todos").body(Body::empty()).unwrap();
+        app.clone().oneshot(req).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn replays_the_last_answer_while_the_database_is_down() {
+        let health = Health::default();
+        let failing = Arc::new(AtomicBool::new(false));
+        let app = app(StaleResponses::new(10), health.clone(), failing.clone());
+
+        let res = get_todos(&app).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.headers().get(header::WARNING).is_none());
+
+        health.record(Err("connection refused".to_string()));
+        let res = get_todos(&app).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::WARNING], STALE);
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&bytes[..], b"[]");
+
+        health.record(Ok(()));
+        failing.store(true, Ordering::Relaxed);
+        let res = get_todos(&app).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::WARNING], REVALIDATION_FAILED);
+    }
+
+    #[tokio::test]
+    async fn fails_as_usual_without_a_stored_answer() {
+        let health = Health::default();
+        health.record(Err("connection refused".to_string()));
+        let app = app(
+            StaleResponses::new(10),
+            health,
+            Arc::new(AtomicBool::new(true)),
+        );
+
+        let res = get_todos(&app).await;
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
@@ -0,0 +1,89 @@
+use axum::{
+    http::{Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::handlers::error::Problem;
+
+/// Endpoints for operators rather than clients: administration and the
+/// stats. With an admin port they are served there only.
+pub fn internal(path: &str) -> bool {
+    path.starts_with("/admin/") || matches!(path, "/cache/stats" | "/repository/stats")
+}
+
+/// Answers `404` to [`internal`] endpoints, on the public port when they
+/// have their own.
+pub async fn hide_internal<B>(req: Request<B>, next: Next<B>) -> Response {
+    if !internal(req.uri().path()) {
+        return next.run(req).await;
+    }
+    not_found(req.uri().path())
+}
+
+/// Answers `404` to everything but the [`internal`] endpoints and the
+/// health probes, on the admin port. The probes stay on the public port
+/// too.
+pub async fn only_internal<B>(req: Request<B>, next: Next<B>) -> Response {
+    let path = req.uri().path();
+    if internal(path) || matches!(path, "/healthz" | "/readyz") {
+        return next.run(req).await;
+    }
+    not_found(req.uri().path())
+}
+
+fn not_found(path: &str) -> Response {
+    let mut problem = Problem::new(StatusCode::NOT_FOUND, "not-found", "Not Found".to_string());
+    problem.instance = Some(path.to_string());
+    problem.into_response()
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{body::Body, middleware, routing::get, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn hides_admin_and_stats_endpoints() {
+        let app = Router::new()
+            .route("/todos", get(|| async { "todos" }))
+            .route("/healthz", get(|| async { "alive" }))
+            .route("/admin/flags", get(|| async { "flags" }))
+            .route("/repository/stats", get(|| async { "stats" }))
+            .layer(middleware::from_fn(hide_internal));
+        for (uri, status) in [
+            ("/todos", StatusCode::OK),
+            ("/healthz", StatusCode::OK),
+            ("/admin/flags", StatusCode::NOT_FOUND),
+            ("/repository/stats", StatusCode::NOT_FOUND),
+        ] {
+            let req = Request::get(uri).body(Body::empty()).unwrap();
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), status, "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn serves_only_admin_and_stats_endpoints() {
+        let app = Router::new()
+            .route("/todos", get(|| async { "todos" }))
+            .route("/healthz", get(|| async { "alive" }))
+            .route("/readyz", get(|| async { "ready" }))
+            .route("/admin/flags", get(|| async { "flags" }))
+            .route("/cache/stats", get(|| async { "stats" }))
+            .layer(middleware::from_fn(only_internal));
+        for (uri, status) in [
+            ("/todos", StatusCode::NOT_FOUND),
+            ("/healthz", StatusCode::OK),
+            ("/readyz", StatusCode::OK),
+            ("/admin/flags", StatusCode::OK),
+            ("/cache/stats", StatusCode::OK),
+        ] {
+            let req = Request::get(uri).body(Body::empty()).unwrap();
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), status, "{}", uri);
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use std::sync::Arc;
+
+use axum::{
+    http::{header, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tokio::sync::Semaphore;
+
+use crate::{
+    config::ServerConfig, handlers::error::Problem, repositories::instrument::QueryMetrics,
+};
+
+/// Clients are told to come back this soon, as overload tends to pass.
+const RETRY_AFTER_SECS: u64 = 1;
+
+/// Turns requests away with a `503` rather than letting them queue while
+/// the server is saturated, so those already in flight keep their latency.
+#[derive(Debug, Clone)]
+pub struct LoadShed {
+    permits: Option<Arc<Semaphore>>,
+    /// The repository calls running and how many connections the pool has.
+    pool: Option<(Arc<QueryMetrics>, usize)>,
+}
+
+impl LoadShed {
+    /// Up to `max_concurrent_requests` at once, and with
+    /// `shed_on_busy_pool` none while `max_connections` repository calls
+    /// are running. `None` when neither is set; `max_connections` is
+    /// `None` on backends without a pool.
+    pub fn from_config(
+        config: &ServerConfig,
+        metrics: Arc<QueryMetrics>,
+        max_connections: Option<u32>,
+    ) -> Option<Self> {
+        let permits = (config.max_concurrent_requests > 0)
+            .then(|| Arc::new(Semaphore::new(config.max_concurrent_requests)));
+        let pool = max_connections
+            .filter(|_| config.shed_on_busy_pool)
+            .map(|max| (metrics, max as usize));
+        (permits.is_some() || pool.is_some()).then_some(Self { permits, pool })
+    }
+
+    fn pool_busy(&self) -> bool {
+        self.pool
+            .as_ref()
+            .is_some_and(|(metrics, max)| metrics.in_flight() >= *max)
+    }
+}
+
+fn overloaded(path: &str) -> Response {
+    let mut problem = Problem::new(
+        StatusCode::SERVICE_UNAVAILABLE,
+        "overloaded",
+        "The server is busy, try again later".to_string(),
+    );
+    problem.instance = Some(path.to_string());
+    let mut res = problem.into_response();
+    res.headers_mut()
+        .insert(header::RETRY_AFTER, HeaderValue::from(RETRY_AFTER_SECS));
+    res
+}
+
+/// Answers `503` with a `Retry-After` when `shed` says the server is
+/// saturated, but to probes, which would otherwise take it out of rotation.
+/// A streamed response stops counting once its headers are sent.
+pub async fn shed_load<B>(req: Request<B>, next: Next<B>, shed: LoadShed) -> Response {
+    if matches!(req.uri().path(), "/healthz" | "/readyz") {
+        return next.run(req).await;
+    }
+    if shed.pool_busy() {
+        return overloaded(req.uri().path());
+    }
+    let _permit = match shed.permits.map(Semaphore::try_acquire_owned) {
+        Some(Err(_)) => return overloaded(req.uri().path()),
+        permit => permit,
+    };
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use axum::{body::Body, middleware, routing::get, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn app(shed: LoadShed) -> Router {
+        Router::new()
+            .route(
+                "/slow",
+                get(|| async {
+                    tokio::time::sleep(Duration::from_millis(200)).await;
+                    "done"
+                }),
+            )
+            .route("/healthz", get(|| async { "alive" }))
+            .layer(middleware::from_fn(move |req, next| {
+                shed_load(req, next, shed.clone())
+            }))
+    }
+
+    fn request(uri: &str) -> Request<Body> {
+        Request::get(uri).body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn sheds_requests_over_the_limit() {
+        let config = ServerConfig {
+            max_concurrent_requests: 1,
+            ..ServerConfig::default()
+        };
+        let shed = LoadShed::from_config(&config, Arc::default(), None).unwrap();
+        let app = app(shed);
+
+        let first = tokio::spawn(app.clone().oneshot(request("/slow")));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let res = app.clone().oneshot(request("/slow")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(res.headers()[header::RETRY_AFTER], "1");
+        let res = app.clone().oneshot(request("/healthz")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        assert_eq!(first.await.unwrap().unwrap().status(), StatusCode::OK);
+        let res = app.oneshot(request("/slow")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn is_off_unless_configured() {
+        let config = ServerConfig::default();
+        assert!(LoadShed::from_config(&config, Arc::default(), Some(10)).is_none());
+        let config = ServerConfig {
+            shed_on_busy_pool: true,
+            ..ServerConfig::default()
+        };
+        assert!(LoadShed::from_config(&config, Arc::default(), None).is_none());
+        let shed = LoadShed::from_config(&config, Arc::default(), Some(10)).unwrap();
+        assert!(!shed.pool_busy());
+    }
+}
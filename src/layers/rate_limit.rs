@@ -0,0 +1,334 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    http::{header, HeaderMap, HeaderValue, Method, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use super::client_ip::TrustedProxies;
+use crate::{config::FeatureConfig, handlers::error::Problem};
+
+const PRUNE_THRESHOLD: usize = 10_000;
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    /// Until the bucket is full again.
+    pub reset: Duration,
+    /// Until the next request would be let through.
+    pub retry_after: Duration,
+}
+
+fn ceil_secs(duration: Duration) -> u64 {
+    duration.as_secs() + u64::from(duration.subsec_nanos() > 0)
+}
+
+impl Decision {
+    fn write_headers(&self, headers: &mut HeaderMap) {
+        headers.insert("x-ratelimit-limit", HeaderValue::from(self.limit));
+        headers.insert("x-ratelimit-remaining", HeaderValue::from(self.remaining));
+        headers.insert(
+            "x-ratelimit-reset",
+            HeaderValue::from(ceil_secs(self.reset)),
+        );
+    }
+}
+
+/// How many requests a client may send.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Limits {
+    burst: u32,
+    /// Tokens per second.
+    rate: f64,
+    reads: bool,
+}
+
+impl Limits {
+    /// `None` when `rate_limit_requests` is unset.
+    fn from_config(config: &FeatureConfig) -> anyhow::Result<Option<Self>> {
+        let Some(limit) = config.rate_limit_requests else {
+            return Ok(None);
+        };
+        if limit == 0 || config.rate_limit_window_secs == 0 {
+            anyhow::bail!(
+                "invalid [RATE_LIMIT_REQUESTS]: {} per {} seconds",
+                limit,
+                config.rate_limit_window_secs
+            );
+        }
+        Ok(Some(Self {
+            burst: config.rate_limit_burst.unwrap_or(limit).max(1),
+            rate: f64::from(limit) / config.rate_limit_window_secs as f64,
+            reads: config.rate_limit_reads,
+        }))
+    }
+
+    fn counts<B>(&self, req: &Request<B>) -> bool {
+        self.reads || !matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS)
+    }
+}
+
+/// Token bucket per client ip: `burst` requests at once, refilled at
+/// `limit` per `window`. Only requests that change data are counted unless
+/// reads are too. The limits can change while running, buckets are kept.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    limits: Arc<RwLock<Option<Limits>>>,
+    proxies: TrustedProxies,
+    clients: Arc<Mutex<HashMap<String, Bucket>>>,
+}
+
+impl RateLimiter {
+    #[cfg(test)]
+    pub fn new(limit: u32, window: Duration) -> Self {
+        let limits = Limits {
+            burst: limit,
+            rate: f64::from(limit) / window.as_secs_f64(),
+            reads: false,
+        };
+        Self {
+            limits: Arc::new(RwLock::new(Some(limits))),
+            proxies: TrustedProxies::default(),
+            clients: Arc::default(),
+        }
+    }
+
+    /// `rate_limit_requests` per `rate_limit_window_secs` (default 60), in
+    /// bursts of up to `rate_limit_burst` (default `rate_limit_requests`).
+    /// Throttling is off unless `rate_limit_requests` is set.
+    pub fn from_config(config: &FeatureConfig) -> Self {
+        let limiter = Self {
+            limits: Arc::default(),
+            proxies: TrustedProxies::default(),
+            clients: Arc::default(),
+        };
+        limiter.set(config).unwrap_or_else(|e| panic!("{}", e));
+        limiter
+    }
+
+    /// Replaces the limits with those of `config`, unless they are invalid.
+    pub fn set(&self, config: &FeatureConfig) -> anyhow::Result<()> {
+        let limits = Limits::from_config(config)?;
+        *self.limits.write().unwrap_or_else(|e| e.into_inner()) = limits;
+        Ok(())
+    }
+
+    fn limits(&self) -> Option<Limits> {
+        *self.limits.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.limits().is_some()
+    }
+
+    /// Tells clients apart by the `X-Forwarded-For` of `proxies`.
+    pub fn behind(mut self, proxies: TrustedProxies) -> Self {
+        self.proxies = proxies;
+        self
+    }
+
+    /// Takes a token from `client`'s bucket; `None` while throttling is off.
+    pub fn check(&self, client: &str) -> Option<Decision> {
+        let limits = self.limits()?;
+        let now = Instant::now();
+        let burst = f64::from(limits.burst);
+        let mut clients = self.clients.lock().unwrap();
+        if clients.len() > PRUNE_THRESHOLD {
+            clients.retain(|_, b| {
+                b.tokens + now.duration_since(b.updated).as_secs_f64() * limits.rate < burst
+            });
+        }
+
+        let b = clients.entry(client.to_string()).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+        b.tokens =
+            (b.tokens + now.duration_since(b.updated).as_secs_f64() * limits.rate).min(burst);
+        b.updated = now;
+
+        let allowed = b.tokens >= 1.0;
+        if allowed {
+            b.tokens -= 1.0;
+        }
+
+        Some(Decision {
+            allowed,
+            limit: limits.burst,
+            remaining: b.tokens as u32,
+            reset: Duration::from_secs_f64((burst - b.tokens) / limits.rate),
+            retry_after: Duration::from_secs_f64((1.0 - b.tokens).max(0.0) / limits.rate),
+        })
+    }
+
+    fn client_key<B>(&self, req: &Request<B>) -> String {
+        self.proxies
+            .client_ip(req)
+            .map(|ip| ip.to_string())
+            .unwrap_or_else(|| "unknown".to_string())
+    }
+}
+
+pub async fn rate_limit<B>(req: Request<B>, next: Next<B>, limiter: RateLimiter) -> Response {
+    if !limiter.limits().is_some_and(|limits| limits.counts(&req)) {
+        return next.run(req).await;
+    }
+    let Some(decision) = limiter.check(&limiter.client_key(&req)) else {
+        return next.run(req).await;
+    };
+
+    let mut res = if decision.allowed {
+        next.run(req).await
+    } else {
+        let mut problem = Problem::new(
+            StatusCode::TOO_MANY_REQUESTS,
+            "rate-limited",
+            format!(
+                "Rate limit of {} requests exceeded, retry in {} seconds",
+                decision.limit,
+                ceil_secs(decision.retry_after)
+            ),
+        );
+        problem.instance = Some(req.uri().path().to_string());
+        let mut res = problem.into_response();
+        res.headers_mut().insert(
+            header::RETRY_AFTER,
+            HeaderValue::from(ceil_secs(decision.retry_after)),
+        );
+        res
+    };
+
+    decision.write_headers(res.headers_mut());
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::SocketAddr;
+
+    use axum::{body::Body, extract::ConnectInfo, middleware, routing::get, Router};
+    use tower::ServiceExt;
+
+    #[test]
+    fn counts_down_and_blocks() {
+        let limiter = RateLimiter::new(2, Duration::from_secs(60));
+
+        assert_eq!(limiter.check("a").unwrap().remaining, 1);
+        assert!(limiter.check("a").unwrap().allowed);
+        let blocked = limiter.check("a").unwrap();
+        assert!(!blocked.allowed);
+        assert_eq!(blocked.remaining, 0);
+
+        assert!(limiter.check("b").unwrap().allowed);
+    }
+
+    #[test]
+    fn refills_the_bucket_over_time() {
+        let limiter = RateLimiter::new(2, Duration::from_millis(200));
+        for _ in 0..2 {
+            assert!(limiter.check("a").unwrap().allowed);
+        }
+        assert!(!limiter.check("a").unwrap().allowed);
+        std::thread::sleep(Duration::from_millis(120));
+        assert!(limiter.check("a").unwrap().allowed);
+    }
+
+    #[tokio::test]
+    async fn rejects_with_429_and_headers() {
+        let limiter = RateLimiter::new(1, Duration::from_secs(60));
+        let app = Router::new()
+            .route("/", get(|| async { "ok" }).post(|| async { "ok" }))
+            .layer(middleware::from_fn(move |req, next| {
+                rate_limit(req, next, limiter.clone())
+            }));
+
+        let req = || Request::post("/").body(Body::empty()).unwrap();
+        let res = app.clone().oneshot(req()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()["x-ratelimit-limit"], "1");
+        assert_eq!(res.headers()["x-ratelimit-remaining"], "0");
+
+        let res = app.clone().oneshot(req()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(res.headers()[header::RETRY_AFTER], "60");
+        assert_eq!(res.headers()["x-ratelimit-reset"], "60");
+
+        let res = app
+            .oneshot(Request::get("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(!res.headers().contains_key("x-ratelimit-limit"));
+    }
+
+    #[test]
+    fn takes_new_limits_and_keeps_the_buckets() {
+        let mut config = FeatureConfig::default();
+        let limiter = RateLimiter::from_config(&config);
+        assert!(!limiter.enabled());
+        assert_eq!(limiter.check("a"), None);
+
+        config.rate_limit_requests = Some(2);
+        limiter.set(&config).unwrap();
+        assert_eq!(limiter.check("a").unwrap().remaining, 1);
+        config.rate_limit_requests = Some(10);
+        limiter.set(&config).unwrap();
+        assert_eq!(limiter.check("a").unwrap().remaining, 0);
+        assert_eq!(limiter.check("a").unwrap().limit, 10);
+
+        config.rate_limit_window_secs = 0;
+        assert!(limiter.set(&config).is_err());
+        assert!(limiter.enabled());
+    }
+
+    #[test]
+    fn tells_clients_behind_a_proxy_apart() {
+        let proxies = TrustedProxies::from_config(&crate::config::ServerConfig {
+            trusted_proxies: vec!["10.0.0.1".to_string()],
+            ..Default::default()
+        });
+        let limiter = RateLimiter::new(1, Duration::from_secs(60)).behind(proxies);
+        let req = |client: &str| {
+            let mut req = Request::post("/")
+                .header("x-forwarded-for", client)
+                .body(Body::empty())
+                .unwrap();
+            let proxy: SocketAddr = "10.0.0.1:4000".parse().unwrap();
+            req.extensions_mut().insert(ConnectInfo(proxy));
+            req
+        };
+
+        assert!(
+            limiter
+                .check(&limiter.client_key(&req("198.51.100.1")))
+                .unwrap()
+                .allowed
+        );
+        assert!(
+            limiter
+                .check(&limiter.client_key(&req("198.51.100.2")))
+                .unwrap()
+                .allowed
+        );
+        assert!(
+            !limiter
+                .check(&limiter.client_key(&req("198.51.100.1")))
+                .unwrap()
+                .allowed
+        );
+    }
+}
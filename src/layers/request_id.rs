@@ -0,0 +1,82 @@
+use axum::{
+    http::{HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::repositories::id::Uuid;
+
+pub const X_REQUEST_ID: &str = "x-request-id";
+
+/// Longest `X-Request-Id` taken from a client, longer ones are replaced.
+const MAX_LEN: usize = 128;
+
+/// The id of the request being served, quoted back in its `X-Request-Id`
+/// response header, its `request` span and any problem it answers with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    /// The caller's id when it sent a usable one, e.g. from a proxy in
+    /// front, or a fresh uuid.
+    fn from_request<B>(req: &Request<B>) -> Self {
+        let id = req
+            .headers()
+            .get(X_REQUEST_ID)
+            .and_then(|value| value.to_str().ok())
+            .filter(|id| {
+                !id.is_empty() && id.len() <= MAX_LEN && id.chars().all(|c| c.is_ascii_graphic())
+            });
+        match id {
+            Some(id) => RequestId(id.to_string()),
+            None => RequestId(Uuid::now_v7().as_str().to_string()),
+        }
+    }
+}
+
+/// Gives every request a [`RequestId`] extension and echoes it back.
+pub async fn request_id<B>(mut req: Request<B>, next: Next<B>) -> Response {
+    let id = RequestId::from_request(&req);
+    let value = HeaderValue::from_str(&id.0).expect("checked to be visible ascii");
+    req.extensions_mut().insert(id);
+    let mut res = next.run(req).await;
+    res.headers_mut().insert(X_REQUEST_ID, value);
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{body::Body, extract::Extension, middleware, routing::get, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn keeps_the_callers_id_or_makes_one() {
+        let app = Router::new()
+            .route(
+                "/",
+                get(|Extension(id): Extension<RequestId>| async move { id.0 }),
+            )
+            .layer(middleware::from_fn(request_id));
+
+        let req = Request::builder()
+            .uri("/")
+            .header(X_REQUEST_ID, "from-the-proxy")
+            .body(Body::empty())
+            .unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.headers()[X_REQUEST_ID], "from-the-proxy");
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&bytes[..], b"from-the-proxy");
+
+        let req = Request::builder()
+            .uri("/")
+            .header(X_REQUEST_ID, "has spaces")
+            .body(Body::empty())
+            .unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        let id = res.headers()[X_REQUEST_ID].to_str().unwrap();
+        assert!(id.parse::<Uuid>().is_ok(), "{}", id);
+    }
+}
@@ -0,0 +1,135 @@
+use std::time::Duration;
+
+use axum::{
+    http::{Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::{config::ServerConfig, handlers::error::Problem};
+
+/// How long requests may take to be answered, so a stuck query gives the
+/// client a `504` rather than holding its connection open. Only the wait
+/// for the response to start counts: streams such as `/todos/events` run
+/// on once they have.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Timeouts {
+    default: Option<Duration>,
+    /// Longest prefix first.
+    routes: Vec<(String, Option<Duration>)>,
+}
+
+fn secs(secs: u64) -> Option<Duration> {
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+impl Timeouts {
+    /// `request_timeout_secs`, or the `route_timeouts` of the longest
+    /// matching prefix.
+    pub fn from_config(config: &ServerConfig) -> Self {
+        let mut routes: Vec<(String, Option<Duration>)> = config
+            .route_timeouts
+            .iter()
+            .map(|(prefix, timeout)| {
+                if !prefix.starts_with('/') {
+                    panic!(
+                        "invalid route timeout prefix: {}, must start with /",
+                        prefix
+                    );
+                }
+                (prefix.trim_end_matches('/').to_string(), secs(*timeout))
+            })
+            .collect();
+        routes.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+        Self {
+            default: secs(config.request_timeout_secs),
+            routes,
+        }
+    }
+
+    /// `/admin` covers `/admin` and `/admin/backup`, not `/administrators`.
+    fn for_path(&self, path: &str) -> Option<Duration> {
+        self.routes
+            .iter()
+            .find(|(prefix, _)| {
+                path.strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+            .map_or(self.default, |(_, timeout)| *timeout)
+    }
+}
+
+pub async fn request_timeout<B>(req: Request<B>, next: Next<B>, timeouts: Timeouts) -> Response {
+    let Some(timeout) = timeouts.for_path(req.uri().path()) else {
+        return next.run(req).await;
+    };
+    let path = req.uri().path().to_string();
+    match tokio::time::timeout(timeout, next.run(req)).await {
+        Ok(res) => res,
+        Err(_) => {
+            tracing::warn!("{} timed out after {:?}", path, timeout);
+            let mut problem = Problem::new(
+                StatusCode::GATEWAY_TIMEOUT,
+                "timeout",
+                format!("The request took longer than {} seconds", timeout.as_secs()),
+            );
+            problem.instance = Some(path);
+            problem.into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use axum::{body::Body, middleware, routing::get, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[test]
+    fn picks_the_longest_matching_prefix() {
+        let config = ServerConfig {
+            request_timeout_secs: 30,
+            route_timeouts: BTreeMap::from([
+                ("/admin".to_string(), 300),
+                ("/admin/restore/".to_string(), 0),
+            ]),
+            ..ServerConfig::default()
+        };
+        let timeouts = Timeouts::from_config(&config);
+        assert_eq!(timeouts.for_path("/todos"), secs(30));
+        assert_eq!(timeouts.for_path("/admin"), secs(300));
+        assert_eq!(timeouts.for_path("/admin/backup"), secs(300));
+        assert_eq!(timeouts.for_path("/admin/restore"), None);
+        assert_eq!(timeouts.for_path("/administrators"), secs(30));
+    }
+
+    #[tokio::test]
+    async fn answers_504_when_the_handler_is_stuck() {
+        let timeouts = Timeouts {
+            default: Some(Duration::from_millis(50)),
+            routes: vec![],
+        };
+        let app = Router::new()
+            .route(
+                "/todos",
+                get(|| async {
+                    tokio::time::sleep(Duration::from_secs(60)).await;
+                    "too late"
+                }),
+            )
+            .layer(middleware::from_fn(move |req, next| {
+                request_timeout(req, next, timeouts.clone())
+            }));
+
+        let req = Request::get("/todos").body(Body::empty()).unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::GATEWAY_TIMEOUT);
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let problem: Problem = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(problem.problem_type, "/problems/timeout");
+        assert_eq!(problem.instance.as_deref(), Some("/todos"));
+    }
+}
@@ -0,0 +1,114 @@
+use std::time::Instant;
+
+use axum::{
+    extract::MatchedPath,
+    http::{HeaderMap, Request},
+    middleware::Next,
+    response::Response,
+};
+use opentelemetry::propagation::Extractor;
+use tracing::{field, Instrument, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use super::request_id::RequestId;
+
+/// Reads W3C `traceparent` and `tracestate` out of request headers.
+struct Headers<'a>(&'a HeaderMap);
+
+impl Extractor for Headers<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
+
+/// Runs each request in a `request` span, a child of the caller's span
+/// when it sent a `traceparent`, tagged with the [`RequestId`]. Named after the method until
+/// [`record_route`] knows the route.
+pub async fn trace_requests<B>(req: Request<B>, next: Next<B>) -> Response {
+    let parent = opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.extract(&Headers(req.headers()))
+    });
+    let span = tracing::info_span!(
+        "request",
+        otel.name = %req.method(),
+        otel.kind = "server",
+        request_id = field::Empty,
+        method = %req.method(),
+        target = %req.uri(),
+        route = field::Empty,
+        status = field::Empty,
+        latency_ms = field::Empty,
+    );
+    if let Some(RequestId(id)) = req.extensions().get() {
+        span.record("request_id", id.as_str());
+    }
+    span.set_parent(parent);
+
+    let started = Instant::now();
+    let res = next.run(req).instrument(span.clone()).await;
+    span.record("status", res.status().as_u16());
+    span.record("latency_ms", started.elapsed().as_secs_f64() * 1000.0);
+    res
+}
+
+/// Names the request span after the matched route, `GET /todos/:id`, so
+/// every todo's requests group together.
+pub async fn record_route<B>(req: Request<B>, next: Next<B>) -> Response {
+    if let Some(route) = req.extensions().get::<MatchedPath>() {
+        let span = Span::current();
+        span.record(
+            "otel.name",
+            format!("{} {}", req.method(), route.as_str()).as_str(),
+        );
+        span.record("route", route.as_str());
+    }
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{body::Body, middleware, routing::get, Router};
+    use opentelemetry::trace::TraceContextExt;
+    use opentelemetry_sdk::propagation::TraceContextPropagator;
+    use tower::ServiceExt;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn continues_the_callers_trace() {
+        opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+        let tracer = opentelemetry_sdk::trace::TracerProvider::default();
+        let subscriber =
+            tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(
+                opentelemetry::trace::TracerProvider::tracer(&tracer, "test"),
+            ));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let app = Router::new()
+            .route(
+                "/todos/:id",
+                get(|| async {
+                    let context = Span::current().context();
+                    context.span().span_context().trace_id().to_string()
+                }),
+            )
+            .route_layer(middleware::from_fn(record_route))
+            .layer(middleware::from_fn(trace_requests));
+        let req = Request::builder()
+            .uri("/todos/1")
+            .header(
+                "traceparent",
+                "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+            )
+            .body(Body::empty())
+            .unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&bytes[..], b"0af7651916cd43dd8448eb211c80319c");
+    }
+}
@@ -1,61 +1,887 @@
+mod attachments;
+mod calendar;
+mod check;
+mod client;
+mod config;
+mod events;
+mod flags;
+mod github;
 mod handlers;
+mod health;
+mod jobs;
+mod layers;
+mod mailer;
+mod maintenance;
+mod mcp;
+mod notify;
+mod outbox;
+mod push;
+mod reload;
+mod reminders;
 mod repositories;
+mod secrets;
+mod seed;
+mod sentry;
+mod server;
+mod shutdown;
+mod spa;
+mod telemetry;
+#[cfg(test)]
+mod testing;
 
 use crate::repositories::{
-    label::LabelRepositoryForDb,
-    todo::{TodoRepository, TodoRepositoryForDb},
+    job::{JobRepository, JobRepositoryForDb, JobRepositoryForMemory, JobRepositoryForSqlite},
+    label::{LabelRepositoryForDb, LabelRepositoryForMemory, LabelRepositoryForSqlite},
+    todo::{TodoRepository, TodoRepositoryForDb, TodoRepositoryForMemory, TodoRepositoryForSqlite},
 };
+use attachments::Attachments;
 use axum::{
     extract::Extension,
-    routing::{delete, get, post},
+    middleware,
+    routing::{any, delete, get, post, put},
     Router,
 };
+use events::{todo_events, EventBus, Publishing, EVENT_BUFFER};
+use flags::{flags, gate_features, update_flags, Flags};
+use github::GitHubSync;
 use handlers::{
+    admin::{backup, restore, seed_demo, AdminToken, DevMode},
+    attachment::{delete_attachment, get_attachment, list_attachments, put_attachment},
+    caldav,
+    csv::{export_csv, import_csv},
+    error::{method_not_allowed, problem_instance},
+    feed::{calendar_feed, todos_feed, FeedToken},
+    github::github_webhook,
+    i18n::localize,
+    job::{find_job, purge_todos},
+    json::{export_json, import_json},
     label::{all_label, create_label, delete_label},
-    todo::{all_todo, create_todo, delete_todo, find_todo, update_todo},
+    mail::{receive_mail, MailInbox},
+    markdown::export_markdown,
+    pdf::export_pdf,
+    push::{push_key, subscribe_push, unsubscribe_push},
+    slack::{slack_command, SlackSecret},
+    stats::{cache_stats, repository_stats},
+    telegram::{issue_telegram_code, telegram_update, TelegramBot},
+    todo::{
+        all_todo, archived_todos, create_todo, delete_todo, export_todos, find_todo, head_todos,
+        search_todos, update_todo,
+    },
+    todoist::import_todoist,
+    trello::import_trello,
+    version::version,
+    xlsx::export_xlsx,
+    StrictJson,
+};
+use health::{healthz, readyz, Health};
+use layers::{
+    access_log::AccessLog,
+    body_limit::{limit_body, BodyLimit},
+    catch_panic::catch_panic,
+    client_ip::TrustedProxies,
+    compression::{compression_from_config, decompress_request, inflate_limit},
+    cors::{cors_layer, AllowedOrigins},
+    degraded::{degraded_reads, StaleResponses},
+    internal::{hide_internal, only_internal},
+    load_shed::{shed_load, LoadShed},
+    rate_limit::{rate_limit, RateLimiter},
+    request_id::request_id,
+    timeout::{request_timeout, Timeouts},
+    trace::{record_route, trace_requests},
+};
+use maintenance::{
+    end_maintenance, maintenance, maintenance_window, start_maintenance, Maintenance,
+};
+use outbox::Dispatcher;
+use push::WebPush;
+use reload::Reloadable;
+use repositories::{
+    backup::{
+        BackupRepository, BackupRepositoryFor, BackupRepositoryForDb, BackupRepositoryForSqlite,
+    },
+    cache::{Cache, Caching},
+    encrypt::{Encrypting, Encryption},
+    id::{self, IdFormat},
+    instrument::{Instrumented, QueryMetrics},
+    label::LabelRepository,
+    link::{LinkRepository, LinkRepositoryForDb, LinkRepositoryForSqlite},
+    outbox::{OutboxRepositoryForDb, OutboxRepositoryForSqlite},
+    retry::{RetryPolicy, Retrying},
+    Migrations, PoolSettings, StorageBackend,
 };
-use repositories::label::LabelRepository;
+use sentry::Sentry;
+use server::{unix_incoming, BindAddr, Listener, Tls, TlsConn, UnixSocket};
+use shutdown::Shutdown;
+use spa::Spa;
 use std::net::SocketAddr;
 use std::{env, sync::Arc};
+use telemetry::{LogFormat, Telemetry};
 
+use config::AppConfig;
 use dotenv::dotenv;
-use hyper::header::CONTENT_TYPE;
-use sqlx::PgPool;
-use tower_http::cors::{Any, CorsLayer, Origin};
+use hyper::server::conn::AddrStream;
 
-#[tokio::main]
-async fn main() {
-    // logging
-    let log_level = env::var("RUST_LOG").unwrap_or("info".to_string());
-    env::set_var("RUST_LOG", log_level);
-    tracing_subscriber::fmt::init();
+fn main() {
     dotenv().ok();
+    // Before the runtime's threads exist, as it changes the environment.
+    let secrets = secrets::load().unwrap_or_else(|e| panic!("{:#}", e));
+    tokio::runtime::Runtime::new()
+        .unwrap_or_else(|e| panic!("fail start the runtime: {}", e))
+        .block_on(run(secrets));
+}
+
+async fn run(secrets: Vec<secrets::Loaded>) {
+    let config = AppConfig::load();
+    // logging
+    let mcp_mode = env::args().any(|arg| arg == "--mcp");
+    let check_mode = env::args().any(|arg| arg == "--check");
+    let telemetry = Telemetry::from_env();
+    let exporting = telemetry.is_some();
+    let sentry = Sentry::from_env();
+    let reporting = sentry.is_some();
+    let log_filter = telemetry::init(
+        LogFormat::from_env(),
+        &config.server.log_filter,
+        telemetry,
+        sentry,
+        mcp_mode,
+    );
+    if exporting {
+        tracing::info!("exporting traces over OTLP");
+    }
+    if reporting {
+        tracing::info!("reporting errors to sentry");
+    }
+    for secret in secrets {
+        tracing::info!("read {} from {}", secret.name, secret.from);
+    }
 
-    let database_url = &env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+    let backend = StorageBackend::from_config(&config.database);
+    let database_url = &match config.database.url.clone() {
+        Some(url) => url,
+        None if backend == StorageBackend::Memory => String::new(),
+        None => panic!("undefined [DATABASE_URL]"),
+    };
+    let migrations = Migrations::from_config(&config.database);
+    let pool_settings = PoolSettings::from_config(&config.database);
+    let retry = RetryPolicy::from_config(&config.database);
+    let bus = EventBus::new(EVENT_BUFFER);
+    let id_format = config.features.id_format;
+    let cache = Cache::from_env().await;
+    let encryption = Encryption::from_env();
+    let dispatcher = Dispatcher::from_env();
+    let web_push = WebPush::from_env();
+    let metrics = Arc::new(QueryMetrics::from_config(&config.database));
+    let health = Health::default();
+    let flags = Flags::from_config(&config.flags);
+    let shutdown = Shutdown::from_config(&config.server);
+    let addr = BindAddr::from_config(&config.server, config.features.dev_mode);
+    let admin_addr = BindAddr::admin_from_config(&config.server);
+    let tls = Tls::from_config(&config.server);
+    let unix_socket = UnixSocket::from_config(&config.server);
+    if cache.is_some() {
+        tracing::info!("caching reads in redis");
+    }
+    if encryption.is_some() {
+        tracing::info!("encrypting todo texts");
+    }
+    let sql = matches!(
+        backend,
+        StorageBackend::Postgres | StorageBackend::Sqlite | StorageBackend::MySql
+    );
+    if dispatcher.is_some() && !sql {
+        tracing::warn!("the outbox needs a SQL database, no events are delivered");
+    }
+    if !sql && env::var_os("GITHUB_REPO").is_some() {
+        tracing::warn!("the GitHub sync needs a SQL database, no issues are synced");
+    }
+    if !sql && env::var_os("GOOGLE_CALENDAR_ID").is_some() {
+        tracing::warn!("the calendar sync needs a SQL database, the calendar is not synced");
+    }
+    if !sql && env::var_os("ARCHIVE_AFTER_DAYS").is_some() {
+        panic!("invalid [ARCHIVE_AFTER_DAYS]: only a SQL database keeps an archive");
+    }
+    if check_mode {
+        if let Err(e) = check::run(&config, backend, database_url, &pool_settings).await {
+            tracing::error!("check failed: {:#}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
     tracing::debug!("start connect database...");
-    let pool = PgPool::connect(database_url)
+    let mut app = match backend {
+        StorageBackend::Memory => {
+            let snapshot = database_url.strip_prefix("memory:").unwrap_or_default();
+            let todos = if snapshot.is_empty() {
+                TodoRepositoryForMemory::new()
+            } else {
+                TodoRepositoryForMemory::with_snapshot(snapshot)
+                    .await
+                    .unwrap_or_else(|e| panic!("fail load snapshot [{}]: {:#}", snapshot, e))
+            };
+            let labels = LabelRepositoryForMemory::new();
+            let backup = BackupRepositoryFor::new("memory", todos.clone(), labels.clone());
+            let backup = Caching::new(backup, cache.clone());
+            let todos = Instrumented::new(todos, metrics.clone());
+            let todos = Retrying::new(todos, retry.clone());
+            let todos = Encrypting::new(Caching::new(todos, cache.clone()), encryption);
+            jobs::reencrypt(todos.clone());
+            let todos = Publishing::new(todos, bus.clone());
+            let labels = Instrumented::new(labels, metrics.clone());
+            let labels = Retrying::new(labels, retry.clone());
+            let labels = Caching::new(labels, cache.clone());
+            seed_if_requested(&todos, &labels, id_format).await;
+            notify::spawn_from_env(todos.clone(), &bus);
+            reminders::spawn_from_env(todos.clone());
+            push::spawn(web_push.as_ref(), todos.clone(), &bus);
+            if mcp_mode {
+                return serve_mcp(todos, id_format).await;
+            }
+            create_app(
+                todos,
+                labels,
+                Instrumented::new(JobRepositoryForMemory::new(), metrics.clone()),
+                backup,
+            )
+        }
+        StorageBackend::MySql => match mysql_app(
+            database_url,
+            &pool_settings,
+            retry,
+            bus.clone(),
+            migrations,
+            id_format,
+            cache.clone(),
+            encryption,
+            web_push.as_ref(),
+            dispatcher,
+            metrics.clone(),
+            &health,
+            &shutdown,
+            mcp_mode,
+        )
+        .await
+        {
+            Some(app) => app,
+            None => return,
+        },
+        StorageBackend::MongoDb => {
+            match mongo_app(
+                database_url,
+                retry,
+                bus.clone(),
+                id_format,
+                cache.clone(),
+                encryption,
+                web_push.as_ref(),
+                metrics.clone(),
+                mcp_mode,
+            )
+            .await
+            {
+                Some(app) => app,
+                None => return,
+            }
+        }
+        StorageBackend::DynamoDb => {
+            match dynamo_app(
+                database_url,
+                retry,
+                bus.clone(),
+                id_format,
+                cache.clone(),
+                encryption,
+                web_push.as_ref(),
+                metrics.clone(),
+                mcp_mode,
+            )
+            .await
+            {
+                Some(app) => app,
+                None => return,
+            }
+        }
+        StorageBackend::Sqlite => {
+            tracing::info!("database pool: {:?}", pool_settings);
+            let pool = repositories::connect_sqlite(database_url, &pool_settings, migrations)
+                .await
+                .unwrap_or_else(|e| panic!("fail open sqlite, url is [{}]: {}", database_url, e));
+            health.watch(pool.clone(), &repositories::SQLITE_MIGRATOR);
+            shutdown.close_on_exit(pool.clone());
+            if id_format == IdFormat::Uuid {
+                let count = id::backfill(&pool, "update {} set uuid=?1 where id=?2")
+                    .await
+                    .unwrap_or_else(|e| panic!("fail backfill uuids: {:#}", e));
+                tracing::info!("gave {} existing rows a uuid", count);
+            }
+            let mut todos = TodoRepositoryForSqlite::new(pool.clone());
+            if let Some(dispatcher) = dispatcher {
+                todos = todos.with_outbox();
+                dispatcher.spawn(OutboxRepositoryForSqlite::new(pool.clone()));
+            }
+            let todos = Instrumented::new(todos, metrics.clone());
+            let todos = Retrying::new(todos, retry.clone());
+            let todos = Encrypting::new(Caching::new(todos, cache.clone()), encryption);
+            jobs::reencrypt(todos.clone());
+            let todos = Publishing::new(todos, bus.clone());
+            let labels =
+                Instrumented::new(LabelRepositoryForSqlite::new(pool.clone()), metrics.clone());
+            let labels = Retrying::new(labels, retry.clone());
+            let labels = Caching::new(labels, cache.clone());
+            seed_if_requested(&todos, &labels, id_format).await;
+            notify::spawn_from_env(todos.clone(), &bus);
+            let links: Arc<dyn LinkRepository> =
+                Arc::new(LinkRepositoryForSqlite::new(pool.clone()));
+            github::spawn_from_env(todos.clone(), links.clone(), id_format, &bus);
+            calendar::spawn_from_env(todos.clone(), links.clone(), id_format, &bus);
+            reminders::spawn_from_env(todos.clone());
+            push::spawn(web_push.as_ref(), todos.clone(), &bus);
+            jobs::archive_from_env(todos.clone());
+            if mcp_mode {
+                return serve_mcp(todos, id_format).await;
+            }
+            create_app(
+                todos,
+                labels,
+                Retrying::new(
+                    Instrumented::new(JobRepositoryForSqlite::new(pool.clone()), metrics.clone()),
+                    retry,
+                ),
+                Caching::new(BackupRepositoryForSqlite::new(pool), cache.clone()),
+            )
+            .layer(Extension(links))
+        }
+        StorageBackend::Postgres => {
+            tracing::info!("database pool: {:?}", pool_settings);
+            let pool = repositories::connect_postgres(database_url, &pool_settings, migrations)
+                .await
+                .unwrap_or_else(|e| {
+                    panic!("fail connect database, url is [{}]: {:#}", database_url, e)
+                });
+            health.watch(pool.clone(), &repositories::MIGRATOR);
+            shutdown.close_on_exit(pool.clone());
+            if id_format == IdFormat::Uuid {
+                let count = id::backfill(&pool, "update {} set uuid=$1 where id=$2")
+                    .await
+                    .unwrap_or_else(|e| panic!("fail backfill uuids: {:#}", e));
+                tracing::info!("gave {} existing rows a uuid", count);
+            }
+            let mut todos = TodoRepositoryForDb::new(pool.clone());
+            if let Some(dispatcher) = dispatcher {
+                todos = todos.with_outbox();
+                dispatcher.spawn(OutboxRepositoryForDb::new(pool.clone()));
+            }
+            if let Some(read_url) = &config.database.read_url {
+                tracing::info!("list queries go to the read replica");
+                let read_pool = repositories::connect_postgres_replica(read_url, &pool_settings)
+                    .await
+                    .unwrap_or_else(|e| {
+                        panic!("fail connect read replica, url is [{}]: {:#}", read_url, e)
+                    });
+                todos = todos.with_read_pool(read_pool);
+            }
+            let todos = Instrumented::new(todos, metrics.clone());
+            let todos = Caching::new(Retrying::new(todos, retry.clone()), cache.clone());
+            let todos = Encrypting::new(todos, encryption);
+            jobs::reencrypt(todos.clone());
+            let labels =
+                Instrumented::new(LabelRepositoryForDb::new(pool.clone()), metrics.clone());
+            let labels = Retrying::new(labels, retry.clone());
+            let labels = Caching::new(labels, cache.clone());
+            // The repository notifies on every change, this instance's included.
+            events::listen_postgres(&pool, bus.clone())
+                .await
+                .unwrap_or_else(|e| panic!("fail listen for todo events: {:#}", e));
+            seed_if_requested(&todos, &labels, id_format).await;
+            notify::spawn_from_env(todos.clone(), &bus);
+            let links: Arc<dyn LinkRepository> = Arc::new(LinkRepositoryForDb::new(pool.clone()));
+            github::spawn_from_env(todos.clone(), links.clone(), id_format, &bus);
+            calendar::spawn_from_env(todos.clone(), links.clone(), id_format, &bus);
+            reminders::spawn_from_env(todos.clone());
+            push::spawn(web_push.as_ref(), todos.clone(), &bus);
+            jobs::archive_from_env(todos.clone());
+            if mcp_mode {
+                return serve_mcp(todos, id_format).await;
+            }
+            create_app(
+                todos,
+                labels,
+                Retrying::new(
+                    Instrumented::new(JobRepositoryForDb::new(pool.clone()), metrics.clone()),
+                    retry,
+                ),
+                Caching::new(BackupRepositoryForDb::new(pool), cache.clone()),
+            )
+            .layer(Extension(links))
+        }
+    };
+    if let Some(spa) = Spa::from_config(&config.server) {
+        tracing::info!("serving the frontend under {}", spa::PATH);
+        app = app.nest(spa::PATH, spa.service());
+    }
+    app = app
+        .layer(Extension(bus.clone()))
+        .layer(Extension(id_format))
+        .layer(Extension(metrics.clone()))
+        .layer(Extension(health.clone()))
+        .layer(Extension(flags.clone()))
+        .layer(Extension(Maintenance::default()));
+    if let Some(cache) = cache {
+        app = app.layer(Extension(cache.metrics()));
+    }
+    if config.features.strict_json {
+        app = app.layer(Extension(StrictJson));
+    }
+    if let Some(token) = FeedToken::from_env() {
+        app = app.layer(Extension(token));
+    }
+    if let Some(token) = AdminToken::from_env() {
+        app = app.layer(Extension(token));
+    }
+    if let Some(inbox) = MailInbox::from_env() {
+        app = app.layer(Extension(inbox));
+    }
+    if let Some(secret) = SlackSecret::from_env() {
+        app = app.layer(Extension(secret));
+    }
+    if let Some(bot) = TelegramBot::from_env() {
+        app = app.layer(Extension(bot));
+    }
+    if let Some(sync) = GitHubSync::from_env() {
+        app = app.layer(Extension(sync.with_format(id_format)));
+    }
+    if let Some(push) = web_push {
+        app = app.layer(Extension(push));
+    }
+    if let Some(attachments) = Attachments::from_config(&config.attachments) {
+        tracing::info!("keeping attachments in {:?}", config.attachments.backend);
+        attachments.delete_with_todos(&bus);
+        app = app.layer(Extension(attachments));
+    }
+    if config.features.dev_mode {
+        app = app.layer(Extension(DevMode));
+    }
+    let timeouts = Timeouts::from_config(&config.server);
+    app = app.layer(middleware::from_fn(move |req, next| {
+        request_timeout(req, next, timeouts.clone())
+    }));
+    if let Some(stale) = StaleResponses::from_config(&config.features) {
+        tracing::info!("serving stale reads while the database is down");
+        app = app.layer(middleware::from_fn(move |req, next| {
+            degraded_reads(req, next, stale.clone(), health.clone())
+        }));
+    }
+    let inflate = inflate_limit(&config.server);
+    app = app.layer(middleware::from_fn(move |req, next| {
+        decompress_request(req, next, inflate)
+    }));
+    if let Some(limit) = BodyLimit::from_config(&config.server) {
+        app = app.layer(middleware::from_fn(move |req, next| {
+            limit_body(req, next, limit)
+        }));
+    }
+    if let Some(compression) = compression_from_config(&config.features) {
+        app = app.layer(compression);
+    }
+    let max_connections = sql.then_some(pool_settings.max_connections);
+    if let Some(shed) = LoadShed::from_config(&config.server, metrics.clone(), max_connections) {
+        tracing::info!("shedding load: {:?}", shed);
+        app = app.layer(middleware::from_fn(move |req, next| {
+            shed_load(req, next, shed.clone())
+        }));
+    }
+    let limiter = RateLimiter::from_config(&config.features)
+        .behind(TrustedProxies::from_config(&config.server));
+    if limiter.enabled() {
+        tracing::info!("rate limiting enabled: {:?}", limiter);
+    }
+    let reload_limiter = limiter.clone();
+    app = app.layer(middleware::from_fn(move |req, next| {
+        rate_limit(req, next, limiter.clone())
+    }));
+    let origins = AllowedOrigins::from_config(&config.cors);
+    app = app.layer(cors_layer(origins.clone(), config.features.dev_mode));
+    if let Some(access_log) = AccessLog::from_config(&config.server) {
+        app = app.layer(access_log.layer());
+    }
+    app = app
+        .layer(middleware::from_fn(trace_requests))
+        .layer(middleware::from_fn(request_id));
+    if let Some(admin_addr) = admin_addr {
+        let listener = std::net::TcpListener::bind(admin_addr.0)
+            .unwrap_or_else(|e| panic!("failed to listen on {}: {}", admin_addr, e));
+        let server = axum::Server::from_tcp(listener)
+            .unwrap_or_else(|e| panic!("failed to listen on {}: {}", admin_addr, e))
+            .serve(
+                app.clone()
+                    .layer(middleware::from_fn(only_internal))
+                    .into_make_service_with_connect_info::<SocketAddr, &AddrStream>(),
+            );
+        tracing::info!("serving admin endpoints on http://{}", admin_addr);
+        shutdown.spawn("admin listener", |stop| server.with_graceful_shutdown(stop));
+        app = app.layer(middleware::from_fn(hide_internal));
+    }
+    Reloadable {
+        config: config.clone(),
+        log_filter,
+        origins,
+        limiter: reload_limiter,
+        flags,
+    }
+    .spawn();
+    let activated = Listener::from_systemd();
+    let socket_activated = activated.is_some();
+    let listener = activated.unwrap_or_else(|| match &unix_socket {
+        Some(socket) => Listener::Unix(
+            socket
+                .bind()
+                .unwrap_or_else(|e| panic!("failed to listen on {}: {}", socket, e)),
+        ),
+        None => Listener::Tcp(
+            std::net::TcpListener::bind(addr.0)
+                .unwrap_or_else(|e| panic!("failed to listen on {}: {}", addr, e)),
+        ),
+    });
+    if socket_activated {
+        tracing::info!("serving on the socket systemd passed");
+    }
+    match (listener, tls) {
+        (Listener::Unix(listener), None) => {
+            let path = listener.local_addr().ok();
+            let incoming = unix_incoming(listener)
+                .unwrap_or_else(|e| panic!("failed to listen on a unix socket: {}", e));
+            let server = axum::Server::builder(incoming)
+                .http1_only(!config.server.http2)
+                .serve(app.into_make_service());
+            if let Some(path) = path.as_ref().and_then(|path| path.as_pathname()) {
+                tracing::info!("listening on unix:{}", path.display());
+            }
+            shutdown
+                .run(|stop| server.with_graceful_shutdown(stop))
+                .await;
+        }
+        (Listener::Unix(_), Some(_)) => {
+            panic!("invalid [TLS_CERT_PATH]: TLS is only served over TCP")
+        }
+        (Listener::Tcp(listener), Some(tls)) => {
+            let local_addr = listener.local_addr().expect("bound");
+            let incoming = tls
+                .incoming(listener)
+                .unwrap_or_else(|e| panic!("failed to listen on {}: {}", local_addr, e));
+            let server = axum::Server::builder(incoming)
+                .http1_only(!config.server.http2)
+                .serve(app.into_make_service_with_connect_info::<SocketAddr, &TlsConn>());
+            tracing::info!("listening on https://{}", local_addr);
+            shutdown
+                .run(|stop| server.with_graceful_shutdown(stop))
+                .await;
+        }
+        (Listener::Tcp(listener), None) => {
+            let local_addr = listener.local_addr().expect("bound");
+            let server = axum::Server::from_tcp(listener)
+                .unwrap_or_else(|e| panic!("failed to listen on {}: {}", local_addr, e))
+                .http1_only(!config.server.http2)
+                .serve(app.into_make_service_with_connect_info::<SocketAddr, &AddrStream>());
+            tracing::info!("listening on http://{}", local_addr);
+            shutdown
+                .run(|stop| server.with_graceful_shutdown(stop))
+                .await;
+        }
+    }
+    // systemd owns the socket it passed, and keeps it for the next start.
+    if let Some(socket) = unix_socket.filter(|_| !socket_activated) {
+        socket.remove();
+    }
+    telemetry::flush().await;
+}
+
+/// The app on MySQL repositories, or `None` once MCP mode has finished.
+#[cfg(feature = "mysql")]
+#[allow(clippy::too_many_arguments)]
+async fn mysql_app(
+    database_url: &str,
+    pool_settings: &PoolSettings,
+    retry: RetryPolicy,
+    bus: EventBus,
+    migrations: Migrations,
+    id_format: IdFormat,
+    cache: Option<Cache>,
+    encryption: Option<Encryption>,
+    web_push: Option<&WebPush>,
+    dispatcher: Option<Dispatcher>,
+    metrics: Arc<QueryMetrics>,
+    health: &Health,
+    shutdown: &Shutdown,
+    mcp_mode: bool,
+) -> Option<Router> {
+    use repositories::{
+        backup::BackupRepositoryForMySql, job::JobRepositoryForMySql,
+        label::LabelRepositoryForMySql, link::LinkRepositoryForMySql,
+        outbox::OutboxRepositoryForMySql, todo::TodoRepositoryForMySql,
+    };
+
+    tracing::info!("database pool: {:?}", pool_settings);
+    let pool = repositories::connect_mysql(database_url, pool_settings, migrations)
         .await
-        .expect(&format!("fail connect database, url is [{}]", database_url));
+        .unwrap_or_else(|e| panic!("fail connect mysql, url is [{}]: {}", database_url, e));
+    health.watch(pool.clone(), &repositories::MYSQL_MIGRATOR);
+    shutdown.close_on_exit(pool.clone());
+    if id_format == IdFormat::Uuid {
+        let count = id::backfill(&pool, "update {} set uuid=? where id=?")
+            .await
+            .unwrap_or_else(|e| panic!("fail backfill uuids: {:#}", e));
+        tracing::info!("gave {} existing rows a uuid", count);
+    }
+    let mut todos = TodoRepositoryForMySql::new(pool.clone());
+    if let Some(dispatcher) = dispatcher {
+        todos = todos.with_outbox();
+        dispatcher.spawn(OutboxRepositoryForMySql::new(pool.clone()));
+    }
+    let todos = Instrumented::new(todos, metrics.clone());
+    let todos = Retrying::new(todos, retry.clone());
+    let todos = Encrypting::new(Caching::new(todos, cache.clone()), encryption);
+    jobs::reencrypt(todos.clone());
+    let todos = Publishing::new(todos, bus.clone());
+    let labels = Instrumented::new(LabelRepositoryForMySql::new(pool.clone()), metrics.clone());
+    let labels = Retrying::new(labels, retry.clone());
+    let labels = Caching::new(labels, cache.clone());
+    seed_if_requested(&todos, &labels, id_format).await;
+    notify::spawn_from_env(todos.clone(), &bus);
+    let links: Arc<dyn LinkRepository> = Arc::new(LinkRepositoryForMySql::new(pool.clone()));
+    github::spawn_from_env(todos.clone(), links.clone(), id_format, &bus);
+    calendar::spawn_from_env(todos.clone(), links.clone(), id_format, &bus);
+    reminders::spawn_from_env(todos.clone());
+    push::spawn(web_push, todos.clone(), &bus);
+    jobs::archive_from_env(todos.clone());
+    if mcp_mode {
+        serve_mcp(todos, id_format).await;
+        return None;
+    }
+    Some(
+        create_app(
+            todos,
+            labels,
+            Retrying::new(
+                Instrumented::new(JobRepositoryForMySql::new(pool.clone()), metrics),
+                retry,
+            ),
+            Caching::new(BackupRepositoryForMySql::new(pool), cache),
+        )
+        .layer(Extension(links)),
+    )
+}
 
-    let app = create_app(
-        TodoRepositoryForDb::new(pool.clone()),
-        LabelRepositoryForDb::new(pool.clone()),
+#[cfg(not(feature = "mysql"))]
+#[allow(clippy::too_many_arguments)]
+async fn mysql_app(
+    database_url: &str,
+    _pool_settings: &PoolSettings,
+    _retry: RetryPolicy,
+    _bus: EventBus,
+    _migrations: Migrations,
+    _id_format: IdFormat,
+    _cache: Option<Cache>,
+    _encryption: Option<Encryption>,
+    _web_push: Option<&WebPush>,
+    _dispatcher: Option<Dispatcher>,
+    _metrics: Arc<QueryMetrics>,
+    _health: &Health,
+    _shutdown: &Shutdown,
+    _mcp_mode: bool,
+) -> Option<Router> {
+    panic!(
+        "[DATABASE_URL] is [{}], but MySQL support needs a build with `--features mysql`",
+        database_url
     );
-    let addr = SocketAddr::from(([0, 0, 0, 0], 3000));
-    tracing::debug!("listening on {}", addr);
-    axum::Server::bind(&addr)
-        .serve(app.into_make_service())
+}
+
+/// The app on MongoDB repositories, or `None` once MCP mode has finished.
+/// Jobs are kept in memory, and the database is not probed for `/readyz`.
+#[cfg(feature = "mongodb")]
+#[allow(clippy::too_many_arguments)]
+async fn mongo_app(
+    database_url: &str,
+    retry: RetryPolicy,
+    bus: EventBus,
+    id_format: IdFormat,
+    cache: Option<Cache>,
+    encryption: Option<Encryption>,
+    web_push: Option<&WebPush>,
+    metrics: Arc<QueryMetrics>,
+    mcp_mode: bool,
+) -> Option<Router> {
+    use repositories::{label::LabelRepositoryForMongo, todo::TodoRepositoryForMongo};
+
+    let db = repositories::connect_mongo(database_url)
         .await
-        .unwrap();
+        .unwrap_or_else(|e| panic!("fail connect mongodb, url is [{}]: {:#}", database_url, e));
+    let backup = BackupRepositoryFor::new(
+        "mongodb",
+        TodoRepositoryForMongo::new(db.clone()),
+        LabelRepositoryForMongo::new(db.clone()),
+    );
+    let backup = Caching::new(backup, cache.clone());
+    let todos = Instrumented::new(TodoRepositoryForMongo::new(db.clone()), metrics.clone());
+    let todos = Retrying::new(todos, retry.clone());
+    let todos = Encrypting::new(Caching::new(todos, cache.clone()), encryption);
+    jobs::reencrypt(todos.clone());
+    let todos = Publishing::new(todos, bus.clone());
+    let labels = Instrumented::new(LabelRepositoryForMongo::new(db), metrics.clone());
+    let labels = Retrying::new(labels, retry);
+    let labels = Caching::new(labels, cache);
+    seed_if_requested(&todos, &labels, id_format).await;
+    notify::spawn_from_env(todos.clone(), &bus);
+    reminders::spawn_from_env(todos.clone());
+    push::spawn(web_push, todos.clone(), &bus);
+    if mcp_mode {
+        serve_mcp(todos, id_format).await;
+        return None;
+    }
+    Some(create_app(
+        todos,
+        labels,
+        Instrumented::new(JobRepositoryForMemory::new(), metrics),
+        backup,
+    ))
+}
+
+#[cfg(not(feature = "mongodb"))]
+#[allow(clippy::too_many_arguments)]
+async fn mongo_app(
+    database_url: &str,
+    _retry: RetryPolicy,
+    _bus: EventBus,
+    _id_format: IdFormat,
+    _cache: Option<Cache>,
+    _encryption: Option<Encryption>,
+    _web_push: Option<&WebPush>,
+    _metrics: Arc<QueryMetrics>,
+    _mcp_mode: bool,
+) -> Option<Router> {
+    panic!(
+        "[DATABASE_URL] is [{}], but MongoDB support needs a build with `--features mongodb`",
+        database_url
+    );
+}
+
+/// The app on a DynamoDB table, or `None` once MCP mode has finished.
+/// Jobs are kept in memory, and the table is not probed for `/readyz`.
+#[cfg(feature = "dynamodb")]
+#[allow(clippy::too_many_arguments)]
+async fn dynamo_app(
+    database_url: &str,
+    retry: RetryPolicy,
+    bus: EventBus,
+    id_format: IdFormat,
+    cache: Option<Cache>,
+    encryption: Option<Encryption>,
+    web_push: Option<&WebPush>,
+    metrics: Arc<QueryMetrics>,
+    mcp_mode: bool,
+) -> Option<Router> {
+    use repositories::{label::LabelRepositoryForDynamo, todo::TodoRepositoryForDynamo};
+
+    let table = repositories::connect_dynamo(database_url)
+        .await
+        .unwrap_or_else(|e| panic!("fail connect dynamodb, url is [{}]: {:#}", database_url, e));
+    let backup = BackupRepositoryFor::new(
+        "dynamodb",
+        TodoRepositoryForDynamo::new(table.clone()),
+        LabelRepositoryForDynamo::new(table.clone()),
+    );
+    let backup = Caching::new(backup, cache.clone());
+    let todos = Instrumented::new(TodoRepositoryForDynamo::new(table.clone()), metrics.clone());
+    let todos = Retrying::new(todos, retry.clone());
+    let todos = Encrypting::new(Caching::new(todos, cache.clone()), encryption);
+    jobs::reencrypt(todos.clone());
+    let todos = Publishing::new(todos, bus.clone());
+    let labels = Instrumented::new(LabelRepositoryForDynamo::new(table), metrics.clone());
+    let labels = Retrying::new(labels, retry);
+    let labels = Caching::new(labels, cache);
+    seed_if_requested(&todos, &labels, id_format).await;
+    notify::spawn_from_env(todos.clone(), &bus);
+    reminders::spawn_from_env(todos.clone());
+    push::spawn(web_push, todos.clone(), &bus);
+    if mcp_mode {
+        serve_mcp(todos, id_format).await;
+        return None;
+    }
+    Some(create_app(
+        todos,
+        labels,
+        Instrumented::new(JobRepositoryForMemory::new(), metrics),
+        backup,
+    ))
+}
+
+#[cfg(not(feature = "dynamodb"))]
+#[allow(clippy::too_many_arguments)]
+async fn dynamo_app(
+    database_url: &str,
+    _retry: RetryPolicy,
+    _bus: EventBus,
+    _id_format: IdFormat,
+    _cache: Option<Cache>,
+    _encryption: Option<Encryption>,
+    _web_push: Option<&WebPush>,
+    _metrics: Arc<QueryMetrics>,
+    _mcp_mode: bool,
+) -> Option<Router> {
+    panic!(
+        "[DATABASE_URL] is [{}], but DynamoDB support needs a build with `--features dynamodb`",
+        database_url
+    );
+}
+
+/// Fills an empty database with demo data when started with `--seed`.
+async fn seed_if_requested<T: TodoRepository, L: LabelRepository>(
+    todos: &T,
+    labels: &L,
+    format: IdFormat,
+) {
+    if !env::args().any(|arg| arg == "--seed") {
+        return;
+    }
+    match seed::seed(todos, labels, format).await {
+        Ok(seeded) => tracing::info!("seeded {} todos and {} labels", seeded.todos, seeded.labels),
+        Err(e) => panic!("fail seed database: {:#}", e),
+    }
+}
+
+async fn serve_mcp<T: TodoRepository>(repository: T, format: IdFormat) {
+    tracing::info!("serving MCP on stdio");
+    if let Err(e) = mcp::serve_stdio(repository, format).await {
+        tracing::error!("mcp server failed: {:#}", e);
+    }
 }
 
-fn create_app<Todo: TodoRepository, Label: LabelRepository>(
+fn create_app<
+    Todo: TodoRepository,
+    Label: LabelRepository,
+    Job: JobRepository,
+    Backup: BackupRepository,
+>(
     todo_repository: Todo,
     label_repository: Label,
+    job_repository: Job,
+    backup_repository: Backup,
 ) -> Router {
     Router::new()
         .route("/", get(root))
-        .route("/todos", post(create_todo::<Todo>).get(all_todo::<Todo>))
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/version", get(version))
+        .route(
+            "/todos",
+            post(create_todo::<Todo>)
+                .get(all_todo::<Todo>)
+                .head(head_todos::<Todo>),
+        )
+        .route("/todos/purge", post(purge_todos::<Todo, Job>))
+        .route("/todos/export.ndjson", get(export_todos::<Todo>))
+        .route("/todos/events", get(todo_events))
+        .route("/todos/search", get(search_todos::<Todo>))
+        .route("/todos/archived", get(archived_todos::<Todo>))
         .route(
             "/todos/:id",
             get(find_todo::<Todo>)
@@ -67,14 +893,61 @@ fn create_app<Todo: TodoRepository, Label: LabelRepository>(
             post(create_label::<Label>).get(all_label::<Label>),
         )
         .route("/labels/:id", delete(delete_label::<Label>))
+        .route("/labels/:id/export.pdf", get(export_pdf::<Todo, Label>))
+        .route("/import/csv", post(import_csv::<Todo, Label>))
+        .route("/export/csv", get(export_csv::<Todo>))
+        .route("/export/json", get(export_json::<Todo, Label>))
+        .route("/export/markdown", get(export_markdown::<Todo>))
+        .route("/export/xlsx", get(export_xlsx::<Todo>))
+        .route("/import/json", post(import_json::<Todo, Label>))
+        .route("/import/todoist", post(import_todoist::<Todo, Label>))
+        .route("/import/trello", post(import_trello::<Todo, Label>))
+        .route("/integrations/mail", post(receive_mail::<Todo>))
+        .route("/integrations/slack/command", post(slack_command::<Todo>))
+        .route("/integrations/telegram", post(telegram_update::<Todo>))
+        .route("/integrations/github", post(github_webhook::<Todo>))
+        .route("/export/calendar.ics", get(calendar_feed::<Todo>))
+        .route("/attachments/:id", get(list_attachments::<Todo>))
+        .route(
+            "/attachments/:id/:name",
+            put(put_attachment::<Todo>)
+                .get(get_attachment::<Todo>)
+                .delete(delete_attachment::<Todo>),
+        )
+        .route("/push/key", get(push_key))
+        .route(
+            "/push/subscriptions",
+            post(subscribe_push).delete(unsubscribe_push),
+        )
+        .route("/jobs/:id", get(find_job::<Job>))
+        .route("/cache/stats", get(cache_stats))
+        .route("/repository/stats", get(repository_stats))
+        .route("/admin/seed", post(seed_demo::<Todo, Label>))
+        .route("/admin/backup", get(backup::<Backup>))
+        .route("/admin/restore", post(restore::<Backup>))
+        .route("/admin/flags", get(flags).patch(update_flags))
+        .route("/admin/telegram/codes", post(issue_telegram_code))
+        .route(
+            "/admin/maintenance",
+            get(maintenance_window)
+                .put(start_maintenance)
+                .delete(end_maintenance),
+        )
+        .route("/feeds/todos.atom", get(todos_feed::<Todo>))
+        .route("/caldav/todos", any(caldav::collection::<Todo>))
+        .route("/caldav/todos/", any(caldav::collection::<Todo>))
+        .route("/caldav/todos/:file", any(caldav::resource::<Todo>))
+        .route_layer(middleware::from_fn(record_route))
         .layer(Extension(Arc::new(todo_repository)))
         .layer(Extension(Arc::new(label_repository)))
-        .layer(
-            CorsLayer::new()
-                .allow_origin(Origin::exact("http://localhost:3001".parse().unwrap()))
-                .allow_methods(Any)
-                .allow_headers(vec![CONTENT_TYPE]),
-        )
+        .layer(Extension(Arc::new(job_repository)))
+        .layer(Extension(Arc::new(backup_repository)))
+        .layer(middleware::from_fn(gate_features))
+        .layer(middleware::from_fn(maintenance))
+        .layer(middleware::from_fn(catch_panic))
+        .layer(middleware::from_fn(method_not_allowed))
+        .layer(middleware::from_fn(problem_instance))
+        .layer(middleware::from_fn(localize))
 }
 
 async fn root() -> &'static str {
@@ -84,11 +957,22 @@ async fn root() -> &'static str {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::repositories::todo::{CreateTodo, Todo, TodoRepositoryForMemory};
+    use crate::handlers::{
+        error::{Problem, PROBLEM_JSON},
+        feed::ATOM,
+        jsonapi::JSON_API,
+        todo::NDJSON,
+    };
+    use crate::layers::request_id::X_REQUEST_ID;
+    use crate::repositories::{
+        id::{IdFormat, Uuid},
+        job::JobStatus,
+        todo::{CreateTodo, Todo, TodoFilter, UpdateTodo},
+    };
     use axum::response::Response;
     use axum::{body::Body, http::Request};
 
-    use hyper::{header, Method, StatusCode};
+    use hyper::{body::HttpBody, header, Method, StatusCode};
     use tower::ServiceExt;
 
     fn build_todo_req_with_json(path: &str, method: Method, json_body: String) -> Request<Body> {
@@ -108,6 +992,14 @@ mod test {
             .unwrap()
     }
 
+    fn memory_backup() -> BackupRepositoryFor<TodoRepositoryForMemory, LabelRepositoryForMemory> {
+        BackupRepositoryFor::new(
+            "memory",
+            TodoRepositoryForMemory::new(),
+            LabelRepositoryForMemory::new(),
+        )
+    }
+
     async fn res_to_string(res: Response) -> String {
         let b = res.into_body();
         let bytes = hyper::body::to_bytes(b).await.unwrap();
@@ -116,10 +1008,11 @@ mod test {
 
     async fn res_to_todo(res: Response) -> Todo {
         let body = res_to_string(res).await;
-        let todo: Todo = serde_json::from_str(&body).expect(&format!("body: {}", body));
+        let todo: Todo = serde_json::from_str(&body).unwrap_or_else(|_| panic!("body: {}", body));
         todo
     }
 
+    #[tokio::test]
     async fn should_created_todo() {
         let expected = Todo::new(1, "should_created_todo".to_string());
 
@@ -130,11 +1023,20 @@ mod test {
             r#"{"text": "should_created_todo" }"#.to_string(),
         );
 
-        let res = create_app(repository).oneshot(req).await.unwrap();
+        let res = create_app(
+            repository,
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
         let todo = res_to_todo(res).await;
         assert_eq!(expected, todo);
     }
 
+    #[tokio::test]
     async fn should_find_todo() {
         // 期待値作成
         let expected = Todo::new(1, "should_find_todo".to_string());
@@ -148,7 +1050,15 @@ mod test {
         // リクエストを作成
         let req = build_todo_req_with_empty("/todos/1", Method::GET);
         // レスポンスを作成
-        let res = create_app(repository).oneshot(req).await.unwrap();
+        let res = create_app(
+            repository,
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
         // レスポンスから、todoを生成
         let todo = res_to_todo(res).await;
         // expected
@@ -164,16 +1074,27 @@ mod test {
             .await
             .expect("failed create todo");
         let req = build_todo_req_with_empty("/todos", Method::GET);
-        let res = create_app(repository).oneshot(req).await.unwrap();
+        let res = create_app(
+            repository,
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
         let body = res_to_string(res).await;
         let todo: Vec<Todo> = serde_json::from_str(&body)
-            .expect(&format!("connot convert TOdo instance. boy: {}", body));
+            .unwrap_or_else(|_| panic!("connot convert TOdo instance. boy: {}", body));
         assert_eq!(vec![expected], todo)
     }
 
     #[tokio::test]
     async fn should_update_todo() {
-        let expected = Todo::new(1, "should_update_todo".to_string());
+        let expected: Todo = serde_json::from_str(
+            r#"{"id": 1, "text": "should_update_todo", "completed": false, "version": 2}"#,
+        )
+        .unwrap();
         let repository = TodoRepositoryForMemory::new();
         repository
             .create(CreateTodo::new("before_should_update_todo".to_string()))
@@ -190,7 +1111,15 @@ mod test {
             }"#
             .to_string(),
         );
-        let res = create_app(repository).oneshot(req).await.unwrap();
+        let res = create_app(
+            repository,
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
         let todo = res_to_todo(res).await;
 
         assert_eq!(expected, todo);
@@ -205,18 +1134,1803 @@ mod test {
             .expect("failed create todo");
 
         let req = build_todo_req_with_empty("/todos/1", Method::DELETE);
-        let res = create_app(repository).oneshot(req).await.unwrap();
+        let res = create_app(
+            repository,
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
 
         assert_eq!(StatusCode::NO_CONTENT, res.status());
     }
 
     #[tokio::test]
-    async fn should_return_hello_world() {
+    async fn should_return_problem_for_missing_todo() {
         let repository = TodoRepositoryForMemory::new();
-        let req = Request::builder().uri("/").body(Body::empty()).unwrap();
-        let res = create_app(repository).oneshot(req).await.unwrap();
+        let req = build_todo_req_with_empty("/todos/1", Method::GET);
+        let res = create_app(
+            repository,
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+
+        assert_eq!(StatusCode::NOT_FOUND, res.status());
+        assert_eq!(res.headers()[header::CONTENT_TYPE], PROBLEM_JSON);
+        let body = res_to_string(res).await;
+        let problem: Problem = serde_json::from_str(&body).expect(&body);
+        assert_eq!(problem.status, 404);
+        assert_eq!(problem.title, "Not Found");
+        assert_eq!(problem.instance, Some("/todos/1".to_string()));
+    }
+
+    #[tokio::test]
+    async fn should_quote_the_request_id_in_problems() {
+        let mut req = build_todo_req_with_empty("/todos/1", Method::GET);
+        req.headers_mut()
+            .insert(X_REQUEST_ID, "support-ticket-1".parse().unwrap());
+        let res = create_app(
+            TodoRepositoryForMemory::new(),
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        )
+        .layer(middleware::from_fn(request_id))
+        .oneshot(req)
+        .await
+        .unwrap();
+
+        assert_eq!(res.headers()[X_REQUEST_ID], "support-ticket-1");
+        let body = res_to_string(res).await;
+        let problem: Problem = serde_json::from_str(&body).expect(&body);
+        assert_eq!(problem.request_id.as_deref(), Some("support-ticket-1"));
+    }
+
+    #[tokio::test]
+    async fn should_link_todo_resources() {
+        let repository = TodoRepositoryForMemory::new();
+        repository
+            .create(CreateTodo::new("should_link_todo_resources".to_string()))
+            .await
+            .expect("failed create todo");
+
+        let req = build_todo_req_with_empty("/todos/1", Method::GET);
+        let res = create_app(
+            repository.clone(),
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        let body: serde_json::Value = serde_json::from_str(&res_to_string(res).await).unwrap();
+        assert_eq!(body["_links"]["self"]["href"], "/todos/1");
+        assert_eq!(body["_links"]["labels"]["href"], "/labels");
+
+        let req = build_todo_req_with_empty("/api/todos", Method::GET);
+        let app = Router::new().nest(
+            "/api",
+            create_app(
+                repository,
+                LabelRepositoryForMemory::new(),
+                JobRepositoryForMemory::new(),
+                memory_backup(),
+            ),
+        );
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(
+            res.headers()[header::LINK],
+            r#"</api/todos>; rel="self", </api/labels>; rel="labels""#
+        );
+        let body: serde_json::Value = serde_json::from_str(&res_to_string(res).await).unwrap();
+        assert_eq!(body[0]["_links"]["self"]["href"], "/api/todos/1");
+    }
+
+    #[tokio::test]
+    async fn should_trim_todos_to_requested_fields() {
+        let repository = TodoRepositoryForMemory::new();
+        repository
+            .create(CreateTodo::new("should_trim_todos".to_string()))
+            .await
+            .expect("failed create todo");
+
+        let req = build_todo_req_with_empty("/todos?fields=id,text", Method::GET);
+        let res = create_app(
+            repository.clone(),
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        let body: serde_json::Value = serde_json::from_str(&res_to_string(res).await).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!([{ "id": 1, "text": "should_trim_todos" }])
+        );
+
+        let req = build_todo_req_with_empty("/todos/1?fields=completed", Method::GET);
+        let res = create_app(
+            repository,
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        let body: serde_json::Value = serde_json::from_str(&res_to_string(res).await).unwrap();
+        assert_eq!(body, serde_json::json!({ "completed": false }));
+    }
+
+    #[tokio::test]
+    async fn should_include_labels_only_on_request() {
+        let repository = TodoRepositoryForMemory::new();
+        repository
+            .create(CreateTodo::new("should_include_labels".to_string()))
+            .await
+            .expect("failed create todo");
+
+        let req = build_todo_req_with_empty("/todos/1", Method::GET);
+        let res = create_app(
+            repository.clone(),
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        let body: serde_json::Value = serde_json::from_str(&res_to_string(res).await).unwrap();
+        assert!(body.get("labels").is_none());
+
+        let req = build_todo_req_with_empty("/todos?include=labels", Method::GET);
+        let res = create_app(
+            repository.clone(),
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        let body: serde_json::Value = serde_json::from_str(&res_to_string(res).await).unwrap();
+        assert_eq!(body[0]["labels"], serde_json::json!([]));
+
+        let req = build_todo_req_with_empty("/todos?include=lables", Method::GET);
+        let res = create_app(
+            repository,
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(StatusCode::BAD_REQUEST, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_filter_todos_by_search_query() {
+        let repository = TodoRepositoryForMemory::new();
+        for (text, due) in [("buy oat milk", None), ("call mom", Some("2024-05-31"))] {
+            let due = due.map(|due| due.parse().unwrap());
+            repository
+                .create(CreateTodo::new(text.to_string()).with_due(due))
+                .await
+                .expect("failed create todo");
+        }
+        let app = create_app(
+            repository,
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        );
+
+        let req = build_todo_req_with_empty("/todos?q=is:open%20MILK", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        let body: serde_json::Value = serde_json::from_str(&res_to_string(res).await).unwrap();
+        assert_eq!(body.as_array().unwrap().len(), 1);
+        assert_eq!(body[0]["text"], "buy oat milk");
+
+        let req = build_todo_req_with_empty("/todos?ids=2,1", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        let body: serde_json::Value = serde_json::from_str(&res_to_string(res).await).unwrap();
+        assert_eq!(body[0]["id"], 2);
+        assert_eq!(body[1]["id"], 1);
+
+        let req = build_todo_req_with_empty("/todos?q=due%3C2024-06-01", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        let body: serde_json::Value = serde_json::from_str(&res_to_string(res).await).unwrap();
+        assert_eq!(body.as_array().unwrap().len(), 1);
+        assert_eq!(body[0]["text"], "call mom");
+
+        let req = build_todo_req_with_empty("/todos?q=due%3CJune", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::BAD_REQUEST, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_serve_feeds_guarded_by_token() {
+        let repository = TodoRepositoryForMemory::new();
+        repository
+            .create(CreateTodo::new("should_serve_atom_feed".to_string()))
+            .await
+            .expect("failed create todo");
+        repository
+            .create(
+                CreateTodo::new("should_serve_calendar_feed".to_string())
+                    .with_due("2024-06-01".parse().ok()),
+            )
+            .await
+            .expect("failed create todo");
+        let app = create_app(
+            repository,
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        );
+
+        let req = build_todo_req_with_empty("/feeds/todos.atom", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.headers()[header::CONTENT_TYPE], ATOM);
+        assert!(res_to_string(res)
+            .await
+            .contains("<title>should_serve_atom_feed</title>"));
+        let req = build_todo_req_with_empty("/export/calendar.ics", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::NOT_FOUND, res.status());
+
+        let app = app.layer(Extension(FeedToken("secret".to_string())));
+        let req = build_todo_req_with_empty("/feeds/todos.atom", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::UNAUTHORIZED, res.status());
+        let req = build_todo_req_with_empty("/feeds/todos.atom?token=secreT", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::UNAUTHORIZED, res.status());
+        let req = build_todo_req_with_empty("/feeds/todos.atom?token=secret", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+
+        let req = build_todo_req_with_empty("/export/calendar.ics", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::UNAUTHORIZED, res.status());
+        let req = build_todo_req_with_empty("/export/calendar.ics?token=secret", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(
+            res.headers()[header::CONTENT_TYPE],
+            "text/calendar; charset=utf-8"
+        );
+        let calendar = res_to_string(res).await;
+        assert!(calendar.contains("SUMMARY:should_serve_calendar_feed\r\n"));
+        assert!(calendar.contains("DTSTART;VALUE=DATE:20240601\r\n"));
+        assert!(!calendar.contains("SUMMARY:should_serve_atom_feed\r\n"));
+    }
+
+    #[tokio::test]
+    async fn should_sync_todos_over_caldav() {
+        let repository = TodoRepositoryForMemory::new();
+        let app = create_app(
+            repository.clone(),
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        );
+
+        let ics = "BEGIN:VCALENDAR\r\nBEGIN:VTODO\r\nUID:abc\r\nSUMMARY:from reminders\r\nEND:VTODO\r\nEND:VCALENDAR\r\n";
+        let req = Request::builder()
+            .uri("/caldav/todos/abc.ics")
+            .method(Method::PUT)
+            .body(Body::from(ics))
+            .unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+        assert_eq!(res.headers()[header::LOCATION], "/caldav/todos/1.ics");
+        assert_eq!(repository.find(1).await.unwrap().text(), "from reminders");
+
+        let req = Request::builder()
+            .uri("/caldav/todos/")
+            .method("PROPFIND")
+            .header("depth", "1")
+            .body(Body::empty())
+            .unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::MULTI_STATUS, res.status());
+        assert!(res_to_string(res)
+            .await
+            .contains("<d:href>/caldav/todos/1.ics</d:href>"));
+
+        let ics = ics.replace("SUMMARY:from reminders", "SUMMARY:done\r\nSTATUS:COMPLETED");
+        let req = Request::builder()
+            .uri("/caldav/todos/1.ics")
+            .method(Method::PUT)
+            .body(Body::from(ics))
+            .unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::NO_CONTENT, res.status());
+
+        let req = build_todo_req_with_empty("/caldav/todos/1.ics", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        let body = res_to_string(res).await;
+        assert!(body.contains("SUMMARY:done\r\n"));
+        assert!(body.contains("STATUS:COMPLETED\r\n"));
+    }
+
+    #[tokio::test]
+    async fn should_answer_unknown_methods_with_405_problem() {
+        let req = build_todo_req_with_empty("/labels", Method::PUT);
+        let res = create_app(
+            TodoRepositoryForMemory::new(),
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(StatusCode::METHOD_NOT_ALLOWED, res.status());
+        assert_eq!(res.headers()[header::CONTENT_TYPE], PROBLEM_JSON);
+        let allow = res.headers()[header::ALLOW].to_str().unwrap().to_string();
+        assert!(allow.contains("GET") && allow.contains("POST"));
+        let problem: Problem = serde_json::from_str(&res_to_string(res).await).unwrap();
+        assert_eq!(problem.problem_type, "/problems/method-not-allowed");
+        assert_eq!(problem.instance.as_deref(), Some("/labels"));
+    }
+
+    #[tokio::test]
+    async fn should_report_collection_size_on_head() {
+        let repository = TodoRepositoryForMemory::new();
+        repository
+            .create(CreateTodo::new("should_report_size".to_string()))
+            .await
+            .expect("failed create todo");
+        let app = create_app(
+            repository,
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        );
+
+        let req = build_todo_req_with_empty("/todos", Method::HEAD);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        assert_eq!(res.headers()["x-total-count"], "1");
+        let etag = res.headers()[header::ETAG].clone();
+        assert!(res_to_string(res).await.is_empty());
+
+        let req = build_todo_req_with_empty("/todos", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.headers()["x-total-count"], "1");
+        assert_eq!(res.headers()[header::ETAG], etag);
+    }
+
+    #[tokio::test]
+    async fn should_change_the_collection_etag_with_edits_and_queries() {
+        let repository = TodoRepositoryForMemory::new();
+        let todo = repository
+            .create(CreateTodo::new("should_change_etag".to_string()))
+            .await
+            .expect("failed create todo");
+        let app = create_app(
+            repository.clone(),
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        );
+        let etag = |uri: &'static str| {
+            let app = app.clone();
+            async move {
+                let req = build_todo_req_with_empty(uri, Method::HEAD);
+                app.oneshot(req).await.unwrap().headers()[header::ETAG].clone()
+            }
+        };
+
+        let before = etag("/todos").await;
+        assert_ne!(etag("/todos?q=milk").await, before);
+        repository
+            .update(todo.id(), UpdateTodo::new(None, Some(true)))
+            .await
+            .expect("failed update todo");
+        let edited = etag("/todos").await;
+        assert_ne!(edited, before, "an edit within the second changes it");
+        repository
+            .delete(todo.id())
+            .await
+            .expect("failed delete todo");
+        repository
+            .create(CreateTodo::new("should_change_etag".to_string()))
+            .await
+            .expect("failed create todo");
+        assert_ne!(etag("/todos").await, edited);
+    }
+
+    #[tokio::test]
+    async fn should_reject_unknown_fields_in_strict_mode() {
+        let repository = TodoRepositoryForMemory::new();
+        repository
+            .create(CreateTodo::new("should_reject_unknown".to_string()))
+            .await
+            .expect("failed create todo");
+        let app = create_app(
+            repository,
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        );
+        let body = r#"{ "compleated": true }"#.to_string();
+
+        let req = build_todo_req_with_json("/todos/1", Method::PATCH, body.clone());
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+
+        let app = app.layer(Extension(StrictJson));
+        let req = build_todo_req_with_json("/todos/1", Method::PATCH, body);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, res.status());
+        let problem: Problem = serde_json::from_str(&res_to_string(res).await).unwrap();
+        assert!(problem.errors.unwrap().contains_key("compleated"));
+    }
+
+    #[tokio::test]
+    async fn should_seed_only_in_dev_mode() {
+        let app = create_app(
+            TodoRepositoryForMemory::new(),
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        );
+        let req = build_todo_req_with_empty("/admin/seed", Method::POST);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::NOT_FOUND, res.status());
+
+        let app = app.layer(Extension(DevMode));
+        let req = build_todo_req_with_empty("/admin/seed", Method::POST);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+
+        let req = build_todo_req_with_empty("/todos", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        let todos: Vec<Todo> = serde_json::from_str(&res_to_string(res).await).unwrap();
+        assert!(!todos.is_empty());
+    }
+
+    #[tokio::test]
+    async fn should_back_up_behind_the_admin_token() {
+        let todos = TodoRepositoryForMemory::new();
+        let labels = LabelRepositoryForMemory::new();
+        todos
+            .create(CreateTodo::new("backed up".to_string()))
+            .await
+            .unwrap();
+        let app = create_app(
+            todos.clone(),
+            labels.clone(),
+            JobRepositoryForMemory::new(),
+            BackupRepositoryFor::new("memory", todos, labels),
+        );
+        let req = build_todo_req_with_empty("/admin/backup", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::NOT_FOUND, res.status());
+
+        let app = app.layer(Extension(AdminToken("secret".to_string())));
+        let req = build_todo_req_with_empty("/admin/backup", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::UNAUTHORIZED, res.status());
+
+        let req = Request::builder()
+            .uri("/admin/backup")
+            .header(header::AUTHORIZATION, "Bearer secret")
+            .body(Body::empty())
+            .unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let backup: serde_json::Value = serde_json::from_str(&res_to_string(res).await).unwrap();
+        assert_eq!(backup["format"], 1);
+        assert_eq!(backup["backend"], "memory");
+        assert_eq!(backup["labels"], serde_json::json!([]));
+        assert_eq!(backup["todos"][0]["text"], "backed up");
+        assert_eq!(backup["todo_labels"], serde_json::json!([]));
+        assert_eq!(backup["archived_todos"], serde_json::json!([]));
+    }
+
+    #[tokio::test]
+    async fn should_toggle_feature_flags_behind_the_admin_token() {
+        let app = create_app(
+            TodoRepositoryForMemory::new(),
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            BackupRepositoryFor::new(
+                "memory",
+                TodoRepositoryForMemory::new(),
+                LabelRepositoryForMemory::new(),
+            ),
+        )
+        .layer(Extension(Flags::default()))
+        .layer(Extension(AdminToken("secret".to_string())));
+        let feed = || build_todo_req_with_empty("/feeds/todos.atom", Method::GET);
+        let res = app.clone().oneshot(feed()).await.unwrap();
+        assert_ne!(StatusCode::NOT_FOUND, res.status());
+
+        let req = Request::builder()
+            .method(Method::PATCH)
+            .uri("/admin/flags")
+            .header(header::AUTHORIZATION, "Bearer secret")
+            .body(Body::from(r#"{"feeds": false}"#))
+            .unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let flags: serde_json::Value = serde_json::from_str(&res_to_string(res).await).unwrap();
+        assert_eq!(
+            flags,
+            serde_json::json!({ "caldav": true, "feeds": false, "events": true })
+        );
+
+        let res = app.clone().oneshot(feed()).await.unwrap();
+        assert_eq!(StatusCode::NOT_FOUND, res.status());
+        let problem: Problem = serde_json::from_str(&res_to_string(res).await).unwrap();
+        assert_eq!(problem.detail, "Feature [feeds] is disabled");
+
+        let req = build_todo_req_with_empty("/admin/flags", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::UNAUTHORIZED, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_report_liveness_and_readiness() {
+        let health = Health::default();
+        let app = create_app(
+            TodoRepositoryForMemory::new(),
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        )
+        .layer(Extension(health.clone()));
+        let req = build_todo_req_with_empty("/readyz", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+
+        health.record(Err("connection refused".to_string()));
+        let req = build_todo_req_with_empty("/readyz", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, res.status());
+        let body: serde_json::Value = serde_json::from_str(&res_to_string(res).await).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "ready": false,
+                "checks": {
+                    "database": {"up": false, "error": "connection refused"},
+                    "migrations": {"up": true},
+                },
+            })
+        );
+
+        // alive regardless
+        let req = build_todo_req_with_empty("/healthz", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        assert_eq!(res_to_string(res).await, r#"{"alive":true}"#);
+    }
+
+    #[tokio::test]
+    async fn should_report_the_build() {
+        let todos = TodoRepositoryForMemory::new();
+        let labels = LabelRepositoryForMemory::new();
+        let app = create_app(
+            todos.clone(),
+            labels.clone(),
+            JobRepositoryForMemory::new(),
+            BackupRepositoryFor::new("memory", todos, labels),
+        );
+        let req = build_todo_req_with_empty("/version", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let body: serde_json::Value = serde_json::from_str(&res_to_string(res).await).unwrap();
+        assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+        assert!(body["git_sha"].is_string());
+        assert!(body["built_at"].as_str().unwrap().ends_with('Z'));
+        assert!(body["features"].is_array());
+    }
+
+    #[tokio::test]
+    async fn should_restore_a_backup_behind_the_admin_token() {
+        let todos = TodoRepositoryForMemory::new();
+        let labels = LabelRepositoryForMemory::new();
+        todos
+            .create(CreateTodo::new("wiped".to_string()))
+            .await
+            .unwrap();
+        let app = create_app(
+            todos.clone(),
+            labels.clone(),
+            JobRepositoryForMemory::new(),
+            BackupRepositoryFor::new("memory", todos.clone(), labels.clone()),
+        )
+        .layer(Extension(AdminToken("secret".to_string())));
+        let backup = r#"{
+            "format": 1,
+            "backend": "sqlite",
+            "taken_at": "2026-10-14T00:00:00Z",
+            "labels": [{"id": 7, "name": "work"}],
+            "todos": [{"id": 3, "text": "restored", "completed": true, "version": 2, "updated_at": 0}],
+            "todo_labels": [],
+            "archived_todos": []
+        }"#;
+        let restore = |uri: &str, body: &str| {
+            Request::builder()
+                .uri(uri)
+                .method(Method::POST)
+                .header(header::AUTHORIZATION, "Bearer secret")
+                .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                .body(Body::from(body.to_string()))
+                .unwrap()
+        };
+
+        let dangling = backup.replace(
+            r#""todo_labels": []"#,
+            r#""todo_labels": [{"todo_id": 3, "label_id": 8}]"#,
+        );
+        let res = app
+            .clone()
+            .oneshot(restore("/admin/restore", &dangling))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, res.status());
+
+        // without uuids a merge would add the todos again every time
+        let res = app
+            .clone()
+            .oneshot(restore("/admin/restore", backup))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, res.status());
+        let problem: Problem = serde_json::from_str(&res_to_string(res).await).unwrap();
+        assert!(problem.errors.unwrap().contains_key("todos"));
+
+        let res = app
+            .oneshot(restore("/admin/restore?mode=wipe", backup))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let restored: serde_json::Value = serde_json::from_str(&res_to_string(res).await).unwrap();
+        assert_eq!(
+            restored,
+            serde_json::json!({"labels": 1, "todos": 1, "todo_labels": 0, "archived_todos": 0})
+        );
+        let stored = todos.all(&TodoFilter::default()).await.unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].text(), "restored");
+        assert!(stored[0].completed());
+        assert_eq!(labels.all().await.unwrap()[0].name, "work");
+    }
+
+    #[tokio::test]
+    async fn should_address_todos_by_uuid() {
+        let app = create_app(
+            TodoRepositoryForMemory::new(),
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        )
+        .layer(Extension(IdFormat::Uuid));
+
+        let req = build_todo_req_with_json(
+            "/todos",
+            Method::POST,
+            r#"{"text": "should_address_todos_by_uuid"}"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let body: serde_json::Value = serde_json::from_str(&res_to_string(res).await).unwrap();
+        let uuid = body["uuid"].as_str().unwrap().to_string();
+        assert_eq!(body["_links"]["self"]["href"], format!("/todos/{}", uuid));
+
+        let req = build_todo_req_with_empty(&format!("/todos/{}", uuid), Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(
+            res_to_todo(res).await.text(),
+            "should_address_todos_by_uuid"
+        );
+        let req = build_todo_req_with_empty("/todos/1", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let minted = "0192f3a4-5b6c-7d8e-9f00-112233445566";
+        let req = build_todo_req_with_json(
+            "/todos",
+            Method::POST,
+            format!(r#"{{"text": "offline", "uuid": "{}"}}"#, minted),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let req = build_todo_req_with_empty(&format!("/todos/{}", minted), Method::DELETE);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+    }
+
+    #[tokio::test]
+    async fn should_search_todos() {
+        let repository = TodoRepositoryForMemory::new();
+        repository
+            .create(CreateTodo::new("buy milk".to_string()))
+            .await
+            .expect("failed create todo");
+        let app = create_app(
+            repository,
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        );
+
+        let req = build_todo_req_with_empty("/todos/search?q=is:open%20milk", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let hits: Vec<serde_json::Value> = serde_json::from_str(&res_to_string(res).await).unwrap();
+        assert_eq!(hits[0]["highlight"], "buy <mark>milk</mark>");
+
+        let req = build_todo_req_with_empty("/todos/search?q=is:open", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::BAD_REQUEST, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_stream_todo_events() {
+        let bus = EventBus::new(8);
+        let app = create_app(
+            TodoRepositoryForMemory::new(),
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        )
+        .layer(Extension(bus.clone()));
+
+        let req = build_todo_req_with_empty("/todos/events", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "text/event-stream");
+
+        bus.publish(events::TodoEvent::Created { id: 1 });
+        let chunk = res.into_body().data().await.unwrap().unwrap();
+        let chunk = String::from_utf8(chunk.to_vec()).unwrap();
+        assert!(chunk.starts_with("event: created\n"));
+        assert!(chunk.contains(r#"data: {"type":"created","id":1}"#));
+    }
+
+    #[tokio::test]
+    async fn should_import_todos_from_csv() {
+        let app = create_app(
+            TodoRepositoryForMemory::new(),
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        );
+        let csv = "Title,Done\nShip it,yes\n,no\nCall mom,\n";
+        let req = Request::builder()
+            .uri("/import/csv?text=title&completed=done")
+            .method(Method::POST)
+            .header(header::CONTENT_TYPE, "text/csv")
+            .body(Body::from(csv))
+            .unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+        let report: handlers::csv::ImportReport =
+            serde_json::from_str(&res_to_string(res).await).unwrap();
+        assert_eq!((report.imported, report.failed), (2, 1));
+        assert_eq!(report.rows[0].id, Some(1));
+        assert!(report.rows[1].errors.as_ref().unwrap().contains_key("text"));
+        assert_eq!((report.rows[2].line, report.rows[2].id), (4, Some(2)));
+
+        let req = build_todo_req_with_empty("/todos/1", Method::GET);
+        let todo = res_to_todo(app.clone().oneshot(req).await.unwrap()).await;
+        assert!(todo.completed());
+
+        let req = Request::builder()
+            .uri("/import/csv?labels=tags")
+            .method(Method::POST)
+            .body(Body::from(csv))
+            .unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::BAD_REQUEST, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_export_the_listed_todos_as_csv() {
+        let repository = TodoRepositoryForMemory::new();
+        for text in ["first", "second", "third"] {
+            repository
+                .create(CreateTodo::new(text.to_string()))
+                .await
+                .expect("failed create todo");
+        }
+        repository
+            .update(2, UpdateTodo::new(None, Some(true)))
+            .await
+            .unwrap();
+        let app = create_app(
+            repository,
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        );
+
+        let req = build_todo_req_with_empty("/export/csv", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.headers()[header::CONTENT_TYPE], handlers::csv::CSV);
+        assert_eq!(res_to_string(res).await.lines().count(), 4);
+
+        let req = build_todo_req_with_empty("/export/csv?q=is:open&ids=3,2", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(
+            res_to_string(res).await,
+            "id,uuid,text,completed,due,labels\n3,,third,false,,\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn should_export_a_markdown_checklist() {
+        let (app, todos, labels) = sqlite_app().await;
+        let garden = labels.create("garden".to_string(), None).await.unwrap();
+        todos
+            .create(CreateTodo::new("Prune roses".to_string()).with_labels(vec![garden.id]))
+            .await
+            .unwrap();
+        let done = todos
+            .create(CreateTodo::new("Call mum".to_string()))
+            .await
+            .unwrap();
+        todos
+            .update(done.id(), UpdateTodo::new(None, Some(true)))
+            .await
+            .unwrap();
+
+        let req = build_todo_req_with_empty("/export/markdown", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(
+            res.headers()[header::CONTENT_TYPE],
+            handlers::markdown::MARKDOWN
+        );
+        assert_eq!(
+            res_to_string(res).await,
+            "# Todos\n\n## garden\n\n- [ ] Prune roses\n\n## Without a label\n\n- [x] Call mum\n"
+        );
+
+        let req = build_todo_req_with_empty("/export/markdown?q=is:open", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        assert!(!res_to_string(res).await.contains("Call mum"));
+    }
+
+    #[tokio::test]
+    async fn should_export_an_xlsx_workbook() {
+        let (app, todos, _) = sqlite_app().await;
+        todos
+            .create(CreateTodo::new("Prune roses".to_string()))
+            .await
+            .unwrap();
+
+        let req = build_todo_req_with_empty("/export/xlsx", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        assert_eq!(res.headers()[header::CONTENT_TYPE], handlers::xlsx::XLSX);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert!(body.starts_with(b"PK\x03\x04"), "a zip archive");
+    }
+
+    #[tokio::test]
+    async fn should_print_the_todos_of_a_label() {
+        let (app, todos, labels) = sqlite_app().await;
+        let garden = labels.create("garden".to_string(), None).await.unwrap();
+        todos
+            .create(CreateTodo::new("Prune roses".to_string()).with_labels(vec![garden.id]))
+            .await
+            .unwrap();
+        todos
+            .create(CreateTodo::new("Call mum".to_string()))
+            .await
+            .unwrap();
+
+        let req =
+            build_todo_req_with_empty(&format!("/labels/{}/export.pdf", garden.id), Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.headers()[header::CONTENT_TYPE], handlers::pdf::PDF);
+        let pdf = res_to_string(res).await;
+        assert!(pdf.starts_with("%PDF-1.4\n"));
+        let hex = |text: &str| -> String { text.bytes().map(|b| format!("{:02X}", b)).collect() };
+        assert!(pdf.contains(&hex("Prune roses")));
+        assert!(!pdf.contains(&hex("Call mum")));
+
+        let req = build_todo_req_with_empty("/labels/99/export.pdf", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::NOT_FOUND, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_export_everything_as_one_document() {
+        let repository = TodoRepositoryForMemory::new();
+        for text in ["first", "second"] {
+            repository
+                .create(CreateTodo::new(text.to_string()))
+                .await
+                .expect("failed create todo");
+        }
+        let labels = LabelRepositoryForMemory::new();
+        labels.create("work".to_string(), None).await.unwrap();
+        let app = create_app(
+            repository,
+            labels,
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        );
+
+        let req = build_todo_req_with_empty("/export/json", Method::GET);
+        let res = app.oneshot(req).await.unwrap();
+        let export: handlers::json::Export =
+            serde_json::from_str(&res_to_string(res).await).unwrap();
+        assert_eq!(export.format, handlers::json::EXPORT_FORMAT);
+        assert_eq!(export.labels[0].name, "work");
+        let texts: Vec<_> = export.todos.iter().map(|todo| todo.text.as_str()).collect();
+        assert_eq!(texts, vec!["first", "second"]);
+    }
+
+    /// An app over an in-memory SQLite database, for tests needing labels.
+    async fn sqlite_app() -> (Router, TodoRepositoryForSqlite, LabelRepositoryForSqlite) {
+        let pool = repositories::connect_sqlite(
+            "sqlite::memory:",
+            &PoolSettings::default(),
+            Migrations::Apply,
+        )
+        .await
+        .expect("failed open sqlite");
+        let todos = TodoRepositoryForSqlite::new(pool.clone());
+        let labels = LabelRepositoryForSqlite::new(pool.clone());
+        let links: Arc<dyn LinkRepository> = Arc::new(LinkRepositoryForSqlite::new(pool));
+        let app = create_app(
+            todos.clone(),
+            labels.clone(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        )
+        .layer(Extension(links));
+        (app, todos, labels)
+    }
+
+    #[tokio::test]
+    async fn should_import_an_export_on_another_instance() {
+        let (source, todos, labels) = sqlite_app().await;
+        labels.create("unused".to_string(), None).await.unwrap();
+        let work = labels.create("work".to_string(), None).await.unwrap();
+        let uuid = Uuid::now_v7();
+        todos
+            .create(CreateTodo::new("plain".to_string()))
+            .await
+            .unwrap();
+        let done = todos
+            .create(
+                CreateTodo::new("labelled".to_string())
+                    .with_labels(vec![work.id])
+                    .with_uuid(Some(uuid.clone()))
+                    .with_due("2024-06-01".parse().ok()),
+            )
+            .await
+            .unwrap();
+        todos
+            .update(done.id(), UpdateTodo::new(None, Some(true)))
+            .await
+            .unwrap();
+        let req = build_todo_req_with_empty("/export/json", Method::GET);
+        let export = res_to_string(source.oneshot(req).await.unwrap()).await;
+
+        let (target, todos, labels) = sqlite_app().await;
+        labels.create("work".to_string(), None).await.unwrap();
+        let req = build_todo_req_with_json("/import/json", Method::POST, export.clone());
+        let res = target.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+        let imported: handlers::json::Imported =
+            serde_json::from_str(&res_to_string(res).await).unwrap();
+        assert_eq!(
+            (imported.labels, imported.todos, imported.skipped),
+            (1, 2, 0)
+        );
+
+        let imported_done = todos
+            .find_with_labels(imported.ids[&done.id()])
+            .await
+            .unwrap();
+        assert!(imported_done.todo.completed());
+        assert_eq!(imported_done.todo.version(), 1);
+        assert_eq!(imported_done.todo.uuid(), Some(&uuid));
+        assert_eq!(
+            imported_done.todo.due().map(|due| due.as_str()),
+            Some("2024-06-01")
+        );
+        assert_eq!(imported_done.labels[0].name, "work");
+        assert_eq!(labels.all().await.unwrap().len(), 2);
+
+        let req = build_todo_req_with_json("/import/json", Method::POST, export.clone());
+        let res = target.clone().oneshot(req).await.unwrap();
+        let imported: handlers::json::Imported =
+            serde_json::from_str(&res_to_string(res).await).unwrap();
+        assert_eq!(
+            (imported.labels, imported.todos, imported.skipped),
+            (0, 1, 1)
+        );
+
+        let unsupported = export.replacen(r#""format":1"#, r#""format":0"#, 1);
+        let req = build_todo_req_with_json("/import/json", Method::POST, unsupported);
+        let res = target.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_import_a_todoist_sync() {
+        let (app, todos, labels) = sqlite_app().await;
+        let sync = r#"{
+            "full_sync": true,
+            "labels": [{ "id": "2156154810", "name": "work" }],
+            "projects": [{ "id": "6Jf8VQXxpwv56VQ7", "name": "Garden" }],
+            "items": [
+                { "id": "6X7rM8997g3RQmvh", "content": "Prune roses", "checked": true,
+                  "labels": ["work"], "project_id": "6Jf8VQXxpwv56VQ7",
+                  "due": { "date": "2026-10-20" } }
+            ]
+        }"#;
+        let req = build_todo_req_with_json("/import/todoist", Method::POST, sync.to_string());
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+        let imported: handlers::json::Imported =
+            serde_json::from_str(&res_to_string(res).await).unwrap();
+
+        let todo = todos.find_with_labels(imported.ids[&1]).await.unwrap();
+        assert_eq!(todo.todo.text(), "Prune roses");
+        assert!(todo.todo.completed());
+        let names: Vec<_> = todo
+            .labels
+            .iter()
+            .map(|label| label.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["work", "Garden"]);
+        assert_eq!(labels.all().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn should_import_a_trello_board() {
+        let (app, todos, labels) = sqlite_app().await;
+        let board = r#"{
+            "name": "Garden",
+            "lists": [
+                { "id": "5f1", "name": "Backlog", "closed": false },
+                { "id": "5f2", "name": "Shipped", "closed": false }
+            ],
+            "labels": [{ "id": "6a1", "name": "", "color": "green" }],
+            "cards": [
+                { "id": "7c1", "name": "Prune roses", "desc": "Before the frost",
+                  "idList": "5f1", "idLabels": ["6a1"], "closed": false },
+                { "id": "7c2", "name": "Buy seeds", "idList": "5f2", "idLabels": [] }
+            ],
+            "checklists": []
+        }"#;
+        let req = build_todo_req_with_json(
+            "/import/trello?done=Shipped",
+            Method::POST,
+            board.to_string(),
+        );
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+        let imported: handlers::json::Imported =
+            serde_json::from_str(&res_to_string(res).await).unwrap();
+        assert_eq!(imported.todos, 2);
+
+        let todo = todos.find_with_labels(imported.ids[&1]).await.unwrap();
+        assert_eq!(todo.todo.text(), "Prune roses");
+        assert!(!todo.todo.completed());
+        let names: Vec<_> = todo
+            .labels
+            .iter()
+            .map(|label| label.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["green", "Backlog"]);
+        let done = todos.find_with_labels(imported.ids[&2]).await.unwrap();
+        assert!(done.todo.completed());
+        assert!(done.labels.is_empty());
+        assert_eq!(labels.all().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn should_turn_mail_into_todos() {
+        let repository = TodoRepositoryForMemory::new();
+        let app = create_app(
+            repository.clone(),
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        );
+        let mail = |recipient: &str, signing_key: &str| {
+            let timestamp = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+                .to_string();
+            let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, signing_key.as_bytes());
+            let signature = ring::hmac::sign(&key, format!("{}c0ffee", timestamp).as_bytes());
+            let signature = handlers::hex(signature.as_ref());
+            let form = serde_urlencoded::to_string([
+                ("recipient", recipient),
+                ("sender", "me@example.com"),
+                ("subject", "Buy milk"),
+                ("body-plain", "On the way home"),
+                ("timestamp", &timestamp),
+                ("token", "c0ffee"),
+                ("signature", &signature),
+            ])
+            .unwrap();
+            Request::builder()
+                .uri("/integrations/mail")
+                .method(Method::POST)
+                .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .body(Body::from(form))
+                .unwrap()
+        };
+
+        let res = app
+            .clone()
+            .oneshot(mail("todo@example.com", "key"))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::NOT_FOUND, res.status());
+
+        let app = app.layer(Extension(MailInbox::new("todo@example.com", "key")));
+        let res = app
+            .clone()
+            .oneshot(mail("todo@example.com", "wrong"))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::UNAUTHORIZED, res.status());
+        let res = app
+            .clone()
+            .oneshot(mail("someone@example.com", "key"))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::NOT_ACCEPTABLE, res.status());
+        let res = app
+            .oneshot(mail("Todos <todo@example.com>", "key"))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+        let todo = res_to_todo(res).await;
+        assert_eq!(todo.text(), "Buy milk");
+        assert_eq!(repository.find(todo.id()).await.unwrap(), todo);
+    }
+
+    #[tokio::test]
+    async fn should_answer_slack_commands() {
+        let repository = TodoRepositoryForMemory::new();
+        let app = create_app(
+            repository.clone(),
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        )
+        .layer(Extension(SlackSecret::new("shh")));
+        let command = |text: &str, secret: &str| {
+            let timestamp = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+                .to_string();
+            let form = serde_urlencoded::to_string([
+                ("command", "/todo"),
+                ("text", text),
+                ("user_id", "U2147483697"),
+            ])
+            .unwrap();
+            let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, secret.as_bytes());
+            let tag = ring::hmac::sign(&key, format!("v0:{}:{}", timestamp, form).as_bytes());
+            let hex = handlers::hex(tag.as_ref());
+            Request::builder()
+                .uri("/integrations/slack/command")
+                .method(Method::POST)
+                .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .header("x-slack-request-timestamp", timestamp)
+                .header("x-slack-signature", format!("v0={}", hex))
+                .body(Body::from(form))
+                .unwrap()
+        };
+
+        let res = app
+            .clone()
+            .oneshot(command("list", "guessed"))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::UNAUTHORIZED, res.status());
+
+        let res = app
+            .clone()
+            .oneshot(command("add Buy milk", "shh"))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let reply: serde_json::Value = serde_json::from_str(&res_to_string(res).await).unwrap();
+        assert_eq!(reply["response_type"], "in_channel");
+        assert_eq!(reply["text"], "<@U2147483697> added *Buy milk*");
+        assert_eq!(repository.find(1).await.unwrap().text(), "Buy milk");
+
+        let res = app.oneshot(command("list", "shh")).await.unwrap();
+        let reply: serde_json::Value = serde_json::from_str(&res_to_string(res).await).unwrap();
+        assert_eq!(reply["blocks"][0]["text"]["text"], "1 open todos");
+        assert_eq!(reply["blocks"][1]["text"]["text"], "• Buy milk `#1`");
+    }
+
+    #[tokio::test]
+    async fn should_talk_to_linked_telegram_chats() {
+        let repository = TodoRepositoryForMemory::new();
+        let app = create_app(
+            repository.clone(),
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        )
+        .layer(Extension(TelegramBot::new("s3cret", [])))
+        .layer(Extension(AdminToken("secret".to_string())));
+        let message = |secret: &str, text: &str| {
+            let update = serde_json::json!({
+                "update_id": 10000,
+                "message": {
+                    "message_id": 1365,
+                    "date": 1441645532,
+                    "chat": { "id": 1111111, "type": "private" },
+                    "text": text,
+                },
+            });
+            Request::builder()
+                .uri("/integrations/telegram")
+                .method(Method::POST)
+                .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                .header("x-telegram-bot-api-secret-token", secret)
+                .body(Body::from(update.to_string()))
+                .unwrap()
+        };
+        let reply = |res: Response| async {
+            let reply: serde_json::Value = serde_json::from_str(&res_to_string(res).await).unwrap();
+            assert_eq!(reply["method"], "sendMessage");
+            assert_eq!(reply["chat_id"], 1111111);
+            reply["text"].as_str().unwrap().to_string()
+        };
+
+        let res = app
+            .clone()
+            .oneshot(message("guessed", "/list"))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::UNAUTHORIZED, res.status());
+        let res = app
+            .clone()
+            .oneshot(message("s3cret", "/add Buy milk"))
+            .await
+            .unwrap();
+        assert!(reply(res).await.starts_with("Send /link"));
+        assert!(repository
+            .all(&TodoFilter::default())
+            .await
+            .unwrap()
+            .is_empty());
+
+        let req = Request::builder()
+            .uri("/admin/telegram/codes")
+            .method(Method::POST)
+            .header(header::AUTHORIZATION, "Bearer secret")
+            .body(Body::empty())
+            .unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+        let code: serde_json::Value = serde_json::from_str(&res_to_string(res).await).unwrap();
+        let code = code["code"].as_str().unwrap();
+        let link = format!("/link {}", code);
+        let res = app.clone().oneshot(message("s3cret", &link)).await.unwrap();
+        assert!(reply(res).await.starts_with("Linked."));
+
+        let res = app
+            .clone()
+            .oneshot(message("s3cret", "/add@my_todo_bot Buy milk"))
+            .await
+            .unwrap();
+        assert_eq!(reply(res).await, "Added #1: Buy milk");
+        let res = app
+            .clone()
+            .oneshot(message("s3cret", "/list"))
+            .await
+            .unwrap();
+        assert_eq!(reply(res).await, "1 open todos:\n#1 Buy milk");
+        let res = app.oneshot(message("s3cret", "/done #1")).await.unwrap();
+        assert_eq!(reply(res).await, "Done: Buy milk");
+        assert!(repository.find(1).await.unwrap().completed());
+    }
+
+    #[tokio::test]
+    async fn should_follow_github_issues_through_the_webhook() {
+        let (app, todos, _) = sqlite_app().await;
+        let sync = GitHubSync::new("octo/garden", "mona", "t0k3n")
+            .with_webhook_secret(Some("hook".to_string()));
+        let app = app.layer(Extension(sync));
+        let delivery = |event: &str, state: &str, secret: &str| {
+            let body = serde_json::json!({
+                "action": "opened",
+                "issue": {
+                    "number": 7,
+                    "title": "Prune roses",
+                    "state": state,
+                    "assignees": [{ "login": "mona" }],
+                },
+                "repository": { "full_name": "octo/garden" },
+            })
+            .to_string();
+            let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, secret.as_bytes());
+            let tag = ring::hmac::sign(&key, body.as_bytes());
+            let hex = handlers::hex(tag.as_ref());
+            Request::builder()
+                .uri("/integrations/github")
+                .method(Method::POST)
+                .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                .header("x-github-event", event)
+                .header("x-hub-signature-256", format!("sha256={}", hex))
+                .body(Body::from(body))
+                .unwrap()
+        };
+
+        let res = app
+            .clone()
+            .oneshot(delivery("issues", "open", "guessed"))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::UNAUTHORIZED, res.status());
+        let res = app
+            .clone()
+            .oneshot(delivery("ping", "open", "hook"))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::NO_CONTENT, res.status());
+
+        let res = app
+            .clone()
+            .oneshot(delivery("issues", "open", "hook"))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let all = todos.all_with_labels(&TodoFilter::default()).await.unwrap();
+        assert_eq!(all.len(), 1);
+        assert_eq!(all[0].todo.text(), "Prune roses");
+        assert!(all[0].labels.is_empty());
+
+        let res = app
+            .oneshot(delivery("issues", "closed", "hook"))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        assert!(todos.find(all[0].todo.id()).await.unwrap().completed());
+    }
+
+    #[tokio::test]
+    async fn should_refuse_github_webhooks_without_a_sql_database() {
+        let sync = GitHubSync::new("octo/garden", "mona", "t0k3n")
+            .with_webhook_secret(Some("hook".to_string()));
+        let app = create_app(
+            TodoRepositoryForMemory::new(),
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        )
+        .layer(Extension(sync));
+        let body = r#"{"issue":{"number":7,"title":"Prune roses","state":"open"},"repository":{"full_name":"octo/garden"}}"#;
+        let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, b"hook");
+        let tag = ring::hmac::sign(&key, body.as_bytes());
+        let req = Request::builder()
+            .uri("/integrations/github")
+            .method(Method::POST)
+            .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .header("x-github-event", "issues")
+            .header(
+                "x-hub-signature-256",
+                format!("sha256={}", handlers::hex(tag.as_ref())),
+            )
+            .body(Body::from(body))
+            .unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::NOT_FOUND, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_register_push_subscriptions() {
+        let app = create_app(
+            TodoRepositoryForMemory::new(),
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        );
+        let req = build_todo_req_with_empty("/push/key", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::NOT_FOUND, res.status());
+
+        let public =
+            "BJgRr_49WwOR1fzppiqq04vCeaWSQFwC7bGq_J--yXYb9BZ27UNFWP3m85jnsCnyl3Ft2rWytx6NU6D8UHFCWas";
+        let push = WebPush::new(
+            public,
+            "_r_2LS1KMYydAgsUHTU-nkDpbHq6-omulHS39NE8_OY",
+            "mailto:admin@example.com",
+            Default::default(),
+        )
+        .unwrap();
+        let app = app.layer(Extension(push));
+        let req = build_todo_req_with_empty("/push/key", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        let body: serde_json::Value = serde_json::from_str(&res_to_string(res).await).unwrap();
+        assert_eq!(body["public_key"], public);
+
+        let subscription = |endpoint: &str| {
+            serde_json::json!({
+                "endpoint": endpoint,
+                "expirationTime": null,
+                "keys": {
+                    "p256dh": "BCVxsr7N_eNgVRqvHtD0zTZsEc6-VV-JvLexhqUzORcxaOzi6-AYWXvTBHm4bjyPjs7Vd8pZGH6SRpkNtoIAiw4",
+                    "auth": "BTBZMqHH6r4Tts7J_aSIgg",
+                },
+            })
+            .to_string()
+        };
+        let req = build_todo_req_with_json(
+            "/push/subscriptions",
+            Method::POST,
+            subscription("http://localhost:6379/"),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::BAD_REQUEST, res.status());
+        let endpoint = "https://updates.push.services.mozilla.com/wpush/v2/abc";
+        let req =
+            build_todo_req_with_json("/push/subscriptions", Method::POST, subscription(endpoint));
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+
+        let unsubscribe = serde_json::json!({ "endpoint": endpoint }).to_string();
+        for status in [StatusCode::NO_CONTENT, StatusCode::NOT_FOUND] {
+            let req = build_todo_req_with_json(
+                "/push/subscriptions",
+                Method::DELETE,
+                unsubscribe.clone(),
+            );
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(status, res.status());
+        }
+    }
+
+    #[tokio::test]
+    async fn should_attach_files_to_todos() {
+        let app = create_app(
+            TodoRepositoryForMemory::new(),
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        );
+        let req = build_todo_req_with_empty("/attachments/1", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::NOT_FOUND, res.status());
+
+        let dir = std::env::temp_dir().join(format!("my-todo-attach-{}", std::process::id()));
+        let store = attachments::AttachmentStoreForDisk::new(&dir);
+        let app = app.layer(Extension(Attachments::new(Arc::new(store), 8)));
+        let req = build_todo_req_with_json(
+            "/todos",
+            Method::POST,
+            r#"{"text": "file taxes"}"#.to_string(),
+        );
+        app.clone().oneshot(req).await.unwrap();
+
+        let upload = |name: &str, body: &'static str| {
+            Request::builder()
+                .uri(format!("/attachments/1/{}", name))
+                .method(Method::PUT)
+                .body(Body::from(body))
+                .unwrap()
+        };
+        let res = app
+            .clone()
+            .oneshot(upload("w2.txt", "income"))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+        let res = app
+            .clone()
+            .oneshot(upload("big.txt", "more than 8"))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::PAYLOAD_TOO_LARGE, res.status());
+        let res = app.clone().oneshot(upload(".env", "x")).await.unwrap();
+        assert_eq!(StatusCode::NOT_FOUND, res.status());
+
+        let req = build_todo_req_with_empty("/attachments/1", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        let body: serde_json::Value = serde_json::from_str(&res_to_string(res).await).unwrap();
+        assert_eq!(body, serde_json::json!([{ "name": "w2.txt", "size": 6 }]));
+        let req = build_todo_req_with_empty("/attachments/1/w2.txt", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(
+            res.headers()[header::CONTENT_DISPOSITION],
+            "attachment; filename*=UTF-8''w2.txt"
+        );
+        assert_eq!(res_to_string(res).await, "income");
+
+        for status in [StatusCode::NO_CONTENT, StatusCode::NOT_FOUND] {
+            let req = build_todo_req_with_empty("/attachments/1/w2.txt", Method::DELETE);
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(status, res.status());
+        }
+        let req = build_todo_req_with_empty("/attachments/2", Method::GET);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::NOT_FOUND, res.status());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn should_export_todos_as_ndjson() {
+        let repository = TodoRepositoryForMemory::new();
+        for text in ["first", "second"] {
+            repository
+                .create(CreateTodo::new(text.to_string()))
+                .await
+                .expect("failed create todo");
+        }
+
+        let req = build_todo_req_with_empty("/todos/export.ndjson", Method::GET);
+        let res = create_app(
+            repository,
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(res.headers()[header::CONTENT_TYPE], NDJSON);
+        let body = res_to_string(res).await;
+        let todos: Vec<Todo> = body
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            todos,
+            vec![
+                Todo::new(1, "first".to_string()),
+                Todo::new(2, "second".to_string())
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn should_negotiate_json_api_documents() {
+        let repository = TodoRepositoryForMemory::new();
+        repository
+            .create(CreateTodo::new("should_negotiate_json_api".to_string()))
+            .await
+            .expect("failed create todo");
+
+        let req = Request::builder()
+            .uri("/todos/1?include=labels")
+            .header(header::ACCEPT, JSON_API)
+            .body(Body::empty())
+            .unwrap();
+        let res = create_app(
+            repository,
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+
+        assert_eq!(res.headers()[header::CONTENT_TYPE], JSON_API);
+        let body: serde_json::Value = serde_json::from_str(&res_to_string(res).await).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "data": {
+                    "type": "todos",
+                    "id": "1",
+                    "attributes": {
+                        "text": "should_negotiate_json_api",
+                        "completed": false,
+                        "version": 1
+                    },
+                    "relationships": { "labels": { "data": [] } },
+                    "links": { "self": { "href": "/todos/1" } }
+                }
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn should_purge_completed_todos_in_a_job() {
+        let repository = TodoRepositoryForMemory::new();
+        repository
+            .create(CreateTodo::new("should_purge".to_string()))
+            .await
+            .expect("failed create todo");
+        repository
+            .update(1, serde_json::from_str(r#"{"completed": true}"#).unwrap())
+            .await
+            .expect("failed update todo");
+        let app = create_app(
+            repository.clone(),
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        );
+
+        let req = build_todo_req_with_empty("/todos/purge", Method::POST);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::ACCEPTED, res.status());
+        assert_eq!(res.headers()[header::LOCATION], "/jobs/1");
+
+        let job = loop {
+            let req = build_todo_req_with_empty("/jobs/1", Method::GET);
+            let res = app.clone().oneshot(req).await.unwrap();
+            let job: serde_json::Value = serde_json::from_str(&res_to_string(res).await).unwrap();
+            if job["status"] != serde_json::json!(JobStatus::Pending)
+                && job["status"] != serde_json::json!(JobStatus::Running)
+            {
+                break job;
+            }
+            tokio::task::yield_now().await;
+        };
+        assert_eq!(job["status"], "succeeded");
+        assert_eq!(job["result"], serde_json::json!({ "purged": 1 }));
+        assert!(repository
+            .all(&TodoFilter::default())
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn should_answer_if_modified_since_with_304() {
+        let repository = TodoRepositoryForMemory::new();
+        repository
+            .create(CreateTodo::new("should_answer_304".to_string()))
+            .await
+            .expect("failed create todo");
+        let app = create_app(
+            repository,
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        );
+
+        for path in ["/todos/1", "/todos"] {
+            let res = app
+                .clone()
+                .oneshot(build_todo_req_with_empty(path, Method::GET))
+                .await
+                .unwrap();
+            assert_eq!(StatusCode::OK, res.status());
+            let last_modified = res.headers()[header::LAST_MODIFIED].clone();
+
+            let req = Request::builder()
+                .uri(path)
+                .header(header::IF_MODIFIED_SINCE, last_modified)
+                .body(Body::empty())
+                .unwrap();
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(StatusCode::NOT_MODIFIED, res.status());
+
+            let req = Request::builder()
+                .uri(path)
+                .header(header::IF_MODIFIED_SINCE, "Thu, 01 Jan 1970 00:00:00 GMT")
+                .body(Body::empty())
+                .unwrap();
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(StatusCode::OK, res.status());
+        }
+    }
+
+    #[tokio::test]
+    async fn should_reject_empty_todo_with_422() {
+        let repository = TodoRepositoryForMemory::new();
+        let req = build_todo_req_with_json("/todos", Method::POST, r#"{"text": ""}"#.to_string());
+        let res = create_app(
+            repository,
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, res.status());
+        let body = res_to_string(res).await;
+        let problem: Problem = serde_json::from_str(&body).expect(&body);
+        assert_eq!(
+            problem.errors,
+            Some(
+                [("text".to_string(), vec!["can not be empty".to_string()])]
+                    .into_iter()
+                    .collect()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn should_localize_errors_by_accept_language() {
+        let repository = TodoRepositoryForMemory::new();
+        let req = Request::builder()
+            .uri("/todos")
+            .method(Method::POST)
+            .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .header(header::ACCEPT_LANGUAGE, "ja-JP,ja;q=0.9,en;q=0.8")
+            .body(Body::from(r#"{"text": ""}"#))
+            .unwrap();
+        let res = create_app(
+            repository,
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+
+        assert_eq!(res.headers()[header::CONTENT_LANGUAGE], "ja");
+        let body = res_to_string(res).await;
+        let problem: Problem = serde_json::from_str(&body).expect(&body);
+        assert_eq!(problem.title, "処理できない内容です");
+        assert_eq!(problem.detail, "入力値が不正です: [text: 空にできません]");
+    }
+
+    #[tokio::test]
+    async fn should_reject_duplicate_label_with_409() {
+        let label_repository = LabelRepositoryForMemory::new();
+        label_repository
+            .create("duplicate".to_string(), None)
+            .await
+            .expect("failed create label");
+
+        let req = build_todo_req_with_json(
+            "/labels",
+            Method::POST,
+            r#"{"name": "duplicate"}"#.to_string(),
+        );
+        let res = create_app(
+            TodoRepositoryForMemory::new(),
+            label_repository,
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+
+        assert_eq!(StatusCode::CONFLICT, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_return_hello_world() {
+        let repository = TodoRepositoryForMemory::new();
+        let req = Request::builder().uri("/").body(Body::empty()).unwrap();
+        let res = create_app(
+            repository,
+            LabelRepositoryForMemory::new(),
+            JobRepositoryForMemory::new(),
+            memory_backup(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
         let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
         let body = String::from_utf8(bytes.to_vec()).unwrap();
-        assert_eq!(body, "Hello, world!!");
+        assert_eq!(body, "Hello, World!");
     }
 }
@@ -0,0 +1,144 @@
+use std::sync::{Arc, RwLock};
+
+use axum::{
+    extract::Extension,
+    http::{header, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::handlers::{admin::Admin, error::ApiError, i18n::tr, ValidatedJson};
+
+/// How long clients are told to wait when the operator gave no estimate.
+const RETRY_AFTER_SECS: u64 = 60;
+
+/// Why the API is down, shown to clients instead of half-broken responses.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Validate)]
+pub struct Window {
+    #[validate(length(max = 500))]
+    pub message: Option<String>,
+    /// Sent as `Retry-After`.
+    #[serde(default = "default_retry_after")]
+    #[validate(range(min = 1))]
+    pub retry_after_secs: u64,
+}
+
+fn default_retry_after() -> u64 {
+    RETRY_AFTER_SECS
+}
+
+/// Whether the API is down for maintenance, switched on and off through
+/// `/admin/maintenance` around migrations and restores. It is off after a
+/// restart.
+#[derive(Debug, Clone, Default)]
+pub struct Maintenance(Arc<RwLock<Option<Window>>>);
+
+impl Maintenance {
+    pub fn current(&self) -> Option<Window> {
+        self.0.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn set(&self, window: Option<Window>) {
+        *self.0.write().unwrap_or_else(|e| e.into_inner()) = window;
+    }
+}
+
+/// Requests that keep being served during maintenance: probes, and the
+/// admin endpoints that run it.
+fn exempt(path: &str) -> bool {
+    matches!(path, "/healthz" | "/readyz") || path.starts_with("/admin/")
+}
+
+/// Answers `503` with a `Retry-After` to everything but [`exempt`]
+/// requests while maintenance is on.
+pub async fn maintenance<B>(req: Request<B>, next: Next<B>) -> Response {
+    let window = req
+        .extensions()
+        .get::<Maintenance>()
+        .and_then(Maintenance::current);
+    let Some(window) = window.filter(|_| !exempt(req.uri().path())) else {
+        return next.run(req).await;
+    };
+    let message = window
+        .message
+        .unwrap_or_else(|| tr("Down for maintenance, try again later"));
+    let mut res = ApiError::Unavailable(message).into_response();
+    res.headers_mut().insert(
+        header::RETRY_AFTER,
+        HeaderValue::from(window.retry_after_secs),
+    );
+    res
+}
+
+/// `GET /admin/maintenance`: the window in effect, `null` when serving.
+pub async fn maintenance_window(
+    _: Admin,
+    Extension(maintenance): Extension<Maintenance>,
+) -> Json<Option<Window>> {
+    Json(maintenance.current())
+}
+
+/// `PUT /admin/maintenance`: stops serving until it is deleted.
+pub async fn start_maintenance(
+    _: Admin,
+    Extension(maintenance): Extension<Maintenance>,
+    ValidatedJson(window): ValidatedJson<Window>,
+) -> Json<Window> {
+    tracing::warn!("maintenance started: {:?}", window);
+    maintenance.set(Some(window.clone()));
+    Json(window)
+}
+
+/// `DELETE /admin/maintenance`: serves again.
+pub async fn end_maintenance(
+    _: Admin,
+    Extension(maintenance): Extension<Maintenance>,
+) -> StatusCode {
+    if maintenance.current().is_some() {
+        tracing::warn!("maintenance ended");
+    }
+    maintenance.set(None);
+    StatusCode::NO_CONTENT
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{body::Body, middleware, routing::get, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn answers_503_but_to_probes_and_admins() {
+        let state = Maintenance::default();
+        let app = Router::new()
+            .route("/todos", get(|| async { "todos" }))
+            .route("/healthz", get(|| async { "alive" }))
+            .route("/admin/maintenance", get(|| async { "on" }))
+            .layer(middleware::from_fn(maintenance))
+            .layer(Extension(state.clone()));
+        let get = |uri: &str| Request::get(uri).body(Body::empty()).unwrap();
+
+        let res = app.clone().oneshot(get("/todos")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        state.set(Some(Window {
+            message: Some("Restoring last night's backup".to_string()),
+            retry_after_secs: 300,
+        }));
+        let res = app.clone().oneshot(get("/todos")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(res.headers()[header::RETRY_AFTER], "300");
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let problem: crate::handlers::error::Problem = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(problem.detail, "Restoring last night's backup");
+
+        for uri in ["/healthz", "/admin/maintenance"] {
+            let res = app.clone().oneshot(get(uri)).await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK, "{}", uri);
+        }
+    }
+}
@@ -0,0 +1,286 @@
+//! Model Context Protocol server over stdio, started with `--mcp`.
+//!
+//! Speaks newline-delimited JSON-RPC 2.0 and exposes `list_todos`,
+//! `create_todo` and `complete_todo` tools backed by the same repository as
+//! the HTTP API. Nothing can be deleted through it, so an assistant can't
+//! lose data by mistake. Todos are addressed by the same ids as in paths,
+//! so with `ID_FORMAT=uuid` by their uuid.
+
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use validator::Validate;
+
+use crate::repositories::{
+    id::{IdFormat, Key},
+    todo::{CreateTodo, TodoFilter, TodoRepository, UpdateTodo},
+};
+
+const PROTOCOL_VERSION: &str = "2024-11-05";
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+pub async fn serve_stdio<T: TodoRepository>(repository: T, format: IdFormat) -> anyhow::Result<()> {
+    let server = Server::new(repository, format);
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut stdout = tokio::io::stdout();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Value>(&line) {
+            Ok(message) => server.handle(message).await,
+            Err(e) => Some(error(Value::Null, PARSE_ERROR, e.to_string())),
+        };
+        if let Some(response) = response {
+            stdout.write_all(response.to_string().as_bytes()).await?;
+            stdout.write_all(b"\n").await?;
+            stdout.flush().await?;
+        }
+    }
+    Ok(())
+}
+
+pub struct Server<T> {
+    repository: T,
+    format: IdFormat,
+}
+
+impl<T: TodoRepository> Server<T> {
+    pub fn new(repository: T, format: IdFormat) -> Self {
+        Self { repository, format }
+    }
+
+    /// Answers one JSON-RPC message; notifications get no response.
+    pub async fn handle(&self, message: Value) -> Option<Value> {
+        let id = message.get("id").cloned()?;
+        let Some(method) = message.get("method").and_then(Value::as_str) else {
+            return Some(error(id, INVALID_REQUEST, "missing method".to_string()));
+        };
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+
+        let response = match method {
+            "initialize" => result(
+                id,
+                json!({
+                    "protocolVersion": PROTOCOL_VERSION,
+                    "capabilities": { "tools": {} },
+                    "serverInfo": {
+                        "name": env!("CARGO_PKG_NAME"),
+                        "version": env!("CARGO_PKG_VERSION"),
+                    },
+                }),
+            ),
+            "ping" => result(id, json!({})),
+            "tools/list" => result(id, json!({ "tools": tools() })),
+            "tools/call" => match self.call(&params).await {
+                Ok(content) => result(id, content),
+                Err(ToolError::InvalidParams(message)) => error(id, INVALID_PARAMS, message),
+                Err(ToolError::Failed(message)) => result(
+                    id,
+                    json!({
+                        "content": [{ "type": "text", "text": message }],
+                        "isError": true,
+                    }),
+                ),
+            },
+            _ => error(id, METHOD_NOT_FOUND, format!("unknown method: {}", method)),
+        };
+        Some(response)
+    }
+
+    async fn call(&self, params: &Value) -> Result<Value, ToolError> {
+        let name = params
+            .get("name")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        let arguments = params.get("arguments").cloned().unwrap_or(json!({}));
+
+        let output = match name {
+            "list_todos" => {
+                let filter = TodoFilter {
+                    completed: arguments.get("completed").and_then(Value::as_bool),
+                    ..Default::default()
+                };
+                let todos = self.repository.all(&filter).await?;
+                json!({ "todos": todos })
+            }
+            "create_todo" => {
+                let text = arguments
+                    .get("text")
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| ToolError::InvalidParams("text is required".to_string()))?;
+                let payload = CreateTodo::new(text.to_string());
+                payload
+                    .validate()
+                    .map_err(|e| ToolError::Failed(e.to_string()))?;
+                let todo = self
+                    .repository
+                    .create(payload.with_format(self.format))
+                    .await?;
+                serde_json::to_value(todo).map_err(anyhow::Error::from)?
+            }
+            "complete_todo" => {
+                let raw = match arguments.get("id") {
+                    Some(Value::String(id)) => id.clone(),
+                    Some(Value::Number(id)) => id.to_string(),
+                    _ => return Err(ToolError::InvalidParams("id is required".to_string())),
+                };
+                let key = Key::parse(&raw, self.format)
+                    .ok_or_else(|| ToolError::Failed(format!("There is no todo {}.", raw)))?;
+                let id = self.repository.resolve(&key).await?;
+                let todo = self
+                    .repository
+                    .update(id, UpdateTodo::new(None, Some(true)))
+                    .await?;
+                serde_json::to_value(todo).map_err(anyhow::Error::from)?
+            }
+            _ => return Err(ToolError::InvalidParams(format!("unknown tool: {}", name))),
+        };
+
+        Ok(json!({
+            "content": [{ "type": "text", "text": output.to_string() }],
+            "structuredContent": output,
+        }))
+    }
+}
+
+enum ToolError {
+    InvalidParams(String),
+    Failed(String),
+}
+
+impl From<anyhow::Error> for ToolError {
+    fn from(e: anyhow::Error) -> Self {
+        tracing::debug!("mcp tool call failed: {:#}", e);
+        ToolError::Failed(e.to_string())
+    }
+}
+
+fn tools() -> Value {
+    json!([
+        {
+            "name": "list_todos",
+            "description": "List todos, optionally only open or only completed ones.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "completed": { "type": "boolean" },
+                },
+            },
+        },
+        {
+            "name": "create_todo",
+            "description": "Create a todo with the given text (1 to 100 characters).",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "text": { "type": "string" },
+                },
+                "required": ["text"],
+            },
+        },
+        {
+            "name": "complete_todo",
+            "description": "Mark the todo with the given id, as list_todos shows it, as completed.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "id": { "type": "string" },
+                },
+                "required": ["id"],
+            },
+        },
+    ])
+}
+
+fn result(id: Value, result: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+fn error(id: Value, code: i64, message: String) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::repositories::todo::TodoRepositoryForMemory;
+
+    fn call(id: i64, name: &str, arguments: Value) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": "tools/call",
+            "params": { "name": name, "arguments": arguments },
+        })
+    }
+
+    #[tokio::test]
+    async fn manages_todos_through_tools() {
+        let server = Server::new(TodoRepositoryForMemory::new(), IdFormat::Serial);
+
+        let initialized = json!({ "jsonrpc": "2.0", "method": "notifications/initialized" });
+        assert!(server.handle(initialized).await.is_none());
+
+        let list = json!({ "jsonrpc": "2.0", "id": 1, "method": "tools/list" });
+        let res = server.handle(list).await.unwrap();
+        assert_eq!(res["result"]["tools"].as_array().unwrap().len(), 3);
+
+        let res = server
+            .handle(call(2, "create_todo", json!({ "text": "from mcp" })))
+            .await
+            .unwrap();
+        assert_eq!(res["result"]["structuredContent"]["text"], "from mcp");
+
+        let res = server
+            .handle(call(3, "complete_todo", json!({ "id": "1" })))
+            .await
+            .unwrap();
+        assert_eq!(res["result"]["structuredContent"]["completed"], true);
+
+        let res = server
+            .handle(call(4, "list_todos", json!({ "completed": false })))
+            .await
+            .unwrap();
+        assert_eq!(res["result"]["structuredContent"]["todos"], json!([]));
+
+        let res = server
+            .handle(call(5, "complete_todo", json!({ "id": "9" })))
+            .await
+            .unwrap();
+        assert_eq!(res["result"]["isError"], true);
+
+        let res = server
+            .handle(call(6, "delete_todo", json!({ "id": 1 })))
+            .await
+            .unwrap();
+        assert_eq!(res["error"]["code"], INVALID_PARAMS);
+    }
+
+    #[tokio::test]
+    async fn addresses_todos_by_uuid() {
+        let server = Server::new(TodoRepositoryForMemory::new(), IdFormat::Uuid);
+
+        let res = server
+            .handle(call(1, "create_todo", json!({ "text": "from mcp" })))
+            .await
+            .unwrap();
+        let uuid = res["result"]["structuredContent"]["uuid"].clone();
+        assert!(uuid.is_string(), "{}", res);
+
+        let res = server
+            .handle(call(2, "complete_todo", json!({ "id": 1 })))
+            .await
+            .unwrap();
+        assert_eq!(res["result"]["isError"], true);
+
+        let res = server
+            .handle(call(3, "complete_todo", json!({ "id": uuid })))
+            .await
+            .unwrap();
+        assert_eq!(res["result"]["structuredContent"]["completed"], true);
+    }
+}
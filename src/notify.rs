@@ -0,0 +1,621 @@
+use std::{collections::HashSet, env, time::Duration};
+
+use hyper::{header, Body, Method, Request, StatusCode, Uri};
+use serde_json::{json, Value};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{
+    client::{self, HttpsConnector},
+    events::{EventBus, TodoEvent},
+    handlers::slack,
+    repositories::{
+        date::Date,
+        todo::{Todo, TodoFilter, TodoRepository},
+    },
+};
+
+/// Attempts at posting one message before it is dropped.
+const MAX_ATTEMPTS: u32 = 5;
+
+/// The wait before the first retry, doubled for every further one.
+const FIRST_RETRY_IN: Duration = Duration::from_secs(1);
+
+/// A post that takes longer than this counts as failed.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often the todos coming due are looked for.
+pub const DUE_CHECK_EVERY: Duration = Duration::from_secs(60 * 60);
+
+/// How many days ahead of its due day a todo is due soon.
+const DUE_SOON_DAYS: i64 = 1;
+
+/// The chat a webhook posts to, which decides the message's shape.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    Slack,
+    Discord,
+}
+
+impl Platform {
+    /// Discord for its webhook hosts, otherwise Slack, whose `text`
+    /// messages Mattermost and Rocket.Chat take too.
+    fn of(url: &Uri) -> Self {
+        match url.host() {
+            Some("discord.com" | "discordapp.com") => Platform::Discord,
+            _ => Platform::Slack,
+        }
+    }
+
+    fn bold(self, text: &str) -> String {
+        match self {
+            Platform::Slack => format!("*{}*", slack::escape(text)),
+            Platform::Discord => {
+                let mut escaped = String::with_capacity(text.len());
+                for c in text.chars() {
+                    if "\\*_~`|>".contains(c) {
+                        escaped.push('\\');
+                    }
+                    escaped.push(c);
+                }
+                format!("**{}**", escaped)
+            }
+        }
+    }
+
+    fn body(self, text: &str) -> Value {
+        match self {
+            Platform::Slack => json!({ "text": text }),
+            // no pinging @everyone from a todo's text
+            Platform::Discord => json!({ "content": text, "allowed_mentions": { "parse": [] } }),
+        }
+    }
+}
+
+/// The changes a channel can be told about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Notice {
+    Created,
+    Completed,
+    Deleted,
+    DueSoon,
+    Overdue,
+}
+
+impl Notice {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "created" => Some(Notice::Created),
+            "completed" => Some(Notice::Completed),
+            "deleted" => Some(Notice::Deleted),
+            "due_soon" => Some(Notice::DueSoon),
+            "overdue" => Some(Notice::Overdue),
+            _ => None,
+        }
+    }
+
+    /// The notices `var` lists, comma separated from `created`,
+    /// `completed`, `deleted`, `due_soon` and `overdue`; the first two when
+    /// it is unset.
+    pub fn list_from_env(var: &str) -> HashSet<Self> {
+        let names = env::var(var).unwrap_or_else(|_| "created,completed".to_string());
+        names
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(|name| Self::parse(name).unwrap_or_else(|| panic!("invalid [{}]: {}", var, name)))
+            .collect()
+    }
+}
+
+/// A change worth a notice, with the todo as it is now.
+#[derive(Debug)]
+pub enum Change {
+    Created(Todo),
+    Completed(Todo),
+    Deleted(i64),
+    /// The todo is due within [`DUE_SOON_DAYS`] days.
+    DueSoon(Todo),
+    /// The todo's due day passed while it was open.
+    Overdue(Todo),
+}
+
+impl Change {
+    pub fn notice(&self) -> Notice {
+        match self {
+            Change::Created(_) => Notice::Created,
+            Change::Completed(_) => Notice::Completed,
+            Change::Deleted(_) => Notice::Deleted,
+            Change::DueSoon(_) => Notice::DueSoon,
+            Change::Overdue(_) => Notice::Overdue,
+        }
+    }
+}
+
+/// Tells the open todos due in a window of days around today, each once
+/// per due day. Todos come due as days pass rather than by any change, so
+/// this is looked at on a schedule rather than on the events of the bus.
+#[derive(Debug, Clone)]
+pub struct DueTodos {
+    /// Days from today the due day falls after, when bounded, and before.
+    after: Option<i64>,
+    before: i64,
+    /// The todos told of, with the day they were due then.
+    told: HashSet<(i64, Option<Date>)>,
+}
+
+impl DueTodos {
+    /// The todos due from today up to `days` days on.
+    pub fn within(days: i64) -> Self {
+        Self {
+            after: Some(-1),
+            before: days + 1,
+            told: HashSet::new(),
+        }
+    }
+
+    /// The todos due before today.
+    pub fn overdue() -> Self {
+        Self {
+            after: None,
+            before: 0,
+            told: HashSet::new(),
+        }
+    }
+
+    /// How many days from today the window reaches.
+    pub fn days(&self) -> i64 {
+        self.before - 1
+    }
+
+    /// The todos due then that haven't been told of for that day. Those no
+    /// longer due then are forgotten, to be told of again should they come
+    /// due once more.
+    pub async fn untold<T: TodoRepository>(&mut self, todos: &T) -> anyhow::Result<Vec<Todo>> {
+        let today = Date::today().days();
+        let filter = TodoFilter {
+            completed: Some(false),
+            due_after: self.after.map(|after| Date::from_days(today + after)),
+            due_before: Some(Date::from_days(today + self.before)),
+            ..TodoFilter::default()
+        };
+        let due = todos.all(&filter).await?;
+        let keys: HashSet<_> = due.iter().map(told).collect();
+        self.told.retain(|told| keys.contains(told));
+        Ok(due
+            .into_iter()
+            .filter(|todo| !self.told.contains(&told(todo)))
+            .collect())
+    }
+
+    /// Marks `todos` as told of.
+    pub fn tell(&mut self, todos: &[Todo]) {
+        self.told.extend(todos.iter().map(told));
+    }
+}
+
+/// What telling of `todo` is remembered by.
+fn told(todo: &Todo) -> (i64, Option<Date>) {
+    (todo.id(), todo.due().cloned())
+}
+
+/// Tells the changes worth a notice from the events of the bus, and the
+/// todos coming due or overdue when asked. It holds the todos known to be
+/// completed, so only the update completing a todo is told, and those
+/// overdue already, so only the todos becoming overdue are.
+#[derive(Debug)]
+pub struct Changes {
+    completed: HashSet<i64>,
+    due_soon: DueTodos,
+    overdue: DueTodos,
+}
+
+impl Changes {
+    /// Starts with the todos completed by now.
+    pub async fn load<T: TodoRepository>(todos: &T) -> Self {
+        let filter = TodoFilter {
+            completed: Some(true),
+            ..TodoFilter::default()
+        };
+        let completed = match todos.all(&filter).await {
+            Ok(todos) => todos.iter().map(|todo| todo.id()).collect(),
+            Err(e) => {
+                tracing::warn!("failed to read completed todos: {:#}", e);
+                HashSet::new()
+            }
+        };
+        let mut overdue = DueTodos::overdue();
+        match overdue.untold(todos).await {
+            Ok(todos) => overdue.tell(&todos),
+            Err(e) => tracing::warn!("failed to read overdue todos: {:#}", e),
+        }
+        Self {
+            completed,
+            due_soon: DueTodos::within(DUE_SOON_DAYS),
+            overdue,
+        }
+    }
+
+    /// The todos newly due soon and newly overdue, as far as `notices`
+    /// asks for them.
+    pub async fn due<T: TodoRepository>(
+        &mut self,
+        todos: &T,
+        notices: &HashSet<Notice>,
+    ) -> anyhow::Result<Vec<Change>> {
+        let mut changes = vec![];
+        if notices.contains(&Notice::DueSoon) {
+            let due = self.due_soon.untold(todos).await?;
+            self.due_soon.tell(&due);
+            changes.extend(due.into_iter().map(Change::DueSoon));
+        }
+        if notices.contains(&Notice::Overdue) {
+            let overdue = self.overdue.untold(todos).await?;
+            self.overdue.tell(&overdue);
+            changes.extend(overdue.into_iter().map(Change::Overdue));
+        }
+        Ok(changes)
+    }
+
+    pub async fn of<T: TodoRepository>(
+        &mut self,
+        todos: &T,
+        event: &TodoEvent,
+    ) -> anyhow::Result<Option<Change>> {
+        Ok(match *event {
+            // todos start open, however this one looks by now
+            TodoEvent::Created { id } => Some(Change::Created(todos.find(id).await?)),
+            TodoEvent::Updated { id } => {
+                let todo = todos.find(id).await?;
+                if !todo.completed() {
+                    self.completed.remove(&id);
+                    None
+                } else if self.completed.insert(id) {
+                    Some(Change::Completed(todo))
+                } else {
+                    None
+                }
+            }
+            TodoEvent::Deleted { id } => {
+                self.completed.remove(&id);
+                Some(Change::Deleted(id))
+            }
+            TodoEvent::Purged { .. } | TodoEvent::Archived { .. } => None,
+        })
+    }
+}
+
+/// Posts todo changes to a Slack or Discord channel through its incoming
+/// webhook. Messages are retried a few times, then dropped; they aren't
+/// queued anywhere that outlives the process.
+#[derive(Clone)]
+pub struct ChatNotifier {
+    url: Uri,
+    platform: Platform,
+    notices: HashSet<Notice>,
+    client: hyper::Client<HttpsConnector>,
+}
+
+impl ChatNotifier {
+    pub fn new(url: Uri, notices: HashSet<Notice>) -> Self {
+        Self {
+            platform: Platform::of(&url),
+            url,
+            notices,
+            client: client::https(),
+        }
+    }
+
+    /// Posts to `CHAT_WEBHOOK_URL` about the changes `CHAT_EVENTS` lists,
+    /// comma separated from `created`, `completed`, `deleted`, `due_soon`
+    /// and `overdue`; the first two by default. `None` when the URL is
+    /// unset.
+    pub fn from_env() -> Option<Self> {
+        let url = env::var("CHAT_WEBHOOK_URL").ok()?;
+        let url = url
+            .parse()
+            .unwrap_or_else(|e| panic!("invalid [CHAT_WEBHOOK_URL]: {}, {}", url, e));
+        Some(Self::new(url, Notice::list_from_env("CHAT_EVENTS")))
+    }
+
+    /// Keeps posting the changes published on `bus`, and the todos coming
+    /// due every [`DUE_CHECK_EVERY`], on a background task. Every instance
+    /// sharing a Postgres database sees every change, so only one of them
+    /// should be given a webhook.
+    pub fn spawn<T: TodoRepository>(self, todos: T, bus: &EventBus) {
+        tracing::info!("posting {:?} todos to {}", self.notices, self.url);
+        let mut events = bus.subscribe();
+        tokio::spawn(async move {
+            let mut changes = Changes::load(&todos).await;
+            let mut checks = tokio::time::interval(DUE_CHECK_EVERY);
+            loop {
+                let event = tokio::select! {
+                    event = events.recv() => event,
+                    _ = checks.tick() => {
+                        match changes.due(&todos, &self.notices).await {
+                            Ok(due) => {
+                                for change in due {
+                                    if let Some(text) = self.message(&change) {
+                                        self.post(&text).await;
+                                    }
+                                }
+                            }
+                            Err(e) => tracing::warn!("failed to read the todos coming due: {:#}", e),
+                        }
+                        continue;
+                    }
+                };
+                let event = match event {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(missed)) => {
+                        tracing::warn!("missed {} todo events to post", missed);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => return,
+                };
+                match changes.of(&todos, &event).await {
+                    Ok(Some(change)) => {
+                        if let Some(text) = self.message(&change) {
+                            self.post(&text).await;
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(e) => tracing::warn!("failed to read the todo of {:?}: {:#}", event, e),
+                }
+            }
+        });
+    }
+
+    /// What to post about `change`, if the channel wants to hear of it.
+    fn message(&self, change: &Change) -> Option<String> {
+        if !self.notices.contains(&change.notice()) {
+            return None;
+        }
+        Some(match change {
+            Change::Created(todo) => format!("New todo: {}", self.platform.bold(todo.text())),
+            Change::Completed(todo) => format!("Done: {}", self.platform.bold(todo.text())),
+            Change::Deleted(id) => format!("Deleted todo #{}", id),
+            Change::DueSoon(todo) => format!(
+                "Due soon: {} ({})",
+                self.platform.bold(todo.text()),
+                todo.due().map(Date::as_str).unwrap_or_default()
+            ),
+            Change::Overdue(todo) => format!(
+                "Overdue: {} ({})",
+                self.platform.bold(todo.text()),
+                todo.due().map(Date::as_str).unwrap_or_default()
+            ),
+        })
+    }
+
+    /// Posts `text`, retrying with a doubling wait until it is taken or
+    /// [`MAX_ATTEMPTS`] failed.
+    async fn post(&self, text: &str) {
+        let mut retry_in = FIRST_RETRY_IN;
+        for attempt in 1..=MAX_ATTEMPTS {
+            match self.deliver(text).await {
+                Ok(()) => return,
+                Err(e) if attempt == MAX_ATTEMPTS => {
+                    tracing::error!("dropped a chat message after {} attempts: {:#}", attempt, e);
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        "failed to post to the chat, retrying in {:?}: {:#}",
+                        retry_in,
+                        e
+                    );
+                    tokio::time::sleep(retry_in).await;
+                    retry_in *= 2;
+                }
+            }
+        }
+    }
+
+    async fn deliver(&self, text: &str) -> anyhow::Result<()> {
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri(self.url.clone())
+            .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .body(Body::from(self.platform.body(text).to_string()))?;
+        let res = client::request(&self.client, req, DELIVERY_TIMEOUT).await?;
+        match res.status() {
+            status if status.is_success() => Ok(()),
+            StatusCode::TOO_MANY_REQUESTS => anyhow::bail!("webhook is rate limited"),
+            status => anyhow::bail!("webhook answered {}", status),
+        }
+    }
+}
+
+/// Posts todo changes on a background task when `CHAT_WEBHOOK_URL` is
+/// set; see [`ChatNotifier::from_env`].
+pub fn spawn_from_env<T: TodoRepository>(todos: T, bus: &EventBus) {
+    if let Some(notifier) = ChatNotifier::from_env() {
+        notifier.spawn(todos, bus);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use axum::{extract::Extension, http::StatusCode, routing::post, Json, Router};
+
+    use super::*;
+    use crate::{
+        events::Publishing,
+        repositories::todo::{CreateTodo, TodoRepositoryForMemory, UpdateTodo},
+    };
+
+    type Received = Arc<Mutex<Vec<Value>>>;
+
+    /// A webhook answering 500 to the first `fail` posts.
+    async fn webhook(fail: usize) -> (Uri, Received) {
+        let received = Received::default();
+        let app = Router::new()
+            .route(
+                "/hook",
+                post(
+                    move |Json(body): Json<Value>, Extension(received): Extension<Received>| async move {
+                        let mut received = received.lock().unwrap();
+                        received.push(body);
+                        if received.len() <= fail {
+                            StatusCode::INTERNAL_SERVER_ERROR
+                        } else {
+                            StatusCode::OK
+                        }
+                    },
+                ),
+            )
+            .layer(Extension(received.clone()));
+        let addr = crate::testing::serve(app);
+        (format!("http://{}/hook", addr).parse().unwrap(), received)
+    }
+
+    #[tokio::test]
+    async fn tells_the_channel_what_it_wants_to_hear() {
+        let (url, received) = webhook(1).await;
+        let bus = EventBus::new(16);
+        let todos = Publishing::new(TodoRepositoryForMemory::new(), bus.clone());
+        let notices = HashSet::from([Notice::Created, Notice::Completed]);
+        ChatNotifier::new(url, notices).spawn(todos.clone(), &bus);
+        tokio::task::yield_now().await;
+
+        let todo = todos
+            .create(CreateTodo::new("Buy <milk>".to_string()))
+            .await
+            .unwrap();
+        for _ in 0..2 {
+            todos
+                .update(todo.id(), UpdateTodo::new(None, Some(true)))
+                .await
+                .unwrap();
+        }
+        // the first post fails and is retried after a second
+        tokio::time::sleep(FIRST_RETRY_IN + Duration::from_millis(500)).await;
+        todos.delete(todo.id()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let received = received.lock().unwrap();
+        let texts: Vec<_> = received.iter().map(|body| body["text"].clone()).collect();
+        assert_eq!(
+            texts,
+            vec![
+                json!("New todo: *Buy &lt;milk&gt;*"),
+                json!("New todo: *Buy &lt;milk&gt;*"),
+                json!("Done: *Buy &lt;milk&gt;*"),
+            ]
+        );
+    }
+
+    /// A todo due `days` days from today.
+    fn due_in(text: &str, days: i64) -> CreateTodo {
+        let due = Date::from_days(Date::today().days() + days);
+        CreateTodo::new(text.to_string()).with_due(Some(due))
+    }
+
+    #[tokio::test]
+    async fn tells_of_each_todo_coming_due_once() {
+        let todos = TodoRepositoryForMemory::new();
+        for todo in [
+            CreateTodo::new("Undated".to_string()),
+            due_in("Past", -1),
+            due_in("Later", 3),
+        ] {
+            todos.create(todo).await.unwrap();
+        }
+        let mut due = DueTodos::within(1);
+        assert!(due.untold(&todos).await.unwrap().is_empty());
+
+        let today = todos.create(due_in("Today", 0)).await.unwrap();
+        let tomorrow = todos.create(due_in("Tomorrow", 1)).await.unwrap();
+        let mut untold = due.untold(&todos).await.unwrap();
+        untold.sort_by_key(Todo::id);
+        assert_eq!(untold, vec![today.clone(), tomorrow.clone()]);
+        due.tell(&untold);
+        assert!(due.untold(&todos).await.unwrap().is_empty());
+
+        // A todo moved to another day in the window is due anew, and one
+        // completed isn't.
+        let moved = todos
+            .update(
+                today.id(),
+                UpdateTodo::new(None, None).with_due(tomorrow.due().cloned()),
+            )
+            .await
+            .unwrap();
+        todos
+            .update(tomorrow.id(), UpdateTodo::new(None, Some(true)))
+            .await
+            .unwrap();
+        assert_eq!(due.untold(&todos).await.unwrap(), vec![moved]);
+        let later = DueTodos::within(3).untold(&todos).await.unwrap();
+        assert_eq!(later.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn tells_todos_due_soon_when_asked_for() {
+        let todos = TodoRepositoryForMemory::new();
+        let todo = todos.create(due_in("Rent", 1)).await.unwrap();
+        let mut changes = Changes::load(&todos).await;
+        let wanted = HashSet::from([Notice::DueSoon]);
+        assert!(changes
+            .due(&todos, &HashSet::new())
+            .await
+            .unwrap()
+            .is_empty());
+        let due = changes.due(&todos, &wanted).await.unwrap();
+        assert!(matches!(&due[..], [Change::DueSoon(due)] if *due == todo));
+        assert!(changes.due(&todos, &wanted).await.unwrap().is_empty());
+
+        let (url, _) = webhook(0).await;
+        let notifier = ChatNotifier::new(url, wanted);
+        let text = notifier.message(&due[0]).unwrap();
+        assert_eq!(text, format!("Due soon: *Rent* ({})", todo.due().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn tells_todos_becoming_overdue_when_asked_for() {
+        let todos = TodoRepositoryForMemory::new();
+        todos.create(due_in("Long overdue", -3)).await.unwrap();
+        todos.create(due_in("Today", 0)).await.unwrap();
+        let mut changes = Changes::load(&todos).await;
+        let wanted = HashSet::from([Notice::Overdue]);
+        assert!(changes.due(&todos, &wanted).await.unwrap().is_empty());
+
+        // moved into the past, as a day passing would
+        let todo = todos.create(due_in("Rent", 1)).await.unwrap();
+        let yesterday = Date::from_days(Date::today().days() - 1);
+        let todo = todos
+            .update(
+                todo.id(),
+                UpdateTodo::new(None, None).with_due(Some(yesterday)),
+            )
+            .await
+            .unwrap();
+        assert!(changes
+            .due(&todos, &HashSet::new())
+            .await
+            .unwrap()
+            .is_empty());
+        let overdue = changes.due(&todos, &wanted).await.unwrap();
+        assert!(matches!(&overdue[..], [Change::Overdue(overdue)] if *overdue == todo));
+        assert!(changes.due(&todos, &wanted).await.unwrap().is_empty());
+
+        let (url, _) = webhook(0).await;
+        let notifier = ChatNotifier::new(url, wanted);
+        let text = notifier.message(&overdue[0]).unwrap();
+        assert_eq!(text, format!("Overdue: *Rent* ({})", todo.due().unwrap()));
+    }
+
+    #[test]
+    fn shapes_messages_for_the_platform() {
+        let discord: Uri = "https://discord.com/api/webhooks/1/abc".parse().unwrap();
+        assert_eq!(Platform::of(&discord), Platform::Discord);
+        let slack: Uri = "https://hooks.slack.com/services/T0/B0/x".parse().unwrap();
+        assert_eq!(Platform::of(&slack), Platform::Slack);
+
+        assert_eq!(Platform::Discord.bold("a*b_c"), r"**a\*b\_c**");
+        let body = Platform::Discord.body("@everyone hi");
+        assert_eq!(body["content"], "@everyone hi");
+        assert_eq!(body["allowed_mentions"]["parse"], json!([]));
+        assert_eq!(Platform::Slack.body("hi"), json!({ "text": "hi" }));
+    }
+}
@@ -0,0 +1,311 @@
+use std::{env, sync::Arc, time::Duration};
+
+use anyhow::Context;
+use hyper::{header, Body, Method, Request, Uri};
+use serde_json::{json, Value};
+
+use crate::{
+    client::{self, HttpsConnector},
+    repositories::{
+        env_or,
+        outbox::{OutboxEntry, OutboxRepository},
+    },
+};
+
+/// Events read from the outbox per round.
+const BATCH: i64 = 100;
+
+/// The longest wait before retrying a delivery.
+const MAX_RETRY_IN: Duration = Duration::from_secs(60 * 60);
+
+/// A delivery that takes longer than this counts as failed.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Delivers the events queued in the outbox to a webhook, at least once
+/// each: a receiver may see an event again, recognizable by its `id`.
+#[derive(Clone)]
+pub struct Dispatcher {
+    url: Uri,
+    poll_every: Duration,
+    template: Option<Arc<Template>>,
+    client: hyper::Client<HttpsConnector>,
+}
+
+impl Dispatcher {
+    pub fn new(url: Uri, poll_every: Duration) -> Self {
+        Self {
+            url,
+            poll_every,
+            template: None,
+            client: client::https(),
+        }
+    }
+
+    /// Posts `template` rendered instead of the default body.
+    pub fn with_template(mut self, template: Template) -> Self {
+        self.template = Some(Arc::new(template));
+        self
+    }
+
+    /// Posts to `OUTBOX_WEBHOOK_URL`, looking for due events every
+    /// `OUTBOX_POLL_MS` (1000) milliseconds. `None` when the URL is unset,
+    /// in which case nothing is queued either. The body is the
+    /// [`Template`] at `OUTBOX_WEBHOOK_TEMPLATE_PATH` when that is set.
+    pub fn from_env() -> Option<Self> {
+        let url = env::var("OUTBOX_WEBHOOK_URL").ok()?;
+        let url = url
+            .parse()
+            .unwrap_or_else(|e| panic!("invalid [OUTBOX_WEBHOOK_URL]: {}, {}", url, e));
+        let dispatcher = Self::new(url, Duration::from_millis(env_or("OUTBOX_POLL_MS", 1000)));
+        let Ok(path) = env::var("OUTBOX_WEBHOOK_TEMPLATE_PATH") else {
+            return Some(dispatcher);
+        };
+        let template = std::fs::read_to_string(&path)
+            .context("fail read the template")
+            .and_then(|source| Template::parse(&source))
+            .unwrap_or_else(|e| {
+                panic!("invalid [OUTBOX_WEBHOOK_TEMPLATE_PATH]: {}, {:#}", path, e)
+            });
+        Some(dispatcher.with_template(template))
+    }
+
+    /// Keeps delivering on a background task until the process exits.
+    pub fn spawn<O: OutboxRepository>(self, outbox: O) {
+        tracing::info!("delivering todo events to {}", self.url);
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(self.poll_every);
+            loop {
+                ticks.tick().await;
+                if let Err(e) = self.dispatch(&outbox).await {
+                    tracing::error!("failed to read the outbox: {:#}", e);
+                }
+            }
+        });
+    }
+
+    /// Attempts every due event once, in the order they were queued, and
+    /// returns how many were delivered.
+    pub async fn dispatch<O: OutboxRepository>(&self, outbox: &O) -> anyhow::Result<u64> {
+        let mut delivered = 0;
+        loop {
+            let entries = outbox.due(BATCH).await?;
+            let last_batch = (entries.len() as i64) < BATCH;
+            for entry in entries {
+                match self.deliver(&entry).await {
+                    Ok(()) => {
+                        outbox.delivered(entry.id).await?;
+                        delivered += 1;
+                    }
+                    Err(e) => {
+                        let retry_in = retry_in(entry.attempts);
+                        tracing::warn!(
+                            "failed to deliver event {}, retrying in {:?}: {:#}",
+                            entry.id,
+                            retry_in,
+                            e
+                        );
+                        outbox
+                            .failed(entry.id, &format!("{:#}", e), retry_in)
+                            .await?;
+                    }
+                }
+            }
+            if last_batch {
+                return Ok(delivered);
+            }
+        }
+    }
+
+    async fn deliver(&self, entry: &OutboxEntry) -> anyhow::Result<()> {
+        let body = json!({ "id": entry.id, "event": entry.event });
+        let body = match &self.template {
+            Some(template) => template.render(&body),
+            None => body.to_string(),
+        };
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri(self.url.clone())
+            .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .body(Body::from(body))?;
+        let res = client::request(&self.client, req, DELIVERY_TIMEOUT).await?;
+        if !res.status().is_success() {
+            anyhow::bail!("webhook answered {}", res.status());
+        }
+        Ok(())
+    }
+}
+
+/// A webhook body in the shape a receiver expects, such as a Zapier or
+/// n8n trigger. Text is kept as written, and each `{{ path }}` is replaced
+/// by the value at that dotted path of the default body, so `{{ id }}`
+/// and `{{ event.type }}`. Strings are escaped to sit inside JSON quotes,
+/// other values are written as JSON, and a missing value is left empty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template(Vec<Part>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Value(Vec<String>),
+}
+
+impl Template {
+    pub fn parse(source: &str) -> anyhow::Result<Self> {
+        let mut parts = Vec::new();
+        let mut rest = source;
+        while let Some(start) = rest.find("{{") {
+            parts.push(Part::Text(rest[..start].to_string()));
+            let end = rest[start..]
+                .find("}}")
+                .with_context(|| format!("unclosed {{{{ at {}", &rest[start..]))?;
+            let path = rest[start + 2..start + end].trim();
+            if path.is_empty() || path.split('.').any(str::is_empty) {
+                anyhow::bail!("{{{{ {} }}}} names no value", path);
+            }
+            parts.push(Part::Value(path.split('.').map(str::to_string).collect()));
+            rest = &rest[start + end + 2..];
+        }
+        parts.push(Part::Text(rest.to_string()));
+        Ok(Self(parts))
+    }
+
+    pub fn render(&self, body: &Value) -> String {
+        let mut rendered = String::new();
+        for part in &self.0 {
+            match part {
+                Part::Text(text) => rendered.push_str(text),
+                Part::Value(path) => {
+                    match path.iter().try_fold(body, |value, key| value.get(key)) {
+                        None | Some(Value::Null) => {}
+                        Some(Value::String(text)) => {
+                            let quoted = Value::from(text.as_str()).to_string();
+                            rendered.push_str(&quoted[1..quoted.len() - 1]);
+                        }
+                        Some(value) => rendered.push_str(&value.to_string()),
+                    }
+                }
+            }
+        }
+        rendered
+    }
+}
+
+/// One second after the first failure, doubling up to [`MAX_RETRY_IN`].
+fn retry_in(attempts: i32) -> Duration {
+    Duration::from_secs(1u64 << attempts.clamp(0, 12)).min(MAX_RETRY_IN)
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use axum::{extract::Extension, http::StatusCode, routing::post, Json, Router};
+    use serde_json::Value;
+
+    use super::*;
+    use crate::repositories::{
+        outbox::OutboxRepositoryForSqlite,
+        todo::{CreateTodo, TodoRepository, TodoRepositoryForSqlite},
+    };
+
+    type Received = Arc<Mutex<Vec<Value>>>;
+
+    /// A webhook answering 500 to the first `fail` posts.
+    async fn webhook(fail: usize) -> (Uri, Received) {
+        let received = Received::default();
+        let app = Router::new()
+            .route(
+                "/hook",
+                post(
+                    move |Json(body): Json<Value>, Extension(received): Extension<Received>| async move {
+                        let mut received = received.lock().unwrap();
+                        received.push(body);
+                        if received.len() <= fail {
+                            StatusCode::INTERNAL_SERVER_ERROR
+                        } else {
+                            StatusCode::NO_CONTENT
+                        }
+                    },
+                ),
+            )
+            .layer(Extension(received.clone()));
+        let addr = crate::testing::serve(app);
+        (format!("http://{}/hook", addr).parse().unwrap(), received)
+    }
+
+    #[tokio::test]
+    async fn delivers_queued_events_until_they_are_accepted() {
+        let pool = crate::repositories::connect_sqlite(
+            "sqlite::memory:",
+            &crate::repositories::PoolSettings::default(),
+            crate::repositories::Migrations::Apply,
+        )
+        .await
+        .expect("failed open sqlite");
+        let todos = TodoRepositoryForSqlite::new(pool.clone()).with_outbox();
+        let outbox = OutboxRepositoryForSqlite::new(pool.clone());
+        let todo = todos
+            .create(CreateTodo::new("tell the webhook".to_string()))
+            .await
+            .unwrap();
+        todos.delete(todo.id()).await.unwrap();
+
+        let (url, received) = webhook(1).await;
+        let dispatcher = Dispatcher::new(url, Duration::from_secs(1));
+        assert_eq!(dispatcher.dispatch(&outbox).await.unwrap(), 1);
+        // the failed one waits for its retry
+        assert_eq!(dispatcher.dispatch(&outbox).await.unwrap(), 0);
+        let (attempts, error): (i32, String) =
+            sqlx::query_as("select attempts, last_error from outbox")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(attempts, 1);
+        assert_eq!(error, "webhook answered 500 Internal Server Error");
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        assert_eq!(
+            received[0]["event"],
+            json!({ "type": "created", "id": todo.id() })
+        );
+        assert_eq!(
+            received[1]["event"],
+            json!({ "type": "deleted", "id": todo.id() })
+        );
+        assert!(received[0]["id"].as_i64() < received[1]["id"].as_i64());
+    }
+
+    #[test]
+    fn renders_templates_with_values_of_the_body() {
+        let template = Template::parse(
+            r#"{"text": "todo {{ event.id }} was {{event.type}}", "seq": {{ id }}, "who": "{{ user }}", "event": {{ event }}}"#,
+        )
+        .unwrap();
+        let rendered = template.render(&json!({
+            "id": 7,
+            "event": { "type": "say \"hi\"", "id": 3 },
+        }));
+        let rendered: Value = serde_json::from_str(&rendered).unwrap();
+        assert_eq!(
+            rendered,
+            json!({
+                "text": "todo 3 was say \"hi\"",
+                "seq": 7,
+                "who": "",
+                "event": { "type": "say \"hi\"", "id": 3 },
+            })
+        );
+
+        for invalid in ["{{ id", "{{ }}", "{{ event..id }}"] {
+            assert!(Template::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn backs_off_exponentially_up_to_an_hour() {
+        assert_eq!(retry_in(0), Duration::from_secs(1));
+        assert_eq!(retry_in(3), Duration::from_secs(8));
+        assert_eq!(retry_in(40), MAX_RETRY_IN);
+    }
+}
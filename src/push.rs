@@ -0,0 +1,772 @@
+//! Web Push of todo changes to the browsers that subscribed: messages are
+//! encrypted for each browser (RFC 8291) and signed with the server's
+//! VAPID key (RFC 8292), so push services take them without an account.
+
+use std::{
+    collections::{HashMap, HashSet},
+    env, fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use futures_util::future::join_all;
+use hyper::{header, Body, Method, Request, StatusCode, Uri};
+use ring::{
+    aead, agreement,
+    error::Unspecified,
+    hkdf,
+    rand::SecureRandom,
+    rand::SystemRandom,
+    signature::{self, EcdsaKeyPair},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::broadcast::error::RecvError;
+use validator::Validate;
+
+use crate::{
+    client::{self, HttpsConnector},
+    events::EventBus,
+    notify::{Change, Changes, Notice, DUE_CHECK_EVERY},
+    repositories::todo::TodoRepository,
+};
+
+/// Subscriptions kept at most, so registering can't fill the memory.
+pub const MAX_SUBSCRIPTIONS: usize = 1000;
+
+/// Failed pushes in a row after which a subscription is dropped.
+const MAX_FAILURES: u32 = 5;
+
+/// A push that takes longer than this counts as failed.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a push service holds a message for a browser that is offline.
+const MESSAGE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How long a VAPID token is good for; push services take up to a day.
+const TOKEN_TTL: Duration = Duration::from_secs(12 * 60 * 60);
+
+/// The record size stated in the encrypted message, which is one record.
+const RECORD_SIZE: u32 = 4096;
+
+/// The push services of Chrome, Firefox, Safari and Edge, which endpoints
+/// must be at, or under, unless `PUSH_SERVICE_HOSTS` lists others: the
+/// server posts to whatever a subscription names.
+const PUSH_SERVICES: [&str; 4] = [
+    "fcm.googleapis.com",
+    "updates.push.services.mozilla.com",
+    "push.apple.com",
+    "notify.windows.com",
+];
+
+fn b64(bytes: impl AsRef<[u8]>) -> String {
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+fn unb64(text: &str) -> Option<Vec<u8>> {
+    base64::decode_config(text.trim_end_matches('='), base64::URL_SAFE_NO_PAD).ok()
+}
+
+/// A browser's `PushSubscription`, as its `toJSON()` has it.
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct SubscriptionJson {
+    pub endpoint: String,
+    keys: SubscriptionKeys,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct SubscriptionKeys {
+    p256dh: String,
+    auth: String,
+}
+
+#[derive(Debug, Clone)]
+struct Subscription {
+    endpoint: Uri,
+    /// The browser's P-256 public key, uncompressed.
+    p256dh: Vec<u8>,
+    auth: Vec<u8>,
+    failures: u32,
+}
+
+impl Subscription {
+    /// Fails unless the endpoint is `https` at one of `services` and the
+    /// keys are sound.
+    fn parse(json: &SubscriptionJson, services: &[String]) -> Result<Self, SubscribeError> {
+        let endpoint: Uri = json.endpoint.parse().map_err(|_| SubscribeError::Invalid)?;
+        let host = match endpoint.host() {
+            Some(host) if endpoint.scheme_str() == Some("https") => host,
+            _ => return Err(SubscribeError::Invalid),
+        };
+        if !services.iter().any(|service| at_service(host, service)) {
+            return Err(SubscribeError::UnknownService);
+        }
+        let p256dh = unb64(&json.keys.p256dh).filter(|key| key.len() == 65 && key[0] == 4);
+        let auth = unb64(&json.keys.auth).filter(|auth| auth.len() == 16);
+        let (Some(p256dh), Some(auth)) = (p256dh, auth) else {
+            return Err(SubscribeError::Invalid);
+        };
+        Ok(Self {
+            endpoint,
+            p256dh,
+            auth,
+            failures: 0,
+        })
+    }
+}
+
+/// Whether `host` is `service` or one of its subdomains.
+fn at_service(host: &str, service: &str) -> bool {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    host == service
+        || host
+            .strip_suffix(service)
+            .is_some_and(|sub| sub.ends_with('.'))
+}
+
+/// Why a subscription was not taken.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SubscribeError {
+    Invalid,
+    /// The endpoint is not at a push service.
+    UnknownService,
+    Full,
+}
+
+struct LenOf(usize);
+
+impl hkdf::KeyType for LenOf {
+    fn len(&self) -> usize {
+        self.0
+    }
+}
+
+fn expand(prk: &hkdf::Prk, info: &[u8], len: usize) -> Result<Vec<u8>, Unspecified> {
+    let mut out = vec![0; len];
+    prk.expand(&[info], LenOf(len))?.fill(&mut out)?;
+    Ok(out)
+}
+
+/// `plaintext` as the `aes128gcm` body for the browser of `ua_public` and
+/// `auth`, from the secret agreed with the server's one-off key
+/// `as_public`.
+fn seal(
+    ecdh_secret: &[u8],
+    auth: &[u8],
+    ua_public: &[u8],
+    as_public: &[u8],
+    salt: &[u8; 16],
+    plaintext: &[u8],
+) -> Result<Vec<u8>, Unspecified> {
+    let mut info = b"WebPush: info\0".to_vec();
+    info.extend_from_slice(ua_public);
+    info.extend_from_slice(as_public);
+    let ikm = expand(
+        &hkdf::Salt::new(hkdf::HKDF_SHA256, auth).extract(ecdh_secret),
+        &info,
+        32,
+    )?;
+    let prk = hkdf::Salt::new(hkdf::HKDF_SHA256, salt).extract(&ikm);
+    let cek = expand(&prk, b"Content-Encoding: aes128gcm\0", 16)?;
+    let nonce = expand(&prk, b"Content-Encoding: nonce\0", 12)?;
+
+    let key = aead::LessSafeKey::new(aead::UnboundKey::new(&aead::AES_128_GCM, &cek)?);
+    let mut record = plaintext.to_vec();
+    // the delimiter of the last record, with no padding
+    record.push(2);
+    key.seal_in_place_append_tag(
+        aead::Nonce::try_assume_unique_for_key(&nonce)?,
+        aead::Aad::empty(),
+        &mut record,
+    )?;
+
+    let mut body = salt.to_vec();
+    body.extend_from_slice(&RECORD_SIZE.to_be_bytes());
+    body.push(as_public.len() as u8);
+    body.extend_from_slice(as_public);
+    body.extend_from_slice(&record);
+    Ok(body)
+}
+
+/// `plaintext` encrypted for `subscription` with a one-off key.
+fn encrypt(subscription: &Subscription, plaintext: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let rng = SystemRandom::new();
+    let private = agreement::EphemeralPrivateKey::generate(&agreement::ECDH_P256, &rng)
+        .map_err(|_| anyhow::anyhow!("no randomness for a push key"))?;
+    let public = private
+        .compute_public_key()
+        .map_err(|_| anyhow::anyhow!("fail compute the push key"))?;
+    let mut salt = [0u8; 16];
+    rng.fill(&mut salt)
+        .map_err(|_| anyhow::anyhow!("no randomness for a push salt"))?;
+    let peer = agreement::UnparsedPublicKey::new(&agreement::ECDH_P256, &subscription.p256dh);
+    agreement::agree_ephemeral(private, &peer, Unspecified, |secret| {
+        seal(
+            secret,
+            &subscription.auth,
+            &subscription.p256dh,
+            public.as_ref(),
+            &salt,
+            plaintext,
+        )
+    })
+    .map_err(|_| anyhow::anyhow!("fail encrypt the push message"))
+}
+
+/// The server's VAPID key pair, which browsers subscribe with.
+struct Vapid {
+    key: EcdsaKeyPair,
+    /// The public key, as the browser's `applicationServerKey`.
+    public: String,
+    /// A `mailto:` or `https:` contact for the push services.
+    subject: String,
+}
+
+impl Vapid {
+    /// From the base64url keys `npx web-push generate-vapid-keys` prints.
+    fn new(public: &str, private: &str, subject: &str) -> anyhow::Result<Self> {
+        let public_bytes =
+            unb64(public).ok_or_else(|| anyhow::anyhow!("the public key is not base64url"))?;
+        let private_bytes =
+            unb64(private).ok_or_else(|| anyhow::anyhow!("the private key is not base64url"))?;
+        let key = EcdsaKeyPair::from_private_key_and_public_key(
+            &signature::ECDSA_P256_SHA256_FIXED_SIGNING,
+            &private_bytes,
+            &public_bytes,
+        )
+        .map_err(|e| anyhow::anyhow!("the keys are no P-256 pair: {}", e))?;
+        Ok(Self {
+            key,
+            public: b64(public_bytes),
+            subject: subject.to_string(),
+        })
+    }
+
+    /// The `Authorization` of a push to `endpoint`: a JWT for its origin
+    /// and the public key to check it with.
+    fn authorization(&self, endpoint: &Uri, now: SystemTime) -> anyhow::Result<String> {
+        let audience = format!(
+            "{}://{}",
+            endpoint.scheme_str().unwrap_or("https"),
+            endpoint.authority().map(|a| a.as_str()).unwrap_or_default()
+        );
+        let expires = (now + TOKEN_TTL)
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let claims = json!({ "aud": audience, "exp": expires, "sub": self.subject });
+        let input = format!(
+            "{}.{}",
+            b64(r#"{"typ":"JWT","alg":"ES256"}"#),
+            b64(claims.to_string())
+        );
+        let signature = self
+            .key
+            .sign(&SystemRandom::new(), input.as_bytes())
+            .map_err(|_| anyhow::anyhow!("fail sign the VAPID token"))?;
+        Ok(format!(
+            "vapid t={}.{}, k={}",
+            input,
+            b64(signature),
+            self.public
+        ))
+    }
+}
+
+/// Pushes todo changes to the browsers subscribed with
+/// `POST /push/subscriptions`. Subscriptions are kept in memory, so the
+/// frontend registers again on every load; a push service saying one is
+/// gone, or failing it [`MAX_FAILURES`] times in a row, drops it.
+#[derive(Clone)]
+pub struct WebPush {
+    vapid: Arc<Vapid>,
+    notices: HashSet<Notice>,
+    /// The hosts endpoints may be at, with their subdomains.
+    services: Arc<Vec<String>>,
+    subscriptions: Arc<Mutex<HashMap<String, Subscription>>>,
+    client: hyper::Client<HttpsConnector>,
+}
+
+impl fmt::Debug for WebPush {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebPush")
+            .field("subject", &self.vapid.subject)
+            .field("notices", &self.notices)
+            .field("services", &self.services)
+            .finish_non_exhaustive()
+    }
+}
+
+impl WebPush {
+    pub fn new(
+        public: &str,
+        private: &str,
+        subject: &str,
+        notices: HashSet<Notice>,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            vapid: Arc::new(Vapid::new(public, private, subject)?),
+            notices,
+            services: Arc::new(PUSH_SERVICES.iter().map(|s| s.to_string()).collect()),
+            subscriptions: Arc::default(),
+            client: client::https(),
+        })
+    }
+
+    /// Takes endpoints at `services` and their subdomains only.
+    pub fn with_services(mut self, services: Vec<String>) -> Self {
+        self.services = Arc::new(services);
+        self
+    }
+
+    /// Signs with `VAPID_PUBLIC_KEY` and `VAPID_PRIVATE_KEY`, giving
+    /// `VAPID_SUBJECT` as the contact, and pushes the changes `PUSH_EVENTS`
+    /// lists as `CHAT_EVENTS` does, `due_soon` and `overdue` included.
+    /// `PUSH_SERVICE_HOSTS` replaces the push services endpoints may be at,
+    /// comma separated. `None` when the private key is unset.
+    pub fn from_env() -> Option<Self> {
+        let private = env::var("VAPID_PRIVATE_KEY")
+            .ok()
+            .filter(|key| !key.is_empty())?;
+        let public = env::var("VAPID_PUBLIC_KEY").expect("undefined [VAPID_PUBLIC_KEY]");
+        let subject = env::var("VAPID_SUBJECT").expect("undefined [VAPID_SUBJECT]");
+        let push = Self::new(
+            &public,
+            &private,
+            &subject,
+            Notice::list_from_env("PUSH_EVENTS"),
+        )
+        .unwrap_or_else(|e| panic!("invalid [VAPID_PRIVATE_KEY]: {:#}", e));
+        let push = match env::var("PUSH_SERVICE_HOSTS") {
+            Ok(hosts) => push.with_services(
+                hosts
+                    .split(',')
+                    .map(|host| host.trim().to_ascii_lowercase())
+                    .filter(|host| !host.is_empty())
+                    .collect(),
+            ),
+            Err(_) => push,
+        };
+        Some(push)
+    }
+
+    /// The key browsers subscribe with, as `applicationServerKey`.
+    pub fn public_key(&self) -> &str {
+        &self.vapid.public
+    }
+
+    fn subscriptions(&self) -> std::sync::MutexGuard<'_, HashMap<String, Subscription>> {
+        self.subscriptions.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Takes `json`, replacing the subscription of its endpoint if there
+    /// is one.
+    pub fn subscribe(&self, json: &SubscriptionJson) -> Result<(), SubscribeError> {
+        let subscription = Subscription::parse(json, &self.services)?;
+        let mut subscriptions = self.subscriptions();
+        if subscriptions.len() >= MAX_SUBSCRIPTIONS && !subscriptions.contains_key(&json.endpoint) {
+            return Err(SubscribeError::Full);
+        }
+        subscriptions.insert(json.endpoint.clone(), subscription);
+        Ok(())
+    }
+
+    /// Whether there was a subscription for `endpoint`.
+    pub fn unsubscribe(&self, endpoint: &str) -> bool {
+        self.subscriptions().remove(endpoint).is_some()
+    }
+
+    /// Keeps pushing the changes published on `bus`, and the todos coming
+    /// due every [`DUE_CHECK_EVERY`], on a background task.
+    pub fn spawn<T: TodoRepository>(self, todos: T, bus: &EventBus) {
+        tracing::info!("pushing {:?} todos to subscribed browsers", self.notices);
+        let mut events = bus.subscribe();
+        tokio::spawn(async move {
+            let mut changes = Changes::load(&todos).await;
+            let mut checks = tokio::time::interval(DUE_CHECK_EVERY);
+            loop {
+                let event = tokio::select! {
+                    event = events.recv() => event,
+                    _ = checks.tick() => {
+                        match changes.due(&todos, &self.notices).await {
+                            Ok(due) => {
+                                for change in due {
+                                    self.push_all(&message(&change)).await;
+                                }
+                            }
+                            Err(e) => tracing::warn!("failed to read the todos coming due: {:#}", e),
+                        }
+                        continue;
+                    }
+                };
+                let event = match event {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(missed)) => {
+                        tracing::warn!("missed {} todo events to push", missed);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => return,
+                };
+                match changes.of(&todos, &event).await {
+                    Ok(Some(change)) if self.notices.contains(&change.notice()) => {
+                        self.push_all(&message(&change)).await;
+                    }
+                    Ok(_) => {}
+                    Err(e) => tracing::warn!("failed to read the todo of {:?}: {:#}", event, e),
+                }
+            }
+        });
+    }
+
+    /// Pushes `message` to every subscription at once, then drops the
+    /// ones that are gone.
+    async fn push_all(&self, message: &Value) {
+        // By the endpoint as subscribed, which its `Uri` may spell otherwise.
+        let subscriptions: Vec<_> = self
+            .subscriptions()
+            .iter()
+            .map(|(key, subscription)| (key.clone(), subscription.clone()))
+            .collect();
+        let payload = message.to_string();
+        let results = join_all(
+            subscriptions
+                .iter()
+                .map(|(_, subscription)| self.deliver(subscription, payload.as_bytes())),
+        )
+        .await;
+
+        let mut kept = self.subscriptions();
+        for ((key, subscription), result) in subscriptions.iter().zip(results) {
+            let gone = match result {
+                Ok(status) if status.is_success() => {
+                    if let Some(kept) = kept.get_mut(key) {
+                        kept.failures = 0;
+                    }
+                    false
+                }
+                Ok(StatusCode::NOT_FOUND | StatusCode::GONE) => true,
+                failure => {
+                    let failure = match failure {
+                        Ok(status) => format!("push service answered {}", status),
+                        Err(e) => format!("{:#}", e),
+                    };
+                    tracing::warn!("failed to push to {}: {}", subscription.endpoint, failure);
+                    kept.get_mut(key).is_some_and(|kept| {
+                        kept.failures += 1;
+                        kept.failures >= MAX_FAILURES
+                    })
+                }
+            };
+            if gone && kept.remove(key).is_some() {
+                tracing::info!("dropped the push subscription {}", subscription.endpoint);
+            }
+        }
+    }
+
+    async fn deliver(
+        &self,
+        subscription: &Subscription,
+        payload: &[u8],
+    ) -> anyhow::Result<StatusCode> {
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri(subscription.endpoint.clone())
+            .header(
+                header::AUTHORIZATION,
+                self.vapid
+                    .authorization(&subscription.endpoint, SystemTime::now())?,
+            )
+            .header(header::CONTENT_ENCODING, "aes128gcm")
+            .header("ttl", MESSAGE_TTL.as_secs())
+            .body(Body::from(encrypt(subscription, payload)?))?;
+        let res = client::request(&self.client, req, DELIVERY_TIMEOUT).await?;
+        Ok(res.status())
+    }
+}
+
+/// What the frontend's service worker is sent to show for `change`.
+fn message(change: &Change) -> Value {
+    match change {
+        Change::Created(todo) => json!({
+            "notice": "created",
+            "id": todo.id(),
+            "title": "New todo",
+            "body": todo.text(),
+        }),
+        Change::Completed(todo) => json!({
+            "notice": "completed",
+            "id": todo.id(),
+            "title": "Done",
+            "body": todo.text(),
+        }),
+        Change::Deleted(id) => json!({
+            "notice": "deleted",
+            "id": id,
+            "title": "Deleted todo",
+            "body": format!("#{}", id),
+        }),
+        Change::DueSoon(todo) => json!({
+            "notice": "due_soon",
+            "id": todo.id(),
+            "title": "Due soon",
+            "body": todo.text(),
+            "due": todo.due(),
+        }),
+        Change::Overdue(todo) => json!({
+            "notice": "overdue",
+            "id": todo.id(),
+            "title": "Overdue",
+            "body": todo.text(),
+            "due": todo.due(),
+        }),
+    }
+}
+
+/// Pushes todo changes on a background task when `push` is set up; see
+/// [`WebPush::from_env`].
+pub fn spawn<T: TodoRepository>(push: Option<&WebPush>, todos: T, bus: &EventBus) {
+    if let Some(push) = push {
+        push.clone().spawn(todos, bus);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{net::SocketAddr, sync::atomic::AtomicU16, sync::atomic::Ordering};
+
+    use axum::{body::Bytes, extract::Extension, http::HeaderMap, routing::post, Router};
+
+    use super::*;
+    use crate::{
+        events::Publishing,
+        repositories::todo::{CreateTodo, TodoRepositoryForMemory, UpdateTodo},
+    };
+
+    // a pair made for these tests
+    const PUBLIC: &str =
+        "BJgRr_49WwOR1fzppiqq04vCeaWSQFwC7bGq_J--yXYb9BZ27UNFWP3m85jnsCnyl3Ft2rWytx6NU6D8UHFCWas";
+    const PRIVATE: &str = "_r_2LS1KMYydAgsUHTU-nkDpbHq6-omulHS39NE8_OY";
+
+    fn push() -> WebPush {
+        let notices = HashSet::from([Notice::Created, Notice::Completed]);
+        WebPush::new(PUBLIC, PRIVATE, "mailto:admin@example.com", notices).unwrap()
+    }
+
+    fn subscription_json(endpoint: &str) -> SubscriptionJson {
+        serde_json::from_value(json!({
+            "endpoint": endpoint,
+            "keys": {
+                "p256dh": "BCVxsr7N_eNgVRqvHtD0zTZsEc6-VV-JvLexhqUzORcxaOzi6-AYWXvTBHm4bjyPjs7Vd8pZGH6SRpkNtoIAiw4",
+                "auth": "BTBZMqHH6r4Tts7J_aSIgg",
+            },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn encrypts_as_rfc_8291_does() {
+        // the example of RFC 8291, section 5
+        let salt: [u8; 16] = unb64("DGv6ra1nlYgDCS1FRnbzlw").unwrap().try_into().unwrap();
+        let body = seal(
+            &unb64("kyrL1jIIOHEzg3sM2ZWRHDRB62YACZhhSlknJ672kSs").unwrap(),
+            &unb64("BTBZMqHH6r4Tts7J_aSIgg").unwrap(),
+            &unb64("BCVxsr7N_eNgVRqvHtD0zTZsEc6-VV-JvLexhqUzORcxaOzi6-AYWXvTBHm4bjyPjs7Vd8pZGH6SRpkNtoIAiw4").unwrap(),
+            &unb64("BP4z9KsN6nGRTbVYI_c7VJSPQTBtkgcy27mlmlMoZIIgDll6e3vCYLocInmYWAmS6TlzAC8wEqKK6PBru3jl7A8").unwrap(),
+            &salt,
+            b"When I grow up, I want to be a watermelon",
+        )
+        .unwrap();
+        assert_eq!(
+            b64(body),
+            "DGv6ra1nlYgDCS1FRnbzlwAAEABBBP4z9KsN6nGRTbVYI_c7VJSPQTBtkgcy27mlmlMoZIIgDll6e3vCYLocInmYWAmS6TlzAC8wEqKK6PBru3jl7A_yl95bQpu6cVPTpK4Mqgkf1CXztLVBSt2Ks3oZwbuwXPXLWyouBWLVWGNWQexSgSxsj_Qulcy4a-fN"
+        );
+    }
+
+    #[test]
+    fn signs_tokens_for_the_push_service() {
+        let push = push();
+        let endpoint: Uri = "https://fcm.googleapis.com/fcm/send/abc".parse().unwrap();
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let authorization = push.vapid.authorization(&endpoint, now).unwrap();
+        let (token, key) = authorization
+            .strip_prefix("vapid t=")
+            .and_then(|rest| rest.split_once(", k="))
+            .unwrap();
+        assert_eq!(key, PUBLIC);
+        let (input, signature) = token.rsplit_once('.').unwrap();
+        signature::UnparsedPublicKey::new(
+            &signature::ECDSA_P256_SHA256_FIXED,
+            unb64(PUBLIC).unwrap(),
+        )
+        .verify(input.as_bytes(), &unb64(signature).unwrap())
+        .unwrap();
+        let claims: Value =
+            serde_json::from_slice(&unb64(input.split('.').nth(1).unwrap()).unwrap()).unwrap();
+        assert_eq!(
+            claims,
+            json!({
+                "aud": "https://fcm.googleapis.com",
+                "exp": 1_700_000_000 + TOKEN_TTL.as_secs(),
+                "sub": "mailto:admin@example.com",
+            })
+        );
+    }
+
+    #[test]
+    fn takes_sound_subscriptions_only() {
+        let push = push();
+        assert_eq!(
+            push.subscribe(&subscription_json("https://fcm.googleapis.com/fcm/send/1")),
+            Ok(())
+        );
+        assert_eq!(
+            push.subscribe(&subscription_json("http://fcm.googleapis.com/fcm/send/1")),
+            Err(SubscribeError::Invalid)
+        );
+        let mut json = subscription_json("https://fcm.googleapis.com/fcm/send/2");
+        json.keys.auth = "short".to_string();
+        assert_eq!(push.subscribe(&json), Err(SubscribeError::Invalid));
+        assert!(push.unsubscribe("https://fcm.googleapis.com/fcm/send/1"));
+        assert!(!push.unsubscribe("https://fcm.googleapis.com/fcm/send/1"));
+    }
+
+    #[test]
+    fn takes_endpoints_at_push_services_only() {
+        let push = push();
+        for endpoint in [
+            "https://wns2-par02p.notify.windows.com/w/?token=abc",
+            "https://web.push.apple.com/abc",
+            "https://UPDATES.push.services.mozilla.com/wpush/v2/abc",
+        ] {
+            assert_eq!(
+                push.subscribe(&subscription_json(endpoint)),
+                Ok(()),
+                "{}",
+                endpoint
+            );
+        }
+        for endpoint in [
+            "https://169.254.169.254/latest/meta-data",
+            "https://localhost/admin/flags",
+            "https://evilfcm.googleapis.com.example.com/",
+            "https://notfcm.googleapis.com/",
+        ] {
+            assert_eq!(
+                push.subscribe(&subscription_json(endpoint)),
+                Err(SubscribeError::UnknownService),
+                "{}",
+                endpoint
+            );
+        }
+        let push = push.with_services(vec!["push.example.com".to_string()]);
+        assert_eq!(
+            push.subscribe(&subscription_json("https://push.example.com/1")),
+            Ok(())
+        );
+        assert_eq!(
+            push.subscribe(&subscription_json("https://fcm.googleapis.com/fcm/send/1")),
+            Err(SubscribeError::UnknownService)
+        );
+    }
+
+    /// A push service answering `status` to every push, counting them.
+    async fn push_service(status: StatusCode) -> (SocketAddr, Arc<AtomicU16>) {
+        let pushes = Arc::new(AtomicU16::new(0));
+        // Every path, the endpoints without one too.
+        let app = Router::new()
+            .fallback(post(
+                move |headers: HeaderMap,
+                      body: Bytes,
+                      Extension(pushes): Extension<Arc<AtomicU16>>| async move {
+                    assert_eq!(headers["content-encoding"], "aes128gcm");
+                    assert!(headers["authorization"]
+                        .to_str()
+                        .unwrap()
+                        .starts_with("vapid t="));
+                    assert!(body.len() > 16 + 4 + 1 + 65);
+                    pushes.fetch_add(1, Ordering::SeqCst);
+                    status
+                },
+            ))
+            .layer(Extension(pushes.clone()));
+        let addr = crate::testing::serve(app);
+        (addr, pushes)
+    }
+
+    /// Subscribes `endpoint` as is, plain `http` included.
+    fn subscribe_plain(push: &WebPush, endpoint: String) {
+        let mut subscription = Subscription::parse(
+            &subscription_json("https://fcm.googleapis.com/fcm/send/x"),
+            &[PUSH_SERVICES[0].to_string()],
+        )
+        .unwrap();
+        subscription.endpoint = endpoint.parse().unwrap();
+        push.subscriptions().insert(endpoint, subscription);
+    }
+
+    #[tokio::test]
+    async fn pushes_changes_and_drops_gone_subscriptions() {
+        let (live, live_pushes) = push_service(StatusCode::CREATED).await;
+        let (gone, gone_pushes) = push_service(StatusCode::GONE).await;
+        let push = push();
+        subscribe_plain(&push, format!("http://{}/1", live));
+        subscribe_plain(&push, format!("http://{}/2", gone));
+
+        let bus = EventBus::new(16);
+        let todos = Publishing::new(TodoRepositoryForMemory::new(), bus.clone());
+        push.clone().spawn(todos.clone(), &bus);
+        tokio::task::yield_now().await;
+
+        let todo = todos
+            .create(CreateTodo::new("Buy milk".to_string()))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(push.subscriptions().len(), 1, "the gone one is dropped");
+        todos
+            .update(todo.id(), UpdateTodo::new(None, Some(true)))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        // deletions aren't asked for
+        todos.delete(todo.id()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        assert_eq!(live_pushes.load(Ordering::SeqCst), 2);
+        assert_eq!(gone_pushes.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn drops_gone_subscriptions_without_a_path() {
+        let (gone, gone_pushes) = push_service(StatusCode::GONE).await;
+        let push = push();
+        // Its `Uri` spells it with a `/` at the end.
+        subscribe_plain(&push, format!("http://{}", gone));
+
+        let todo = crate::repositories::todo::Todo::new(1, "Buy milk".to_string());
+        push.push_all(&message(&Change::Created(todo))).await;
+        assert_eq!(gone_pushes.load(Ordering::SeqCst), 1);
+        assert!(push.subscriptions().is_empty());
+    }
+
+    #[test]
+    fn tells_the_service_worker_what_to_show() {
+        let todo = crate::repositories::todo::Todo::new(3, "Buy milk".to_string());
+        let shown = message(&Change::Completed(todo));
+        assert_eq!(shown["title"], "Done");
+        assert_eq!(shown["body"], "Buy milk");
+        assert_eq!(message(&Change::Deleted(3))["body"], "#3");
+        let rent = || {
+            serde_json::from_value(json!({
+                "id": 4, "text": "Rent", "completed": false, "due": "2024-06-01"
+            }))
+            .unwrap()
+        };
+        let shown = message(&Change::DueSoon(rent()));
+        assert_eq!(shown["notice"], "due_soon");
+        assert_eq!(shown["due"], "2024-06-01");
+        let shown = message(&Change::Overdue(rent()));
+        assert_eq!(shown["notice"], "overdue");
+        assert_eq!(shown["title"], "Overdue");
+    }
+}
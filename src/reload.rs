@@ -0,0 +1,148 @@
+use crate::{
+    config::AppConfig,
+    flags::Flags,
+    layers::{cors::AllowedOrigins, rate_limit::RateLimiter},
+    telemetry::LogFilter,
+};
+
+/// The settings that take effect without a restart: the log filter, the
+/// CORS origins, the rate limits and the feature flags. The others shape
+/// listeners, pools and the router, which are built once.
+pub struct Reloadable {
+    pub config: AppConfig,
+    pub log_filter: LogFilter,
+    pub origins: AllowedOrigins,
+    pub limiter: RateLimiter,
+    pub flags: Flags,
+}
+
+impl Reloadable {
+    /// Re-reads the configuration on every SIGHUP, keeping what is in
+    /// effect when it can't be loaded.
+    pub fn spawn(mut self) {
+        #[cfg(unix)]
+        tokio::spawn(async move {
+            use tokio::signal::unix::{signal, SignalKind};
+
+            let mut hangups = match signal(SignalKind::hangup()) {
+                Ok(hangups) => hangups,
+                Err(e) => {
+                    tracing::error!("failed to listen for SIGHUP: {}", e);
+                    return;
+                }
+            };
+            while hangups.recv().await.is_some() {
+                match AppConfig::try_load() {
+                    Ok(config) => self.apply(config),
+                    Err(e) => tracing::error!("configuration not reloaded: {:#}", e),
+                }
+            }
+        });
+        #[cfg(not(unix))]
+        drop(self);
+    }
+
+    /// Puts the reloadable settings of `config` in effect, each unless it
+    /// is invalid, and warns about the others changing.
+    fn apply(&mut self, config: AppConfig) {
+        let old = &self.config;
+        if config.server.log_filter != old.server.log_filter {
+            match self.log_filter.set(&config.server.log_filter) {
+                Ok(()) => tracing::info!("log filter now {}", config.server.log_filter),
+                Err(e) => {
+                    tracing::error!("invalid [RUST_LOG]: {}, {}", config.server.log_filter, e)
+                }
+            }
+        }
+        if config.cors != old.cors {
+            match self.origins.set(&config.cors) {
+                Ok(()) => tracing::info!("CORS origins now {:?}", config.cors.allowed_origins),
+                Err(e) => tracing::error!("invalid [CORS_ALLOWED_ORIGINS]: {}", e),
+            }
+        }
+        if config.features != old.features {
+            match self.limiter.set(&config.features) {
+                Ok(()) => tracing::info!("rate limits reloaded"),
+                Err(e) => tracing::error!("{}", e),
+            }
+        }
+        if config.flags != old.flags {
+            self.flags.set(&config.flags);
+            tracing::info!("feature flags now {:?}", config.flags);
+        }
+
+        if structural(&config) != structural(old) {
+            tracing::warn!("configuration changed beyond what reloads, restart to apply it");
+        }
+        self.config = config;
+    }
+}
+
+/// `config` without the settings [`Reloadable`] applies.
+fn structural(config: &AppConfig) -> AppConfig {
+    let mut config = config.clone();
+    config.server.log_filter = String::new();
+    config.cors = Default::default();
+    config.features.rate_limit_requests = None;
+    config.features.rate_limit_window_secs = 0;
+    config.features.rate_limit_burst = None;
+    config.features.rate_limit_reads = false;
+    config.flags = Default::default();
+    config
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::CorsConfig;
+
+    use super::*;
+
+    fn reloadable(config: &AppConfig) -> Reloadable {
+        Reloadable {
+            config: config.clone(),
+            log_filter: LogFilter::detached(),
+            origins: AllowedOrigins::from_config(&config.cors),
+            limiter: RateLimiter::from_config(&config.features),
+            flags: Flags::from_config(&config.flags),
+        }
+    }
+
+    #[test]
+    fn applies_the_reloadable_settings() {
+        let mut config = AppConfig::default();
+        let mut reloadable = reloadable(&config);
+
+        config.server.log_filter = "debug".to_string();
+        config.features.rate_limit_requests = Some(5);
+        config.flags.feeds = false;
+        reloadable.apply(config.clone());
+        assert!(reloadable.limiter.enabled());
+        assert!(!reloadable.flags.current().feeds);
+        assert_eq!(reloadable.config, config);
+    }
+
+    #[test]
+    fn keeps_settings_that_are_invalid() {
+        let mut config = AppConfig::default();
+        config.features.rate_limit_requests = Some(5);
+        let mut reloadable = reloadable(&config);
+
+        config.features.rate_limit_window_secs = 0;
+        config.cors = CorsConfig {
+            allowed_origins: vec!["bad\norigin".to_string()],
+        };
+        reloadable.apply(config);
+        assert_eq!(reloadable.limiter.check("a").unwrap().limit, 5);
+    }
+
+    #[test]
+    fn tells_structural_changes_apart() {
+        let config = AppConfig::default();
+        let mut changed = config.clone();
+        changed.flags.caldav = false;
+        changed.features.rate_limit_requests = Some(1);
+        assert_eq!(structural(&changed), structural(&config));
+        changed.server.port = 1;
+        assert_ne!(structural(&changed), structural(&config));
+    }
+}
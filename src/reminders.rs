@@ -0,0 +1,222 @@
+use std::{env, fs, sync::Arc, time::Duration};
+
+use crate::{
+    handlers::escape,
+    mailer::Mailer,
+    notify::DueTodos,
+    repositories::todo::{Todo, TodoRepository},
+};
+
+/// The reminder unless `REMINDER_TEMPLATE_PATH` names another.
+const DEFAULT_TEMPLATE: &str = "<!DOCTYPE html>
+<html>
+<body>
+<p>{{ count }} todos are due soon:</p>
+<ul>
+{{ todos }}
+</ul>
+</body>
+</html>
+";
+
+/// Emails the addresses that opted in about the open todos coming due, as
+/// HTML, once per todo and due day. It looks for them every so often, and
+/// sends nothing while none are newly due.
+///
+/// The todos reminded of are remembered in memory only, so a restart may
+/// remind of them again.
+#[derive(Debug, Clone)]
+pub struct Reminders {
+    mailer: Mailer,
+    recipients: Vec<String>,
+    every: Duration,
+    template: Arc<String>,
+    /// The todos coming due, and those reminded of.
+    due: DueTodos,
+}
+
+impl Reminders {
+    pub fn new(mailer: Mailer, recipients: Vec<String>, every: Duration) -> Self {
+        Self {
+            mailer,
+            recipients,
+            every,
+            template: Arc::new(DEFAULT_TEMPLATE.to_string()),
+            due: DueTodos::within(1),
+        }
+    }
+
+    /// Reminds of todos due up to `days` days from today rather than 1.
+    pub fn with_lead_days(mut self, days: i64) -> Self {
+        self.due = DueTodos::within(days);
+        self
+    }
+
+    /// Renders the reminder from `template`, where `{{ count }}` is the
+    /// number of todos coming due and `{{ todos }}` their `<li>` items.
+    pub fn with_template(mut self, template: String) -> Self {
+        self.template = Arc::new(template);
+        self
+    }
+
+    /// Mails the addresses `REMINDER_RECIPIENTS` lists, comma separated,
+    /// about the todos due within `REMINDER_LEAD_DAYS` days (1 by default),
+    /// looking every `REMINDER_EVERY_HOURS` hours (1 by default), through
+    /// the [`Mailer::from_env`] server, rendering `REMINDER_TEMPLATE_PATH`
+    /// when set. `None` unless recipients are listed.
+    pub fn from_env() -> Option<Self> {
+        let recipients: Vec<String> = env::var("REMINDER_RECIPIENTS")
+            .ok()?
+            .split(',')
+            .map(|address| address.trim().to_string())
+            .filter(|address| !address.is_empty())
+            .collect();
+        if recipients.is_empty() {
+            return None;
+        }
+        if let Some(address) = recipients
+            .iter()
+            .find(|address| address.contains(['<', '>', '\r', '\n']) || !address.contains('@'))
+        {
+            panic!("invalid [REMINDER_RECIPIENTS]: {:?} is no address", address);
+        }
+        let mailer = Mailer::from_env().expect("undefined [SMTP_URL], reminders need it");
+        let hours = env::var("REMINDER_EVERY_HOURS").unwrap_or_else(|_| "1".to_string());
+        let every = hours
+            .parse::<u64>()
+            .ok()
+            .filter(|hours| *hours > 0)
+            .unwrap_or_else(|| panic!("invalid [REMINDER_EVERY_HOURS]: {}", hours));
+        let days = env::var("REMINDER_LEAD_DAYS").unwrap_or_else(|_| "1".to_string());
+        let lead_days = days
+            .parse::<i64>()
+            .ok()
+            .filter(|days| *days >= 0)
+            .unwrap_or_else(|| panic!("invalid [REMINDER_LEAD_DAYS]: {}", days));
+        let reminders = Self::new(mailer, recipients, Duration::from_secs(every * 60 * 60))
+            .with_lead_days(lead_days);
+        Some(match env::var("REMINDER_TEMPLATE_PATH") {
+            Ok(path) => {
+                reminders.with_template(fs::read_to_string(&path).unwrap_or_else(|e| {
+                    panic!("invalid [REMINDER_TEMPLATE_PATH]: {}, {}", path, e)
+                }))
+            }
+            Err(_) => reminders,
+        })
+    }
+
+    /// The reminder of `todos`, their texts escaped and their due days
+    /// after them.
+    fn render(&self, todos: &[Todo]) -> String {
+        let items: Vec<String> = todos
+            .iter()
+            .map(|todo| match todo.due() {
+                Some(due) => format!("<li>{} ({})</li>", escape(todo.text()), due),
+                None => format!("<li>{}</li>", escape(todo.text())),
+            })
+            .collect();
+        self.template
+            .replace("{{ count }}", &todos.len().to_string())
+            .replace("{{ todos }}", &items.join("\n"))
+    }
+
+    /// Mails a reminder of the todos newly coming due, and returns how many
+    /// it listed. Those that failed to go out are tried again next time.
+    pub async fn remind<T: TodoRepository>(&mut self, todos: &T) -> anyhow::Result<usize> {
+        let due = self.due.untold(todos).await?;
+        if due.is_empty() {
+            return Ok(0);
+        }
+        let subject = format!("{} todos are due soon", due.len());
+        self.mailer
+            .send(&self.recipients, &subject, &self.render(&due))
+            .await?;
+        self.due.tell(&due);
+        Ok(due.len())
+    }
+
+    /// Looks for todos coming due every `every` on a background task, the
+    /// first time after the first interval rather than at every start.
+    pub fn spawn<T: TodoRepository>(mut self, todos: T) {
+        tracing::info!(
+            "reminding {} recipients of the todos due within {} days, looking every {:?}",
+            self.recipients.len(),
+            self.due.days(),
+            self.every
+        );
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(self.every);
+            ticks.tick().await;
+            loop {
+                ticks.tick().await;
+                match self.remind(&todos).await {
+                    Ok(0) => {}
+                    Ok(count) => tracing::info!("mailed a reminder of {} todos", count),
+                    Err(e) => tracing::warn!("failed to mail the reminder: {:#}", e),
+                }
+            }
+        });
+    }
+}
+
+/// Mails reminders on a background task when `REMINDER_RECIPIENTS` is set;
+/// see [`Reminders::from_env`].
+pub fn spawn_from_env<T: TodoRepository>(todos: T) {
+    if let Some(reminders) = Reminders::from_env() {
+        reminders.spawn(todos);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::repositories::{
+        date::Date,
+        todo::{CreateTodo, TodoRepositoryForMemory},
+    };
+
+    fn reminders() -> Reminders {
+        let mailer = Mailer::new("smtp://127.0.0.1:1", "todos@example.com").unwrap();
+        Reminders::new(
+            mailer,
+            vec!["mona@example.com".to_string()],
+            Duration::from_secs(60),
+        )
+    }
+
+    #[test]
+    fn renders_the_todos_escaped() {
+        let todos = [
+            serde_json::from_value(serde_json::json!({
+                "id": 1, "text": "milk & <eggs>", "completed": false, "due": "2024-06-01"
+            }))
+            .unwrap(),
+            Todo::new(2, "Mow".to_string()),
+        ];
+        let html = reminders()
+            .with_template("<b>{{ count }}</b><ul>{{ todos }}</ul>".to_string())
+            .render(&todos);
+        assert_eq!(
+            html,
+            "<b>2</b><ul><li>milk &amp; &lt;eggs&gt; (2024-06-01)</li>\n<li>Mow</li></ul>"
+        );
+        assert!(reminders().render(&todos).contains("2 todos are due soon"));
+    }
+
+    #[tokio::test]
+    async fn reminds_until_the_reminder_goes_out() {
+        let todos = TodoRepositoryForMemory::new();
+        let mut reminders = reminders();
+        todos
+            .create(CreateTodo::new("Undated".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(reminders.remind(&todos).await.unwrap(), 0);
+
+        let today = CreateTodo::new("Today".to_string()).with_due(Some(Date::today()));
+        let todo = todos.create(today).await.unwrap();
+        // The server is unreachable, so the todo stays to be reminded of.
+        reminders.remind(&todos).await.unwrap_err();
+        assert_eq!(reminders.due.untold(&todos).await.unwrap(), vec![todo]);
+    }
+}
@@ -0,0 +1,1218 @@
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hash,
+};
+
+use axum::async_trait;
+use futures_util::{stream::BoxStream, StreamExt};
+use serde::{Deserialize, Serialize};
+use sqlx::{
+    database::HasArguments, query::Query, ColumnIndex, Database, Decode, Encode, Executor, FromRow,
+    IntoArguments, PgPool, Pool, Row, SqlitePool, Transaction, Type,
+};
+use tokio::sync::mpsc;
+use validator::{Validate, ValidationError, ValidationErrors};
+
+use super::{
+    date::Date,
+    id::Uuid,
+    label::{Label, LabelRepository},
+    todo::{epoch_secs, CreateTodo, TodoFilter, TodoRepository, UpdateTodo},
+};
+
+/// Version of the backup layout; bumped whenever a field changes meaning.
+pub const BACKUP_FORMAT: u32 = 1;
+
+/// Parts read ahead of a slow client.
+const BACKUP_BUFFER: usize = 64;
+
+/// A todo as it is stored: texts stay encrypted when encryption is on, and
+/// times are seconds since the epoch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct BackupTodo {
+    pub id: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uuid: Option<Uuid>,
+    pub text: String,
+    pub completed: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub due: Option<Date>,
+    pub version: i32,
+    pub updated_at: i64,
+    /// Only set for archived todos.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archived_at: Option<i64>,
+}
+
+/// A label attached to a todo.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct TodoLabel {
+    pub todo_id: i64,
+    pub label_id: i64,
+}
+
+/// One record of a backup. A backup yields every label, then every todo,
+/// then every todo label, then every archived todo, each in id order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BackupPart {
+    Label(Label),
+    Todo(BackupTodo),
+    TodoLabel(TodoLabel),
+    Archived(BackupTodo),
+}
+
+/// A backup document as `GET /admin/backup` writes it, read back whole
+/// for a restore.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Backup {
+    pub format: u32,
+    #[serde(default)]
+    pub backend: String,
+    #[serde(default)]
+    pub taken_at: String,
+    #[serde(default)]
+    pub labels: Vec<Label>,
+    #[serde(default)]
+    pub todos: Vec<BackupTodo>,
+    #[serde(default)]
+    pub todo_labels: Vec<TodoLabel>,
+    #[serde(default)]
+    pub archived_todos: Vec<BackupTodo>,
+}
+
+/// Checks the backup is one this version wrote and hangs together. Texts
+/// are not checked: encrypted ones are longer than any todo may be.
+impl Validate for Backup {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        if self.format != BACKUP_FORMAT {
+            errors.add("format", invalid("unsupported backup format"));
+        }
+        if !unique(self.labels.iter().map(|label| label.id)) {
+            errors.add("labels", invalid("duplicate ids"));
+        }
+        if !unique(self.labels.iter().map(|label| &label.name)) {
+            errors.add("labels", invalid("duplicate names"));
+        }
+        if !unique(self.labels.iter().filter_map(|label| label.uuid.as_ref())) {
+            errors.add("labels", invalid("duplicate uuids"));
+        }
+        // archived todos took their ids from the same sequence
+        let todos = || self.todos.iter().chain(&self.archived_todos);
+        if !unique(todos().map(|todo| todo.id)) {
+            errors.add("todos", invalid("duplicate ids"));
+        }
+        if !unique(todos().filter_map(|todo| todo.uuid.as_ref())) {
+            errors.add("todos", invalid("duplicate uuids"));
+        }
+        let todo_ids: HashSet<_> = self.todos.iter().map(|todo| todo.id).collect();
+        let label_ids: HashSet<_> = self.labels.iter().map(|label| label.id).collect();
+        if !self.todo_labels.iter().all(|todo_label| {
+            todo_ids.contains(&todo_label.todo_id) && label_ids.contains(&todo_label.label_id)
+        }) {
+            errors.add("todo_labels", invalid("unknown todo or label"));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+impl Backup {
+    /// Checks the backup can be merged: a merge tells stored todos by their
+    /// uuid, so todos without one would be added again on every restore.
+    pub fn check_mergeable(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        if self.todos.iter().any(|todo| todo.uuid.is_none()) {
+            errors.add(
+                "todos",
+                invalid("todos without a uuid can only be wiped back"),
+            );
+        }
+        if self.archived_todos.iter().any(|todo| todo.uuid.is_none()) {
+            errors.add(
+                "archived_todos",
+                invalid("todos without a uuid can only be wiped back"),
+            );
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+pub fn unique<T: Eq + Hash>(mut items: impl Iterator<Item = T>) -> bool {
+    let mut seen = HashSet::new();
+    items.all(|item| seen.insert(item))
+}
+
+pub fn invalid(message: &'static str) -> ValidationError {
+    let mut error = ValidationError::new("invalid");
+    error.message = Some(message.into());
+    error
+}
+
+/// What a restore does with the data already stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RestoreMode {
+    /// Deletes everything first; restored rows keep their ids.
+    Wipe,
+    /// Keeps what is there: labels are matched by name, todos whose uuid is
+    /// already stored are skipped, and the rest get new ids. Only for
+    /// backups whose todos all have a uuid; see [`Backup::check_mergeable`].
+    #[default]
+    Merge,
+}
+
+/// How many rows of each kind a restore added.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub struct Restored {
+    pub labels: usize,
+    pub todos: usize,
+    pub todo_labels: usize,
+    pub archived_todos: usize,
+}
+
+#[async_trait]
+pub trait BackupRepository: Clone + std::marker::Send + std::marker::Sync + 'static {
+    /// The backend the backup comes from, recorded with it.
+    fn backend(&self) -> &'static str;
+    /// Everything stored, produced incrementally.
+    fn backup(&self) -> BoxStream<'static, anyhow::Result<BackupPart>>;
+    /// Puts a validated backup back. SQL backends restore it in one
+    /// transaction, so either all of it or none is stored.
+    async fn restore(&self, backup: Backup, mode: RestoreMode) -> anyhow::Result<Restored>;
+}
+
+/// The statements reading a backup. `isolate` runs first in the transaction.
+struct BackupQueries {
+    isolate: Option<&'static str>,
+    labels: &'static str,
+    todos: &'static str,
+    todo_labels: &'static str,
+    archived: &'static str,
+}
+
+/// Reads `queries` inside one transaction on its own task, so all parts
+/// come from the same snapshot of the database.
+fn backup_sql<DB>(
+    pool: Pool<DB>,
+    queries: &'static BackupQueries,
+) -> BoxStream<'static, anyhow::Result<BackupPart>>
+where
+    DB: Database,
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+    for<'q> <DB as HasArguments<'q>>::Arguments: IntoArguments<'q, DB>,
+    Label: for<'r> FromRow<'r, DB::Row>,
+    BackupTodo: for<'r> FromRow<'r, DB::Row>,
+    TodoLabel: for<'r> FromRow<'r, DB::Row>,
+{
+    let (tx, rx) = mpsc::channel(BACKUP_BUFFER);
+    tokio::spawn(async move {
+        if let Err(e) = read_backup(&pool, queries, &tx).await {
+            tx.send(Err(e)).await.ok();
+        }
+    });
+
+    futures_util::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|part| (part, rx))
+    })
+    .boxed()
+}
+
+async fn read_backup<DB>(
+    pool: &Pool<DB>,
+    queries: &BackupQueries,
+    out: &mpsc::Sender<anyhow::Result<BackupPart>>,
+) -> anyhow::Result<()>
+where
+    DB: Database,
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+    for<'q> <DB as HasArguments<'q>>::Arguments: IntoArguments<'q, DB>,
+    Label: for<'r> FromRow<'r, DB::Row>,
+    BackupTodo: for<'r> FromRow<'r, DB::Row>,
+    TodoLabel: for<'r> FromRow<'r, DB::Row>,
+{
+    let mut tx = pool.begin().await?;
+    if let Some(isolate) = queries.isolate {
+        sqlx::query(isolate).execute(&mut *tx).await?;
+    }
+    // sending fails once the client went away, which ends the backup
+    let mut labels = sqlx::query_as::<DB, Label>(queries.labels).fetch(&mut *tx);
+    while let Some(label) = labels.next().await {
+        if out.send(Ok(BackupPart::Label(label?))).await.is_err() {
+            return Ok(());
+        }
+    }
+    drop(labels);
+    let mut todos = sqlx::query_as::<DB, BackupTodo>(queries.todos).fetch(&mut *tx);
+    while let Some(todo) = todos.next().await {
+        if out.send(Ok(BackupPart::Todo(todo?))).await.is_err() {
+            return Ok(());
+        }
+    }
+    drop(todos);
+    let mut todo_labels = sqlx::query_as::<DB, TodoLabel>(queries.todo_labels).fetch(&mut *tx);
+    while let Some(todo_label) = todo_labels.next().await {
+        if out
+            .send(Ok(BackupPart::TodoLabel(todo_label?)))
+            .await
+            .is_err()
+        {
+            return Ok(());
+        }
+    }
+    drop(todo_labels);
+    let mut archived = sqlx::query_as::<DB, BackupTodo>(queries.archived).fetch(&mut *tx);
+    while let Some(todo) = archived.next().await {
+        if out.send(Ok(BackupPart::Archived(todo?))).await.is_err() {
+            return Ok(());
+        }
+    }
+    drop(archived);
+    tx.rollback().await?;
+
+    Ok(())
+}
+
+/// The statements restoring a backup, all run in one transaction. Inserts
+/// take a null id for a fresh one; without `returning`, `todo_id` and
+/// `label_id` read back a fresh id afterwards.
+struct RestoreQueries {
+    wipe: &'static [&'static str],
+    label_named: &'static str,
+    label_uuid_taken: &'static str,
+    todo_uuid_taken: &'static str,
+    insert_label: &'static str,
+    label_id: Option<&'static str>,
+    insert_todo: &'static str,
+    todo_id: Option<&'static str>,
+    insert_todo_label: &'static str,
+    insert_archived: &'static str,
+    delete_todo: &'static str,
+    /// Run last, so the sequences hand out ids after the restored ones.
+    resequence: &'static [&'static str],
+}
+
+/// The id of the row `insert` adds, `given` or else the one the database
+/// picked.
+async fn inserted_id<'q, DB>(
+    tx: &mut Transaction<'_, DB>,
+    insert: Query<'q, DB, <DB as HasArguments<'q>>::Arguments>,
+    given: Option<i64>,
+    read_id: Option<&'q str>,
+) -> anyhow::Result<i64>
+where
+    DB: Database,
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+    for<'a> <DB as HasArguments<'a>>::Arguments: IntoArguments<'a, DB>,
+    i64: for<'r> Decode<'r, DB> + Type<DB>,
+    usize: ColumnIndex<DB::Row>,
+{
+    let id = match read_id {
+        None => insert.fetch_one(&mut **tx).await?.try_get(0)?,
+        Some(read_id) => {
+            insert.execute(&mut **tx).await?;
+            match given {
+                Some(id) => id,
+                None => sqlx::query(read_id)
+                    .fetch_one(&mut **tx)
+                    .await?
+                    .try_get(0)?,
+            }
+        }
+    };
+    Ok(id)
+}
+
+async fn restore_sql<DB>(
+    pool: &Pool<DB>,
+    queries: &RestoreQueries,
+    backup: Backup,
+    mode: RestoreMode,
+) -> anyhow::Result<Restored>
+where
+    DB: Database,
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+    for<'q> <DB as HasArguments<'q>>::Arguments: IntoArguments<'q, DB>,
+    for<'q> Option<i64>: Encode<'q, DB> + Type<DB>,
+    for<'q> Option<Uuid>: Encode<'q, DB> + Type<DB>,
+    for<'q> Option<Date>: Encode<'q, DB> + Type<DB>,
+    for<'q> String: Encode<'q, DB> + Type<DB>,
+    for<'q> bool: Encode<'q, DB> + Type<DB>,
+    for<'q> i64: Encode<'q, DB> + Type<DB>,
+    for<'q> i32: Encode<'q, DB> + Type<DB>,
+    i64: for<'r> Decode<'r, DB>,
+    usize: ColumnIndex<DB::Row>,
+{
+    let mut tx = pool.begin().await?;
+    let wipe = mode == RestoreMode::Wipe;
+    if wipe {
+        for statement in queries.wipe {
+            sqlx::query(statement).execute(&mut *tx).await?;
+        }
+    }
+    let mut restored = Restored::default();
+
+    let mut label_ids = HashMap::new();
+    for label in backup.labels {
+        if !wipe {
+            let existing = sqlx::query(queries.label_named)
+                .bind(label.name.clone())
+                .fetch_optional(&mut *tx)
+                .await?;
+            if let Some(existing) = existing {
+                label_ids.insert(label.id, existing.try_get(0)?);
+                continue;
+            }
+        }
+        // wiped uuids can't be taken
+        let uuid = match !wipe && uuid_taken(&mut tx, queries.label_uuid_taken, &label.uuid).await?
+        {
+            true => None,
+            false => label.uuid,
+        };
+        let given = wipe.then_some(label.id);
+        let insert = sqlx::query(queries.insert_label)
+            .bind(given)
+            .bind(uuid)
+            .bind(label.name);
+        let id = inserted_id(&mut tx, insert, given, queries.label_id).await?;
+        label_ids.insert(label.id, id);
+        restored.labels += 1;
+    }
+
+    let mut todo_ids = HashMap::new();
+    for todo in backup.todos {
+        if !wipe && uuid_taken(&mut tx, queries.todo_uuid_taken, &todo.uuid).await? {
+            continue;
+        }
+        let id = insert_todo(&mut tx, queries, wipe, &todo).await?;
+        todo_ids.insert(todo.id, id);
+        restored.todos += 1;
+    }
+    for todo_label in backup.todo_labels {
+        let (Some(&todo_id), Some(&label_id)) = (
+            todo_ids.get(&todo_label.todo_id),
+            label_ids.get(&todo_label.label_id),
+        ) else {
+            continue;
+        };
+        sqlx::query(queries.insert_todo_label)
+            .bind(todo_id)
+            .bind(label_id)
+            .execute(&mut *tx)
+            .await?;
+        restored.todo_labels += 1;
+    }
+
+    // archived todos go through `todos`, which hands out their ids
+    for todo in backup.archived_todos {
+        if !wipe && uuid_taken(&mut tx, queries.todo_uuid_taken, &todo.uuid).await? {
+            continue;
+        }
+        let id = insert_todo(&mut tx, queries, wipe, &todo).await?;
+        sqlx::query(queries.delete_todo)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(queries.insert_archived)
+            .bind(id)
+            .bind(todo.uuid)
+            .bind(todo.text)
+            .bind(todo.completed)
+            .bind(todo.version)
+            .bind(todo.updated_at)
+            .bind(todo.archived_at.unwrap_or(todo.updated_at))
+            .bind(todo.due)
+            .execute(&mut *tx)
+            .await?;
+        restored.archived_todos += 1;
+    }
+
+    for statement in queries.resequence {
+        sqlx::query(statement).execute(&mut *tx).await?;
+    }
+    tx.commit().await?;
+
+    Ok(restored)
+}
+
+async fn uuid_taken<DB>(
+    tx: &mut Transaction<'_, DB>,
+    query: &str,
+    uuid: &Option<Uuid>,
+) -> anyhow::Result<bool>
+where
+    DB: Database,
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+    for<'q> <DB as HasArguments<'q>>::Arguments: IntoArguments<'q, DB>,
+    for<'q> Option<Uuid>: Encode<'q, DB> + Type<DB>,
+    i64: for<'r> Decode<'r, DB> + Type<DB>,
+    usize: ColumnIndex<DB::Row>,
+{
+    if uuid.is_none() {
+        return Ok(false);
+    }
+    let count: i64 = sqlx::query(query)
+        .bind(uuid.clone())
+        .fetch_one(&mut **tx)
+        .await?
+        .try_get(0)?;
+    Ok(count > 0)
+}
+
+async fn insert_todo<DB>(
+    tx: &mut Transaction<'_, DB>,
+    queries: &RestoreQueries,
+    wipe: bool,
+    todo: &BackupTodo,
+) -> anyhow::Result<i64>
+where
+    DB: Database,
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+    for<'q> <DB as HasArguments<'q>>::Arguments: IntoArguments<'q, DB>,
+    for<'q> Option<i64>: Encode<'q, DB> + Type<DB>,
+    for<'q> Option<Uuid>: Encode<'q, DB> + Type<DB>,
+    for<'q> Option<Date>: Encode<'q, DB> + Type<DB>,
+    for<'q> String: Encode<'q, DB> + Type<DB>,
+    for<'q> bool: Encode<'q, DB> + Type<DB>,
+    for<'q> i64: Encode<'q, DB> + Type<DB>,
+    for<'q> i32: Encode<'q, DB> + Type<DB>,
+    i64: for<'r> Decode<'r, DB>,
+    usize: ColumnIndex<DB::Row>,
+{
+    let given = wipe.then_some(todo.id);
+    let insert = sqlx::query(queries.insert_todo)
+        .bind(given)
+        .bind(todo.uuid.clone())
+        .bind(todo.text.clone())
+        .bind(todo.completed)
+        .bind(todo.version)
+        .bind(todo.updated_at)
+        .bind(todo.due.clone());
+    inserted_id(tx, insert, given, queries.todo_id).await
+}
+
+static PG_QUERIES: BackupQueries = BackupQueries {
+    isolate: Some("set transaction isolation level repeatable read, read only"),
+    labels: "select id, uuid, name from labels order by id",
+    todos: r#"
+        select id, uuid, text, completed, due, version,
+            extract(epoch from updated_at)::bigint as updated_at, null::bigint as archived_at
+        from todos order by id
+    "#,
+    todo_labels: "select todo_id, label_id from todo_labels order by todo_id, label_id",
+    archived: r#"
+        select id, uuid, text, completed, due, version,
+            extract(epoch from updated_at)::bigint as updated_at,
+            extract(epoch from archived_at)::bigint as archived_at
+        from archived_todos order by id
+    "#,
+};
+
+const WIPE: &[&str] = &[
+    "delete from todo_labels",
+    "delete from archived_todos",
+    "delete from todos",
+    "delete from labels",
+];
+
+static PG_RESTORE: RestoreQueries = RestoreQueries {
+    wipe: WIPE,
+    label_named: "select id from labels where name = $1",
+    label_uuid_taken: "select count(*) from labels where uuid = $1",
+    todo_uuid_taken: r#"
+        select count(*) from (select uuid from todos union all select uuid from archived_todos) uuids
+        where uuid = $1
+    "#,
+    insert_label: r#"
+        insert into labels (id, uuid, name) values (coalesce($1, nextval('labels_id_seq')), $2, $3)
+        returning id
+    "#,
+    label_id: None,
+    insert_todo: r#"
+        insert into todos (id, uuid, text, completed, version, updated_at, due)
+        values (coalesce($1, nextval('todos_id_seq')), $2, $3, $4, $5, to_timestamp($6), $7)
+        returning id
+    "#,
+    todo_id: None,
+    insert_todo_label: "insert into todo_labels (todo_id, label_id) values ($1, $2)",
+    insert_archived: r#"
+        insert into archived_todos (id, uuid, text, completed, version, updated_at, archived_at, due)
+        values ($1, $2, $3, $4, $5, to_timestamp($6), to_timestamp($7), $8)
+    "#,
+    delete_todo: "delete from todos where id = $1",
+    // explicit ids leave the sequences behind
+    resequence: &[
+        r#"
+        select setval('todos_id_seq', greatest(last_value, (
+            select coalesce(max(id), 0) from (select id from todos union all select id from archived_todos) ids
+        ))) from todos_id_seq
+        "#,
+        r#"
+        select setval('labels_id_seq', greatest(last_value, (select coalesce(max(id), 0) from labels)))
+        from labels_id_seq
+        "#,
+    ],
+};
+
+#[derive(Debug, Clone)]
+pub struct BackupRepositoryForDb {
+    pool: PgPool,
+}
+
+impl BackupRepositoryForDb {
+    pub fn new(pool: PgPool) -> Self {
+        BackupRepositoryForDb { pool }
+    }
+}
+
+#[async_trait]
+impl BackupRepository for BackupRepositoryForDb {
+    fn backend(&self) -> &'static str {
+        "postgres"
+    }
+    fn backup(&self) -> BoxStream<'static, anyhow::Result<BackupPart>> {
+        backup_sql(self.pool.clone(), &PG_QUERIES)
+    }
+    async fn restore(&self, backup: Backup, mode: RestoreMode) -> anyhow::Result<Restored> {
+        restore_sql(&self.pool, &PG_RESTORE, backup, mode).await
+    }
+}
+
+/// A read transaction in SQLite sees one snapshot from its first read on.
+static SQLITE_QUERIES: BackupQueries = BackupQueries {
+    isolate: None,
+    labels: "select id, uuid, name from labels order by id",
+    todos: r#"
+        select id, uuid, text, completed, due, version, updated_at, null as archived_at
+        from todos order by id
+    "#,
+    todo_labels: "select todo_id, label_id from todo_labels order by todo_id, label_id",
+    archived: r#"
+        select id, uuid, text, completed, due, version, updated_at, archived_at
+        from archived_todos order by id
+    "#,
+};
+
+/// A null id makes SQLite pick one; explicit ids move AUTOINCREMENT along.
+static SQLITE_RESTORE: RestoreQueries = RestoreQueries {
+    wipe: WIPE,
+    label_named: "select id from labels where name = ?1",
+    label_uuid_taken: "select count(*) from labels where uuid = ?1",
+    todo_uuid_taken: r#"
+        select count(*) from (select uuid from todos union all select uuid from archived_todos)
+        where uuid = ?1
+    "#,
+    insert_label: "insert into labels (id, uuid, name) values (?1, ?2, ?3) returning id",
+    label_id: None,
+    insert_todo: r#"
+        insert into todos (id, uuid, text, completed, version, updated_at, due)
+        values (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+        returning id
+    "#,
+    todo_id: None,
+    insert_todo_label: "insert into todo_labels (todo_id, label_id) values (?1, ?2)",
+    insert_archived: r#"
+        insert into archived_todos (id, uuid, text, completed, version, updated_at, archived_at, due)
+        values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+    "#,
+    delete_todo: "delete from todos where id = ?1",
+    resequence: &[],
+};
+
+#[derive(Debug, Clone)]
+pub struct BackupRepositoryForSqlite {
+    pool: SqlitePool,
+}
+
+impl BackupRepositoryForSqlite {
+    pub fn new(pool: SqlitePool) -> Self {
+        BackupRepositoryForSqlite { pool }
+    }
+}
+
+#[async_trait]
+impl BackupRepository for BackupRepositoryForSqlite {
+    fn backend(&self) -> &'static str {
+        "sqlite"
+    }
+    fn backup(&self) -> BoxStream<'static, anyhow::Result<BackupPart>> {
+        backup_sql(self.pool.clone(), &SQLITE_QUERIES)
+    }
+    async fn restore(&self, backup: Backup, mode: RestoreMode) -> anyhow::Result<Restored> {
+        restore_sql(&self.pool, &SQLITE_RESTORE, backup, mode).await
+    }
+}
+
+/// InnoDB reads in a transaction from one snapshot at its default
+/// repeatable read isolation.
+#[cfg(feature = "mysql")]
+static MYSQL_QUERIES: BackupQueries = BackupQueries {
+    isolate: None,
+    labels: "select id, uuid, name from labels order by id",
+    todos: r#"
+        select id, uuid, text, completed, due, version,
+            cast(unix_timestamp(updated_at) as signed) as updated_at,
+            cast(null as signed) as archived_at
+        from todos order by id
+    "#,
+    todo_labels: "select todo_id, label_id from todo_labels order by todo_id, label_id",
+    archived: r#"
+        select id, uuid, text, completed, due, version,
+            cast(unix_timestamp(updated_at) as signed) as updated_at,
+            cast(unix_timestamp(archived_at) as signed) as archived_at
+        from archived_todos order by id
+    "#,
+};
+
+/// MySQL has no `returning`; fresh ids are read back through
+/// `last_insert_id()`, and explicit ones move AUTO_INCREMENT along.
+#[cfg(feature = "mysql")]
+static MYSQL_RESTORE: RestoreQueries = RestoreQueries {
+    wipe: WIPE,
+    label_named: "select id from labels where name = ?",
+    label_uuid_taken: "select count(*) from labels where uuid = ?",
+    todo_uuid_taken: r#"
+        select count(*) from (select uuid from todos union all select uuid from archived_todos) uuids
+        where uuid = ?
+    "#,
+    insert_label: "insert into labels (id, uuid, name) values (?, ?, ?)",
+    label_id: Some("select id from labels where id = last_insert_id()"),
+    insert_todo: r#"
+        insert into todos (id, uuid, text, completed, version, updated_at, due)
+        values (?, ?, ?, ?, ?, from_unixtime(?), ?)
+    "#,
+    todo_id: Some("select id from todos where id = last_insert_id()"),
+    insert_todo_label: "insert into todo_labels (todo_id, label_id) values (?, ?)",
+    insert_archived: r#"
+        insert into archived_todos (id, uuid, text, completed, version, updated_at, archived_at, due)
+        values (?, ?, ?, ?, ?, from_unixtime(?), from_unixtime(?), ?)
+    "#,
+    delete_todo: "delete from todos where id = ?",
+    resequence: &[],
+};
+
+#[cfg(feature = "mysql")]
+#[derive(Debug, Clone)]
+pub struct BackupRepositoryForMySql {
+    pool: sqlx::MySqlPool,
+}
+
+#[cfg(feature = "mysql")]
+impl BackupRepositoryForMySql {
+    pub fn new(pool: sqlx::MySqlPool) -> Self {
+        BackupRepositoryForMySql { pool }
+    }
+}
+
+#[cfg(feature = "mysql")]
+#[async_trait]
+impl BackupRepository for BackupRepositoryForMySql {
+    fn backend(&self) -> &'static str {
+        "mysql"
+    }
+    fn backup(&self) -> BoxStream<'static, anyhow::Result<BackupPart>> {
+        backup_sql(self.pool.clone(), &MYSQL_QUERIES)
+    }
+    async fn restore(&self, backup: Backup, mode: RestoreMode) -> anyhow::Result<Restored> {
+        restore_sql(&self.pool, &MYSQL_RESTORE, backup, mode).await
+    }
+}
+
+/// Backs up backends without transactions through their repositories, one
+/// read after the other: a change made meanwhile may be half in the backup.
+/// Restores go through them too, so every row gets a new id, archived todos
+/// come back as todos, and a failed restore leaves what it got to. Wrap the
+/// undecorated repositories, so texts are read and written as stored.
+#[derive(Debug, Clone)]
+pub struct BackupRepositoryFor<T, L> {
+    backend: &'static str,
+    todos: T,
+    labels: L,
+}
+
+impl<T: TodoRepository, L: LabelRepository> BackupRepositoryFor<T, L> {
+    pub fn new(backend: &'static str, todos: T, labels: L) -> Self {
+        Self {
+            backend,
+            todos,
+            labels,
+        }
+    }
+
+    async fn parts(&self) -> anyhow::Result<Vec<BackupPart>> {
+        let mut parts: Vec<_> = self
+            .labels
+            .all()
+            .await?
+            .into_iter()
+            .map(BackupPart::Label)
+            .collect();
+        let mut todos = self.todos.recently_modified(i64::MAX).await?;
+        todos.sort_by_key(|(todo, _)| todo.id());
+        parts.extend(todos.into_iter().map(|(todo, at)| {
+            BackupPart::Todo(BackupTodo {
+                id: todo.id(),
+                uuid: todo.uuid().cloned(),
+                text: todo.text().to_string(),
+                completed: todo.completed(),
+                due: todo.due().cloned(),
+                version: todo.version(),
+                updated_at: epoch_secs(at),
+                archived_at: None,
+            })
+        }));
+        let mut labelled = self.todos.all_with_labels(&TodoFilter::default()).await?;
+        labelled.sort_by_key(|todo| todo.todo.id());
+        for todo in labelled {
+            parts.extend(todo.labels.iter().map(|label| {
+                BackupPart::TodoLabel(TodoLabel {
+                    todo_id: todo.todo.id(),
+                    label_id: label.id,
+                })
+            }));
+        }
+
+        Ok(parts)
+    }
+
+    async fn wipe(&self) -> anyhow::Result<()> {
+        for todo in self.todos.all(&TodoFilter::default()).await? {
+            self.todos.delete(todo.id()).await?;
+        }
+        for label in self.labels.all().await? {
+            self.labels.delete(label.id).await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<T: TodoRepository, L: LabelRepository> BackupRepository for BackupRepositoryFor<T, L> {
+    fn backend(&self) -> &'static str {
+        self.backend
+    }
+    fn backup(&self) -> BoxStream<'static, anyhow::Result<BackupPart>> {
+        let this = self.clone();
+        futures_util::stream::once(async move { this.parts().await })
+            .flat_map(|parts| match parts {
+                Ok(parts) => futures_util::stream::iter(parts.into_iter().map(Ok)).boxed(),
+                Err(e) => futures_util::stream::iter([Err(e)]).boxed(),
+            })
+            .boxed()
+    }
+    async fn restore(&self, backup: Backup, mode: RestoreMode) -> anyhow::Result<Restored> {
+        if mode == RestoreMode::Wipe {
+            self.wipe().await?;
+        }
+        let mut restored = Restored::default();
+
+        let existing = self.labels.all().await?;
+        let named: HashMap<_, _> = existing
+            .iter()
+            .map(|label| (label.name.clone(), label.id))
+            .collect();
+        let taken: HashSet<_> = existing
+            .into_iter()
+            .filter_map(|label| label.uuid)
+            .collect();
+        let mut label_ids = HashMap::new();
+        for label in backup.labels {
+            let id = match named.get(&label.name) {
+                Some(&id) => id,
+                None => {
+                    let uuid = label.uuid.filter(|uuid| !taken.contains(uuid));
+                    restored.labels += 1;
+                    self.labels.create(label.name, uuid).await?.id
+                }
+            };
+            label_ids.insert(label.id, id);
+        }
+
+        let mut labels_of: HashMap<_, Vec<_>> = HashMap::new();
+        for todo_label in backup.todo_labels {
+            if let Some(&label_id) = label_ids.get(&todo_label.label_id) {
+                labels_of
+                    .entry(todo_label.todo_id)
+                    .or_default()
+                    .push(label_id);
+            }
+        }
+        let taken: HashSet<_> = self
+            .todos
+            .all(&TodoFilter::default())
+            .await?
+            .iter()
+            .filter_map(|todo| todo.uuid().cloned())
+            .collect();
+        for todo in backup.todos.into_iter().chain(backup.archived_todos) {
+            if todo.uuid.as_ref().is_some_and(|uuid| taken.contains(uuid)) {
+                continue;
+            }
+            let labels = labels_of.remove(&todo.id).unwrap_or_default();
+            restored.todo_labels += labels.len();
+            let payload = CreateTodo::new(todo.text)
+                .with_labels(labels)
+                .with_uuid(todo.uuid)
+                .with_due(todo.due);
+            let created = self.todos.create(payload).await?;
+            if todo.completed {
+                self.todos
+                    .update(created.id(), UpdateTodo::new(None, Some(true)))
+                    .await?;
+            }
+            match todo.archived_at {
+                Some(_) => restored.archived_todos += 1,
+                None => restored.todos += 1,
+            }
+        }
+
+        Ok(restored)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+
+    use dotenv::dotenv;
+    use futures_util::TryStreamExt;
+
+    use super::*;
+    use crate::repositories::{
+        label::LabelRepositoryForSqlite,
+        todo::{CreateTodo, TodoRepositoryForDb, TodoRepositoryForSqlite},
+    };
+
+    #[tokio::test]
+    async fn backs_up_postgres_in_one_transaction() {
+        dotenv().ok();
+        let database_url = &env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let pool = PgPool::connect(database_url)
+            .await
+            .expect("failed connect database");
+        let todos = TodoRepositoryForDb::new(pool.clone());
+        let todo = todos
+            .create(CreateTodo::new(
+                "[backs_up_postgres_in_one_transaction]".to_string(),
+            ))
+            .await
+            .unwrap();
+
+        let parts: Vec<_> = BackupRepositoryForDb::new(pool)
+            .backup()
+            .try_collect()
+            .await
+            .unwrap();
+        let backed_up = parts
+            .iter()
+            .find_map(|part| match part {
+                BackupPart::Todo(backed_up) if backed_up.id == todo.id() => Some(backed_up),
+                _ => None,
+            })
+            .expect("backed up");
+        assert_eq!(backed_up.text, todo.text());
+        assert!(backed_up.updated_at > 0);
+
+        todos.delete(todo.id()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn restores_postgres_by_merging() {
+        dotenv().ok();
+        let database_url = &env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let pool = PgPool::connect(database_url)
+            .await
+            .expect("failed connect database");
+        let archived_uuid = Uuid::now_v7();
+        let backup = Backup {
+            format: BACKUP_FORMAT,
+            labels: vec![Label {
+                id: 1,
+                uuid: None,
+                name: "[restores_postgres_by_merging]".to_string(),
+            }],
+            todos: vec![BackupTodo {
+                id: 1,
+                uuid: Some(Uuid::now_v7()),
+                text: "[restores_postgres_by_merging]".to_string(),
+                completed: true,
+                due: "2024-06-01".parse().ok(),
+                version: 3,
+                updated_at: 86400,
+                archived_at: None,
+            }],
+            todo_labels: vec![TodoLabel {
+                todo_id: 1,
+                label_id: 1,
+            }],
+            archived_todos: vec![BackupTodo {
+                id: 2,
+                uuid: Some(archived_uuid.clone()),
+                text: "[restores_postgres_by_merging] archived".to_string(),
+                completed: true,
+                due: None,
+                version: 1,
+                updated_at: 86400,
+                archived_at: Some(172800),
+            }],
+            ..Backup::default()
+        };
+        let repository = BackupRepositoryForDb::new(pool.clone());
+        let restored = repository
+            .restore(backup.clone(), RestoreMode::Merge)
+            .await
+            .unwrap();
+        assert_eq!(restored.todos, 1);
+        assert_eq!(restored.archived_todos, 1);
+        // both todos are there already, and the label is reused
+        let restored = repository
+            .restore(backup, RestoreMode::Merge)
+            .await
+            .unwrap();
+        assert_eq!(restored, Restored::default());
+
+        let labelled = TodoRepositoryForDb::new(pool.clone())
+            .all_with_labels(&TodoFilter {
+                labels: vec!["[restores_postgres_by_merging]".to_string()],
+                ..TodoFilter::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(labelled.len(), 1);
+        assert!(labelled
+            .iter()
+            .all(|todo| todo.todo.version() == 3 && todo.todo.completed()));
+        assert!(labelled
+            .iter()
+            .all(|todo| todo.todo.due().map(Date::as_str) == Some("2024-06-01")));
+
+        for todo in labelled {
+            sqlx::query("delete from todo_labels where todo_id = $1")
+                .bind(todo.todo.id())
+                .execute(&pool)
+                .await
+                .unwrap();
+            sqlx::query("delete from todos where id = $1")
+                .bind(todo.todo.id())
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        sqlx::query("delete from archived_todos where uuid = $1")
+            .bind(archived_uuid)
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("delete from labels where name = '[restores_postgres_by_merging]'")
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    async fn sqlite() -> SqlitePool {
+        crate::repositories::connect_sqlite(
+            "sqlite::memory:",
+            &crate::repositories::PoolSettings::default(),
+            crate::repositories::Migrations::Apply,
+        )
+        .await
+        .expect("failed open sqlite")
+    }
+
+    async fn snapshot<B: BackupRepository>(repository: &B) -> Backup {
+        let parts: Vec<_> = repository.backup().try_collect().await.unwrap();
+        let mut backup = Backup {
+            format: BACKUP_FORMAT,
+            ..Backup::default()
+        };
+        for part in parts {
+            match part {
+                BackupPart::Label(label) => backup.labels.push(label),
+                BackupPart::Todo(todo) => backup.todos.push(todo),
+                BackupPart::TodoLabel(todo_label) => backup.todo_labels.push(todo_label),
+                BackupPart::Archived(todo) => backup.archived_todos.push(todo),
+            }
+        }
+        backup
+    }
+
+    #[tokio::test]
+    async fn backs_up_sqlite_in_id_order() {
+        let pool = sqlite().await;
+        let todos = TodoRepositoryForSqlite::new(pool.clone());
+        let labels = LabelRepositoryForSqlite::new(pool.clone());
+        let label = labels.create("work".to_string(), None).await.unwrap();
+        let first = todos
+            .create(CreateTodo::new("first".to_string()).with_labels(vec![label.id]))
+            .await
+            .unwrap();
+        let second = todos
+            .create(CreateTodo::new("second".to_string()))
+            .await
+            .unwrap();
+
+        let parts: Vec<_> = BackupRepositoryForSqlite::new(pool)
+            .backup()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(parts.len(), 4);
+        assert_eq!(parts[0], BackupPart::Label(label.clone()));
+        assert!(matches!(&parts[1], BackupPart::Todo(todo) if todo.id == first.id()));
+        assert!(matches!(&parts[2], BackupPart::Todo(todo) if todo.id == second.id()));
+        assert_eq!(
+            parts[3],
+            BackupPart::TodoLabel(TodoLabel {
+                todo_id: first.id(),
+                label_id: label.id,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn restores_sqlite_by_wiping_or_merging() {
+        let pool = sqlite().await;
+        let todos = TodoRepositoryForSqlite::new(pool.clone());
+        let labels = LabelRepositoryForSqlite::new(pool.clone());
+        let label = labels.create("work".to_string(), None).await.unwrap();
+        let labelled = todos
+            .create(
+                CreateTodo::new("labelled".to_string())
+                    .with_uuid(Some(Uuid::now_v7()))
+                    .with_labels(vec![label.id]),
+            )
+            .await
+            .unwrap();
+        let done = todos
+            .create(CreateTodo::new("done".to_string()).with_uuid(Some(Uuid::now_v7())))
+            .await
+            .unwrap();
+        todos
+            .update(done.id(), UpdateTodo::new(None, Some(true)))
+            .await
+            .unwrap();
+        sqlx::query("update todos set updated_at = 0 where id = ?1")
+            .bind(done.id())
+            .execute(&pool)
+            .await
+            .unwrap();
+        todos
+            .archive_completed(std::time::Duration::ZERO)
+            .await
+            .unwrap();
+        let backup = snapshot(&BackupRepositoryForSqlite::new(pool.clone())).await;
+        backup.validate().unwrap();
+
+        let restored_pool = sqlite().await;
+        let restored_todos = TodoRepositoryForSqlite::new(restored_pool.clone());
+        restored_todos
+            .create(CreateTodo::new("wiped".to_string()))
+            .await
+            .unwrap();
+        let repository = BackupRepositoryForSqlite::new(restored_pool);
+        let restored = repository
+            .restore(backup.clone(), RestoreMode::Wipe)
+            .await
+            .unwrap();
+        assert_eq!(
+            restored,
+            Restored {
+                labels: 1,
+                todos: 1,
+                todo_labels: 1,
+                archived_todos: 1,
+            }
+        );
+        assert_eq!(snapshot(&repository).await, backup);
+        // new todos don't take the archived todo's id
+        let next = restored_todos
+            .create(CreateTodo::new("next".to_string()))
+            .await
+            .unwrap();
+        assert!(next.id() > done.id());
+
+        // every uuid is already there, so merging it back adds nothing
+        backup.check_mergeable().unwrap();
+        let repository = BackupRepositoryForSqlite::new(pool);
+        let restored = repository
+            .restore(backup.clone(), RestoreMode::Merge)
+            .await
+            .unwrap();
+        assert_eq!(restored, Restored::default());
+        let merged = todos.all_with_labels(&TodoFilter::default()).await.unwrap();
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].todo.id(), labelled.id());
+        assert_eq!(merged[0].labels, vec![label.clone()]);
+
+        // a new todo in the backup is added once, however often it is merged
+        let mut newer = backup.clone();
+        let mut added = newer.todos[0].clone();
+        added.id = 100;
+        added.uuid = Some(Uuid::now_v7());
+        added.text = "added".to_string();
+        newer.todos.push(added);
+        for _ in 0..2 {
+            repository
+                .restore(newer.clone(), RestoreMode::Merge)
+                .await
+                .unwrap();
+        }
+        let merged = todos.all(&TodoFilter::default()).await.unwrap();
+        assert_eq!(merged.len(), 2);
+    }
+
+    #[test]
+    fn merges_only_backups_with_uuids() {
+        let todo = BackupTodo {
+            id: 1,
+            uuid: Some(Uuid::now_v7()),
+            text: "milk".to_string(),
+            completed: false,
+            due: None,
+            version: 1,
+            updated_at: 0,
+            archived_at: None,
+        };
+        let mut backup = Backup {
+            format: BACKUP_FORMAT,
+            todos: vec![todo.clone()],
+            ..Backup::default()
+        };
+        assert!(backup.check_mergeable().is_ok());
+
+        backup
+            .archived_todos
+            .push(BackupTodo { uuid: None, ..todo });
+        let errors = backup.check_mergeable().unwrap_err();
+        assert!(errors.field_errors().contains_key("archived_todos"));
+    }
+
+    #[test]
+    fn refuses_backups_that_dont_hang_together() {
+        let mut backup = Backup {
+            format: BACKUP_FORMAT,
+            todo_labels: vec![TodoLabel {
+                todo_id: 1,
+                label_id: 1,
+            }],
+            ..Backup::default()
+        };
+        let errors = backup.validate().unwrap_err();
+        assert!(errors.field_errors().contains_key("todo_labels"));
+
+        backup.todo_labels.clear();
+        backup.format = BACKUP_FORMAT + 1;
+        let errors = backup.validate().unwrap_err();
+        assert!(errors.field_errors().contains_key("format"));
+    }
+}
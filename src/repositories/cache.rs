@@ -0,0 +1,473 @@
+use std::{
+    env,
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+
+use axum::async_trait;
+use futures_util::stream::BoxStream;
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{
+    backup::{Backup, BackupPart, BackupRepository, RestoreMode, Restored},
+    env_or,
+    id::{Key, Uuid},
+    label::{Label, LabelRepository},
+    todo::{
+        CreateTodo, Import, ImportedRows, SearchHit, Todo, TodoFilter, TodoRepository,
+        TodoWithLabels, UpdateTodo,
+    },
+};
+
+/// Prefix of every key the cache writes, so it can share a Redis database.
+const PREFIX: &str = "my-todo:";
+const TODOS: &str = "todos";
+const LABELS: &str = "labels";
+
+/// Where cached values live. Values expire on their own after `ttl`; a
+/// namespace's generation counter is bumped instead of deleting its keys.
+#[async_trait]
+pub trait CacheStore: std::fmt::Debug + Send + Sync + 'static {
+    async fn get(&self, key: &str) -> anyhow::Result<Option<String>>;
+    async fn set(&self, key: &str, value: String, ttl: Duration) -> anyhow::Result<()>;
+    async fn incr(&self, key: &str) -> anyhow::Result<i64>;
+}
+
+#[derive(Clone)]
+struct RedisStore(redis::aio::ConnectionManager);
+
+impl std::fmt::Debug for RedisStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("RedisStore")
+    }
+}
+
+#[async_trait]
+impl CacheStore for RedisStore {
+    async fn get(&self, key: &str) -> anyhow::Result<Option<String>> {
+        use redis::AsyncCommands;
+        Ok(self.0.clone().get(key).await?)
+    }
+    async fn set(&self, key: &str, value: String, ttl: Duration) -> anyhow::Result<()> {
+        use redis::AsyncCommands;
+        let secs = ttl.as_secs().max(1) as usize;
+        Ok(self.0.clone().set_ex(key, value, secs).await?)
+    }
+    async fn incr(&self, key: &str) -> anyhow::Result<i64> {
+        use redis::AsyncCommands;
+        Ok(self.0.clone().incr(key, 1).await?)
+    }
+}
+
+/// Lookup counts since startup, shared by every [`Caching`] repository.
+#[derive(Debug, Default)]
+pub struct CacheMetrics {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    errors: AtomicU64,
+}
+
+/// A [`CacheMetrics`] reading, as `GET /cache/stats` answers it.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Store calls that failed; the lookup went to the database instead.
+    pub errors: u64,
+    /// `hits / (hits + misses)`, `0` before the first lookup.
+    pub hit_rate: f64,
+}
+
+impl CacheMetrics {
+    pub fn stats(&self) -> CacheStats {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        let lookups = hits + misses;
+        CacheStats {
+            hits,
+            misses,
+            errors: self.errors.load(Ordering::Relaxed),
+            hit_rate: if lookups == 0 {
+                0.0
+            } else {
+                hits as f64 / lookups as f64
+            },
+        }
+    }
+}
+
+/// A read-through cache for todo and label reads.
+#[derive(Debug, Clone)]
+pub struct Cache {
+    store: Arc<dyn CacheStore>,
+    todo_ttl: Duration,
+    label_ttl: Duration,
+    metrics: Arc<CacheMetrics>,
+}
+
+impl Cache {
+    pub fn new(store: Arc<dyn CacheStore>, todo_ttl: Duration, label_ttl: Duration) -> Self {
+        Self {
+            store,
+            todo_ttl,
+            label_ttl,
+            metrics: Arc::default(),
+        }
+    }
+
+    /// Caches in Redis at `REDIS_URL`, keeping todos for `CACHE_TODO_TTL_SECS`
+    /// (30 by default) and labels for `CACHE_LABEL_TTL_SECS` (300). `None`
+    /// without `REDIS_URL`.
+    pub async fn from_env() -> Option<Self> {
+        let url = env::var("REDIS_URL").ok()?;
+        let client = redis::Client::open(url.as_str())
+            .unwrap_or_else(|e| panic!("invalid [REDIS_URL]: {}, {}", url, e));
+        let manager = redis::aio::ConnectionManager::new(client)
+            .await
+            .unwrap_or_else(|e| panic!("fail connect redis, url is [{}]: {}", url, e));
+        Some(Self::new(
+            Arc::new(RedisStore(manager)),
+            Duration::from_secs(env_or("CACHE_TODO_TTL_SECS", 30)),
+            Duration::from_secs(env_or("CACHE_LABEL_TTL_SECS", 300)),
+        ))
+    }
+
+    pub fn metrics(&self) -> Arc<CacheMetrics> {
+        self.metrics.clone()
+    }
+
+    fn failed(&self, e: anyhow::Error) {
+        self.metrics.errors.fetch_add(1, Ordering::Relaxed);
+        tracing::warn!("cache unavailable: {:#}", e);
+    }
+
+    /// The cached value for `key` in `namespace`, or what `load` returns,
+    /// which is then cached for `ttl`. Errors of `load` aren't cached, and
+    /// a failing store only costs the trip to the database.
+    async fn read<T, F, Fut>(
+        &self,
+        namespace: &str,
+        key: String,
+        ttl: Duration,
+        load: F,
+    ) -> anyhow::Result<T>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = anyhow::Result<T>>,
+    {
+        let generation = match self.store.get(&generation_key(namespace)).await {
+            Ok(generation) => generation.unwrap_or_default(),
+            Err(e) => {
+                self.failed(e);
+                return load().await;
+            }
+        };
+        let key = format!("{}{}:{}:{}", PREFIX, namespace, generation, key);
+        match self.store.get(&key).await {
+            Ok(Some(json)) => match serde_json::from_str(&json) {
+                Ok(value) => {
+                    self.metrics.hits.fetch_add(1, Ordering::Relaxed);
+                    return Ok(value);
+                }
+                // written by another version of the server, most likely
+                Err(e) => tracing::debug!("ignoring cached [{}]: {}", key, e),
+            },
+            Ok(None) => {}
+            Err(e) => {
+                self.failed(e);
+                return load().await;
+            }
+        }
+
+        self.metrics.misses.fetch_add(1, Ordering::Relaxed);
+        let value = load().await?;
+        match serde_json::to_string(&value) {
+            Ok(json) => {
+                if let Err(e) = self.store.set(&key, json, ttl).await {
+                    self.failed(e);
+                }
+            }
+            Err(e) => tracing::warn!("fail cache [{}]: {}", key, e),
+        }
+        Ok(value)
+    }
+
+    /// Drops everything cached in `namespaces`. Should the store be down,
+    /// stale values are served until their ttl runs out.
+    async fn invalidate(&self, namespaces: &[&str]) {
+        for namespace in namespaces {
+            if let Err(e) = self.store.incr(&generation_key(namespace)).await {
+                self.failed(e);
+            }
+        }
+    }
+}
+
+fn generation_key(namespace: &str) -> String {
+    format!("{}{}:generation", PREFIX, namespace)
+}
+
+/// A repository whose `find`/`all` (todos) and `all` (labels) answers are
+/// cached. Any change made through it invalidates the affected namespace
+/// for every server sharing the cache; changes made behind its back show
+/// up once the ttl runs out. Without a [`Cache`] every call goes straight
+/// through.
+#[derive(Debug, Clone)]
+pub struct Caching<R> {
+    inner: R,
+    cache: Option<Cache>,
+}
+
+impl<R> Caching<R> {
+    pub fn new(inner: R, cache: Option<Cache>) -> Self {
+        Self { inner, cache }
+    }
+
+    async fn invalidating<T>(
+        &self,
+        result: anyhow::Result<T>,
+        namespaces: &[&str],
+    ) -> anyhow::Result<T> {
+        if let (Ok(_), Some(cache)) = (&result, &self.cache) {
+            cache.invalidate(namespaces).await;
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl<R: TodoRepository> TodoRepository for Caching<R> {
+    async fn create(&self, payload: CreateTodo) -> anyhow::Result<Todo> {
+        let result = self.inner.create(payload).await;
+        self.invalidating(result, &[TODOS]).await
+    }
+    async fn create_many(&self, payloads: Vec<CreateTodo>) -> anyhow::Result<Vec<Todo>> {
+        let result = self.inner.create_many(payloads).await;
+        self.invalidating(result, &[TODOS]).await
+    }
+    async fn import<L: LabelRepository>(
+        &self,
+        labels: &L,
+        import: Import,
+    ) -> anyhow::Result<ImportedRows> {
+        let result = self.inner.import(labels, import).await;
+        self.invalidating(result, &[LABELS, TODOS]).await
+    }
+    async fn find(&self, id: i64) -> anyhow::Result<Todo> {
+        match &self.cache {
+            Some(cache) => {
+                let key = format!("find:{}", id);
+                cache
+                    .read(TODOS, key, cache.todo_ttl, || self.inner.find(id))
+                    .await
+            }
+            None => self.inner.find(id).await,
+        }
+    }
+    async fn all(&self, filter: &TodoFilter) -> anyhow::Result<Vec<Todo>> {
+        match &self.cache {
+            Some(cache) => {
+                let key = format!("all:{:?}", filter);
+                cache
+                    .read(TODOS, key, cache.todo_ttl, || self.inner.all(filter))
+                    .await
+            }
+            None => self.inner.all(filter).await,
+        }
+    }
+    async fn count(&self, filter: &TodoFilter) -> anyhow::Result<i64> {
+        self.inner.count(filter).await
+    }
+    fn stream_all(&self) -> BoxStream<'static, anyhow::Result<Todo>> {
+        self.inner.stream_all()
+    }
+    async fn find_with_labels(&self, id: i64) -> anyhow::Result<TodoWithLabels> {
+        self.inner.find_with_labels(id).await
+    }
+    async fn all_with_labels(&self, filter: &TodoFilter) -> anyhow::Result<Vec<TodoWithLabels>> {
+        self.inner.all_with_labels(filter).await
+    }
+    async fn update(&self, id: i64, payload: UpdateTodo) -> anyhow::Result<Todo> {
+        let result = self.inner.update(id, payload).await;
+        self.invalidating(result, &[TODOS]).await
+    }
+    async fn delete(&self, id: i64) -> anyhow::Result<()> {
+        let result = self.inner.delete(id).await;
+        self.invalidating(result, &[TODOS]).await
+    }
+    async fn purge_completed(&self) -> anyhow::Result<u64> {
+        let result = self.inner.purge_completed().await;
+        self.invalidating(result, &[TODOS]).await
+    }
+    async fn archive_completed(&self, older_than: Duration) -> anyhow::Result<u64> {
+        let result = self.inner.archive_completed(older_than).await;
+        self.invalidating(result, &[TODOS]).await
+    }
+    async fn archived(&self) -> anyhow::Result<Vec<Todo>> {
+        self.inner.archived().await
+    }
+    async fn last_modified(&self, id: i64) -> anyhow::Result<SystemTime> {
+        self.inner.last_modified(id).await
+    }
+    async fn collection_last_modified(&self) -> anyhow::Result<SystemTime> {
+        self.inner.collection_last_modified().await
+    }
+    async fn recently_modified(&self, limit: i64) -> anyhow::Result<Vec<(Todo, SystemTime)>> {
+        self.inner.recently_modified(limit).await
+    }
+    async fn resolve(&self, key: &Key) -> anyhow::Result<i64> {
+        self.inner.resolve(key).await
+    }
+    async fn search(&self, filter: &TodoFilter, limit: i64) -> anyhow::Result<Vec<SearchHit>> {
+        self.inner.search(filter, limit).await
+    }
+}
+
+#[async_trait]
+impl<R: LabelRepository> LabelRepository for Caching<R> {
+    async fn create(&self, name: String, uuid: Option<Uuid>) -> anyhow::Result<Label> {
+        let result = self.inner.create(name, uuid).await;
+        self.invalidating(result, &[LABELS]).await
+    }
+    async fn all(&self) -> anyhow::Result<Vec<Label>> {
+        match &self.cache {
+            Some(cache) => {
+                cache
+                    .read(LABELS, "all".to_string(), cache.label_ttl, || {
+                        self.inner.all()
+                    })
+                    .await
+            }
+            None => self.inner.all().await,
+        }
+    }
+    async fn delete(&self, id: i64) -> anyhow::Result<()> {
+        // Deleting a label also takes it off todos, which label filters see.
+        let result = self.inner.delete(id).await;
+        self.invalidating(result, &[LABELS, TODOS]).await
+    }
+    async fn resolve(&self, key: &Key) -> anyhow::Result<i64> {
+        self.inner.resolve(key).await
+    }
+}
+
+/// Backups read past the cache; a restore invalidates everything it wrote.
+#[async_trait]
+impl<R: BackupRepository> BackupRepository for Caching<R> {
+    fn backend(&self) -> &'static str {
+        self.inner.backend()
+    }
+    fn backup(&self) -> BoxStream<'static, anyhow::Result<BackupPart>> {
+        self.inner.backup()
+    }
+    async fn restore(&self, backup: Backup, mode: RestoreMode) -> anyhow::Result<Restored> {
+        let result = self.inner.restore(backup, mode).await;
+        self.invalidating(result, &[LABELS, TODOS]).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, sync::Mutex};
+
+    use super::*;
+    use crate::repositories::todo::TodoRepositoryForMemory;
+
+    /// A store without expiry; `down` makes every call fail.
+    #[derive(Debug, Default)]
+    struct MemoryStore {
+        values: Mutex<HashMap<String, String>>,
+        down: std::sync::atomic::AtomicBool,
+    }
+
+    impl MemoryStore {
+        fn check(&self) -> anyhow::Result<()> {
+            if self.down.load(Ordering::Relaxed) {
+                anyhow::bail!("connection refused");
+            }
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl CacheStore for MemoryStore {
+        async fn get(&self, key: &str) -> anyhow::Result<Option<String>> {
+            self.check()?;
+            Ok(self.values.lock().unwrap().get(key).cloned())
+        }
+        async fn set(&self, key: &str, value: String, _ttl: Duration) -> anyhow::Result<()> {
+            self.check()?;
+            self.values.lock().unwrap().insert(key.to_string(), value);
+            Ok(())
+        }
+        async fn incr(&self, key: &str) -> anyhow::Result<i64> {
+            self.check()?;
+            let mut values = self.values.lock().unwrap();
+            let generation = values.get(key).map_or(0, |value| value.parse().unwrap()) + 1;
+            values.insert(key.to_string(), generation.to_string());
+            Ok(generation)
+        }
+    }
+
+    fn caching(store: Arc<MemoryStore>) -> (Caching<TodoRepositoryForMemory>, Arc<CacheMetrics>) {
+        let cache = Cache::new(store, Duration::from_secs(30), Duration::from_secs(30));
+        let metrics = cache.metrics();
+        (
+            Caching::new(TodoRepositoryForMemory::new(), Some(cache)),
+            metrics,
+        )
+    }
+
+    #[tokio::test]
+    async fn caches_reads_until_a_change() {
+        let (repository, metrics) = caching(Arc::default());
+        let todo = repository
+            .create(CreateTodo::new("cached".to_string()))
+            .await
+            .unwrap();
+
+        assert_eq!(repository.find(todo.id()).await.unwrap(), todo);
+        assert_eq!(repository.find(todo.id()).await.unwrap(), todo);
+        let all = repository.all(&TodoFilter::default()).await.unwrap();
+        assert_eq!(all, vec![todo.clone()]);
+        assert_eq!((metrics.stats().hits, metrics.stats().misses), (1, 2));
+
+        let updated = repository
+            .update(todo.id(), UpdateTodo::new(None, Some(true)))
+            .await
+            .unwrap();
+        assert_eq!(repository.find(todo.id()).await.unwrap(), updated);
+        repository.delete(todo.id()).await.unwrap();
+        assert!(repository.find(todo.id()).await.is_err());
+        assert!(repository
+            .all(&TodoFilter::default())
+            .await
+            .unwrap()
+            .is_empty());
+
+        let stats = metrics.stats();
+        assert_eq!((stats.hits, stats.misses, stats.errors), (1, 5, 0));
+        assert_eq!(stats.hit_rate, 1.0 / 6.0);
+    }
+
+    #[tokio::test]
+    async fn reads_through_a_failing_store() {
+        let store = Arc::new(MemoryStore::default());
+        let (repository, metrics) = caching(store.clone());
+        let todo = repository
+            .create(CreateTodo::new("uncached".to_string()))
+            .await
+            .unwrap();
+        store.down.store(true, Ordering::Relaxed);
+
+        assert_eq!(repository.find(todo.id()).await.unwrap(), todo);
+        let stats = metrics.stats();
+        assert_eq!((stats.hits, stats.misses), (0, 0));
+        assert_eq!(stats.errors, 1);
+    }
+}
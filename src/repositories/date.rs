@@ -0,0 +1,161 @@
+use std::{
+    fmt,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// A calendar day as `YYYY-MM-DD`, stored as text in every backend so no
+/// driver needs a date type. Todos are due on a day, not at an instant.
+#[derive(
+    Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, sqlx::Type,
+)]
+#[serde(try_from = "String", into = "String")]
+#[sqlx(transparent)]
+pub struct Date(String);
+
+impl Date {
+    /// The day `days` after 1970-01-01.
+    pub fn from_days(days: i64) -> Self {
+        let (year, month, day) = civil_from_days(days);
+        Date(format!("{:04}-{:02}-{:02}", year, month, day))
+    }
+
+    /// The current day in UTC.
+    pub fn today() -> Self {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64);
+        Self::from_days(secs.div_euclid(86_400))
+    }
+
+    /// Days since 1970-01-01, negative before it.
+    pub fn days(&self) -> i64 {
+        let (year, month, day) = self.parts();
+        days_from_civil(year, month, day)
+    }
+
+    /// The iCalendar `DATE` form, `YYYYMMDD`.
+    pub fn basic(&self) -> String {
+        self.0.replace('-', "")
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    fn parts(&self) -> (i64, u32, u32) {
+        // well formed since `from_str` or `from_days` made it
+        (
+            self.0[..4].parse().unwrap_or_default(),
+            self.0[5..7].parse().unwrap_or_default(),
+            self.0[8..].parse().unwrap_or_default(),
+        )
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("invalid date: [{0}], expected YYYY-MM-DD")]
+pub struct InvalidDate(String);
+
+impl FromStr for Date {
+    type Err = InvalidDate;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let well_formed = s.len() == 10
+            && s.bytes().enumerate().all(|(i, b)| match i {
+                4 | 7 => b == b'-',
+                _ => b.is_ascii_digit(),
+            });
+        if !well_formed {
+            return Err(InvalidDate(s.to_string()));
+        }
+        // a day past the end of its month comes back as another date
+        let date = Date(s.to_string());
+        if Date::from_days(date.days()) != date {
+            return Err(InvalidDate(s.to_string()));
+        }
+        Ok(date)
+    }
+}
+
+impl TryFrom<String> for Date {
+    type Error = InvalidDate;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Date> for String {
+    fn from(date: Date) -> Self {
+        date.0
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Gregorian date of a day count since 1970-01-01 (Howard Hinnant's
+/// `civil_from_days`).
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// The inverse of [`civil_from_days`] (Hinnant's `days_from_civil`).
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = i64::from(if month > 2 { month - 3 } else { month + 9 });
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn counts_days_both_ways() {
+        assert_eq!(Date::from_days(0).as_str(), "1970-01-01");
+        assert_eq!(Date::from_days(19_875).as_str(), "2024-06-01");
+        assert_eq!(Date::from_days(-1).as_str(), "1969-12-31");
+        let leap: Date = "2024-02-29".parse().unwrap();
+        assert_eq!(Date::from_days(leap.days() + 1).as_str(), "2024-03-01");
+        assert_eq!(leap.basic(), "20240229");
+    }
+
+    #[test]
+    fn refuses_malformed_and_impossible_days() {
+        for raw in [
+            "2024-6-01",
+            "2024/06/01",
+            "2023-02-29",
+            "2024-13-01",
+            "2024-04-31",
+            "",
+        ] {
+            assert!(raw.parse::<Date>().is_err(), "{}", raw);
+        }
+        assert!(serde_json::from_str::<Date>(r#""2024-06-31""#).is_err());
+        assert_eq!(
+            serde_json::from_str::<Date>(r#""2024-06-30""#).unwrap(),
+            Date::from_days(19_904)
+        );
+    }
+}
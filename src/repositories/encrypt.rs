@@ -0,0 +1,477 @@
+use std::{
+    collections::HashMap,
+    env,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, Context};
+use axum::async_trait;
+use futures_util::{stream::BoxStream, StreamExt};
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    rand::{SecureRandom, SystemRandom},
+};
+
+use super::{
+    id::Key,
+    label::LabelRepository,
+    todo::{
+        CreateTodo, Import, ImportedRows, SearchHit, Todo, TodoFilter, TodoRepository,
+        TodoWithLabels, UpdateTodo,
+    },
+    RepositoryError,
+};
+
+/// Marks an encrypted text: `enc:<key id>:<base64 of nonce, ciphertext and tag>`.
+const SEALED: &str = "enc:";
+
+/// AES-256-GCM keys by id. The current key encrypts; every key, retired
+/// ones included, decrypts what it encrypted.
+#[derive(Debug, Clone)]
+pub struct Encryption {
+    current: String,
+    keys: Arc<HashMap<String, LessSafeKey>>,
+    rng: SystemRandom,
+}
+
+impl Encryption {
+    /// `keys` are `(id, 32 byte key)` pairs, the current one first.
+    pub fn new(keys: Vec<(String, Vec<u8>)>) -> anyhow::Result<Self> {
+        let current = match keys.first() {
+            Some((id, _)) => id.clone(),
+            None => anyhow::bail!("no keys"),
+        };
+        let mut by_id = HashMap::new();
+        for (id, bytes) in keys {
+            if id.is_empty() || id.contains(':') {
+                anyhow::bail!("key id [{}] must be non-empty and without ':'", id);
+            }
+            let key = UnboundKey::new(&AES_256_GCM, &bytes)
+                .map_err(|_| anyhow!("key [{}] is not 32 bytes", id))?;
+            if by_id.insert(id.clone(), LessSafeKey::new(key)).is_some() {
+                anyhow::bail!("key id [{}] is used twice", id);
+            }
+        }
+        Ok(Self {
+            current,
+            keys: Arc::new(by_id),
+            rng: SystemRandom::new(),
+        })
+    }
+
+    /// Keys from `ENCRYPTION_KEYS`, comma separated `id:base64` pairs with
+    /// the current key first; to rotate, prepend a new key and keep the old
+    /// ones until [`Encrypting::reencrypt`] has run. `None` when unset.
+    pub fn from_env() -> Option<Self> {
+        let value = env::var("ENCRYPTION_KEYS").ok()?;
+        let keys = value
+            .split(',')
+            .map(|pair| {
+                // never echo the key itself
+                let (id, key) = pair
+                    .trim()
+                    .split_once(':')
+                    .unwrap_or_else(|| panic!("invalid [ENCRYPTION_KEYS]: expected id:base64"));
+                let bytes = base64::decode(key).unwrap_or_else(|_| {
+                    panic!("invalid [ENCRYPTION_KEYS]: key [{}] is not base64", id)
+                });
+                (id.to_string(), bytes)
+            })
+            .collect();
+        Some(Self::new(keys).unwrap_or_else(|e| panic!("invalid [ENCRYPTION_KEYS]: {}", e)))
+    }
+
+    fn seal(&self, text: &str) -> anyhow::Result<String> {
+        let key = &self.keys[&self.current];
+        let mut nonce = [0; NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_| anyhow!("no randomness for a nonce"))?;
+        let mut sealed = text.as_bytes().to_vec();
+        key.seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(self.current.as_bytes()),
+            &mut sealed,
+        )
+        .map_err(|_| anyhow!("fail encrypt"))?;
+        let mut bytes = nonce.to_vec();
+        bytes.extend(sealed);
+        Ok(format!(
+            "{}{}:{}",
+            SEALED,
+            self.current,
+            base64::encode(bytes)
+        ))
+    }
+
+    /// The plain text of `stored`. Texts written before encryption was
+    /// switched on are returned as they are.
+    fn open(&self, stored: &str) -> anyhow::Result<String> {
+        let (id, encoded) = match stored.strip_prefix(SEALED).and_then(|s| s.split_once(':')) {
+            Some(parts) => parts,
+            None => return Ok(stored.to_string()),
+        };
+        let key = self
+            .keys
+            .get(id)
+            .with_context(|| format!("unknown encryption key [{}]", id))?;
+        let mut bytes = base64::decode(encoded).context("malformed encrypted text")?;
+        if bytes.len() < NONCE_LEN {
+            anyhow::bail!("malformed encrypted text");
+        }
+        let mut sealed = bytes.split_off(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(&bytes).expect("checked length");
+        let text = key
+            .open_in_place(nonce, Aad::from(id.as_bytes()), &mut sealed)
+            .map_err(|_| anyhow!("fail decrypt with key [{}]", id))?;
+        Ok(String::from_utf8(text.to_vec())?)
+    }
+
+    fn is_current(&self, stored: &str) -> bool {
+        stored
+            .strip_prefix(SEALED)
+            .and_then(|s| s.split_once(':'))
+            .is_some_and(|(id, _)| id == self.current)
+    }
+}
+
+/// A repository that stores todo texts encrypted, so a copy of the
+/// database alone doesn't reveal them. Label names stay readable.
+///
+/// The database can no longer match text terms, so filters and searches
+/// with terms read every todo the rest of the filter matches and apply the
+/// terms here. Without an [`Encryption`] every call goes straight through.
+#[derive(Debug, Clone)]
+pub struct Encrypting<R> {
+    inner: R,
+    encryption: Option<Encryption>,
+}
+
+impl<R> Encrypting<R> {
+    pub fn new(inner: R, encryption: Option<Encryption>) -> Self {
+        Self { inner, encryption }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.encryption.is_some()
+    }
+}
+
+fn open_todo(encryption: &Encryption, mut todo: Todo) -> anyhow::Result<Todo> {
+    let text = encryption
+        .open(todo.text())
+        .with_context(|| format!("todo {}", todo.id()))?;
+    *todo.text_mut() = text;
+    Ok(todo)
+}
+
+fn seal_create(encryption: &Encryption, mut payload: CreateTodo) -> anyhow::Result<CreateTodo> {
+    let text = encryption.seal(payload.text_mut())?;
+    *payload.text_mut() = text;
+    Ok(payload)
+}
+
+/// `filter` itself, and whether its text terms were taken out to be
+/// applied after decrypting.
+fn without_terms(filter: &TodoFilter) -> (TodoFilter, bool) {
+    let terms = !filter.text.is_empty();
+    let filter = TodoFilter {
+        text: vec![],
+        ..filter.clone()
+    };
+    (filter, terms)
+}
+
+fn matches_terms(todo: &Todo, terms: &[String]) -> bool {
+    let text = todo.text().to_lowercase();
+    terms.iter().all(|term| text.contains(&term.to_lowercase()))
+}
+
+impl<R: TodoRepository> Encrypting<R> {
+    /// Encrypts with the current key every text still in plain or under a
+    /// retired key, and returns how many were rewritten. Each rewrite
+    /// counts as an update; todos changed meanwhile are left to that change.
+    pub async fn reencrypt(&self) -> anyhow::Result<u64> {
+        let encryption = match &self.encryption {
+            Some(encryption) => encryption,
+            None => return Ok(0),
+        };
+        // collected first, so no read is left open while rewriting
+        let mut stale = Vec::new();
+        let mut todos = self.inner.stream_all();
+        while let Some(todo) = todos.next().await {
+            let todo = todo?;
+            if !encryption.is_current(todo.text()) {
+                stale.push(todo);
+            }
+        }
+        drop(todos);
+        let mut count = 0;
+        for todo in stale {
+            let (id, version) = (todo.id(), todo.version());
+            let text = encryption.seal(open_todo(encryption, todo)?.text())?;
+            let payload = UpdateTodo::new(Some(text), None).with_version(version);
+            match self.inner.update(id, payload).await {
+                Ok(_) => count += 1,
+                Err(e) => match e.downcast_ref::<RepositoryError>() {
+                    Some(RepositoryError::StaleVersion(_) | RepositoryError::NotFound(_)) => {}
+                    _ => return Err(e),
+                },
+            }
+        }
+        Ok(count)
+    }
+}
+
+#[async_trait]
+impl<R: TodoRepository> TodoRepository for Encrypting<R> {
+    async fn create(&self, payload: CreateTodo) -> anyhow::Result<Todo> {
+        match &self.encryption {
+            Some(encryption) => {
+                let todo = self.inner.create(seal_create(encryption, payload)?).await?;
+                open_todo(encryption, todo)
+            }
+            None => self.inner.create(payload).await,
+        }
+    }
+    async fn create_many(&self, payloads: Vec<CreateTodo>) -> anyhow::Result<Vec<Todo>> {
+        match &self.encryption {
+            Some(encryption) => {
+                let payloads = payloads
+                    .into_iter()
+                    .map(|payload| seal_create(encryption, payload))
+                    .collect::<anyhow::Result<_>>()?;
+                let todos = self.inner.create_many(payloads).await?;
+                todos
+                    .into_iter()
+                    .map(|todo| open_todo(encryption, todo))
+                    .collect()
+            }
+            None => self.inner.create_many(payloads).await,
+        }
+    }
+    async fn import<L: LabelRepository>(
+        &self,
+        labels: &L,
+        import: Import,
+    ) -> anyhow::Result<ImportedRows> {
+        let Some(encryption) = &self.encryption else {
+            return self.inner.import(labels, import).await;
+        };
+        // label names are stored as they are, like `LabelRepository` does
+        let todos = import
+            .todos
+            .into_iter()
+            .map(|(payload, labels)| Ok((seal_create(encryption, payload)?, labels)))
+            .collect::<anyhow::Result<_>>()?;
+        let import = Import { todos, ..import };
+        let mut imported = self.inner.import(labels, import).await?;
+        imported.todos = std::mem::take(&mut imported.todos)
+            .into_iter()
+            .map(|todo| open_todo(encryption, todo))
+            .collect::<anyhow::Result<_>>()?;
+        Ok(imported)
+    }
+    async fn find(&self, id: i64) -> anyhow::Result<Todo> {
+        let todo = self.inner.find(id).await?;
+        match &self.encryption {
+            Some(encryption) => open_todo(encryption, todo),
+            None => Ok(todo),
+        }
+    }
+    async fn all(&self, filter: &TodoFilter) -> anyhow::Result<Vec<Todo>> {
+        let encryption = match &self.encryption {
+            Some(encryption) => encryption,
+            None => return self.inner.all(filter).await,
+        };
+        let (narrowed, terms) = without_terms(filter);
+        let mut todos = Vec::new();
+        for todo in self.inner.all(&narrowed).await? {
+            let todo = open_todo(encryption, todo)?;
+            if !terms || matches_terms(&todo, &filter.text) {
+                todos.push(todo);
+            }
+        }
+        Ok(todos)
+    }
+    async fn count(&self, filter: &TodoFilter) -> anyhow::Result<i64> {
+        if self.encryption.is_none() || filter.text.is_empty() {
+            return self.inner.count(filter).await;
+        }
+        Ok(self.all(filter).await?.len() as i64)
+    }
+    fn stream_all(&self) -> BoxStream<'static, anyhow::Result<Todo>> {
+        let todos = self.inner.stream_all();
+        match self.encryption.clone() {
+            Some(encryption) => todos
+                .map(move |todo| todo.and_then(|todo| open_todo(&encryption, todo)))
+                .boxed(),
+            None => todos,
+        }
+    }
+    async fn find_with_labels(&self, id: i64) -> anyhow::Result<TodoWithLabels> {
+        let mut todo = self.inner.find_with_labels(id).await?;
+        if let Some(encryption) = &self.encryption {
+            todo.todo = open_todo(encryption, todo.todo)?;
+        }
+        Ok(todo)
+    }
+    async fn all_with_labels(&self, filter: &TodoFilter) -> anyhow::Result<Vec<TodoWithLabels>> {
+        let encryption = match &self.encryption {
+            Some(encryption) => encryption,
+            None => return self.inner.all_with_labels(filter).await,
+        };
+        let (narrowed, terms) = without_terms(filter);
+        let mut todos = Vec::new();
+        for mut todo in self.inner.all_with_labels(&narrowed).await? {
+            todo.todo = open_todo(encryption, todo.todo)?;
+            if !terms || matches_terms(&todo.todo, &filter.text) {
+                todos.push(todo);
+            }
+        }
+        Ok(todos)
+    }
+    async fn update(&self, id: i64, mut payload: UpdateTodo) -> anyhow::Result<Todo> {
+        let encryption = match &self.encryption {
+            Some(encryption) => encryption,
+            None => return self.inner.update(id, payload).await,
+        };
+        if let Some(text) = payload.text_mut() {
+            *text = encryption.seal(text)?;
+        }
+        open_todo(encryption, self.inner.update(id, payload).await?)
+    }
+    async fn delete(&self, id: i64) -> anyhow::Result<()> {
+        self.inner.delete(id).await
+    }
+    async fn purge_completed(&self) -> anyhow::Result<u64> {
+        self.inner.purge_completed().await
+    }
+    async fn archive_completed(&self, older_than: Duration) -> anyhow::Result<u64> {
+        self.inner.archive_completed(older_than).await
+    }
+    async fn archived(&self) -> anyhow::Result<Vec<Todo>> {
+        let todos = self.inner.archived().await?;
+        match &self.encryption {
+            Some(encryption) => todos
+                .into_iter()
+                .map(|todo| open_todo(encryption, todo))
+                .collect(),
+            None => Ok(todos),
+        }
+    }
+    async fn last_modified(&self, id: i64) -> anyhow::Result<SystemTime> {
+        self.inner.last_modified(id).await
+    }
+    async fn collection_last_modified(&self) -> anyhow::Result<SystemTime> {
+        self.inner.collection_last_modified().await
+    }
+    async fn recently_modified(&self, limit: i64) -> anyhow::Result<Vec<(Todo, SystemTime)>> {
+        let todos = self.inner.recently_modified(limit).await?;
+        match &self.encryption {
+            Some(encryption) => todos
+                .into_iter()
+                .map(|(todo, modified)| Ok((open_todo(encryption, todo)?, modified)))
+                .collect(),
+            None => Ok(todos),
+        }
+    }
+    async fn resolve(&self, key: &Key) -> anyhow::Result<i64> {
+        self.inner.resolve(key).await
+    }
+    async fn search(&self, filter: &TodoFilter, limit: i64) -> anyhow::Result<Vec<SearchHit>> {
+        if self.encryption.is_none() {
+            return self.inner.search(filter, limit).await;
+        }
+        // The same ranking as the trait's default, on the decrypted texts.
+        let mut hits: Vec<SearchHit> = self
+            .all(filter)
+            .await?
+            .into_iter()
+            .map(|todo| SearchHit::scan(todo, &filter.text))
+            .collect();
+        hits.sort_by(|a, b| b.rank.total_cmp(&a.rank));
+        hits.truncate(limit.max(0) as usize);
+        Ok(hits)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::repositories::todo::TodoRepositoryForMemory;
+
+    fn encryption(keys: &[(&str, u8)]) -> Encryption {
+        Encryption::new(
+            keys.iter()
+                .map(|(id, byte)| (id.to_string(), vec![*byte; 32]))
+                .collect(),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn stores_texts_encrypted_and_reads_them_back() {
+        let memory = TodoRepositoryForMemory::new();
+        let repository = Encrypting::new(memory.clone(), Some(encryption(&[("k1", 1)])));
+        let todo = repository
+            .create(CreateTodo::new("buy Milk".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(todo.text(), "buy Milk");
+        let stored = memory.find(todo.id()).await.unwrap();
+        assert!(stored.text().starts_with("enc:k1:"));
+        assert!(!stored.text().contains("Milk"));
+
+        repository
+            .create(CreateTodo::new("walk the dog".to_string()))
+            .await
+            .unwrap();
+        let filter = TodoFilter {
+            text: vec!["milk".to_string()],
+            ..Default::default()
+        };
+        let found = repository.all(&filter).await.unwrap();
+        assert_eq!(found, vec![todo.clone()]);
+        assert_eq!(repository.count(&filter).await.unwrap(), 1);
+        let hits = repository.search(&filter, 10).await.unwrap();
+        assert_eq!(hits[0].highlight, "buy <mark>Milk</mark>");
+    }
+
+    #[tokio::test]
+    async fn reencrypts_under_the_current_key() {
+        let memory = TodoRepositoryForMemory::new();
+        let plain = memory
+            .create(CreateTodo::new("written in plain".to_string()))
+            .await
+            .unwrap();
+        let old = Encrypting::new(memory.clone(), Some(encryption(&[("old", 1)])));
+        let sealed = old
+            .create(CreateTodo::new("under the old key".to_string()))
+            .await
+            .unwrap();
+
+        let rotated = Encrypting::new(memory.clone(), Some(encryption(&[("new", 2), ("old", 1)])));
+        assert_eq!(rotated.find(sealed.id()).await.unwrap(), sealed);
+        assert_eq!(rotated.reencrypt().await.unwrap(), 2);
+        assert_eq!(rotated.reencrypt().await.unwrap(), 0);
+        for todo in memory.all(&TodoFilter::default()).await.unwrap() {
+            assert!(todo.text().starts_with("enc:new:"));
+        }
+
+        let retired = Encrypting::new(memory, Some(encryption(&[("new", 2)])));
+        assert_eq!(
+            retired.find(plain.id()).await.unwrap().text(),
+            "written in plain"
+        );
+        let wrong = Encrypting::new(retired.inner, Some(encryption(&[("new", 3)])));
+        assert!(wrong.find(plain.id()).await.is_err());
+    }
+
+    #[test]
+    fn rejects_keys_of_the_wrong_size() {
+        assert!(Encryption::new(vec![("k".to_string(), vec![0; 16])]).is_err());
+        assert!(Encryption::new(vec![]).is_err());
+    }
+}
@@ -0,0 +1,219 @@
+use std::{
+    collections::hash_map::RandomState,
+    fmt,
+    hash::{BuildHasher, Hasher},
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use sqlx::{database::HasArguments, Database, Decode, Encode, Executor, IntoArguments, Pool, Type};
+
+use crate::handlers::hex;
+
+/// Which ids the API hands out in links and accepts in paths, `serial` or
+/// `uuid`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdFormat {
+    /// The integer primary keys, `/todos/1`.
+    #[default]
+    Serial,
+    /// Each row's uuid, so ids can't be enumerated and clients may mint
+    /// their own before syncing.
+    Uuid,
+}
+
+/// A hyphenated, lower case uuid, stored as text in every backend.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, sqlx::Type)]
+#[serde(try_from = "String", into = "String")]
+#[sqlx(transparent)]
+pub struct Uuid(String);
+
+impl Uuid {
+    /// A version 7 uuid: the unix time in milliseconds followed by random
+    /// bits, so new rows still sort roughly by creation.
+    pub fn now_v7() -> Self {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let random = random_u128();
+        let mut bytes = random.to_be_bytes();
+        bytes[..6].copy_from_slice(&millis.to_be_bytes()[2..]);
+        bytes[6] = 0x70 | (bytes[6] & 0x0f);
+        bytes[8] = 0x80 | (bytes[8] & 0x3f);
+        Uuid::from_bytes(bytes)
+    }
+
+    fn from_bytes(bytes: [u8; 16]) -> Self {
+        let hex = hex(&bytes);
+        Uuid(format!(
+            "{}-{}-{}-{}-{}",
+            &hex[..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..]
+        ))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+fn random_u128() -> u128 {
+    let mut high = RandomState::new().build_hasher();
+    high.write_u8(0);
+    let mut low = RandomState::new().build_hasher();
+    low.write_u8(1);
+    (u128::from(high.finish()) << 64) | u128::from(low.finish())
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("invalid uuid: [{0}]")]
+pub struct InvalidUuid(String);
+
+impl FromStr for Uuid {
+    type Err = InvalidUuid;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let groups: Vec<&str> = s.split('-').collect();
+        let well_formed = groups.iter().map(|group| group.len()).eq([8, 4, 4, 4, 12])
+            && groups
+                .iter()
+                .all(|group| group.chars().all(|c| c.is_ascii_hexdigit()));
+        if !well_formed {
+            return Err(InvalidUuid(s.to_string()));
+        }
+        Ok(Uuid(s.to_ascii_lowercase()))
+    }
+}
+
+impl TryFrom<String> for Uuid {
+    type Error = InvalidUuid;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Uuid> for String {
+    fn from(uuid: Uuid) -> Self {
+        uuid.0
+    }
+}
+
+impl fmt::Display for Uuid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// A todo or label as addressed in a path, see [`IdFormat`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Key {
+    Serial(i64),
+    Uuid(Uuid),
+}
+
+impl Key {
+    /// Parses `raw` the way `format` expects; the other kind of id is
+    /// refused so serial ids can't be probed once uuids are on.
+    pub fn parse(raw: &str, format: IdFormat) -> Option<Self> {
+        match format {
+            IdFormat::Serial => raw.parse().ok().map(Key::Serial),
+            IdFormat::Uuid => raw.parse().ok().map(Key::Uuid),
+        }
+    }
+}
+
+/// Gives the todos and labels created before `ID_FORMAT=uuid` was switched
+/// on a uuid, so they stay reachable. `update` sets the uuid (first
+/// parameter) of row `id` (second) in table `{}`.
+pub async fn backfill<DB>(pool: &Pool<DB>, update: &str) -> anyhow::Result<u64>
+where
+    DB: Database,
+    for<'c> &'c Pool<DB>: Executor<'c, Database = DB>,
+    for<'q> <DB as HasArguments<'q>>::Arguments: IntoArguments<'q, DB>,
+    i64: Type<DB> + for<'r> Decode<'r, DB> + for<'q> Encode<'q, DB>,
+    String: Type<DB> + for<'q> Encode<'q, DB>,
+    usize: sqlx::ColumnIndex<DB::Row>,
+{
+    let mut backfilled = 0;
+    for table in ["todos", "labels"] {
+        let ids: Vec<i64> =
+            sqlx::query_scalar(&format!("select id from {} where uuid is null", table))
+                .fetch_all(pool)
+                .await?;
+        let update = update.replace("{}", table);
+        for id in ids {
+            sqlx::query(&update)
+                .bind(String::from(Uuid::now_v7()))
+                .bind(id)
+                .execute(pool)
+                .await?;
+            backfilled += 1;
+        }
+    }
+    Ok(backfilled)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn v7_uuids_are_versioned_and_ordered() {
+        let first = Uuid::now_v7();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        let second = Uuid::now_v7();
+        assert_ne!(first, second);
+        assert!(first.as_str() < second.as_str());
+        assert_eq!(&first.as_str()[14..15], "7");
+        assert!("89ab".contains(&first.as_str()[19..20]));
+        assert_eq!(first.as_str().parse::<Uuid>().unwrap(), first);
+    }
+
+    #[test]
+    fn keys_follow_the_id_format() {
+        let uuid = "0192F3A4-5B6C-7D8E-9F00-112233445566";
+        assert_eq!(Key::parse("1", IdFormat::Serial), Some(Key::Serial(1)));
+        assert_eq!(Key::parse(uuid, IdFormat::Serial), None);
+        assert_eq!(
+            Key::parse(uuid, IdFormat::Uuid),
+            Some(Key::Uuid(uuid.to_ascii_lowercase().parse().unwrap()))
+        );
+        assert_eq!(Key::parse("1", IdFormat::Uuid), None);
+        assert!("0192f3a4-5b6c-7d8e-9f00".parse::<Uuid>().is_err());
+    }
+
+    #[tokio::test]
+    async fn backfills_rows_without_a_uuid() {
+        let pool = crate::repositories::connect_sqlite(
+            "sqlite::memory:",
+            &crate::repositories::PoolSettings::default(),
+            crate::repositories::Migrations::Apply,
+        )
+        .await
+        .unwrap();
+        sqlx::query("insert into todos (text) values ('old'), ('older')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("insert into labels (name) values ('work')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let update = "update {} set uuid=?1 where id=?2";
+        assert_eq!(backfill(&pool, update).await.unwrap(), 3);
+        assert_eq!(backfill(&pool, update).await.unwrap(), 0);
+        let uuids: Vec<Uuid> = sqlx::query_scalar("select uuid from todos")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_ne!(uuids[0], uuids[1]);
+    }
+}
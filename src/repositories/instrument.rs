@@ -0,0 +1,431 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use axum::async_trait;
+use futures_util::stream::BoxStream;
+use serde::Serialize;
+use tracing::Instrument;
+
+use crate::config::DatabaseConfig;
+
+use super::{
+    id::{Key, Uuid},
+    job::{Job, JobKind, JobRepository, UpdateJob},
+    label::{Label, LabelRepository},
+    todo::{
+        CreateTodo, Import, ImportedRows, SearchHit, Todo, TodoFilter, TodoRepository,
+        TodoWithLabels, UpdateTodo,
+    },
+};
+
+/// Timings of one repository method since startup.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct MethodStats {
+    pub calls: u64,
+    pub errors: u64,
+    /// Calls slower than the slow query threshold.
+    pub slow: u64,
+    pub total_ms: f64,
+    pub max_ms: f64,
+}
+
+/// Per-method call, error and time counts, shared by every [`Instrumented`]
+/// repository and read by `GET /repository/stats`, and the calls running.
+#[derive(Debug, Default)]
+pub struct QueryMetrics {
+    methods: Mutex<HashMap<&'static str, MethodStats>>,
+    in_flight: AtomicUsize,
+    /// Calls taking longer are logged; `None` logs none.
+    slow: Option<Duration>,
+}
+
+/// Counts a call as running until dropped, also when it is cancelled.
+struct Running<'a>(&'a AtomicUsize);
+
+impl<'a> Running<'a> {
+    fn start(in_flight: &'a AtomicUsize) -> Self {
+        in_flight.fetch_add(1, Ordering::Relaxed);
+        Self(in_flight)
+    }
+}
+
+impl Drop for Running<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl QueryMetrics {
+    /// Logging calls slower than `slow_query_ms`, `0` none.
+    pub fn from_config(config: &DatabaseConfig) -> Self {
+        Self {
+            slow: (config.slow_query_ms > 0).then(|| Duration::from_millis(config.slow_query_ms)),
+            ..Self::default()
+        }
+    }
+
+    fn record(
+        &self,
+        method: &'static str,
+        params: impl FnOnce() -> String,
+        elapsed: Duration,
+        failed: bool,
+    ) {
+        let ms = elapsed.as_secs_f64() * 1000.0;
+        let slow = self.slow.is_some_and(|slow| elapsed > slow);
+        if slow {
+            let params = params();
+            let params = params.as_str();
+            tracing::warn!(
+                method,
+                params,
+                duration_ms = ms,
+                "slow repository call {}({}) took {:?}",
+                method,
+                params,
+                elapsed
+            );
+        }
+        let mut methods = self.methods.lock().unwrap_or_else(|e| e.into_inner());
+        let stats = methods.entry(method).or_default();
+        stats.calls += 1;
+        stats.errors += u64::from(failed);
+        stats.slow += u64::from(slow);
+        stats.total_ms += ms;
+        stats.max_ms = stats.max_ms.max(ms);
+    }
+
+    /// Every method called so far, by name (`todos.find`, `labels.all`).
+    pub fn stats(&self) -> BTreeMap<&'static str, MethodStats> {
+        let methods = self.methods.lock().unwrap_or_else(|e| e.into_inner());
+        methods
+            .iter()
+            .map(|(method, stats)| (*method, *stats))
+            .collect()
+    }
+
+    /// Repository calls running now, each holding a connection on a SQL
+    /// backend.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+}
+
+/// A repository whose calls each run in a `repository` span and are
+/// counted in [`QueryMetrics`]. It wraps the backend directly, so retried
+/// attempts count separately and cache hits don't count at all.
+#[derive(Debug, Clone)]
+pub struct Instrumented<R> {
+    inner: R,
+    metrics: Arc<QueryMetrics>,
+}
+
+impl<R> Instrumented<R> {
+    pub fn new(inner: R, metrics: Arc<QueryMetrics>) -> Self {
+        Self { inner, metrics }
+    }
+
+    /// Runs `call` of `method`, with `params` summarizing its arguments
+    /// for the slow query log; it is only called for slow calls.
+    async fn run<T>(
+        &self,
+        method: &'static str,
+        params: impl FnOnce() -> String,
+        call: impl Future<Output = anyhow::Result<T>>,
+    ) -> anyhow::Result<T> {
+        let started = Instant::now();
+        let running = Running::start(&self.metrics.in_flight);
+        let result = call
+            .instrument(tracing::debug_span!(
+                "repository",
+                otel.name = method,
+                method
+            ))
+            .await;
+        drop(running);
+        let elapsed = started.elapsed();
+        tracing::trace!("{} took {:?}", method, elapsed);
+        self.metrics
+            .record(method, params, elapsed, result.is_err());
+        result
+    }
+}
+
+#[async_trait]
+impl<R: TodoRepository> TodoRepository for Instrumented<R> {
+    async fn create(&self, payload: CreateTodo) -> anyhow::Result<Todo> {
+        self.run("todos.create", String::new, self.inner.create(payload))
+            .await
+    }
+    async fn create_many(&self, payloads: Vec<CreateTodo>) -> anyhow::Result<Vec<Todo>> {
+        let count = payloads.len();
+        self.run(
+            "todos.create_many",
+            move || format!("{} todos", count),
+            self.inner.create_many(payloads),
+        )
+        .await
+    }
+    async fn import<L: LabelRepository>(
+        &self,
+        labels: &L,
+        import: Import,
+    ) -> anyhow::Result<ImportedRows> {
+        let counts = (import.labels.len(), import.todos.len());
+        self.run(
+            "todos.import",
+            move || format!("{} labels, {} todos", counts.0, counts.1),
+            self.inner.import(labels, import),
+        )
+        .await
+    }
+    async fn find(&self, id: i64) -> anyhow::Result<Todo> {
+        self.run(
+            "todos.find",
+            move || format!("id={}", id),
+            self.inner.find(id),
+        )
+        .await
+    }
+    async fn all(&self, filter: &TodoFilter) -> anyhow::Result<Vec<Todo>> {
+        self.run(
+            "todos.all",
+            move || format!("{:?}", filter),
+            self.inner.all(filter),
+        )
+        .await
+    }
+    async fn count(&self, filter: &TodoFilter) -> anyhow::Result<i64> {
+        self.run(
+            "todos.count",
+            move || format!("{:?}", filter),
+            self.inner.count(filter),
+        )
+        .await
+    }
+    fn stream_all(&self) -> BoxStream<'static, anyhow::Result<Todo>> {
+        // Runs for as long as the client reads, which says little about the query.
+        self.inner.stream_all()
+    }
+    async fn find_with_labels(&self, id: i64) -> anyhow::Result<TodoWithLabels> {
+        self.run(
+            "todos.find_with_labels",
+            move || format!("id={}", id),
+            self.inner.find_with_labels(id),
+        )
+        .await
+    }
+    async fn all_with_labels(&self, filter: &TodoFilter) -> anyhow::Result<Vec<TodoWithLabels>> {
+        self.run(
+            "todos.all_with_labels",
+            move || format!("{:?}", filter),
+            self.inner.all_with_labels(filter),
+        )
+        .await
+    }
+    async fn update(&self, id: i64, payload: UpdateTodo) -> anyhow::Result<Todo> {
+        self.run(
+            "todos.update",
+            move || format!("id={}", id),
+            self.inner.update(id, payload),
+        )
+        .await
+    }
+    async fn delete(&self, id: i64) -> anyhow::Result<()> {
+        self.run(
+            "todos.delete",
+            move || format!("id={}", id),
+            self.inner.delete(id),
+        )
+        .await
+    }
+    async fn purge_completed(&self) -> anyhow::Result<u64> {
+        self.run(
+            "todos.purge_completed",
+            String::new,
+            self.inner.purge_completed(),
+        )
+        .await
+    }
+    async fn archive_completed(&self, older_than: Duration) -> anyhow::Result<u64> {
+        self.run(
+            "todos.archive_completed",
+            move || format!("older_than={:?}", older_than),
+            self.inner.archive_completed(older_than),
+        )
+        .await
+    }
+    async fn archived(&self) -> anyhow::Result<Vec<Todo>> {
+        self.run("todos.archived", String::new, self.inner.archived())
+            .await
+    }
+    async fn last_modified(&self, id: i64) -> anyhow::Result<SystemTime> {
+        self.run(
+            "todos.last_modified",
+            move || format!("id={}", id),
+            self.inner.last_modified(id),
+        )
+        .await
+    }
+    async fn collection_last_modified(&self) -> anyhow::Result<SystemTime> {
+        self.run(
+            "todos.collection_last_modified",
+            String::new,
+            self.inner.collection_last_modified(),
+        )
+        .await
+    }
+    async fn recently_modified(&self, limit: i64) -> anyhow::Result<Vec<(Todo, SystemTime)>> {
+        self.run(
+            "todos.recently_modified",
+            move || format!("limit={}", limit),
+            self.inner.recently_modified(limit),
+        )
+        .await
+    }
+    async fn resolve(&self, key: &Key) -> anyhow::Result<i64> {
+        self.run(
+            "todos.resolve",
+            move || format!("{:?}", key),
+            self.inner.resolve(key),
+        )
+        .await
+    }
+    async fn search(&self, filter: &TodoFilter, limit: i64) -> anyhow::Result<Vec<SearchHit>> {
+        self.run(
+            "todos.search",
+            move || format!("{:?}, limit={}", filter, limit),
+            self.inner.search(filter, limit),
+        )
+        .await
+    }
+}
+
+#[async_trait]
+impl<R: LabelRepository> LabelRepository for Instrumented<R> {
+    async fn create(&self, name: String, uuid: Option<Uuid>) -> anyhow::Result<Label> {
+        let shown = name.clone();
+        self.run(
+            "labels.create",
+            move || format!("name={:?}", shown),
+            self.inner.create(name, uuid),
+        )
+        .await
+    }
+    async fn all(&self) -> anyhow::Result<Vec<Label>> {
+        self.run("labels.all", String::new, self.inner.all()).await
+    }
+    async fn delete(&self, id: i64) -> anyhow::Result<()> {
+        self.run(
+            "labels.delete",
+            move || format!("id={}", id),
+            self.inner.delete(id),
+        )
+        .await
+    }
+    async fn resolve(&self, key: &Key) -> anyhow::Result<i64> {
+        self.run(
+            "labels.resolve",
+            move || format!("{:?}", key),
+            self.inner.resolve(key),
+        )
+        .await
+    }
+}
+
+#[async_trait]
+impl<R: JobRepository> JobRepository for Instrumented<R> {
+    async fn create(&self, kind: JobKind) -> anyhow::Result<Job> {
+        self.run(
+            "jobs.create",
+            move || format!("{:?}", kind),
+            self.inner.create(kind),
+        )
+        .await
+    }
+    async fn find(&self, id: i32) -> anyhow::Result<Job> {
+        self.run(
+            "jobs.find",
+            move || format!("id={}", id),
+            self.inner.find(id),
+        )
+        .await
+    }
+    async fn update(&self, id: i32, payload: UpdateJob) -> anyhow::Result<Job> {
+        self.run(
+            "jobs.update",
+            move || format!("id={}", id),
+            self.inner.update(id, payload),
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::repositories::todo::TodoRepositoryForMemory;
+
+    #[tokio::test]
+    async fn counts_calls_and_errors_per_method() {
+        let metrics = Arc::new(QueryMetrics::default());
+        let repository = Instrumented::new(TodoRepositoryForMemory::new(), metrics.clone());
+        let todo = repository
+            .create(CreateTodo::new("timed".to_string()))
+            .await
+            .unwrap();
+        repository.find(todo.id()).await.unwrap();
+        repository.find(todo.id() + 1).await.unwrap_err();
+
+        let stats = metrics.stats();
+        assert_eq!(
+            stats.keys().copied().collect::<Vec<_>>(),
+            ["todos.create", "todos.find"]
+        );
+        let find = stats["todos.find"];
+        assert_eq!((find.calls, find.errors), (2, 1));
+        assert!(find.max_ms <= find.total_ms);
+        assert_eq!(metrics.in_flight(), 0);
+        assert_eq!(find.slow, 0);
+    }
+
+    #[tokio::test]
+    async fn counts_calls_over_the_slow_threshold() {
+        let metrics = Arc::new(QueryMetrics {
+            slow: Some(Duration::ZERO),
+            ..QueryMetrics::default()
+        });
+        let repository = Instrumented::new(TodoRepositoryForMemory::new(), metrics.clone());
+        repository.find(1).await.unwrap_err();
+        repository.find(2).await.unwrap_err();
+        assert_eq!(metrics.stats()["todos.find"].slow, 2);
+    }
+
+    #[test]
+    fn summarizes_params_only_for_slow_calls() {
+        let metrics = QueryMetrics::default();
+        metrics.record("todos.all", || unreachable!(), Duration::ZERO, false);
+        let slow = QueryMetrics {
+            slow: Some(Duration::ZERO),
+            ..QueryMetrics::default()
+        };
+        let mut summarized = false;
+        slow.record(
+            "todos.all",
+            || {
+                summarized = true;
+                String::new()
+            },
+            Duration::from_millis(1),
+            false,
+        );
+        assert!(summarized);
+    }
+}
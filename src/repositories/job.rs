@@ -0,0 +1,439 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use axum::async_trait;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{FromRow, PgPool, SqlitePool};
+
+use super::{not_found, RepositoryError};
+
+#[async_trait]
+pub trait JobRepository: Clone + std::marker::Send + std::marker::Sync + 'static {
+    async fn create(&self, kind: JobKind) -> anyhow::Result<Job>;
+    async fn find(&self, id: i32) -> anyhow::Result<Job>;
+    async fn update(&self, id: i32, payload: UpdateJob) -> anyhow::Result<Job>;
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+    PurgeCompleted,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Pending,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+impl JobKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            JobKind::PurgeCompleted => "purge_completed",
+        }
+    }
+}
+
+impl JobStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            JobStatus::Pending => "pending",
+            JobStatus::Running => "running",
+            JobStatus::Succeeded => "succeeded",
+            JobStatus::Failed => "failed",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Job {
+    pub id: i32,
+    pub kind: JobKind,
+    pub status: JobStatus,
+    pub progress: i32,
+    pub result: Option<Value>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct UpdateJob {
+    pub status: Option<JobStatus>,
+    pub progress: Option<i32>,
+    pub result: Option<Value>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, FromRow)]
+struct JobFromRow {
+    id: i32,
+    kind: String,
+    status: String,
+    progress: i32,
+    result: Option<Value>,
+    error: Option<String>,
+}
+
+fn parse<T: DeserializeOwned>(column: &str, value: String) -> Result<T, RepositoryError> {
+    serde_json::from_value(Value::String(value))
+        .map_err(|e| RepositoryError::Unexpected(format!("invalid job {}: {}", column, e)))
+}
+
+impl TryFrom<JobFromRow> for Job {
+    type Error = RepositoryError;
+
+    fn try_from(row: JobFromRow) -> Result<Self, Self::Error> {
+        Ok(Job {
+            id: row.id,
+            kind: parse("kind", row.kind)?,
+            status: parse("status", row.status)?,
+            progress: row.progress,
+            result: row.result,
+            error: row.error,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct JobRepositoryForDb {
+    pool: PgPool,
+}
+
+impl JobRepositoryForDb {
+    pub fn new(pool: PgPool) -> Self {
+        JobRepositoryForDb { pool }
+    }
+}
+
+#[async_trait]
+impl JobRepository for JobRepositoryForDb {
+    async fn create(&self, kind: JobKind) -> anyhow::Result<Job> {
+        let row = sqlx::query_as::<_, JobFromRow>(
+            r#"
+            insert into jobs (kind, status, progress)
+            values ($1, $2, 0)
+            returning *
+        "#,
+        )
+        .bind(kind.as_str())
+        .bind(JobStatus::Pending.as_str())
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(row.try_into()?)
+    }
+    async fn find(&self, id: i32) -> anyhow::Result<Job> {
+        let row = sqlx::query_as::<_, JobFromRow>(
+            r#"
+            select * from jobs where id=$1
+        "#,
+        )
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(not_found(id.into()))?;
+
+        Ok(row.try_into()?)
+    }
+    async fn update(&self, id: i32, payload: UpdateJob) -> anyhow::Result<Job> {
+        let row = sqlx::query_as::<_, JobFromRow>(
+            r#"
+            update jobs set
+                status=coalesce($1, status),
+                progress=coalesce($2, progress),
+                result=coalesce($3, result),
+                error=coalesce($4, error)
+            where id=$5
+            returning *
+        "#,
+        )
+        .bind(payload.status.map(|status| status.as_str()))
+        .bind(payload.progress)
+        .bind(payload.result)
+        .bind(payload.error)
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(not_found(id.into()))?;
+
+        Ok(row.try_into()?)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct JobRepositoryForSqlite {
+    pool: SqlitePool,
+}
+
+impl JobRepositoryForSqlite {
+    pub fn new(pool: SqlitePool) -> Self {
+        JobRepositoryForSqlite { pool }
+    }
+}
+
+#[async_trait]
+impl JobRepository for JobRepositoryForSqlite {
+    async fn create(&self, kind: JobKind) -> anyhow::Result<Job> {
+        let row = sqlx::query_as::<_, JobFromRow>(
+            r#"
+            insert into jobs (kind, status, progress)
+            values (?1, ?2, 0)
+            returning *
+        "#,
+        )
+        .bind(kind.as_str())
+        .bind(JobStatus::Pending.as_str())
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(row.try_into()?)
+    }
+    async fn find(&self, id: i32) -> anyhow::Result<Job> {
+        let row = sqlx::query_as::<_, JobFromRow>(
+            r#"
+            select * from jobs where id=?1
+        "#,
+        )
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(not_found(id.into()))?;
+
+        Ok(row.try_into()?)
+    }
+    async fn update(&self, id: i32, payload: UpdateJob) -> anyhow::Result<Job> {
+        let row = sqlx::query_as::<_, JobFromRow>(
+            r#"
+            update jobs set
+                status=coalesce(?1, status),
+                progress=coalesce(?2, progress),
+                result=coalesce(?3, result),
+                error=coalesce(?4, error)
+            where id=?5
+            returning *
+        "#,
+        )
+        .bind(payload.status.map(|status| status.as_str()))
+        .bind(payload.progress)
+        .bind(payload.result)
+        .bind(payload.error)
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(not_found(id.into()))?;
+
+        Ok(row.try_into()?)
+    }
+}
+
+#[cfg(feature = "mysql")]
+#[derive(Debug, Clone)]
+pub struct JobRepositoryForMySql {
+    pool: sqlx::MySqlPool,
+}
+
+#[cfg(feature = "mysql")]
+impl JobRepositoryForMySql {
+    pub fn new(pool: sqlx::MySqlPool) -> Self {
+        JobRepositoryForMySql { pool }
+    }
+}
+
+#[cfg(feature = "mysql")]
+#[async_trait]
+impl JobRepository for JobRepositoryForMySql {
+    async fn create(&self, kind: JobKind) -> anyhow::Result<Job> {
+        let result = sqlx::query(
+            r#"
+            insert into jobs (kind, status, progress)
+            values (?, ?, 0)
+        "#,
+        )
+        .bind(kind.as_str())
+        .bind(JobStatus::Pending.as_str())
+        .execute(&self.pool)
+        .await?;
+
+        self.find(result.last_insert_id() as i32).await
+    }
+    async fn find(&self, id: i32) -> anyhow::Result<Job> {
+        let row = sqlx::query_as::<_, JobFromRow>(
+            r#"
+            select * from jobs where id=?
+        "#,
+        )
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(not_found(id.into()))?;
+
+        Ok(row.try_into()?)
+    }
+    async fn update(&self, id: i32, payload: UpdateJob) -> anyhow::Result<Job> {
+        let result = sqlx::query(
+            r#"
+            update jobs set
+                status=coalesce(?, status),
+                progress=coalesce(?, progress),
+                result=coalesce(?, result),
+                error=coalesce(?, error)
+            where id=?
+        "#,
+        )
+        .bind(payload.status.map(|status| status.as_str()))
+        .bind(payload.progress)
+        .bind(payload.result)
+        .bind(payload.error)
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(id.into()).into());
+        }
+
+        self.find(id).await
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct JobRepositoryForMemory {
+    store: Arc<RwLock<HashMap<i32, Job>>>,
+}
+
+impl JobRepositoryForMemory {
+    pub fn new() -> Self {
+        JobRepositoryForMemory {
+            store: Arc::default(),
+        }
+    }
+}
+
+#[async_trait]
+impl JobRepository for JobRepositoryForMemory {
+    async fn create(&self, kind: JobKind) -> anyhow::Result<Job> {
+        let mut store = self.store.write().unwrap();
+        let id = (store.len() + 1) as i32;
+        let job = Job {
+            id,
+            kind,
+            status: JobStatus::Pending,
+            progress: 0,
+            result: None,
+            error: None,
+        };
+        store.insert(id, job.clone());
+        Ok(job)
+    }
+    async fn find(&self, id: i32) -> anyhow::Result<Job> {
+        let store = self.store.read().unwrap();
+        let job = store
+            .get(&id)
+            .cloned()
+            .ok_or(RepositoryError::NotFound(id.into()))?;
+        Ok(job)
+    }
+    async fn update(&self, id: i32, payload: UpdateJob) -> anyhow::Result<Job> {
+        let mut store = self.store.write().unwrap();
+        let job = store
+            .get_mut(&id)
+            .ok_or(RepositoryError::NotFound(id.into()))?;
+        if let Some(status) = payload.status {
+            job.status = status;
+        }
+        if let Some(progress) = payload.progress {
+            job.progress = progress;
+        }
+        job.result = payload.result.or(job.result.take());
+        job.error = payload.error.or(job.error.take());
+        Ok(job.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use dotenv::dotenv;
+    use std::env;
+
+    #[tokio::test]
+    async fn sqlite_crud_scenario() {
+        let pool = crate::repositories::connect_sqlite(
+            "sqlite::memory:",
+            &crate::repositories::PoolSettings::default(),
+            crate::repositories::Migrations::Apply,
+        )
+        .await
+        .expect("failed open sqlite");
+        let repository = JobRepositoryForSqlite::new(pool);
+
+        let created = repository.create(JobKind::PurgeCompleted).await.unwrap();
+        assert_eq!(created.status, JobStatus::Pending);
+        let updated = repository
+            .update(
+                created.id,
+                UpdateJob {
+                    status: Some(JobStatus::Succeeded),
+                    result: Some(serde_json::json!({ "purged": 2 })),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(updated.result, Some(serde_json::json!({ "purged": 2 })));
+        assert_eq!(repository.find(created.id).await.unwrap(), updated);
+        assert!(repository.find(created.id + 1).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn crud_scenario() {
+        dotenv().ok();
+        let database_url = &env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+
+        let pool = PgPool::connect(database_url)
+            .await
+            .expect("failed connect database");
+
+        let repository = JobRepositoryForDb::new(pool.clone());
+
+        // create
+        let created = repository.create(JobKind::PurgeCompleted).await.unwrap();
+        assert_eq!(created.status, JobStatus::Pending);
+        assert_eq!(created.progress, 0);
+
+        // update
+        let updated = repository
+            .update(
+                created.id,
+                UpdateJob {
+                    status: Some(JobStatus::Succeeded),
+                    progress: Some(100),
+                    result: Some(serde_json::json!({ "purged": 2 })),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(updated.status, JobStatus::Succeeded);
+        assert_eq!(updated.result, Some(serde_json::json!({ "purged": 2 })));
+
+        // find
+        let found = repository.find(created.id).await.unwrap();
+        assert_eq!(found, updated);
+
+        sqlx::query(
+            r#"
+        delete from jobs where id=$1
+        "#,
+        )
+        .bind(created.id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    }
+}
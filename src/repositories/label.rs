@@ -1,26 +1,93 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
 use axum::async_trait;
 use serde::{Deserialize, Serialize};
-use sqlx::{FromRow, PgPool};
+use sqlx::{FromRow, PgPool, SqlitePool};
 
-use super::RepositoryError;
+use super::{
+    id::{Key, Uuid},
+    not_found, RepositoryError,
+};
 
 #[async_trait]
 pub trait LabelRepository: Clone + std::marker::Send + std::marker::Sync + 'static {
-    async fn create(&self, text: String) -> anyhow::Result<Label>;
+    /// `uuid` is only given when `ID_FORMAT=uuid`.
+    async fn create(&self, name: String, uuid: Option<Uuid>) -> anyhow::Result<Label>;
     async fn all(&self) -> anyhow::Result<Vec<Label>>;
-    async fn delete(&self, id: i32) -> anyhow::Result<()>;
+    async fn delete(&self, id: i64) -> anyhow::Result<()>;
+    /// The serial id a path [`Key`] refers to; there are few labels, so
+    /// this scans them all.
+    async fn resolve(&self, key: &Key) -> anyhow::Result<i64> {
+        match key {
+            Key::Serial(id) => Ok(*id),
+            Key::Uuid(uuid) => self
+                .all()
+                .await?
+                .into_iter()
+                .find(|label| label.uuid.as_ref() == Some(uuid))
+                .map(|label| label.id)
+                .ok_or_else(|| RepositoryError::UnknownUuid(uuid.clone()).into()),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, FromRow)]
 pub struct Label {
-    pub id: i32,
-    pub text: String,
+    pub id: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uuid: Option<Uuid>,
+    pub name: String,
+}
+
+type LabelDatas = HashMap<i64, Label>;
+
+#[derive(Debug, Clone)]
+pub struct LabelRepositoryForMemory {
+    store: Arc<RwLock<LabelDatas>>,
+}
+
+impl LabelRepositoryForMemory {
+    pub fn new() -> Self {
+        LabelRepositoryForMemory {
+            store: Arc::default(),
+        }
+    }
+
+    fn write_store_ref(&self) -> RwLockWriteGuard<'_, LabelDatas> {
+        self.store.write().unwrap()
+    }
+
+    fn read_store_ref(&self) -> RwLockReadGuard<'_, LabelDatas> {
+        self.store.read().unwrap()
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
-pub struct UpdateLabel {
-    pub id: i32,
-    pub text: String,
+#[async_trait]
+impl LabelRepository for LabelRepositoryForMemory {
+    async fn create(&self, name: String, uuid: Option<Uuid>) -> anyhow::Result<Label> {
+        let mut store = self.write_store_ref();
+        if let Some(label) = store.values().find(|label| label.name == name) {
+            return Err(RepositoryError::Duplicate(label.id).into());
+        }
+        let id = store.keys().max().map_or(1, |id| id + 1);
+        let label = Label { id, uuid, name };
+        store.insert(id, label.clone());
+        Ok(label)
+    }
+    async fn all(&self) -> anyhow::Result<Vec<Label>> {
+        let store = self.read_store_ref();
+        let mut labels = Vec::from_iter(store.values().cloned());
+        labels.sort_by_key(|label| label.id);
+        Ok(labels)
+    }
+    async fn delete(&self, id: i64) -> anyhow::Result<()> {
+        let mut store = self.write_store_ref();
+        store.remove(&id).ok_or(RepositoryError::NotFound(id))?;
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -36,7 +103,7 @@ impl LabelRepositoryForDb {
 
 #[async_trait]
 impl LabelRepository for LabelRepositoryForDb {
-    async fn create(&self, name: String) -> anyhow::Result<Label> {
+    async fn create(&self, name: String, uuid: Option<Uuid>) -> anyhow::Result<Label> {
         let optional_label = sqlx::query_as::<_, Label>(
             r#"
         select * from labels where name = $1
@@ -47,17 +114,18 @@ impl LabelRepository for LabelRepositoryForDb {
         .await?;
 
         if let Some(label) = optional_label {
-            return Err(RepositoryError::Unexpected(label.id.to_string()).into());
+            return Err(RepositoryError::Duplicate(label.id).into());
         }
 
         let label = sqlx::query_as::<_, Label>(
             r#"
-            insert into labels ( name )
-            values ( $1 )
+            insert into labels ( name, uuid )
+            values ( $1, $2 )
             returning *
             "#,
         )
         .bind(name.clone())
+        .bind(uuid)
         .fetch_one(&self.pool)
         .await?;
 
@@ -75,8 +143,8 @@ impl LabelRepository for LabelRepositoryForDb {
 
         Ok(labels)
     }
-    async fn delete(&self, id: i32) -> anyhow::Result<()> {
-        sqlx::query(
+    async fn delete(&self, id: i64) -> anyhow::Result<()> {
+        let result = sqlx::query(
             r#"
           delete from labels where id=$1
           "#,
@@ -84,15 +152,406 @@ impl LabelRepository for LabelRepositoryForDb {
         .bind(id)
         .execute(&self.pool)
         .await
-        .map_err(|e| match e {
-            sqlx::Error::RowNotFound => RepositoryError::NotFound(id),
-            _ => RepositoryError::Unexpected(e.to_string()),
-        })?;
+        .map_err(not_found(id))?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(id).into());
+        }
 
         Ok(())
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct LabelRepositoryForSqlite {
+    pool: SqlitePool,
+}
+
+impl LabelRepositoryForSqlite {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl LabelRepository for LabelRepositoryForSqlite {
+    async fn create(&self, name: String, uuid: Option<Uuid>) -> anyhow::Result<Label> {
+        let optional_label = sqlx::query_as::<_, Label>(
+            r#"
+        select * from labels where name = ?1
+        "#,
+        )
+        .bind(name.clone())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        if let Some(label) = optional_label {
+            return Err(RepositoryError::Duplicate(label.id).into());
+        }
+
+        let label = sqlx::query_as::<_, Label>(
+            r#"
+            insert into labels ( name, uuid )
+            values ( ?1, ?2 )
+            returning *
+            "#,
+        )
+        .bind(name.clone())
+        .bind(uuid)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(label)
+    }
+    async fn all(&self) -> anyhow::Result<Vec<Label>> {
+        let labels = sqlx::query_as::<_, Label>(
+            r#"
+            select * from labels
+            order by labels.id asc;
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(labels)
+    }
+    async fn delete(&self, id: i64) -> anyhow::Result<()> {
+        let result = sqlx::query(
+            r#"
+          delete from labels where id=?1
+          "#,
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(id).into());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "mysql")]
+#[derive(Debug, Clone)]
+pub struct LabelRepositoryForMySql {
+    pool: sqlx::MySqlPool,
+}
+
+#[cfg(feature = "mysql")]
+impl LabelRepositoryForMySql {
+    pub fn new(pool: sqlx::MySqlPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[cfg(feature = "mysql")]
+#[async_trait]
+impl LabelRepository for LabelRepositoryForMySql {
+    async fn create(&self, name: String, uuid: Option<Uuid>) -> anyhow::Result<Label> {
+        let optional_label = sqlx::query_as::<_, Label>(
+            r#"
+        select * from labels where name = ?
+        "#,
+        )
+        .bind(name.clone())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        if let Some(label) = optional_label {
+            return Err(RepositoryError::Duplicate(label.id).into());
+        }
+
+        let result = sqlx::query(
+            r#"
+            insert into labels ( name, uuid )
+            values ( ?, ? )
+            "#,
+        )
+        .bind(name.clone())
+        .bind(uuid.clone())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(Label {
+            id: result.last_insert_id() as i64,
+            uuid,
+            name,
+        })
+    }
+    async fn all(&self) -> anyhow::Result<Vec<Label>> {
+        let labels = sqlx::query_as::<_, Label>(
+            r#"
+            select * from labels
+            order by labels.id asc;
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(labels)
+    }
+    async fn delete(&self, id: i64) -> anyhow::Result<()> {
+        let result = sqlx::query(
+            r#"
+          delete from labels where id=?
+          "#,
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(id).into());
+        }
+
+        Ok(())
+    }
+}
+
+/// How a label is stored in MongoDB's `labels` collection.
+#[cfg(feature = "mongodb")]
+#[derive(Debug, Serialize, Deserialize)]
+pub(super) struct LabelDocument {
+    #[serde(rename = "_id")]
+    id: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    uuid: Option<Uuid>,
+    name: String,
+}
+
+#[cfg(feature = "mongodb")]
+impl From<LabelDocument> for Label {
+    fn from(document: LabelDocument) -> Self {
+        Label {
+            id: document.id,
+            uuid: document.uuid,
+            name: document.name,
+        }
+    }
+}
+
+#[cfg(feature = "mongodb")]
+#[derive(Debug, Clone)]
+pub struct LabelRepositoryForMongo {
+    db: mongodb::Database,
+}
+
+#[cfg(feature = "mongodb")]
+impl LabelRepositoryForMongo {
+    pub fn new(db: mongodb::Database) -> Self {
+        Self { db }
+    }
+
+    fn labels(&self) -> mongodb::Collection<LabelDocument> {
+        self.db.collection("labels")
+    }
+}
+
+#[cfg(feature = "mongodb")]
+#[async_trait]
+impl LabelRepository for LabelRepositoryForMongo {
+    async fn create(&self, name: String, uuid: Option<Uuid>) -> anyhow::Result<Label> {
+        use mongodb::bson::doc;
+
+        let existing = self.labels().find_one(doc! { "name": &name }, None).await?;
+        if let Some(label) = existing {
+            return Err(RepositoryError::Duplicate(label.id).into());
+        }
+        let document = LabelDocument {
+            id: super::next_id(&self.db, "labels").await?,
+            uuid,
+            name,
+        };
+        self.labels().insert_one(&document, None).await?;
+
+        Ok(document.into())
+    }
+    async fn all(&self) -> anyhow::Result<Vec<Label>> {
+        use futures_util::TryStreamExt;
+        use mongodb::bson::doc;
+
+        let options = mongodb::options::FindOptions::builder()
+            .sort(doc! { "_id": 1 })
+            .build();
+        let labels = self
+            .labels()
+            .find(None, options)
+            .await?
+            .map_ok(Label::from)
+            .try_collect()
+            .await?;
+
+        Ok(labels)
+    }
+    async fn delete(&self, id: i64) -> anyhow::Result<()> {
+        use mongodb::bson::doc;
+
+        let result = self.labels().delete_one(doc! { "_id": id }, None).await?;
+        if result.deleted_count == 0 {
+            return Err(RepositoryError::NotFound(id).into());
+        }
+        // what `on delete cascade` does for the SQL backends
+        self.db
+            .collection::<mongodb::bson::Document>("todos")
+            .update_many(
+                doc! { "labels.id": id },
+                doc! { "$pull": { "labels": { "id": id } } },
+                None,
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// How a label is stored in the `labels` partition of a [`DynamoTable`].
+///
+/// [`DynamoTable`]: super::DynamoTable
+#[cfg(feature = "dynamodb")]
+#[derive(Debug, Serialize, Deserialize)]
+struct LabelItem {
+    pk: String,
+    sk: String,
+    id: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    uuid: Option<Uuid>,
+    name: String,
+}
+
+#[cfg(feature = "dynamodb")]
+#[derive(Debug, Clone)]
+pub struct LabelRepositoryForDynamo {
+    table: super::DynamoTable,
+}
+
+#[cfg(feature = "dynamodb")]
+impl LabelRepositoryForDynamo {
+    pub fn new(table: super::DynamoTable) -> Self {
+        Self { table }
+    }
+}
+
+#[cfg(feature = "dynamodb")]
+#[async_trait]
+impl LabelRepository for LabelRepositoryForDynamo {
+    async fn create(&self, name: String, uuid: Option<Uuid>) -> anyhow::Result<Label> {
+        use super::DynamoTable;
+        use serde_dynamo::aws_sdk_dynamodb_1::to_item;
+
+        let id = self.table.next_id("labels").await?;
+        let item = LabelItem {
+            pk: "labels".to_string(),
+            sk: DynamoTable::sort_key("label", id),
+            id,
+            uuid,
+            name,
+        };
+        let mut steps = vec![
+            self.table.put_new(to_item(&item)?)?,
+            self.table
+                .put_new(DynamoTable::claim("label-names", &item.name, id))?,
+        ];
+        if let Some(uuid) = &item.uuid {
+            steps.push(
+                self.table
+                    .put_new(DynamoTable::claim("label-uuids", uuid.as_str(), id))?,
+            );
+        }
+        if !self.table.transact(steps).await? {
+            if let Some(existing) = self.table.claimed_by("label-names", &item.name).await? {
+                return Err(RepositoryError::Duplicate(existing).into());
+            }
+            anyhow::bail!("label {} was created concurrently", id);
+        }
+
+        Ok(Label {
+            id,
+            uuid: item.uuid,
+            name: item.name,
+        })
+    }
+    async fn all(&self) -> anyhow::Result<Vec<Label>> {
+        let items: Vec<LabelItem> = serde_dynamo::aws_sdk_dynamodb_1::from_items(
+            self.table.partition("labels", true).await?,
+        )?;
+
+        Ok(items
+            .into_iter()
+            .map(|item| Label {
+                id: item.id,
+                uuid: item.uuid,
+                name: item.name,
+            })
+            .collect())
+    }
+    async fn delete(&self, id: i64) -> anyhow::Result<()> {
+        use super::DynamoTable;
+        use serde_dynamo::aws_sdk_dynamodb_1::{from_item, from_items, to_attribute_value};
+
+        let sk = DynamoTable::sort_key("label", id);
+        let item: LabelItem = match self.table.get("labels", &sk).await? {
+            Some(item) => from_item(item)?,
+            None => return Err(RepositoryError::NotFound(id).into()),
+        };
+        let mut steps = vec![
+            self.table.delete("labels", sk)?,
+            self.table.delete("label-names", &item.name)?,
+        ];
+        if let Some(uuid) = &item.uuid {
+            steps.push(self.table.delete("label-uuids", uuid.as_str())?);
+        }
+        self.table.transact(steps).await?;
+
+        // what `on delete cascade` does for the SQL backends
+        #[derive(Deserialize)]
+        struct Labelled {
+            sk: String,
+            #[serde(default)]
+            labels: Vec<Label>,
+        }
+        let todos: Vec<Labelled> = from_items(self.table.partition("todos", true).await?)?;
+        for mut todo in todos {
+            if !todo.labels.iter().any(|label| label.id == id) {
+                continue;
+            }
+            todo.labels.retain(|label| label.id != id);
+            let updated = self
+                .table
+                .client()
+                .update_item()
+                .table_name(self.table.name())
+                .set_key(Some(DynamoTable::key("todos", todo.sk)))
+                .update_expression("SET labels = :labels")
+                .condition_expression("attribute_exists(pk)")
+                .expression_attribute_values(":labels", to_attribute_value(&todo.labels)?)
+                .send()
+                .await;
+            match updated {
+                // deleted meanwhile, along with its labels
+                Err(aws_sdk_dynamodb::error::SdkError::ServiceError(e))
+                    if e.err().is_conditional_check_failed_exception() => {}
+                updated => {
+                    updated?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+    async fn resolve(&self, key: &Key) -> anyhow::Result<i64> {
+        match key {
+            Key::Serial(id) => Ok(*id),
+            Key::Uuid(uuid) => self
+                .table
+                .claimed_by("label-uuids", uuid.as_str())
+                .await?
+                .ok_or_else(|| RepositoryError::UnknownUuid(uuid.clone()).into()),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -100,6 +559,39 @@ mod test {
     use sqlx::PgPool;
     use std::env;
 
+    #[tokio::test]
+    async fn memory_never_reuses_ids() {
+        let repository = LabelRepositoryForMemory::new();
+        let work = repository.create("work".to_string(), None).await.unwrap();
+        let home = repository.create("home".to_string(), None).await.unwrap();
+        repository.delete(work.id).await.unwrap();
+
+        let play = repository.create("play".to_string(), None).await.unwrap();
+        assert!(play.id > home.id);
+        assert_eq!(repository.all().await.unwrap(), vec![home, play]);
+    }
+
+    #[tokio::test]
+    async fn sqlite_crud_scenario() {
+        let pool = crate::repositories::connect_sqlite(
+            "sqlite::memory:",
+            &crate::repositories::PoolSettings::default(),
+            crate::repositories::Migrations::Apply,
+        )
+        .await
+        .expect("failed open sqlite");
+        let repository = LabelRepositoryForSqlite::new(pool);
+
+        let created = repository.create("work".to_string(), None).await.unwrap();
+        assert_eq!(created.name, "work");
+        assert!(repository.create("work".to_string(), None).await.is_err());
+        assert_eq!(repository.all().await.unwrap(), vec![created.clone()]);
+
+        repository.delete(created.id).await.unwrap();
+        assert!(repository.all().await.unwrap().is_empty());
+        assert!(repository.delete(created.id).await.is_err());
+    }
+
     #[tokio::test]
     async fn crud_scenario() {
         dotenv().ok();
@@ -113,14 +605,17 @@ mod test {
         let repository = LabelRepositoryForDb::new(pool.clone());
         let label_text = "test_label";
 
-        let created = repository.create(label_text.to_string()).await.unwrap();
+        let created = repository
+            .create(label_text.to_string(), None)
+            .await
+            .unwrap();
 
-        assert_eq!(created.text, label_text.to_string());
+        assert_eq!(created.name, label_text.to_string());
 
         let all = repository.all().await.unwrap();
 
         let label = all.last().unwrap();
-        assert_eq!(label.text, created.text);
+        assert_eq!(label.name, created.name);
 
         repository.delete(label.id).await.unwrap();
     }
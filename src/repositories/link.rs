@@ -0,0 +1,316 @@
+use std::fmt::Debug;
+
+use axum::async_trait;
+use sqlx::{PgPool, SqlitePool};
+
+use super::{
+    todo::{Todo, TodoRepository},
+    RepositoryError,
+};
+
+/// Which todo stands for which thing of a synced service, an event or an
+/// issue, and where the service's last sync left off. `service` tells the
+/// synced services apart, e.g. `gcal:<calendar id>`; `external` is the
+/// service's own id.
+#[async_trait]
+pub trait LinkRepository: Debug + Send + Sync + 'static {
+    /// The todo linked to `external`.
+    async fn todo(&self, service: &str, external: &str) -> anyhow::Result<Option<i64>>;
+    /// What of `service` todo `todo_id` is linked to.
+    async fn externals(&self, service: &str, todo_id: i64) -> anyhow::Result<Vec<String>>;
+    /// Links `external` to todo `todo_id`, in place of any other todo.
+    async fn link(&self, service: &str, external: &str, todo_id: i64) -> anyhow::Result<()>;
+    /// Drops every link of todo `todo_id`, once it is deleted.
+    async fn unlink(&self, todo_id: i64) -> anyhow::Result<()>;
+    /// The token the next sync with `service` carries on from.
+    async fn sync_token(&self, service: &str) -> anyhow::Result<Option<String>>;
+    async fn set_sync_token(&self, service: &str, token: &str) -> anyhow::Result<()>;
+}
+
+/// Todo `id` of a link, or `None` once it was deleted or archived.
+pub async fn linked_todo<T: TodoRepository>(todos: &T, id: i64) -> anyhow::Result<Option<Todo>> {
+    match todos.find(id).await {
+        Ok(todo) => Ok(Some(todo)),
+        Err(e) if matches!(e.downcast_ref(), Some(RepositoryError::NotFound(_))) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LinkRepositoryForDb {
+    pool: PgPool,
+}
+
+impl LinkRepositoryForDb {
+    pub fn new(pool: PgPool) -> Self {
+        LinkRepositoryForDb { pool }
+    }
+}
+
+#[async_trait]
+impl LinkRepository for LinkRepositoryForDb {
+    async fn todo(&self, service: &str, external: &str) -> anyhow::Result<Option<i64>> {
+        let id = sqlx::query_scalar("select todo_id from links where service=$1 and external=$2")
+            .bind(service)
+            .bind(external)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(id)
+    }
+    async fn externals(&self, service: &str, todo_id: i64) -> anyhow::Result<Vec<String>> {
+        let externals = sqlx::query_scalar(
+            "select external from links where service=$1 and todo_id=$2 order by external",
+        )
+        .bind(service)
+        .bind(todo_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(externals)
+    }
+    async fn link(&self, service: &str, external: &str, todo_id: i64) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            insert into links (service, external, todo_id) values ($1, $2, $3)
+            on conflict (service, external) do update set todo_id=excluded.todo_id
+        "#,
+        )
+        .bind(service)
+        .bind(external)
+        .bind(todo_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+    async fn unlink(&self, todo_id: i64) -> anyhow::Result<()> {
+        sqlx::query("delete from links where todo_id=$1")
+            .bind(todo_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+    async fn sync_token(&self, service: &str) -> anyhow::Result<Option<String>> {
+        let token = sqlx::query_scalar("select token from sync_tokens where service=$1")
+            .bind(service)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(token)
+    }
+    async fn set_sync_token(&self, service: &str, token: &str) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            insert into sync_tokens (service, token) values ($1, $2)
+            on conflict (service) do update set token=excluded.token
+        "#,
+        )
+        .bind(service)
+        .bind(token)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LinkRepositoryForSqlite {
+    pool: SqlitePool,
+}
+
+impl LinkRepositoryForSqlite {
+    pub fn new(pool: SqlitePool) -> Self {
+        LinkRepositoryForSqlite { pool }
+    }
+}
+
+#[async_trait]
+impl LinkRepository for LinkRepositoryForSqlite {
+    async fn todo(&self, service: &str, external: &str) -> anyhow::Result<Option<i64>> {
+        let id = sqlx::query_scalar("select todo_id from links where service=?1 and external=?2")
+            .bind(service)
+            .bind(external)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(id)
+    }
+    async fn externals(&self, service: &str, todo_id: i64) -> anyhow::Result<Vec<String>> {
+        let externals = sqlx::query_scalar(
+            "select external from links where service=?1 and todo_id=?2 order by external",
+        )
+        .bind(service)
+        .bind(todo_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(externals)
+    }
+    async fn link(&self, service: &str, external: &str, todo_id: i64) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            insert into links (service, external, todo_id) values (?1, ?2, ?3)
+            on conflict (service, external) do update set todo_id=excluded.todo_id
+        "#,
+        )
+        .bind(service)
+        .bind(external)
+        .bind(todo_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+    async fn unlink(&self, todo_id: i64) -> anyhow::Result<()> {
+        sqlx::query("delete from links where todo_id=?1")
+            .bind(todo_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+    async fn sync_token(&self, service: &str) -> anyhow::Result<Option<String>> {
+        let token = sqlx::query_scalar("select token from sync_tokens where service=?1")
+            .bind(service)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(token)
+    }
+    async fn set_sync_token(&self, service: &str, token: &str) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            insert into sync_tokens (service, token) values (?1, ?2)
+            on conflict (service) do update set token=excluded.token
+        "#,
+        )
+        .bind(service)
+        .bind(token)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "mysql")]
+#[derive(Debug, Clone)]
+pub struct LinkRepositoryForMySql {
+    pool: sqlx::MySqlPool,
+}
+
+#[cfg(feature = "mysql")]
+impl LinkRepositoryForMySql {
+    pub fn new(pool: sqlx::MySqlPool) -> Self {
+        LinkRepositoryForMySql { pool }
+    }
+}
+
+#[cfg(feature = "mysql")]
+#[async_trait]
+impl LinkRepository for LinkRepositoryForMySql {
+    async fn todo(&self, service: &str, external: &str) -> anyhow::Result<Option<i64>> {
+        let id = sqlx::query_scalar("select todo_id from links where service=? and external=?")
+            .bind(service)
+            .bind(external)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(id)
+    }
+    async fn externals(&self, service: &str, todo_id: i64) -> anyhow::Result<Vec<String>> {
+        let externals = sqlx::query_scalar(
+            "select external from links where service=? and todo_id=? order by external",
+        )
+        .bind(service)
+        .bind(todo_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(externals)
+    }
+    async fn link(&self, service: &str, external: &str, todo_id: i64) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            insert into links (service, external, todo_id) values (?, ?, ?)
+            on duplicate key update todo_id=values(todo_id)
+        "#,
+        )
+        .bind(service)
+        .bind(external)
+        .bind(todo_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+    async fn unlink(&self, todo_id: i64) -> anyhow::Result<()> {
+        sqlx::query("delete from links where todo_id=?")
+            .bind(todo_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+    async fn sync_token(&self, service: &str) -> anyhow::Result<Option<String>> {
+        let token = sqlx::query_scalar("select token from sync_tokens where service=?")
+            .bind(service)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(token)
+    }
+    async fn set_sync_token(&self, service: &str, token: &str) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            insert into sync_tokens (service, token) values (?, ?)
+            on duplicate key update token=values(token)
+        "#,
+        )
+        .bind(service)
+        .bind(token)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::repositories::todo::{CreateTodo, TodoRepositoryForSqlite};
+
+    #[tokio::test]
+    async fn links_todos_and_keeps_sync_tokens() {
+        let pool = crate::repositories::connect_sqlite(
+            "sqlite::memory:",
+            &crate::repositories::PoolSettings::default(),
+            crate::repositories::Migrations::Apply,
+        )
+        .await
+        .expect("failed open sqlite");
+        let todos = TodoRepositoryForSqlite::new(pool.clone());
+        let repository = LinkRepositoryForSqlite::new(pool);
+        let todo = todos
+            .create(CreateTodo::new("Dentist".to_string()))
+            .await
+            .unwrap();
+
+        assert_eq!(repository.todo("gcal:a", "dentist").await.unwrap(), None);
+        repository.link("gcal:a", "dentist", 7).await.unwrap();
+        repository
+            .link("gcal:a", "dentist", todo.id())
+            .await
+            .unwrap();
+        repository.link("gcal:b", "dentist", 7).await.unwrap();
+        assert_eq!(
+            repository.todo("gcal:a", "dentist").await.unwrap(),
+            Some(todo.id())
+        );
+        assert_eq!(
+            repository.externals("gcal:a", todo.id()).await.unwrap(),
+            vec!["dentist"]
+        );
+        assert!(linked_todo(&todos, todo.id()).await.unwrap().is_some());
+
+        todos.delete(todo.id()).await.unwrap();
+        assert!(linked_todo(&todos, todo.id()).await.unwrap().is_none());
+        repository.unlink(todo.id()).await.unwrap();
+        assert_eq!(repository.todo("gcal:a", "dentist").await.unwrap(), None);
+        assert_eq!(repository.todo("gcal:b", "dentist").await.unwrap(), Some(7));
+
+        assert_eq!(repository.sync_token("gcal:a").await.unwrap(), None);
+        repository.set_sync_token("gcal:a", "s1").await.unwrap();
+        repository.set_sync_token("gcal:a", "s2").await.unwrap();
+        assert_eq!(
+            repository.sync_token("gcal:a").await.unwrap().as_deref(),
+            Some("s2")
+        );
+    }
+}
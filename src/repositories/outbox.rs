@@ -0,0 +1,283 @@
+use std::time::Duration;
+
+use axum::async_trait;
+use serde::Serialize;
+use sqlx::{types::Json, FromRow, PgPool, SqlitePool};
+
+use crate::events::TodoEvent;
+
+/// The queue of todo events waiting for delivery. Events are added by the
+/// todo repositories, inside the transaction of the change itself, so an
+/// event is queued if and only if its change committed.
+#[async_trait]
+pub trait OutboxRepository: Clone + std::marker::Send + std::marker::Sync + 'static {
+    /// Up to `limit` events due for a delivery attempt, oldest first.
+    async fn due(&self, limit: i64) -> anyhow::Result<Vec<OutboxEntry>>;
+    /// Takes a delivered event off the queue.
+    async fn delivered(&self, id: i64) -> anyhow::Result<()>;
+    /// Records a failed attempt; the event is due again after `retry_in`.
+    async fn failed(&self, id: i64, error: &str, retry_in: Duration) -> anyhow::Result<()>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, FromRow)]
+pub struct OutboxEntry {
+    /// Increases with every event, so receivers can drop redeliveries.
+    pub id: i64,
+    pub event: Json<TodoEvent>,
+    /// Failed attempts so far.
+    pub attempts: i32,
+}
+
+/// Queues `events` as part of `tx`.
+pub(super) async fn enqueue_pg(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    events: &[String],
+) -> anyhow::Result<()> {
+    sqlx::query(
+        "insert into outbox (event) select event::jsonb from unnest($1::text[]) as events(event)",
+    )
+    .bind(events)
+    .execute(&mut *tx)
+    .await?;
+    Ok(())
+}
+
+/// SQLite counterpart of [`enqueue_pg`], one insert per event.
+pub(super) async fn enqueue_sqlite(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    events: &[TodoEvent],
+) -> anyhow::Result<()> {
+    for event in events {
+        sqlx::query("insert into outbox (event) values (?1)")
+            .bind(Json(event))
+            .execute(&mut *tx)
+            .await?;
+    }
+    Ok(())
+}
+
+/// MySQL counterpart of [`enqueue_pg`], one insert per event.
+#[cfg(feature = "mysql")]
+pub(super) async fn enqueue_mysql(
+    tx: &mut sqlx::Transaction<'_, sqlx::MySql>,
+    events: &[TodoEvent],
+) -> anyhow::Result<()> {
+    for event in events {
+        sqlx::query("insert into outbox (event) values (?)")
+            .bind(Json(event))
+            .execute(&mut *tx)
+            .await?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub struct OutboxRepositoryForDb {
+    pool: PgPool,
+}
+
+impl OutboxRepositoryForDb {
+    pub fn new(pool: PgPool) -> Self {
+        OutboxRepositoryForDb { pool }
+    }
+}
+
+#[async_trait]
+impl OutboxRepository for OutboxRepositoryForDb {
+    async fn due(&self, limit: i64) -> anyhow::Result<Vec<OutboxEntry>> {
+        let entries = sqlx::query_as::<_, OutboxEntry>(
+            r#"
+            select id, event, attempts from outbox
+            where next_attempt_at <= now()
+            order by id
+            limit $1
+        "#,
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(entries)
+    }
+    async fn delivered(&self, id: i64) -> anyhow::Result<()> {
+        sqlx::query("delete from outbox where id=$1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+    async fn failed(&self, id: i64, error: &str, retry_in: Duration) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            update outbox set attempts=attempts+1, last_error=$1,
+                next_attempt_at=now() + make_interval(secs => $2)
+            where id=$3
+        "#,
+        )
+        .bind(error)
+        .bind(retry_in.as_secs_f64())
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OutboxRepositoryForSqlite {
+    pool: SqlitePool,
+}
+
+impl OutboxRepositoryForSqlite {
+    pub fn new(pool: SqlitePool) -> Self {
+        OutboxRepositoryForSqlite { pool }
+    }
+}
+
+#[async_trait]
+impl OutboxRepository for OutboxRepositoryForSqlite {
+    async fn due(&self, limit: i64) -> anyhow::Result<Vec<OutboxEntry>> {
+        let entries = sqlx::query_as::<_, OutboxEntry>(
+            r#"
+            select id, event, attempts from outbox
+            where next_attempt_at <= cast(strftime('%s', 'now') as integer)
+            order by id
+            limit ?1
+        "#,
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(entries)
+    }
+    async fn delivered(&self, id: i64) -> anyhow::Result<()> {
+        sqlx::query("delete from outbox where id=?1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+    async fn failed(&self, id: i64, error: &str, retry_in: Duration) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            update outbox set attempts=attempts+1, last_error=?1,
+                next_attempt_at=cast(strftime('%s', 'now') as integer) + ?2
+            where id=?3
+        "#,
+        )
+        .bind(error)
+        .bind(retry_in.as_secs() as i64)
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "mysql")]
+#[derive(Debug, Clone)]
+pub struct OutboxRepositoryForMySql {
+    pool: sqlx::MySqlPool,
+}
+
+#[cfg(feature = "mysql")]
+impl OutboxRepositoryForMySql {
+    pub fn new(pool: sqlx::MySqlPool) -> Self {
+        OutboxRepositoryForMySql { pool }
+    }
+}
+
+#[cfg(feature = "mysql")]
+#[async_trait]
+impl OutboxRepository for OutboxRepositoryForMySql {
+    async fn due(&self, limit: i64) -> anyhow::Result<Vec<OutboxEntry>> {
+        let entries = sqlx::query_as::<_, OutboxEntry>(
+            r#"
+            select id, event, attempts from outbox
+            where next_attempt_at <= current_timestamp
+            order by id
+            limit ?
+        "#,
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(entries)
+    }
+    async fn delivered(&self, id: i64) -> anyhow::Result<()> {
+        sqlx::query("delete from outbox where id=?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+    async fn failed(&self, id: i64, error: &str, retry_in: Duration) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            update outbox set attempts=attempts+1, last_error=?,
+                next_attempt_at=current_timestamp + interval ? second
+            where id=?
+        "#,
+        )
+        .bind(error)
+        .bind(retry_in.as_secs() as i64)
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+
+    use dotenv::dotenv;
+
+    use super::*;
+    use crate::repositories::todo::{CreateTodo, TodoRepository, TodoRepositoryForDb};
+
+    #[tokio::test]
+    async fn queues_events_with_the_change() {
+        dotenv().ok();
+        let database_url = &env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let pool = PgPool::connect(database_url)
+            .await
+            .expect("failed connect database");
+        let todos = TodoRepositoryForDb::new(pool.clone()).with_outbox();
+        let outbox = OutboxRepositoryForDb::new(pool.clone());
+
+        let todo = todos
+            .create(CreateTodo::new(
+                "[queues_events_with_the_change]".to_string(),
+            ))
+            .await
+            .unwrap();
+        let created = Json(TodoEvent::Created { id: todo.id() });
+        let entry = outbox
+            .due(i64::MAX)
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|entry| entry.event == created)
+            .expect("queued with the todo");
+        assert_eq!(entry.attempts, 0);
+
+        outbox
+            .failed(entry.id, "connection refused", Duration::from_secs(60))
+            .await
+            .unwrap();
+        let due = outbox.due(i64::MAX).await.unwrap();
+        assert!(due.iter().all(|due| due.id != entry.id));
+
+        outbox.delivered(entry.id).await.unwrap();
+        todos.delete(todo.id()).await.unwrap();
+        sqlx::query("delete from outbox where event = $1")
+            .bind(Json(TodoEvent::Deleted { id: todo.id() }))
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,408 @@
+use std::{
+    collections::hash_map::RandomState,
+    future::Future,
+    hash::{BuildHasher, Hasher},
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+
+use axum::async_trait;
+use futures_util::stream::BoxStream;
+use sqlx::error::DatabaseError;
+
+use crate::config::DatabaseConfig;
+
+use super::{
+    id::{Key, Uuid},
+    job::{Job, JobKind, JobRepository, UpdateJob},
+    label::{Label, LabelRepository},
+    todo::{
+        CreateTodo, Import, ImportedRows, SearchHit, Todo, TodoFilter, TodoRepository,
+        TodoWithLabels, UpdateTodo,
+    },
+};
+
+/// What a failed call did to the database, as far as retrying goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Transient {
+    /// The server rolled the work back (serialization failure, deadlock) or
+    /// it never started (no connection to be had); repeating it is safe.
+    RolledBack,
+    /// The connection went away mid-call. A write may or may not have been
+    /// committed, so only reads are repeated.
+    Connection,
+}
+
+fn classify(e: &anyhow::Error) -> Option<Transient> {
+    let e = e.chain().find_map(|e| e.downcast_ref::<sqlx::Error>())?;
+    match e {
+        sqlx::Error::PoolTimedOut => Some(Transient::RolledBack),
+        sqlx::Error::Io(_) => Some(Transient::Connection),
+        sqlx::Error::Database(e) => {
+            if let Some(e) = e.try_downcast_ref::<sqlx::sqlite::SqliteError>() {
+                // SQLITE_BUSY and SQLITE_LOCKED, including their extended codes
+                let code: i32 = e.code()?.parse().ok()?;
+                return matches!(code & 0xff, 5 | 6).then_some(Transient::RolledBack);
+            }
+            match e.code()?.as_ref() {
+                // serialization_failure (MySQL deadlocks too), deadlock_detected
+                "40001" | "40P01" => Some(Transient::RolledBack),
+                // admin_shutdown, cannot_connect_now, connection exceptions
+                "57P01" | "57P03" => Some(Transient::Connection),
+                code if code.starts_with("08") => Some(Transient::Connection),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Token bucket limiting retries to a share of all calls, so an outage
+/// doesn't turn into a retry storm. Counted in thousandths of a token.
+#[derive(Debug)]
+struct RetryBudget {
+    balance: AtomicI64,
+    /// Earned by every call.
+    deposit: i64,
+    /// The most that can be saved up, also the starting balance.
+    max: i64,
+}
+
+const RETRY_COST: i64 = 1000;
+
+impl RetryBudget {
+    fn new(ratio: f64, max_tokens: u32) -> Self {
+        let max = i64::from(max_tokens) * RETRY_COST;
+        Self {
+            balance: AtomicI64::new(max),
+            deposit: (ratio * RETRY_COST as f64) as i64,
+            max,
+        }
+    }
+
+    fn deposit(&self) {
+        let _ = self
+            .balance
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |balance| {
+                Some((balance + self.deposit).min(self.max))
+            });
+    }
+
+    fn withdraw(&self) -> bool {
+        self.balance
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |balance| {
+                (balance >= RETRY_COST).then_some(balance - RETRY_COST)
+            })
+            .is_ok()
+    }
+}
+
+/// Retries repository calls that failed on a transient database error,
+/// sleeping a random "full jitter" backoff between attempts.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    budget: Arc<RetryBudget>,
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: u32, base_delay: Duration, max_delay: Duration) -> Self {
+        Self {
+            max_attempts,
+            base_delay,
+            max_delay,
+            // retries may add up to 20% on top of the calls, after a burst of 10
+            budget: Arc::new(RetryBudget::new(0.2, 10)),
+        }
+    }
+
+    /// `retry_attempts` attempts per call, backing off from
+    /// `retry_base_delay_ms` up to 2s.
+    pub fn from_config(config: &DatabaseConfig) -> Self {
+        if config.retry_attempts == 0 {
+            panic!("invalid [DB_RETRY_ATTEMPTS]: 0");
+        }
+        let base_delay = Duration::from_millis(config.retry_base_delay_ms.into());
+        Self::new(config.retry_attempts, base_delay, Duration::from_secs(2))
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        let ceiling = self
+            .base_delay
+            .saturating_mul(1 << attempt.min(16))
+            .min(self.max_delay);
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u32(attempt);
+        let fraction = (hasher.finish() % 1001) as f64 / 1000.0;
+        ceiling.mul_f64(fraction)
+    }
+
+    async fn run<T, F, Fut>(&self, write: bool, mut call: F) -> anyhow::Result<T>
+    where
+        F: FnMut() -> Fut + Send,
+        Fut: Future<Output = anyhow::Result<T>> + Send,
+    {
+        self.budget.deposit();
+        let mut attempt = 1;
+        loop {
+            let e = match call().await {
+                Ok(value) => return Ok(value),
+                Err(e) => e,
+            };
+            let retryable = match classify(&e) {
+                Some(Transient::RolledBack) => true,
+                Some(Transient::Connection) => !write,
+                None => false,
+            };
+            if !retryable || attempt >= self.max_attempts || !self.budget.withdraw() {
+                return Err(e);
+            }
+            let delay = self.backoff(attempt);
+            tracing::warn!(
+                "transient database error, retry {} in {:?}: {:#}",
+                attempt,
+                delay,
+                e
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+/// A repository whose calls go through a [`RetryPolicy`].
+#[derive(Debug, Clone)]
+pub struct Retrying<R> {
+    inner: R,
+    policy: RetryPolicy,
+}
+
+impl<R> Retrying<R> {
+    pub fn new(inner: R, policy: RetryPolicy) -> Self {
+        Self { inner, policy }
+    }
+}
+
+const READ: bool = false;
+const WRITE: bool = true;
+
+#[async_trait]
+impl<R: TodoRepository> TodoRepository for Retrying<R> {
+    async fn create(&self, payload: CreateTodo) -> anyhow::Result<Todo> {
+        self.policy
+            .run(WRITE, || self.inner.create(payload.clone()))
+            .await
+    }
+    async fn create_many(&self, payloads: Vec<CreateTodo>) -> anyhow::Result<Vec<Todo>> {
+        self.policy
+            .run(WRITE, || self.inner.create_many(payloads.clone()))
+            .await
+    }
+    async fn import<L: LabelRepository>(
+        &self,
+        labels: &L,
+        import: Import,
+    ) -> anyhow::Result<ImportedRows> {
+        self.policy
+            .run(WRITE, || self.inner.import(labels, import.clone()))
+            .await
+    }
+    async fn find(&self, id: i64) -> anyhow::Result<Todo> {
+        self.policy.run(READ, || self.inner.find(id)).await
+    }
+    async fn all(&self, filter: &TodoFilter) -> anyhow::Result<Vec<Todo>> {
+        self.policy.run(READ, || self.inner.all(filter)).await
+    }
+    async fn count(&self, filter: &TodoFilter) -> anyhow::Result<i64> {
+        self.policy.run(READ, || self.inner.count(filter)).await
+    }
+    fn stream_all(&self) -> BoxStream<'static, anyhow::Result<Todo>> {
+        // Part of the stream may already be on the wire, so it isn't repeated.
+        self.inner.stream_all()
+    }
+    async fn find_with_labels(&self, id: i64) -> anyhow::Result<TodoWithLabels> {
+        self.policy
+            .run(READ, || self.inner.find_with_labels(id))
+            .await
+    }
+    async fn all_with_labels(&self, filter: &TodoFilter) -> anyhow::Result<Vec<TodoWithLabels>> {
+        self.policy
+            .run(READ, || self.inner.all_with_labels(filter))
+            .await
+    }
+    async fn update(&self, id: i64, payload: UpdateTodo) -> anyhow::Result<Todo> {
+        self.policy
+            .run(WRITE, || self.inner.update(id, payload.clone()))
+            .await
+    }
+    async fn delete(&self, id: i64) -> anyhow::Result<()> {
+        self.policy.run(WRITE, || self.inner.delete(id)).await
+    }
+    async fn purge_completed(&self) -> anyhow::Result<u64> {
+        self.policy
+            .run(WRITE, || self.inner.purge_completed())
+            .await
+    }
+    async fn archive_completed(&self, older_than: Duration) -> anyhow::Result<u64> {
+        self.policy
+            .run(WRITE, || self.inner.archive_completed(older_than))
+            .await
+    }
+    async fn archived(&self) -> anyhow::Result<Vec<Todo>> {
+        self.policy.run(READ, || self.inner.archived()).await
+    }
+    async fn last_modified(&self, id: i64) -> anyhow::Result<SystemTime> {
+        self.policy.run(READ, || self.inner.last_modified(id)).await
+    }
+    async fn collection_last_modified(&self) -> anyhow::Result<SystemTime> {
+        self.policy
+            .run(READ, || self.inner.collection_last_modified())
+            .await
+    }
+    async fn recently_modified(&self, limit: i64) -> anyhow::Result<Vec<(Todo, SystemTime)>> {
+        self.policy
+            .run(READ, || self.inner.recently_modified(limit))
+            .await
+    }
+    async fn resolve(&self, key: &Key) -> anyhow::Result<i64> {
+        self.policy.run(READ, || self.inner.resolve(key)).await
+    }
+    async fn search(&self, filter: &TodoFilter, limit: i64) -> anyhow::Result<Vec<SearchHit>> {
+        self.policy
+            .run(READ, || self.inner.search(filter, limit))
+            .await
+    }
+}
+
+#[async_trait]
+impl<R: LabelRepository> LabelRepository for Retrying<R> {
+    async fn create(&self, name: String, uuid: Option<Uuid>) -> anyhow::Result<Label> {
+        self.policy
+            .run(WRITE, || self.inner.create(name.clone(), uuid.clone()))
+            .await
+    }
+    async fn all(&self) -> anyhow::Result<Vec<Label>> {
+        self.policy.run(READ, || self.inner.all()).await
+    }
+    async fn delete(&self, id: i64) -> anyhow::Result<()> {
+        self.policy.run(WRITE, || self.inner.delete(id)).await
+    }
+}
+
+#[async_trait]
+impl<R: JobRepository> JobRepository for Retrying<R> {
+    async fn create(&self, kind: JobKind) -> anyhow::Result<Job> {
+        self.policy.run(WRITE, || self.inner.create(kind)).await
+    }
+    async fn find(&self, id: i32) -> anyhow::Result<Job> {
+        self.policy.run(READ, || self.inner.find(id)).await
+    }
+    async fn update(&self, id: i32, payload: UpdateJob) -> anyhow::Result<Job> {
+        self.policy
+            .run(WRITE, || self.inner.update(id, payload.clone()))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::AtomicU32;
+
+    use super::*;
+    use crate::repositories::RepositoryError;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy::new(3, Duration::from_millis(1), Duration::from_millis(5))
+    }
+
+    async fn failing(
+        calls: &AtomicU32,
+        failures: u32,
+        e: fn() -> sqlx::Error,
+    ) -> anyhow::Result<u32> {
+        let call = calls.fetch_add(1, Ordering::SeqCst) + 1;
+        if call <= failures {
+            Err(e().into())
+        } else {
+            Ok(call)
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_transient_errors_up_to_the_limit() {
+        let policy = policy();
+        let calls = AtomicU32::new(0);
+        let result = policy
+            .run(WRITE, || failing(&calls, 2, || sqlx::Error::PoolTimedOut))
+            .await;
+        assert_eq!(result.unwrap(), 3);
+
+        let calls = AtomicU32::new(0);
+        let result = policy
+            .run(WRITE, || failing(&calls, 3, || sqlx::Error::PoolTimedOut))
+            .await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn repeats_writes_only_when_they_were_rolled_back() {
+        let policy = policy();
+        let dropped = || sqlx::Error::Io(std::io::ErrorKind::ConnectionReset.into());
+
+        let calls = AtomicU32::new(0);
+        assert!(policy
+            .run(WRITE, || failing(&calls, 1, dropped))
+            .await
+            .is_err());
+        let calls = AtomicU32::new(0);
+        assert!(policy
+            .run(READ, || failing(&calls, 1, dropped))
+            .await
+            .is_ok());
+
+        let calls = AtomicU32::new(0);
+        let result = policy
+            .run(READ, || async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err::<(), _>(RepositoryError::NotFound(1).into())
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn stops_retrying_once_the_budget_is_spent() {
+        let policy = policy();
+        for _ in 0..5 {
+            let calls = AtomicU32::new(0);
+            let _ = policy
+                .run(READ, || {
+                    failing(&calls, u32::MAX, || sqlx::Error::PoolTimedOut)
+                })
+                .await;
+        }
+        // Each run spent two retries and earned a fifth of one back, which
+        // leaves the sixth a single retry and the seventh none.
+        let calls = AtomicU32::new(0);
+        let _ = policy
+            .run(READ, || {
+                failing(&calls, u32::MAX, || sqlx::Error::PoolTimedOut)
+            })
+            .await;
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        let calls = AtomicU32::new(0);
+        let _ = policy
+            .run(READ, || {
+                failing(&calls, u32::MAX, || sqlx::Error::PoolTimedOut)
+            })
+            .await;
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
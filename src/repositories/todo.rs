@@ -1,30 +1,435 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
     sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
 };
 
 use anyhow::Context;
 use axum::async_trait;
+use futures_util::{stream::BoxStream, StreamExt};
 use serde::{Deserialize, Serialize};
-use sqlx::{FromRow, PgPool};
+use sqlx::{types::Json, FromRow, PgPool, SqlitePool};
 use validator::Validate;
 
-use super::RepositoryError;
+use super::{
+    date::Date,
+    id::{IdFormat, Key, Uuid},
+    label::{Label, LabelRepository},
+    not_found, outbox, RepositoryError,
+};
+use crate::{
+    events::{TodoEvent, CHANNEL},
+    handlers::escape,
+};
+#[cfg(any(feature = "mongodb", feature = "dynamodb"))]
+use futures_util::TryStreamExt;
+#[cfg(feature = "dynamodb")]
+use {
+    super::DynamoTable,
+    aws_sdk_dynamodb::types::{AttributeValue, ReturnValue},
+    serde_dynamo::aws_sdk_dynamodb_1::{from_item, from_items, to_attribute_value, to_item},
+};
+#[cfg(feature = "mongodb")]
+use {
+    super::{label::LabelDocument, next_id},
+    mongodb::{
+        bson::{self, doc},
+        options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument, UpdateOptions},
+    },
+};
 
 #[async_trait]
 pub trait TodoRepository: Clone + std::marker::Send + std::marker::Sync + 'static {
     async fn create(&self, payload: CreateTodo) -> anyhow::Result<Todo>;
-    async fn find(&self, id: i32) -> anyhow::Result<Todo>;
-    async fn all(&self) -> anyhow::Result<Vec<Todo>>;
-    async fn update(&self, id: i32, payload: UpdateTodo) -> anyhow::Result<Todo>;
-    async fn delete(&self, id: i32) -> anyhow::Result<()>;
+    /// Creates every todo in `payloads`, returned in the same order. This
+    /// default creates them one at a time; SQL backends insert them all in
+    /// one transaction, so either all or none are created.
+    async fn create_many(&self, payloads: Vec<CreateTodo>) -> anyhow::Result<Vec<Todo>> {
+        let mut todos = Vec::with_capacity(payloads.len());
+        for payload in payloads {
+            todos.push(self.create(payload).await?);
+        }
+        Ok(todos)
+    }
+    /// Stores `import`, its labels and then its todos. SQL backends store it
+    /// in one transaction, so either all of it or none is stored. This
+    /// default creates the labels through `labels` and then the todos with
+    /// `create_many`, so a failure leaves the labels created so far.
+    async fn import<L: LabelRepository>(
+        &self,
+        labels: &L,
+        import: Import,
+    ) -> anyhow::Result<ImportedRows> {
+        let existing = labels.all().await?;
+        let named: HashMap<_, _> = existing
+            .iter()
+            .map(|label| (label.name.clone(), label.id))
+            .collect();
+        let taken: HashSet<_> = existing
+            .into_iter()
+            .filter_map(|label| label.uuid)
+            .collect();
+        let mut imported = ImportedRows::default();
+        for label in import.labels {
+            let id = match named.get(&label.name) {
+                Some(&id) => id,
+                None => {
+                    let uuid = label.uuid.filter(|uuid| !taken.contains(uuid));
+                    imported.created_labels += 1;
+                    labels.create(label.name, uuid).await?.id
+                }
+            };
+            imported.label_ids.push(id);
+        }
+        let payloads = labelled(import.todos, &imported.label_ids);
+        imported.todos = self.create_many(payloads).await?;
+        Ok(imported)
+    }
+    async fn find(&self, id: i64) -> anyhow::Result<Todo>;
+    async fn all(&self, filter: &TodoFilter) -> anyhow::Result<Vec<Todo>>;
+    async fn count(&self, filter: &TodoFilter) -> anyhow::Result<i64>;
+    /// Every todo in id order, produced incrementally for exports.
+    fn stream_all(&self) -> BoxStream<'static, anyhow::Result<Todo>>;
+    async fn find_with_labels(&self, id: i64) -> anyhow::Result<TodoWithLabels>;
+    async fn all_with_labels(&self, filter: &TodoFilter) -> anyhow::Result<Vec<TodoWithLabels>>;
+    async fn update(&self, id: i64, payload: UpdateTodo) -> anyhow::Result<Todo>;
+    async fn delete(&self, id: i64) -> anyhow::Result<()>;
+    async fn purge_completed(&self) -> anyhow::Result<u64>;
+    /// Moves the todos completed and left alone for `older_than` to the
+    /// archive, unlinking their labels, and returns how many moved. This
+    /// default archives nothing; backends with an archive table override it.
+    async fn archive_completed(&self, _older_than: Duration) -> anyhow::Result<u64> {
+        Ok(0)
+    }
+    /// The archived todos, most recently archived first.
+    async fn archived(&self) -> anyhow::Result<Vec<Todo>> {
+        Ok(vec![])
+    }
+    async fn last_modified(&self, id: i64) -> anyhow::Result<SystemTime>;
+    async fn collection_last_modified(&self) -> anyhow::Result<SystemTime>;
+    /// Up to `limit` todos with their modification time, most recent first.
+    async fn recently_modified(&self, limit: i64) -> anyhow::Result<Vec<(Todo, SystemTime)>>;
+    /// The serial id a path [`Key`] refers to. This default scans every
+    /// todo; backends with a uuid index override it.
+    async fn resolve(&self, key: &Key) -> anyhow::Result<i64> {
+        match key {
+            Key::Serial(id) => Ok(*id),
+            Key::Uuid(uuid) => self
+                .all(&TodoFilter::default())
+                .await?
+                .into_iter()
+                .find(|todo| todo.uuid.as_ref() == Some(uuid))
+                .map(|todo| todo.id)
+                .ok_or_else(|| RepositoryError::UnknownUuid(uuid.clone()).into()),
+        }
+    }
+    /// Up to `limit` todos matching `filter`, best match for its text terms
+    /// first. This default ranks by how often the terms occur; backends with
+    /// a text index override it.
+    async fn search(&self, filter: &TodoFilter, limit: i64) -> anyhow::Result<Vec<SearchHit>> {
+        let mut hits: Vec<SearchHit> = self
+            .all(filter)
+            .await?
+            .into_iter()
+            .map(|todo| SearchHit::scan(todo, &filter.text))
+            .collect();
+        // stable, so equal ranks keep the newest first like `all`
+        hits.sort_by(|a, b| b.rank.total_cmp(&a.rank));
+        hits.truncate(limit.max(0) as usize);
+        Ok(hits)
+    }
+}
+
+/// A label [`TodoRepository::import`] stores, unless one of the same name
+/// is stored already.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportLabel {
+    pub name: String,
+    /// Left out when another label has it.
+    pub uuid: Option<Uuid>,
+}
+
+/// Labels and todos [`TodoRepository::import`] stores together. Each todo
+/// comes with its labels as indexes into `labels`.
+#[derive(Debug, Clone, Default)]
+pub struct Import {
+    pub labels: Vec<ImportLabel>,
+    pub todos: Vec<(CreateTodo, Vec<usize>)>,
+}
+
+/// What [`TodoRepository::import`] stored.
+#[derive(Debug, Default)]
+pub struct ImportedRows {
+    /// The id of every label, in the order of [`Import::labels`].
+    pub label_ids: Vec<i64>,
+    /// How many of them are new rather than found by name.
+    pub created_labels: usize,
+    /// In the order of [`Import::todos`].
+    pub todos: Vec<Todo>,
+}
+
+/// The payloads of `todos`, their label indexes swapped for `label_ids`.
+fn labelled(todos: Vec<(CreateTodo, Vec<usize>)>, label_ids: &[i64]) -> Vec<CreateTodo> {
+    todos
+        .into_iter()
+        .map(|(payload, labels)| {
+            payload.with_labels(labels.into_iter().map(|i| label_ids[i]).collect())
+        })
+        .collect()
+}
+
+/// A [`TodoRepository::search`] result. `highlight` is the text escaped as
+/// HTML, with the matched words wrapped in `<mark>` tags.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct SearchHit {
+    #[serde(flatten)]
+    pub todo: Todo,
+    pub rank: f32,
+    pub highlight: String,
+}
+
+impl SearchHit {
+    pub(super) fn scan(todo: Todo, terms: &[String]) -> Self {
+        let lower = todo.text.to_lowercase();
+        let mut marks: Vec<(usize, usize)> = Vec::new();
+        // Offsets into `lower` only carry over when lowercasing kept the length.
+        if lower.len() == todo.text.len() {
+            for term in terms.iter().map(|term| term.to_lowercase()) {
+                if term.is_empty() {
+                    continue;
+                }
+                marks.extend(
+                    lower
+                        .match_indices(&term)
+                        .map(|(start, _)| (start, start + term.len()))
+                        .filter(|(start, end)| {
+                            todo.text.is_char_boundary(*start) && todo.text.is_char_boundary(*end)
+                        }),
+                );
+            }
+        }
+        let rank = marks.len() as f32;
+
+        marks.sort_unstable();
+        let mut highlight = String::new();
+        let mut copied = 0;
+        for (start, end) in marks {
+            if end <= copied {
+                continue;
+            }
+            let start = start.max(copied);
+            highlight.push_str(&escape(&todo.text[copied..start]));
+            highlight.push_str("<mark>");
+            highlight.push_str(&escape(&todo.text[start..end]));
+            highlight.push_str("</mark>");
+            copied = end;
+        }
+        highlight.push_str(&escape(&todo.text[copied..]));
+
+        Self {
+            todo,
+            rank,
+            highlight,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, FromRow)]
 pub struct Todo {
-    id: i32,
+    id: i64,
+    /// Only set for todos created with `ID_FORMAT=uuid`, or backfilled when
+    /// it was switched on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    uuid: Option<Uuid>,
+    text: String,
+    completed: bool,
+    /// The day the todo should be done by.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    due: Option<Date>,
+    /// Bumped by every update; see [`UpdateTodo::with_version`].
+    #[serde(default = "first_version")]
+    version: i32,
+}
+
+fn first_version() -> i32 {
+    1
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct TodoWithLabels {
+    #[serde(flatten)]
+    pub todo: Todo,
+    pub labels: Vec<Label>,
+}
+
+impl AsRef<Todo> for Todo {
+    fn as_ref(&self) -> &Todo {
+        self
+    }
+}
+
+impl AsRef<Todo> for TodoWithLabels {
+    fn as_ref(&self) -> &Todo {
+        &self.todo
+    }
+}
+
+/// Narrows `all`/`all_with_labels`; every condition must hold. The default
+/// filter matches every todo.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TodoFilter {
+    pub completed: Option<bool>,
+    /// Label names the todo must carry, all of them.
+    pub labels: Vec<String>,
+    /// Case-insensitive substrings the text must contain, all of them.
+    pub text: Vec<String>,
+    /// Exactly these todos, returned in this order.
+    pub ids: Option<Vec<i64>>,
+    /// Todos due before this day; undated todos never are.
+    pub due_before: Option<Date>,
+    /// Todos due after this day.
+    pub due_after: Option<Date>,
+}
+
+impl TodoFilter {
+    fn matches(&self, todo: &Todo, labels: &[Label]) -> bool {
+        let text = todo.text.to_lowercase();
+        self.completed
+            .is_none_or(|completed| todo.completed == completed)
+            && self
+                .labels
+                .iter()
+                .all(|name| labels.iter().any(|label| &label.name == name))
+            && self
+                .text
+                .iter()
+                .all(|word| text.contains(&word.to_lowercase()))
+            && self.ids.as_ref().is_none_or(|ids| ids.contains(&todo.id))
+            && self
+                .due_before
+                .as_ref()
+                .is_none_or(|day| todo.due.as_ref().is_some_and(|due| due < day))
+            && self
+                .due_after
+                .as_ref()
+                .is_none_or(|day| todo.due.as_ref().is_some_and(|due| due > day))
+    }
+}
+
+#[derive(Debug, FromRow)]
+struct SearchHitFromRow {
+    id: i64,
+    uuid: Option<Uuid>,
+    text: String,
+    completed: bool,
+    due: Option<Date>,
+    version: i32,
+    rank: f32,
+}
+
+#[derive(Debug, FromRow)]
+struct RecentTodoFromRow {
+    id: i64,
+    uuid: Option<Uuid>,
+    text: String,
+    completed: bool,
+    due: Option<Date>,
+    version: i32,
+    modified_secs: i64,
+}
+
+/// `where` conditions for a [`TodoFilter`] bound as `$1` (completed), `$2`
+/// (label names), `$3` (text terms), `$4` (ids), `$5` (due before) and `$6`
+/// (due after) against the `todos` table. [`FILTER_ORDER`] keeps the
+/// requested id order. Days compare as text, which `YYYY-MM-DD` sorts by.
+const FILTER_CONDITIONS: &str = r#"
+    ($1::boolean is null or todos.completed = $1)
+    and not exists (
+        select 1 from unnest($2::text[]) as wanted(name)
+        where not exists (
+            select 1 from todo_labels tl
+                join labels on labels.id = tl.label_id
+            where tl.todo_id = todos.id and labels.name = wanted.name
+        )
+    )
+    and not exists (
+        select 1 from unnest($3::text[]) as term(word)
+        where strpos(lower(todos.text), lower(term.word)) = 0
+    )
+    and ($4::bigint[] is null or todos.id = any($4))
+    and ($5::text is null or todos.due < $5)
+    and ($6::text is null or todos.due > $6)
+"#;
+
+const FILTER_ORDER: &str = "array_position($4::bigint[], todos.id)";
+
+/// SQLite counterparts of [`FILTER_CONDITIONS`] and [`FILTER_ORDER`]; the
+/// list parameters are bound as JSON arrays and expanded with `json_each`.
+const SQLITE_FILTER_CONDITIONS: &str = r#"
+    (?1 is null or todos.completed = ?1)
+    and not exists (
+        select 1 from json_each(?2) as wanted
+        where not exists (
+            select 1 from todo_labels tl
+                join labels on labels.id = tl.label_id
+            where tl.todo_id = todos.id and labels.name = wanted.value
+        )
+    )
+    and not exists (
+        select 1 from json_each(?3) as term
+        where instr(lower(todos.text), lower(term.value)) = 0
+    )
+    and (?4 is null or todos.id in (select value from json_each(?4)))
+    and (?5 is null or todos.due < ?5)
+    and (?6 is null or todos.due > ?6)
+"#;
+
+const SQLITE_FILTER_ORDER: &str = "(select key from json_each(?4) where value = todos.id)";
+
+/// `filter`'s list members as the JSON arrays [`SQLITE_FILTER_CONDITIONS`]
+/// expects.
+fn sqlite_filter_params(filter: &TodoFilter) -> (String, String, Option<String>) {
+    (
+        serde_json::to_string(&filter.labels).unwrap_or_default(),
+        serde_json::to_string(&filter.text).unwrap_or_default(),
+        filter
+            .ids
+            .as_ref()
+            .map(|ids| serde_json::to_string(ids).unwrap_or_default()),
+    )
+}
+
+/// A todo with its labels aggregated into a JSON array, so loading any
+/// number of todos with labels is a single query returning one row each.
+#[derive(Debug, FromRow)]
+struct TodoWithLabelsFromRow {
+    id: i64,
+    uuid: Option<Uuid>,
     text: String,
     completed: bool,
+    due: Option<Date>,
+    version: i32,
+    labels: Json<Vec<Label>>,
+}
+
+impl From<TodoWithLabelsFromRow> for TodoWithLabels {
+    fn from(row: TodoWithLabelsFromRow) -> Self {
+        let Json(mut labels) = row.labels;
+        // not every backend orders inside the aggregate
+        labels.sort_by_key(|label| label.id);
+        TodoWithLabels {
+            todo: Todo {
+                id: row.id,
+                uuid: row.uuid,
+                text: row.text,
+                completed: row.completed,
+                due: row.due,
+                version: row.version,
+            },
+            labels,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Validate)]
@@ -32,12 +437,61 @@ pub struct CreateTodo {
     #[validate(length(min = 1, message = "can not be empty"))]
     #[validate(length(max = 100, message = "can not be over 100"))]
     text: String,
+    /// Labels attached together with the todo, in the same transaction.
+    #[serde(default)]
+    labels: Vec<i64>,
+    /// A uuid the client minted itself, e.g. while offline; one is
+    /// generated when `ID_FORMAT=uuid` and this is absent.
+    #[serde(default)]
+    uuid: Option<Uuid>,
+    /// Stores the todo as done from the start, e.g. when it is imported.
+    #[serde(default)]
+    completed: bool,
+    #[serde(default)]
+    due: Option<Date>,
 }
 
-#[cfg(test)]
 impl CreateTodo {
     pub fn new(text: String) -> Self {
-        Self { text }
+        Self {
+            text,
+            labels: vec![],
+            uuid: None,
+            completed: false,
+            due: None,
+        }
+    }
+
+    /// Makes sure the todo gets a uuid when `format` hands those out.
+    pub fn with_format(mut self, format: IdFormat) -> Self {
+        if format == IdFormat::Uuid {
+            self.uuid.get_or_insert_with(Uuid::now_v7);
+        }
+        self
+    }
+
+    pub fn with_labels(mut self, labels: Vec<i64>) -> Self {
+        self.labels = labels;
+        self
+    }
+
+    pub fn with_uuid(mut self, uuid: Option<Uuid>) -> Self {
+        self.uuid = uuid;
+        self
+    }
+
+    pub fn with_completed(mut self, completed: bool) -> Self {
+        self.completed = completed;
+        self
+    }
+
+    pub fn with_due(mut self, due: Option<Date>) -> Self {
+        self.due = due;
+        self
+    }
+
+    pub(super) fn text_mut(&mut self) -> &mut String {
+        &mut self.text
     }
 }
 
@@ -47,37 +501,254 @@ pub struct UpdateTodo {
     #[validate(length(max = 100, message = "can not be over 100"))]
     text: Option<String>,
     completed: Option<bool>,
+    /// Sets the due date when present, clears it when `null`; absent keeps it.
+    #[serde(
+        default,
+        deserialize_with = "present",
+        skip_serializing_if = "Option::is_none"
+    )]
+    due: Option<Option<Date>>,
+    /// Replaces the todo's labels when present; absent keeps them as they are.
+    #[serde(default)]
+    labels: Option<Vec<i64>>,
+    /// The version the client last saw; when present the update only
+    /// applies if nobody changed the todo since.
+    #[serde(default)]
+    version: Option<i32>,
+}
+
+impl UpdateTodo {
+    pub fn new(text: Option<String>, completed: Option<bool>) -> Self {
+        Self {
+            text,
+            completed,
+            due: None,
+            labels: None,
+            version: None,
+        }
+    }
+
+    pub fn with_version(mut self, version: i32) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    /// Sets the due date, or clears it with `None`.
+    pub fn with_due(mut self, due: Option<Date>) -> Self {
+        self.due = Some(due);
+        self
+    }
+
+    #[cfg(test)]
+    pub fn with_labels(mut self, labels: Vec<i64>) -> Self {
+        self.labels = Some(labels);
+        self
+    }
+
+    pub(super) fn text_mut(&mut self) -> Option<&mut String> {
+        self.text.as_mut()
+    }
+}
+
+/// Tells a `null` field from an absent one: absent stays `None` through
+/// `#[serde(default)]`, anything present, `null` included, is `Some`.
+fn present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+/// Why an update matched no row: the todo is gone, or someone else bumped
+/// its version since the client read it.
+fn missing_or_stale(id: i64, exists: bool) -> anyhow::Error {
+    if exists {
+        RepositoryError::StaleVersion(id).into()
+    } else {
+        RepositoryError::NotFound(id).into()
+    }
+}
+
+/// `labels` without repeats, keeping the first occurrence of each.
+fn distinct_labels(labels: &[i64]) -> Vec<i64> {
+    let mut seen = std::collections::HashSet::new();
+    labels
+        .iter()
+        .copied()
+        .filter(|id| seen.insert(*id))
+        .collect()
+}
+
+impl Todo {
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    /// How `format` addresses this todo in paths and links.
+    pub fn key(&self, format: IdFormat) -> String {
+        match (format, &self.uuid) {
+            (IdFormat::Uuid, Some(uuid)) => uuid.to_string(),
+            _ => self.id.to_string(),
+        }
+    }
+
+    pub fn uuid(&self) -> Option<&Uuid> {
+        self.uuid.as_ref()
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn completed(&self) -> bool {
+        self.completed
+    }
+
+    pub fn due(&self) -> Option<&Date> {
+        self.due.as_ref()
+    }
+
+    pub fn version(&self) -> i32 {
+        self.version
+    }
+
+    pub(super) fn text_mut(&mut self) -> &mut String {
+        &mut self.text
+    }
 }
 
 impl Todo {
-    pub fn new(id: i32, text: String) -> Self {
+    pub fn new(id: i64, text: String) -> Self {
         Self {
             id,
+            uuid: None,
             text,
             completed: false,
+            due: None,
+            version: first_version(),
         }
     }
 }
 
-type TodoDatas = HashMap<i32, Todo>;
+type TodoDatas = HashMap<i64, Todo>;
+
+#[derive(Debug, Default)]
+struct Modified {
+    collection: Option<SystemTime>,
+    todos: HashMap<i64, SystemTime>,
+}
+
+/// On-disk form of a [`TodoRepositoryForMemory`] snapshot.
+#[derive(Debug, Serialize, Deserialize)]
+struct SnapshotTodo {
+    #[serde(flatten)]
+    todo: Todo,
+    modified_secs: u64,
+}
 
 #[derive(Debug, Clone)]
 pub struct TodoRepositoryForMemory {
     store: Arc<RwLock<TodoDatas>>,
+    modified: Arc<RwLock<Modified>>,
+    /// Wakes the snapshot writer, when persistence is enabled.
+    dirty: Option<Arc<tokio::sync::Notify>>,
 }
 
 impl TodoRepositoryForMemory {
     pub fn new() -> Self {
         TodoRepositoryForMemory {
             store: Arc::default(),
+            modified: Arc::default(),
+            dirty: None,
+        }
+    }
+
+    /// A repository persisted to the JSON file at `path`: loaded now if it
+    /// exists, and rewritten in the background after mutations. Changes made
+    /// while a write is in flight are coalesced into the next one.
+    pub async fn with_snapshot(path: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let path = path.into();
+        let mut repository = Self::new();
+        match tokio::fs::read(&path).await {
+            Ok(bytes) => {
+                let todos: Vec<SnapshotTodo> = serde_json::from_slice(&bytes)
+                    .with_context(|| format!("invalid snapshot {}", path.display()))?;
+                let mut store = repository.write_store_ref();
+                let mut modified = repository.modified.write().unwrap();
+                for SnapshotTodo {
+                    todo,
+                    modified_secs,
+                } in todos
+                {
+                    let at = from_epoch_secs(modified_secs as i64);
+                    modified.collection = modified.collection.max(Some(at));
+                    modified.todos.insert(todo.id, at);
+                    store.insert(todo.id, todo);
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).context(format!("failed read {}", path.display())),
+        }
+
+        let dirty = Arc::new(tokio::sync::Notify::new());
+        repository.dirty = Some(dirty.clone());
+        let writer = repository.clone();
+        tokio::spawn(async move {
+            loop {
+                dirty.notified().await;
+                if let Err(e) = writer.write_snapshot(&path).await {
+                    tracing::error!("failed to write snapshot {}: {:#}", path.display(), e);
+                }
+            }
+        });
+
+        Ok(repository)
+    }
+
+    async fn write_snapshot(&self, path: &Path) -> anyhow::Result<()> {
+        let bytes = {
+            let store = self.read_store_ref();
+            let modified = self.modified.read().unwrap();
+            let mut todos: Vec<_> = store
+                .values()
+                .map(|todo| SnapshotTodo {
+                    todo: todo.clone(),
+                    modified_secs: modified
+                        .todos
+                        .get(&todo.id)
+                        .and_then(|at| at.duration_since(UNIX_EPOCH).ok())
+                        .map_or(0, |d| d.as_secs()),
+                })
+                .collect();
+            todos.sort_by_key(|snapshot| snapshot.todo.id);
+            serde_json::to_vec_pretty(&todos)?
+        };
+
+        // Replace the file atomically so a crash never leaves half a snapshot.
+        let tmp = path.with_extension("tmp");
+        tokio::fs::write(&tmp, bytes).await?;
+        tokio::fs::rename(&tmp, path).await?;
+        Ok(())
+    }
+
+    fn touch(&self, id: Option<i64>) {
+        let now = SystemTime::now();
+        let mut modified = self.modified.write().unwrap();
+        modified.collection = Some(now);
+        if let Some(id) = id {
+            modified.todos.insert(id, now);
+        }
+        if let Some(dirty) = &self.dirty {
+            dirty.notify_one();
         }
     }
 
-    fn write_store_ref(&self) -> RwLockWriteGuard<TodoDatas> {
+    fn write_store_ref(&self) -> RwLockWriteGuard<'_, TodoDatas> {
         self.store.write().unwrap()
     }
 
-    fn read_store_ref(&self) -> RwLockReadGuard<TodoDatas> {
+    fn read_store_ref(&self) -> RwLockReadGuard<'_, TodoDatas> {
         self.store.read().unwrap()
     }
 }
@@ -85,74 +756,394 @@ impl TodoRepositoryForMemory {
 #[async_trait]
 impl TodoRepository for TodoRepositoryForMemory {
     async fn create(&self, payload: CreateTodo) -> anyhow::Result<Todo> {
+        // There are no labels in memory, so any label id is unknown.
+        if let Some(label_id) = payload.labels.first() {
+            return Err(RepositoryError::NotFound(*label_id).into());
+        }
         let mut store = self.write_store_ref();
-        let id = (store.len() + 1) as i32;
-        let todo = Todo::new(id, payload.text.clone());
+        if let Some(existing) = store
+            .values()
+            .find(|todo| payload.uuid.is_some() && todo.uuid == payload.uuid)
+        {
+            return Err(RepositoryError::Duplicate(existing.id).into());
+        }
+        let id = store.keys().max().map_or(1, |id| id + 1);
+        let todo = Todo {
+            uuid: payload.uuid,
+            completed: payload.completed,
+            due: payload.due,
+            ..Todo::new(id, payload.text)
+        };
         store.insert(id, todo.clone());
+        self.touch(Some(id));
         Ok(todo)
     }
-    async fn find(&self, id: i32) -> anyhow::Result<Todo> {
+    async fn create_many(&self, payloads: Vec<CreateTodo>) -> anyhow::Result<Vec<Todo>> {
+        // Refuse labels up front rather than halfway through the batch.
+        if let Some(label_id) = payloads.iter().flat_map(|payload| &payload.labels).next() {
+            return Err(RepositoryError::NotFound(*label_id).into());
+        }
+        let mut todos = Vec::with_capacity(payloads.len());
+        for payload in payloads {
+            todos.push(self.create(payload).await?);
+        }
+        Ok(todos)
+    }
+    async fn find(&self, id: i64) -> anyhow::Result<Todo> {
         let store = self.read_store_ref();
         let todo = store
             .get(&id)
-            .map(|todo| todo.clone())
+            .cloned()
             .ok_or(RepositoryError::NotFound(id))?;
 
         Ok(todo)
     }
-    async fn all(&self) -> anyhow::Result<Vec<Todo>> {
+    async fn all(&self, filter: &TodoFilter) -> anyhow::Result<Vec<Todo>> {
         let store = self.read_store_ref();
-        Ok(Vec::from_iter(store.values().map(|todo| todo.clone())))
+        let mut todos: Vec<_> = store
+            .values()
+            .filter(|todo| filter.matches(todo, &[]))
+            .cloned()
+            .collect();
+        if let Some(ids) = &filter.ids {
+            todos.sort_by_key(|todo| ids.iter().position(|id| *id == todo.id));
+        }
+        Ok(todos)
+    }
+    async fn count(&self, filter: &TodoFilter) -> anyhow::Result<i64> {
+        Ok(self.all(filter).await?.len() as i64)
+    }
+    fn stream_all(&self) -> BoxStream<'static, anyhow::Result<Todo>> {
+        let mut todos: Vec<_> = self.read_store_ref().values().cloned().collect();
+        todos.sort_by_key(|todo| todo.id);
+        futures_util::stream::iter(todos.into_iter().map(Ok)).boxed()
+    }
+    async fn find_with_labels(&self, id: i64) -> anyhow::Result<TodoWithLabels> {
+        let todo = self.find(id).await?;
+        Ok(TodoWithLabels {
+            todo,
+            labels: vec![],
+        })
+    }
+    async fn all_with_labels(&self, filter: &TodoFilter) -> anyhow::Result<Vec<TodoWithLabels>> {
+        let todos = self.all(filter).await?;
+        Ok(todos
+            .into_iter()
+            .map(|todo| TodoWithLabels {
+                todo,
+                labels: vec![],
+            })
+            .collect())
     }
-    async fn update(&self, id: i32, payload: UpdateTodo) -> anyhow::Result<Todo> {
+    async fn update(&self, id: i64, payload: UpdateTodo) -> anyhow::Result<Todo> {
+        if let Some(label_id) = payload.labels.iter().flatten().next() {
+            return Err(RepositoryError::NotFound(*label_id).into());
+        }
         let mut store = self.write_store_ref();
         let todo = store.get(&id).context(RepositoryError::NotFound(id))?;
+        if payload
+            .version
+            .is_some_and(|version| version != todo.version)
+        {
+            return Err(RepositoryError::StaleVersion(id).into());
+        }
         let text = payload.text.unwrap_or(todo.text.clone());
         let completed = payload.completed.unwrap_or(todo.completed);
+        let due = payload.due.unwrap_or_else(|| todo.due.clone());
 
         let todo = Todo {
             id,
+            uuid: todo.uuid.clone(),
             text,
             completed,
+            due,
+            version: todo.version + 1,
         };
         store.insert(id, todo.clone());
+        self.touch(Some(id));
         Ok(todo)
     }
-    async fn delete(&self, id: i32) -> anyhow::Result<()> {
+    async fn delete(&self, id: i64) -> anyhow::Result<()> {
         let mut store = self.write_store_ref();
         store.remove(&id).ok_or(RepositoryError::NotFound(id))?;
+        self.touch(None);
         Ok(())
     }
+    async fn purge_completed(&self) -> anyhow::Result<u64> {
+        let mut store = self.write_store_ref();
+        let before = store.len();
+        store.retain(|_, todo| !todo.completed);
+        self.touch(None);
+        Ok((before - store.len()) as u64)
+    }
+    async fn last_modified(&self, id: i64) -> anyhow::Result<SystemTime> {
+        if !self.read_store_ref().contains_key(&id) {
+            return Err(RepositoryError::NotFound(id).into());
+        }
+        let modified = self.modified.read().unwrap();
+        let last_modified = modified
+            .todos
+            .get(&id)
+            .copied()
+            .ok_or(RepositoryError::NotFound(id))?;
+        Ok(last_modified)
+    }
+    async fn collection_last_modified(&self) -> anyhow::Result<SystemTime> {
+        let modified = self.modified.read().unwrap();
+        Ok(modified.collection.unwrap_or(UNIX_EPOCH))
+    }
+    async fn recently_modified(&self, limit: i64) -> anyhow::Result<Vec<(Todo, SystemTime)>> {
+        let store = self.read_store_ref();
+        let modified = self.modified.read().unwrap();
+        let mut todos: Vec<_> = store
+            .values()
+            .map(|todo| {
+                let at = modified.todos.get(&todo.id).copied().unwrap_or(UNIX_EPOCH);
+                (todo.clone(), at)
+            })
+            .collect();
+        todos.sort_by(|(a, a_at), (b, b_at)| b_at.cmp(a_at).then(b.id.cmp(&a.id)));
+        todos.truncate(limit.max(0) as usize);
+        Ok(todos)
+    }
 }
 
+/// Rows buffered between the database cursor and a streamed export.
+const EXPORT_BUFFER: usize = 64;
+
 #[derive(Debug, Clone)]
 pub struct TodoRepositoryForDb {
     pool: PgPool,
+    /// Serves the list queries (`all`, `count`, exports, feeds); the primary
+    /// pool unless a replica is configured.
+    read_pool: PgPool,
+    /// Whether changes also queue their events in the outbox.
+    outbox: bool,
 }
 
 impl TodoRepositoryForDb {
     pub fn new(pool: PgPool) -> Self {
-        TodoRepositoryForDb { pool }
+        TodoRepositoryForDb {
+            read_pool: pool.clone(),
+            pool,
+            outbox: false,
+        }
+    }
+
+    /// Queues the event of every change in the outbox, for the webhook
+    /// dispatcher to deliver.
+    pub fn with_outbox(mut self) -> Self {
+        self.outbox = true;
+        self
+    }
+
+    /// Sends the list queries to a read replica. Single-todo lookups stay on
+    /// the primary so a client reading its own write never sees it stale.
+    pub fn with_read_pool(mut self, read_pool: PgPool) -> Self {
+        self.read_pool = read_pool;
+        self
+    }
+}
+
+/// Links `labels` to the todo as part of `tx`, failing with the first id
+/// that names no label so the caller's transaction rolls back.
+async fn attach_labels_pg(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    todo_id: i64,
+    labels: &[i64],
+) -> anyhow::Result<()> {
+    let labels = distinct_labels(labels);
+    attach_label_pairs_pg(tx, &vec![todo_id; labels.len()], &labels).await
+}
+
+/// Links `todo_ids[i]` to `label_ids[i]` for every `i` in one statement.
+async fn attach_label_pairs_pg(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    todo_ids: &[i64],
+    label_ids: &[i64],
+) -> anyhow::Result<()> {
+    if label_ids.is_empty() {
+        return Ok(());
+    }
+    let attached: Vec<i64> = sqlx::query_scalar(
+        r#"
+        insert into todo_labels (todo_id, label_id)
+        select pair.todo_id, labels.id
+        from unnest($1::bigint[], $2::bigint[]) as pair(todo_id, label_id)
+            join labels on labels.id = pair.label_id
+        returning label_id
+    "#,
+    )
+    .bind(todo_ids)
+    .bind(label_ids)
+    .fetch_all(&mut *tx)
+    .await?;
+    match label_ids.iter().find(|id| !attached.contains(id)) {
+        Some(missing) => Err(RepositoryError::NotFound(*missing).into()),
+        None => Ok(()),
     }
 }
 
+/// Tells every server instance listening on [`CHANNEL`] about `events`,
+/// and queues them in the outbox when `outbox` is set. Sent inside `tx`,
+/// they only go out once it commits.
+async fn notify_pg(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    events: &[TodoEvent],
+    outbox: bool,
+) -> anyhow::Result<()> {
+    let events = events
+        .iter()
+        .map(serde_json::to_string)
+        .collect::<Result<Vec<_>, _>>()?;
+    sqlx::query("select pg_notify($1, event) from unnest($2::text[]) as events(event)")
+        .bind(CHANNEL)
+        .bind(&events)
+        .execute(&mut *tx)
+        .await?;
+    if outbox {
+        outbox::enqueue_pg(tx, &events).await?;
+    }
+    Ok(())
+}
+
+/// Inserts `payloads` with their labels in one statement each, inside
+/// `tx`, and returns the todos in the same order.
+async fn create_many_pg(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    payloads: &[CreateTodo],
+) -> anyhow::Result<Vec<Todo>> {
+    if payloads.is_empty() {
+        return Ok(vec![]);
+    }
+    let texts: Vec<&str> = payloads
+        .iter()
+        .map(|payload| payload.text.as_str())
+        .collect();
+    let completed: Vec<bool> = payloads.iter().map(|payload| payload.completed).collect();
+    let uuids: Vec<Option<&str>> = payloads
+        .iter()
+        .map(|payload| payload.uuid.as_ref().map(Uuid::as_str))
+        .collect();
+    let dues: Vec<Option<&str>> = payloads
+        .iter()
+        .map(|payload| payload.due.as_ref().map(Date::as_str))
+        .collect();
+    let mut todos = sqlx::query_as::<_, Todo>(
+        r#"
+      insert into todos (text, completed, uuid, due)
+      select text, completed, uuid, due
+      from unnest($1::text[], $2::boolean[], $3::text[], $4::text[])
+          with ordinality as batch(text, completed, uuid, due, n)
+      order by n
+      returning *
+    "#,
+    )
+    .bind(&texts)
+    .bind(&completed)
+    .bind(&uuids)
+    .bind(&dues)
+    .fetch_all(&mut *tx)
+    .await?;
+    // the sequence hands out ids in `n` order, unlike `returning`
+    todos.sort_by_key(|todo| todo.id);
+    let (todo_ids, label_ids): (Vec<i64>, Vec<i64>) = todos
+        .iter()
+        .zip(payloads)
+        .flat_map(|(todo, payload)| {
+            distinct_labels(&payload.labels)
+                .into_iter()
+                .map(move |label_id| (todo.id, label_id))
+        })
+        .unzip();
+    attach_label_pairs_pg(tx, &todo_ids, &label_ids).await?;
+    Ok(todos)
+}
+
 #[async_trait]
 impl TodoRepository for TodoRepositoryForDb {
     async fn create(&self, payload: CreateTodo) -> anyhow::Result<Todo> {
+        let mut tx = self.pool.begin().await?;
         let todo = sqlx::query_as::<_, Todo>(
             r#"
-          insert into todos (text, completed)
-          values ($1, false)
+          insert into todos (text, completed, uuid, due)
+          values ($1, $2, $3, $4)
           returning *
         "#,
         )
         .bind(payload.text.clone())
-        .fetch_one(&self.pool)
+        .bind(payload.completed)
+        .bind(payload.uuid.clone())
+        .bind(payload.due.clone())
+        .fetch_one(&mut tx)
         .await?;
+        attach_labels_pg(&mut tx, todo.id, &payload.labels).await?;
+        let event = TodoEvent::Created { id: todo.id };
+        notify_pg(&mut tx, &[event], self.outbox).await?;
+        tx.commit().await?;
 
         Ok(todo)
     }
-    async fn find(&self, id: i32) -> anyhow::Result<Todo> {
+    async fn create_many(&self, payloads: Vec<CreateTodo>) -> anyhow::Result<Vec<Todo>> {
+        if payloads.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut tx = self.pool.begin().await?;
+        let todos = create_many_pg(&mut tx, &payloads).await?;
+        let events: Vec<_> = todos
+            .iter()
+            .map(|todo| TodoEvent::Created { id: todo.id })
+            .collect();
+        notify_pg(&mut tx, &events, self.outbox).await?;
+        tx.commit().await?;
+
+        Ok(todos)
+    }
+    async fn import<L: LabelRepository>(
+        &self,
+        _labels: &L,
+        import: Import,
+    ) -> anyhow::Result<ImportedRows> {
+        let mut tx = self.pool.begin().await?;
+        let mut imported = ImportedRows::default();
+        for label in import.labels {
+            let existing: Option<i64> = sqlx::query_scalar("select id from labels where name = $1")
+                .bind(&label.name)
+                .fetch_optional(&mut tx)
+                .await?;
+            let id = match existing {
+                Some(id) => id,
+                None => {
+                    imported.created_labels += 1;
+                    sqlx::query_scalar(
+                        r#"
+                        insert into labels (name, uuid)
+                        select $1, case when exists (select 1 from labels where uuid = $2)
+                            then null else $2 end
+                        returning id
+                    "#,
+                    )
+                    .bind(&label.name)
+                    .bind(&label.uuid)
+                    .fetch_one(&mut tx)
+                    .await?
+                }
+            };
+            imported.label_ids.push(id);
+        }
+        let payloads = labelled(import.todos, &imported.label_ids);
+        imported.todos = create_many_pg(&mut tx, &payloads).await?;
+        let events: Vec<_> = imported
+            .todos
+            .iter()
+            .map(|todo| TodoEvent::Created { id: todo.id })
+            .collect();
+        notify_pg(&mut tx, &events, self.outbox).await?;
+        tx.commit().await?;
+
+        Ok(imported)
+    }
+    async fn find(&self, id: i64) -> anyhow::Result<Todo> {
         let todo = sqlx::query_as::<_, Todo>(
             r#"
             select * from todos where id=$1
@@ -161,118 +1152,2667 @@ impl TodoRepository for TodoRepositoryForDb {
         .bind(id)
         .fetch_one(&self.pool)
         .await
-        .map_err(|e| match e {
-            sqlx::Error::RowNotFound => RepositoryError::NotFound(id),
-            _ => RepositoryError::Unexpected(e.to_string()),
-        })?;
+        .map_err(not_found(id))?;
 
         Ok(todo)
     }
-    async fn all(&self) -> anyhow::Result<Vec<Todo>> {
-        let todos = sqlx::query_as::<_, Todo>(
+    async fn all(&self, filter: &TodoFilter) -> anyhow::Result<Vec<Todo>> {
+        let sql = format!(
             r#"
             select * from todos
-            order by id desc;
+            where {}
+            order by {}, id desc;
         "#,
+            FILTER_CONDITIONS, FILTER_ORDER
+        );
+        let todos = sqlx::query_as::<_, Todo>(&sql)
+            .bind(filter.completed)
+            .bind(&filter.labels)
+            .bind(&filter.text)
+            .bind(&filter.ids)
+            .bind(&filter.due_before)
+            .bind(&filter.due_after)
+            .fetch_all(&self.read_pool)
+            .await?;
+
+        Ok(todos)
+    }
+    async fn count(&self, filter: &TodoFilter) -> anyhow::Result<i64> {
+        let sql = format!("select count(*) from todos where {}", FILTER_CONDITIONS);
+        let count = sqlx::query_scalar(&sql)
+            .bind(filter.completed)
+            .bind(&filter.labels)
+            .bind(&filter.text)
+            .bind(&filter.ids)
+            .bind(&filter.due_before)
+            .bind(&filter.due_after)
+            .fetch_one(&self.read_pool)
+            .await?;
+
+        Ok(count)
+    }
+    fn stream_all(&self) -> BoxStream<'static, anyhow::Result<Todo>> {
+        // The cursor borrows the pool, so it's drained on its own task and
+        // handed over through a small channel that also applies backpressure.
+        let pool = self.read_pool.clone();
+        let (tx, rx) = tokio::sync::mpsc::channel(EXPORT_BUFFER);
+        tokio::spawn(async move {
+            let mut rows =
+                sqlx::query_as::<_, Todo>("select * from todos order by id asc").fetch(&pool);
+            while let Some(row) = rows.next().await {
+                let failed = row.is_err();
+                if tx.send(row.map_err(anyhow::Error::from)).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        futures_util::stream::unfold(
+            rx,
+            |mut rx| async move { rx.recv().await.map(|row| (row, rx)) },
         )
-        .fetch_all(&self.pool)
+        .boxed()
+    }
+    async fn find_with_labels(&self, id: i64) -> anyhow::Result<TodoWithLabels> {
+        let row = sqlx::query_as::<_, TodoWithLabelsFromRow>(
+            r#"
+            select todos.*, coalesce(summary.labels, '[]') as labels
+            from todos
+                left outer join todo_label_summaries summary on summary.todo_id = todos.id
+            where todos.id=$1;
+        "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
         .await?;
 
-        Ok(todos)
+        let todo = row.ok_or(RepositoryError::NotFound(id))?;
+
+        Ok(todo.into())
     }
-    async fn update(&self, id: i32, payload: UpdateTodo) -> anyhow::Result<Todo> {
-        let old_todo = self.find(id).await?;
+    async fn all_with_labels(&self, filter: &TodoFilter) -> anyhow::Result<Vec<TodoWithLabels>> {
+        // Triggers keep `todo_label_summaries` current, so there is nothing
+        // to aggregate here.
+        let sql = format!(
+            r#"
+            select todos.*, coalesce(summary.labels, '[]') as labels
+            from todos
+                left outer join todo_label_summaries summary on summary.todo_id = todos.id
+            where {}
+            order by {}, todos.id desc;
+        "#,
+            FILTER_CONDITIONS, FILTER_ORDER
+        );
+        let rows = sqlx::query_as::<_, TodoWithLabelsFromRow>(&sql)
+            .bind(filter.completed)
+            .bind(&filter.labels)
+            .bind(&filter.text)
+            .bind(&filter.ids)
+            .bind(&filter.due_before)
+            .bind(&filter.due_after)
+            .fetch_all(&self.read_pool)
+            .await?;
+
+        Ok(rows.into_iter().map(TodoWithLabels::from).collect())
+    }
+    async fn update(&self, id: i64, payload: UpdateTodo) -> anyhow::Result<Todo> {
+        let mut tx = self.pool.begin().await?;
         let todo = sqlx::query_as::<_, Todo>(
             r#"
-            update todos set text=$1, completed=$2
-            where id=$3
+            update todos set text=coalesce($1, text), completed=coalesce($2, completed),
+                due=case when $5 then $6 else due end,
+                version=version+1, updated_at=now()
+            where id=$3 and ($4::integer is null or version=$4)
             returning *
         "#,
         )
-        .bind(payload.text.clone())
-        .bind(payload.completed.unwrap_or(old_todo.completed))
+        .bind(payload.text)
+        .bind(payload.completed)
         .bind(id)
-        .fetch_one(&self.pool)
-        .await
-        .map_err(|e| match e {
-            sqlx::Error::RowNotFound => RepositoryError::NotFound(id),
-            _ => RepositoryError::Unexpected(e.to_string()),
-        })?;
+        .bind(payload.version)
+        .bind(payload.due.is_some())
+        .bind(payload.due.flatten())
+        .fetch_optional(&mut tx)
+        .await?;
+        let todo = match todo {
+            Some(todo) => todo,
+            None => {
+                let exists: bool =
+                    sqlx::query_scalar("select exists(select 1 from todos where id=$1)")
+                        .bind(id)
+                        .fetch_one(&mut tx)
+                        .await?;
+                return Err(missing_or_stale(id, exists));
+            }
+        };
+        if let Some(labels) = payload.labels {
+            sqlx::query("delete from todo_labels where todo_id=$1")
+                .bind(id)
+                .execute(&mut tx)
+                .await?;
+            attach_labels_pg(&mut tx, id, &labels).await?;
+        }
+        notify_pg(&mut tx, &[TodoEvent::Updated { id }], self.outbox).await?;
+        tx.commit().await?;
 
         Ok(todo)
     }
-    async fn delete(&self, id: i32) -> anyhow::Result<()> {
-        sqlx::query(
+    async fn delete(&self, id: i64) -> anyhow::Result<()> {
+        let mut tx = self.pool.begin().await?;
+        let result = sqlx::query(
             r#"
             delete from todos where id=$1
         "#,
         )
         .bind(id)
-        .execute(&self.pool)
+        .execute(&mut tx)
         .await
-        .map_err(|e| match e {
-            sqlx::Error::RowNotFound => RepositoryError::NotFound(id),
-            _ => RepositoryError::Unexpected(e.to_string()),
-        })?;
+        .map_err(not_found(id))?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(id).into());
+        }
+        notify_pg(&mut tx, &[TodoEvent::Deleted { id }], self.outbox).await?;
+        tx.commit().await?;
 
         Ok(())
     }
-}
+    async fn purge_completed(&self) -> anyhow::Result<u64> {
+        let mut tx = self.pool.begin().await?;
+        let purged: i64 = sqlx::query_scalar(
+            r#"
+            with purged as (
+                delete from todos where completed
+                returning id
+            ), unlinked as (
+                delete from todo_labels where todo_id in (select id from purged)
+            )
+            select count(*) from purged
+        "#,
+        )
+        .fetch_one(&mut tx)
+        .await?;
+        if purged > 0 {
+            let count = purged as u64;
+            notify_pg(&mut tx, &[TodoEvent::Purged { count }], self.outbox).await?;
+        }
+        tx.commit().await?;
 
-#[cfg(test)]
-mod test {
-    use std::env;
+        Ok(purged as u64)
+    }
+    async fn archive_completed(&self, older_than: Duration) -> anyhow::Result<u64> {
+        let mut tx = self.pool.begin().await?;
+        let archived: i64 = sqlx::query_scalar(
+            r#"
+            with moved as (
+                delete from todos
+                where completed and updated_at < now() - make_interval(secs => $1)
+                returning id, uuid, text, completed, version, updated_at, due
+            ), unlinked as (
+                delete from todo_labels where todo_id in (select id from moved)
+            ), archived as (
+                insert into archived_todos (id, uuid, text, completed, version, updated_at, due)
+                select * from moved
+                returning id
+            )
+            select count(*) from archived
+        "#,
+        )
+        .bind(older_than.as_secs_f64())
+        .fetch_one(&mut tx)
+        .await?;
+        if archived > 0 {
+            let count = archived as u64;
+            notify_pg(&mut tx, &[TodoEvent::Archived { count }], self.outbox).await?;
+        }
+        tx.commit().await?;
+
+        Ok(archived as u64)
+    }
+    async fn archived(&self) -> anyhow::Result<Vec<Todo>> {
+        let todos = sqlx::query_as::<_, Todo>(
+            r#"
+            select * from archived_todos
+            order by archived_at desc, id desc
+        "#,
+        )
+        .fetch_all(&self.read_pool)
+        .await?;
+
+        Ok(todos)
+    }
+    async fn last_modified(&self, id: i64) -> anyhow::Result<SystemTime> {
+        let secs: i64 = sqlx::query_scalar(
+            r#"
+            select floor(extract(epoch from updated_at))::bigint from todos where id=$1
+        "#,
+        )
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(not_found(id))?;
+
+        Ok(from_epoch_secs(secs))
+    }
+    async fn collection_last_modified(&self) -> anyhow::Result<SystemTime> {
+        let secs: i64 = sqlx::query_scalar(
+            r#"
+            select floor(extract(epoch from modified_at))::bigint from last_modified
+            where resource='todos'
+        "#,
+        )
+        .fetch_one(&self.read_pool)
+        .await?;
+
+        Ok(from_epoch_secs(secs))
+    }
+    async fn resolve(&self, key: &Key) -> anyhow::Result<i64> {
+        match key {
+            Key::Serial(id) => Ok(*id),
+            Key::Uuid(uuid) => sqlx::query_scalar("select id from todos where uuid=$1")
+                .bind(uuid)
+                .fetch_optional(&self.read_pool)
+                .await?
+                .ok_or_else(|| RepositoryError::UnknownUuid(uuid.clone()).into()),
+        }
+    }
+    async fn recently_modified(&self, limit: i64) -> anyhow::Result<Vec<(Todo, SystemTime)>> {
+        let rows = sqlx::query_as::<_, RecentTodoFromRow>(
+            r#"
+            select id, uuid, text, completed, due, version,
+                floor(extract(epoch from updated_at))::bigint as modified_secs
+            from todos
+            order by updated_at desc, id desc
+            limit $1
+        "#,
+        )
+        .bind(limit)
+        .fetch_all(&self.read_pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let todo = Todo {
+                    id: row.id,
+                    text: row.text,
+                    completed: row.completed,
+                    due: row.due,
+                    version: row.version,
+                    uuid: row.uuid,
+                };
+                (todo, from_epoch_secs(row.modified_secs))
+            })
+            .collect())
+    }
+    async fn search(&self, filter: &TodoFilter, limit: i64) -> anyhow::Result<Vec<SearchHit>> {
+        // `search @@ query` is answered from the GIN index; the filter's own
+        // substring check then rechecks the few candidates. The highlight is
+        // marked here rather than by `ts_headline`, which leaves the text
+        // around the marks unescaped.
+        let sql = format!(
+            r#"
+            select todos.id, todos.uuid, todos.text, todos.completed, todos.due, todos.version,
+                ts_rank(todos.search, query) as rank
+            from todos, plainto_tsquery('simple', array_to_string($3::text[], ' ')) as query
+            where todos.search @@ query and {}
+            order by rank desc, todos.id desc
+            limit $7
+        "#,
+            FILTER_CONDITIONS
+        );
+        let rows = sqlx::query_as::<_, SearchHitFromRow>(&sql)
+            .bind(filter.completed)
+            .bind(&filter.labels)
+            .bind(&filter.text)
+            .bind(&filter.ids)
+            .bind(&filter.due_before)
+            .bind(&filter.due_after)
+            .bind(limit)
+            .fetch_all(&self.read_pool)
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let todo = Todo {
+                    id: row.id,
+                    text: row.text,
+                    completed: row.completed,
+                    due: row.due,
+                    version: row.version,
+                    uuid: row.uuid,
+                };
+                SearchHit {
+                    rank: row.rank,
+                    ..SearchHit::scan(todo, &filter.text)
+                }
+            })
+            .collect())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TodoRepositoryForSqlite {
+    pool: SqlitePool,
+    /// Whether changes also queue their events in the outbox.
+    outbox: bool,
+}
+
+impl TodoRepositoryForSqlite {
+    pub fn new(pool: SqlitePool) -> Self {
+        TodoRepositoryForSqlite {
+            pool,
+            outbox: false,
+        }
+    }
+
+    /// See [`TodoRepositoryForDb::with_outbox`].
+    pub fn with_outbox(mut self) -> Self {
+        self.outbox = true;
+        self
+    }
+
+    async fn enqueue(
+        &self,
+        tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+        events: &[TodoEvent],
+    ) -> anyhow::Result<()> {
+        if self.outbox {
+            outbox::enqueue_sqlite(tx, events).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Rows per multi-row insert, keeping the bound parameters well under
+/// SQLite's limit.
+const SQLITE_INSERT_CHUNK: usize = 500;
+
+/// SQLite counterpart of [`attach_labels_pg`], one insert per label.
+async fn attach_labels_sqlite(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    todo_id: i64,
+    labels: &[i64],
+) -> anyhow::Result<()> {
+    for label_id in distinct_labels(labels) {
+        let result = sqlx::query(
+            r#"
+            insert into todo_labels (todo_id, label_id)
+            select ?1, id from labels where id=?2
+        "#,
+        )
+        .bind(todo_id)
+        .bind(label_id)
+        .execute(&mut *tx)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(label_id).into());
+        }
+    }
+    Ok(())
+}
+
+/// Inserts `payloads` with their labels inside `tx`, a multi-row insert
+/// per chunk, and returns the todos in the same order.
+async fn create_many_sqlite(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    payloads: &[CreateTodo],
+) -> anyhow::Result<Vec<Todo>> {
+    let mut todos = Vec::with_capacity(payloads.len());
+    for chunk in payloads.chunks(SQLITE_INSERT_CHUNK) {
+        let sql = format!(
+            "insert into todos (text, completed, uuid, due) values {} returning *",
+            vec!["(?, ?, ?, ?)"; chunk.len()].join(", ")
+        );
+        let mut query = sqlx::query_as::<_, Todo>(&sql);
+        for payload in chunk {
+            query = query
+                .bind(&payload.text)
+                .bind(payload.completed)
+                .bind(&payload.uuid)
+                .bind(&payload.due);
+        }
+        let mut inserted = query.fetch_all(&mut *tx).await?;
+        inserted.sort_by_key(|todo| todo.id);
+        for (todo, payload) in inserted.iter().zip(chunk) {
+            attach_labels_sqlite(tx, todo.id, &payload.labels).await?;
+        }
+        todos.extend(inserted);
+    }
+    Ok(todos)
+}
+
+#[async_trait]
+impl TodoRepository for TodoRepositoryForSqlite {
+    async fn create(&self, payload: CreateTodo) -> anyhow::Result<Todo> {
+        let mut tx = self.pool.begin().await?;
+        let todo = sqlx::query_as::<_, Todo>(
+            r#"
+          insert into todos (text, completed, uuid, due)
+          values (?1, ?2, ?3, ?4)
+          returning *
+        "#,
+        )
+        .bind(payload.text.clone())
+        .bind(payload.completed)
+        .bind(payload.uuid.clone())
+        .bind(payload.due.clone())
+        .fetch_one(&mut tx)
+        .await?;
+        attach_labels_sqlite(&mut tx, todo.id, &payload.labels).await?;
+        self.enqueue(&mut tx, &[TodoEvent::Created { id: todo.id }])
+            .await?;
+        tx.commit().await?;
+
+        Ok(todo)
+    }
+    async fn create_many(&self, payloads: Vec<CreateTodo>) -> anyhow::Result<Vec<Todo>> {
+        let mut tx = self.pool.begin().await?;
+        let todos = create_many_sqlite(&mut tx, &payloads).await?;
+        let events: Vec<_> = todos
+            .iter()
+            .map(|todo| TodoEvent::Created { id: todo.id })
+            .collect();
+        self.enqueue(&mut tx, &events).await?;
+        tx.commit().await?;
+
+        Ok(todos)
+    }
+    async fn import<L: LabelRepository>(
+        &self,
+        _labels: &L,
+        import: Import,
+    ) -> anyhow::Result<ImportedRows> {
+        let mut tx = self.pool.begin().await?;
+        let mut imported = ImportedRows::default();
+        for label in import.labels {
+            let existing: Option<i64> = sqlx::query_scalar("select id from labels where name = ?1")
+                .bind(&label.name)
+                .fetch_optional(&mut tx)
+                .await?;
+            let id = match existing {
+                Some(id) => id,
+                None => {
+                    imported.created_labels += 1;
+                    sqlx::query_scalar(
+                        r#"
+                        insert into labels (name, uuid)
+                        select ?1, case when exists (select 1 from labels where uuid = ?2)
+                            then null else ?2 end
+                        returning id
+                    "#,
+                    )
+                    .bind(&label.name)
+                    .bind(&label.uuid)
+                    .fetch_one(&mut tx)
+                    .await?
+                }
+            };
+            imported.label_ids.push(id);
+        }
+        let payloads = labelled(import.todos, &imported.label_ids);
+        imported.todos = create_many_sqlite(&mut tx, &payloads).await?;
+        let events: Vec<_> = imported
+            .todos
+            .iter()
+            .map(|todo| TodoEvent::Created { id: todo.id })
+            .collect();
+        self.enqueue(&mut tx, &events).await?;
+        tx.commit().await?;
+
+        Ok(imported)
+    }
+    async fn find(&self, id: i64) -> anyhow::Result<Todo> {
+        let todo = sqlx::query_as::<_, Todo>(
+            r#"
+            select * from todos where id=?1
+        "#,
+        )
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(not_found(id))?;
+
+        Ok(todo)
+    }
+    async fn all(&self, filter: &TodoFilter) -> anyhow::Result<Vec<Todo>> {
+        let sql = format!(
+            r#"
+            select * from todos
+            where {}
+            order by {}, id desc;
+        "#,
+            SQLITE_FILTER_CONDITIONS, SQLITE_FILTER_ORDER
+        );
+        let (labels, text, ids) = sqlite_filter_params(filter);
+        let todos = sqlx::query_as::<_, Todo>(&sql)
+            .bind(filter.completed)
+            .bind(labels)
+            .bind(text)
+            .bind(ids)
+            .bind(&filter.due_before)
+            .bind(&filter.due_after)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(todos)
+    }
+    async fn count(&self, filter: &TodoFilter) -> anyhow::Result<i64> {
+        let sql = format!(
+            "select count(*) from todos where {}",
+            SQLITE_FILTER_CONDITIONS
+        );
+        let (labels, text, ids) = sqlite_filter_params(filter);
+        let count = sqlx::query_scalar(&sql)
+            .bind(filter.completed)
+            .bind(labels)
+            .bind(text)
+            .bind(ids)
+            .bind(&filter.due_before)
+            .bind(&filter.due_after)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(count)
+    }
+    fn stream_all(&self) -> BoxStream<'static, anyhow::Result<Todo>> {
+        let pool = self.pool.clone();
+        let (tx, rx) = tokio::sync::mpsc::channel(EXPORT_BUFFER);
+        tokio::spawn(async move {
+            let mut rows =
+                sqlx::query_as::<_, Todo>("select * from todos order by id asc").fetch(&pool);
+            while let Some(row) = rows.next().await {
+                let failed = row.is_err();
+                if tx.send(row.map_err(anyhow::Error::from)).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        futures_util::stream::unfold(
+            rx,
+            |mut rx| async move { rx.recv().await.map(|row| (row, rx)) },
+        )
+        .boxed()
+    }
+    async fn find_with_labels(&self, id: i64) -> anyhow::Result<TodoWithLabels> {
+        let row = sqlx::query_as::<_, TodoWithLabelsFromRow>(
+            r#"
+            select todos.*,
+                json_group_array(json_object('id', labels.id, 'uuid', labels.uuid, 'name', labels.name))
+                    filter (where labels.id is not null) as labels
+            from todos
+                left outer join todo_labels tl on todos.id = tl.todo_id
+                left outer join labels on labels.id = tl.label_id
+            where todos.id=?1
+            group by todos.id;
+        "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let todo = row.ok_or(RepositoryError::NotFound(id))?;
+
+        Ok(todo.into())
+    }
+    async fn all_with_labels(&self, filter: &TodoFilter) -> anyhow::Result<Vec<TodoWithLabels>> {
+        let sql = format!(
+            r#"
+            select todos.*,
+                json_group_array(json_object('id', labels.id, 'uuid', labels.uuid, 'name', labels.name))
+                    filter (where labels.id is not null) as labels
+            from todos
+                left outer join todo_labels tl on todos.id = tl.todo_id
+                left outer join labels on labels.id = tl.label_id
+            where {}
+            group by todos.id
+            order by {}, todos.id desc;
+        "#,
+            SQLITE_FILTER_CONDITIONS, SQLITE_FILTER_ORDER
+        );
+        let (labels, text, ids) = sqlite_filter_params(filter);
+        let rows = sqlx::query_as::<_, TodoWithLabelsFromRow>(&sql)
+            .bind(filter.completed)
+            .bind(labels)
+            .bind(text)
+            .bind(ids)
+            .bind(&filter.due_before)
+            .bind(&filter.due_after)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.into_iter().map(TodoWithLabels::from).collect())
+    }
+    async fn update(&self, id: i64, payload: UpdateTodo) -> anyhow::Result<Todo> {
+        let mut tx = self.pool.begin().await?;
+        let todo = sqlx::query_as::<_, Todo>(
+            r#"
+            update todos set text=coalesce(?1, text), completed=coalesce(?2, completed),
+                due=case when ?5 then ?6 else due end,
+                version=version+1, updated_at=cast(strftime('%s', 'now') as integer)
+            where id=?3 and (?4 is null or version=?4)
+            returning *
+        "#,
+        )
+        .bind(payload.text)
+        .bind(payload.completed)
+        .bind(id)
+        .bind(payload.version)
+        .bind(payload.due.is_some())
+        .bind(payload.due.flatten())
+        .fetch_optional(&mut tx)
+        .await?;
+        let todo = match todo {
+            Some(todo) => todo,
+            None => {
+                let exists: bool =
+                    sqlx::query_scalar("select exists(select 1 from todos where id=?1)")
+                        .bind(id)
+                        .fetch_one(&mut tx)
+                        .await?;
+                return Err(missing_or_stale(id, exists));
+            }
+        };
+        if let Some(labels) = payload.labels {
+            sqlx::query("delete from todo_labels where todo_id=?1")
+                .bind(id)
+                .execute(&mut tx)
+                .await?;
+            attach_labels_sqlite(&mut tx, id, &labels).await?;
+        }
+        self.enqueue(&mut tx, &[TodoEvent::Updated { id }]).await?;
+        tx.commit().await?;
+
+        Ok(todo)
+    }
+    async fn delete(&self, id: i64) -> anyhow::Result<()> {
+        let mut tx = self.pool.begin().await?;
+        let result = sqlx::query(
+            r#"
+            delete from todos where id=?1
+        "#,
+        )
+        .bind(id)
+        .execute(&mut tx)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(id).into());
+        }
+        self.enqueue(&mut tx, &[TodoEvent::Deleted { id }]).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+    async fn purge_completed(&self) -> anyhow::Result<u64> {
+        let mut tx = self.pool.begin().await?;
+        // todo_labels rows go with their todo through `on delete cascade`.
+        let result = sqlx::query(
+            r#"
+            delete from todos where completed
+        "#,
+        )
+        .execute(&mut tx)
+        .await?;
+        let count = result.rows_affected();
+        if count > 0 {
+            self.enqueue(&mut tx, &[TodoEvent::Purged { count }])
+                .await?;
+        }
+        tx.commit().await?;
+
+        Ok(count)
+    }
+    async fn archive_completed(&self, older_than: Duration) -> anyhow::Result<u64> {
+        let cutoff = epoch_secs(SystemTime::now() - older_than);
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            r#"
+            insert into archived_todos (id, uuid, text, completed, version, updated_at, due)
+            select id, uuid, text, completed, version, updated_at, due from todos
+            where completed and updated_at < ?1
+        "#,
+        )
+        .bind(cutoff)
+        .execute(&mut tx)
+        .await?;
+        // todo_labels rows go with their todo through `on delete cascade`.
+        let result = sqlx::query("delete from todos where completed and updated_at < ?1")
+            .bind(cutoff)
+            .execute(&mut tx)
+            .await?;
+        let count = result.rows_affected();
+        if count > 0 {
+            self.enqueue(&mut tx, &[TodoEvent::Archived { count }])
+                .await?;
+        }
+        tx.commit().await?;
+
+        Ok(count)
+    }
+    async fn archived(&self) -> anyhow::Result<Vec<Todo>> {
+        let todos = sqlx::query_as::<_, Todo>(
+            r#"
+            select * from archived_todos
+            order by archived_at desc, id desc
+        "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(todos)
+    }
+    async fn last_modified(&self, id: i64) -> anyhow::Result<SystemTime> {
+        let secs: i64 = sqlx::query_scalar(
+            r#"
+            select updated_at from todos where id=?1
+        "#,
+        )
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(not_found(id))?;
+
+        Ok(from_epoch_secs(secs))
+    }
+    async fn collection_last_modified(&self) -> anyhow::Result<SystemTime> {
+        let secs: i64 = sqlx::query_scalar(
+            r#"
+            select modified_at from last_modified
+            where resource='todos'
+        "#,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(from_epoch_secs(secs))
+    }
+    async fn resolve(&self, key: &Key) -> anyhow::Result<i64> {
+        match key {
+            Key::Serial(id) => Ok(*id),
+            Key::Uuid(uuid) => sqlx::query_scalar("select id from todos where uuid=?1")
+                .bind(uuid)
+                .fetch_optional(&self.pool)
+                .await?
+                .ok_or_else(|| RepositoryError::UnknownUuid(uuid.clone()).into()),
+        }
+    }
+    async fn recently_modified(&self, limit: i64) -> anyhow::Result<Vec<(Todo, SystemTime)>> {
+        let rows = sqlx::query_as::<_, RecentTodoFromRow>(
+            r#"
+            select id, uuid, text, completed, due, version, updated_at as modified_secs
+            from todos
+            order by updated_at desc, id desc
+            limit ?1
+        "#,
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let todo = Todo {
+                    id: row.id,
+                    text: row.text,
+                    completed: row.completed,
+                    due: row.due,
+                    version: row.version,
+                    uuid: row.uuid,
+                };
+                (todo, from_epoch_secs(row.modified_secs))
+            })
+            .collect())
+    }
+}
+
+/// MySQL counterpart of [`FILTER_CONDITIONS`]. Placeholders can't be
+/// reused, so [`bind_mysql_filter`] binds values once per occurrence; label
+/// and text lists arrive as JSON arrays, ids as a `,1,5,9,` list.
+#[cfg(feature = "mysql")]
+const MYSQL_FILTER_CONDITIONS: &str = r#"
+    (? is null or todos.completed = ?)
+    and not exists (
+        select 1 from json_table(?, '$[*]' columns (name varchar(255) path '$')) as wanted
+        where not exists (
+            select 1 from todo_labels tl
+                join labels on labels.id = tl.label_id
+            where tl.todo_id = todos.id and labels.name = wanted.name
+        )
+    )
+    and not exists (
+        select 1 from json_table(?, '$[*]' columns (word text path '$')) as term
+        where locate(lower(term.word), lower(todos.text)) = 0
+    )
+    and (? is null or locate(concat(',', todos.id, ','), ?) > 0)
+    and (? is null or todos.due < ?)
+    and (? is null or todos.due > ?)
+"#;
+
+#[cfg(feature = "mysql")]
+const MYSQL_FILTER_ORDER: &str =
+    "case when ? is null then 0 else locate(concat(',', todos.id, ','), ?) end";
+
+#[cfg(feature = "mysql")]
+type MySqlQueryAs<'q, O> = sqlx::query::QueryAs<'q, sqlx::MySql, O, sqlx::mysql::MySqlArguments>;
+
+#[cfg(feature = "mysql")]
+fn bind_mysql_filter<'q, O>(
+    query: MySqlQueryAs<'q, O>,
+    filter: &TodoFilter,
+    ordered: bool,
+) -> MySqlQueryAs<'q, O> {
+    let ids = filter.ids.as_ref().map(|ids| {
+        let ids: Vec<String> = ids.iter().map(i64::to_string).collect();
+        format!(",{},", ids.join(","))
+    });
+    let query = query
+        .bind(filter.completed)
+        .bind(filter.completed)
+        .bind(serde_json::to_string(&filter.labels).unwrap_or_default())
+        .bind(serde_json::to_string(&filter.text).unwrap_or_default())
+        .bind(ids.clone())
+        .bind(ids.clone())
+        .bind(filter.due_before.clone())
+        .bind(filter.due_before.clone())
+        .bind(filter.due_after.clone())
+        .bind(filter.due_after.clone());
+    if ordered {
+        query.bind(ids.clone()).bind(ids)
+    } else {
+        query
+    }
+}
+
+#[cfg(feature = "mysql")]
+#[derive(Debug, Clone)]
+pub struct TodoRepositoryForMySql {
+    pool: sqlx::MySqlPool,
+    /// Whether changes also queue their events in the outbox.
+    outbox: bool,
+}
+
+#[cfg(feature = "mysql")]
+impl TodoRepositoryForMySql {
+    pub fn new(pool: sqlx::MySqlPool) -> Self {
+        TodoRepositoryForMySql {
+            pool,
+            outbox: false,
+        }
+    }
+
+    /// See [`TodoRepositoryForDb::with_outbox`].
+    pub fn with_outbox(mut self) -> Self {
+        self.outbox = true;
+        self
+    }
+
+    async fn enqueue(
+        &self,
+        tx: &mut sqlx::Transaction<'_, sqlx::MySql>,
+        events: &[TodoEvent],
+    ) -> anyhow::Result<()> {
+        if self.outbox {
+            outbox::enqueue_mysql(tx, events).await?;
+        }
+        Ok(())
+    }
+}
+
+/// MySQL counterpart of [`attach_labels_pg`], one insert per label.
+#[cfg(feature = "mysql")]
+async fn attach_labels_mysql(
+    tx: &mut sqlx::Transaction<'_, sqlx::MySql>,
+    todo_id: i64,
+    labels: &[i64],
+) -> anyhow::Result<()> {
+    for label_id in distinct_labels(labels) {
+        let result = sqlx::query(
+            r#"
+            insert into todo_labels (todo_id, label_id)
+            select ?, id from labels where id=?
+        "#,
+        )
+        .bind(todo_id)
+        .bind(label_id)
+        .execute(&mut *tx)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(label_id).into());
+        }
+    }
+    Ok(())
+}
+
+/// MySQL has no `returning`, so each todo is inserted and read back on its
+/// own, inside the caller's transaction.
+#[cfg(feature = "mysql")]
+async fn create_mysql(
+    tx: &mut sqlx::Transaction<'_, sqlx::MySql>,
+    payload: &CreateTodo,
+) -> anyhow::Result<Todo> {
+    let result = sqlx::query(
+        r#"
+          insert into todos (text, completed, uuid, due)
+          values (?, ?, ?, ?)
+        "#,
+    )
+    .bind(&payload.text)
+    .bind(payload.completed)
+    .bind(&payload.uuid)
+    .bind(&payload.due)
+    .execute(&mut *tx)
+    .await?;
+    let id = result.last_insert_id() as i64;
+    attach_labels_mysql(tx, id, &payload.labels).await?;
+    let todo = sqlx::query_as::<_, Todo>("select * from todos where id=?")
+        .bind(id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+    Ok(todo)
+}
+
+#[cfg(feature = "mysql")]
+#[async_trait]
+impl TodoRepository for TodoRepositoryForMySql {
+    async fn create(&self, payload: CreateTodo) -> anyhow::Result<Todo> {
+        let mut tx = self.pool.begin().await?;
+        let todo = create_mysql(&mut tx, &payload).await?;
+        self.enqueue(&mut tx, &[TodoEvent::Created { id: todo.id }])
+            .await?;
+        tx.commit().await?;
+
+        Ok(todo)
+    }
+    async fn create_many(&self, payloads: Vec<CreateTodo>) -> anyhow::Result<Vec<Todo>> {
+        let mut tx = self.pool.begin().await?;
+        let mut todos = Vec::with_capacity(payloads.len());
+        for payload in &payloads {
+            todos.push(create_mysql(&mut tx, payload).await?);
+        }
+        let events: Vec<_> = todos
+            .iter()
+            .map(|todo| TodoEvent::Created { id: todo.id })
+            .collect();
+        self.enqueue(&mut tx, &events).await?;
+        tx.commit().await?;
+
+        Ok(todos)
+    }
+    async fn import<L: LabelRepository>(
+        &self,
+        _labels: &L,
+        import: Import,
+    ) -> anyhow::Result<ImportedRows> {
+        let mut tx = self.pool.begin().await?;
+        let mut imported = ImportedRows::default();
+        for label in import.labels {
+            let existing: Option<i64> = sqlx::query_scalar("select id from labels where name = ?")
+                .bind(&label.name)
+                .fetch_optional(&mut tx)
+                .await?;
+            let id = match existing {
+                Some(id) => id,
+                None => {
+                    let (taken,): (i64,) =
+                        sqlx::query_as("select count(*) from labels where uuid = ?")
+                            .bind(&label.uuid)
+                            .fetch_one(&mut tx)
+                            .await?;
+                    let uuid = label.uuid.filter(|_| taken == 0);
+                    let result = sqlx::query("insert into labels (name, uuid) values (?, ?)")
+                        .bind(&label.name)
+                        .bind(uuid)
+                        .execute(&mut tx)
+                        .await?;
+                    imported.created_labels += 1;
+                    result.last_insert_id() as i64
+                }
+            };
+            imported.label_ids.push(id);
+        }
+        for payload in labelled(import.todos, &imported.label_ids) {
+            imported.todos.push(create_mysql(&mut tx, &payload).await?);
+        }
+        let events: Vec<_> = imported
+            .todos
+            .iter()
+            .map(|todo| TodoEvent::Created { id: todo.id })
+            .collect();
+        self.enqueue(&mut tx, &events).await?;
+        tx.commit().await?;
+
+        Ok(imported)
+    }
+    async fn find(&self, id: i64) -> anyhow::Result<Todo> {
+        let todo = sqlx::query_as::<_, Todo>(
+            r#"
+            select * from todos where id=?
+        "#,
+        )
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(not_found(id))?;
+
+        Ok(todo)
+    }
+    async fn all(&self, filter: &TodoFilter) -> anyhow::Result<Vec<Todo>> {
+        let sql = format!(
+            r#"
+            select * from todos
+            where {}
+            order by {}, id desc;
+        "#,
+            MYSQL_FILTER_CONDITIONS, MYSQL_FILTER_ORDER
+        );
+        let todos = bind_mysql_filter(sqlx::query_as::<_, Todo>(&sql), filter, true)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(todos)
+    }
+    async fn count(&self, filter: &TodoFilter) -> anyhow::Result<i64> {
+        let sql = format!(
+            "select count(*) from todos where {}",
+            MYSQL_FILTER_CONDITIONS
+        );
+        let (count,) = bind_mysql_filter(sqlx::query_as::<_, (i64,)>(&sql), filter, false)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(count)
+    }
+    fn stream_all(&self) -> BoxStream<'static, anyhow::Result<Todo>> {
+        let pool = self.pool.clone();
+        let (tx, rx) = tokio::sync::mpsc::channel(EXPORT_BUFFER);
+        tokio::spawn(async move {
+            let mut rows =
+                sqlx::query_as::<_, Todo>("select * from todos order by id asc").fetch(&pool);
+            while let Some(row) = rows.next().await {
+                let failed = row.is_err();
+                if tx.send(row.map_err(anyhow::Error::from)).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        futures_util::stream::unfold(
+            rx,
+            |mut rx| async move { rx.recv().await.map(|row| (row, rx)) },
+        )
+        .boxed()
+    }
+    async fn find_with_labels(&self, id: i64) -> anyhow::Result<TodoWithLabels> {
+        let row = sqlx::query_as::<_, TodoWithLabelsFromRow>(
+            r#"
+            select todos.*, if(
+                    count(labels.id) = 0,
+                    json_array(),
+                    json_arrayagg(json_object('id', labels.id, 'uuid', labels.uuid, 'name', labels.name))
+                ) as labels
+            from todos
+                left outer join todo_labels tl on todos.id = tl.todo_id
+                left outer join labels on labels.id = tl.label_id
+            where todos.id=?
+            group by todos.id;
+        "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let todo = row.ok_or(RepositoryError::NotFound(id))?;
+
+        Ok(todo.into())
+    }
+    async fn all_with_labels(&self, filter: &TodoFilter) -> anyhow::Result<Vec<TodoWithLabels>> {
+        let sql = format!(
+            r#"
+            select todos.*, if(
+                    count(labels.id) = 0,
+                    json_array(),
+                    json_arrayagg(json_object('id', labels.id, 'uuid', labels.uuid, 'name', labels.name))
+                ) as labels
+            from todos
+                left outer join todo_labels tl on todos.id = tl.todo_id
+                left outer join labels on labels.id = tl.label_id
+            where {}
+            group by todos.id
+            order by {}, todos.id desc;
+        "#,
+            MYSQL_FILTER_CONDITIONS, MYSQL_FILTER_ORDER
+        );
+        let rows = bind_mysql_filter(
+            sqlx::query_as::<_, TodoWithLabelsFromRow>(&sql),
+            filter,
+            true,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(TodoWithLabels::from).collect())
+    }
+    async fn update(&self, id: i64, payload: UpdateTodo) -> anyhow::Result<Todo> {
+        let mut tx = self.pool.begin().await?;
+        let result = sqlx::query(
+            r#"
+            update todos set text=coalesce(?, text), completed=coalesce(?, completed),
+                due=if(?, ?, due), version=version+1, updated_at=current_timestamp
+            where id=? and (? is null or version=?)
+        "#,
+        )
+        .bind(payload.text)
+        .bind(payload.completed)
+        .bind(payload.due.is_some())
+        .bind(payload.due.flatten())
+        .bind(id)
+        .bind(payload.version)
+        .bind(payload.version)
+        .execute(&mut tx)
+        .await?;
+        // The version bump means a matched row always counts as affected.
+        if result.rows_affected() == 0 {
+            let count: i64 = sqlx::query_scalar("select count(*) from todos where id=?")
+                .bind(id)
+                .fetch_one(&mut tx)
+                .await?;
+            return Err(missing_or_stale(id, count > 0));
+        }
+        let todo = sqlx::query_as::<_, Todo>("select * from todos where id=?")
+            .bind(id)
+            .fetch_one(&mut tx)
+            .await
+            .map_err(not_found(id))?;
+        if let Some(labels) = payload.labels {
+            sqlx::query("delete from todo_labels where todo_id=?")
+                .bind(id)
+                .execute(&mut tx)
+                .await?;
+            attach_labels_mysql(&mut tx, id, &labels).await?;
+        }
+        self.enqueue(&mut tx, &[TodoEvent::Updated { id }]).await?;
+        tx.commit().await?;
+
+        Ok(todo)
+    }
+    async fn delete(&self, id: i64) -> anyhow::Result<()> {
+        let mut tx = self.pool.begin().await?;
+        let result = sqlx::query(
+            r#"
+            delete from todos where id=?
+        "#,
+        )
+        .bind(id)
+        .execute(&mut tx)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(RepositoryError::NotFound(id).into());
+        }
+        self.enqueue(&mut tx, &[TodoEvent::Deleted { id }]).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+    async fn purge_completed(&self) -> anyhow::Result<u64> {
+        let mut tx = self.pool.begin().await?;
+        // todo_labels rows go with their todo through `on delete cascade`.
+        let result = sqlx::query(
+            r#"
+            delete from todos where completed
+        "#,
+        )
+        .execute(&mut tx)
+        .await?;
+        let count = result.rows_affected();
+        if count > 0 {
+            self.enqueue(&mut tx, &[TodoEvent::Purged { count }])
+                .await?;
+        }
+        tx.commit().await?;
+
+        Ok(count)
+    }
+    async fn archive_completed(&self, older_than: Duration) -> anyhow::Result<u64> {
+        let cutoff = epoch_secs(SystemTime::now() - older_than);
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            r#"
+            insert into archived_todos (id, uuid, text, completed, version, updated_at, due)
+            select id, uuid, text, completed, version, updated_at, due from todos
+            where completed and updated_at < from_unixtime(?)
+        "#,
+        )
+        .bind(cutoff)
+        .execute(&mut tx)
+        .await?;
+        // todo_labels rows go with their todo through `on delete cascade`.
+        let result =
+            sqlx::query("delete from todos where completed and updated_at < from_unixtime(?)")
+                .bind(cutoff)
+                .execute(&mut tx)
+                .await?;
+        let count = result.rows_affected();
+        if count > 0 {
+            self.enqueue(&mut tx, &[TodoEvent::Archived { count }])
+                .await?;
+        }
+        tx.commit().await?;
+
+        Ok(count)
+    }
+    async fn archived(&self) -> anyhow::Result<Vec<Todo>> {
+        let todos = sqlx::query_as::<_, Todo>(
+            r#"
+            select * from archived_todos
+            order by archived_at desc, id desc
+        "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(todos)
+    }
+    async fn last_modified(&self, id: i64) -> anyhow::Result<SystemTime> {
+        let (secs,): (i64,) = sqlx::query_as(
+            r#"
+            select cast(unix_timestamp(updated_at) as signed) from todos where id=?
+        "#,
+        )
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(not_found(id))?;
+
+        Ok(from_epoch_secs(secs))
+    }
+    async fn collection_last_modified(&self) -> anyhow::Result<SystemTime> {
+        let (secs,): (i64,) = sqlx::query_as(
+            r#"
+            select cast(unix_timestamp(modified_at) as signed) from last_modified
+            where resource='todos'
+        "#,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(from_epoch_secs(secs))
+    }
+    async fn resolve(&self, key: &Key) -> anyhow::Result<i64> {
+        match key {
+            Key::Serial(id) => Ok(*id),
+            Key::Uuid(uuid) => sqlx::query_scalar("select id from todos where uuid=?")
+                .bind(uuid)
+                .fetch_optional(&self.pool)
+                .await?
+                .ok_or_else(|| RepositoryError::UnknownUuid(uuid.clone()).into()),
+        }
+    }
+    async fn recently_modified(&self, limit: i64) -> anyhow::Result<Vec<(Todo, SystemTime)>> {
+        let rows = sqlx::query_as::<_, RecentTodoFromRow>(
+            r#"
+            select id, uuid, text, completed, due, version,
+                cast(unix_timestamp(updated_at) as signed) as modified_secs
+            from todos
+            order by updated_at desc, id desc
+            limit ?
+        "#,
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let todo = Todo {
+                    id: row.id,
+                    text: row.text,
+                    completed: row.completed,
+                    due: row.due,
+                    version: row.version,
+                    uuid: row.uuid,
+                };
+                (todo, from_epoch_secs(row.modified_secs))
+            })
+            .collect())
+    }
+}
+
+/// How a todo is stored in MongoDB: one document per todo with copies of
+/// its labels embedded, so no lookup is ever needed to read it.
+#[cfg(feature = "mongodb")]
+#[derive(Debug, Serialize, Deserialize)]
+struct TodoDocument {
+    #[serde(rename = "_id")]
+    id: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    uuid: Option<Uuid>,
+    text: String,
+    completed: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    due: Option<Date>,
+    version: i32,
+    updated_at: bson::DateTime,
+    /// In label id order.
+    #[serde(default)]
+    labels: Vec<Label>,
+}
+
+#[cfg(feature = "mongodb")]
+impl From<TodoDocument> for TodoWithLabels {
+    fn from(document: TodoDocument) -> Self {
+        TodoWithLabels {
+            todo: Todo {
+                id: document.id,
+                uuid: document.uuid,
+                text: document.text,
+                completed: document.completed,
+                due: document.due,
+                version: document.version,
+            },
+            labels: document.labels,
+        }
+    }
+}
+
+#[cfg(feature = "mongodb")]
+impl From<TodoDocument> for Todo {
+    fn from(document: TodoDocument) -> Self {
+        TodoWithLabels::from(document).todo
+    }
+}
+
+/// `filter` as a MongoDB query on the `todos` collection.
+#[cfg(feature = "mongodb")]
+fn mongo_filter(filter: &TodoFilter) -> bson::Document {
+    let mut conditions = Vec::new();
+    if let Some(completed) = filter.completed {
+        conditions.push(doc! { "completed": completed });
+    }
+    if !filter.labels.is_empty() {
+        conditions.push(doc! { "labels.name": { "$all": filter.labels.clone() } });
+    }
+    for term in &filter.text {
+        conditions.push(doc! { "text": { "$regex": regex_literal(term), "$options": "i" } });
+    }
+    if let Some(ids) = &filter.ids {
+        conditions.push(doc! { "_id": { "$in": ids.clone() } });
+    }
+    if let Some(day) = &filter.due_before {
+        conditions.push(doc! { "due": { "$lt": day.as_str() } });
+    }
+    if let Some(day) = &filter.due_after {
+        conditions.push(doc! { "due": { "$gt": day.as_str() } });
+    }
+    if conditions.is_empty() {
+        doc! {}
+    } else {
+        doc! { "$and": conditions }
+    }
+}
+
+/// A regular expression matching `text` literally: a backslash before any
+/// other ASCII character than a letter or digit makes it stand for itself.
+#[cfg(feature = "mongodb")]
+fn regex_literal(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_ascii() && !c.is_ascii_alphanumeric() {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(feature = "mongodb")]
+#[derive(Debug, Clone)]
+pub struct TodoRepositoryForMongo {
+    db: mongodb::Database,
+}
+
+#[cfg(feature = "mongodb")]
+impl TodoRepositoryForMongo {
+    pub fn new(db: mongodb::Database) -> Self {
+        TodoRepositoryForMongo { db }
+    }
+
+    fn todos(&self) -> mongodb::Collection<TodoDocument> {
+        self.db.collection("todos")
+    }
+
+    /// The labels named by `ids`, in id order, failing with the first id
+    /// that names no label.
+    async fn labels(&self, ids: &[i64]) -> anyhow::Result<Vec<Label>> {
+        let ids = distinct_labels(ids);
+        if ids.is_empty() {
+            return Ok(vec![]);
+        }
+        let options = FindOptions::builder().sort(doc! { "_id": 1 }).build();
+        let labels: Vec<Label> = self
+            .db
+            .collection::<LabelDocument>("labels")
+            .find(doc! { "_id": { "$in": ids.clone() } }, options)
+            .await?
+            .map_ok(Label::from)
+            .try_collect()
+            .await?;
+        match ids
+            .iter()
+            .find(|id| !labels.iter().any(|label| label.id == **id))
+        {
+            Some(missing) => Err(RepositoryError::NotFound(*missing).into()),
+            None => Ok(labels),
+        }
+    }
+
+    /// Records that the collection changed, for `collection_last_modified`.
+    async fn touch(&self) -> anyhow::Result<()> {
+        self.db
+            .collection::<bson::Document>("last_modified")
+            .update_one(
+                doc! { "_id": "todos" },
+                doc! { "$set": { "modified_at": bson::DateTime::now() } },
+                UpdateOptions::builder().upsert(true).build(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn find_document(&self, id: i64) -> anyhow::Result<TodoDocument> {
+        let document = self.todos().find_one(doc! { "_id": id }, None).await?;
+        Ok(document.ok_or(RepositoryError::NotFound(id))?)
+    }
+
+    async fn filtered(&self, filter: &TodoFilter) -> anyhow::Result<Vec<TodoDocument>> {
+        let options = FindOptions::builder().sort(doc! { "_id": -1 }).build();
+        let mut documents: Vec<TodoDocument> = self
+            .todos()
+            .find(mongo_filter(filter), options)
+            .await?
+            .try_collect()
+            .await?;
+        if let Some(ids) = &filter.ids {
+            documents.sort_by_key(|document| ids.iter().position(|id| *id == document.id));
+        }
+        Ok(documents)
+    }
+}
+
+#[cfg(feature = "mongodb")]
+fn modified_at(document: &TodoDocument) -> SystemTime {
+    from_epoch_secs(document.updated_at.timestamp_millis() / 1000)
+}
+
+#[cfg(feature = "mongodb")]
+#[async_trait]
+impl TodoRepository for TodoRepositoryForMongo {
+    async fn create(&self, payload: CreateTodo) -> anyhow::Result<Todo> {
+        let labels = self.labels(&payload.labels).await?;
+        if let Some(uuid) = &payload.uuid {
+            let existing = self
+                .todos()
+                .find_one(doc! { "uuid": uuid.as_str() }, None)
+                .await?;
+            if let Some(existing) = existing {
+                return Err(RepositoryError::Duplicate(existing.id).into());
+            }
+        }
+        let document = TodoDocument {
+            id: next_id(&self.db, "todos").await?,
+            uuid: payload.uuid,
+            text: payload.text,
+            completed: payload.completed,
+            due: payload.due,
+            version: first_version(),
+            updated_at: bson::DateTime::now(),
+            labels,
+        };
+        self.todos().insert_one(&document, None).await?;
+        self.touch().await?;
+
+        Ok(document.into())
+    }
+    async fn find(&self, id: i64) -> anyhow::Result<Todo> {
+        Ok(self.find_document(id).await?.into())
+    }
+    async fn all(&self, filter: &TodoFilter) -> anyhow::Result<Vec<Todo>> {
+        let documents = self.filtered(filter).await?;
+        Ok(documents.into_iter().map(Todo::from).collect())
+    }
+    async fn count(&self, filter: &TodoFilter) -> anyhow::Result<i64> {
+        let count = self
+            .todos()
+            .count_documents(mongo_filter(filter), None)
+            .await?;
+        Ok(count as i64)
+    }
+    fn stream_all(&self) -> BoxStream<'static, anyhow::Result<Todo>> {
+        let todos = self.todos();
+        futures_util::stream::once(async move {
+            let options = FindOptions::builder().sort(doc! { "_id": 1 }).build();
+            todos.find(None, options).await
+        })
+        .try_flatten()
+        .map_ok(Todo::from)
+        .map_err(anyhow::Error::from)
+        .boxed()
+    }
+    async fn find_with_labels(&self, id: i64) -> anyhow::Result<TodoWithLabels> {
+        Ok(self.find_document(id).await?.into())
+    }
+    async fn all_with_labels(&self, filter: &TodoFilter) -> anyhow::Result<Vec<TodoWithLabels>> {
+        let documents = self.filtered(filter).await?;
+        Ok(documents.into_iter().map(TodoWithLabels::from).collect())
+    }
+    async fn update(&self, id: i64, payload: UpdateTodo) -> anyhow::Result<Todo> {
+        let mut changes = doc! { "updated_at": bson::DateTime::now() };
+        if let Some(text) = payload.text {
+            changes.insert("text", text);
+        }
+        if let Some(completed) = payload.completed {
+            changes.insert("completed", completed);
+        }
+        if let Some(due) = &payload.due {
+            changes.insert("due", bson::to_bson(due)?);
+        }
+        if let Some(labels) = &payload.labels {
+            changes.insert("labels", bson::to_bson(&self.labels(labels).await?)?);
+        }
+        let mut selector = doc! { "_id": id };
+        if let Some(version) = payload.version {
+            selector.insert("version", version);
+        }
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+        let document = self
+            .todos()
+            .find_one_and_update(
+                selector,
+                doc! { "$set": changes, "$inc": { "version": 1 } },
+                options,
+            )
+            .await?;
+        let document = match document {
+            Some(document) => document,
+            None => {
+                let exists = self
+                    .todos()
+                    .count_documents(doc! { "_id": id }, None)
+                    .await?
+                    > 0;
+                return Err(missing_or_stale(id, exists));
+            }
+        };
+        self.touch().await?;
+
+        Ok(document.into())
+    }
+    async fn delete(&self, id: i64) -> anyhow::Result<()> {
+        let result = self.todos().delete_one(doc! { "_id": id }, None).await?;
+        if result.deleted_count == 0 {
+            return Err(RepositoryError::NotFound(id).into());
+        }
+        self.touch().await?;
+
+        Ok(())
+    }
+    async fn purge_completed(&self) -> anyhow::Result<u64> {
+        let result = self
+            .todos()
+            .delete_many(doc! { "completed": true }, None)
+            .await?;
+        self.touch().await?;
+
+        Ok(result.deleted_count)
+    }
+    async fn last_modified(&self, id: i64) -> anyhow::Result<SystemTime> {
+        Ok(modified_at(&self.find_document(id).await?))
+    }
+    async fn collection_last_modified(&self) -> anyhow::Result<SystemTime> {
+        let modified = self
+            .db
+            .collection::<bson::Document>("last_modified")
+            .find_one(doc! { "_id": "todos" }, None)
+            .await?;
+        Ok(match modified {
+            Some(modified) => {
+                from_epoch_secs(modified.get_datetime("modified_at")?.timestamp_millis() / 1000)
+            }
+            None => UNIX_EPOCH,
+        })
+    }
+    async fn recently_modified(&self, limit: i64) -> anyhow::Result<Vec<(Todo, SystemTime)>> {
+        let options = FindOptions::builder()
+            .sort(doc! { "updated_at": -1, "_id": -1 })
+            .limit(limit.max(0))
+            .build();
+        let documents: Vec<TodoDocument> = self
+            .todos()
+            .find(None, options)
+            .await?
+            .try_collect()
+            .await?;
+
+        Ok(documents
+            .into_iter()
+            .map(|document| {
+                let at = modified_at(&document);
+                (document.into(), at)
+            })
+            .collect())
+    }
+    async fn resolve(&self, key: &Key) -> anyhow::Result<i64> {
+        match key {
+            Key::Serial(id) => Ok(*id),
+            Key::Uuid(uuid) => self
+                .todos()
+                .find_one(doc! { "uuid": uuid.as_str() }, None)
+                .await?
+                .map(|document| document.id)
+                .ok_or_else(|| RepositoryError::UnknownUuid(uuid.clone()).into()),
+        }
+    }
+}
+
+/// How a todo is stored in the `todos` partition of a [`DynamoTable`], with
+/// copies of its labels like [`TodoDocument`] in MongoDB.
+#[cfg(feature = "dynamodb")]
+#[derive(Debug, Serialize, Deserialize)]
+struct TodoItem {
+    pk: String,
+    sk: String,
+    id: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    uuid: Option<Uuid>,
+    text: String,
+    completed: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    due: Option<Date>,
+    version: i32,
+    /// Seconds since the epoch.
+    updated_at: i64,
+    /// In label id order.
+    #[serde(default)]
+    labels: Vec<Label>,
+}
+
+#[cfg(feature = "dynamodb")]
+impl From<TodoItem> for TodoWithLabels {
+    fn from(item: TodoItem) -> Self {
+        TodoWithLabels {
+            todo: Todo {
+                id: item.id,
+                uuid: item.uuid,
+                text: item.text,
+                completed: item.completed,
+                due: item.due,
+                version: item.version,
+            },
+            labels: item.labels,
+        }
+    }
+}
+
+#[cfg(feature = "dynamodb")]
+impl From<TodoItem> for Todo {
+    fn from(item: TodoItem) -> Self {
+        TodoWithLabels::from(item).todo
+    }
+}
+
+/// Todos in a single DynamoDB table, see [`DynamoTable`]. Lists read the
+/// whole `todos` partition and filter it here, which suits the few thousand
+/// todos of a person or a team.
+#[cfg(feature = "dynamodb")]
+#[derive(Debug, Clone)]
+pub struct TodoRepositoryForDynamo {
+    table: DynamoTable,
+}
+
+#[cfg(feature = "dynamodb")]
+impl TodoRepositoryForDynamo {
+    pub fn new(table: DynamoTable) -> Self {
+        TodoRepositoryForDynamo { table }
+    }
+
+    /// The labels named by `ids`, in id order, failing with the first id
+    /// that names no label.
+    async fn labels(&self, ids: &[i64]) -> anyhow::Result<Vec<Label>> {
+        let mut ids = distinct_labels(ids);
+        ids.sort_unstable();
+        let mut labels = Vec::with_capacity(ids.len());
+        for id in ids {
+            let item = self
+                .table
+                .get("labels", DynamoTable::sort_key("label", id))
+                .await?
+                .ok_or(RepositoryError::NotFound(id))?;
+            labels.push(from_item(item)?);
+        }
+        Ok(labels)
+    }
+
+    /// Records that the collection changed, for `collection_last_modified`.
+    async fn touch(&self) -> anyhow::Result<()> {
+        let mut item = DynamoTable::key("last-modified", "todos");
+        item.insert(
+            "modified_at".to_string(),
+            AttributeValue::N(epoch_secs(SystemTime::now()).to_string()),
+        );
+        self.table
+            .client()
+            .put_item()
+            .table_name(self.table.name())
+            .set_item(Some(item))
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    async fn find_item(&self, id: i64) -> anyhow::Result<TodoItem> {
+        let item = self
+            .table
+            .get("todos", DynamoTable::sort_key("todo", id))
+            .await?
+            .ok_or(RepositoryError::NotFound(id))?;
+        Ok(from_item(item)?)
+    }
+
+    /// Every todo, newest first.
+    async fn items(&self) -> anyhow::Result<Vec<TodoItem>> {
+        Ok(from_items(self.table.partition("todos", false).await?)?)
+    }
+
+    async fn filtered(&self, filter: &TodoFilter) -> anyhow::Result<Vec<TodoItem>> {
+        let mut items: Vec<TodoItem> = self
+            .items()
+            .await?
+            .into_iter()
+            .filter(|item| {
+                let todo = Todo {
+                    id: item.id,
+                    uuid: None,
+                    text: item.text.clone(),
+                    completed: item.completed,
+                    due: item.due.clone(),
+                    version: item.version,
+                };
+                filter.matches(&todo, &item.labels)
+            })
+            .collect();
+        if let Some(ids) = &filter.ids {
+            items.sort_by_key(|item| ids.iter().position(|id| *id == item.id));
+        }
+        Ok(items)
+    }
+}
+
+#[cfg(feature = "dynamodb")]
+#[async_trait]
+impl TodoRepository for TodoRepositoryForDynamo {
+    async fn create(&self, payload: CreateTodo) -> anyhow::Result<Todo> {
+        let labels = self.labels(&payload.labels).await?;
+        let id = self.table.next_id("todos").await?;
+        let item = TodoItem {
+            pk: "todos".to_string(),
+            sk: DynamoTable::sort_key("todo", id),
+            id,
+            uuid: payload.uuid,
+            text: payload.text,
+            completed: payload.completed,
+            due: payload.due,
+            version: first_version(),
+            updated_at: epoch_secs(SystemTime::now()),
+            labels,
+        };
+        let mut steps = vec![self.table.put_new(to_item(&item)?)?];
+        if let Some(uuid) = &item.uuid {
+            steps.push(
+                self.table
+                    .put_new(DynamoTable::claim("todo-uuids", uuid.as_str(), id))?,
+            );
+        }
+        if !self.table.transact(steps).await? {
+            if let Some(uuid) = &item.uuid {
+                if let Some(existing) = self.table.claimed_by("todo-uuids", uuid.as_str()).await? {
+                    return Err(RepositoryError::Duplicate(existing).into());
+                }
+            }
+            anyhow::bail!("todo {} was created concurrently", id);
+        }
+        self.touch().await?;
+
+        Ok(item.into())
+    }
+    async fn find(&self, id: i64) -> anyhow::Result<Todo> {
+        Ok(self.find_item(id).await?.into())
+    }
+    async fn all(&self, filter: &TodoFilter) -> anyhow::Result<Vec<Todo>> {
+        let items = self.filtered(filter).await?;
+        Ok(items.into_iter().map(Todo::from).collect())
+    }
+    async fn count(&self, filter: &TodoFilter) -> anyhow::Result<i64> {
+        Ok(self.filtered(filter).await?.len() as i64)
+    }
+    fn stream_all(&self) -> BoxStream<'static, anyhow::Result<Todo>> {
+        let table = self.table.clone();
+        futures_util::stream::once(async move { table.partition("todos", true).await })
+            .map_ok(|items| {
+                futures_util::stream::iter(items.into_iter().map(|item| {
+                    let item: TodoItem = from_item(item)?;
+                    Ok(item.into())
+                }))
+            })
+            .try_flatten()
+            .boxed()
+    }
+    async fn find_with_labels(&self, id: i64) -> anyhow::Result<TodoWithLabels> {
+        Ok(self.find_item(id).await?.into())
+    }
+    async fn all_with_labels(&self, filter: &TodoFilter) -> anyhow::Result<Vec<TodoWithLabels>> {
+        let items = self.filtered(filter).await?;
+        Ok(items.into_iter().map(TodoWithLabels::from).collect())
+    }
+    async fn update(&self, id: i64, payload: UpdateTodo) -> anyhow::Result<Todo> {
+        let mut update = self
+            .table
+            .client()
+            .update_item()
+            .table_name(self.table.name())
+            .set_key(Some(DynamoTable::key(
+                "todos",
+                DynamoTable::sort_key("todo", id),
+            )))
+            .expression_attribute_names("#version", "version")
+            .expression_attribute_names("#updated_at", "updated_at")
+            .expression_attribute_values(":one", AttributeValue::N("1".to_string()))
+            .expression_attribute_values(
+                ":now",
+                AttributeValue::N(epoch_secs(SystemTime::now()).to_string()),
+            )
+            .return_values(ReturnValue::AllNew);
+        let mut set = vec!["#version = #version + :one", "#updated_at = :now"];
+        if let Some(text) = payload.text {
+            set.push("#text = :text");
+            update = update
+                .expression_attribute_names("#text", "text")
+                .expression_attribute_values(":text", AttributeValue::S(text));
+        }
+        if let Some(completed) = payload.completed {
+            set.push("#completed = :completed");
+            update = update
+                .expression_attribute_names("#completed", "completed")
+                .expression_attribute_values(":completed", AttributeValue::Bool(completed));
+        }
+        let mut remove = vec![];
+        match payload.due {
+            Some(Some(due)) => {
+                set.push("#due = :due");
+                update = update
+                    .expression_attribute_names("#due", "due")
+                    .expression_attribute_values(":due", AttributeValue::S(due.into()));
+            }
+            Some(None) => {
+                remove.push("#due");
+                update = update.expression_attribute_names("#due", "due");
+            }
+            None => {}
+        }
+        if let Some(labels) = &payload.labels {
+            set.push("#labels = :labels");
+            update = update
+                .expression_attribute_names("#labels", "labels")
+                .expression_attribute_values(
+                    ":labels",
+                    to_attribute_value(self.labels(labels).await?)?,
+                );
+        }
+        let mut condition = "attribute_exists(pk)".to_string();
+        if let Some(version) = payload.version {
+            condition.push_str(" AND #version = :version");
+            update = update
+                .expression_attribute_values(":version", AttributeValue::N(version.to_string()));
+        }
+        let mut expression = format!("SET {}", set.join(", "));
+        if !remove.is_empty() {
+            expression.push_str(&format!(" REMOVE {}", remove.join(", ")));
+        }
+        let updated = update
+            .update_expression(expression)
+            .condition_expression(condition)
+            .send()
+            .await;
+        let attributes = match updated {
+            Ok(updated) => updated.attributes.unwrap_or_default(),
+            Err(aws_sdk_dynamodb::error::SdkError::ServiceError(e))
+                if e.err().is_conditional_check_failed_exception() =>
+            {
+                let exists = self
+                    .table
+                    .get("todos", DynamoTable::sort_key("todo", id))
+                    .await?
+                    .is_some();
+                return Err(missing_or_stale(id, exists));
+            }
+            Err(e) => return Err(e.into()),
+        };
+        self.touch().await?;
+
+        let item: TodoItem = from_item(attributes)?;
+        Ok(item.into())
+    }
+    async fn delete(&self, id: i64) -> anyhow::Result<()> {
+        let item = self.find_item(id).await?;
+        let mut steps = vec![self.table.delete("todos", item.sk)?];
+        if let Some(uuid) = &item.uuid {
+            steps.push(self.table.delete("todo-uuids", uuid.as_str())?);
+        }
+        self.table.transact(steps).await?;
+        self.touch().await?;
+
+        Ok(())
+    }
+    async fn purge_completed(&self) -> anyhow::Result<u64> {
+        let mut purged = 0;
+        for item in self.items().await? {
+            if !item.completed {
+                continue;
+            }
+            let mut steps = vec![self.table.delete("todos", item.sk)?];
+            if let Some(uuid) = &item.uuid {
+                steps.push(self.table.delete("todo-uuids", uuid.as_str())?);
+            }
+            self.table.transact(steps).await?;
+            purged += 1;
+        }
+        self.touch().await?;
+
+        Ok(purged)
+    }
+    async fn last_modified(&self, id: i64) -> anyhow::Result<SystemTime> {
+        Ok(from_epoch_secs(self.find_item(id).await?.updated_at))
+    }
+    async fn collection_last_modified(&self) -> anyhow::Result<SystemTime> {
+        let modified = self
+            .table
+            .get("last-modified", "todos")
+            .await?
+            .and_then(|mut item| item.remove("modified_at"));
+        Ok(match modified {
+            Some(secs) => from_epoch_secs(serde_dynamo::from_attribute_value(secs)?),
+            None => UNIX_EPOCH,
+        })
+    }
+    async fn recently_modified(&self, limit: i64) -> anyhow::Result<Vec<(Todo, SystemTime)>> {
+        let mut items = self.items().await?;
+        // already newest id first, a stable sort keeps that among equals
+        items.sort_by_key(|item| std::cmp::Reverse(item.updated_at));
+        items.truncate(limit.max(0) as usize);
+
+        Ok(items
+            .into_iter()
+            .map(|item| {
+                let at = from_epoch_secs(item.updated_at);
+                (item.into(), at)
+            })
+            .collect())
+    }
+    async fn resolve(&self, key: &Key) -> anyhow::Result<i64> {
+        match key {
+            Key::Serial(id) => Ok(*id),
+            Key::Uuid(uuid) => self
+                .table
+                .claimed_by("todo-uuids", uuid.as_str())
+                .await?
+                .ok_or_else(|| RepositoryError::UnknownUuid(uuid.clone()).into()),
+        }
+    }
+}
+
+fn from_epoch_secs(secs: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(secs.max(0) as u64)
+}
+
+pub(super) fn epoch_secs(at: SystemTime) -> i64 {
+    at.duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
 
     use super::*;
     use dotenv::dotenv;
 
+    /// Creates a todo due on a day, then checks updates keep, move and
+    /// clear its due date, that filters compare it, and deletes it.
+    async fn due_dates_are_kept_moved_and_cleared<T: TodoRepository>(repository: &T) {
+        let day = |raw: &str| Some(raw.parse::<Date>().unwrap());
+        let created = repository
+            .create(CreateTodo::new("[due] rent".to_string()).with_due(day("2024-06-01")))
+            .await
+            .unwrap();
+        assert_eq!(created.due, day("2024-06-01"));
+        let json = serde_json::to_value(&created).unwrap();
+        assert_eq!(json["due"], "2024-06-01");
+
+        let kept = repository
+            .update(created.id, UpdateTodo::new(None, Some(true)))
+            .await
+            .unwrap();
+        assert_eq!(kept.due, day("2024-06-01"));
+        let moved = repository
+            .update(
+                created.id,
+                UpdateTodo::new(None, None).with_due(day("2024-07-01")),
+            )
+            .await
+            .unwrap();
+        assert_eq!(moved.due, day("2024-07-01"));
+        let between = |before: &str, after: &str| TodoFilter {
+            ids: Some(vec![created.id]),
+            due_before: day(before),
+            due_after: day(after),
+            ..TodoFilter::default()
+        };
+        let filter = between("2024-07-02", "2024-06-30");
+        assert_eq!(repository.count(&filter).await.unwrap(), 1);
+        let filter = between("2024-07-01", "2024-06-01");
+        assert_eq!(repository.count(&filter).await.unwrap(), 0);
+        let payload: UpdateTodo = serde_json::from_str(r#"{"due": null}"#).unwrap();
+        let cleared = repository.update(created.id, payload).await.unwrap();
+        assert_eq!(cleared.due, None);
+        let filter = between("2099-01-01", "1970-01-01");
+        assert_eq!(repository.count(&filter).await.unwrap(), 0);
+        assert!(serde_json::to_value(&cleared).unwrap().get("due").is_none());
+        let found = repository.find(created.id).await.unwrap();
+        assert_eq!(found, cleared);
+
+        repository.delete(created.id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn memory_keeps_due_dates() {
+        due_dates_are_kept_moved_and_cleared(&TodoRepositoryForMemory::new()).await;
+        let absent: UpdateTodo = serde_json::from_str(r#"{"completed": true}"#).unwrap();
+        assert_eq!(absent, UpdateTodo::new(None, Some(true)));
+        assert!(serde_json::from_str::<CreateTodo>(r#"{"text": "a", "due": "June"}"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn search_ranks_and_highlights_in_memory() {
+        let repository = TodoRepositoryForMemory::new();
+        for text in [
+            "Buy milk",
+            "milk, MILK and more milk",
+            "bread",
+            "<b>milk</b> or milk",
+        ] {
+            repository
+                .create(CreateTodo::new(text.to_string()))
+                .await
+                .unwrap();
+        }
+        let filter = TodoFilter {
+            text: vec!["milk".to_string()],
+            ..Default::default()
+        };
+
+        let hits = repository.search(&filter, 10).await.unwrap();
+        let highlights: Vec<_> = hits.iter().map(|hit| hit.highlight.as_str()).collect();
+        assert_eq!(
+            highlights,
+            vec![
+                "<mark>milk</mark>, <mark>MILK</mark> and more <mark>milk</mark>",
+                "&lt;b&gt;<mark>milk</mark>&lt;/b&gt; or <mark>milk</mark>",
+                "Buy <mark>milk</mark>",
+            ]
+        );
+        assert_eq!(repository.search(&filter, 1).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn todo_curd_scenario() {
+        let text = "todo text".to_string();
+        let id = 1;
+        let expected = Todo::new(id, text.clone());
+
+        // create
+        let repository = TodoRepositoryForMemory::new();
+        let todo = repository
+            .create(CreateTodo::new(text.clone()))
+            .await
+            .expect("failed");
+        assert_eq!(todo, expected);
+
+        // find
+        let todo = repository.find(id).await.unwrap();
+        assert_eq!(todo, expected);
+
+        // all
+        let todos = repository.all(&TodoFilter::default()).await.unwrap();
+        assert_eq!(todos, vec![expected.clone()]);
+        let open = TodoFilter {
+            completed: Some(false),
+            text: vec!["TEXT".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(repository.all(&open).await.unwrap(), vec![expected.clone()]);
+        let done = TodoFilter {
+            completed: Some(true),
+            ..Default::default()
+        };
+        assert!(repository.all(&done).await.unwrap().is_empty());
+
+        // update
+        let text = "update todo".to_string();
+        let todo = repository
+            .update(
+                id,
+                UpdateTodo {
+                    text: Some(text.clone()),
+                    completed: None,
+                    due: None,
+                    labels: None,
+                    version: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        let expected = Todo {
+            id,
+            uuid: None,
+            text,
+            completed: false,
+            due: None,
+            version: 2,
+        };
+
+        assert_eq!(todo, expected);
+
+        // stale version
+        let stale = UpdateTodo::new(None, Some(true)).with_version(1);
+        let e = repository.update(id, stale).await.unwrap_err();
+        assert!(matches!(
+            e.downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::StaleVersion(_))
+        ));
+        let current = UpdateTodo::new(None, Some(true)).with_version(2);
+        assert_eq!(repository.update(id, current).await.unwrap().version(), 3);
+
+        // delete
+        repository.delete(id).await.unwrap();
+        let todo = repository.find(id).await;
+        assert!(todo.is_err());
+    }
+
+    #[tokio::test]
+    async fn memory_snapshot_survives_restart() {
+        let path = env::temp_dir().join(format!("my-todo-snapshot-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let repository = TodoRepositoryForMemory::with_snapshot(&path).await.unwrap();
+        let created = repository
+            .create(CreateTodo::new("persisted".to_string()))
+            .await
+            .unwrap();
+        repository
+            .update(created.id, UpdateTodo::new(None, Some(true)))
+            .await
+            .unwrap();
+        let written = loop {
+            match std::fs::read_to_string(&path) {
+                Ok(json) if json.contains("true") => break json,
+                _ => tokio::time::sleep(Duration::from_millis(10)).await,
+            }
+        };
+        assert!(written.contains("persisted"));
+
+        let restarted = TodoRepositoryForMemory::with_snapshot(&path).await.unwrap();
+        let todo = restarted.find(created.id).await.unwrap();
+        assert_eq!(todo.text, "persisted");
+        assert!(todo.completed);
+        assert!(restarted.last_modified(created.id).await.is_ok());
+        let next = restarted
+            .create(CreateTodo::new("next".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(next.id, created.id + 1);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn sqlite_crud_scenario() {
+        let pool = crate::repositories::connect_sqlite(
+            "sqlite::memory:",
+            &crate::repositories::PoolSettings::default(),
+            crate::repositories::Migrations::Apply,
+        )
+        .await
+        .expect("failed open sqlite");
+        let repository = TodoRepositoryForSqlite::new(pool.clone());
+
+        // create
+        let created = repository
+            .create(CreateTodo::new("[sqlite] text".to_string()))
+            .await
+            .unwrap();
+        assert!(!created.completed);
+        let other = repository
+            .create(CreateTodo::new("[sqlite] other".to_string()))
+            .await
+            .unwrap();
+
+        // uuids
+        let minted = repository
+            .create(CreateTodo::new("[sqlite] minted".to_string()).with_format(IdFormat::Uuid))
+            .await
+            .unwrap();
+        let key = Key::parse(&minted.key(IdFormat::Uuid), IdFormat::Uuid).unwrap();
+        assert_eq!(repository.resolve(&key).await.unwrap(), minted.id);
+        let again = CreateTodo {
+            uuid: minted.uuid.clone(),
+            ..CreateTodo::new("[sqlite] again".to_string())
+        };
+        assert!(repository.create(again).await.is_err());
+        repository.delete(minted.id).await.unwrap();
+        assert!(matches!(
+            repository.resolve(&key).await.unwrap_err().downcast_ref(),
+            Some(RepositoryError::UnknownUuid(_))
+        ));
+
+        // find, all
+        assert_eq!(repository.find(created.id).await.unwrap(), created);
+        let all = repository.all(&TodoFilter::default()).await.unwrap();
+        assert_eq!(all, vec![other.clone(), created.clone()]);
+        let ids = TodoFilter {
+            ids: Some(vec![created.id, other.id]),
+            ..Default::default()
+        };
+        assert_eq!(
+            repository.all(&ids).await.unwrap(),
+            vec![created.clone(), other.clone()]
+        );
+
+        // labels and filters
+        let label_id: i64 =
+            sqlx::query_scalar("insert into labels (name) values ('work') returning id")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        sqlx::query("insert into todo_labels (todo_id, label_id) values (?1, ?2)")
+            .bind(created.id)
+            .bind(label_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        let with_labels = repository.find_with_labels(created.id).await.unwrap();
+        assert_eq!(with_labels.labels[0].name, "work");
+        let filter = TodoFilter {
+            labels: vec!["work".to_string()],
+            text: vec!["TEXT".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(repository.count(&filter).await.unwrap(), 1);
+        assert_eq!(
+            repository.all_with_labels(&filter).await.unwrap(),
+            vec![with_labels]
+        );
+
+        // labels attach in the same transaction as the todo
+        let labelled = repository
+            .create(CreateTodo::new("labelled".to_string()).with_labels(vec![label_id, label_id]))
+            .await
+            .unwrap();
+        let found = repository.find_with_labels(labelled.id).await.unwrap();
+        assert_eq!(found.labels.len(), 1);
+        let missing = repository
+            .create(CreateTodo::new("rolled back".to_string()).with_labels(vec![label_id, -1]))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            missing.downcast_ref(),
+            Some(RepositoryError::NotFound(-1))
+        ));
+        let rolled_back = TodoFilter {
+            text: vec!["rolled back".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(repository.count(&rolled_back).await.unwrap(), 0);
+        let relabel = UpdateTodo::new(Some("relabelled".to_string()), None).with_labels(vec![-1]);
+        assert!(repository.update(labelled.id, relabel).await.is_err());
+        let found = repository.find_with_labels(labelled.id).await.unwrap();
+        assert_eq!(found.todo.text, "labelled");
+        assert_eq!(found.labels.len(), 1);
+        let unlabel = UpdateTodo::new(None, None).with_labels(vec![]);
+        repository.update(labelled.id, unlabel).await.unwrap();
+        let found = repository.find_with_labels(labelled.id).await.unwrap();
+        assert!(found.labels.is_empty());
+        repository.delete(labelled.id).await.unwrap();
+
+        // update, purge
+        let updated = repository
+            .update(created.id, UpdateTodo::new(None, Some(true)))
+            .await
+            .unwrap();
+        assert!(updated.completed);
+        assert_eq!(updated.version, created.version + 1);
+        let stale = UpdateTodo::new(None, Some(false)).with_version(created.version);
+        assert!(matches!(
+            repository
+                .update(created.id, stale)
+                .await
+                .unwrap_err()
+                .downcast_ref(),
+            Some(RepositoryError::StaleVersion(_))
+        ));
+        assert!(repository.last_modified(created.id).await.is_ok());
+        assert_eq!(repository.purge_completed().await.unwrap(), 1);
+
+        // delete
+        repository.delete(other.id).await.unwrap();
+        assert!(repository.find(other.id).await.is_err());
+        assert!(repository.delete(other.id).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn sqlite_loads_labels_for_many_todos_at_once() {
+        let pool = crate::repositories::connect_sqlite(
+            "sqlite::memory:",
+            &crate::repositories::PoolSettings::default(),
+            crate::repositories::Migrations::Apply,
+        )
+        .await
+        .expect("failed open sqlite");
+        let repository = TodoRepositoryForSqlite::new(pool.clone());
+        let mut label_ids = vec![];
+        for name in ["work", "home"] {
+            let id: i64 = sqlx::query_scalar("insert into labels (name) values (?1) returning id")
+                .bind(name)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+            label_ids.push(id);
+        }
+        for i in 0..100 {
+            let labels = if i % 2 == 0 {
+                vec![label_ids[1], label_ids[0]]
+            } else {
+                vec![]
+            };
+            repository
+                .create(CreateTodo::new(format!("todo {}", i)).with_labels(labels))
+                .await
+                .unwrap();
+        }
+
+        let todos = repository
+            .all_with_labels(&TodoFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(todos.len(), 100);
+        assert_eq!(todos[0].todo.text, "todo 99");
+        assert!(todos[0].labels.is_empty());
+        let names: Vec<_> = todos[1].labels.iter().map(|label| &label.name).collect();
+        assert_eq!(names, ["work", "home"]);
+        let labelled = todos.iter().filter(|todo| !todo.labels.is_empty()).count();
+        assert_eq!(labelled, 50);
+    }
+
+    #[tokio::test]
+    async fn sqlite_creates_many_todos_in_one_transaction() {
+        let pool = crate::repositories::connect_sqlite(
+            "sqlite::memory:",
+            &crate::repositories::PoolSettings::default(),
+            crate::repositories::Migrations::Apply,
+        )
+        .await
+        .expect("failed open sqlite");
+        let repository = TodoRepositoryForSqlite::new(pool.clone());
+        let label_id: i64 =
+            sqlx::query_scalar("insert into labels (name) values ('bulk') returning id")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+
+        let payloads: Vec<_> = (0..SQLITE_INSERT_CHUNK + 1)
+            .map(|i| CreateTodo::new(format!("bulk {}", i)).with_labels(vec![label_id]))
+            .collect();
+        let created = repository.create_many(payloads).await.unwrap();
+        assert_eq!(created.len(), SQLITE_INSERT_CHUNK + 1);
+        assert_eq!(created[0].text, "bulk 0");
+        assert_eq!(
+            created[SQLITE_INSERT_CHUNK].text,
+            format!("bulk {}", SQLITE_INSERT_CHUNK)
+        );
+        assert!(created.windows(2).all(|pair| pair[0].id < pair[1].id));
+        let linked = repository
+            .find_with_labels(created[SQLITE_INSERT_CHUNK].id)
+            .await
+            .unwrap();
+        assert_eq!(linked.labels[0].id, label_id);
+
+        let payloads = vec![
+            CreateTodo::new("kept?".to_string()),
+            CreateTodo::new("missing label".to_string()).with_labels(vec![i64::MAX]),
+        ];
+        let e = repository.create_many(payloads).await.unwrap_err();
+        assert!(matches!(
+            e.downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::NotFound(i64::MAX))
+        ));
+        let count = repository.count(&TodoFilter::default()).await.unwrap();
+        assert_eq!(count, SQLITE_INSERT_CHUNK as i64 + 1);
+    }
+
+    #[tokio::test]
+    async fn sqlite_hands_out_ids_past_32_bits() {
+        let pool = crate::repositories::connect_sqlite(
+            "sqlite::memory:",
+            &crate::repositories::PoolSettings::default(),
+            crate::repositories::Migrations::Apply,
+        )
+        .await
+        .expect("failed open sqlite");
+        let big = i64::from(i32::MAX) + 1;
+        sqlx::query("insert into todos (id, text) values (?1, 'big')")
+            .bind(big)
+            .execute(&pool)
+            .await
+            .unwrap();
+        let label_id: i64 =
+            sqlx::query_scalar("insert into labels (id, name) values (?1, 'wide') returning id")
+                .bind(big)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        let repository = TodoRepositoryForSqlite::new(pool);
+
+        let todo = repository
+            .create(CreateTodo::new("bigger".to_string()).with_labels(vec![label_id]))
+            .await
+            .unwrap();
+        assert_eq!(todo.id(), big + 1);
+        let found = repository.find_with_labels(todo.id()).await.unwrap();
+        assert_eq!(found.labels[0].id, big);
+        assert_eq!(repository.find(big).await.unwrap().text(), "big");
+    }
+
+    #[tokio::test]
+    async fn sqlite_imports_in_one_transaction() {
+        let pool = crate::repositories::connect_sqlite(
+            "sqlite::memory:",
+            &crate::repositories::PoolSettings::default(),
+            crate::repositories::Migrations::Apply,
+        )
+        .await
+        .expect("failed open sqlite");
+        let repository = TodoRepositoryForSqlite::new(pool.clone());
+        let labels = crate::repositories::label::LabelRepositoryForSqlite::new(pool);
+        let work = labels.create("work".to_string(), None).await.unwrap();
+        let uuid = Uuid::now_v7();
+        let label = |name: &str| ImportLabel {
+            name: name.to_string(),
+            uuid: None,
+        };
+
+        let import = Import {
+            labels: vec![label("home"), label("work")],
+            todos: vec![
+                (
+                    CreateTodo::new("done".to_string()).with_completed(true),
+                    vec![0, 1],
+                ),
+                (
+                    CreateTodo::new("open".to_string()).with_uuid(Some(uuid.clone())),
+                    vec![],
+                ),
+            ],
+        };
+        let imported = repository.import(&labels, import.clone()).await.unwrap();
+        assert_eq!(imported.created_labels, 1);
+        assert_eq!(imported.label_ids[1], work.id);
+        let done = repository
+            .find_with_labels(imported.todos[0].id)
+            .await
+            .unwrap();
+        assert!(done.todo.completed());
+        assert_eq!(done.todo.version(), first_version());
+        assert_eq!(done.labels.len(), 2);
+
+        // the second todo's uuid is taken now, so none of it is stored
+        let import = Import {
+            labels: vec![label("errand"), label("work")],
+            ..import
+        };
+        assert!(repository.import(&labels, import).await.is_err());
+        assert_eq!(labels.all().await.unwrap().len(), 2);
+        assert_eq!(
+            repository.all(&TodoFilter::default()).await.unwrap().len(),
+            2
+        );
+    }
+
     #[tokio::test]
-    async fn todo_curd_scenario() {
-        let text = "todo text".to_string();
-        let id = 1;
-        let expected = Todo::new(id, text.clone());
+    async fn sqlite_keeps_due_dates() {
+        let pool = crate::repositories::connect_sqlite(
+            "sqlite::memory:",
+            &crate::repositories::PoolSettings::default(),
+            crate::repositories::Migrations::Apply,
+        )
+        .await
+        .expect("failed open sqlite");
+        let repository = TodoRepositoryForSqlite::new(pool);
+        due_dates_are_kept_moved_and_cleared(&repository).await;
 
-        // create
-        let repository = TodoRepositoryForMemory::new();
-        let todo = repository
-            .create(CreateTodo { text: text.clone() })
+        let due = "2024-06-01".parse::<Date>().ok();
+        let many = repository
+            .create_many(vec![
+                CreateTodo::new("due".to_string()).with_due(due.clone()),
+                CreateTodo::new("whenever".to_string()),
+            ])
             .await
-            .expect("failed");
-        assert_eq!(todo, expected);
+            .unwrap();
+        assert_eq!(many[0].due, due);
+        assert_eq!(many[1].due, None);
+        let (recent, _) = &repository.recently_modified(2).await.unwrap()[1];
+        assert_eq!(recent, &many[0]);
+    }
 
-        // find
-        let todo = repository.find(id).await.unwrap();
-        assert_eq!(todo, expected);
+    #[tokio::test]
+    async fn sqlite_archives_old_completed_todos() {
+        let pool = crate::repositories::connect_sqlite(
+            "sqlite::memory:",
+            &crate::repositories::PoolSettings::default(),
+            crate::repositories::Migrations::Apply,
+        )
+        .await
+        .expect("failed open sqlite");
+        let repository = TodoRepositoryForSqlite::new(pool.clone());
+        let mut ids = vec![];
+        for text in ["old and done", "old and open", "done today"] {
+            ids.push(
+                repository
+                    .create(CreateTodo::new(text.to_string()))
+                    .await
+                    .unwrap()
+                    .id,
+            );
+        }
+        for id in [ids[0], ids[2]] {
+            repository
+                .update(id, UpdateTodo::new(None, Some(true)))
+                .await
+                .unwrap();
+        }
+        sqlx::query("update todos set updated_at = updated_at - 10 * 86400 where id in (?1, ?2)")
+            .bind(ids[0])
+            .bind(ids[1])
+            .execute(&pool)
+            .await
+            .unwrap();
 
-        // all
-        let todos = repository.all().await.unwrap();
-        assert_eq!(todos, vec![expected.clone()]);
+        let week = Duration::from_secs(7 * 86400);
+        assert_eq!(repository.archive_completed(week).await.unwrap(), 1);
+        assert_eq!(repository.archive_completed(week).await.unwrap(), 0);
+        let archived = repository.archived().await.unwrap();
+        assert_eq!(archived.len(), 1);
+        assert_eq!(archived[0].text, "old and done");
+        assert!(repository.find(ids[0]).await.is_err());
+        assert_eq!(repository.count(&TodoFilter::default()).await.unwrap(), 2);
+    }
 
-        // update
-        let text = "update todo".to_string();
-        let todo = repository
-            .update(
-                id,
-                UpdateTodo {
-                    text: Some(text.clone()),
-                    completed: None,
-                },
-            )
+    #[cfg(feature = "mysql")]
+    #[tokio::test]
+    async fn mysql_crud_scenario() {
+        dotenv().ok();
+        let database_url = &env::var("MYSQL_DATABASE_URL").expect("undefined [MYSQL_DATABASE_URL]");
+        let pool = crate::repositories::connect_mysql(
+            database_url,
+            &crate::repositories::PoolSettings::default(),
+            crate::repositories::Migrations::Apply,
+        )
+        .await
+        .expect("failed connect mysql");
+        let repository = TodoRepositoryForMySql::new(pool);
+
+        let created = repository
+            .create(CreateTodo::new("[mysql] text".to_string()))
             .await
             .unwrap();
-
-        let expected = Todo {
-            id,
-            text,
-            completed: false,
+        assert_eq!(repository.find(created.id).await.unwrap(), created);
+        let filter = TodoFilter {
+            text: vec!["MYSQL".to_string()],
+            ids: Some(vec![created.id]),
+            ..Default::default()
         };
+        assert_eq!(
+            repository.all(&filter).await.unwrap(),
+            vec![created.clone()]
+        );
+        assert_eq!(repository.count(&filter).await.unwrap(), 1);
 
-        assert_eq!(todo, expected);
+        let updated = repository
+            .update(created.id, UpdateTodo::new(None, Some(true)))
+            .await
+            .unwrap();
+        assert!(updated.completed);
+        assert!(repository.last_modified(created.id).await.is_ok());
 
-        // delete
-        repository.delete(id).await.unwrap();
-        let todo = repository.find(id).await;
-        assert!(!todo.is_ok());
+        repository.delete(created.id).await.unwrap();
+        assert!(repository.find(created.id).await.is_err());
+        due_dates_are_kept_moved_and_cleared(&repository).await;
     }
 
     #[tokio::test]
@@ -294,6 +3834,17 @@ mod test {
             .unwrap();
         assert_eq!(created.text, todo_text);
         assert!(!created.completed);
+        let minted = repository
+            .create(CreateTodo::new(todo_text.to_string()).with_format(IdFormat::Uuid))
+            .await
+            .unwrap();
+        let key = Key::parse(&minted.key(IdFormat::Uuid), IdFormat::Uuid).unwrap();
+        assert_eq!(repository.resolve(&key).await.unwrap(), minted.id);
+        repository.delete(minted.id).await.unwrap();
+
+        // last modified
+        let created_at = repository.last_modified(created.id).await.unwrap();
+        assert!(repository.collection_last_modified().await.unwrap() >= created_at);
 
         // find
         let finded = repository.find(created.id).await.unwrap();
@@ -301,8 +3852,18 @@ mod test {
         assert_eq!(finded, created);
 
         // all
-        let all = repository.all().await.unwrap();
+        let all = repository.all(&TodoFilter::default()).await.unwrap();
         let todo = all.first().unwrap();
+        assert_eq!(
+            repository.count(&TodoFilter::default()).await.unwrap(),
+            all.len() as i64
+        );
+        let streamed: Vec<_> = repository
+            .stream_all()
+            .map(|todo| todo.unwrap())
+            .collect()
+            .await;
+        assert_eq!(streamed.last(), Some(&created));
 
         assert_eq!(created, *todo);
 
@@ -314,6 +3875,9 @@ mod test {
                 UpdateTodo {
                     text: Some(updated_text.to_string()),
                     completed: Some(true),
+                    due: None,
+                    labels: None,
+                    version: Some(created.version),
                 },
             )
             .await
@@ -323,11 +3887,152 @@ mod test {
             updated,
             Todo {
                 id: created.id,
+                uuid: None,
                 text: updated_text.to_string(),
-                completed: true
+                completed: true,
+                due: None,
+                version: created.version + 1,
             }
         );
 
+        // stale version
+        let stale = UpdateTodo::new(None, Some(false)).with_version(created.version);
+        let e = repository.update(created.id, stale).await.unwrap_err();
+        assert!(matches!(
+            e.downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::StaleVersion(_))
+        ));
+        let stale = UpdateTodo::new(None, None).with_version(created.version);
+        let e = repository.update(i64::MAX, stale).await.unwrap_err();
+        assert!(matches!(
+            e.downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::NotFound(_))
+        ));
+
+        assert!(repository.last_modified(created.id).await.unwrap() >= created_at);
+
+        // full-text search
+        let search = TodoFilter {
+            text: vec!["UPDATED".to_string()],
+            ids: Some(vec![created.id]),
+            ..Default::default()
+        };
+        let hits = repository.search(&search, 10).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].todo, updated);
+        assert!(hits[0].rank > 0.0);
+        assert_eq!(hits[0].highlight, "[test] <mark>updated</mark> text");
+        let partial = TodoFilter {
+            text: vec!["upd".to_string()],
+            ..search
+        };
+        assert!(repository.search(&partial, 10).await.unwrap().is_empty());
+        let recent = repository.recently_modified(1).await.unwrap();
+        assert_eq!(recent.first().map(|(todo, _)| todo), Some(&updated));
+
+        // find with labels
+        let label = sqlx::query_as::<_, Label>(
+            r#"
+        insert into labels (name) values ('[crud_scenario] label') returning *
+        "#,
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            r#"
+        insert into todo_labels (todo_id, label_id) values ($1, $2)
+        "#,
+        )
+        .bind(created.id)
+        .bind(label.id)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let with_labels = repository.find_with_labels(created.id).await.unwrap();
+        assert_eq!(with_labels.todo, updated);
+        assert_eq!(with_labels.labels, vec![label.clone()]);
+
+        let all = repository
+            .all_with_labels(&TodoFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(*all.first().unwrap(), with_labels);
+
+        // filter
+        let filter = TodoFilter {
+            completed: Some(true),
+            labels: vec![label.name.clone()],
+            text: vec!["UPDATED".to_string()],
+            ids: Some(vec![created.id]),
+            ..Default::default()
+        };
+        let all = repository.all_with_labels(&filter).await.unwrap();
+        assert_eq!(all, vec![with_labels.clone()]);
+        let filter = TodoFilter {
+            labels: vec![label.name.clone(), "[crud_scenario] missing".to_string()],
+            ..Default::default()
+        };
+        assert!(repository.all(&filter).await.unwrap().is_empty());
+
+        // labels attach in the same transaction as the todo
+        let labelled = repository
+            .create(
+                CreateTodo::new("[crud_scenario] labelled".to_string())
+                    .with_labels(vec![label.id, label.id]),
+            )
+            .await
+            .unwrap();
+        let found = repository.find_with_labels(labelled.id).await.unwrap();
+        assert_eq!(found.labels, vec![label.clone()]);
+        let missing = repository
+            .create(
+                CreateTodo::new("[crud_scenario] rolled back".to_string())
+                    .with_labels(vec![label.id, -1]),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            missing.downcast_ref(),
+            Some(RepositoryError::NotFound(-1))
+        ));
+        let rolled_back = TodoFilter {
+            text: vec!["[crud_scenario] rolled back".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(repository.count(&rolled_back).await.unwrap(), 0);
+        let relabel = UpdateTodo::new(Some("[crud_scenario] relabelled".to_string()), None)
+            .with_labels(vec![-1]);
+        assert!(repository.update(labelled.id, relabel).await.is_err());
+        let found = repository.find_with_labels(labelled.id).await.unwrap();
+        assert_eq!(found.todo.text, "[crud_scenario] labelled");
+        assert_eq!(found.labels, vec![label.clone()]);
+        let unlabel = UpdateTodo::new(None, None).with_labels(vec![]);
+        repository.update(labelled.id, unlabel).await.unwrap();
+        let found = repository.find_with_labels(labelled.id).await.unwrap();
+        assert!(found.labels.is_empty());
+        repository.delete(labelled.id).await.unwrap();
+
+        sqlx::query(
+            r#"
+        delete from todo_labels where todo_id=$1
+        "#,
+        )
+        .bind(created.id)
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            r#"
+        delete from labels where id=$1
+        "#,
+        )
+        .bind(label.id)
+        .execute(&pool)
+        .await
+        .unwrap();
+
         // delete
         let result = repository.delete(created.id).await;
         assert!(result.is_ok());
@@ -344,4 +4049,375 @@ mod test {
 
         assert!(todo_rows.is_empty());
     }
+
+    #[tokio::test]
+    async fn creates_many_todos_in_one_statement() {
+        dotenv().ok();
+        let database_url = &env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let pool = PgPool::connect(database_url)
+            .await
+            .expect("failed connect database");
+        let repository = TodoRepositoryForDb::new(pool.clone());
+        let label_id: i64 =
+            sqlx::query_scalar("insert into labels (name) values ('[create_many]') returning id")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+
+        let payloads = vec![
+            CreateTodo::new("[create_many] first".to_string()).with_labels(vec![label_id]),
+            CreateTodo::new("[create_many] second".to_string()).with_format(IdFormat::Uuid),
+            CreateTodo::new("[create_many] third".to_string())
+                .with_labels(vec![label_id, label_id]),
+        ];
+        let created = repository.create_many(payloads).await.unwrap();
+        let texts: Vec<_> = created.iter().map(|todo| todo.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            [
+                "[create_many] first",
+                "[create_many] second",
+                "[create_many] third"
+            ]
+        );
+        assert!(created[1].uuid.is_some());
+        let third = repository.find_with_labels(created[2].id).await.unwrap();
+        assert_eq!(third.labels.len(), 1);
+
+        let payloads = vec![
+            CreateTodo::new("[create_many] rolled back".to_string()),
+            CreateTodo::new("[create_many] rolled back".to_string()).with_labels(vec![i64::MAX]),
+        ];
+        let e = repository.create_many(payloads).await.unwrap_err();
+        assert!(matches!(
+            e.downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::NotFound(i64::MAX))
+        ));
+        let filter = TodoFilter {
+            text: vec!["rolled back".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(repository.count(&filter).await.unwrap(), 0);
+
+        sqlx::query("delete from todo_labels where label_id=$1")
+            .bind(label_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        for todo in created {
+            repository.delete(todo.id).await.unwrap();
+        }
+        sqlx::query("delete from labels where id=$1")
+            .bind(label_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn label_summaries_follow_label_changes() {
+        dotenv().ok();
+        let database_url = &env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let pool = PgPool::connect(database_url)
+            .await
+            .expect("failed connect database");
+        let repository = TodoRepositoryForDb::new(pool.clone());
+        let label_id: i64 =
+            sqlx::query_scalar("insert into labels (name) values ('[summaries]') returning id")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        let todo = repository
+            .create(CreateTodo::new("[summaries] text".to_string()).with_labels(vec![label_id]))
+            .await
+            .unwrap();
+
+        sqlx::query("update labels set name='[summaries] renamed' where id=$1")
+            .bind(label_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        let found = repository.find_with_labels(todo.id).await.unwrap();
+        assert_eq!(found.labels[0].name, "[summaries] renamed");
+        let filter = TodoFilter {
+            ids: Some(vec![todo.id]),
+            ..Default::default()
+        };
+        let listed = repository.all_with_labels(&filter).await.unwrap();
+        assert_eq!(listed, vec![found]);
+
+        repository
+            .update(todo.id, UpdateTodo::new(None, None).with_labels(vec![]))
+            .await
+            .unwrap();
+        let found = repository.find_with_labels(todo.id).await.unwrap();
+        assert!(found.labels.is_empty());
+
+        repository.delete(todo.id).await.unwrap();
+        sqlx::query("delete from labels where id=$1")
+            .bind(label_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn keeps_due_dates() {
+        dotenv().ok();
+        let database_url = &env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let pool = PgPool::connect(database_url)
+            .await
+            .expect("failed connect database");
+        let repository = TodoRepositoryForDb::new(pool);
+        due_dates_are_kept_moved_and_cleared(&repository).await;
+
+        let due = "2024-06-01".parse::<Date>().ok();
+        let many = repository
+            .create_many(vec![
+                CreateTodo::new("[keeps_due_dates] due".to_string()).with_due(due.clone()),
+                CreateTodo::new("[keeps_due_dates] whenever".to_string()),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(many[0].due, due);
+        assert_eq!(many[1].due, None);
+        for todo in many {
+            repository.delete(todo.id).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn archives_old_completed_todos() {
+        dotenv().ok();
+        let database_url = &env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        let pool = PgPool::connect(database_url)
+            .await
+            .expect("failed connect database");
+        let repository = TodoRepositoryForDb::new(pool.clone());
+        let label_id: i64 =
+            sqlx::query_scalar("insert into labels (name) values ('[archive]') returning id")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        let due = "2024-06-01".parse::<Date>().ok();
+        let todo = repository
+            .create(
+                CreateTodo::new("[archive] old".to_string())
+                    .with_labels(vec![label_id])
+                    .with_due(due.clone()),
+            )
+            .await
+            .unwrap();
+        repository
+            .update(todo.id, UpdateTodo::new(None, Some(true)))
+            .await
+            .unwrap();
+        // far older than anything the other tests leave behind
+        sqlx::query("update todos set updated_at = now() - interval '100 years' where id=$1")
+            .bind(todo.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let century = Duration::from_secs(99 * 365 * 86400);
+        assert_eq!(repository.archive_completed(century).await.unwrap(), 1);
+        let archived = repository.archived().await.unwrap();
+        assert_eq!(archived[0].id, todo.id);
+        assert_eq!(archived[0].version, todo.version + 1);
+        assert_eq!(archived[0].due, due);
+        assert!(repository.find(todo.id).await.is_err());
+
+        sqlx::query("delete from archived_todos where id=$1")
+            .bind(todo.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("delete from labels where id=$1")
+            .bind(label_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    #[cfg(feature = "mongodb")]
+    #[test]
+    fn mongo_filters_match_terms_literally() {
+        assert_eq!(regex_literal("a.b (c)*"), r"a\.b\ \(c\)\*");
+        let filter = TodoFilter {
+            completed: Some(true),
+            text: vec!["1+1".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            mongo_filter(&filter),
+            doc! { "$and": [
+                { "completed": true },
+                { "text": { "$regex": r"1\+1", "$options": "i" } },
+            ] }
+        );
+        assert_eq!(mongo_filter(&TodoFilter::default()), doc! {});
+    }
+
+    #[cfg(feature = "mongodb")]
+    #[tokio::test]
+    async fn mongo_crud_scenario() {
+        dotenv().ok();
+        let database_url = &env::var("MONGODB_URL").expect("undefined [MONGODB_URL]");
+        let db = crate::repositories::connect_mongo(database_url)
+            .await
+            .expect("failed connect mongodb");
+        let labels = crate::repositories::label::LabelRepositoryForMongo::new(db.clone());
+        let repository = TodoRepositoryForMongo::new(db);
+        let label = crate::repositories::label::LabelRepository::create(
+            &labels,
+            "[mongo] label".to_string(),
+            None,
+        )
+        .await
+        .unwrap();
+
+        let created = repository
+            .create(CreateTodo::new("[mongo] text".to_string()).with_labels(vec![label.id]))
+            .await
+            .unwrap();
+        let found = repository.find_with_labels(created.id).await.unwrap();
+        assert_eq!(found.todo, created);
+        assert_eq!(found.labels, vec![label.clone()]);
+        let filter = TodoFilter {
+            labels: vec![label.name.clone()],
+            text: vec!["[MONGO]".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            repository.all(&filter).await.unwrap(),
+            vec![created.clone()]
+        );
+
+        let updated = repository
+            .update(
+                created.id,
+                UpdateTodo::new(None, Some(true)).with_version(created.version),
+            )
+            .await
+            .unwrap();
+        assert!(updated.completed);
+        let stale = repository
+            .update(
+                created.id,
+                UpdateTodo::new(None, Some(false)).with_version(created.version),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            stale.downcast_ref(),
+            Some(RepositoryError::StaleVersion(_))
+        ));
+
+        crate::repositories::label::LabelRepository::delete(&labels, label.id)
+            .await
+            .unwrap();
+        assert!(repository
+            .find_with_labels(created.id)
+            .await
+            .unwrap()
+            .labels
+            .is_empty());
+        repository.delete(created.id).await.unwrap();
+        assert!(repository.find(created.id).await.is_err());
+    }
+
+    #[cfg(feature = "dynamodb")]
+    #[test]
+    fn dynamo_items_sort_by_id() {
+        let item = TodoItem {
+            pk: "todos".to_string(),
+            sk: DynamoTable::sort_key("todo", 42),
+            id: 42,
+            uuid: None,
+            text: "text".to_string(),
+            completed: false,
+            due: None,
+            version: 1,
+            updated_at: 0,
+            labels: vec![],
+        };
+        let attributes = to_item(&item).unwrap();
+        assert_eq!(
+            attributes["sk"],
+            AttributeValue::S("todo#0000000000000000042".to_string())
+        );
+        assert!(!attributes.contains_key("uuid"));
+        assert!(!attributes.contains_key("due"));
+        assert!(DynamoTable::sort_key("todo", 9) < DynamoTable::sort_key("todo", 10));
+        let todo: Todo = from_item::<TodoItem>(attributes).unwrap().into();
+        assert_eq!(todo.id, 42);
+    }
+
+    #[cfg(feature = "dynamodb")]
+    #[tokio::test]
+    async fn dynamo_crud_scenario() {
+        dotenv().ok();
+        let database_url = &env::var("DYNAMODB_URL").expect("undefined [DYNAMODB_URL]");
+        let table = crate::repositories::connect_dynamo(database_url)
+            .await
+            .expect("failed connect dynamodb");
+        let labels = crate::repositories::label::LabelRepositoryForDynamo::new(table.clone());
+        let repository = TodoRepositoryForDynamo::new(table);
+        let label = crate::repositories::label::LabelRepository::create(
+            &labels,
+            "[dynamo] label".to_string(),
+            None,
+        )
+        .await
+        .unwrap();
+
+        let created = repository
+            .create(CreateTodo::new("[dynamo] text".to_string()).with_labels(vec![label.id]))
+            .await
+            .unwrap();
+        let found = repository.find_with_labels(created.id).await.unwrap();
+        assert_eq!(found.todo, created);
+        assert_eq!(found.labels, vec![label.clone()]);
+        let filter = TodoFilter {
+            labels: vec![label.name.clone()],
+            text: vec!["[DYNAMO]".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            repository.all(&filter).await.unwrap(),
+            vec![created.clone()]
+        );
+
+        let updated = repository
+            .update(
+                created.id,
+                UpdateTodo::new(None, Some(true)).with_version(created.version),
+            )
+            .await
+            .unwrap();
+        assert!(updated.completed);
+        let stale = repository
+            .update(
+                created.id,
+                UpdateTodo::new(None, Some(false)).with_version(created.version),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            stale.downcast_ref(),
+            Some(RepositoryError::StaleVersion(_))
+        ));
+
+        crate::repositories::label::LabelRepository::delete(&labels, label.id)
+            .await
+            .unwrap();
+        assert!(repository
+            .find_with_labels(created.id)
+            .await
+            .unwrap()
+            .labels
+            .is_empty());
+        repository.delete(created.id).await.unwrap();
+        assert!(repository.find(created.id).await.is_err());
+    }
 }
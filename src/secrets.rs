@@ -0,0 +1,222 @@
+use std::{env, fs, time::Duration};
+
+use axum::http::{Request, Uri};
+use hyper::Body;
+use serde_json::Value;
+
+use crate::client;
+
+/// The variables holding credentials. Each can instead be read from the
+/// file `<NAME>_FILE` names, as Docker and Kubernetes mount secrets, or
+/// from Vault.
+const SECRETS: [&str; 23] = [
+    "DATABASE_URL",
+    "DATABASE_READ_URL",
+    "MYSQL_DATABASE_URL",
+    "MONGODB_URL",
+    "REDIS_URL",
+    "SMTP_URL",
+    "ADMIN_TOKEN",
+    "FEED_TOKEN",
+    "MAILGUN_SIGNING_KEY",
+    "SLACK_SIGNING_SECRET",
+    "TELEGRAM_WEBHOOK_SECRET",
+    "GITHUB_TOKEN",
+    "GITHUB_WEBHOOK_SECRET",
+    "GOOGLE_CLIENT_SECRET",
+    "GOOGLE_REFRESH_TOKEN",
+    "VAPID_PRIVATE_KEY",
+    "AWS_SECRET_ACCESS_KEY",
+    "ENCRYPTION_KEYS",
+    "OUTBOX_WEBHOOK_URL",
+    "CHAT_WEBHOOK_URL",
+    "SENTRY_DSN",
+    "OTEL_EXPORTER_OTLP_ENDPOINT",
+    "VAULT_TOKEN",
+];
+
+/// Where a secret came from, for the startup log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loaded {
+    pub name: &'static str,
+    pub from: String,
+}
+
+/// Sets the secrets missing from the environment from their `_FILE`, then
+/// from Vault when `VAULT_ADDR` is set, before the configuration is read. A
+/// variable set in the environment wins over both.
+///
+/// Changing the environment is only sound while no other thread reads it,
+/// so this runs before the runtime is built; Vault is read on a runtime of
+/// its own, shut down before anything is set.
+pub fn load() -> anyhow::Result<Vec<Loaded>> {
+    let mut loaded = from_files(|name| env::var(name).ok())?;
+    let mut values = Vec::new();
+    for Loaded { name, from } in &loaded {
+        values.push((*name, read(from)?));
+    }
+    if let Some(vault) = Vault::from_env()? {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let fetched = runtime.block_on(vault.fetch());
+        // Joins the resolver's threads too.
+        runtime.shutdown_timeout(VAULT_SHUTDOWN);
+        for (name, value) in fetched? {
+            if env::var_os(name).is_none() {
+                values.push((name, value));
+                loaded.push(Loaded {
+                    name,
+                    from: format!("vault {}", vault.path),
+                });
+            }
+        }
+    }
+    for (name, value) in values {
+        env::set_var(name, value);
+    }
+    Ok(loaded)
+}
+
+/// How long the Vault runtime waits for its threads to finish.
+const VAULT_SHUTDOWN: Duration = Duration::from_secs(5);
+
+/// The secrets `var` lacks but has a `_FILE` for.
+fn from_files(var: impl Fn(&str) -> Option<String>) -> anyhow::Result<Vec<Loaded>> {
+    let mut loaded = Vec::new();
+    for name in SECRETS {
+        let Some(path) = var(&format!("{}_FILE", name)) else {
+            continue;
+        };
+        if var(name).is_some() {
+            anyhow::bail!("invalid [{}_FILE]: {} is set too", name, name);
+        }
+        loaded.push(Loaded { name, from: path });
+    }
+    Ok(loaded)
+}
+
+/// The file's contents without the newline editors leave at its end.
+fn read(path: &str) -> anyhow::Result<String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("failed to read secret {}: {}", path, e))?;
+    Ok(contents.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// A HashiCorp Vault KV secret whose keys are named after the variables,
+/// e.g. `DATABASE_URL`.
+#[derive(Debug)]
+struct Vault {
+    addr: String,
+    token: String,
+    path: String,
+}
+
+impl Vault {
+    /// `VAULT_ADDR`, read with `VAULT_TOKEN` at `VAULT_SECRET_PATH`
+    /// (`secret/data/my-todo`, the KV version 2 layout). `None` when the
+    /// address is unset.
+    fn from_env() -> anyhow::Result<Option<Self>> {
+        let Ok(addr) = env::var("VAULT_ADDR") else {
+            return Ok(None);
+        };
+        let token = env::var("VAULT_TOKEN")
+            .map_err(|_| anyhow::anyhow!("undefined [VAULT_TOKEN], VAULT_ADDR needs it"))?;
+        let path =
+            env::var("VAULT_SECRET_PATH").unwrap_or_else(|_| "secret/data/my-todo".to_string());
+        Ok(Some(Self {
+            addr: addr.trim_end_matches('/').to_string(),
+            token,
+            path: path.trim_matches('/').to_string(),
+        }))
+    }
+
+    async fn fetch(&self) -> anyhow::Result<Vec<(&'static str, String)>> {
+        let uri: Uri = format!("{}/v1/{}", self.addr, self.path)
+            .parse()
+            .map_err(|e| anyhow::anyhow!("invalid [VAULT_ADDR]: {}, {}", self.addr, e))?;
+        let req = Request::get(uri)
+            .header("x-vault-token", &self.token)
+            .body(Body::empty())?;
+        let res = client::https()
+            .request(req)
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to reach vault at {}: {}", self.addr, e))?;
+        let status = res.status();
+        let body = hyper::body::to_bytes(res.into_body()).await?;
+        if !status.is_success() {
+            anyhow::bail!("vault answered {} for {}", status, self.path);
+        }
+        Ok(secrets_in(&serde_json::from_slice(&body)?))
+    }
+}
+
+/// The secrets in a KV read, version 2 nesting them under `data.data`
+/// and version 1 under `data`.
+fn secrets_in(read: &Value) -> Vec<(&'static str, String)> {
+    let data = &read["data"];
+    let data = data
+        .get("data")
+        .filter(|data| data.is_object())
+        .unwrap_or(data);
+    SECRETS
+        .iter()
+        .filter_map(|name| Some((*name, data.get(*name)?.as_str()?.to_string())))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn reads_secrets_from_their_files() {
+        let path = env::temp_dir().join(format!("my-todo-secret-{}", std::process::id()));
+        fs::write(&path, "postgres://todo:hunter2@db/todo\n").unwrap();
+        let path = path.to_str().unwrap().to_string();
+        let vars = HashMap::from([
+            ("DATABASE_URL_FILE".to_string(), path.clone()),
+            ("ADMIN_TOKEN".to_string(), "plain".to_string()),
+        ]);
+
+        let loaded = from_files(|name| vars.get(name).cloned()).unwrap();
+        assert_eq!(
+            loaded,
+            [Loaded {
+                name: "DATABASE_URL",
+                from: path.clone()
+            }]
+        );
+        assert_eq!(read(&path).unwrap(), "postgres://todo:hunter2@db/todo");
+        fs::remove_file(&path).unwrap();
+        assert!(read(&path).is_err());
+    }
+
+    #[test]
+    fn refuses_a_secret_set_twice() {
+        let vars = HashMap::from([
+            (
+                "FEED_TOKEN_FILE".to_string(),
+                "/run/secrets/feed".to_string(),
+            ),
+            ("FEED_TOKEN".to_string(), "plain".to_string()),
+        ]);
+        assert!(from_files(|name| vars.get(name).cloned()).is_err());
+    }
+
+    #[test]
+    fn finds_secrets_in_both_kv_versions() {
+        let v2 =
+            json!({ "data": { "data": { "ADMIN_TOKEN": "a", "other": "x" }, "metadata": {} } });
+        assert_eq!(secrets_in(&v2), [("ADMIN_TOKEN", "a".to_string())]);
+        let v1 = json!({ "data": { "REDIS_URL": "redis://cache", "FEED_TOKEN": 3 } });
+        assert_eq!(
+            secrets_in(&v1),
+            [("REDIS_URL", "redis://cache".to_string())]
+        );
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::repositories::{
+    id::{IdFormat, Uuid},
+    label::LabelRepository,
+    todo::{CreateTodo, TodoFilter, TodoRepository, UpdateTodo},
+    RepositoryError,
+};
+
+const LABELS: &[&str] = &["work", "home", "errands", "health", "urgent"];
+
+/// Demo todos as `(text, completed, labels)`.
+const TODOS: &[(&str, bool, &[&str])] = &[
+    (
+        "Prepare slides for Monday's planning meeting",
+        false,
+        &["work", "urgent"],
+    ),
+    (
+        "Review the pull request for the login page",
+        false,
+        &["work"],
+    ),
+    ("Send the invoice to the design agency", true, &["work"]),
+    ("Book a dentist appointment", false, &["health"]),
+    ("Go for a 5k run", true, &["health"]),
+    ("Buy milk, eggs and coffee beans", false, &["errands"]),
+    ("Pick up the dry cleaning", false, &["errands"]),
+    ("Fix the leaking kitchen tap", false, &["home", "urgent"]),
+    ("Water the plants", true, &["home"]),
+    ("Call grandma", false, &[]),
+];
+
+/// How many records a [`seed`] run created.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Seeded {
+    pub todos: usize,
+    pub labels: usize,
+}
+
+/// Fills an empty database with demo todos and labels. A database that
+/// already has todos is left alone, so seeding on every boot is harmless.
+///
+/// Labels that already exist are reused by name. Backends that can't link
+/// todos to labels (the in-memory one) get the todos without them.
+pub async fn seed<T: TodoRepository, L: LabelRepository>(
+    todos: &T,
+    labels: &L,
+    format: IdFormat,
+) -> anyhow::Result<Seeded> {
+    let mut seeded = Seeded::default();
+    if todos.count(&TodoFilter::default()).await? > 0 {
+        return Ok(seeded);
+    }
+
+    let mut ids: HashMap<String, i64> = labels
+        .all()
+        .await?
+        .into_iter()
+        .map(|label| (label.name, label.id))
+        .collect();
+    for name in LABELS {
+        if !ids.contains_key(*name) {
+            let label = labels
+                .create(
+                    name.to_string(),
+                    (format == IdFormat::Uuid).then(Uuid::now_v7),
+                )
+                .await?;
+            ids.insert(label.name, label.id);
+            seeded.labels += 1;
+        }
+    }
+
+    let payloads: Vec<CreateTodo> = TODOS
+        .iter()
+        .map(|(text, _, _)| CreateTodo::new(text.to_string()).with_format(format))
+        .collect();
+    let labelled = payloads
+        .iter()
+        .zip(TODOS)
+        .map(|(payload, (_, _, names))| {
+            let label_ids = names.iter().map(|name| ids[*name]).collect();
+            payload.clone().with_labels(label_ids)
+        })
+        .collect();
+    let created = match todos.create_many(labelled).await {
+        Err(e) if matches!(e.downcast_ref(), Some(RepositoryError::NotFound(_))) => {
+            todos.create_many(payloads).await?
+        }
+        created => created?,
+    };
+    for (todo, (_, completed, _)) in created.iter().zip(TODOS) {
+        if *completed {
+            todos
+                .update(todo.id(), UpdateTodo::new(None, Some(true)))
+                .await?;
+        }
+    }
+    seeded.todos = created.len();
+
+    Ok(seeded)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::repositories::{
+        connect_sqlite, label::LabelRepositoryForSqlite, todo::TodoRepositoryForSqlite, Migrations,
+        PoolSettings,
+    };
+
+    #[tokio::test]
+    async fn seeds_an_empty_database_once() {
+        let pool = connect_sqlite(
+            "sqlite::memory:",
+            &PoolSettings::default(),
+            Migrations::Apply,
+        )
+        .await
+        .unwrap();
+        let todos = TodoRepositoryForSqlite::new(pool.clone());
+        let labels = LabelRepositoryForSqlite::new(pool);
+        labels.create("work".to_string(), None).await.unwrap();
+
+        let seeded = seed(&todos, &labels, IdFormat::Uuid).await.unwrap();
+        assert_eq!(
+            seeded,
+            Seeded {
+                todos: TODOS.len(),
+                labels: LABELS.len() - 1,
+            }
+        );
+        let urgent = TodoFilter {
+            labels: vec!["urgent".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(todos.count(&urgent).await.unwrap(), 2);
+        let done = TodoFilter {
+            completed: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(todos.count(&done).await.unwrap(), 3);
+        let all = todos.all(&TodoFilter::default()).await.unwrap();
+        assert!(all
+            .iter()
+            .all(|todo| todo.key(IdFormat::Uuid).contains('-')));
+
+        assert_eq!(
+            seed(&todos, &labels, IdFormat::Serial).await.unwrap(),
+            Seeded::default()
+        );
+    }
+}
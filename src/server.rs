@@ -0,0 +1,473 @@
+use std::{
+    env, fs, io,
+    net::{SocketAddr, ToSocketAddrs},
+    os::unix::{
+        fs::FileTypeExt,
+        io::{FromRawFd, IntoRawFd, RawFd},
+        net as unix,
+    },
+    path::PathBuf,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use axum::extract::connect_info::Connected;
+use futures_util::stream;
+use hyper::server::accept::{self, Accept};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::{TcpListener, TcpStream, UnixListener, UnixStream},
+    sync::mpsc,
+};
+use tokio_rustls::{
+    rustls::{self, internal::pemfile, NoClientAuth},
+    server::TlsStream,
+    TlsAcceptor,
+};
+
+use crate::config::ServerConfig;
+
+/// A client that hasn't finished its TLS handshake by then is dropped.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Handshaken connections waiting for the server to take them.
+const BACKLOG: usize = 128;
+
+/// The first file descriptor systemd passes sockets from, see
+/// `sd_listen_fds(3)`.
+const LISTEN_FDS_START: RawFd = 3;
+
+/// The address the API listens on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BindAddr(pub SocketAddr);
+
+impl BindAddr {
+    /// `host` and `port`. `host` is an ip or a name resolved at startup,
+    /// `localhost` to keep the API off the network; it defaults to every
+    /// interface, or to `127.0.0.1` in dev mode.
+    pub fn from_config(config: &ServerConfig, dev_mode: bool) -> Self {
+        let host = config
+            .host
+            .as_deref()
+            .unwrap_or(if dev_mode { "127.0.0.1" } else { "0.0.0.0" });
+        Self::resolve(host, config.port)
+            .unwrap_or_else(|e| panic!("invalid [HOST]: {}, {}", host, e))
+    }
+
+    /// `admin_host` and `admin_port`, `None` without a port.
+    pub fn admin_from_config(config: &ServerConfig) -> Option<Self> {
+        let port = config.admin_port?;
+        let addr = Self::resolve(&config.admin_host, port)
+            .unwrap_or_else(|e| panic!("invalid [ADMIN_HOST]: {}, {}", config.admin_host, e));
+        if config.port == port && config.unix_socket.is_none() {
+            panic!("invalid [ADMIN_PORT]: {}, the API listens on it", port);
+        }
+        Some(addr)
+    }
+
+    /// The first address `host` resolves to.
+    fn resolve(host: &str, port: u16) -> anyhow::Result<Self> {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let addr = (host, port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("resolves to no address"))?;
+        Ok(Self(addr))
+    }
+}
+
+impl std::fmt::Display for BindAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// A certificate and key to serve HTTPS with, for deployments without a
+/// reverse proxy in front. They are read once, so a renewed certificate is
+/// served after a restart.
+#[derive(Clone)]
+pub struct Tls {
+    acceptor: TlsAcceptor,
+}
+
+impl Tls {
+    /// A PEM certificate chain, leaf first, and its PKCS#8 or RSA key,
+    /// offering clients HTTP/2 unless `http2` is off.
+    pub fn from_pem(cert: &[u8], key: &[u8], http2: bool) -> anyhow::Result<Self> {
+        let certs = pemfile::certs(&mut &cert[..])
+            .map_err(|_| anyhow::anyhow!("unreadable certificate"))?;
+        if certs.is_empty() {
+            anyhow::bail!("no certificate found");
+        }
+        let mut keys = pemfile::pkcs8_private_keys(&mut &key[..])
+            .map_err(|_| anyhow::anyhow!("unreadable key"))?;
+        if keys.is_empty() {
+            keys = pemfile::rsa_private_keys(&mut &key[..])
+                .map_err(|_| anyhow::anyhow!("unreadable key"))?;
+        }
+        let key = keys
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("no private key found"))?;
+        let mut config = rustls::ServerConfig::new(NoClientAuth::new());
+        config.set_single_cert(certs, key)?;
+        if http2 {
+            config.set_protocols(&[b"h2".to_vec(), b"http/1.1".to_vec()]);
+        } else {
+            config.set_protocols(&[b"http/1.1".to_vec()]);
+        }
+        Ok(Self {
+            acceptor: TlsAcceptor::from(Arc::new(config)),
+        })
+    }
+
+    /// Serves HTTPS with the PEM files at `tls_cert_path` and
+    /// `tls_key_path`; `None`, plain HTTP, when neither is set.
+    pub fn from_config(config: &ServerConfig) -> Option<Self> {
+        let (cert_path, key_path) = match (&config.tls_cert_path, &config.tls_key_path) {
+            (None, None) => return None,
+            (Some(cert), Some(key)) => (cert, key),
+            (Some(_), None) => panic!("undefined [TLS_KEY_PATH]"),
+            (None, Some(_)) => panic!("undefined [TLS_CERT_PATH]"),
+        };
+        let cert = fs::read(cert_path)
+            .unwrap_or_else(|e| panic!("invalid [TLS_CERT_PATH]: {}, {}", cert_path.display(), e));
+        let key = fs::read(key_path)
+            .unwrap_or_else(|e| panic!("invalid [TLS_KEY_PATH]: {}, {}", key_path.display(), e));
+        let tls = Self::from_pem(&cert, &key, config.http2)
+            .unwrap_or_else(|e| panic!("invalid [TLS_CERT_PATH]: {}, {}", cert_path.display(), e));
+        Some(tls)
+    }
+
+    /// Connections accepted on `listener`, each handshaking on its own task
+    /// so slow or broken clients hold up no one else.
+    pub fn incoming(
+        self,
+        listener: std::net::TcpListener,
+    ) -> io::Result<impl Accept<Conn = TlsConn, Error = io::Error>> {
+        listener.set_nonblocking(true)?;
+        let listener = TcpListener::from_std(listener)?;
+        let (handshaken, waiting) = mpsc::channel(BACKLOG);
+        tokio::spawn(async move {
+            loop {
+                let (tcp, remote) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        tracing::warn!("failed to accept a connection: {}", e);
+                        continue;
+                    }
+                };
+                tcp.set_nodelay(true).ok();
+                let acceptor = self.acceptor.clone();
+                let sender = handshaken.clone();
+                tokio::spawn(async move {
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(tcp)).await {
+                        Ok(Ok(tls)) => {
+                            sender.send(TlsConn { tls, remote }).await.ok();
+                        }
+                        Ok(Err(e)) => {
+                            tracing::debug!("TLS handshake with {} failed: {}", remote, e)
+                        }
+                        Err(_) => tracing::debug!("TLS handshake with {} timed out", remote),
+                    }
+                });
+                if handshaken.is_closed() {
+                    return;
+                }
+            }
+        });
+        let conns = stream::unfold(waiting, |mut waiting| async move {
+            let conn = waiting.recv().await?;
+            Some((Ok(conn), waiting))
+        });
+        Ok(accept::from_stream(conns))
+    }
+}
+
+/// A unix socket to serve plain HTTP on instead of TCP, for nginx or caddy
+/// proxying from the same host. Requests over it carry no client address:
+/// the proxy's `X-Forwarded-For` names the client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnixSocket {
+    path: PathBuf,
+}
+
+impl UnixSocket {
+    /// `unix_socket`, which `host` and `port` then don't apply to; `None`
+    /// to listen on TCP.
+    pub fn from_config(config: &ServerConfig) -> Option<Self> {
+        let path = config.unix_socket.clone()?;
+        if config.tls_cert_path.is_some() {
+            panic!(
+                "invalid [UNIX_SOCKET]: {}, TLS is only served over TCP",
+                path.display()
+            );
+        }
+        Some(Self { path })
+    }
+
+    /// Binds the socket, replacing the one a previous run left behind.
+    pub fn bind(&self) -> io::Result<unix::UnixListener> {
+        match fs::symlink_metadata(&self.path) {
+            Ok(stale) if stale.file_type().is_socket() => fs::remove_file(&self.path)?,
+            _ => {}
+        }
+        unix::UnixListener::bind(&self.path)
+    }
+
+    /// Deletes the socket once the server stopped.
+    pub fn remove(&self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            tracing::warn!("failed to remove {}: {}", self, e);
+        }
+    }
+}
+
+impl std::fmt::Display for UnixSocket {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.path.display().fmt(f)
+    }
+}
+
+/// Connections accepted on a unix socket.
+pub fn unix_incoming(
+    listener: unix::UnixListener,
+) -> io::Result<impl Accept<Conn = UnixStream, Error = io::Error>> {
+    listener.set_nonblocking(true)?;
+    let listener = UnixListener::from_std(listener)?;
+    let conns = stream::unfold(listener, |listener| async move {
+        let conn = listener.accept().await.map(|(conn, _)| conn);
+        Some((conn, listener))
+    });
+    Ok(accept::from_stream(conns))
+}
+
+/// The socket to serve on: bound by the server, or by systemd and passed
+/// on for socket activation, which keeps it open across restarts so
+/// clients queue rather than being refused while the service restarts.
+#[derive(Debug)]
+pub enum Listener {
+    Tcp(std::net::TcpListener),
+    Unix(unix::UnixListener),
+}
+
+impl Listener {
+    /// The socket systemd passed through `LISTEN_FDS`, `None` when the
+    /// service isn't socket activated. Only the first is served.
+    pub fn from_systemd() -> Option<Self> {
+        let pid = env::var("LISTEN_PID").ok()?;
+        if pid.parse() != Ok(std::process::id()) {
+            return None;
+        }
+        let fds = env::var("LISTEN_FDS").unwrap_or_default();
+        let fds: RawFd = fds
+            .parse()
+            .unwrap_or_else(|e| panic!("invalid [LISTEN_FDS]: {}, {}", fds, e));
+        // The variables are left set, as the runtime may be reading the
+        // environment; a process this one starts sees a `LISTEN_PID` that
+        // isn't its own and ignores them.
+        if fds > 1 {
+            tracing::warn!("systemd passed {} sockets, serving the first", fds);
+        }
+        // Safety: systemd hands the process its sockets from
+        // `LISTEN_FDS_START` on, and nothing else takes them.
+        (fds > 0).then(|| unsafe { Self::from_raw_fd(LISTEN_FDS_START) })
+    }
+
+    /// # Safety
+    ///
+    /// `fd` must be a listening socket nothing else owns.
+    unsafe fn from_raw_fd(fd: RawFd) -> Self {
+        let tcp = std::net::TcpListener::from_raw_fd(fd);
+        if tcp.local_addr().is_ok() {
+            Listener::Tcp(tcp)
+        } else {
+            Listener::Unix(unix::UnixListener::from_raw_fd(tcp.into_raw_fd()))
+        }
+    }
+}
+
+/// An HTTPS connection, which handlers see by its client's address.
+pub struct TlsConn {
+    tls: TlsStream<TcpStream>,
+    remote: SocketAddr,
+}
+
+impl Connected<&TlsConn> for SocketAddr {
+    fn connect_info(conn: &TlsConn) -> Self {
+        conn.remote
+    }
+}
+
+impl AsyncRead for TlsConn {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().tls).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for TlsConn {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().tls).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().tls).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().tls).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{IpAddr, Ipv6Addr};
+
+    use tokio_rustls::rustls::Session;
+
+    use super::*;
+
+    #[test]
+    fn resolves_ips_and_names() {
+        assert_eq!(
+            BindAddr::resolve("0.0.0.0", 3000).unwrap().to_string(),
+            "0.0.0.0:3000"
+        );
+        assert_eq!(
+            BindAddr::resolve("[::1]", 8080).unwrap().0,
+            SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 8080)
+        );
+        assert!(BindAddr::resolve("localhost", 3000)
+            .unwrap()
+            .0
+            .ip()
+            .is_loopback());
+        assert!(BindAddr::resolve("not a host", 3000).is_err());
+    }
+
+    #[tokio::test]
+    async fn serves_https() {
+        let cert = include_bytes!("../testdata/localhost.pem");
+        let key = include_bytes!("../testdata/localhost-key.pem");
+        let tls = Tls::from_pem(cert, key, true).unwrap();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = axum::Router::new().route(
+            "/",
+            axum::routing::get(
+                |axum::extract::ConnectInfo(client): axum::extract::ConnectInfo<SocketAddr>| async move {
+                    client.ip().to_string()
+                },
+            ),
+        );
+        tokio::spawn(
+            axum::Server::builder(tls.incoming(listener).unwrap())
+                .serve(app.into_make_service_with_connect_info::<SocketAddr, &TlsConn>()),
+        );
+
+        let mut config = tokio_rustls::rustls::ClientConfig::new();
+        config
+            .root_store
+            .add_pem_file(&mut &cert[..])
+            .expect("trusts the test certificate");
+        config.set_protocols(&[b"h2".to_vec(), b"http/1.1".to_vec()]);
+        let connector = tokio_rustls::TlsConnector::from(Arc::new(config));
+        let tcp = TcpStream::connect(addr).await.unwrap();
+        let domain = tokio_rustls::webpki::DNSNameRef::try_from_ascii_str("localhost").unwrap();
+        let tls = connector.connect(domain, tcp).await.unwrap();
+        assert_eq!(tls.get_ref().1.get_alpn_protocol(), Some(&b"h2"[..]));
+        let (mut sender, conn) = hyper::client::conn::Builder::new()
+            .http2_only(true)
+            .handshake(tls)
+            .await
+            .unwrap();
+        tokio::spawn(conn);
+        let req = hyper::Request::get("https://localhost/")
+            .body(hyper::Body::empty())
+            .unwrap();
+        let res = sender.send_request(req).await.unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::OK);
+        assert_eq!(res.version(), hyper::Version::HTTP_2);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"127.0.0.1");
+    }
+
+    #[tokio::test]
+    async fn speaks_h2c_to_clients_with_prior_knowledge() {
+        let app = axum::Router::new().route("/", axum::routing::get(|| async { "ok" }));
+        let addr = crate::testing::serve(app);
+
+        for http2 in [false, true] {
+            let tcp = TcpStream::connect(addr).await.unwrap();
+            let (mut sender, conn) = hyper::client::conn::Builder::new()
+                .http2_only(http2)
+                .handshake(tcp)
+                .await
+                .unwrap();
+            tokio::spawn(conn);
+            let req = hyper::Request::get("/").body(hyper::Body::empty()).unwrap();
+            let res = sender.send_request(req).await.unwrap();
+            assert_eq!(res.status(), hyper::StatusCode::OK);
+            let version = if http2 {
+                hyper::Version::HTTP_2
+            } else {
+                hyper::Version::HTTP_11
+            };
+            assert_eq!(res.version(), version);
+        }
+    }
+
+    #[tokio::test]
+    async fn serves_over_a_unix_socket() {
+        let path = std::env::temp_dir().join(format!("my-todo-{}.sock", std::process::id()));
+        let socket = UnixSocket { path: path.clone() };
+        let app = axum::Router::new().route("/", axum::routing::get(|| async { "ok" }));
+        let incoming = unix_incoming(socket.bind().unwrap()).unwrap();
+        tokio::spawn(axum::Server::builder(incoming).serve(app.clone().into_make_service()));
+        // a second run takes over the socket the first one left
+        let incoming = unix_incoming(socket.bind().unwrap()).unwrap();
+        tokio::spawn(axum::Server::builder(incoming).serve(app.into_make_service()));
+
+        let conn = UnixStream::connect(&path).await.unwrap();
+        let (mut sender, conn) = hyper::client::conn::handshake(conn).await.unwrap();
+        tokio::spawn(conn);
+        let req = hyper::Request::get("/").body(hyper::Body::empty()).unwrap();
+        let res = sender.send_request(req).await.unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::OK);
+
+        socket.remove();
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn tells_passed_sockets_apart() {
+        let tcp = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = tcp.local_addr().unwrap();
+        let listener = unsafe { Listener::from_raw_fd(tcp.into_raw_fd()) };
+        assert!(matches!(listener, Listener::Tcp(tcp) if tcp.local_addr().unwrap() == addr));
+
+        let path = std::env::temp_dir().join(format!("my-todo-fd-{}.sock", std::process::id()));
+        let socket = UnixSocket { path: path.clone() };
+        let unix = socket.bind().unwrap();
+        let listener = unsafe { Listener::from_raw_fd(unix.into_raw_fd()) };
+        assert!(matches!(listener, Listener::Unix(_)));
+        socket.remove();
+    }
+
+    #[test]
+    fn refuses_a_key_without_a_certificate() {
+        let key = include_bytes!("../testdata/localhost-key.pem");
+        assert!(Tls::from_pem(b"", key, true).is_err());
+        assert!(Tls::from_pem(key, key, true).is_err());
+    }
+}
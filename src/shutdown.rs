@@ -0,0 +1,159 @@
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use futures_util::{future::BoxFuture, FutureExt};
+use sqlx::{Database, Pool};
+use tokio::sync::oneshot;
+
+use crate::config::ServerConfig;
+
+/// Stops the server on SIGTERM, which orchestrators send before killing a
+/// process, or on Ctrl-C: it stops accepting connections, lets in-flight
+/// requests finish within a grace period, then closes the database pools.
+#[derive(Clone)]
+pub struct Shutdown {
+    grace: Duration,
+    pools: Arc<Mutex<Vec<BoxFuture<'static, ()>>>>,
+}
+
+impl Shutdown {
+    pub fn new(grace: Duration) -> Self {
+        Self {
+            grace,
+            pools: Arc::default(),
+        }
+    }
+
+    /// Waits up to `shutdown_grace_secs` seconds for requests in flight;
+    /// live update streams never end on their own, so they are cut when it
+    /// runs out.
+    pub fn from_config(config: &ServerConfig) -> Self {
+        Self::new(Duration::from_secs(config.shutdown_grace_secs))
+    }
+
+    /// Closes `pool` once the server has stopped, so the database sees its
+    /// connections end instead of having them dropped.
+    pub fn close_on_exit<DB: Database>(&self, pool: Pool<DB>) {
+        let close = async move { pool.close().await }.boxed();
+        self.pools
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(close);
+    }
+
+    /// Runs `serve`, handing it the future telling it to stop, until it
+    /// has drained or the grace period after the signal is over.
+    pub async fn run<S, F, E>(self, serve: S)
+    where
+        S: FnOnce(BoxFuture<'static, ()>) -> F,
+        F: Future<Output = Result<(), E>>,
+        E: std::fmt::Display,
+    {
+        let (signalled, grace_started) = oneshot::channel();
+        let stop = async move {
+            signal().await;
+            tracing::info!("shutting down, finishing requests in flight");
+            signalled.send(()).ok();
+        }
+        .boxed();
+        let grace = self.grace;
+        let grace_over = async move {
+            if grace_started.await.is_ok() {
+                tokio::time::sleep(grace).await;
+            } else {
+                futures_util::future::pending::<()>().await;
+            }
+        };
+        tokio::select! {
+            result = serve(stop) => {
+                if let Err(e) = result {
+                    tracing::error!("server failed: {}", e);
+                }
+            }
+            _ = grace_over => {
+                tracing::warn!("requests still open after {:?}, closing them", grace);
+            }
+        }
+        self.close().await;
+    }
+
+    /// Runs `serve` on a task beside the server [`Shutdown::run`] runs,
+    /// handing it the future telling it to stop at the same signal. It is
+    /// cut with the process if still draining when the grace period is over.
+    pub fn spawn<S, F, E>(&self, name: &'static str, serve: S)
+    where
+        S: FnOnce(BoxFuture<'static, ()>) -> F,
+        F: Future<Output = Result<(), E>> + Send + 'static,
+        E: std::fmt::Display,
+    {
+        let server = serve(signal().boxed());
+        tokio::spawn(async move {
+            if let Err(e) = server.await {
+                tracing::error!("{} failed: {}", name, e);
+            }
+        });
+    }
+
+    async fn close(&self) {
+        let pools = std::mem::take(&mut *self.pools.lock().unwrap_or_else(|e| e.into_inner()));
+        if !pools.is_empty() {
+            tracing::info!("closing database connections");
+        }
+        futures_util::future::join_all(pools).await;
+    }
+}
+
+/// Resolves on the first SIGTERM or Ctrl-C.
+async fn signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("failed to listen for Ctrl-C: {}", e);
+            futures_util::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("failed to listen for SIGTERM: {}", e);
+                futures_util::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = futures_util::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn closes_pools_after_the_server_stops() {
+        let pool = crate::repositories::connect_sqlite(
+            "sqlite::memory:",
+            &crate::repositories::PoolSettings::default(),
+            crate::repositories::Migrations::Apply,
+        )
+        .await
+        .expect("failed open sqlite");
+        let shutdown = Shutdown::new(Duration::from_secs(30));
+        shutdown.close_on_exit(pool.clone());
+
+        shutdown
+            .run(|_stop| async { Ok::<_, std::convert::Infallible>(()) })
+            .await;
+        assert!(pool.is_closed());
+    }
+}
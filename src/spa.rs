@@ -0,0 +1,128 @@
+use std::{
+    convert::Infallible,
+    path::{Path, PathBuf},
+};
+
+use axum::{
+    body::{boxed, Body},
+    http::{Request, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get_service, MethodRouter},
+};
+use tower::ServiceExt;
+use tower_http::services::{ServeDir, ServeFile};
+
+use crate::{config::ServerConfig, handlers::error::ApiError};
+
+/// Where the frontend is mounted.
+pub const PATH: &str = "/app";
+
+/// The single page frontend, shipped next to the binary: its files are
+/// served as they are, and any other page gets `index.html` so the
+/// frontend's router can show it.
+#[derive(Debug, Clone)]
+pub struct Spa {
+    dir: PathBuf,
+}
+
+impl Spa {
+    /// `spa_dir`, which must hold an `index.html`; none by default.
+    pub fn from_config(config: &ServerConfig) -> Option<Self> {
+        let dir = config.spa_dir.clone()?;
+        if !dir.join("index.html").is_file() {
+            panic!("invalid [SPA_DIR]: {}, no index.html", dir.display());
+        }
+        Some(Self { dir })
+    }
+
+    /// The service to nest under [`PATH`].
+    pub fn service(self) -> MethodRouter {
+        get_service(tower::service_fn(move |req: Request<Body>| {
+            let spa = self.clone();
+            async move { Ok::<_, Infallible>(spa.serve(req).await) }
+        }))
+    }
+
+    async fn serve(&self, req: Request<Body>) -> Response {
+        let fallback = page(req.uri().path()).then(|| without_body(&req));
+        let res = match ServeDir::new(&self.dir).oneshot(req).await {
+            Ok(res) if res.status() == StatusCode::NOT_FOUND && fallback.is_some() => {
+                ServeFile::new(self.dir.join("index.html"))
+                    .oneshot(fallback.expect("checked"))
+                    .await
+            }
+            res => res,
+        };
+        match res {
+            Ok(res) => res.map(boxed),
+            Err(e) => {
+                tracing::error!("failed to serve {}: {}", self.dir.display(), e);
+                ApiError::Internal.into_response()
+            }
+        }
+    }
+}
+
+/// Whether `path` names a page of the frontend rather than one of its
+/// files, which are answered `404` when missing rather than with the page.
+fn page(path: &str) -> bool {
+    Path::new(path).extension().is_none()
+}
+
+fn without_body(req: &Request<Body>) -> Request<()> {
+    let mut fallback = Request::new(());
+    *fallback.method_mut() = req.method().clone();
+    *fallback.uri_mut() = req.uri().clone();
+    *fallback.headers_mut() = req.headers().clone();
+    fallback
+}
+
+#[cfg(test)]
+mod test {
+    use axum::Router;
+
+    use super::*;
+
+    fn app() -> (Router, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("my-todo-spa-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("assets")).unwrap();
+        std::fs::write(dir.join("index.html"), "<div id=app></div>").unwrap();
+        std::fs::write(dir.join("assets/main.js"), "render()").unwrap();
+        let spa = Spa::from_config(&ServerConfig {
+            spa_dir: Some(dir.clone()),
+            ..ServerConfig::default()
+        })
+        .unwrap();
+        (Router::new().nest(PATH, spa.service()), dir)
+    }
+
+    async fn get(app: &Router, uri: &str) -> (StatusCode, String) {
+        let req = Request::get(uri).body(Body::empty()).unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+        let status = res.status();
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        (status, String::from_utf8(bytes.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn serves_files_and_falls_back_to_the_index() {
+        let (app, dir) = app();
+        assert_eq!(
+            get(&app, "/app/assets/main.js").await,
+            (StatusCode::OK, "render()".to_string())
+        );
+        for uri in ["/app/", "/app/todos/42"] {
+            assert_eq!(
+                get(&app, uri).await,
+                (StatusCode::OK, "<div id=app></div>".to_string()),
+                "{}",
+                uri
+            );
+        }
+        assert_eq!(
+            get(&app, "/app/assets/gone.js").await.0,
+            StatusCode::NOT_FOUND
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
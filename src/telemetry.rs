@@ -0,0 +1,165 @@
+use std::env;
+
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{propagation::TraceContextPropagator, runtime, trace, Resource};
+use tracing_subscriber::{
+    fmt::{format::FmtSpan, writer::BoxMakeWriter},
+    layer::SubscriberExt,
+    reload,
+    util::SubscriberInitExt,
+    EnvFilter, Layer, Registry,
+};
+
+use crate::{layers::access_log, repositories::env_or, sentry::Sentry};
+
+/// Exports spans to an OpenTelemetry collector over OTLP/gRPC, so a
+/// request shows up in Jaeger or Tempo with its repository calls below it,
+/// under the trace of the caller when it sent a `traceparent`.
+#[derive(Debug)]
+pub struct Telemetry {
+    endpoint: String,
+    service: String,
+    filter: EnvFilter,
+}
+
+impl Telemetry {
+    /// Exports to `OTEL_EXPORTER_OTLP_ENDPOINT` as `OTEL_SERVICE_NAME`
+    /// (`my-todo`), keeping the spans `OTEL_TRACES_FILTER`
+    /// (`info,my_todo=debug`) lets through. `None` when the endpoint is
+    /// unset.
+    pub fn from_env() -> Option<Self> {
+        let endpoint = env::var("OTEL_EXPORTER_OTLP_ENDPOINT").ok()?;
+        let filter: String = env_or("OTEL_TRACES_FILTER", "info,my_todo=debug".to_string());
+        let filter = EnvFilter::try_new(&filter)
+            .unwrap_or_else(|e| panic!("invalid [OTEL_TRACES_FILTER]: {}, {}", filter, e));
+        Some(Self {
+            endpoint,
+            service: env_or("OTEL_SERVICE_NAME", "my-todo".to_string()),
+            filter,
+        })
+    }
+
+    fn tracer(&self) -> trace::Tracer {
+        opentelemetry_otlp::new_pipeline()
+            .tracing()
+            .with_exporter(
+                opentelemetry_otlp::new_exporter()
+                    .tonic()
+                    .with_endpoint(&self.endpoint),
+            )
+            .with_trace_config(
+                trace::config().with_resource(Resource::new(vec![KeyValue::new(
+                    "service.name",
+                    self.service.clone(),
+                )])),
+            )
+            .install_batch(runtime::Tokio)
+            .unwrap_or_else(|e| {
+                panic!(
+                    "invalid [OTEL_EXPORTER_OTLP_ENDPOINT]: {}, {}",
+                    self.endpoint, e
+                )
+            })
+    }
+}
+
+/// How log lines are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    /// One JSON object per line, for log aggregation. Each request also
+    /// logs a line when it finishes, all carrying the `request` span's
+    /// `request_id`, `route`, `status` and `latency_ms`.
+    Json,
+}
+
+impl LogFormat {
+    /// `LOG_FORMAT=text|json`; text by default.
+    pub fn from_env() -> Self {
+        match env::var("LOG_FORMAT").as_deref() {
+            Ok("text") | Err(_) => LogFormat::Text,
+            Ok("json") => LogFormat::Json,
+            Ok(value) => panic!("invalid [LOG_FORMAT]: {}", value),
+        }
+    }
+}
+
+/// The filter of the logs, `RUST_LOG` directives such as
+/// `info,my_todo=debug`, which can be changed while running.
+#[derive(Debug, Clone)]
+pub struct LogFilter(reload::Handle<EnvFilter, Registry>);
+
+/// `directives`, letting the access log through at its own level.
+fn log_filter(directives: &str) -> anyhow::Result<EnvFilter> {
+    let access = format!("{}=trace", access_log::TARGET).parse()?;
+    Ok(EnvFilter::try_new(directives)?.add_directive(access))
+}
+
+impl LogFilter {
+    pub fn set(&self, directives: &str) -> anyhow::Result<()> {
+        self.0.reload(log_filter(directives)?)?;
+        Ok(())
+    }
+
+    /// A filter no subscriber uses, for tests. Its layer is leaked so the
+    /// handle can still reload it.
+    #[cfg(test)]
+    pub fn detached() -> Self {
+        let (layer, handle) = reload::Layer::new(EnvFilter::default());
+        std::mem::forget(layer);
+        Self(handle)
+    }
+}
+
+/// Installs the global subscriber: logs go to stdout, or to stderr when
+/// stdout carries the protocol in MCP mode, filtered by `directives` but
+/// for the access log, which has its own level; spans go to the collector
+/// too when `telemetry` is given, and errors to `sentry`.
+pub fn init(
+    format: LogFormat,
+    directives: &str,
+    telemetry: Option<Telemetry>,
+    sentry: Option<Sentry>,
+    mcp_mode: bool,
+) -> LogFilter {
+    let writer = if mcp_mode {
+        BoxMakeWriter::new(std::io::stderr)
+    } else {
+        BoxMakeWriter::new(std::io::stdout)
+    };
+    let filter = log_filter(directives)
+        .unwrap_or_else(|e| panic!("invalid [RUST_LOG]: {}, {}", directives, e));
+    let (filter, handle) = reload::Layer::new(filter);
+    let logs = tracing_subscriber::fmt::layer().with_writer(writer);
+    let logs = match format {
+        LogFormat::Text => logs.boxed(),
+        LogFormat::Json => logs
+            .json()
+            .flatten_event(true)
+            .with_span_list(false)
+            .with_span_events(FmtSpan::CLOSE)
+            .boxed(),
+    }
+    .with_filter(filter);
+    let spans = telemetry.map(|telemetry| {
+        opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+        tracing_opentelemetry::layer()
+            .with_tracer(telemetry.tracer())
+            .with_filter(telemetry.filter)
+    });
+    tracing_subscriber::registry()
+        .with(logs)
+        .with(spans)
+        .with(sentry.map(Sentry::layer))
+        .init();
+    LogFilter(handle)
+}
+
+/// Sends the spans still buffered, so the last requests before exit aren't
+/// lost.
+pub async fn flush() {
+    tokio::task::spawn_blocking(opentelemetry::global::shutdown_tracer_provider)
+        .await
+        .ok();
+}
@@ -0,0 +1,38 @@
+//! Fixtures the unit tests of several modules share.
+
+use std::net::SocketAddr;
+
+use axum::Router;
+use serde_json::json;
+
+use crate::repositories::{label::Label, todo::TodoWithLabels};
+
+/// A todo with `labels`, numbered from 1 in their order.
+pub fn todo(id: i64, text: &str, completed: bool, labels: &[&str]) -> TodoWithLabels {
+    TodoWithLabels {
+        todo: serde_json::from_value(json!({ "id": id, "text": text, "completed": completed }))
+            .unwrap(),
+        labels: labels
+            .iter()
+            .enumerate()
+            .map(|(i, name)| Label {
+                id: i as i64 + 1,
+                uuid: None,
+                name: name.to_string(),
+            })
+            .collect(),
+    }
+}
+
+/// Serves `app` on a free local port for as long as the test runs, as the
+/// services a client under test calls.
+pub fn serve(app: Router) -> SocketAddr {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service()),
+    );
+    addr
+}